@@ -6,6 +6,18 @@ pub enum AppsMqttTopics {
     DronTopic,
     CameraTopic,
     DescTopic,
+    DronAdminTopic,
+    DronAssignmentTopic,
+    DronHealthTopic,
+    IncidentAttendedTopic,
+    DronHandoffTopic,
+    DronControlTopic,
+    DronHistoryTopic,
+    DronDiagnosticsTopic,
+    CameraAdminTopic,
+    CameraCoverageTopic,
+    CameraHealthTopic,
+    CameraSnapshotTopic,
 }
 
 impl AppsMqttTopics {
@@ -15,15 +27,44 @@ impl AppsMqttTopics {
             AppsMqttTopics::DronTopic => "dron",
             AppsMqttTopics::CameraTopic => "cam",
             AppsMqttTopics::DescTopic => "desc",
+            AppsMqttTopics::DronAdminTopic => "dron-admin",
+            AppsMqttTopics::DronAssignmentTopic => "dron-assign",
+            AppsMqttTopics::DronHealthTopic => "dron-health",
+            AppsMqttTopics::IncidentAttendedTopic => "inc-attended",
+            AppsMqttTopics::DronHandoffTopic => "dron-handoff",
+            AppsMqttTopics::DronControlTopic => "dron-control",
+            AppsMqttTopics::DronHistoryTopic => "dron-history",
+            AppsMqttTopics::DronDiagnosticsTopic => "dron-diagnostics",
+            AppsMqttTopics::CameraAdminTopic => "cam-admin",
+            AppsMqttTopics::CameraCoverageTopic => "cam-gaps",
+            AppsMqttTopics::CameraHealthTopic => "cam-health",
+            AppsMqttTopics::CameraSnapshotTopic => "cam-snapshot",
         }
     }
 
+    /// Devuelve el topic de control del dron `dron_id`: cada dron recibe sus comandos de control por uno propio.
+    pub fn dron_control_topic(dron_id: u8) -> String {
+        format!("{}/{}", AppsMqttTopics::DronControlTopic.to_str(), dron_id)
+    }
+
     pub fn topic_from_str(str: &str) -> Result<Self, Error> {
         match str {
             "inc" => Ok(AppsMqttTopics::IncidentTopic),
             "dron" => Ok(AppsMqttTopics::DronTopic),
             "cam" => Ok(AppsMqttTopics::CameraTopic),
             "desc" => Ok(AppsMqttTopics::DescTopic),
+            "dron-admin" => Ok(AppsMqttTopics::DronAdminTopic),
+            "dron-assign" => Ok(AppsMqttTopics::DronAssignmentTopic),
+            "dron-health" => Ok(AppsMqttTopics::DronHealthTopic),
+            "inc-attended" => Ok(AppsMqttTopics::IncidentAttendedTopic),
+            "dron-handoff" => Ok(AppsMqttTopics::DronHandoffTopic),
+            "dron-history" => Ok(AppsMqttTopics::DronHistoryTopic),
+            "dron-diagnostics" => Ok(AppsMqttTopics::DronDiagnosticsTopic),
+            "cam-admin" => Ok(AppsMqttTopics::CameraAdminTopic),
+            "cam-gaps" => Ok(AppsMqttTopics::CameraCoverageTopic),
+            "cam-health" => Ok(AppsMqttTopics::CameraHealthTopic),
+            "cam-snapshot" => Ok(AppsMqttTopics::CameraSnapshotTopic),
+            str if str.starts_with("dron-control/") => Ok(AppsMqttTopics::DronControlTopic),
             _ => Err(Error::new(std::io::ErrorKind::InvalidInput, "Error: string inválida para crea un enum AppsMqttTopics."))
 
         }
@@ -7,12 +7,13 @@ use std::{
 
 use crate::{logging::string_logger::StringLogger, mqtt::client::mqtt_client::MQTTClient};
 
-use super::apps_mqtt_topics::AppsMqttTopics;
+use super::{apps_mqtt_topics::AppsMqttTopics, payload_codec::TopicCodecs, properties::Properties};
 
-/// Lee el IP del cliente y el puerto en el que el cliente se va a conectar al servidor.
-fn load_ip_and_port() -> Result<(String, u16), Box<Error>> {
+/// Lee el IP del cliente y el puerto en el que el cliente se va a conectar al servidor; y, si la app lo admite
+/// (`accepts_option`), un argumento opcional a continuación.
+fn load_ip_port_and_option(accepts_option: bool) -> Result<(String, u16, Option<String>), Box<Error>> {
     let argv = std::env::args().collect::<Vec<String>>();
-    if argv.len() != 3 {
+    if argv.len() != 3 && !(accepts_option && argv.len() == 4) {
         return Err(Box::new(std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
             "Cantidad de argumentos inválido. Debe ingresar: la dirección IP y 
@@ -30,17 +31,34 @@ fn load_ip_and_port() -> Result<(String, u16), Box<Error>> {
         }
     };
 
-    Ok((ip.to_string(), port))
+    Ok((ip.to_string(), port, argv.get(3).cloned()))
 }
 
 pub fn get_broker_address() -> SocketAddr {
-    let (ip, port) = load_ip_and_port().unwrap_or_else(|e| {
+    get_broker_address_and_option(false).0
+}
+
+/// Devuelve la dirección del servidor, y el argumento opcional que le sigue, si se ingresó.
+pub fn get_broker_address_and_option(accepts_option: bool) -> (SocketAddr, Option<String>) {
+    let (ip, port, option) = load_ip_port_and_option(accepts_option).unwrap_or_else(|e| {
         println!("Error al cargar el puerto: {:?}", e);
         std::process::exit(1);
     });
 
     let broker_addr: String = format!("{}:{}", ip, port);
-    broker_addr.parse().expect("Dirección no válida")
+    (broker_addr.parse().expect("Dirección no válida"), option)
+}
+
+/// Devuelve con qué formato publica la app por cada topic, según la propiedad `json-topics` de `properties_file`
+/// (ie `json-topics=cam`). Si el archivo no existe o no la define, publica todo en binario.
+pub fn get_topic_codecs(properties_file: &str) -> Result<TopicCodecs, Error> {
+    let Ok(properties) = Properties::new(properties_file) else {
+        return Ok(TopicCodecs::default());
+    };
+    match properties.get("json-topics") {
+        Some(json_topics) => TopicCodecs::from_property(json_topics),
+        None => Ok(TopicCodecs::default()),
+    }
 }
 
 pub fn get_app_will_topic() -> String {
@@ -62,18 +80,20 @@ pub fn join_all_threads(children: Vec<JoinHandle<()>>) {
 }
 
 /// Función a llamar desde un hilo dedicado, para que app escuche si dicha app desea salir.
-/// Al recibir por el rx, se encarga de enviar disconnect de mqtt.
+/// Al recibir por el rx, se encarga de enviar disconnect de mqtt. Si ya no queda quién pueda pedirlo (ej se cerró
+/// la ui), también se desconecta, para que el cliente no quede reconectándose.
 pub fn exit_when_asked(mqtt_client: Arc<Mutex<MQTTClient>>, exit_rx: Receiver<bool>) {
     // Espero que otro hilo (ej la ui, ej el abm) me indique que se desea salir
-    if let Ok(exit) = exit_rx.recv(){
-        // Cuando eso ocurre, envío disconnect por mqtt
-        if exit {
-            if let Ok(mut mqtt_locked) = mqtt_client.lock() {
-                match mqtt_locked.mqtt_disconnect() {
-                    Ok(_) => println!("Saliendo exitosamente."),
-                    Err(e) => println!("Error al salir: {:?}", e),
-                }
-            }    
+    let exit = exit_rx.recv().unwrap_or(true);
+    // Cuando eso ocurre, envío disconnect por mqtt
+    if exit {
+        if let Ok(mut mqtt_locked) = mqtt_client.lock() {
+            // Si no hay conexión con el broker, el disconnect no llega, pero igualmente el cliente deja de
+            // reconectarse y sus hilos terminan.
+            match mqtt_locked.mqtt_disconnect() {
+                Ok(_) => println!("Saliendo exitosamente."),
+                Err(e) => println!("Desconectado sin avisar al broker: {:?}", e),
+            }
         }
     }
 }
@@ -0,0 +1,123 @@
+use std::{
+    env::args,
+    io::{Error, ErrorKind},
+    thread,
+};
+
+use rustx::apps::{
+    common_clients::{get_app_will_topic, join_all_threads},
+    geo_position::GeoPosition,
+    sist_camaras::{manage_stored_cameras::create_cameras, sistema_camaras::SistemaCamaras},
+    sist_dron::{
+        dron::Dron,
+        dron_config::{DronConfig, DEFAULT_CONFIG_FILE},
+    },
+    sist_monitoreo::sistema_monitoreo::SistemaMonitoreo,
+};
+use rustx::logging::string_logger::StringLogger;
+use rustx::mqtt::client::mqtt_client_builder::MqttClientBuilder;
+use rustx::mqtt::loopback::{LoopbackConnector, LoopbackListener};
+use rustx::mqtt::mqtt_utils::will_message_utils::{app_type::AppType, will_content::WillContent};
+use rustx::mqtt::server::mqtt_server::MQTTServer;
+
+const DEFAULT_DRONES: u8 = 3;
+// Posición inicial del primer dron; los siguientes se ubican a su lado.
+const INITIAL_LAT: f64 = -34.6037;
+const INITIAL_LON: f64 = -58.3816;
+const DRONES_SEPARATION: f64 = 0.002;
+
+/// Lee la cantidad de drones a lanzar, si se indicó por la consola.
+fn load_drones_amount() -> Result<u8, Error> {
+    match args().nth(1) {
+        Some(arg) => arg.parse::<u8>().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "Uso: demo_main [cantidad_de_drones]. La cantidad proporcionada no es válida.",
+            )
+        }),
+        None => Ok(DEFAULT_DRONES),
+    }
+}
+
+/// Hilo del servidor, que atiende en memoria a los sistemas de la demo.
+fn spawn_server(listener: LoopbackListener, logger: StringLogger) {
+    thread::spawn(move || {
+        let mqtt_server = MQTTServer::new(logger.clone_ref());
+        if let Err(e) = mqtt_server.run_loopback(listener) {
+            logger.error(format!("Error en el servidor de la demo: {:?}.", e));
+        }
+    });
+}
+
+/// Conecta al sistema de cámaras y lanza sus hilos.
+fn spawn_camaras(
+    connector: &LoopbackConnector,
+    qos: u8,
+    logger: StringLogger,
+) -> Result<(), Error> {
+    let will_msg_content = WillContent::new(AppType::Cameras, None);
+    let (mqtt_client, _publish_msg_rx, _handle) = MqttClientBuilder::new("Sistema-Camaras")
+        .will(&get_app_will_topic(), &will_msg_content.to_str(), qos, true)
+        .connect_loopback(connector, logger.clone_ref())?;
+
+    let mut sistema_camaras = SistemaCamaras::new(create_cameras()?, qos, logger);
+    sistema_camaras.spawn_threads(mqtt_client);
+    Ok(())
+}
+
+/// Conecta al dron `id` y lanza sus hilos.
+fn spawn_dron(
+    connector: &LoopbackConnector,
+    id: u8,
+    qos: u8,
+    logger: StringLogger,
+) -> Result<(), Error> {
+    let will_msg_content = WillContent::new(AppType::Dron, Some(id));
+    let (mqtt_client, _publish_msg_rx, _handle) = MqttClientBuilder::new(&format!("dron-{}", id))
+        .will(&get_app_will_topic(), &will_msg_content.to_str(), qos, true)
+        .connect_loopback(connector, logger.clone_ref())?;
+
+    let lon = INITIAL_LON + DRONES_SEPARATION * (id - 1) as f64;
+    let initial_position = GeoPosition::new(INITIAL_LAT, lon)?;
+    let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, id, initial_position)?;
+    let mut dron = Dron::new(id, initial_position, &config, logger)?;
+    dron.spawn_threads(mqtt_client)?;
+    Ok(())
+}
+
+/// Corre el servidor, el sistema de monitoreo, el de cámaras y los drones en un mismo proceso,
+/// conectados en memoria y sin abrir puertos.
+fn main() -> Result<(), Error> {
+    let drones_amount = load_drones_amount()?;
+
+    // Se crean y configuran ambos extremos del string logger
+    let (mut logger, handle_logger) = StringLogger::create_logger(String::from("Demo."));
+
+    let qos = 1; // []
+    let (listener, connector) = LoopbackListener::bind();
+    // Los hilos del servidor, las cámaras y los drones terminan junto con el proceso, al cerrarse la demo.
+    spawn_server(listener, logger.clone_ref());
+    spawn_camaras(&connector, qos, logger.clone_ref())?;
+    for id in 1..=drones_amount {
+        spawn_dron(&connector, id, qos, logger.clone_ref())?;
+    }
+
+    // La ui del sistema de monitoreo corre en el hilo principal; al cerrarla, termina la demo.
+    let sistema_monitoreo = SistemaMonitoreo::new(qos, logger.clone_ref());
+    let (mqtt_client, _publish_msg_rx, handle) = MqttClientBuilder::new("Sistema-Monitoreo")
+        .connect_loopback(&connector, logger.clone_ref())?;
+    println!("Demo iniciada con {} drones.", drones_amount);
+    let mut handles = sistema_monitoreo.spawn_threads(mqtt_client);
+    handles.push(handle);
+    join_all_threads(handles);
+
+    logger.stop_logging();
+    drop(sistema_monitoreo); // porque le hicimos clone_ref al logger.
+
+    // Se espera al hijo para el logger writer
+    if handle_logger.join().is_err() {
+        println!("Error al esperar al hijo para string logger writer.")
+    }
+
+    Ok(())
+}
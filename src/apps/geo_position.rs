@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::payload_version::PayloadReader;
+
+/// Radio medio de la Tierra, en metros.
+pub const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+/// Posición sobre la superficie de la Tierra, en grados de latitud y longitud. Se usa en vez de una tupla
+/// `(f64, f64)` para no confundir latitud con longitud, y para asegurar que sean coordenadas válidas.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "RawGeoPosition")]
+pub struct GeoPosition {
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Lo que se deserializa, antes de validar que sean coordenadas válidas.
+#[derive(Deserialize)]
+struct RawGeoPosition {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl TryFrom<RawGeoPosition> for GeoPosition {
+    type Error = Error;
+
+    fn try_from(raw: RawGeoPosition) -> Result<Self, Self::Error> {
+        Self::new(raw.latitude, raw.longitude)
+    }
+}
+
+impl GeoPosition {
+    /// Crea una posición. Devuelve error si la latitud no está entre -90 y 90, o la longitud entre -180 y 180.
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, Error> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Posición inválida: latitud {}, longitud {}.", latitude, longitude),
+            ));
+        }
+        Ok(Self { latitude, longitude })
+    }
+
+    pub fn get_latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    pub fn get_longitude(&self) -> f64 {
+        self.longitude
+    }
+
+    /// Devuelve la posición desplazada `delta_lat` grados de latitud y `delta_lon` de longitud. Si se pasa de los
+    /// polos queda en el polo, y si se pasa del antimeridiano da la vuelta.
+    pub fn offset(&self, delta_lat: f64, delta_lon: f64) -> Self {
+        let latitude = (self.latitude + delta_lat).clamp(-90.0, 90.0);
+        let longitude = (self.longitude + delta_lon + 180.0).rem_euclid(360.0) - 180.0;
+        Self { latitude, longitude }
+    }
+
+    /// Devuelve la distancia en metros hasta `other` sobre la superficie de la Tierra, con la fórmula de haversine.
+    pub fn distance_to(&self, other: &GeoPosition) -> f64 {
+        let (lat_a, lat_b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let delta_lat = lat_b - lat_a;
+        let delta_lon = (other.longitude - self.longitude).to_radians();
+
+        let h = (delta_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (delta_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * h.sqrt().min(1.0).asin()
+    }
+
+    /// Devuelve el rumbo inicial para ir hasta `other` por el camino más corto, en grados desde el norte en sentido
+    /// horario (de 0 a 360).
+    pub fn bearing_to(&self, other: &GeoPosition) -> f64 {
+        let (lat_a, lat_b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let delta_lon = (other.longitude - self.longitude).to_radians();
+
+        let y = delta_lon.sin() * lat_b.cos();
+        let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * delta_lon.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Convierte la posición a bytes: latitud y longitud, 8 bytes big endian cada una.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.latitude.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.longitude.to_be_bytes());
+        bytes
+    }
+
+    /// Lee una posición de `reader`, en el formato de `to_bytes`.
+    pub fn read_from(reader: &mut PayloadReader) -> Result<Self, Error> {
+        let latitude = reader.read_f64_be()?;
+        let longitude = reader.read_f64_be()?;
+        Self::new(latitude, longitude).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+impl fmt::Display for GeoPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.latitude, self.longitude)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GeoPosition;
+    use crate::apps::payload_version::PayloadReader;
+
+    #[test]
+    fn test_1_las_posiciones_se_validan_y_miden_distancias_y_rumbos() {
+        assert!(GeoPosition::new(-91.0, 0.0).is_err());
+        assert!(GeoPosition::new(0.0, 180.5).is_err());
+        assert!(serde_json::from_str::<GeoPosition>(r#"{"latitude":-58.4,"longitude":-234.6}"#).is_err());
+
+        // Del Obelisco a la Plaza de Mayo, unos 1.1 km hacia el sudeste.
+        let obelisco = GeoPosition::new(-34.6037, -58.3816).unwrap();
+        let plaza_de_mayo = GeoPosition::new(-34.6083, -58.3712).unwrap();
+        assert!((obelisco.distance_to(&plaza_de_mayo) - 1_080.0).abs() < 5.0);
+        let bearing = obelisco.bearing_to(&plaza_de_mayo);
+        assert!(bearing > 90.0 && bearing < 180.0);
+
+        let json = serde_json::to_string(&obelisco).unwrap();
+        assert_eq!(serde_json::from_str::<GeoPosition>(&json).unwrap(), obelisco);
+        let bytes = obelisco.to_bytes();
+        assert_eq!(GeoPosition::read_from(&mut PayloadReader::new(&bytes)).unwrap(), obelisco);
+        assert_eq!(GeoPosition::new(89.0, 179.0).unwrap().offset(2.0, 2.0), GeoPosition::new(90.0, -179.0).unwrap());
+    }
+}
@@ -1,53 +1,178 @@
-use std::io::Error;
+use std::{
+    io::{Error, ErrorKind},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
 
+use crate::apps::geo_position::GeoPosition;
+use crate::apps::payload_version::{split_version_header, with_version_header};
+use crate::mqtt::messages::publish_message::PublishMessage;
+
+use super::incident_category::IncidentCategory;
 use super::incident_info::IncidentInfo;
+use super::incident_priority::IncidentPriority;
+use super::incident_severity::IncidentSeverity;
 use super::incident_state::IncidentState;
 use super::incident_source::IncidentSource;
+use super::incident_transition::IncidentTransition;
+
+/// Versión del formato en bytes del incidente. La 1 agregó el encabezado de versión a la legacy, que ya incluía
+/// la prioridad, la 2 agregó la gravedad, el tipo, los instantes de creación y resolución, y la descripción, y la 3
+/// agregó el historial de cambios de estado.
+pub const INCIDENT_VERSION: u8 = 3;
+/// Cantidad máxima de cambios de estado que se incluyen al convertir un incidente a bytes: los de su ciclo de vida
+/// más largo (reportado, asignado, en atención y resuelto). Si tuviera más, se incluyen los últimos.
+pub const MAX_SERIALIZED_TRANSITIONS: usize = 4;
+/// Largo máximo de un incidente en bytes: lo que entra en un publish por `dron-handoff`, el topic más largo que
+/// lleva incidentes, descontando los 3 bytes que le agrega el pedido de relevo.
+const MAX_INCIDENT_LEN: usize = PublishMessage::max_payload_len("dron-handoff") - 3;
+/// Bytes del incidente sin su descripción y con el historial más largo: encabezado de versión, id, posición, estado,
+/// origen, prioridad, gravedad, tipo, instantes de creación y resolución, largo de la descripción, y cantidad y
+/// cambios de estado (cada uno, el estado y su instante).
+const INCIDENT_FIXED_LEN: usize = 3 + 1 + 16 + 5 + 8 + 8 + 1 + 1 + MAX_SERIALIZED_TRANSITIONS * (1 + 8);
+/// Largo máximo de la descripción de un incidente, en bytes, para que el incidente entre en un publish.
+pub const MAX_DESCRIPTION_LEN: usize = MAX_INCIDENT_LEN - INCIDENT_FIXED_LEN;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Struct que representa un incidente, para ser utilizado por las aplicaciones del sistema de vigilancia (sist de monitoreo, sist central de cámaras, y app de drones).
-/// Posee un id, coordenadas x e y, un estado.
+/// Posee un id, coordenadas x e y, un estado, una prioridad, una gravedad, un tipo, una descripción, y los
+/// instantes en que se creó y se resolvió (en milisegundos desde el epoch). El estado solamente cambia según su ciclo
+/// de vida (ver `IncidentState`), y cada cambio queda registrado en su historial.
 pub struct Incident {
     id: u8, // []
-    latitude: f64,
-    longitude: f64,
+    #[serde(flatten)]
+    position: GeoPosition,
     state: IncidentState,
     source: IncidentSource,
+    priority: IncidentPriority,
+    #[serde(default)]
+    severity: IncidentSeverity,
+    #[serde(default)]
+    category: IncidentCategory,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    resolved_at: Option<u64>,
+    #[serde(default)]
+    history: Vec<IncidentTransition>,
 }
 
 impl Incident {
-    pub fn new(id: u8, location: (f64, f64), source: IncidentSource) -> Self {
+    /// Crea un incidente en estado reportado.
+    pub fn new(id: u8, position: GeoPosition, source: IncidentSource) -> Self {
+        let created_at = now_millis();
         Self {
             id,
-            latitude: location.0,
-            longitude: location.1,
-            state: IncidentState::ActiveIncident,
+            position,
+            state: IncidentState::Reported,
             source,
+            priority: IncidentPriority::default(),
+            severity: IncidentSeverity::default(),
+            category: IncidentCategory::default(),
+            description: String::new(),
+            created_at,
+            resolved_at: None,
+            history: vec![IncidentTransition::new(IncidentState::Reported, created_at)],
         }
     }
 
-    /// Devuelve coordenadas (x, y) correspondientes a la posición del incidente.
-    pub fn get_position(&self) -> (f64, f64) {
-        (self.latitude, self.longitude)
+    /// Devuelve el incidente con la prioridad recibida.
+    pub fn with_priority(mut self, priority: IncidentPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Devuelve el incidente con la gravedad recibida.
+    pub fn with_severity(mut self, severity: IncidentSeverity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Devuelve el incidente con el tipo recibido.
+    pub fn with_category(mut self, category: IncidentCategory) -> Self {
+        self.category = category;
+        self
+    }
+
+    /// Devuelve el incidente con la descripción recibida, recortada a `MAX_DESCRIPTION_LEN` bytes.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = truncate_description(description).to_string();
+        self
+    }
+
+    /// Devuelve la posición del incidente.
+    pub fn get_position(&self) -> GeoPosition {
+        self.position
     }
 
     /// Devuelve si el incidente tiene estado resuelto o no.
     pub fn is_resolved(&self) -> bool {
-        self.state == IncidentState::ResolvedIncident
+        self.state == IncidentState::Resolved
+    }
+
+    /// Devuelve si el incidente ya no necesita ser atendido, porque se resolvió o se canceló.
+    pub fn is_closed(&self) -> bool {
+        self.state.is_final()
     }
 
-    /// Cambia el estado del incidente a resuelto.
-    pub fn set_resolved(&mut self) {
-        self.state = IncidentState::ResolvedIncident;
+    /// Cambia el estado del incidente a `state`, y registra el cambio en su historial. Devuelve error si el ciclo de
+    /// vida no permite pasar del estado actual a `state`.
+    pub fn transition_to(&mut self, state: IncidentState) -> Result<(), Error> {
+        if !self.state.can_transition_to(state) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "El incidente {} no puede pasar de {} a {}.",
+                    self.id,
+                    self.state.to_str(),
+                    state.to_str()
+                ),
+            ));
+        }
+        let at = now_millis();
+        self.state = state;
+        self.history.push(IncidentTransition::new(state, at));
+        if state == IncidentState::Resolved {
+            self.resolved_at = Some(at);
+        }
+        Ok(())
     }
 
+    /// Devuelve el incidente con el id y el origen de `info`, manteniendo el resto (ie para tratarlo como otro
+    /// incidente con el que se unió).
+    pub fn with_info(mut self, info: IncidentInfo) -> Self {
+        self.id = info.get_inc_id();
+        self.source = *info.get_src();
+        self
+    }
+
+    /// Convierte el incidente a bytes, precedidos por el encabezado con su versión. Incluye a lo sumo
+    /// `MAX_DESCRIPTION_LEN` bytes de la descripción y los últimos `MAX_SERIALIZED_TRANSITIONS` cambios de estado,
+    /// para que entre en un publish.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.id];
-        bytes.extend_from_slice(&self.latitude.to_le_bytes());
-        bytes.extend_from_slice(&self.longitude.to_le_bytes());
+        bytes.extend_from_slice(&self.position.get_latitude().to_le_bytes());
+        bytes.extend_from_slice(&self.position.get_longitude().to_le_bytes());
         bytes.push(self.state.to_byte()[0]);
         bytes.push(self.source.to_byte()[0]);
-        bytes
+        bytes.push(self.priority.to_byte()[0]);
+        bytes.push(self.severity.to_byte()[0]);
+        bytes.push(self.category.to_byte()[0]);
+        bytes.extend_from_slice(&self.created_at.to_be_bytes());
+        // 0 si no se resolvió.
+        bytes.extend_from_slice(&self.resolved_at.unwrap_or(0).to_be_bytes());
+        let description = truncate_description(&self.description);
+        bytes.push(description.len() as u8);
+        bytes.extend_from_slice(description.as_bytes());
+        let history = &self.history[self.history.len().saturating_sub(MAX_SERIALIZED_TRANSITIONS)..];
+        bytes.push(history.len() as u8);
+        for transition in history {
+            bytes.push(transition.get_state().to_byte()[0]);
+            bytes.extend_from_slice(&transition.get_at().to_be_bytes());
+        }
+        with_version_header(INCIDENT_VERSION, bytes)
     }
 
     pub fn get_id(&self) -> u8 {
@@ -58,40 +183,57 @@ impl Incident {
         IncidentInfo::new(self.id, self.source)
     }
 
+    /// Obtiene el incidente a partir de bytes, con o sin encabezado de versión. Ignora los campos de versiones más
+    /// nuevas que no conoce, y usa valores por defecto para los que agregaron versiones posteriores a la recibida.
     pub fn from_bytes(msg_bytes: Vec<u8>) -> Result<Self, Error> {
-        let id = msg_bytes[0];
-        let latitude = f64::from_le_bytes([
-            msg_bytes[1],
-            msg_bytes[2],
-            msg_bytes[3],
-            msg_bytes[4],
-            msg_bytes[5],
-            msg_bytes[6],
-            msg_bytes[7],
-            msg_bytes[8],
-        ]);
-        let longitude = f64::from_le_bytes([
-            msg_bytes[9],
-            msg_bytes[10],
-            msg_bytes[11],
-            msg_bytes[12],
-            msg_bytes[13],
-            msg_bytes[14],
-            msg_bytes[15],
-            msg_bytes[16],
-        ]);
-        
-        let state = IncidentState::from_byte([msg_bytes[17]])?;
-
-        let source = IncidentSource::from_byte([msg_bytes[18]])?;
-
-        Ok(Self {
+        let (_version, mut reader) = split_version_header(&msg_bytes);
+        let id = reader.read_u8()?;
+        let latitude = reader.read_f64_le()?;
+        let longitude = reader.read_f64_le()?;
+        let position =
+            GeoPosition::new(latitude, longitude).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        let state = IncidentState::from_byte([reader.read_u8()?])?;
+
+        let source = IncidentSource::from_byte([reader.read_u8()?])?;
+
+        // Los incidentes publicados antes de que existieran las prioridades no la incluyen.
+        let priority = match reader.has_remaining() {
+            true => IncidentPriority::from_byte([reader.read_u8()?])?,
+            false => IncidentPriority::default(),
+        };
+
+        // Los incidentes de versiones anteriores a la 2 no incluyen los campos siguientes.
+        let mut incident = Self {
             id,
-            latitude,
-            longitude,
+            position,
             state,
             source,
-        })
+            priority,
+            severity: IncidentSeverity::default(),
+            category: IncidentCategory::default(),
+            description: String::new(),
+            created_at: 0,
+            resolved_at: None,
+            history: vec![],
+        };
+        if reader.has_remaining() {
+            incident.severity = IncidentSeverity::from_byte([reader.read_u8()?])?;
+            incident.category = IncidentCategory::from_byte([reader.read_u8()?])?;
+            incident.created_at = reader.read_u64_be()?;
+            incident.resolved_at = Some(reader.read_u64_be()?).filter(|resolved_at| *resolved_at != 0);
+            let len = reader.read_u8()? as usize;
+            incident.description = String::from_utf8(reader.read_bytes(len)?.to_vec())
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Descripción de incidente no válida."))?;
+        }
+        // Los de versiones anteriores a la 3 no incluyen el historial.
+        if reader.has_remaining() {
+            for _ in 0..reader.read_u8()? {
+                let state = IncidentState::from_byte([reader.read_u8()?])?;
+                incident.history.push(IncidentTransition::new(state, reader.read_u64_be()?));
+            }
+        }
+        Ok(incident)
     }
 
     /// Devuelve el estado del incidente.
@@ -102,28 +244,193 @@ impl Incident {
     pub fn get_source(&self) -> &IncidentSource {
         &self.source
     }
+
+    /// Devuelve la prioridad del incidente.
+    pub fn get_priority(&self) -> IncidentPriority {
+        self.priority
+    }
+
+    pub fn get_severity(&self) -> IncidentSeverity {
+        self.severity
+    }
+
+    pub fn get_category(&self) -> IncidentCategory {
+        self.category
+    }
+
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    /// Devuelve el instante en que se creó el incidente, en milisegundos desde el epoch (0 si no se conoce).
+    pub fn get_created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    /// Devuelve el instante en que se resolvió el incidente, en milisegundos desde el epoch, si ya se resolvió.
+    pub fn get_resolved_at(&self) -> Option<u64> {
+        self.resolved_at
+    }
+
+    /// Devuelve los cambios de estado del incidente, en orden, empezando por su creación. Los incidentes recibidos
+    /// de versiones anteriores no lo tienen.
+    pub fn get_history(&self) -> &[IncidentTransition] {
+        &self.history
+    }
+
+    /// Devuelve qué tan urgente es atender al incidente: primero por prioridad, y a igual prioridad, por gravedad.
+    pub fn get_urgency(&self) -> (IncidentPriority, IncidentSeverity) {
+        (self.priority, self.severity)
+    }
+}
+
+/// Devuelve el instante actual, en milisegundos desde el epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
+/// Devuelve los primeros `MAX_DESCRIPTION_LEN` bytes de `description`, sin cortar un caracter a la mitad.
+fn truncate_description(description: &str) -> &str {
+    let mut len = description.len().min(MAX_DESCRIPTION_LEN);
+    while !description.is_char_boundary(len) {
+        len -= 1;
+    }
+    &description[..len]
+}
+
 // hacer test de los metodos from_bytes y to_bytes
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apps::sist_dron::handoff::HandoffRequest;
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::MAX_REMAINING_LENGTH};
 
     #[test]
     fn test_reverse_to_bytes() {
         let incident = Incident {
             id: 1,
-            latitude: 2.0,
-            longitude: 2.0,
-            state: IncidentState::ActiveIncident,
+            position: GeoPosition::new(2.0, 2.0).unwrap(),
+            state: IncidentState::Resolved,
             source: IncidentSource::Manual,
+            priority: IncidentPriority::High,
+            severity: IncidentSeverity::Critical,
+            category: IncidentCategory::Fire,
+            description: "Incendio en el tercer piso".to_string(),
+            created_at: 1_700_000_000_000,
+            resolved_at: Some(1_700_000_060_000),
+            history: vec![
+                IncidentTransition::new(IncidentState::Reported, 1_700_000_000_000),
+                IncidentTransition::new(IncidentState::Resolved, 1_700_000_060_000),
+            ],
         };
         let bytes = incident.to_bytes();
         let incident_bytes = Incident::from_bytes(bytes).unwrap();
         assert_eq!(incident_bytes.id, incident.id);
-        assert_eq!(incident_bytes.latitude, incident.latitude);
-        assert_eq!(incident_bytes.longitude, incident.longitude);
+        assert_eq!(incident_bytes.position, incident.position);
         assert_eq!(incident_bytes.state, incident.state);
+        assert_eq!(incident_bytes.priority, incident.priority);
+        assert_eq!(incident_bytes.severity, incident.severity);
+        assert_eq!(incident_bytes.category, incident.category);
+        assert_eq!(incident_bytes.description, incident.description);
+        assert_eq!(incident_bytes.created_at, incident.created_at);
+        assert_eq!(incident_bytes.resolved_at, incident.resolved_at);
+        assert_eq!(incident_bytes.history, incident.history);
+    }
+
+    #[test]
+    fn test_incidente_sin_prioridad_tiene_la_prioridad_por_defecto() {
+        let incident = Incident::new(1, GeoPosition::new(2.0, 2.0).unwrap(), IncidentSource::Manual)
+            .with_priority(IncidentPriority::Low);
+        // Sin el historial (con la creación), los campos de la versión 2 (gravedad, tipo, instantes y descripción
+        // vacía), ni la prioridad.
+        let mut bytes = incident.to_bytes();
+        bytes.truncate(bytes.len() - 10 - 20);
+
+        let incident_bytes = Incident::from_bytes(bytes).unwrap();
+        assert_eq!(incident_bytes.get_priority(), IncidentPriority::Medium);
     }
-}
 
+    #[test]
+    fn test_incidente_de_version_1_tiene_gravedad_y_tipo_por_defecto() {
+        let incident = Incident::new(1, GeoPosition::new(2.0, 2.0).unwrap(), IncidentSource::Manual)
+            .with_severity(IncidentSeverity::Serious)
+            .with_category(IncidentCategory::Medical)
+            .with_description(&"á".repeat(200));
+        // La descripción se recorta sin partir ninguna "á" (de 2 bytes).
+        assert_eq!(incident.get_description().len(), MAX_DESCRIPTION_LEN / 2 * 2);
+        let mut bytes = incident.to_bytes();
+        bytes.truncate(bytes.len() - 10 - 19 - incident.get_description().len());
+
+        let incident_bytes = Incident::from_bytes(bytes).unwrap();
+        assert_eq!(incident_bytes.get_urgency(), (IncidentPriority::Medium, IncidentSeverity::Moderate));
+        assert_eq!(incident_bytes.get_category(), IncidentCategory::Other);
+        assert_eq!(incident_bytes.get_description(), "");
+        assert_eq!(incident_bytes.get_resolved_at(), None);
+    }
+    #[test]
+    fn test_el_incidente_cambia_de_estado_segun_su_ciclo_de_vida() {
+        let mut incident = Incident::new(1, GeoPosition::new(2.0, 2.0).unwrap(), IncidentSource::Manual);
+        assert!(incident.transition_to(IncidentState::Resolved).is_err());
+        incident.transition_to(IncidentState::Assigned).unwrap();
+        incident.transition_to(IncidentState::InAttention).unwrap();
+        assert!(!incident.is_closed());
+        incident.transition_to(IncidentState::Resolved).unwrap();
+        assert!(incident.is_resolved() && incident.is_closed());
+        assert!(incident.transition_to(IncidentState::Cancelled).is_err());
+
+        let states: Vec<IncidentState> = incident.get_history().iter().map(|t| t.get_state()).collect();
+        assert_eq!(
+            states,
+            vec![
+                IncidentState::Reported,
+                IncidentState::Assigned,
+                IncidentState::InAttention,
+                IncidentState::Resolved
+            ]
+        );
+        assert_eq!(incident.get_resolved_at(), Some(incident.get_history()[3].get_at()));
+        let received = Incident::from_bytes(incident.to_bytes()).unwrap();
+        assert_eq!(received.get_history(), incident.get_history());
+    }
+
+    #[test]
+    fn test_el_incidente_mas_grande_entra_en_un_publish() {
+        let mut incident = Incident::new(1, GeoPosition::new(-34.6, -58.4).unwrap(), IncidentSource::Manual)
+            .with_description(&"x".repeat(300));
+        assert_eq!(incident.get_description().len(), MAX_DESCRIPTION_LEN);
+        incident.transition_to(IncidentState::Assigned).unwrap();
+        incident.transition_to(IncidentState::InAttention).unwrap();
+        incident.transition_to(IncidentState::Resolved).unwrap();
+        assert_eq!(incident.get_history().len(), MAX_SERIALIZED_TRANSITIONS);
+
+        // Por `inc`, y dentro del pedido de relevo, el mensaje más grande que lo incluye.
+        let handoff = HandoffRequest::new(incident.clone(), 2, Some(3));
+        for (topic, payload) in [("inc", incident.to_bytes()), ("dron-handoff", handoff.to_bytes())] {
+            let flags = PublishFlags::new(0, 1, 0).unwrap();
+            let bytes = PublishMessage::new(flags, topic, Some(1), &payload).unwrap().to_bytes();
+            assert!(bytes.len() - 2 <= MAX_REMAINING_LENGTH);
+            assert_eq!(bytes[1] as usize, bytes.len() - 2);
+            assert_eq!(PublishMessage::from_bytes(bytes).unwrap().get_payload(), payload);
+        }
+        let received = Incident::from_bytes(incident.to_bytes()).unwrap();
+        assert_eq!(received.get_description(), incident.get_description());
+        assert_eq!(received.get_history(), incident.get_history());
+    }
+
+    #[test]
+    fn test_se_incluyen_solamente_los_ultimos_cambios_de_estado_y_la_descripcion_maxima() {
+        let mut incident = Incident::new(1, GeoPosition::new(2.0, 2.0).unwrap(), IncidentSource::Manual);
+        // Un incidente leído de otro lado puede tener un historial más largo, o una descripción más larga.
+        incident.description = "y".repeat(MAX_DESCRIPTION_LEN + 10);
+        incident.history = (0..7)
+            .map(|at| IncidentTransition::new(IncidentState::Assigned, at))
+            .collect();
+
+        let received = Incident::from_bytes(incident.to_bytes()).unwrap();
+        assert_eq!(received.get_description().len(), MAX_DESCRIPTION_LEN);
+        assert_eq!(received.get_history(), &incident.get_history()[7 - MAX_SERIALIZED_TRANSITIONS..]);
+    }
+}
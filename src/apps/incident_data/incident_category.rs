@@ -0,0 +1,75 @@
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Tipo de incidente. Los incidentes de los que no se sabe el tipo son de tipo `Other`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum IncidentCategory {
+    #[default]
+    Other,
+    Fire,
+    Intrusion,
+    Medical,
+    Accident,
+}
+
+impl IncidentCategory {
+    /// Todos los tipos de incidente.
+    pub const ALL: [IncidentCategory; 5] = [
+        IncidentCategory::Other,
+        IncidentCategory::Fire,
+        IncidentCategory::Intrusion,
+        IncidentCategory::Medical,
+        IncidentCategory::Accident,
+    ];
+
+    pub fn to_byte(&self) -> [u8; 1] {
+        match self {
+            IncidentCategory::Other => 1_u8.to_be_bytes(),
+            IncidentCategory::Fire => 2_u8.to_be_bytes(),
+            IncidentCategory::Intrusion => 3_u8.to_be_bytes(),
+            IncidentCategory::Medical => 4_u8.to_be_bytes(),
+            IncidentCategory::Accident => 5_u8.to_be_bytes(),
+        }
+    }
+
+    pub fn from_byte(byte: [u8; 1]) -> Result<Self, Error> {
+        match u8::from_be_bytes(byte) {
+            1 => Ok(IncidentCategory::Other),
+            2 => Ok(IncidentCategory::Fire),
+            3 => Ok(IncidentCategory::Intrusion),
+            4 => Ok(IncidentCategory::Medical),
+            5 => Ok(IncidentCategory::Accident),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Tipo de incidente no válido",
+            )),
+        }
+    }
+
+    /// Devuelve el nombre del tipo, para mostrarlo en la interfaz.
+    pub fn to_str(&self) -> &str {
+        match self {
+            IncidentCategory::Other => "Otro",
+            IncidentCategory::Fire => "Incendio",
+            IncidentCategory::Intrusion => "Intrusión",
+            IncidentCategory::Medical => "Emergencia médica",
+            IncidentCategory::Accident => "Accidente",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentCategory;
+
+    #[test]
+    fn test_1_incident_category_to_and_from_bytes_works() {
+        for category in IncidentCategory::ALL {
+            assert_eq!(
+                category,
+                IncidentCategory::from_byte(category.to_byte()).unwrap()
+            );
+        }
+        assert!(IncidentCategory::from_byte([6]).is_err());
+    }
+}
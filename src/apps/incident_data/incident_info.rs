@@ -1,9 +1,10 @@
 use std::io::Error;
+use serde::{Deserialize, Serialize};
 
 use super::incident_source::IncidentSource;
 
 /// Este struct se utiliza como clave en hashmaps para identificar a un Incident.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct IncidentInfo {
     inc_id: u8,
     src: IncidentSource,
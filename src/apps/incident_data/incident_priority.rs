@@ -0,0 +1,64 @@
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Prioridad de un incidente. Los drones pueden dejar de atender un incidente para atender uno de mayor prioridad,
+/// según su política de desalojo.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum IncidentPriority {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl IncidentPriority {
+    pub fn to_byte(&self) -> [u8; 1] {
+        match self {
+            IncidentPriority::Low => 1_u8.to_be_bytes(),
+            IncidentPriority::Medium => 2_u8.to_be_bytes(),
+            IncidentPriority::High => 3_u8.to_be_bytes(),
+        }
+    }
+
+    pub fn from_byte(byte: [u8; 1]) -> Result<Self, Error> {
+        match u8::from_be_bytes(byte) {
+            1 => Ok(IncidentPriority::Low),
+            2 => Ok(IncidentPriority::Medium),
+            3 => Ok(IncidentPriority::High),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Prioridad de incidente no válida",
+            )),
+        }
+    }
+
+    /// Devuelve el nombre de la prioridad, para mostrarlo en la interfaz.
+    pub fn to_str(&self) -> &str {
+        match self {
+            IncidentPriority::Low => "Baja",
+            IncidentPriority::Medium => "Media",
+            IncidentPriority::High => "Alta",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentPriority;
+
+    #[test]
+    fn test_1_incident_priority_to_and_from_bytes_works() {
+        for priority in [
+            IncidentPriority::Low,
+            IncidentPriority::Medium,
+            IncidentPriority::High,
+        ] {
+            assert_eq!(
+                priority,
+                IncidentPriority::from_byte(priority.to_byte()).unwrap()
+            );
+        }
+        assert!(IncidentPriority::High > IncidentPriority::Medium);
+        assert!(IncidentPriority::from_byte([0]).is_err());
+    }
+}
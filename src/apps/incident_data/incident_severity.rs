@@ -0,0 +1,71 @@
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Gravedad de un incidente. A igual prioridad, los drones atienden primero a los incidentes más graves.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum IncidentSeverity {
+    Minor,
+    #[default]
+    Moderate,
+    Serious,
+    Critical,
+}
+
+impl IncidentSeverity {
+    /// Todas las gravedades, de menor a mayor.
+    pub const ALL: [IncidentSeverity; 4] = [
+        IncidentSeverity::Minor,
+        IncidentSeverity::Moderate,
+        IncidentSeverity::Serious,
+        IncidentSeverity::Critical,
+    ];
+
+    pub fn to_byte(&self) -> [u8; 1] {
+        match self {
+            IncidentSeverity::Minor => 1_u8.to_be_bytes(),
+            IncidentSeverity::Moderate => 2_u8.to_be_bytes(),
+            IncidentSeverity::Serious => 3_u8.to_be_bytes(),
+            IncidentSeverity::Critical => 4_u8.to_be_bytes(),
+        }
+    }
+
+    pub fn from_byte(byte: [u8; 1]) -> Result<Self, Error> {
+        match u8::from_be_bytes(byte) {
+            1 => Ok(IncidentSeverity::Minor),
+            2 => Ok(IncidentSeverity::Moderate),
+            3 => Ok(IncidentSeverity::Serious),
+            4 => Ok(IncidentSeverity::Critical),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Gravedad de incidente no válida",
+            )),
+        }
+    }
+
+    /// Devuelve el nombre de la gravedad, para mostrarlo en la interfaz.
+    pub fn to_str(&self) -> &str {
+        match self {
+            IncidentSeverity::Minor => "Leve",
+            IncidentSeverity::Moderate => "Moderada",
+            IncidentSeverity::Serious => "Grave",
+            IncidentSeverity::Critical => "Crítica",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentSeverity;
+
+    #[test]
+    fn test_1_incident_severity_to_and_from_bytes_works() {
+        for severity in IncidentSeverity::ALL {
+            assert_eq!(
+                severity,
+                IncidentSeverity::from_byte(severity.to_byte()).unwrap()
+            );
+        }
+        assert!(IncidentSeverity::Critical > IncidentSeverity::Serious);
+        assert!(IncidentSeverity::from_byte([0]).is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
 
 /// Representa el origen en el que se generó el incidente:
 /// puede ser `Manual`, si fue generado manualmente desde la ui de sistema de monitoreo;
 /// o `Automated` si se generó automáticamente mediante inteligencia artificial en sistema cámaras.
-#[derive(Debug, PartialEq, Clone, Copy, Hash, Eq)]
+#[derive(Debug, PartialEq, Clone, Copy, Hash, Eq, Serialize, Deserialize)]
 pub enum IncidentSource {
     Manual,
     Automated,
@@ -1,27 +1,99 @@
 use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Clone)]
+/// Estado de un incidente. Un incidente se reporta, se le asignan drones, los drones llegan y lo atienden, y por
+/// último se resuelve; en cualquier momento antes de resolverse, se lo puede cancelar.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum IncidentState {
-    ActiveIncident,
-    ResolvedIncident,
+    #[serde(alias = "ActiveIncident")]
+    Reported,
+    Assigned,
+    InAttention,
+    #[serde(alias = "ResolvedIncident")]
+    Resolved,
+    Cancelled,
 }
 
 impl IncidentState {
+    // Los valores 1 y 2 son los que usaban los estados activo y resuelto, antes de que existieran los demás.
     pub fn to_byte(&self) -> [u8; 1] {
         match self {
-            IncidentState::ActiveIncident => 1_u8.to_be_bytes(),
-            IncidentState::ResolvedIncident => 2_u8.to_be_bytes(),
+            IncidentState::Reported => 1_u8.to_be_bytes(),
+            IncidentState::Resolved => 2_u8.to_be_bytes(),
+            IncidentState::Assigned => 3_u8.to_be_bytes(),
+            IncidentState::InAttention => 4_u8.to_be_bytes(),
+            IncidentState::Cancelled => 5_u8.to_be_bytes(),
         }
     }
 
     pub fn from_byte(byte: [u8; 1]) -> Result<Self, Error> {
         match u8::from_be_bytes(byte) {
-            1 => Ok(IncidentState::ActiveIncident),
-            2 => Ok(IncidentState::ResolvedIncident),
+            1 => Ok(IncidentState::Reported),
+            2 => Ok(IncidentState::Resolved),
+            3 => Ok(IncidentState::Assigned),
+            4 => Ok(IncidentState::InAttention),
+            5 => Ok(IncidentState::Cancelled),
             _ => Err(Error::new(
                 ErrorKind::Other,
                 "Estado de incidente no válido",
             )),
         }
     }
+
+    /// Devuelve si el incidente puede pasar de este estado a `next`: Reported → Assigned → InAttention → Resolved,
+    /// o a Cancelled desde cualquiera de los que no son finales.
+    pub fn can_transition_to(&self, next: IncidentState) -> bool {
+        matches!(
+            (self, next),
+            (IncidentState::Reported, IncidentState::Assigned)
+                | (IncidentState::Assigned, IncidentState::InAttention)
+                | (IncidentState::InAttention, IncidentState::Resolved)
+                | (
+                    IncidentState::Reported | IncidentState::Assigned | IncidentState::InAttention,
+                    IncidentState::Cancelled
+                )
+        )
+    }
+
+    /// Devuelve si el estado es final, ie el incidente ya no necesita ser atendido.
+    pub fn is_final(&self) -> bool {
+        matches!(self, IncidentState::Resolved | IncidentState::Cancelled)
+    }
+
+    /// Devuelve el nombre del estado, para mostrarlo en la interfaz.
+    pub fn to_str(&self) -> &str {
+        match self {
+            IncidentState::Reported => "Reportado",
+            IncidentState::Assigned => "Asignado",
+            IncidentState::InAttention => "En atención",
+            IncidentState::Resolved => "Resuelto",
+            IncidentState::Cancelled => "Cancelado",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentState;
+
+    #[test]
+    fn test_1_solamente_se_permiten_las_transiciones_del_ciclo_de_vida() {
+        let lifecycle = [
+            IncidentState::Reported,
+            IncidentState::Assigned,
+            IncidentState::InAttention,
+            IncidentState::Resolved,
+        ];
+        for pair in lifecycle.windows(2) {
+            assert!(pair[0].can_transition_to(pair[1]));
+            assert!(!pair[1].can_transition_to(pair[0]));
+            assert_eq!(IncidentState::from_byte(pair[0].to_byte()).unwrap(), pair[0]);
+        }
+        assert!(!IncidentState::Reported.can_transition_to(IncidentState::Resolved));
+        assert!(IncidentState::Assigned.can_transition_to(IncidentState::Cancelled));
+        assert!(!IncidentState::Resolved.can_transition_to(IncidentState::Cancelled));
+        assert!(IncidentState::Cancelled.is_final() && !IncidentState::InAttention.is_final());
+        // Los incidentes activos de antes se leen como reportados.
+        assert_eq!(serde_json::from_str::<IncidentState>("\"ActiveIncident\"").unwrap(), IncidentState::Reported);
+    }
 }
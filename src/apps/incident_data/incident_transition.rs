@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use super::incident_state::IncidentState;
+
+/// Cambio de estado de un incidente: a qué estado pasó, y cuándo (en milisegundos desde el epoch).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct IncidentTransition {
+    state: IncidentState,
+    at: u64,
+}
+
+impl IncidentTransition {
+    pub fn new(state: IncidentState, at: u64) -> Self {
+        Self { state, at }
+    }
+
+    pub fn get_state(&self) -> IncidentState {
+        self.state
+    }
+
+    pub fn get_at(&self) -> u64 {
+        self.at
+    }
+}
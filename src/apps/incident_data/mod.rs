@@ -1,4 +1,8 @@
 pub mod incident;
 pub mod incident_state;
 pub mod incident_source;
-pub mod incident_info;
\ No newline at end of file
+pub mod incident_info;
+pub mod incident_priority;
+pub mod incident_severity;
+pub mod incident_category;
+pub mod incident_transition;
@@ -0,0 +1,219 @@
+use std::{
+    io::Error,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        incident_data::{incident::Incident, incident_source::IncidentSource},
+        sist_camaras::{camera::Camera, camera_state::CameraState},
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState},
+    },
+    logging::string_logger::StringLogger,
+    mqtt::client::mqtt_client::MQTTClient,
+};
+
+use super::{latency_tracker::LatencyTracker, load_settings::LoadSettings};
+
+/// Primer id de los incidentes, cámaras y drones sintéticos, para no pisar los de las apps (que empiezan en 1).
+pub const FIRST_SYNTHETIC_ID: u8 = 200;
+/// Rango de las cámaras sintéticas, en cuadras, como el de las cámaras de `cameras.properties`.
+const SYNTHETIC_CAMERA_RANGE: u8 = 1;
+/// Prefijo de los topics en los que se publica la carga, salvo que se configure `production-topics`.
+pub const LOAD_TOPIC_PREFIX: &str = "load/";
+/// Marca del número de secuencia que se agrega al final de cada mensaje sintético.
+const SEQUENCE_MARKER: [u8; 2] = [0x4C, 0x47];
+const SEQUENCE_TRAILER_LEN: usize = SEQUENCE_MARKER.len() + 4;
+
+/// Tipo de mensaje que publica el generador de carga.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoadStream {
+    Incidents,
+    Cameras,
+    Drones,
+}
+
+impl LoadStream {
+    /// Devuelve el topic por el que se publica el tipo de mensaje: el de las apps si `production_topics`, o si no
+    /// el mismo con el prefijo `LOAD_TOPIC_PREFIX` (ie `load/inc`).
+    pub fn get_topic(&self, production_topics: bool) -> String {
+        let topic = match self {
+            LoadStream::Incidents => AppsMqttTopics::IncidentTopic,
+            LoadStream::Cameras => AppsMqttTopics::CameraTopic,
+            LoadStream::Drones => AppsMqttTopics::DronTopic,
+        };
+        match production_topics {
+            true => topic.to_str().to_string(),
+            false => format!("{}{}", LOAD_TOPIC_PREFIX, topic.to_str()),
+        }
+    }
+}
+
+/// Devuelve el número de secuencia que el generador agregó al final de `payload`, o None si no es un mensaje del
+/// generador. Como las apps ignoran los campos que no conocen al final de un payload, lo pueden leer igual.
+pub fn sequence_of(payload: &[u8]) -> Option<u32> {
+    let trailer = payload.len().checked_sub(SEQUENCE_TRAILER_LEN).map(|start| &payload[start..])?;
+    match trailer {
+        [m0, m1, seq @ ..] if [*m0, *m1] == SEQUENCE_MARKER => seq.try_into().ok().map(u32::from_be_bytes),
+        _ => None,
+    }
+}
+
+/// Publica incidentes, cámaras y posiciones de drones sintéticos al broker, a las tasas y en las posiciones de su
+/// configuración, y registra cuándo publicó cada mensaje para medir cuánto tarda en volver.
+#[derive(Debug)]
+pub struct LoadGenerator {
+    settings: LoadSettings,
+    rng: StdRng,
+    tracker: Arc<Mutex<LatencyTracker>>,
+    logger: StringLogger,
+}
+
+impl LoadGenerator {
+    pub fn new(settings: LoadSettings, tracker: Arc<Mutex<LatencyTracker>>, logger: StringLogger) -> Self {
+        let rng = match settings.get_seed() {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            settings,
+            rng,
+            tracker,
+            logger,
+        }
+    }
+
+    /// Arma el mensaje número `seq` del tipo `stream`, en una posición al azar según la distribución configurada,
+    /// con `seq` al final para identificarlo al recibirlo. Los ids se reparten entre `FIRST_SYNTHETIC_ID` y 255.
+    pub fn next_message(&mut self, stream: LoadStream, seq: u32) -> Vec<u8> {
+        let id = FIRST_SYNTHETIC_ID + (seq % (u8::MAX - FIRST_SYNTHETIC_ID + 1) as u32) as u8;
+        let position = self.settings.get_distribution().sample(&mut self.rng);
+        let mut payload = match stream {
+            LoadStream::Incidents => Incident::new(id, position, IncidentSource::Automated).to_bytes(),
+            LoadStream::Cameras => {
+                let mut camera = Camera::new(id, position, SYNTHETIC_CAMERA_RANGE);
+                if self.rng.gen_bool(0.5) {
+                    camera.set_state_to(CameraState::Active);
+                }
+                camera.to_bytes()
+            }
+            LoadStream::Drones => {
+                let battery = self.rng.gen_range(20..=100);
+                DronCurrentInfo::new(id, position, battery, DronState::Flying).to_bytes()
+            }
+        };
+        payload.extend_from_slice(&SEQUENCE_MARKER);
+        payload.extend_from_slice(&seq.to_be_bytes());
+        payload
+    }
+
+    /// Publica los mensajes de cada tipo a su tasa, intercalados, durante el tiempo configurado.
+    /// Devuelve cuántos mensajes publicó.
+    pub fn run(&mut self, mqtt_client: &Mutex<MQTTClient>) -> Result<usize, Error> {
+        let (incidents, cameras, drones) = self.settings.get_rates();
+        let start = Instant::now();
+        let end = start + self.settings.get_duration();
+        // Cuándo toca publicar el siguiente mensaje de cada tipo, y cuántos se publicaron
+        let mut streams: Vec<(LoadStream, Duration, Instant, u32)> = [
+            (LoadStream::Incidents, incidents),
+            (LoadStream::Cameras, cameras),
+            (LoadStream::Drones, drones),
+        ]
+        .into_iter()
+        .filter(|(_, rate)| *rate > 0.0)
+        .map(|(stream, rate)| (stream, Duration::from_secs_f64(1.0 / rate), start, 0))
+        .collect();
+
+        let mut published = 0;
+        while let Some(next) = streams.iter_mut().min_by_key(|(_, _, due, _)| *due) {
+            let (stream, interval, due, seq) = next;
+            if *due >= end {
+                break;
+            }
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+
+            let payload = self.next_message(*stream, *seq);
+            let topic = stream.get_topic(self.settings.uses_production_topics());
+            if let Ok(mut tracker) = self.tracker.lock() {
+                tracker.record_sent(&topic, *seq, Instant::now());
+            }
+            match mqtt_client.lock() {
+                Ok(mut client) => client.mqtt_publish(&topic, &payload, self.settings.get_qos())?,
+                Err(_) => {
+                    self.logger.log("Generador: error al tomar lock del cliente mqtt.".to_string());
+                    break;
+                }
+            };
+            published += 1;
+            *seq += 1;
+            *due += *interval;
+        }
+        self.logger
+            .log(format!("Generador: publicados {} mensajes en {:?}.", published, start.elapsed()));
+        Ok(published)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{sequence_of, LoadGenerator, LoadStream, FIRST_SYNTHETIC_ID};
+    use crate::{
+        apps::{
+            geo_position::GeoPosition,
+            incident_data::incident::Incident,
+            load_generator::{
+                latency_tracker::LatencyTracker,
+                load_settings::{GeoDistribution, LoadSettings},
+            },
+            sist_camaras::camera::Camera,
+            sist_dron::dron_current_info::DronCurrentInfo,
+        },
+        logging::string_logger::StringLogger,
+    };
+
+    #[test]
+    fn test_1_los_mensajes_sinteticos_caen_en_la_zona_con_ids_reservados() {
+        let center = GeoPosition::new(-34.6037, -58.3816).unwrap();
+        let hotspot = GeoDistribution::Hotspot { center, radius: 300.0 };
+        let settings = LoadSettings::new(1.0, 1.0, 1.0, Duration::from_secs(1), hotspot, 1, Some(7)).unwrap();
+        assert!(LoadSettings::new(-1.0, 1.0, 1.0, Duration::from_secs(1), hotspot, 1, None).is_err());
+        let (tx, _rx) = mpsc::channel();
+        let tracker = Arc::new(Mutex::new(LatencyTracker::new()));
+        let mut generator = LoadGenerator::new(settings, tracker, StringLogger::new(tx));
+        let near = |position: GeoPosition| position.distance_to(&center) <= 300.0;
+
+        let payload = generator.next_message(LoadStream::Incidents, 60);
+        assert_eq!(sequence_of(&payload), Some(60));
+        let incident = Incident::from_bytes(payload).unwrap();
+        assert_eq!(incident.get_id(), FIRST_SYNTHETIC_ID + 4);
+        assert!(near(incident.get_position()));
+        let camera = Camera::from_bytes(&generator.next_message(LoadStream::Cameras, 0)).unwrap();
+        assert_eq!(camera.get_id(), FIRST_SYNTHETIC_ID);
+        assert!(near(camera.get_position()));
+        let dron = DronCurrentInfo::from_bytes(generator.next_message(LoadStream::Drones, 1)).unwrap();
+        assert_eq!(dron.get_id(), FIRST_SYNTHETIC_ID + 1);
+        assert!(near(dron.get_current_position()));
+        // Los mensajes de las apps no tienen número de secuencia.
+        assert_eq!(sequence_of(&incident.to_bytes()), None);
+    }
+
+    #[test]
+    fn test_2_por_defecto_se_publica_en_los_topics_de_carga() {
+        let settings = LoadSettings::from_file("load_generator.properties").unwrap();
+        assert!(!settings.uses_production_topics());
+        assert_eq!(LoadStream::Incidents.get_topic(false), "load/inc");
+        assert_eq!(LoadStream::Drones.get_topic(false), "load/dron");
+        assert_eq!(LoadStream::Cameras.get_topic(true), "cam");
+        assert!(settings.with_production_topics(true).uses_production_topics());
+    }
+}
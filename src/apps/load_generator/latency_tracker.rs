@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Latencias de punta a punta de los mensajes de un topic: cuántos se enviaron, cuántos volvieron del broker, y los
+/// percentiles del tiempo entre el envío y la recepción.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TopicLatency {
+    topic: String,
+    sent: usize,
+    received: usize,
+    p50: Duration,
+    p90: Duration,
+    p99: Duration,
+    max: Duration,
+}
+
+impl TopicLatency {
+    pub fn get_topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn get_sent(&self) -> usize {
+        self.sent
+    }
+
+    pub fn get_received(&self) -> usize {
+        self.received
+    }
+
+    /// Devuelve los percentiles 50, 90 y 99 de la latencia.
+    pub fn get_percentiles(&self) -> (Duration, Duration, Duration) {
+        (self.p50, self.p90, self.p99)
+    }
+
+    pub fn get_max(&self) -> Duration {
+        self.max
+    }
+}
+
+/// Mide cuánto tardan en volver del broker los mensajes publicados por el generador de carga, que está suscripto a
+/// los mismos topics. Cada mensaje se identifica por su topic y su número de secuencia en el mismo; los mensajes
+/// recibidos que no envió el generador (ie de otras apps) se ignoran.
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    in_flight: HashMap<(String, u32), Instant>, // (topic, número de secuencia) -> cuándo se envió
+    sent: HashMap<String, usize>,
+    latencies: HashMap<String, Vec<Duration>>,
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra que se publicó el mensaje número `seq` en `topic` en el instante `at`.
+    pub fn record_sent(&mut self, topic: &str, seq: u32, at: Instant) {
+        *self.sent.entry(topic.to_string()).or_default() += 1;
+        self.in_flight.insert((topic.to_string(), seq), at);
+    }
+
+    /// Registra que se recibió el mensaje número `seq` por `topic` en el instante `at`. Devuelve si era un mensaje
+    /// del generador que todavía no había vuelto.
+    pub fn record_received(&mut self, topic: &str, seq: u32, at: Instant) -> bool {
+        let key = (topic.to_string(), seq);
+        let Some(sent_at) = self.in_flight.remove(&key) else {
+            return false;
+        };
+        self.latencies
+            .entry(key.0)
+            .or_default()
+            .push(at.saturating_duration_since(sent_at));
+        true
+    }
+
+    /// Devuelve cuántos mensajes enviados todavía no se recibieron.
+    pub fn pending(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Devuelve las latencias de cada topic al que se publicó, ordenados por nombre.
+    pub fn report(&self) -> Vec<TopicLatency> {
+        let mut report: Vec<TopicLatency> = self
+            .sent
+            .iter()
+            .map(|(topic, sent)| {
+                let mut latencies = self.latencies.get(topic).cloned().unwrap_or_default();
+                latencies.sort();
+                TopicLatency {
+                    topic: topic.clone(),
+                    sent: *sent,
+                    received: latencies.len(),
+                    p50: percentile(&latencies, 50.0),
+                    p90: percentile(&latencies, 90.0),
+                    p99: percentile(&latencies, 99.0),
+                    max: latencies.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| a.topic.cmp(&b.topic));
+        report
+    }
+}
+
+/// Devuelve el percentil `p` de las latencias ordenadas `sorted`, por el método del rango más cercano.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::LatencyTracker;
+
+    #[test]
+    fn test_1_se_calculan_los_percentiles_de_latencia_por_topic() {
+        let mut tracker = LatencyTracker::new();
+        let start = Instant::now();
+        for i in 0..100 {
+            tracker.record_sent("inc", i, start);
+        }
+        tracker.record_sent("cam", 0, start);
+        // Los mensajes que no se enviaron, o que ya volvieron, se ignoran.
+        assert!(!tracker.record_received("inc", 100, start));
+        for i in 0..100 {
+            let at = start + Duration::from_millis(i as u64 + 1);
+            assert!(tracker.record_received("inc", i, at));
+        }
+        assert!(!tracker.record_received("inc", 0, start));
+        assert_eq!(tracker.pending(), 1);
+
+        let report = tracker.report();
+        assert_eq!(report.len(), 2);
+        let (cam, inc) = (&report[0], &report[1]);
+        assert_eq!((cam.get_topic(), cam.get_sent(), cam.get_received()), ("cam", 1, 0));
+        assert_eq!((inc.get_topic(), inc.get_sent(), inc.get_received()), ("inc", 100, 100));
+        assert_eq!(
+            inc.get_percentiles(),
+            (Duration::from_millis(50), Duration::from_millis(90), Duration::from_millis(99))
+        );
+        assert_eq!(inc.get_max(), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rustx::{
+    apps::{
+        common_clients::get_broker_address,
+        load_generator::{
+            generator::{sequence_of, LoadGenerator, LoadStream},
+            latency_tracker::LatencyTracker,
+            load_settings::LoadSettings,
+        },
+    },
+    logging::string_logger::StringLogger,
+    mqtt::{client::mqtt_client_builder::MqttClientBuilder, messages::publish_message::PublishMessage},
+};
+
+const PROPERTIES_FILE: &str = "load_generator.properties";
+/// Cuánto se espera, luego de publicar el último mensaje, a que vuelvan los que faltan.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn get_formatted_app_id() -> String {
+    String::from("Generador-Carga")
+}
+
+/// Recibe los mensajes que reenvía el broker, y registra cuánto tardaron los publicados por el generador, hasta que
+/// se indique `stop`.
+fn spawn_receive_thread(
+    publish_msg_rx: Receiver<PublishMessage>,
+    tracker: Arc<Mutex<LatencyTracker>>,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            if let Ok(msg) = publish_msg_rx.recv_timeout(DRAIN_POLL_INTERVAL) {
+                let received_at = Instant::now();
+                let Some(seq) = sequence_of(&msg.get_payload()) else {
+                    continue;
+                };
+                if let Ok(mut tracker) = tracker.lock() {
+                    tracker.record_received(&msg.get_topic(), seq, received_at);
+                }
+            }
+        }
+    })
+}
+
+/// Espera a que vuelvan todos los mensajes publicados, o hasta `DRAIN_TIMEOUT`.
+fn wait_for_pending(tracker: &Mutex<LatencyTracker>) {
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while Instant::now() < deadline {
+        match tracker.lock() {
+            Ok(tracker) if tracker.pending() > 0 => {}
+            _ => return,
+        }
+        thread::sleep(DRAIN_POLL_INTERVAL);
+    }
+}
+
+fn print_report(tracker: &Mutex<LatencyTracker>) {
+    let Ok(tracker) = tracker.lock() else {
+        println!("Error al tomar lock para mostrar las latencias.");
+        return;
+    };
+    println!("Topic | enviados | recibidos | p50 | p90 | p99 | máx");
+    for latency in tracker.report() {
+        let (p50, p90, p99) = latency.get_percentiles();
+        println!(
+            "{} | {} | {} | {:?} | {:?} | {:?} | {:?}",
+            latency.get_topic(),
+            latency.get_sent(),
+            latency.get_received(),
+            p50,
+            p90,
+            p99,
+            latency.get_max()
+        );
+    }
+}
+
+fn main() -> Result<(), Error> {
+    let broker_addr = get_broker_address();
+    let settings = LoadSettings::from_file(PROPERTIES_FILE)?;
+
+    // Se crean y configuran ambos extremos del string logger
+    let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id());
+
+    let (mut mqtt_client, publish_msg_rx, handle) =
+        MqttClientBuilder::new(&get_formatted_app_id()).connect(&broker_addr, logger.clone_ref())?;
+    println!("Conectado al broker MQTT.");
+
+    // Se suscribe a los topics a los que publica, para medir cuánto tarda el broker en reenviar cada mensaje
+    let topics = [LoadStream::Incidents, LoadStream::Cameras, LoadStream::Drones]
+        .iter()
+        .map(|stream| (stream.get_topic(settings.uses_production_topics()), settings.get_qos()))
+        .collect();
+    mqtt_client.mqtt_subscribe(topics)?;
+
+    let tracker = Arc::new(Mutex::new(LatencyTracker::new()));
+    let stop = Arc::new(AtomicBool::new(false));
+    let receive_handle = spawn_receive_thread(publish_msg_rx, tracker.clone(), stop.clone());
+
+    println!("Publicando durante {:?}...", settings.get_duration());
+    let mqtt_client = Mutex::new(mqtt_client);
+    let mut generator = LoadGenerator::new(settings, tracker.clone(), logger.clone_ref());
+    let res = generator.run(&mqtt_client);
+    wait_for_pending(&tracker);
+    stop.store(true, Ordering::Relaxed);
+
+    match res {
+        Ok(published) => {
+            println!("Publicados {} mensajes.", published);
+            print_report(&tracker);
+        }
+        Err(e) => println!("Error al publicar: {:?}", e),
+    }
+
+    if let Ok(mut mqtt_client) = mqtt_client.lock() {
+        if let Err(e) = mqtt_client.mqtt_disconnect() {
+            println!("Error al desconectarse: {:?}", e);
+        }
+    }
+    for child in [receive_handle, handle] {
+        if child.join().is_err() {
+            println!("Error al esperar al hijo.");
+        }
+    }
+    logger.stop_logging();
+    // porque le hicimos clone_ref al logger.
+    drop(generator);
+    drop(mqtt_client);
+
+    // Se espera al hijo para el logger writer
+    if handle_logger.join().is_err() {
+        println!("Error al esperar al hijo para string logger writer.")
+    }
+    Ok(())
+}
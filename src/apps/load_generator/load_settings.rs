@@ -0,0 +1,212 @@
+use std::{
+    f64::consts::TAU,
+    io::{Error, ErrorKind},
+    str::FromStr,
+    time::Duration,
+};
+
+use rand::Rng;
+
+use crate::apps::{geo_position::GeoPosition, properties::Properties, sist_dron::calculations::METERS_PER_DEGREE};
+
+/// Región por defecto en la que se generan las posiciones: el centro de la ciudad, como las cámaras.
+const DEFAULT_REGION: ((f64, f64), (f64, f64)) = ((-34.6150, -58.3950), (-34.5920, -58.3690));
+
+/// Cómo se distribuyen geográficamente las posiciones de los mensajes generados.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GeoDistribution {
+    /// Uniforme en el rectángulo entre las posiciones `min` y `max`.
+    Uniform { min: GeoPosition, max: GeoPosition },
+    /// Uniforme en el círculo de `radius` metros alrededor de `center` (ie muchos incidentes en una misma zona).
+    Hotspot { center: GeoPosition, radius: f64 },
+}
+
+impl GeoDistribution {
+    /// Devuelve una posición al azar según la distribución.
+    pub fn sample(&self, rng: &mut impl Rng) -> GeoPosition {
+        match *self {
+            GeoDistribution::Uniform { min, max } => min.offset(
+                rng.gen_range(0.0..=max.get_latitude() - min.get_latitude()),
+                rng.gen_range(0.0..=max.get_longitude() - min.get_longitude()),
+            ),
+            GeoDistribution::Hotspot { center, radius } => {
+                let distance = radius / METERS_PER_DEGREE * rng.gen_range(0.0..1.0f64).sqrt();
+                let angle = rng.gen_range(0.0..TAU);
+                center.offset(distance * angle.sin(), distance * angle.cos())
+            }
+        }
+    }
+}
+
+/// Configuración del generador de carga: cuántos mensajes por segundo publica de cada tipo (0 para no publicar
+/// ese tipo), durante cuánto tiempo, dónde, y con qué qos.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LoadSettings {
+    incidents_per_sec: f64,
+    cameras_per_sec: f64,
+    drones_per_sec: f64,
+    duration: Duration,
+    distribution: GeoDistribution,
+    qos: u8,
+    seed: Option<u64>, // para repetir la misma carga; si no, es al azar.
+    production_topics: bool,
+}
+
+impl LoadSettings {
+    /// Carga la configuración de `properties_file`:
+    /// - `incidents-per-sec`, `cameras-per-sec` y `drones-per-sec` (por defecto, 1, 5 y 10);
+    /// - `duration-secs` (por defecto, 30);
+    /// - `distribution`: `uniform` en la `region=lat_min,lon_min,lat_max,lon_max`, o `hotspot` en el círculo
+    ///   `hotspot=lat,lon,radio_en_metros`;
+    /// - `qos` (por defecto, 1) y `seed`;
+    /// - `production-topics`: si es `true`, publica en los topics de las apps en vez de en los `load/`.
+    pub fn from_file(properties_file: &str) -> Result<Self, Error> {
+        let properties = Properties::new(properties_file)?;
+        let distribution = match properties.get("distribution").map(String::as_str) {
+            None | Some("uniform") => {
+                let ((lat_min, lon_min), (lat_max, lon_max)) = match properties.get("region") {
+                    Some(region) => match parse_list(region, "region")?[..] {
+                        [lat_min, lon_min, lat_max, lon_max] => ((lat_min, lon_min), (lat_max, lon_max)),
+                        _ => return Err(invalid_property("region", region)),
+                    },
+                    None => DEFAULT_REGION,
+                };
+                let min = parse_position(lat_min, lon_min, "region")?;
+                let max = parse_position(lat_max, lon_max, "region")?;
+                GeoDistribution::Uniform { min, max }
+            }
+            Some("hotspot") => {
+                let hotspot = properties
+                    .get("hotspot")
+                    .ok_or_else(|| invalid_property("hotspot", ""))?;
+                match parse_list(hotspot, "hotspot")?[..] {
+                    [lat, lon, radius] => GeoDistribution::Hotspot {
+                        center: parse_position(lat, lon, "hotspot")?,
+                        radius,
+                    },
+                    _ => return Err(invalid_property("hotspot", hotspot)),
+                }
+            }
+            Some(other) => return Err(invalid_property("distribution", other)),
+        };
+        let production_topics = parse_or(&properties, "production-topics", false)?;
+        Self::new(
+            parse_or(&properties, "incidents-per-sec", 1.0)?,
+            parse_or(&properties, "cameras-per-sec", 5.0)?,
+            parse_or(&properties, "drones-per-sec", 10.0)?,
+            Duration::from_secs(parse_or(&properties, "duration-secs", 30)?),
+            distribution,
+            parse_or(&properties, "qos", 1)?,
+            properties
+                .get("seed")
+                .map(|seed| seed.parse().map_err(|_| invalid_property("seed", seed)))
+                .transpose()?,
+        )
+        .map(|settings| settings.with_production_topics(production_topics))
+    }
+
+    /// Crea la configuración. Devuelve error si alguna tasa es negativa, si la región no es válida, o si el qos no
+    /// es 0, 1 o 2.
+    pub fn new(
+        incidents_per_sec: f64,
+        cameras_per_sec: f64,
+        drones_per_sec: f64,
+        duration: Duration,
+        distribution: GeoDistribution,
+        qos: u8,
+        seed: Option<u64>,
+    ) -> Result<Self, Error> {
+        let rates_are_valid = [incidents_per_sec, cameras_per_sec, drones_per_sec]
+            .iter()
+            .all(|rate| rate.is_finite() && *rate >= 0.0);
+        if !rates_are_valid {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Las tasas de mensajes por segundo no pueden ser negativas.",
+            ));
+        }
+        let distribution_is_valid = match distribution {
+            GeoDistribution::Uniform { min, max } => {
+                min.get_latitude() <= max.get_latitude() && min.get_longitude() <= max.get_longitude()
+            }
+            GeoDistribution::Hotspot { radius, .. } => radius.is_finite() && radius >= 0.0,
+        };
+        if !distribution_is_valid {
+            return Err(Error::new(ErrorKind::InvalidInput, "Distribución geográfica inválida."));
+        }
+        if qos > 2 {
+            return Err(invalid_property("qos", &qos.to_string()));
+        }
+        Ok(Self {
+            incidents_per_sec,
+            cameras_per_sec,
+            drones_per_sec,
+            duration,
+            distribution,
+            qos,
+            seed,
+            production_topics: false,
+        })
+    }
+
+    /// Publica en los topics de las apps (`inc`, `cam` y `dron`), en vez de en los de carga. Por defecto no, para
+    /// que las apps conectadas al mismo broker no procesen los mensajes sintéticos.
+    pub fn with_production_topics(mut self, production_topics: bool) -> Self {
+        self.production_topics = production_topics;
+        self
+    }
+
+    /// Devuelve cuántos incidentes, cámaras y posiciones de drones por segundo se publican.
+    pub fn get_rates(&self) -> (f64, f64, f64) {
+        (self.incidents_per_sec, self.cameras_per_sec, self.drones_per_sec)
+    }
+
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn get_distribution(&self) -> GeoDistribution {
+        self.distribution
+    }
+
+    pub fn get_qos(&self) -> u8 {
+        self.qos
+    }
+
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn uses_production_topics(&self) -> bool {
+        self.production_topics
+    }
+}
+
+/// Devuelve el valor de la property `key` parseado, o `default` si no está definida.
+fn parse_or<T: FromStr>(properties: &Properties, key: &str, default: T) -> Result<T, Error> {
+    match properties.get(key) {
+        Some(value) => value.parse().map_err(|_| invalid_property(key, value)),
+        None => Ok(default),
+    }
+}
+
+/// Parsea una lista de números separados por coma.
+fn parse_list(value: &str, key: &str) -> Result<Vec<f64>, Error> {
+    value
+        .split(',')
+        .map(|number| number.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| invalid_property(key, value))
+}
+
+/// Crea la posición de la property `key`, o devuelve error si no es una posición válida.
+fn parse_position(latitude: f64, longitude: f64, key: &str) -> Result<GeoPosition, Error> {
+    GeoPosition::new(latitude, longitude).map_err(|_| invalid_property(key, &format!("{},{}", latitude, longitude)))
+}
+
+fn invalid_property(key: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Valor inválido para {}: {}.", key, value),
+    )
+}
@@ -0,0 +1,3 @@
+pub mod generator;
+pub mod latency_tracker;
+pub mod load_settings;
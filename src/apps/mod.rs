@@ -1,7 +1,11 @@
 pub mod apps_mqtt_topics;
 pub mod common_client_errors;
 pub mod common_clients;
+pub mod geo_position;
+pub mod load_generator;
 pub mod local_tiles;
+pub mod payload_codec;
+pub mod payload_version;
 pub mod places;
 pub mod plugins;
 pub mod properties;
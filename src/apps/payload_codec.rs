@@ -0,0 +1,247 @@
+use std::io::{Error, ErrorKind};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    apps_mqtt_topics::AppsMqttTopics,
+    incident_data::incident::Incident,
+    sist_camaras::{camera::Camera, cameras_snapshot::CamerasSnapshot},
+    sist_dron::dron_current_info::DronCurrentInfo,
+};
+
+/// Codifica y decodifica los payloads de tipo `T` que publican las aplicaciones.
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error>;
+    fn decode(&self, bytes: &[u8]) -> Result<T, Error>;
+}
+
+/// Formato binario compacto, con encabezado de versión (ver `payload_version`), que usan por defecto las aplicaciones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCodec;
+
+impl Codec<DronCurrentInfo> for BinaryCodec {
+    fn encode(&self, ci: &DronCurrentInfo) -> Result<Vec<u8>, Error> {
+        Ok(ci.to_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<DronCurrentInfo, Error> {
+        DronCurrentInfo::from_bytes(bytes.to_vec())
+    }
+}
+
+impl Codec<Camera> for BinaryCodec {
+    fn encode(&self, camera: &Camera) -> Result<Vec<u8>, Error> {
+        Ok(camera.to_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Camera, Error> {
+        Camera::from_bytes(bytes)
+    }
+}
+
+impl Codec<CamerasSnapshot> for BinaryCodec {
+    fn encode(&self, snapshot: &CamerasSnapshot) -> Result<Vec<u8>, Error> {
+        Ok(snapshot.to_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<CamerasSnapshot, Error> {
+        CamerasSnapshot::from_bytes(bytes)
+    }
+}
+
+impl Codec<Incident> for BinaryCodec {
+    fn encode(&self, incident: &Incident) -> Result<Vec<u8>, Error> {
+        Ok(incident.to_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Incident, Error> {
+        Incident::from_bytes(bytes.to_vec())
+    }
+}
+
+/// JSON, para que herramientas externas (ie mosquitto_sub, dashboards) puedan leer los payloads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, Error> {
+        serde_json::from_slice(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+/// Formato con el que se publica por un topic. Al decodificar, acepta cualquiera de los dos, para que una aplicación
+/// pueda leer lo que publican las demás sin importar cómo estén configuradas.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PayloadCodec {
+    #[default]
+    Binary,
+    Json,
+}
+
+impl<T> Codec<T> for PayloadCodec
+where
+    BinaryCodec: Codec<T>,
+    JsonCodec: Codec<T>,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            PayloadCodec::Binary => BinaryCodec.encode(value),
+            PayloadCodec::Json => JsonCodec.encode(value),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, Error> {
+        // Un payload binario legacy puede empezar con el mismo byte que uno JSON; si no es JSON, se lo lee binario.
+        if bytes.first() == Some(&b'{') {
+            if let Ok(value) = JsonCodec.decode(bytes) {
+                return Ok(value);
+            }
+        }
+        BinaryCodec.decode(bytes)
+    }
+}
+
+/// Decodifica un payload, publicado en cualquiera de los formatos.
+pub fn decode_payload<T>(bytes: &[u8]) -> Result<T, Error>
+where
+    PayloadCodec: Codec<T>,
+{
+    PayloadCodec::default().decode(bytes)
+}
+
+/// Formato con el que una aplicación publica por cada topic: binario, salvo por los configurados como JSON.
+/// Solamente las cámaras (`cam`, junto con sus snapshots de `cam-snapshot`) pueden publicarse como JSON: un
+/// incidente (`inc`) o una current_info de un dron (`dron`) como JSON pueden no entrar en un publish (ver
+/// `PublishMessage::max_payload_len`), por lo que siempre se publican en binario.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct TopicCodecs {
+    json_cameras: bool,
+}
+
+impl TopicCodecs {
+    /// Obtiene los formatos a partir de la lista de topics que se publican como JSON, separados por comas
+    /// (ie `cam`). Devuelve error si alguno no existe o no puede publicarse como JSON.
+    pub fn from_property(json_topics: &str) -> Result<Self, Error> {
+        let mut codecs = Self::default();
+        for topic in json_topics.split(',').map(str::trim).filter(|topic| !topic.is_empty()) {
+            match AppsMqttTopics::topic_from_str(topic) {
+                Ok(AppsMqttTopics::CameraTopic) => codecs.json_cameras = true,
+                Ok(AppsMqttTopics::IncidentTopic | AppsMqttTopics::DronTopic) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("El topic {} no puede publicarse como JSON, no entra en un publish.", topic),
+                    ))
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("El topic {} no puede publicarse como JSON.", topic),
+                    ))
+                }
+            }
+        }
+        Ok(codecs)
+    }
+
+    /// Devuelve el formato con el que se publica por `topic`.
+    pub fn for_topic(&self, topic: &AppsMqttTopics) -> PayloadCodec {
+        let is_json = match topic {
+            AppsMqttTopics::CameraTopic | AppsMqttTopics::CameraSnapshotTopic => self.json_cameras,
+            _ => false,
+        };
+        if is_json {
+            PayloadCodec::Json
+        } else {
+            PayloadCodec::Binary
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_payload, BinaryCodec, Codec, JsonCodec, PayloadCodec, TopicCodecs};
+    use crate::apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        incident_data::{
+            incident::{Incident, MAX_DESCRIPTION_LEN},
+            incident_info::IncidentInfo,
+            incident_source::IncidentSource,
+            incident_state::IncidentState,
+        },
+        sist_camaras::camera::Camera,
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_flying_info::DronFlyingInfo, dron_state::DronState},
+    };
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_se_decodifica_lo_publicado_en_cualquier_formato() {
+        let mut ci = DronCurrentInfo::new(3, position(-34.6, -58.4), 90, DronState::RespondingToIncident);
+        ci.set_inc_id_to_resolve(IncidentInfo::new(7, IncidentSource::Manual));
+        let camera = Camera::new(12, position(-34.6, -58.4), 5);
+        let incident = Incident::new(7, position(-34.6, -58.4), IncidentSource::Automated);
+
+        for codec in [PayloadCodec::Binary, PayloadCodec::Json] {
+            let ci_bytes = codec.encode(&ci).unwrap();
+            assert_eq!(decode_payload::<DronCurrentInfo>(&ci_bytes).unwrap(), ci);
+            let camera_bytes = codec.encode(&camera).unwrap();
+            assert_eq!(decode_payload::<Camera>(&camera_bytes).unwrap(), camera);
+            let incident_bytes = codec.encode(&incident).unwrap();
+            let decoded: Incident = decode_payload(&incident_bytes).unwrap();
+            assert_eq!(decoded.get_info(), incident.get_info());
+            assert_eq!(decoded.get_position(), incident.get_position());
+        }
+        // El JSON es legible por herramientas externas.
+        let json = String::from_utf8(PayloadCodec::Json.encode(&ci).unwrap()).unwrap();
+        assert!(json.contains("\"battery_lvl\":90"));
+    }
+
+    #[test]
+    fn test_2_se_configura_el_formato_de_cada_topic() {
+        let codecs = TopicCodecs::from_property(" cam").unwrap();
+        assert_eq!(codecs.for_topic(&AppsMqttTopics::CameraTopic), PayloadCodec::Json);
+        assert_eq!(codecs.for_topic(&AppsMqttTopics::CameraSnapshotTopic), PayloadCodec::Json);
+        assert_eq!(codecs.for_topic(&AppsMqttTopics::DronTopic), PayloadCodec::Binary);
+        assert_eq!(codecs.for_topic(&AppsMqttTopics::IncidentTopic), PayloadCodec::Binary);
+        assert_eq!(TopicCodecs::from_property("").unwrap(), TopicCodecs::default());
+        assert!(TopicCodecs::from_property("dron").is_err());
+        assert!(TopicCodecs::from_property("cam,inc").is_err());
+        assert!(TopicCodecs::from_property("dron-health").is_err());
+        assert!(TopicCodecs::from_property("drones").is_err());
+    }
+
+    #[test]
+    fn test_3_los_incidentes_y_las_current_info_como_json_no_entran_en_un_publish() {
+        // El incidente más grande: descripción máxima (con caracteres que JSON escapa) e historial completo.
+        let mut incident = Incident::new(255, position(-34.612345, -58.381234), IncidentSource::Automated)
+            .with_description(&"\"".repeat(MAX_DESCRIPTION_LEN));
+        for state in [IncidentState::Assigned, IncidentState::InAttention, IncidentState::Resolved] {
+            incident.transition_to(state).unwrap();
+        }
+        // Una current_info en vuelo, atendiendo un incidente y con incidentes pendientes.
+        let mut ci = DronCurrentInfo::new(255, position(-34.612345, -58.381234), 100, DronState::RespondingToIncident);
+        ci.set_inc_id_to_resolve(IncidentInfo::new(255, IncidentSource::Automated));
+        ci.set_flying_info(DronFlyingInfo::new((0.123456789, -0.987654321), 12.3456789));
+        ci.set_pending_incidents((0..4).map(|inc_id| IncidentInfo::new(inc_id, IncidentSource::Automated)).collect());
+
+        let max_inc_len = PublishMessage::max_payload_len(AppsMqttTopics::IncidentTopic.to_str());
+        let max_dron_len = PublishMessage::max_payload_len(AppsMqttTopics::DronTopic.to_str());
+        assert!(BinaryCodec.encode(&incident).unwrap().len() <= max_inc_len);
+        assert!(BinaryCodec.encode(&ci).unwrap().len() <= max_dron_len);
+        let incident_json = JsonCodec.encode(&incident).unwrap();
+        let ci_json = JsonCodec.encode(&ci).unwrap();
+        assert!(incident_json.len() > max_inc_len);
+        assert!(ci_json.len() > max_dron_len);
+        // Por eso no se permite publicarlos como JSON, y si alguien lo intenta, el publish no se crea.
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        assert!(PublishMessage::new(flags, AppsMqttTopics::IncidentTopic.to_str(), Some(1), &incident_json).is_err());
+    }
+}
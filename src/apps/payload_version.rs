@@ -0,0 +1,105 @@
+use std::io::{Error, ErrorKind};
+
+/// Primeros bytes de los payloads con encabezado de versión, seguidos por la versión. Los publicados antes de que
+/// existiera el encabezado empiezan directamente con sus campos, y se leen como de versión `LEGACY_VERSION`.
+const VERSION_MARKER: [u8; 2] = [0xA5, 0x5A];
+
+/// Versión de los payloads publicados sin encabezado.
+pub const LEGACY_VERSION: u8 = 0;
+
+/// Agrega al comienzo de `body` el encabezado con su `version`.
+pub fn with_version_header(version: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(VERSION_MARKER.len() + 1 + body.len());
+    bytes.extend_from_slice(&VERSION_MARKER);
+    bytes.push(version);
+    bytes.extend(body);
+    bytes
+}
+
+/// Separa el encabezado de versión de `bytes`, y devuelve la versión y el lector de los campos que le siguen.
+/// Si no tiene encabezado, los campos empiezan al comienzo y la versión es `LEGACY_VERSION`.
+pub fn split_version_header(bytes: &[u8]) -> (u8, PayloadReader<'_>) {
+    match bytes {
+        [m0, m1, version, body @ ..] if [*m0, *m1] == VERSION_MARKER => {
+            (*version, PayloadReader::new(body))
+        }
+        _ => (LEGACY_VERSION, PayloadReader::new(bytes)),
+    }
+}
+
+/// Lee en orden los campos de un payload. Los campos que se agreguen en versiones nuevas van al final: quien lee un
+/// payload de una versión más nueva ignora los que no conoce, y al leer uno más viejo, con `has_remaining` sabe si
+/// debe usar los valores por defecto de los que faltan.
+#[derive(Debug)]
+pub struct PayloadReader<'a> {
+    bytes: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, idx: 0 }
+    }
+
+    /// Devuelve si quedan campos por leer.
+    pub fn has_remaining(&self) -> bool {
+        self.idx < self.bytes.len()
+    }
+
+    /// Devuelve los bytes que quedan por leer, sin consumirlos.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.idx..]
+    }
+
+    /// Lee los próximos `len` bytes. Devuelve error si el payload termina antes.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .bytes
+            .get(self.idx..self.idx + len)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Payload incompleto."))?;
+        self.idx += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u64_be(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64_be(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64_le(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_version_header, with_version_header, LEGACY_VERSION};
+
+    #[test]
+    fn test_1_se_lee_la_version_o_se_asume_la_legacy_sin_encabezado() {
+        let bytes = with_version_header(2, vec![7, 8]);
+        let (version, mut reader) = split_version_header(&bytes);
+        assert_eq!(version, 2);
+        assert_eq!(reader.read_u8().unwrap(), 7);
+        assert_eq!(reader.remaining(), &[8]);
+
+        let (version, mut reader) = split_version_header(&[7, 8]);
+        assert_eq!(version, LEGACY_VERSION);
+        assert_eq!(reader.read_bytes(2).unwrap(), &[7, 8]);
+        assert!(!reader.has_remaining());
+        assert!(reader.read_u8().is_err());
+    }
+}
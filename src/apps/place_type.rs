@@ -23,6 +23,15 @@ impl PlaceType {
         }
     }
 
+    /// Devuelve el `IncidentSource` de los incidentes de este `PlaceType`, o `None` si no es un incidente.
+    pub fn to_inc_source(&self) -> Option<IncidentSource> {
+        match self {
+            Self::ManualIncident => Some(IncidentSource::Manual),
+            Self::AutomatedIncident => Some(IncidentSource::Automated),
+            _ => None,
+        }
+    }
+
     /// Devuelve un `PlaceType` acorde al `AppType` del `WillContent` recibido.
     pub fn from_app_type_will_content(app_type: &AppType) -> Self {
         match app_type {
@@ -1,90 +1,341 @@
-use super::vendor::{Image, Images, Texture};
-use super::vendor::{Plugin, Position, Projector};
-use egui::{Color32, Painter, Response};
-
-use super::places;
-
-/// Creates a built-in `Places` plugin with some predefined places.
-// pub fn places() -> impl Plugin {
-//     Places::new(vec![
-// Place {
-//     position: places::obelisco(),
-//     label: "Wrocław Główny\ntrain station".to_owned(),
-//     symbol: '📷',
-//     style: Style::default(),
-// },
-//         Place {
-//             position: places::dworcowa_bus_stop(),
-//             label: "Bus stop".to_owned(),
-//             symbol: '🚌',
-//             style: Style::default(),
-//         },
-//     ])
-// }
-
-/// Estructura de datos para el plugin de imagenes.
-pub struct ImagesPluginData {
-    pub texture: Texture,
-    pub angle: f32,
-    pub x_scale: f32,
-    pub y_scale: f32,
-}
-
-impl ImagesPluginData {
-    pub fn new(egui_ctx: egui::Context) -> Self {
-        Self {
-            texture: Texture::from_color_image(egui::ColorImage::example(), &egui_ctx),
-            angle: 0.0,
-            x_scale: 1.0,
-            y_scale: 1.0,
-        }
-    }
-}
-
-/// Creates a built-in `Images` plugin with an example image.
-pub fn images(images_plugin_data: &mut ImagesPluginData) -> impl Plugin {
-    Images::new(vec![{
-        let mut image = Image::new(images_plugin_data.texture.clone(), places::wroclavia());
-        image.scale(images_plugin_data.x_scale, images_plugin_data.y_scale);
-        image.angle(images_plugin_data.angle.to_radians());
-        image
-    }])
-}
-
-/// Estructura de datos para el plugin de formas personalizadas.
-pub struct CustomShapes {}
-
-impl Plugin for CustomShapes {
-    /// Dibuja un circulo en la posicion del obelisco.
-    fn run(&mut self, response: &Response, painter: Painter, projector: &Projector) {
-        // Posicion del obelisco.
-        let position = places::capitol();
+use super::geo_position::{GeoPosition, EARTH_RADIUS_METERS};
+use super::place_type::PlaceType;
+use super::sist_camaras::{camera::Camera, camera_state::CameraState};
+use super::sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState};
+use super::sist_monitoreo::dron_watchdog::DronHealth;
+use super::vendor::{Place, Plugin, Position, Projector, Style};
+use egui::{Align2, Color32, FontId, Painter, Pos2, Response, Stroke};
+use std::collections::{HashMap, VecDeque};
+
+/// Qué está haciendo un dron, según su estado, para elegir cómo se lo dibuja en el mapa.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DronMarkerKind {
+    Expecting,
+    FlyingToIncident,
+    Attending,
+    Returning,
+    Maintenance,
+}
+
+impl DronMarkerKind {
+    /// Devuelve qué está haciendo el dron en el estado `state`; `has_incident` indica si tiene un incidente a
+    /// resolver, para distinguir si vuela hacia él o de vuelta.
+    pub fn from_state(state: DronState, has_incident: bool) -> Self {
+        match state {
+            DronState::MustRespondToIncident => DronMarkerKind::FlyingToIncident,
+            DronState::Flying if has_incident => DronMarkerKind::FlyingToIncident,
+            DronState::Flying => DronMarkerKind::Returning,
+            DronState::ManagingIncident => DronMarkerKind::Attending,
+            DronState::Mantainance | DronState::ScheduledMaintenance => DronMarkerKind::Maintenance,
+            _ => DronMarkerKind::Expecting,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            DronMarkerKind::Maintenance => '🔧',
+            _ => '🚁',
+        }
+    }
 
-        // Project it into the position on the screen.
-        let position = projector.project(position).to_pos2();
+    fn color(&self) -> Color32 {
+        match self {
+            DronMarkerKind::Expecting => Color32::BLACK.gamma_multiply(0.8),
+            DronMarkerKind::FlyingToIncident => Color32::from_rgb(255, 140, 0), // Color naranja
+            DronMarkerKind::Attending => Color32::from_rgb(220, 0, 0),          // Color rojo
+            DronMarkerKind::Returning => Color32::from_rgb(0, 120, 255),        // Color azul
+            DronMarkerKind::Maintenance => Color32::DARK_GRAY,
+        }
+    }
+}
+
+/// Color de la insignia de batería: verde por encima del 50%, amarillo por encima del 20%, y rojo si no.
+pub fn battery_color(battery_lvl: u8) -> Color32 {
+    match battery_lvl {
+        51.. => Color32::from_rgb(0, 160, 0),
+        21..=50 => Color32::from_rgb(200, 160, 0),
+        _ => Color32::from_rgb(200, 0, 0),
+    }
+}
 
-        let radius = 30.;
+#[derive(Debug, Clone)]
+struct DronMarker {
+    place: Place,
+    kind: DronMarkerKind,
+    battery_lvl: u8,
+    health: DronHealth,
+}
 
-        let hovered = response
-            .hover_pos()
-            .map(|hover_pos| hover_pos.distance(position) < radius)
-            .unwrap_or(false);
+/// Marcadores de los drones en el mapa, que se mueven con cada current_info recibida. El símbolo y su color indican
+/// qué está haciendo el dron, el fondo si dejó de enviar su latido, y una insignia su nivel de batería.
+#[derive(Debug, Default, Clone)]
+pub struct DronMarkers {
+    markers: HashMap<u8, DronMarker>,
+}
 
-        painter.circle_filled(
+impl DronMarkers {
+    /// Agrega o mueve el marcador del dron de `dron`, con el texto `label`.
+    pub fn update(&mut self, dron: &DronCurrentInfo, position: Position, label: String) {
+        let dron_id = dron.get_id();
+        let kind = DronMarkerKind::from_state(dron.get_state(), dron.get_inc_id_to_resolve().is_some());
+        let health = self
+            .markers
+            .get(&dron_id)
+            .map_or(DronHealth::Alive, |marker| marker.health);
+        let place = Place {
             position,
-            radius,
-            Color32::BLACK.gamma_multiply(if hovered { 0.5 } else { 0.2 }),
-        );
+            label,
+            symbol: kind.symbol(),
+            style: Style::default(),
+            id: dron_id,
+            place_type: PlaceType::Dron,
+            tooltip: None,
+        };
+        self.markers.insert(dron_id, DronMarker { place, kind, battery_lvl: dron.get_battery_lvl(), health });
+    }
+
+    /// Indica qué tan reciente es el último latido del dron `dron_id`. Si no tiene marcador, no hace nada.
+    pub fn set_health(&mut self, dron_id: u8, health: DronHealth) {
+        if let Some(marker) = self.markers.get_mut(&dron_id) {
+            marker.health = health;
+        }
+    }
+
+    /// Deja de mostrar al dron `dron_id`.
+    pub fn remove(&mut self, dron_id: u8) {
+        self.markers.remove(&dron_id);
+    }
+
+    /// Devuelve los marcadores de los drones cuyo nivel de batería cumple `keep`.
+    pub fn filtered(&self, keep: impl Fn(u8) -> bool) -> DronMarkers {
+        DronMarkers {
+            markers: self
+                .markers
+                .iter()
+                .filter(|(_, marker)| keep(marker.battery_lvl))
+                .map(|(dron_id, marker)| (*dron_id, marker.clone()))
+                .collect(),
+        }
+    }
+
+    /// Devuelve el id y la posición de cada dron que se muestra.
+    pub fn positions(&self) -> Vec<(u8, Position)> {
+        self.markers
+            .iter()
+            .map(|(dron_id, marker)| (*dron_id, marker.place.position))
+            .collect()
+    }
+}
+
+impl Plugin for &DronMarkers {
+    fn run(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        for marker in self.markers.values() {
+            let mut place = marker.place.clone();
+            place.style.symbol_color = marker.kind.color();
+            place.style.symbol_background = match marker.health {
+                DronHealth::Alive => place.style.symbol_background,
+                DronHealth::Stale => Color32::GRAY,
+                DronHealth::Lost => Color32::RED,
+            };
+            place.draw(response, painter.clone(), projector);
+
+            // Insignia con el nivel de batería, arriba a la derecha del marcador.
+            let badge_position = projector.project(place.position).to_pos2() + egui::vec2(22., -22.);
+            let badge = painter.layout_no_wrap(
+                format!("{}%", marker.battery_lvl),
+                FontId::proportional(10.),
+                Color32::WHITE,
+            );
+            let badge_rect = Align2::CENTER_CENTER.anchor_rect(egui::Rect::from_center_size(badge_position, badge.size()));
+            painter.rect_filled(badge_rect.expand(3.), 6., battery_color(marker.battery_lvl));
+            painter.galley(badge_rect.min, badge, Color32::WHITE);
+        }
+    }
+}
+
+/// Color del círculo de cobertura de una cámara: verde si está en reposo, rojo si está activa siguiendo un
+/// incidente, y gris si está rota o desconectada, porque entonces no cubre su rango.
+pub fn coverage_color(state: CameraState) -> Color32 {
+    match state {
+        CameraState::SavingMode | CameraState::HighSensitivity => Color32::from_rgb(0, 200, 0),
+        CameraState::Active => Color32::from_rgb(220, 0, 0),
+        CameraState::Fault | CameraState::Offline => Color32::GRAY,
+    }
+}
+
+/// Rango de detección de cada cámara, que se dibuja como un círculo translúcido alrededor de ella, para ver de
+/// un vistazo qué zonas están cubiertas.
+#[derive(Debug, Default, Clone)]
+pub struct CameraCoverage {
+    cameras: HashMap<u8, (GeoPosition, f64, CameraState)>, // camera_id -> (posición, rango en metros, estado)
+}
+
+impl CameraCoverage {
+    /// Agrega o actualiza el círculo de `camera`. Si la cámara se eliminó, deja de mostrarlo.
+    pub fn update(&mut self, camera: &Camera) {
+        if camera.is_not_deleted() {
+            self.cameras
+                .insert(camera.get_id(), (camera.get_position(), camera.get_range(), camera.get_state()));
+        } else {
+            self.cameras.remove(&camera.get_id());
+        }
+    }
+
+    /// Deja de mostrar todas las cámaras.
+    pub fn clear(&mut self) {
+        self.cameras.clear();
+    }
+}
+
+impl Plugin for &CameraCoverage {
+    fn run(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        for (position, range, state) in self.cameras.values() {
+            let center = projector.project(to_map_position(*position)).to_pos2();
+            // El radio en pixeles es la distancia en pantalla hasta el punto que está `range` metros al norte.
+            let north = position.offset((range / EARTH_RADIUS_METERS).to_degrees(), 0.0);
+            let radius = center.distance(projector.project(to_map_position(north)).to_pos2());
+            let color = coverage_color(*state);
+            painter.circle(center, radius, color.gamma_multiply(0.2), Stroke::new(1.5, color.gamma_multiply(0.6)));
+        }
+    }
+}
+
+fn to_map_position(position: GeoPosition) -> Position {
+    Position::from_lon_lat(position.get_longitude(), position.get_latitude())
+}
+
+/// Radio en pixeles del círculo de los marcadores, dentro del cual un click lo selecciona.
+const MARKER_RADIUS: f32 = 25.;
+
+/// Detecta los clicks sobre los marcadores del mapa. Antes de dibujar el mapa se le indican los marcadores que se
+/// muestran, y después se pregunta cuál se clickeó.
+#[derive(Debug, Default, Clone)]
+pub struct MarkerPicker {
+    markers: Vec<(PlaceType, u8, Position)>,
+    picked: Option<(PlaceType, u8)>,
+}
+
+impl MarkerPicker {
+    /// Indica los marcadores que se muestran en el mapa, con su tipo, id y posición.
+    pub fn set_markers(&mut self, markers: Vec<(PlaceType, u8, Position)>) {
+        self.markers = markers;
+    }
+
+    /// Devuelve el tipo y el id del marcador clickeado, si hubo un click sobre uno desde la última vez que se
+    /// llamó.
+    pub fn take_picked(&mut self) -> Option<(PlaceType, u8)> {
+        self.picked.take()
+    }
+}
+
+impl Plugin for &mut MarkerPicker {
+    fn run(&mut self, response: &Response, _painter: Painter, projector: &Projector) {
+        if response.changed() || !response.clicked_by(egui::PointerButton::Primary) {
+            return;
+        }
+        let Some(clicked) = response.interact_pointer_pos() else {
+            return;
+        };
+        // Si se superponen varios marcadores, se elige el más cercano al click.
+        self.picked = self
+            .markers
+            .iter()
+            .map(|(place_type, id, position)| {
+                let distance = projector.project(*position).to_pos2().distance(clicked);
+                (distance, place_type, id)
+            })
+            .filter(|(distance, _, _)| *distance <= MARKER_RADIUS)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, place_type, id)| (place_type.clone(), *id));
+    }
+}
+
+/// Lado, en metros, de las celdas en las que se agrupan los incidentes del mapa de calor.
+pub const HEATMAP_CELL_SIZE_M: f64 = 200.0;
+
+/// Mapa de calor de los incidentes: los agrupa en celdas de `HEATMAP_CELL_SIZE_M` metros de lado, y dibuja cada
+/// celda con más incidentes en un color más intenso, para ver dónde conviene agregar cámaras o mover el centro del
+/// rango de los drones.
+#[derive(Debug, Default, Clone)]
+pub struct IncidentHeatmap {
+    cells: Vec<(GeoPosition, usize)>, // (centro de la celda, cantidad de incidentes)
+}
+
+impl IncidentHeatmap {
+    /// Agrupa las posiciones de los incidentes `positions` en celdas.
+    pub fn from_positions(positions: &[GeoPosition]) -> Self {
+        let cell_lat = (HEATMAP_CELL_SIZE_M / EARTH_RADIUS_METERS).to_degrees();
+        let mut counts: HashMap<(i64, i64), usize> = HashMap::new();
+        for position in positions {
+            let row = (position.get_latitude() / cell_lat).floor() as i64;
+            let column = (position.get_longitude() / cell_lon(row, cell_lat)).floor() as i64;
+            *counts.entry((row, column)).or_default() += 1;
+        }
+        let mut cells: Vec<(GeoPosition, usize)> = counts
+            .into_iter()
+            .filter_map(|((row, column), count)| {
+                let cell_lon = cell_lon(row, cell_lat);
+                let center = GeoPosition::new((row as f64 + 0.5) * cell_lat, (column as f64 + 0.5) * cell_lon).ok()?;
+                Some((center, count))
+            })
+            .collect();
+        // Las celdas con más incidentes se dibujan encima.
+        cells.sort_by_key(|(_, count)| *count);
+        Self { cells }
+    }
+
+    /// Devuelve el centro y la cantidad de incidentes de cada celda con alguno, de la que tiene menos a la que
+    /// tiene más.
+    pub fn get_cells(&self) -> &[(GeoPosition, usize)] {
+        &self.cells
+    }
+}
+
+/// Ancho en grados de longitud de las celdas de la fila `row`, para que midan lo mismo en metros que de alto.
+fn cell_lon(row: i64, cell_lat: f64) -> f64 {
+    let latitude = ((row as f64 + 0.5) * cell_lat).clamp(-89.0, 89.0);
+    cell_lat / latitude.to_radians().cos()
+}
+
+/// Color de una celda del mapa de calor con `intensity` entre 0 y 1: de amarillo translúcido a rojo.
+pub fn heat_color(intensity: f32) -> Color32 {
+    let intensity = intensity.clamp(0., 1.);
+    let green = (220. * (1. - intensity)) as u8;
+    Color32::from_rgb(255, green, 0).gamma_multiply(0.25 + 0.45 * intensity)
+}
+
+impl Plugin for &IncidentHeatmap {
+    fn run(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let max = self.cells.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        for (center, count) in &self.cells {
+            let screen_center = projector.project(to_map_position(*center)).to_pos2();
+            let north = center.offset((HEATMAP_CELL_SIZE_M / 2.0 / EARTH_RADIUS_METERS).to_degrees(), 0.0);
+            let radius = screen_center.distance(projector.project(to_map_position(north)).to_pos2());
+            painter.circle_filled(screen_center, radius.max(4.), heat_color(*count as f32 / max as f32));
+        }
     }
 }
 
 #[derive(Default, Clone)]
 pub struct ClickWatcher {
     pub clicked_at: Option<Position>,
+    unread: bool, // si hubo un click que todavía no se leyó con `take_new_click`
 }
 
 impl ClickWatcher {
-    pub fn show_position(&self, ui: &egui::Ui) {
+    /// Devuelve la posición del último click, si hubo uno desde la última vez que se llamó.
+    pub fn take_new_click(&mut self) -> Option<Position> {
+        if !self.unread {
+            return None;
+        }
+        self.unread = false;
+        self.clicked_at
+    }
+
+    /// Muestra la posición del último click y, si `can_create_incident`, un botón para dar de alta un incidente ahí.
+    /// Devuelve si se apretó el botón.
+    pub fn show_position(&self, ui: &egui::Ui, can_create_incident: bool) -> bool {
+        let mut new_incident = false;
         if let Some(clicked_at) = self.clicked_at {
             egui::Window::new("Clicked Position")
                 .collapsible(false)
@@ -95,8 +346,10 @@ impl ClickWatcher {
                     // Muestro la posicion seleccionada como latitud y longitud.
                     ui.label(format!("{:.04} {:.04}", clicked_at.lat(), clicked_at.lon()))
                         .on_hover_text("last clicked position");
+                    new_incident = can_create_incident && ui.button("Alta incidente acá").clicked();
                 });
         }
+        new_incident
     }
 }
 
@@ -106,6 +359,7 @@ impl Plugin for &mut ClickWatcher {
             self.clicked_at = response
                 .interact_pointer_pos()
                 .map(|p| projector.unproject(p - response.rect.center()));
+            self.unread = self.clicked_at.is_some();
         }
 
         if let Some(position) = self.clicked_at {
@@ -113,3 +367,100 @@ impl Plugin for &mut ClickWatcher {
         }
     }
 }
+
+/// Cantidad de posiciones recientes que se conservan del recorrido de cada dron.
+const MAX_TRAIL_LEN: usize = 120;
+
+/// Recorridos recientes de los drones, que se dibujan como líneas en el mapa. Incluye las posiciones que un dron
+/// publica juntas al reconectarse, para que se vea el camino que hizo mientras no tenía conexión.
+#[derive(Default, Clone)]
+pub struct DronTrails {
+    trails: HashMap<u8, VecDeque<Position>>,
+}
+
+impl DronTrails {
+    /// Agrega `position` al final del recorrido del dron `dron_id`, descartando la más vieja si está lleno.
+    pub fn add_position(&mut self, dron_id: u8, position: Position) {
+        let trail = self.trails.entry(dron_id).or_default();
+        if trail.len() == MAX_TRAIL_LEN {
+            trail.pop_front();
+        }
+        trail.push_back(position);
+    }
+
+    /// Deja de mostrar el recorrido del dron `dron_id`.
+    pub fn remove(&mut self, dron_id: u8) {
+        self.trails.remove(&dron_id);
+    }
+}
+
+impl Plugin for &DronTrails {
+    fn run(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let stroke = Stroke::new(2.0, Color32::from_rgb(255, 140, 0).gamma_multiply(0.6));
+        for trail in self.trails.values() {
+            let points: Vec<Pos2> = trail
+                .iter()
+                .map(|position| projector.project(*position).to_pos2())
+                .collect();
+            for segment in points.windows(2) {
+                painter.line_segment([segment[0], segment[1]], stroke);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{battery_color, coverage_color, heat_color, DronMarkerKind, IncidentHeatmap};
+    use crate::apps::{
+        geo_position::GeoPosition, sist_camaras::camera_state::CameraState, sist_dron::dron_state::DronState,
+    };
+
+    #[test]
+    fn test_1_el_marcador_del_dron_depende_de_su_estado() {
+        assert_eq!(DronMarkerKind::from_state(DronState::ExpectingToRecvIncident, false), DronMarkerKind::Expecting);
+        assert_eq!(DronMarkerKind::from_state(DronState::MustRespondToIncident, true), DronMarkerKind::FlyingToIncident);
+        assert_eq!(DronMarkerKind::from_state(DronState::Flying, true), DronMarkerKind::FlyingToIncident);
+        assert_eq!(DronMarkerKind::from_state(DronState::Flying, false), DronMarkerKind::Returning);
+        assert_eq!(DronMarkerKind::from_state(DronState::ManagingIncident, true), DronMarkerKind::Attending);
+        assert_eq!(DronMarkerKind::from_state(DronState::Mantainance, false), DronMarkerKind::Maintenance);
+        assert_eq!(DronMarkerKind::from_state(DronState::ScheduledMaintenance, false), DronMarkerKind::Maintenance);
+    }
+
+    #[test]
+    fn test_2_la_insignia_de_bateria_cambia_de_color_segun_el_nivel() {
+        assert_eq!(battery_color(100), battery_color(51));
+        assert_eq!(battery_color(50), battery_color(21));
+        assert_eq!(battery_color(20), battery_color(0));
+        assert_ne!(battery_color(51), battery_color(50));
+        assert_ne!(battery_color(21), battery_color(20));
+    }
+
+    #[test]
+    fn test_3_la_cobertura_de_la_camara_se_colorea_segun_su_estado() {
+        assert_eq!(coverage_color(CameraState::SavingMode), coverage_color(CameraState::HighSensitivity));
+        assert_eq!(coverage_color(CameraState::Fault), coverage_color(CameraState::Offline));
+        assert_ne!(coverage_color(CameraState::Active), coverage_color(CameraState::SavingMode));
+        assert_ne!(coverage_color(CameraState::Active), coverage_color(CameraState::Offline));
+    }
+
+    #[test]
+    fn test_4_el_mapa_de_calor_agrupa_los_incidentes_cercanos() {
+        let obelisco = GeoPosition::new(-34.6037, -58.3816).unwrap();
+        let far_away = GeoPosition::new(-34.55, -58.45).unwrap(); // a varios kilómetros
+        let heatmap = IncidentHeatmap::from_positions(&[obelisco, far_away, obelisco, obelisco]);
+
+        let counts: Vec<usize> = heatmap.get_cells().iter().map(|(_, count)| *count).collect();
+        assert_eq!(counts, vec![1, 3]);
+        let (center, _) = heatmap.get_cells().last().unwrap();
+        assert!(center.distance_to(&obelisco) < 200.0);
+        assert!(IncidentHeatmap::from_positions(&[]).get_cells().is_empty());
+    }
+
+    #[test]
+    fn test_5_las_celdas_con_mas_incidentes_son_mas_rojas() {
+        assert_eq!(heat_color(1.0).g(), 0);
+        assert!(heat_color(0.0).g() > heat_color(0.5).g());
+        assert!(heat_color(0.0).a() < heat_color(1.0).a());
+    }
+}
@@ -12,10 +12,18 @@ impl Properties {
         let path = Path::new(file_path);
         let file = File::open(path)?;
         let reader = io::BufReader::new(file);
+        Self::from_lines(reader.lines())
+    }
+
+    /// Carga las properties de `contents`, con líneas de la forma `clave=valor`.
+    pub fn from_contents(contents: &str) -> Result<Self, Error> {
+        Self::from_lines(contents.lines().map(|line| Ok(line.to_string())))
+    }
 
+    fn from_lines(lines: impl Iterator<Item = Result<String, Error>>) -> Result<Self, Error> {
         let mut props = HashMap::new();
 
-        for line in reader.lines() {
+        for line in lines {
             let line = line?;
             let mut parts = line.splitn(2, '=');
 
@@ -45,4 +53,9 @@ impl Properties {
     pub fn get(&self, key: &str) -> Option<&String> {
         self.props.get(key)
     }
+
+    /// Devuelve los pares clave - valor, en cualquier orden.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.props.iter()
+    }
 }
@@ -16,7 +16,13 @@ fn main() {
     println!("Iniciando detector.");
 
     // Crea un AutomaticIncidentDetector y lo pone en funcionamiento.
-    let cameras: ShCamerasType = create_cameras();
+    let cameras: ShCamerasType = match create_cameras() {
+        Ok(cameras) => cameras,
+        Err(e) => {
+            println!("Error al leer las cámaras: {}.", e);
+            return;
+        }
+    };
     let (tx, rx) = mpsc::channel::<Incident>();
     let (_exit_tx, exit_rx) = mpsc::channel::<()>();
     let (logger, handle_logger) = StringLogger::create_logger("detector_main".to_string());
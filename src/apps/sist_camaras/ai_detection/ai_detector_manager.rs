@@ -1,11 +1,11 @@
 use notify::{event::EventKind, RecursiveMode, Watcher};
-use rayon::ThreadPoolBuilder;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::{
     error::Error,
     ffi::OsStr,
     fs,
     io::{Error as ioError, ErrorKind},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         mpsc::{self, Receiver, Sender},
         Arc, Mutex,
@@ -18,7 +18,17 @@ use crate::{
         incident_data::incident::Incident,
         sist_camaras::{
             ai_detection::{
-                ai_detector::AutomaticIncidentDetector, properties::DetectorProperties,
+                ai_detector::AutomaticIncidentDetector,
+                custom_vision_detector::CustomVisionDetector,
+                detection_batcher::BatchSettings,
+                http_detector::HttpDetector,
+                incident_detector::{
+                    CameraImage, FilenameRuleDetector, IncidentDetector, METADATA_EXTENSION,
+                },
+                properties::{
+                    DetectorProperties, CUSTOM_VISION_DETECTOR, FILENAME_DETECTOR, HTTP_DETECTOR,
+                    ONNX_DETECTOR,
+                },
             },
             types::shareable_cameras_type::ShCamerasType,
         },
@@ -102,12 +112,21 @@ impl AIDetectorManager {
         let ai_detector = AutomaticIncidentDetector::new(
             self.cameras.clone(),
             self.inc_tx.clone(),
-            self.properties.clone(),
+            self.create_incident_detector()?,
             logger_ai,
         );
 
-        // Crear un pool de threads con el número de threads deseado
-        let pool = ThreadPoolBuilder::new().num_threads(6).build()?;
+        // Las imágenes se agrupan en lotes en otro hilo, y cada lote se analiza en un hilo de la threadpool,
+        // para no bloquear el monitoreo de los directorios mientras el detector trabaja
+        let batch_settings = self.properties.get_batch_settings();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(batch_settings.get_workers())
+            .build()?;
+        let (img_tx, img_rx) = mpsc::channel::<PathBuf>();
+        let logger_batcher = self.logger.clone_ref();
+        let batcher_handle = thread::spawn(move || {
+            run_batcher(ai_detector, pool, batch_settings, img_rx, logger_batcher);
+        });
 
         for event_res in rx_fs {
             // Sale, si lo solicitaron desde abm
@@ -120,7 +139,7 @@ impl AIDetectorManager {
             if let EventKind::Create(_) = event.kind {
                 self.logger.log("Detector: event ok: create".to_string());
                 if let Some(path) = event.paths.first() {
-                    if let Err(e) = self.launch_detection_for_image(&ai_detector, &pool, path) {
+                    if let Err(e) = self.launch_detection_for_image(&img_tx, path) {
                         println!("Detector: Error al procesar la imagen: {:?}, {:?}", path, e);
                         self.logger.log(format!(
                             "Detector: Error al procesar la imagen: {:?}, {:?}",
@@ -131,9 +150,78 @@ impl AIDetectorManager {
             }
         }
 
+        // Se cierra el channel para que el hilo de lotes procese lo pendiente y termine
+        drop(img_tx);
+        if batcher_handle.join().is_err() {
+            self.logger
+                .log("Detector: Error al joinear hilo de lotes.".to_string());
+        }
+
         Ok(())
     }
 
+    /// Crea el detector que indica la property `detector`.
+    fn create_incident_detector(&self) -> Result<Arc<dyn IncidentDetector>, ioError> {
+        self.logger.log(format!(
+            "Detector: analizando imágenes con el detector {}.",
+            self.properties.get_detector()
+        ));
+        match self.properties.get_detector() {
+            FILENAME_DETECTOR => Ok(Arc::new(FilenameRuleDetector::new(
+                self.properties.get_incident_keywords(),
+            ))),
+            CUSTOM_VISION_DETECTOR => Ok(Arc::new(CustomVisionDetector::new(
+                self.properties.clone(),
+                self.logger.clone_ref(),
+            ))),
+            HTTP_DETECTOR => {
+                let endpoint = self.properties.get_http_endpoint().ok_or_else(|| {
+                    ioError::new(ErrorKind::InvalidInput, "Falta propiedad http_endpoint.")
+                })?;
+                let detector = HttpDetector::new(
+                    endpoint.to_string(),
+                    self.properties.get_inc_threshold(),
+                    self.properties.get_http_timeout(),
+                )
+                .map_err(|e| ioError::new(ErrorKind::Other, e.to_string()))?;
+                Ok(Arc::new(detector))
+            }
+            ONNX_DETECTOR => self.create_onnx_detector(),
+            detector => Err(ioError::new(
+                ErrorKind::InvalidInput,
+                format!("Detector inválido: {}.", detector),
+            )),
+        }
+    }
+
+    /// Crea el detector que usa el modelo ONNX local.
+    #[cfg(feature = "onnx")]
+    fn create_onnx_detector(&self) -> Result<Arc<dyn IncidentDetector>, ioError> {
+        use crate::apps::sist_camaras::ai_detection::onnx_detector::{OnnxDetector, OnnxSettings};
+
+        let model_path = self.properties.get_onnx_model_path().ok_or_else(|| {
+            ioError::new(ErrorKind::InvalidInput, "Falta propiedad onnx_model_path.")
+        })?;
+        let detector = OnnxDetector::new(OnnxSettings {
+            model_path: model_path.to_string(),
+            input_size: self.properties.get_onnx_input_size(),
+            incident_index: self.properties.get_onnx_incident_index(),
+            threshold: self.properties.get_inc_threshold(),
+            runtime_path: self.properties.get_onnx_runtime_path().map(String::from),
+        })
+        .map_err(|e| ioError::new(ErrorKind::Other, e.to_string()))?;
+        Ok(Arc::new(detector))
+    }
+
+    /// Sin la feature `onnx` no se incluye ONNX Runtime, por lo que este detector no está disponible.
+    #[cfg(not(feature = "onnx"))]
+    fn create_onnx_detector(&self) -> Result<Arc<dyn IncidentDetector>, ioError> {
+        Err(ioError::new(
+            ErrorKind::Unsupported,
+            "El detector onnx requiere compilar con la feature onnx.",
+        ))
+    }
+
     /// Crea, si no existía, la estructura de directorios necesaria para las imágenes de las cámaras.
     fn create_dirs_tree(&self, base_dir: &Path) -> Result<(), ioError> {
         self.create_basedir(base_dir)?;
@@ -183,30 +271,20 @@ impl AIDetectorManager {
         Ok(())
     }
 
-    /// Envía el pedido a la threadpool para detectar incidente en la imagen.
+    /// Envía la imagen al hilo de lotes, para que se detecte si contiene un incidente.
     fn launch_detection_for_image(
         &self,
-        ai_detector: &AutomaticIncidentDetector,
-        pool: &rayon::ThreadPool,
+        img_tx: &Sender<PathBuf>,
         path: &Path,
     ) -> Result<(), Box<dyn Error>> {
-        if path.is_file() {
+        // Los metadatos se leen junto con su imagen
+        let is_metadata = path.extension().and_then(OsStr::to_str) == Some(METADATA_EXTENSION);
+        if path.is_file() && !is_metadata {
             let image_path = path.to_owned();
             // Validar la extensión del archivo
             self.is_valid_extension(&image_path)?;
 
-            // Ejecuta el procesamiento de la imagen en un hilo de la threadpool
-            let mut aidetector = ai_detector.clone_refs();
-            let logger_c = self.logger.clone_ref();
-            pool.spawn(move || {
-                if let Err(e) = read_and_process_image(&mut aidetector, &image_path) {
-                    println!("Detector: Error en read_and_process_image: {:?}.", e);
-                    logger_c.log(format!(
-                        "Detector: Error en read_and_process_image: {:?}.",
-                        e
-                    ));
-                }
-            });
+            img_tx.send(image_path)?;
         }
         Ok(())
     }
@@ -245,16 +323,51 @@ fn modify_if_exit_requested(exit_requested: Arc<Mutex<bool>>, rx: Receiver<()>)
     }
 }
 
-/// Lee la imagen del archivo path proporcionado y llama a procesarla.
-fn read_and_process_image(
+/// Junta en lotes las imágenes que llegan por `img_rx`, y envía cada lote a un hilo de la threadpool para que
+/// el detector lo analice. Termina cuando se cierra el channel, luego de enviar lo pendiente.
+fn run_batcher(
+    ai_detector: AutomaticIncidentDetector,
+    pool: ThreadPool,
+    settings: BatchSettings,
+    img_rx: Receiver<PathBuf>,
+    logger: StringLogger,
+) {
+    while let Some(paths) = settings.collect_batch(&img_rx) {
+        let mut aidetector = ai_detector.clone_refs();
+        let logger_c = logger.clone_ref();
+        pool.spawn(move || {
+            read_and_process_batch(&mut aidetector, paths, &logger_c);
+        });
+    }
+}
+
+/// Lee las imágenes de los paths proporcionados y llama a procesarlas, en un solo lote.
+/// Las que no se pueden leer se logguean y se descartan.
+fn read_and_process_batch(
     aidetector: &mut AutomaticIncidentDetector,
-    image_path: &Path,
-) -> Result<(), Box<dyn Error>> {
-    let img = read_image(image_path)?;
-    if let Some(cam_id) = extract_camera_id(image_path) {
-        aidetector.process_image(img, cam_id)?;
-    };
-    Ok(())
+    image_paths: Vec<PathBuf>,
+    logger: &StringLogger,
+) {
+    let mut images = vec![];
+    for image_path in image_paths {
+        match read_image(&image_path) {
+            Ok(img) => {
+                if let Some(cam_id) = extract_camera_id(&image_path) {
+                    images.push(CameraImage::new(cam_id, image_path, img));
+                }
+            }
+            Err(e) => {
+                println!("Detector: Error al leer la imagen {:?}: {:?}.", image_path, e);
+                logger.log(format!(
+                    "Detector: Error al leer la imagen {:?}: {:?}.",
+                    image_path, e
+                ));
+            }
+        }
+    }
+    if !images.is_empty() {
+        aidetector.process_batch(images);
+    }
 }
 
 /// Lee la imagen del `image_path`.
@@ -0,0 +1,209 @@
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, CONTENT_TYPE},
+};
+use std::{error::Error, io::ErrorKind};
+
+use crate::{
+    apps::sist_camaras::ai_detection::{
+        api_credentials::ApiCredentials,
+        incident_detector::{CameraImage, IncidentDetector},
+        properties::DetectorProperties,
+    },
+    logging::string_logger::StringLogger,
+};
+
+/// Detector que le envía la imagen al proveedor de inteligencia artificial, y evalúa si la respuesta indica que la
+/// imagen contiene o no un incidente: si la probabilidad de `inc_tag` supera `inc_threshold`.
+#[derive(Debug)]
+pub struct CustomVisionDetector {
+    properties: DetectorProperties,
+    logger: StringLogger,
+}
+
+impl CustomVisionDetector {
+    pub fn new(properties: DetectorProperties, logger: StringLogger) -> Self {
+        Self { properties, logger }
+    }
+
+    /// Interpreta el res_text recibido como json y devuelve la probabilidad con que el mismo afirma que
+    /// se trata de un incidente.
+    fn process_response(&self, res_text: &str) -> Result<f64, Box<dyn Error>> {
+        let res_json: serde_json::Value = serde_json::from_str(res_text)?;
+
+        // Analizamos primero si la respuesta fue un error
+        self.process_error_response(&res_json)?;
+
+        println!("Probando: res_json: {:?}", res_json);
+
+        // Si no lo fue, buscamos la probability
+        let incident_probability_option =
+            res_json["predictions"].as_array().and_then(|predictions| {
+                predictions.iter().find_map(|prediction| {
+                    let tag = self.properties.get_inc_tag();
+                    if prediction["tagName"].as_str() == Some(tag.as_str()) {
+                        prediction["probability"].as_f64()
+                    } else {
+                        None
+                    }
+                })
+            });
+
+        if let Some(incident_probability) = incident_probability_option {
+            Ok(incident_probability)
+        } else {
+            self.logger.log(format!("Response raw recibida: {}.", res_json));
+            Err(Box::new(std::io::Error::new(
+                ErrorKind::Other,
+                "Error al obtener la incident_probability.",
+            )))
+        }
+    }
+
+    /// Analiza si la respuesta de la api informa de un error.
+    /// Si es un error, lo devuelve. Caso contrario devuelve Ok.
+    fn process_error_response(
+        &self,
+        res_json: &serde_json::Value,
+    ) -> Result<(), Box<std::io::Error>> {
+        if let Some((error_code, error_msg)) =
+            self.get_error_code_and_msg_from_error_response(res_json)
+        {
+            // Devolver el error
+            let displayable_error = format!(
+                "Response API es error: code {}, message: {}.",
+                error_code, error_msg
+            );
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::Other,
+                displayable_error,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Parsea la response en busca de `code` y `message` de una respuesta de la api de tipo error.
+    /// Si la respuesta de la api informa que hubo un error, devuelve Some de `code` y `message`.
+    /// Caso contrario devuelve None.
+    fn get_error_code_and_msg_from_error_response(
+        &self,
+        res_json: &serde_json::Value,
+    ) -> Option<(String, String)> {
+        if let Some(error) = res_json.get("error") {
+            // Errores con formato { "error": {"code": ..., "message": ... } }
+            let code = error.get("code").and_then(|c| c.as_str());
+            let message = error.get("message").and_then(|m| m.as_str());
+
+            if let (Some(cod), Some(msg)) = (code, message) {
+                return Some((cod.to_string(), msg.to_string()));
+            }
+        } else if let Some(code) = res_json.get("code").and_then(|c| c.as_str()) {
+            // Errores con formato { "code": "...", "message": "..." }
+            if let Some(message) = res_json.get("message").and_then(|c| c.as_str()) {
+                return Some((code.to_string(), message.to_string()));
+            }
+        }
+        None
+    }
+}
+
+impl IncidentDetector for CustomVisionDetector {
+    /// Le envía la imagen al proveedor de ia y analiza su respuesta para concluir si la imagen contiene o no
+    /// un incidente.
+    fn is_incident(&self, image: &CameraImage) -> Result<bool, Box<dyn Error>> {
+        let api_credentials = ApiCredentials::new(self.properties.get_api_credentials_file_path());
+
+        let (client, headers) = create_client_and_headers(&api_credentials)?;
+
+        println!("DEBUG: Image size: {}", image.get_bytes().len()); // debug
+
+        // Se envía la imagen al proveedor
+        let res = client
+            .post(api_credentials.get_endpoint())
+            .headers(headers)
+            .body(image.get_bytes().to_vec())
+            .send()?;
+
+        println!("DEBUG: res.status: {}", res.status()); // debug
+
+        let res_text = res.text()?;
+        let incident_probability = self.process_response(&res_text)?;
+
+        println!("Detector: Probability: {:?}", incident_probability);
+        self.logger
+            .log(format!("Detector: Probability: {:?}", incident_probability));
+        Ok(incident_probability > self.properties.get_inc_threshold())
+    }
+}
+
+fn create_client_and_headers(
+    api_credentials: &ApiCredentials,
+) -> Result<(Client, HeaderMap), Box<dyn Error>> {
+    let client = Client::new();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Prediction-Key",
+        api_credentials.get_prediction_key().parse()?,
+    );
+    headers.insert(CONTENT_TYPE, "application/octet-stream".parse()?);
+    Ok((client, headers))
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use crate::{apps::sist_camaras::ai_detection::properties::DetectorProperties, logging::string_logger::StringLogger};
+    use super::CustomVisionDetector;
+
+    // Devuelve un json de prueba, como una str.
+    fn create_json_str() -> &'static str {
+        r#"{
+            "id": "a355738d-f52b-437f-a3ed-4098a1b93044",
+            "project": "ee406da4-a7f3-4022-9316-f63d2fef1a20",
+            "iteration": "9c02f50d-9e3e-42d9-9f70-e787dc71adb7",
+            "created": "2024-08-13T11:14:06.633Z",
+            "predictions": [
+                {
+                    "probability": 0.9992791,
+                    "tagId": "6649b3d8-896b-486d-bbc1-c46aff462304",
+                    "tagName": "incidente"
+                },
+                {
+                    "probability": 0.0007209157,
+                    "tagId": "9b3e603a-592c-4811-9e04-44207959f4be",
+                    "tagName": "Negative"
+                }
+            ]
+        }"#
+    }
+
+    // Devuelve un json de prueba, como una str.
+    fn _create_json_str_aux() -> &'static str {
+        r#"{"id": "a355738d-f52b-437f-a3ed-4098a1b93044", "project": "ee406da4-a7f3-4022-9316-f63d2fef1a20", "iteration": "9c02f50d-9e3e-42d9-9f70-e787dc71adb7", "created": "2024-08-13T11:14:06.633Z", "predictions": [{"probability": 0.9992791, "tagId": "6649b3d8-896b-486d-bbc1-c46aff462304", "tagName": "incidente"}, {
+                    "probability": 0.0007209157, "tagId": "9b3e603a-592c-4811-9e04-44207959f4be", "tagName": "Negative"}]}"#
+    }
+
+    fn create_detector() -> CustomVisionDetector {
+        const PROPERTIES_FILE: &str = "./src/apps/sist_camaras/ai_detection/properties.txt";
+        let properties = DetectorProperties::new(PROPERTIES_FILE).unwrap();
+        let (string_tx, _rx) = mpsc::channel::<String>();
+        let logger = StringLogger::new(string_tx);
+
+        CustomVisionDetector::new(properties, logger)
+    }
+
+    #[test]
+    fn test_process_response() {
+        // Creamos un json para emular una respuesta de la api
+        let json_response_str = create_json_str();
+        let detector = create_detector();
+
+        // Procesamos la response como la que contesta el llamado a la api, para obtener la probability
+        let res = detector.process_response(json_response_str);
+
+        assert!(res.is_ok());
+    }
+
+}
@@ -0,0 +1,73 @@
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+/// Cómo se agrupan las imágenes antes de pasárselas al detector: como mucho `max_batch_size` por lote, esperando
+/// hasta `max_wait` desde la primera a que lleguen más; y cuántos hilos (`workers`) analizan lotes a la vez.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BatchSettings {
+    max_batch_size: usize,
+    max_wait: Duration,
+    workers: usize,
+}
+
+impl BatchSettings {
+    pub fn new(max_batch_size: usize, max_wait: Duration, workers: usize) -> Self {
+        Self {
+            max_batch_size: max_batch_size.max(1),
+            max_wait,
+            workers: workers.max(1),
+        }
+    }
+
+    pub fn get_max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Espera a recibir por `rx` el próximo lote: bloquea hasta el primer elemento, y luego junta los que lleguen
+    /// hasta completar el lote o hasta que pase `max_wait`. Devuelve `None` cuando el channel se cerró y ya no
+    /// quedan elementos.
+    pub fn collect_batch<T>(&self, rx: &Receiver<T>) -> Option<Vec<T>> {
+        let mut batch = vec![rx.recv().ok()?];
+        let deadline = Instant::now() + self.max_wait;
+        while batch.len() < self.max_batch_size {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(item) => batch.push(item),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::BatchSettings;
+
+    #[test]
+    fn test_1_se_arman_lotes_por_tamanio_o_por_tiempo() {
+        let settings = BatchSettings::new(3, Duration::from_millis(50), 2);
+        let (tx, rx) = mpsc::channel();
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+
+        // Se completa el primer lote, y el segundo se cierra por tiempo.
+        assert_eq!(settings.collect_batch(&rx), Some(vec![0, 1, 2]));
+        assert_eq!(settings.collect_batch(&rx), Some(vec![3]));
+
+        // Cerrado el channel, se devuelve lo que quedaba, y luego ya no hay lotes.
+        tx.send(4).unwrap();
+        drop(tx);
+        assert_eq!(settings.collect_batch(&rx), Some(vec![4]));
+        assert_eq!(settings.collect_batch(&rx), None);
+    }
+}
@@ -0,0 +1,125 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::blocking::Client;
+use std::{error::Error, io::ErrorKind, time::Duration};
+
+use crate::apps::sist_camaras::ai_detection::incident_detector::{CameraImage, IncidentDetector};
+
+/// Detector que le envía las imágenes a un servicio de inferencia propio, por HTTP, de a lotes.
+/// Hace un POST a `endpoint` con `{"images": [{"camera": id, "name": "archivo.jpg", "data": "<base64>"}, ...]}`,
+/// y espera como respuesta `{"probabilities": [p1, ...]}`, con la probabilidad de incidente de cada imagen en el
+/// mismo orden. Una imagen contiene un incidente si su probabilidad supera `threshold`.
+#[derive(Debug)]
+pub struct HttpDetector {
+    client: Client,
+    endpoint: String,
+    threshold: f64,
+}
+
+impl HttpDetector {
+    pub fn new(endpoint: String, threshold: f64, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            endpoint,
+            threshold,
+        })
+    }
+
+    /// Arma el cuerpo del pedido para las imágenes `images`.
+    fn request_body(&self, images: &[CameraImage]) -> serde_json::Value {
+        let images: Vec<serde_json::Value> = images
+            .iter()
+            .map(|image| {
+                let name = image
+                    .get_path()
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default();
+                serde_json::json!({
+                    "camera": image.get_cam_id(),
+                    "name": name,
+                    "data": STANDARD.encode(image.get_bytes()),
+                })
+            })
+            .collect();
+        serde_json::json!({ "images": images })
+    }
+
+    /// Interpreta la respuesta del servicio, y devuelve si cada una de las `expected` imágenes contiene un incidente.
+    fn process_response(&self, res_text: &str, expected: usize) -> Result<Vec<bool>, Box<dyn Error>> {
+        let res_json: serde_json::Value = serde_json::from_str(res_text)?;
+        let probabilities = res_json["probabilities"]
+            .as_array()
+            .map(|probabilities| probabilities.iter().map(|p| p.as_f64()).collect::<Option<Vec<f64>>>());
+        match probabilities {
+            Some(Some(probabilities)) if probabilities.len() == expected => Ok(probabilities
+                .into_iter()
+                .map(|probability| probability > self.threshold)
+                .collect()),
+            _ => Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Respuesta inválida del servicio de inferencia: {}.", res_json),
+            ))),
+        }
+    }
+
+    /// Envía el lote al servicio, y devuelve si cada imagen contiene un incidente.
+    fn infer(&self, images: &[CameraImage]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let res = self
+            .client
+            .post(&self.endpoint)
+            .json(&self.request_body(images))
+            .send()?
+            .error_for_status()?;
+        self.process_response(&res.text()?, images.len())
+    }
+}
+
+impl IncidentDetector for HttpDetector {
+    fn is_incident(&self, image: &CameraImage) -> Result<bool, Box<dyn Error>> {
+        Ok(self.infer(std::slice::from_ref(image))?[0])
+    }
+
+    /// Envía todas las imágenes en un solo pedido. Si el pedido falla, falla para todas.
+    fn are_incidents(&self, images: &[CameraImage]) -> Vec<Result<bool, Box<dyn Error>>> {
+        match self.infer(images) {
+            Ok(results) => results.into_iter().map(Ok).collect(),
+            Err(e) => images
+                .iter()
+                .map(|_| Err(Box::<dyn Error>::from(e.to_string())))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::HttpDetector;
+    use crate::apps::sist_camaras::ai_detection::incident_detector::CameraImage;
+
+    #[test]
+    fn test_1_se_arma_el_lote_y_se_interpreta_la_respuesta_del_servicio() {
+        let detector =
+            HttpDetector::new("http://127.0.0.1:1/inferir".to_string(), 0.7, Duration::from_secs(1)).unwrap();
+        let images = vec![
+            CameraImage::new(1, PathBuf::from("camera_1/a.jpg"), vec![1, 2, 3]),
+            CameraImage::new(2, PathBuf::from("camera_2/b.jpg"), vec![]),
+        ];
+
+        let body = detector.request_body(&images);
+        assert_eq!(body["images"][0]["camera"], 1);
+        assert_eq!(body["images"][0]["name"], "a.jpg");
+        assert_eq!(body["images"][0]["data"], "AQID");
+
+        let res = detector.process_response(r#"{"probabilities": [0.9, 0.2]}"#, 2).unwrap();
+        assert_eq!(res, vec![true, false]);
+        assert!(detector.process_response(r#"{"probabilities": [0.9]}"#, 2).is_err());
+        assert!(detector.process_response(r#"{"error": "sin modelo"}"#, 2).is_err());
+
+        // Si el servicio no responde, fallan todas las imágenes del lote.
+        let results = super::IncidentDetector::are_incidents(&detector, &images);
+        assert!(results.len() == 2 && results.iter().all(|res| res.is_err()));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    error::Error,
+    fmt::Debug,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::apps::properties::Properties;
+
+/// Extensión del archivo de metadatos que puede acompañar a una imagen (ie `imagen.jpg.meta` para `imagen.jpg`).
+pub const METADATA_EXTENSION: &str = "meta";
+
+/// Imagen capturada por una cámara: el id de la cámara, el archivo del que se leyó, y su contenido.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraImage {
+    cam_id: u8,
+    path: PathBuf,
+    bytes: Vec<u8>,
+}
+
+impl CameraImage {
+    pub fn new(cam_id: u8, path: PathBuf, bytes: Vec<u8>) -> Self {
+        Self {
+            cam_id,
+            path,
+            bytes,
+        }
+    }
+
+    pub fn get_cam_id(&self) -> u8 {
+        self.cam_id
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Analiza las imágenes de las cámaras para decidir si contienen un incidente. Permite usar distintos detectores
+/// (una regla simple, un modelo de inteligencia artificial, etc) sin cambiar cómo se procesan las imágenes.
+pub trait IncidentDetector: Debug + Send + Sync {
+    /// Devuelve si la imagen `image` contiene un incidente.
+    fn is_incident(&self, image: &CameraImage) -> Result<bool, Box<dyn Error>>;
+
+    /// Devuelve, para cada imagen del lote `images`, si contiene un incidente. Por defecto las analiza de a una;
+    /// los detectores que pueden analizar un lote de una sola vez (ie un modelo, o un servicio remoto) lo redefinen.
+    fn are_incidents(&self, images: &[CameraImage]) -> Vec<Result<bool, Box<dyn Error>>> {
+        images.iter().map(|image| self.is_incident(image)).collect()
+    }
+}
+
+/// Detector por regla: una imagen contiene un incidente si su archivo de metadatos (`<imagen>.meta`, con una línea
+/// `incident=true` o `incident=false`) así lo indica; y si no lo tiene, si su nombre contiene alguna de las
+/// palabras clave (ie `accidente_01.jpg`). No analiza el contenido de la imagen.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FilenameRuleDetector {
+    keywords: Vec<String>,
+}
+
+impl FilenameRuleDetector {
+    pub fn new(keywords: Vec<String>) -> Self {
+        let keywords = keywords
+            .into_iter()
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+        Self { keywords }
+    }
+
+    /// Devuelve si el nombre del archivo contiene alguna de las palabras clave.
+    fn name_matches(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+        let name = name.to_lowercase();
+        self.keywords.iter().any(|keyword| name.contains(keyword))
+    }
+
+    /// Devuelve lo que indica el archivo de metadatos de la imagen de `path`, si existe.
+    fn metadata_says(&self, path: &Path) -> Result<Option<bool>, Box<dyn Error>> {
+        let mut metadata_path = path.as_os_str().to_owned();
+        metadata_path.push(format!(".{}", METADATA_EXTENSION));
+        let contents = match fs::read_to_string(PathBuf::from(metadata_path)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Box::new(e)),
+        };
+        let metadata = Properties::from_contents(contents.trim())?;
+        match metadata.get("incident").map(String::as_str) {
+            Some("true") => Ok(Some(true)),
+            Some("false") => Ok(Some(false)),
+            _ => Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "El archivo de metadatos de la imagen debe tener incident=true o incident=false.",
+            ))),
+        }
+    }
+}
+
+impl IncidentDetector for FilenameRuleDetector {
+    fn is_incident(&self, image: &CameraImage) -> Result<bool, Box<dyn Error>> {
+        match self.metadata_says(image.get_path())? {
+            Some(is_incident) => Ok(is_incident),
+            None => Ok(self.name_matches(image.get_path())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use super::{CameraImage, FilenameRuleDetector, IncidentDetector};
+
+    #[test]
+    fn test_1_la_regla_detecta_por_nombre_salvo_que_los_metadatos_indiquen_otra_cosa() {
+        let detector = FilenameRuleDetector::new(vec!["Accidente".to_string(), " incendio ".to_string()]);
+        let image = |path: &str| CameraImage::new(1, PathBuf::from(path), vec![1, 2, 3]);
+
+        assert!(detector.is_incident(&image("camera_1/ACCIDENTE_01.jpg")).unwrap());
+        assert!(detector.is_incident(&image("camera_1/incendio.jpeg")).unwrap());
+        assert!(!detector.is_incident(&image("camera_1/calle.jpg")).unwrap());
+
+        // Los metadatos tienen prioridad sobre el nombre.
+        let dir = std::env::temp_dir().join(format!("rustx_filename_rule_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("calle.jpg.meta"), "incident=true\n").unwrap();
+        fs::write(dir.join("accidente.jpg.meta"), "incident=false").unwrap();
+        fs::write(dir.join("otra.jpg.meta"), "incidente").unwrap();
+        let in_dir = |name: &str| CameraImage::new(1, dir.join(name), vec![]);
+        assert!(detector.is_incident(&in_dir("calle.jpg")).unwrap());
+        assert!(!detector.is_incident(&in_dir("accidente.jpg")).unwrap());
+        assert!(detector.is_incident(&in_dir("otra.jpg")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
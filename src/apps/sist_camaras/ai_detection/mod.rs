@@ -1,4 +1,9 @@
 pub mod ai_detector_manager;
 pub mod ai_detector;
 pub mod api_credentials;
+pub mod custom_vision_detector;
+pub mod detection_batcher;
+pub mod http_detector;
+pub mod incident_detector;
+pub mod onnx_detector;
 pub mod properties;
\ No newline at end of file
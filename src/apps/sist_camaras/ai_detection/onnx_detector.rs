@@ -0,0 +1,141 @@
+use image::imageops::FilterType;
+use std::error::Error;
+
+use crate::apps::sist_camaras::ai_detection::incident_detector::CameraImage;
+
+/// Convierte las imágenes `images` en la entrada de un modelo de clasificación de imágenes: las decodifica, las
+/// escala a `size` x `size` píxeles, y las devuelve en formato NCHW (imagen, canal RGB, fila, columna), con valores
+/// entre 0 y 1.
+pub fn preprocess_batch(images: &[CameraImage], size: u32) -> Result<Vec<f32>, Box<dyn Error>> {
+    let pixels = (size * size) as usize;
+    let mut input = Vec::with_capacity(images.len() * 3 * pixels);
+    for image in images {
+        let rgb = image::load_from_memory(image.get_bytes())?
+            .resize_exact(size, size, FilterType::Triangle)
+            .to_rgb8();
+        for channel in 0..3 {
+            input.extend(rgb.pixels().map(|pixel| pixel.0[channel] as f32 / 255.0));
+        }
+    }
+    Ok(input)
+}
+
+#[cfg(feature = "onnx")]
+pub use self::onnx::{OnnxDetector, OnnxSettings};
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use ort::{session::Session, value::Tensor};
+    use std::{error::Error, io::ErrorKind, sync::Mutex};
+
+    use super::preprocess_batch;
+    use crate::apps::sist_camaras::ai_detection::incident_detector::{CameraImage, IncidentDetector};
+
+    /// Configuración del modelo ONNX: el archivo del modelo, el tamaño de las imágenes que espera, qué clase de su
+    /// salida es la de incidente, y a partir de qué probabilidad se la considera. Si se indica `runtime_path`, la
+    /// biblioteca de ONNX Runtime se carga de allí; y si no, de la variable de entorno `ORT_DYLIB_PATH`.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct OnnxSettings {
+        pub model_path: String,
+        pub input_size: u32,
+        pub incident_index: usize,
+        pub threshold: f64,
+        pub runtime_path: Option<String>,
+    }
+
+    /// Detector que clasifica las imágenes con un modelo ONNX local, que recibe un lote de imágenes en formato NCHW
+    /// y devuelve, por cada una, la probabilidad de cada clase. Analiza el lote entero en una sola inferencia.
+    #[derive(Debug)]
+    pub struct OnnxDetector {
+        session: Mutex<Session>,
+        settings: OnnxSettings,
+    }
+
+    impl OnnxDetector {
+        pub fn new(settings: OnnxSettings) -> Result<Self, Box<dyn Error>> {
+            if let Some(runtime_path) = &settings.runtime_path {
+                ort::init_from(runtime_path).commit()?;
+            }
+            let session = Session::builder()?.commit_from_file(&settings.model_path)?;
+            Ok(Self {
+                session: Mutex::new(session),
+                settings,
+            })
+        }
+
+        /// Ejecuta el modelo sobre el lote, y devuelve si cada imagen contiene un incidente.
+        fn infer(&self, images: &[CameraImage]) -> Result<Vec<bool>, Box<dyn Error>> {
+            let size = self.settings.input_size as usize;
+            let input = preprocess_batch(images, self.settings.input_size)?;
+            let tensor = Tensor::from_array(([images.len(), 3, size, size], input))?;
+
+            let mut session = self
+                .session
+                .lock()
+                .map_err(|_| std::io::Error::new(ErrorKind::Other, "Error al tomar lock de la sesión ONNX."))?;
+            let outputs = session.run(ort::inputs![tensor])?;
+            let (shape, probabilities) = outputs[0].try_extract_tensor::<f32>()?;
+
+            // La salida tiene una fila por imagen, con la probabilidad de cada clase.
+            let classes = probabilities.len() / images.len().max(1);
+            if shape.first() != Some(&(images.len() as i64)) || self.settings.incident_index >= classes {
+                return Err(Box::new(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Salida inesperada del modelo ONNX: {:?}.", shape),
+                )));
+            }
+            Ok(probabilities
+                .chunks(classes)
+                .map(|row| row[self.settings.incident_index] as f64 > self.settings.threshold)
+                .collect())
+        }
+    }
+
+    impl IncidentDetector for OnnxDetector {
+        fn is_incident(&self, image: &CameraImage) -> Result<bool, Box<dyn Error>> {
+            Ok(self.infer(std::slice::from_ref(image))?[0])
+        }
+
+        /// Analiza todas las imágenes en una sola inferencia. Si falla, falla para todas.
+        fn are_incidents(&self, images: &[CameraImage]) -> Vec<Result<bool, Box<dyn Error>>> {
+            match self.infer(images) {
+                Ok(results) => results.into_iter().map(Ok).collect(),
+                Err(e) => images
+                    .iter()
+                    .map(|_| Err(Box::<dyn Error>::from(e.to_string())))
+                    .collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{ImageBuffer, ImageOutputFormat, Rgb};
+    use std::path::PathBuf;
+
+    use super::preprocess_batch;
+    use crate::apps::sist_camaras::ai_detection::incident_detector::CameraImage;
+
+    #[test]
+    fn test_1_las_imagenes_se_convierten_a_la_entrada_del_modelo() {
+        // Una imagen roja de 4x4, en png.
+        let red = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0]));
+        let mut png = vec![];
+        image::DynamicImage::ImageRgb8(red)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        let images = vec![
+            CameraImage::new(1, PathBuf::from("camera_1/a.png"), png.clone()),
+            CameraImage::new(2, PathBuf::from("camera_2/b.png"), png),
+        ];
+
+        let input = preprocess_batch(&images, 2).unwrap();
+        // 2 imágenes, 3 canales, 2x2 píxeles: canal rojo en 1, verde y azul en 0.
+        assert_eq!(input.len(), 2 * 3 * 4);
+        assert_eq!(&input[..12], &[1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        let not_an_image = vec![CameraImage::new(1, PathBuf::from("camera_1/c.jpg"), vec![1, 2, 3])];
+        assert!(preprocess_batch(&not_an_image, 2).is_err());
+    }
+}
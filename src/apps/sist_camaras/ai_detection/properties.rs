@@ -1,6 +1,21 @@
-use std::io::{Error, ErrorKind};
-
-use crate::apps::properties::Properties;
+use std::{
+    io::{Error, ErrorKind},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::apps::{
+    properties::Properties, sist_camaras::ai_detection::detection_batcher::BatchSettings,
+};
+
+/// Detector por regla sobre el nombre y los metadatos de la imagen.
+pub const FILENAME_DETECTOR: &str = "filename";
+/// Detector que consulta al proveedor de inteligencia artificial.
+pub const CUSTOM_VISION_DETECTOR: &str = "custom_vision";
+/// Detector que consulta a un servicio de inferencia propio, por HTTP.
+pub const HTTP_DETECTOR: &str = "http";
+/// Detector que usa un modelo ONNX local.
+pub const ONNX_DETECTOR: &str = "onnx";
 
 /// Struct que posee las constantes para el módulo de detección automática de incidentes del Sistema Cámaras,
 /// cargadas desde su archivo de configuración.
@@ -12,6 +27,15 @@ pub struct DetectorProperties {
     inc_threshold: f64,
     img_valid_extension1: String,
     img_valid_extension2: String,
+    detector: String,
+    incident_keywords: Vec<String>,
+    http_endpoint: Option<String>,
+    http_timeout: Duration,
+    onnx_model_path: Option<String>,
+    onnx_input_size: u32,
+    onnx_incident_index: usize,
+    onnx_runtime_path: Option<String>,
+    batch_settings: BatchSettings,
 }
 
 impl DetectorProperties {
@@ -86,6 +110,29 @@ impl DetectorProperties {
             ));
         }
 
+        // Opcionales: por defecto, se usa el proveedor de inteligencia artificial
+        let detector = global_properties
+            .get("detector")
+            .cloned()
+            .unwrap_or_else(|| String::from(CUSTOM_VISION_DETECTOR));
+
+        let incident_keywords = global_properties
+            .get("incident_keywords")
+            .map(|keywords| keywords.split(',').map(String::from).collect())
+            .unwrap_or_default();
+
+        let http_endpoint = global_properties.get("http_endpoint").cloned();
+        let http_timeout_secs = parse_optional(&global_properties, "http_timeout_secs", 10)?;
+        let onnx_model_path = global_properties.get("onnx_model_path").cloned();
+        let onnx_input_size = parse_optional(&global_properties, "onnx_input_size", 224)?;
+        let onnx_incident_index = parse_optional(&global_properties, "onnx_incident_index", 0)?;
+        let onnx_runtime_path = global_properties.get("onnx_runtime_path").cloned();
+        let batch_settings = BatchSettings::new(
+            parse_optional(&global_properties, "batch_size", 1)?,
+            Duration::from_millis(parse_optional(&global_properties, "batch_wait_ms", 200)?),
+            parse_optional(&global_properties, "detector_workers", 6)?,
+        );
+
         Ok(Self {
             base_dir,
             api_credentials_file_path,
@@ -93,6 +140,15 @@ impl DetectorProperties {
             inc_threshold,
             img_valid_extension1,
             img_valid_extension2,
+            detector,
+            incident_keywords,
+            http_endpoint,
+            http_timeout: Duration::from_secs(http_timeout_secs),
+            onnx_model_path,
+            onnx_input_size,
+            onnx_incident_index,
+            onnx_runtime_path,
+            batch_settings,
         })
     }
 
@@ -128,8 +184,64 @@ impl DetectorProperties {
         self.img_valid_extension2.as_str()
     }
 
+    /// Devuelve qué detector analiza las imágenes: `filename` (por regla) o `custom_vision` (proveedor de ia).
+    pub fn get_detector(&self) -> &str {
+        self.detector.as_str()
+    }
+
+    /// Devuelve las palabras clave que, en el nombre de una imagen, indican que contiene un incidente
+    /// (para el detector `filename`).
+    pub fn get_incident_keywords(&self) -> Vec<String> {
+        self.incident_keywords.clone()
+    }
+
+    /// Devuelve la url del servicio de inferencia (para el detector `http`), si está configurada.
+    pub fn get_http_endpoint(&self) -> Option<&str> {
+        self.http_endpoint.as_deref()
+    }
+
+    /// Devuelve cuánto se espera la respuesta del servicio de inferencia.
+    pub fn get_http_timeout(&self) -> Duration {
+        self.http_timeout
+    }
+
+    /// Devuelve el archivo del modelo (para el detector `onnx`), si está configurado.
+    pub fn get_onnx_model_path(&self) -> Option<&str> {
+        self.onnx_model_path.as_deref()
+    }
+
+    /// Devuelve el tamaño en píxeles de las imágenes que recibe el modelo.
+    pub fn get_onnx_input_size(&self) -> u32 {
+        self.onnx_input_size
+    }
+
+    /// Devuelve la posición de la clase incidente en la salida del modelo.
+    pub fn get_onnx_incident_index(&self) -> usize {
+        self.onnx_incident_index
+    }
+
+    /// Devuelve de dónde cargar la biblioteca de ONNX Runtime, si está configurado.
+    pub fn get_onnx_runtime_path(&self) -> Option<&str> {
+        self.onnx_runtime_path.as_deref()
+    }
+
+    /// Devuelve cómo se agrupan las imágenes en lotes, y cuántos hilos las analizan.
+    pub fn get_batch_settings(&self) -> BatchSettings {
+        self.batch_settings
+    }
+
     /// Devuelve vector con las extensiones de imagen válidas a procesar.
     pub fn get_img_valid_extensions(&self) -> Vec<&str> {
         vec![self.img_valid_extension1.as_str(), self.img_valid_extension2.as_str()]
     }
 }
+
+/// Parsea la property opcional `key`, o devuelve `default` si no está definida.
+fn parse_optional<T: FromStr>(properties: &Properties, key: &str, default: T) -> Result<T, Error> {
+    match properties.get(key) {
+        Some(prop) => prop
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Valor inválido para {}.", key))),
+        None => Ok(default),
+    }
+}
@@ -1,101 +1,173 @@
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+use crate::apps::geo_position::GeoPosition;
 use crate::apps::{incident_data::incident_info::IncidentInfo, sist_camaras::camera_state::CameraState};
+use crate::apps::payload_version::{split_version_header, with_version_header};
+use crate::apps::sist_dron::calculations::{DistanceModel, METERS_PER_DEGREE};
+
+/// Versión del formato en bytes de la cámara. La 1 agregó el encabezado de versión a la legacy, y la 2 el rango
+/// en metros.
+pub const CAMERA_VERSION: u8 = 2;
+
+/// Convierte un rango en cuadras, como se configuraban las cámaras antes, a metros.
+pub fn blocks_to_meters(blocks: u8) -> f64 {
+    (0.00135 + 0.0012 * blocks as f64) * METERS_PER_DEGREE
+}
+
+/// Convierte un rango en metros a la cantidad de cuadras más cercana, para el campo legacy de los bytes.
+fn meters_to_blocks(meters: f64) -> u8 {
+    ((meters / METERS_PER_DEGREE - 0.00135) / 0.0012)
+        .round()
+        .clamp(0.0, u8::MAX as f64) as u8
+}
+
+/// Lee un rango configurado para una cámara: en metros si termina en `m` (ie `250m`), o en cuadras si no (ie `2`).
+/// Devuelve el rango en metros, o un error si no es un número, o si es cero o negativo.
+pub fn parse_range(value: &str) -> Result<f64, Error> {
+    let value = value.trim();
+    let meters = match value.strip_suffix('m') {
+        Some(meters) => meters.trim().parse::<f64>().ok(),
+        None => value.parse::<u8>().ok().map(blocks_to_meters),
+    };
+    match meters {
+        Some(meters) => check_range(meters),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Rango inválido: {}. Debe ser en metros (ie 250m) o en cuadras (ie 2).", value),
+        )),
+    }
+}
+
+/// Rechaza los rangos que no son positivos.
+fn check_range(meters: f64) -> Result<f64, Error> {
+    if meters.is_finite() && meters > 0.0 {
+        Ok(meters)
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("El rango de la cámara debe ser mayor a cero, y es {} m.", meters),
+        ))
+    }
+}
 
 #[derive(Debug, PartialEq)]
 /// Struct que representa el estado de una de las cámaras del sistema central de cámaras.
 /// Tiene:
 /// - id;
 /// - latitud y longitud
-/// - estado;
-/// - rango dentro del cual interesará manejar incidentes, simllar a un radio;
+/// - estado, y el estado de reposo al que vuelve cuando no sigue incidentes;
+/// - rango dentro del cual interesará manejar incidentes, un radio en metros;
 /// - border_cameras: vector con los ids de sus cámaras lindantes;
 /// - deleted: campo que indica si la Camera ha pasado por un borrado lógico en el sistema central de cámaras;
 /// - incs_being_managed: vector con los ids de los incidentes a los que la Camera está prestando atención, esto es, ids de los incidentes que ocasionan que esta Camera esté en estado activo.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Camera {
     id: u8,
-    latitude: f64,
-    longitude: f64,
+    #[serde(flatten)]
+    position: GeoPosition,
     state: CameraState,
-    range: u8,
+    #[serde(skip)]
+    idle_state: CameraState, // SavingMode o HighSensitivity, según el horario.
+    range: f64, // en metros
     border_cameras: Vec<u8>,
     deleted: bool,
+    #[serde(skip)]
     incs_being_managed: Vec<IncidentInfo>, // info (id y src) de los incidentes a los que está prestando atención
+    #[serde(skip)]
+    distance_model: DistanceModel, // cómo calcula si una posición está en su rango.
 }
 
 impl Camera {
-    /// Crea un struct `Camera`.
-    pub fn new(id: u8, latitude: f64, longitude: f64, range: u8) -> Self {
+    /// Crea un struct `Camera`, con rango de `range` cuadras.
+    pub fn new(id: u8, position: GeoPosition, range: u8) -> Self {
+        Self::with_range_in_meters(id, position, blocks_to_meters(range))
+    }
+
+    /// Crea un struct `Camera` con rango de `range` metros. Devuelve error si el rango es cero o negativo.
+    pub fn new_in_meters(id: u8, position: GeoPosition, range: f64) -> Result<Self, Error> {
+        Ok(Self::with_range_in_meters(id, position, check_range(range)?))
+    }
+
+    fn with_range_in_meters(id: u8, position: GeoPosition, range: f64) -> Self {
         Self {
             id,
-            latitude,
-            longitude,
+            position,
             state: CameraState::SavingMode,
+            idle_state: CameraState::SavingMode,
             range,
             border_cameras: vec![],
             deleted: false,
             incs_being_managed: vec![],
+            distance_model: DistanceModel::default(),
         }
     }
 
-    /// Pasa un struct Camera a bytes.
+    /// Indica cómo calcula si una posición está en su rango; por defecto, sobre la superficie de la Tierra.
+    pub fn with_distance_model(mut self, distance_model: DistanceModel) -> Self {
+        self.distance_model = distance_model;
+        self
+    }
+
+    /// Pasa un struct Camera a bytes, precedidos por el encabezado con su versión. El rango va en cuadras, como en
+    /// la legacy, y al final en metros.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.push(self.id);
-        bytes.extend_from_slice(&self.latitude.to_be_bytes());
-        bytes.extend_from_slice(&self.longitude.to_be_bytes());
+        bytes.extend_from_slice(&self.position.to_bytes());
         bytes.extend_from_slice(&self.state.to_byte());
-        bytes.extend_from_slice(&self.range.to_be_bytes());
+        bytes.push(meters_to_blocks(self.range));
         bytes.extend_from_slice(&(self.border_cameras.len() as u8).to_be_bytes());
         for camera in &self.border_cameras {
             bytes.push(*camera);
         }
         bytes.push(self.deleted as u8);
-        bytes
-    }
-
-    /// Lee bytes para devolver un struct Camera.
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let id = bytes[0];
-        let latitude = f64::from_be_bytes([
-            bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7], bytes[8],
-        ]);
-        let longitude = f64::from_be_bytes([
-            bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15], bytes[16],
-        ]);
-        let state = CameraState::from_byte([bytes[17]]);
-        let range = bytes[18];
-        let border_cameras_len = bytes[19];
-        let mut border_cameras = vec![];
-        for i in 0..border_cameras_len {
-            border_cameras.push(bytes[20 + i as usize]);
-        }
-        let deleted = bytes[20 + border_cameras_len as usize] == 1;
-        Self {
+        bytes.extend_from_slice(&self.range.to_be_bytes());
+        with_version_header(CAMERA_VERSION, bytes)
+    }
+
+    /// Lee bytes, con o sin encabezado de versión, para devolver un struct Camera. Ignora los campos de versiones
+    /// más nuevas que no conoce, y usa valores por defecto para los que agregaron versiones posteriores a la recibida.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (_version, mut reader) = split_version_header(bytes);
+        let id = reader.read_u8()?;
+        let position = GeoPosition::read_from(&mut reader)?;
+        let state = CameraState::from_byte([reader.read_u8()?])?;
+        let blocks = reader.read_u8()?;
+        let border_cameras_len = reader.read_u8()?;
+        let border_cameras = reader.read_bytes(border_cameras_len as usize)?.to_vec();
+        let deleted = reader.read_u8()? == 1;
+        let range = if reader.has_remaining() {
+            reader.read_f64_be()?
+        } else {
+            blocks_to_meters(blocks)
+        };
+        Ok(Self {
             id,
-            latitude,
-            longitude,
+            position,
             state,
+            idle_state: CameraState::SavingMode,
             range,
             border_cameras,
             deleted,
             incs_being_managed: vec![],
-        }
+            distance_model: DistanceModel::default(),
+        })
     }
 
     /// Muestra por pantalla los datos de la cámara.
     pub fn display(&self) {
         println!("ID: {}", self.id);
-        println!("Latitude: {}", self.latitude);
-        println!("Longitude: {}", self.longitude);
+        println!("Latitude: {}", self.position.get_latitude());
+        println!("Longitude: {}", self.position.get_longitude());
         println!("Estado: {:?}", self.state);
-        println!("Rango de alcance: {}", self.range);
+        println!("Rango de alcance: {:.0} m", self.range);
         println!("Cámaras lindantes: {:?}\n", self.border_cameras);
     }
 
-    /// Devuelve si el incidente de coordenadas `(inc_coord_x, inc_coord_y)`
-    /// está en el rango de la cámara `Self`.
-    pub fn will_register(&self, (latitude, longitude): (f64, f64)) -> bool {
-        self.is_within_range_from_self(latitude, longitude, self.range as f64)
+    /// Devuelve si el incidente en `position` está en el rango de la cámara `Self`.
+    pub fn will_register(&self, position: GeoPosition) -> bool {
+        self.is_within_range_from_self(position)
     }
 
     /// Modifica su estado al recibido por parámetro, y se marca un atributo
@@ -109,14 +181,20 @@ impl Camera {
         &mut self.border_cameras
     }
 
+    /// Devuelve los ids de sus cámaras lindantes, sin permitir modificarlos.
+    pub fn get_border_cameras(&self) -> &[u8] {
+        &self.border_cameras
+    }
+
     /// Agrega el inc_id a su lista de incidentes a los que le presta atención,
     /// y se cambia el estado a activo. Maneja su marcado.
     /// Devuelve si cambió su estado interno (a Activo).
     pub fn append_to_incs_being_managed(&mut self, inc_info: IncidentInfo) -> bool {
         let mut state_has_changed = false;
         self.incs_being_managed.push(inc_info);
-        // Si ya estaba en estado activo, la dejo como estaba (para no marcarla como modificada)
-        if self.state != CameraState::Active {
+        // Si ya estaba en estado activo, la dejo como estaba (para no marcarla como modificada); y si está rota,
+        // recién pasará a activo cuando se recupere
+        if self.state.is_idle() {
             self.set_state_to(CameraState::Active);
             state_has_changed = true;
         };
@@ -124,21 +202,70 @@ impl Camera {
     }
 
     /// Elimina el inc_id de su lista de incidentes a los que les presta atención,
-    /// y si ya no le quedan incidentes, se cambia el estado a modo ahorro de energía.
-    /// Devuelve si cambió su estado interno (a Ahorro de energía).
+    /// y si ya no le quedan incidentes, se cambia el estado a su estado de reposo (ie ahorro de energía).
+    /// Devuelve si cambió su estado interno (a reposo).
     pub fn remove_from_incs_being_managed(&mut self, inc_info: IncidentInfo) -> bool {
         let mut state_has_changed = false;
         if let Some(pos_de_inc_info) = self.incs_being_managed.iter().position(|&x| x == inc_info) {
             self.incs_being_managed.remove(pos_de_inc_info);
             // Maneja su lista y se cambia el estado si corresponde
-            if self.incs_being_managed.is_empty() {
-                self.set_state_to(CameraState::SavingMode);
+            if self.incs_being_managed.is_empty() && self.state == CameraState::Active {
+                self.set_state_to(self.idle_state);
                 state_has_changed = true;
             }
         }
         state_has_changed
     }
 
+    /// Marca a la cámara como rota, con el estado `Fault` u `Offline` recibido.
+    /// Devuelve si cambió su estado.
+    pub fn mark_as_broken(&mut self, broken_state: CameraState) -> bool {
+        let state_has_changed = self.state != broken_state;
+        self.set_state_to(broken_state);
+        state_has_changed
+    }
+
+    /// Si estaba rota, la vuelve a poner en funcionamiento: activa si sigue prestando atención a algún
+    /// incidente, o en su estado de reposo si no. Devuelve si cambió su estado.
+    pub fn mark_as_operational(&mut self) -> bool {
+        if self.state.is_operational() {
+            return false;
+        }
+        if self.incs_being_managed.is_empty() {
+            self.set_state_to(self.idle_state);
+        } else {
+            self.set_state_to(CameraState::Active);
+        }
+        true
+    }
+
+    /// Indica el estado de reposo, `SavingMode` o `HighSensitivity`, al que vuelve cuando no sigue incidentes. Si
+    /// está en reposo, cambia a él. Devuelve si cambió su estado.
+    pub fn set_idle_state(&mut self, idle_state: CameraState) -> bool {
+        self.idle_state = idle_state;
+        if self.state.is_idle() && self.state != idle_state {
+            self.set_state_to(idle_state);
+            return true;
+        }
+        false
+    }
+
+    /// Devuelve si la cámara funciona, es decir si no está en falla ni desconectada.
+    pub fn is_operational(&self) -> bool {
+        self.state.is_operational()
+    }
+
+    /// Devuelve los incidentes a los que está prestando atención.
+    pub fn get_incs_being_managed(&self) -> &[IncidentInfo] {
+        &self.incs_being_managed
+    }
+
+    /// Restaura el estado y los incidentes a los que prestaba atención, guardados antes de reiniciar.
+    pub fn restore(&mut self, state: CameraState, incs_being_managed: Vec<IncidentInfo>) {
+        self.state = state;
+        self.incs_being_managed = incs_being_managed;
+    }
+
     /// Función getter utilizada con propósitos de debugging.
     pub fn get_id_and_incs_for_debug_display(&self) -> (u8, Vec<IncidentInfo>) {
         (self.id, self.incs_being_managed.to_vec())
@@ -154,25 +281,35 @@ impl Camera {
         self.deleted = true;
     }
 
-    /// Devuelve el rango ajustado de la cámara.
+    /// Devuelve el rango de alcance de la cámara, en metros.
+    pub fn get_range(&self) -> f64 {
+        self.range
+    }
+
+    /// Modifica el rango de alcance de la cámara, en metros. Devuelve error, sin modificarlo, si es cero o negativo.
+    pub fn set_range(&mut self, range: f64) -> Result<(), Error> {
+        self.range = check_range(range)?;
+        Ok(())
+    }
+
+    /// Devuelve el rango de la cámara en la unidad en que mide las distancias su modelo de distancia (grados).
     pub fn get_range_area(&self) -> f64 {
-        0.00135 + 0.0012 * self.range as f64
-        //self.range
+        self.distance_model.meters_to_degrees(self.range)
     }
 
     /// Devuelve la latitud de la cámara.
     pub fn get_latitude(&self) -> f64 {
-        self.latitude
+        self.position.get_latitude()
     }
 
     /// Devuelve la ubicacion de la cámara.
-    pub fn get_position(&self) -> (f64, f64) {
-        (self.latitude, self.longitude)
+    pub fn get_position(&self) -> GeoPosition {
+        self.position
     }
 
     /// Devuelve la longitud de la cámara.
     pub fn get_longitude(&self) -> f64 {
-        self.longitude
+        self.position.get_longitude()
     }
 
     /// Devuelve el id de la cámara.
@@ -185,69 +322,72 @@ impl Camera {
         self.state
     }
 
-    // Analiza si se encuentra la cámara recibida por parámetro dentro del border_range, en caso afirmativo:
-    // tanto self como la cámara recibida por parámetro agregan sus ids mutuamente a la lista de lindantes de la otra.
-    pub fn mutually_add_if_bordering(&mut self, candidate_bordering: &mut Camera) {
-        let const_border_range: f64 = 3.0; // Constante que debe ir en arch de configuración.
-                                           // Se fija si están en rango de lindantes.
-        let in_range = self.is_within_range_from_self(
-            candidate_bordering.get_latitude(),
-            candidate_bordering.get_longitude(),
-            const_border_range,
-        );
-
-        // Si sí, se agregan mutuamente como lindantes
-        if in_range {
-            self.border_cameras.push(candidate_bordering.get_id());
-            candidate_bordering.border_cameras.push(self.id);
-        }
+    /// Devuelve cómo calcula las distancias.
+    pub fn get_distance_model(&self) -> DistanceModel {
+        self.distance_model
     }
 
-    pub fn remove_from_list_if_bordering(&mut self, camera_to_delete: &mut Camera) {
-        // No necesito recalcular para borrarla; "si es lindante" en este contexto es "si está en la lista".        
-        // Busco la pos del id de la camera_to_delete en mi lista de lindantes, y la elimino
-        if let Some(pos) = self
-            .border_cameras
-            .iter()
-            .position(|id| *id == camera_to_delete.get_id())
-        {
-            self.border_cameras.remove(pos);
-        }
+    /// Devuelve la distancia, en grados, entre los bordes de su área de cobertura y la de `other`; si se
+    /// superponen, es negativa.
+    pub fn coverage_gap_to(&self, other: &Camera) -> f64 {
+        let centers_distance = self
+            .distance_model
+            .distance(self.get_position(), other.get_position());
+        centers_distance - self.get_range_area() - other.get_range_area()
     }
 
-    /// Calcula si se encuentra las coordenadas pasadas se encuentran dentro del rango pasado
-    fn is_within_range_from_self(&self, latitude: f64, longitude: f64, range: f64) -> bool {
-        let lat_dist = self.latitude - latitude;
-        let long_dist = self.longitude - longitude;
-        let rad = f64::sqrt(lat_dist.powi(2) + long_dist.powi(2));
-
-        // Se modifica el range de las cámaras, ahora que son latitudes de verdad y no "3 4".
-        let adjusted_range = 0.00135 + 0.0012 * range;
+    /// Calcula si las coordenadas pasadas se encuentran dentro de su rango.
+    fn is_within_range_from_self(&self, position: GeoPosition) -> bool {
+        let rad = self.distance_model.distance(self.position, position);
 
-        /*println!(
-            "Dio que la cuenta vale: {}, y adj_range vale: {}. Era rango: {}",
-            rad, adjusted_range, range
-        ); // debug []*/
-        rad <= (adjusted_range)
+        rad <= self.get_range_area()
     }
 }
 
 #[cfg(test)]
 
 mod test {
-    use super::Camera;
+    use super::{blocks_to_meters, parse_range, Camera};
+    use crate::apps::geo_position::GeoPosition;
+    use crate::apps::sist_camaras::neighbors::Neighbors;
+    use crate::apps::sist_dron::calculations::DistanceModel;
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
 
     #[test]
     fn test_1_camera_to_y_from_bytes() {
-        let camera = Camera::new(12, 3.0, 4.0, 5);
+        let camera = Camera::new(12, position(3.0, 4.0), 5);
 
         let bytes = camera.to_bytes();
 
-        let camera_reconstruida = Camera::from_bytes(&bytes);
+        let camera_reconstruida = Camera::from_bytes(&bytes).unwrap();
 
         assert_eq!(camera_reconstruida, camera);
     }
 
+    #[test]
+    fn test_1b_el_rango_se_configura_en_metros_y_se_valida() {
+        assert_eq!(parse_range("250m").unwrap(), 250.0);
+        assert_eq!(parse_range(" 1 ").unwrap(), blocks_to_meters(1));
+        assert!(parse_range("0m").is_err());
+        assert!(parse_range("-5m").is_err());
+        assert!(parse_range("cerca").is_err());
+        assert!(Camera::new_in_meters(1, position(-34.6040, -58.3873), 0.0).is_err());
+
+        // Una cámara de 100 m registra lo que está a 90 m, pero no a 110 m.
+        let mut camera = Camera::new_in_meters(1, position(-34.6040, -58.3873), 100.0).unwrap();
+        let north = |meters: f64| position(-34.6040 + meters / 111_195.0, -58.3873);
+        assert!(camera.will_register(north(90.0)));
+        assert!(!camera.will_register(north(110.0)));
+        assert!(camera.set_range(-1.0).is_err());
+        assert_eq!(camera.get_range(), 100.0);
+        camera.set_range(150.0).unwrap();
+        assert!(camera.will_register(north(110.0)));
+        assert_eq!(Camera::from_bytes(&camera.to_bytes()).unwrap(), camera);
+    }
+
     #[test]
     fn test_2_camaras_cercanas_son_lindantes() {
         //     Aux: obelisco: lon -58.3861838  lat: -34.6037344
@@ -256,44 +396,36 @@ mod test {
         let lon = -58.3861838;
         let range = 10;
         let incr = 0.0000005;
-        let mut cam_1 = Camera::new(1, lat, lon, range);
+        let cam_1 = Camera::new(1, position(lat, lon), range);
 
         // Otra cámara, con misma longitud, y latitud apenas incrementada
-        let mut cam_2 = Camera::new(2, lat + incr, lon, range);
-
-        cam_1.mutually_add_if_bordering(&mut cam_2);
-        // Aux con estos datos da: Dio que la cuenta vale: 0.0000004999999987376214
+        let cam_2 = Camera::new(2, position(lat + incr, lon), range);
 
-        // Se han agregado mutuamente, xq sí qentraron dentro del border_range para ser consideradas lindantes
-        assert!(cam_1.border_cameras.contains(&cam_2.get_id()));
-        assert!(cam_2.border_cameras.contains(&cam_1.get_id()));
+        // Son lindantes, xq sus áreas de cobertura se superponen
+        assert!(Neighbors::default().are_bordering(&cam_1, &cam_2));
+        assert!(Neighbors::default().are_bordering(&cam_2, &cam_1));
 
         //
         // Ídem con datos "reales"
-        let mut cam_5: Camera = Camera::new(5, -34.6040, -58.3873, 1); // Aux: cámara 5.
-        let mut cam_6: Camera = Camera::new(6, -34.6039, -58.3837, 1); // Aux: cámara 6.
+        let cam_5: Camera = Camera::new(5, position(-34.6040, -58.3873), 1); // Aux: cámara 5.
+        let cam_6: Camera = Camera::new(6, position(-34.6039, -58.3837), 1); // Aux: cámara 6.
 
-        cam_5.mutually_add_if_bordering(&mut cam_6);
-
-        // Se han agregado mutuamente, xq sí qentraron dentro del border_range para ser consideradas lindantes
-        assert!(cam_5.border_cameras.contains(&cam_6.get_id()));
-        assert!(cam_6.border_cameras.contains(&cam_5.get_id()));
+        // Son lindantes, xq sus áreas de cobertura se superponen
+        assert!(Neighbors::default().are_bordering(&cam_5, &cam_6));
     }
 
     #[test]
     fn test_3_camaras_lejanas_no_son_lindantes() {
         // A 5 cuadras de la otra cámara, es decir, afuera de las 4 cuadras de lindantes
         //-58.3950 -34.6044
-        let mut cam_a: Camera = Camera::new(10, -34.6044, -58.3950, 1); // 3 cuadras a la izq de cam 5.
+        let cam_a: Camera = Camera::new(10, position(-34.6044, -58.3950), 1); // 3 cuadras a la izq de cam 5.
 
         // Otra cámara, con misma longitud, y latitud más lejana
-        let mut cam_b: Camera = Camera::new(5, -34.6040, -58.3873, 1); // Aux: cámara 5.
-
-        cam_b.mutually_add_if_bordering(&mut cam_a);
+        let cam_b: Camera = Camera::new(5, position(-34.6040, -58.3873), 1); // Aux: cámara 5.
 
-        // No se han agregado mutuamente, xq no entraron dentro del border_range para ser consideradas lindantes
-        assert!(!cam_a.border_cameras.contains(&cam_b.get_id()));
-        assert!(!cam_b.border_cameras.contains(&cam_a.get_id()));
+        // No son lindantes, xq sus áreas de cobertura no se tocan
+        assert!(!Neighbors::default().are_bordering(&cam_a, &cam_b));
+        assert!(!Neighbors::default().are_bordering(&cam_b, &cam_a));
     }
 
     // #[test]
@@ -302,7 +434,7 @@ mod test {
     //     let camera = Camera::new(5, -34.6040, -58.3873, 3); // Aux: cámara 5.
 
     //     let (lat, lon) = (-34.6042, -58.3897);
-    //     let is_in_range = camera.is_within_range_from_self(lat, lon, camera.range as f64);
+    //     let is_in_range = camera.is_within_range_from_self(lat, lon);
 
     //     assert!(is_in_range);
 
@@ -311,10 +443,10 @@ mod test {
     #[test]
     fn test_4a_una_pos_dentro_de_range_cuadras_esta_dentro_del_rango() {
         // Rango de 1 cuadra.
-        let camera = Camera::new(5, -34.6040, -58.3873, 1); // Aux: cámara 5.
+        let camera = Camera::new(5, position(-34.6040, -58.3873), 1); // Aux: cámara 5.
 
-        let (lat, lon) = (-34.6042, -58.3897); // una cuadra a la izq de la cam 5
-        let is_in_range = camera.is_within_range_from_self(lat, lon, camera.range as f64);
+        let inc_position = position(-34.6042, -58.3897); // una cuadra a la izq de la cam 5
+        let is_in_range = camera.is_within_range_from_self(inc_position);
 
         assert!(is_in_range);
         //assert!(false);
@@ -322,11 +454,11 @@ mod test {
 
     #[test]
     fn test_4b_una_pos_mas_lejana_esta_fuera_del_rango() {
-        // Rango de 1 cuadra.
-        let camera = Camera::new(5, -34.6040, -58.3873, 1); // Aux: cámara 5.
+        // Rango de 1 cuadra, calibrado en el plano lat/lon.
+        let camera = Camera::new(5, position(-34.6040, -58.3873), 1).with_distance_model(DistanceModel::Flat); // Aux: cámara 5.
 
-        let (lat, lon) = (-34.6042, -58.3902);
-        let is_in_range = camera.is_within_range_from_self(lat, lon, camera.range as f64);
+        let inc_position = position(-34.6042, -58.3902);
+        let is_in_range = camera.is_within_range_from_self(inc_position);
 
         assert!(!is_in_range);
     }
@@ -0,0 +1,108 @@
+use std::io::{Error, ErrorKind};
+
+use super::camera::parse_range;
+use crate::apps::geo_position::GeoPosition;
+
+/// Comando del abm de cámaras que se le envía a Sistema Cámaras por el topic `cam-admin`, como texto.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CameraAdminCommand {
+    /// `add <id> <latitud> <longitud> <rango>` agrega una cámara. El rango va en metros (ie `250m`) o en cuadras
+    /// (ie `2`), y se guarda en metros.
+    Add { id: u8, position: GeoPosition, range: f64 },
+    /// `range <id> <rango>` modifica el rango de una cámara existente.
+    ModifyRange { id: u8, range: f64 },
+    /// `delete <id>` elimina una cámara.
+    Delete(u8),
+    /// `gaps` busca las zonas de la región de vigilancia que no cubre ninguna cámara, y las publica por `cam-gaps`.
+    AnalyzeCoverage,
+}
+
+impl CameraAdminCommand {
+    pub fn from_bytes(payload: Vec<u8>) -> Result<Self, Error> {
+        let command = String::from_utf8(payload).map_err(|_| invalid_command())?;
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["add", id, latitude, longitude, range] => Ok(CameraAdminCommand::Add {
+                id: parse(id)?,
+                position: GeoPosition::new(parse(latitude)?, parse(longitude)?).map_err(|_| invalid_command())?,
+                range: parse_range(range)?,
+            }),
+            ["range", id, range] => Ok(CameraAdminCommand::ModifyRange {
+                id: parse(id)?,
+                range: parse_range(range)?,
+            }),
+            ["delete", id] => Ok(CameraAdminCommand::Delete(parse(id)?)),
+            ["gaps"] => Ok(CameraAdminCommand::AnalyzeCoverage),
+            _ => Err(invalid_command()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            CameraAdminCommand::Add { id, position, range } => format!(
+                "add {} {} {} {}m",
+                id,
+                position.get_latitude(),
+                position.get_longitude(),
+                range
+            )
+            .into_bytes(),
+            CameraAdminCommand::ModifyRange { id, range } => {
+                format!("range {} {}m", id, range).into_bytes()
+            }
+            CameraAdminCommand::Delete(id) => format!("delete {}", id).into_bytes(),
+            CameraAdminCommand::AnalyzeCoverage => "gaps".to_string().into_bytes(),
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, Error> {
+    word.parse().map_err(|_| invalid_command())
+}
+
+fn invalid_command() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Comando de administración de cámaras inválido.",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::CameraAdminCommand;
+    use crate::apps::geo_position::GeoPosition;
+    use crate::apps::sist_camaras::camera::blocks_to_meters;
+
+    #[test]
+    fn test_1_se_parsean_los_comandos_del_abm_de_camaras() {
+        let add = CameraAdminCommand::from_bytes(b"add 7 -34.6 -58.4 5\n".to_vec()).unwrap();
+        assert_eq!(
+            add,
+            CameraAdminCommand::Add {
+                id: 7,
+                position: GeoPosition::new(-34.6, -58.4).unwrap(),
+                range: blocks_to_meters(5)
+            }
+        );
+        assert_eq!(CameraAdminCommand::from_bytes(add.to_bytes()).unwrap(), add);
+
+        let modify = CameraAdminCommand::from_bytes(b"range 7 250m".to_vec()).unwrap();
+        assert_eq!(modify, CameraAdminCommand::ModifyRange { id: 7, range: 250.0 });
+        assert_eq!(CameraAdminCommand::from_bytes(modify.to_bytes()).unwrap(), modify);
+        assert_eq!(
+            CameraAdminCommand::from_bytes(b"delete 7".to_vec()).unwrap(),
+            CameraAdminCommand::Delete(7)
+        );
+
+        assert!(CameraAdminCommand::from_bytes(b"add 7 -34.6 -58.4".to_vec()).is_err());
+        assert!(CameraAdminCommand::from_bytes(b"add 7 -134.6 -58.4 5".to_vec()).is_err());
+        assert!(CameraAdminCommand::from_bytes(b"range 7 300".to_vec()).is_err());
+        assert!(CameraAdminCommand::from_bytes(b"range 7 0m".to_vec()).is_err());
+        assert!(CameraAdminCommand::from_bytes(b"range 7 -5m".to_vec()).is_err());
+        assert!(CameraAdminCommand::from_bytes(b"delete siete".to_vec()).is_err());
+        assert_eq!(
+            CameraAdminCommand::from_bytes(b"gaps".to_vec()).unwrap(),
+            CameraAdminCommand::AnalyzeCoverage
+        );
+    }
+}
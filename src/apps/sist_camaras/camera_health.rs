@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    time::{Duration, Instant},
+};
+
+use crate::apps::properties::Properties;
+
+use super::{camera::Camera, camera_state::CameraState};
+
+/// Tiempo sin recibir el latido de una cámara a partir del cual se la considera desconectada, si el archivo no
+/// define `camera-offline-secs`.
+pub const DEFAULT_CAMERA_OFFLINE_SECS: u64 = 30;
+/// Cada cuánto se buscan las cámaras que dejaron de enviar su latido.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Latido que cada cámara publica periódicamente por el topic `cam-health`, con el resultado de su autodiagnóstico.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CameraHeartbeat {
+    id: u8,
+    self_test_ok: bool,
+}
+
+impl CameraHeartbeat {
+    pub fn new(id: u8, self_test_ok: bool) -> Self {
+        Self { id, self_test_ok }
+    }
+
+    pub fn get_id(&self) -> u8 {
+        self.id
+    }
+
+    /// Devuelve si el autodiagnóstico de la cámara fue exitoso.
+    pub fn is_self_test_ok(&self) -> bool {
+        self.self_test_ok
+    }
+
+    /// Convierte el latido a bytes: id, y 1 si el autodiagnóstico fue exitoso o 0 si falló.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![self.id, self.self_test_ok as u8]
+    }
+
+    /// Obtiene el latido a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        match bytes.as_slice() {
+            [id, self_test @ (0 | 1)] => Ok(Self::new(*id, *self_test == 1)),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Latido de cámara inválido.")),
+        }
+    }
+}
+
+/// Vigila los latidos de las cámaras: pone en `Fault` a las que informan que su autodiagnóstico falló, en `Offline`
+/// a las que dejaron de enviarlo por más de `offline_after`, y vuelve a poner en funcionamiento a las que se
+/// recuperan. Solamente vigila a las cámaras de las que ya recibió algún latido.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraHealthMonitor {
+    offline_after: Duration,
+    last_heartbeats: HashMap<u8, Instant>, // cam_id -> cuándo se recibió su último latido
+}
+
+impl CameraHealthMonitor {
+    pub fn new(offline_after: Duration) -> Self {
+        Self {
+            offline_after,
+            last_heartbeats: HashMap::new(),
+        }
+    }
+
+    /// Carga el tiempo de la property `camera-offline-secs` de `properties_file`, en segundos. Si el archivo no
+    /// existe o no la define, usa `DEFAULT_CAMERA_OFFLINE_SECS`.
+    pub fn from_file(properties_file: &str) -> Result<Self, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(Self::default());
+        };
+        match properties.get("camera-offline-secs") {
+            Some(value) => match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(Self::new(Duration::from_secs(secs))),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Valor inválido para camera-offline-secs: {}.", value),
+                )),
+            },
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn get_offline_after(&self) -> Duration {
+        self.offline_after
+    }
+
+    /// Registra el latido recibido en el instante `now`, y actualiza el estado de la cámara según su
+    /// autodiagnóstico. Devuelve si la cámara cambió de estado.
+    pub fn register(
+        &mut self,
+        cams: &mut HashMap<u8, Camera>,
+        heartbeat: &CameraHeartbeat,
+        now: Instant,
+    ) -> bool {
+        let id = heartbeat.get_id();
+        let Some(camera) = cams.get_mut(&id).filter(|camera| camera.is_not_deleted()) else {
+            self.last_heartbeats.remove(&id);
+            return false;
+        };
+        self.last_heartbeats.insert(id, now);
+        if heartbeat.is_self_test_ok() {
+            camera.mark_as_operational()
+        } else {
+            camera.mark_as_broken(CameraState::Fault)
+        }
+    }
+
+    /// Pone en `Offline` a las cámaras cuyo último latido es más viejo que `offline_after` en el instante `now`.
+    /// Devuelve los ids de las cámaras que cambiaron de estado.
+    pub fn check_timeouts(&mut self, cams: &mut HashMap<u8, Camera>, now: Instant) -> Vec<u8> {
+        // Deja de vigilar a las cámaras eliminadas
+        self.last_heartbeats
+            .retain(|id, _| cams.get(id).is_some_and(Camera::is_not_deleted));
+
+        let mut changed = vec![];
+        for (id, last) in &self.last_heartbeats {
+            if now.saturating_duration_since(*last) >= self.offline_after {
+                if let Some(camera) = cams.get_mut(id) {
+                    if camera.mark_as_broken(CameraState::Offline) {
+                        changed.push(*id);
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl Default for CameraHealthMonitor {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_CAMERA_OFFLINE_SECS))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use super::{CameraHealthMonitor, CameraHeartbeat};
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource},
+        sist_camaras::{camera::Camera, camera_state::CameraState},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_las_camaras_pasan_a_falla_o_desconectadas_y_se_recuperan() {
+        let mut cams = HashMap::new();
+        cams.insert(1, Camera::new(1, position(-34.6040, -58.3873), 1));
+        cams.insert(2, Camera::new(2, position(-34.6039, -58.3837), 1));
+        let mut monitor = CameraHealthMonitor::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(
+            CameraHeartbeat::from_bytes(CameraHeartbeat::new(1, false).to_bytes()).unwrap(),
+            CameraHeartbeat::new(1, false)
+        );
+        assert!(CameraHeartbeat::from_bytes(vec![1, 7]).is_err());
+
+        // La 1 falla su autodiagnóstico; la 2 nunca envía latidos, por lo que no se la vigila.
+        assert!(monitor.register(&mut cams, &CameraHeartbeat::new(1, false), start));
+        assert_eq!(cams[&1].get_state(), CameraState::Fault);
+        assert_eq!(monitor.check_timeouts(&mut cams, start + Duration::from_secs(60)), vec![1]);
+        assert_eq!(cams[&1].get_state(), CameraState::Offline);
+        assert_eq!(cams[&2].get_state(), CameraState::SavingMode);
+
+        // Mientras está rota no se activa; al recuperarse, vuelve activa si sigue con incidentes.
+        let inc = IncidentInfo::new(5, IncidentSource::Manual);
+        assert!(!cams.get_mut(&1).unwrap().append_to_incs_being_managed(inc));
+        assert_eq!(cams[&1].get_state(), CameraState::Offline);
+        let later = start + Duration::from_secs(61);
+        assert!(monitor.register(&mut cams, &CameraHeartbeat::new(1, true), later));
+        assert_eq!(cams[&1].get_state(), CameraState::Active);
+        assert!(monitor.check_timeouts(&mut cams, later).is_empty());
+    }
+}
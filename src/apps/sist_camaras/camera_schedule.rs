@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use chrono::NaiveTime;
+
+use crate::apps::properties::Properties;
+
+use super::{camera::Camera, camera_state::CameraState};
+
+/// Cada cuánto se revisa si corresponde cambiar el estado de reposo de las cámaras.
+pub const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Horario de los estados de reposo de las cámaras que no siguen incidentes: a partir de cada hora indicada, las
+/// cámaras en reposo pasan al estado indicado (ie `SavingMode` de día y `HighSensitivity` de noche), hasta la
+/// siguiente. Se configura con la property `camera-schedule`, ie `07:00 saving, 20:00 sensitive`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraSchedule {
+    transitions: Vec<(NaiveTime, CameraState)>, // ordenadas por hora
+}
+
+impl CameraSchedule {
+    /// Crea el horario a partir de sus cambios de estado. Devuelve error si no tiene ninguno, o si alguno no es
+    /// un estado de reposo.
+    pub fn new(mut transitions: Vec<(NaiveTime, CameraState)>) -> Result<Self, Error> {
+        if transitions.is_empty() || transitions.iter().any(|(_, state)| !state.is_idle()) {
+            return Err(invalid_schedule());
+        }
+        transitions.sort_by_key(|(time, _)| *time);
+        Ok(Self { transitions })
+    }
+
+    /// Carga el horario de la property `camera-schedule` de `properties_file`. Si el archivo no existe o no la
+    /// define, las cámaras en reposo quedan siempre en `SavingMode`.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        properties
+            .get("camera-schedule")
+            .map(|value| Self::from_property(value))
+            .transpose()
+    }
+
+    /// Obtiene el horario a partir del valor de la property: cambios `<hh:mm> <saving|sensitive>` separados por coma.
+    pub fn from_property(value: &str) -> Result<Self, Error> {
+        let mut transitions = vec![];
+        for transition in value.split(',') {
+            let words: Vec<&str> = transition.split_whitespace().collect();
+            let [time, profile] = words.as_slice() else {
+                return Err(invalid_schedule());
+            };
+            let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| invalid_schedule())?;
+            let state = match *profile {
+                "saving" => CameraState::SavingMode,
+                "sensitive" => CameraState::HighSensitivity,
+                _ => return Err(invalid_schedule()),
+            };
+            transitions.push((time, state));
+        }
+        Self::new(transitions)
+    }
+
+    /// Devuelve el estado de reposo que corresponde a la hora `time`: el del último cambio anterior, o si todavía
+    /// no hubo ninguno en el día, el del último cambio del día anterior.
+    pub fn idle_state_at(&self, time: NaiveTime) -> CameraState {
+        self.transitions
+            .iter()
+            .rev()
+            .find(|(from, _)| *from <= time)
+            .or(self.transitions.last())
+            .map_or(CameraState::SavingMode, |(_, state)| *state)
+    }
+
+    /// Aplica a las cámaras `cams` el estado de reposo que corresponde a la hora `time`. Devuelve los ids de las
+    /// cámaras que cambiaron de estado.
+    pub fn apply(&self, cams: &mut HashMap<u8, Camera>, time: NaiveTime) -> Vec<u8> {
+        let idle_state = self.idle_state_at(time);
+        cams.values_mut()
+            .filter(|camera| camera.is_not_deleted())
+            .filter_map(|camera| camera.set_idle_state(idle_state).then_some(camera.get_id()))
+            .collect()
+    }
+}
+
+fn invalid_schedule() -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        "Valor inválido para camera-schedule, debe ser como `07:00 saving, 20:00 sensitive`.",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use chrono::NaiveTime;
+
+    use super::CameraSchedule;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource},
+        sist_camaras::{camera::Camera, camera_state::CameraState},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_las_camaras_en_reposo_cambian_de_estado_segun_el_horario() {
+        let schedule = CameraSchedule::from_property("20:00 sensitive, 07:00 saving").unwrap();
+        assert!(CameraSchedule::from_property("20:00 activa").is_err());
+        assert!(CameraSchedule::from_property("25:00 saving").is_err());
+        let at = |hh_mm: &str| NaiveTime::parse_from_str(hh_mm, "%H:%M").unwrap();
+        assert_eq!(schedule.idle_state_at(at("03:00")), CameraState::HighSensitivity);
+        assert_eq!(schedule.idle_state_at(at("12:00")), CameraState::SavingMode);
+
+        let mut cams = HashMap::new();
+        cams.insert(1, Camera::new(1, position(-34.6040, -58.3873), 1));
+        cams.insert(2, Camera::new(2, position(-34.6039, -58.3837), 1));
+        let inc = IncidentInfo::new(1, IncidentSource::Manual);
+        cams.get_mut(&2).unwrap().append_to_incs_being_managed(inc);
+
+        // De noche, la que sigue un incidente queda activa, y al resolverlo pasa a alta sensibilidad.
+        assert_eq!(schedule.apply(&mut cams, at("21:00")), vec![1]);
+        assert_eq!(cams[&1].get_state(), CameraState::HighSensitivity);
+        assert!(schedule.apply(&mut cams, at("22:00")).is_empty());
+        assert!(cams.get_mut(&2).unwrap().remove_from_incs_being_managed(inc));
+        assert_eq!(cams[&2].get_state(), CameraState::HighSensitivity);
+
+        let mut changed = schedule.apply(&mut cams, at("07:30"));
+        changed.sort();
+        assert_eq!(changed, vec![1, 2]);
+        assert_eq!(cams[&1].get_state(), CameraState::SavingMode);
+    }
+}
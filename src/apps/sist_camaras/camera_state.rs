@@ -1,7 +1,17 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Estado de una cámara. `Active` si está siguiendo incidentes; si no, está en reposo, en `SavingMode` o en
+/// `HighSensitivity` según el horario (ie más sensible de noche). `Fault` (su autodiagnóstico falló) y `Offline`
+/// (dejó de enviar su latido) indican que está rota, y mientras tanto no sigue incidentes.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum CameraState {
     Active,
+    #[default]
     SavingMode,
+    Fault,
+    Offline,
+    HighSensitivity,
 }
 
 impl CameraState {
@@ -9,14 +19,33 @@ impl CameraState {
         match self {
             CameraState::Active => 1_u8.to_be_bytes(),
             CameraState::SavingMode => 2_u8.to_be_bytes(),
+            CameraState::Fault => 3_u8.to_be_bytes(),
+            CameraState::Offline => 4_u8.to_be_bytes(),
+            CameraState::HighSensitivity => 5_u8.to_be_bytes(),
         }
     }
 
-    pub fn from_byte(bytes: [u8; 1]) -> Self {
+    pub fn from_byte(bytes: [u8; 1]) -> Result<Self, Error> {
         match u8::from_be_bytes(bytes) {
-            1 => CameraState::Active,
-            2 => CameraState::SavingMode,
-            _ => panic!("Estado de cámara no válido"),
+            1 => Ok(CameraState::Active),
+            2 => Ok(CameraState::SavingMode),
+            3 => Ok(CameraState::Fault),
+            4 => Ok(CameraState::Offline),
+            5 => Ok(CameraState::HighSensitivity),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Estado de cámara no válido")),
         }
     }
+
+    /// Devuelve si la cámara funciona, es decir si puede seguir incidentes.
+    pub fn is_operational(&self) -> bool {
+        matches!(
+            self,
+            CameraState::Active | CameraState::SavingMode | CameraState::HighSensitivity
+        )
+    }
+
+    /// Devuelve si la cámara está en reposo, es decir si funciona pero no sigue ningún incidente.
+    pub fn is_idle(&self) -> bool {
+        matches!(self, CameraState::SavingMode | CameraState::HighSensitivity)
+    }
 }
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::apps::{
+    incident_data::incident_info::IncidentInfo,
+    payload_version::{split_version_header, with_version_header},
+    properties::Properties,
+};
+
+use super::{camera::Camera, camera_state::CameraState, types::hashmap_incs_type::HashmapIncsType};
+
+/// Versión del formato del archivo de estado.
+const STATE_FILE_VERSION: u8 = 1;
+
+/// Guarda en disco el estado de cada cámara y los incidentes a los que presta atención, para que al reiniciar
+/// Sistema Cámaras las cámaras sigan activas (o rotas) como estaban, y vuelvan a ahorro de energía cuando se
+/// resuelvan los incidentes que ya estaban siguiendo.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraStateStore {
+    path: PathBuf,
+}
+
+impl CameraStateStore {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+
+    /// Carga la ruta del archivo de estado de la property `state-file` de `properties_file`. Si el archivo de
+    /// properties no existe o no la define, el estado no se persiste.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        match properties.get("state-file").map(|path| path.trim()) {
+            Some("") => Err(Error::new(ErrorKind::InvalidInput, "Valor inválido para state-file.")),
+            Some(path) => Ok(Some(Self::new(path))),
+            None => Ok(None),
+        }
+    }
+
+    /// Guarda el estado de las cámaras `cams`. Se escribe primero a un archivo temporal que luego se renombra,
+    /// para no perder el estado anterior si se corta a mitad.
+    pub fn save(&self, cams: &HashMap<u8, Camera>) -> Result<(), Error> {
+        let mut body = vec![];
+        let saved: Vec<&Camera> = cams.values().filter(|cam| cam.is_not_deleted()).collect();
+        body.push(saved.len() as u8);
+        for camera in saved {
+            let incs = camera.get_incs_being_managed();
+            body.push(camera.get_id());
+            body.extend_from_slice(&camera.get_state().to_byte());
+            body.push(incs.len() as u8);
+            for inc_info in incs {
+                body.extend(inc_info.to_bytes());
+            }
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(&with_version_header(STATE_FILE_VERSION, body))?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Restaura en `cams` el estado guardado, ignorando las cámaras que ya no existen, y devuelve qué cámaras
+    /// siguen a cada incidente. Si todavía no se guardó ningún estado, no modifica las cámaras.
+    pub fn restore(&self, cams: &mut HashMap<u8, Camera>) -> Result<HashmapIncsType, Error> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let (_version, mut reader) = split_version_header(&bytes);
+        let mut incs_being_managed: HashmapIncsType = HashMap::new();
+        for _ in 0..reader.read_u8()? {
+            let id = reader.read_u8()?;
+            let state = CameraState::from_byte([reader.read_u8()?])?;
+            let mut incs = vec![];
+            for _ in 0..reader.read_u8()? {
+                let inc_bytes = reader.read_bytes(2)?.to_vec();
+                let Some(inc_info) = IncidentInfo::from_bytes(inc_bytes)? else {
+                    return Err(Error::new(ErrorKind::InvalidData, "Incidente inválido en el archivo de estado."));
+                };
+                incs.push(inc_info);
+            }
+
+            if let Some(camera) = cams.get_mut(&id).filter(|camera| camera.is_not_deleted()) {
+                for inc_info in &incs {
+                    incs_being_managed.entry(*inc_info).or_default().push(id);
+                }
+                camera.restore(state, incs);
+            }
+        }
+        Ok(incs_being_managed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::CameraStateStore;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource},
+        sist_camaras::{camera::Camera, camera_state::CameraState},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn cameras() -> HashMap<u8, Camera> {
+        (1..=3).map(|id| (id, Camera::new(id, position(-34.6040, -58.3873), 1))).collect()
+    }
+
+    #[test]
+    fn test_1_el_estado_de_las_camaras_y_sus_incidentes_se_restaura_al_reiniciar() {
+        let path = std::env::temp_dir().join(format!("rustx_camera_state_{}.bin", std::process::id()));
+        let store = CameraStateStore::new(path.to_str().unwrap());
+        let inc = IncidentInfo::new(7, IncidentSource::Manual);
+
+        // Sin estado guardado, las cámaras quedan como estaban.
+        let mut restarted = cameras();
+        assert!(store.restore(&mut restarted).unwrap().is_empty());
+        assert_eq!(restarted, cameras());
+
+        let mut cams = cameras();
+        cams.get_mut(&1).unwrap().append_to_incs_being_managed(inc);
+        cams.get_mut(&2).unwrap().append_to_incs_being_managed(inc);
+        cams.get_mut(&2).unwrap().mark_as_broken(CameraState::Fault);
+        store.save(&cams).unwrap();
+
+        // Al reiniciar, la cámara 3 ya no existe.
+        let mut restarted = cameras();
+        restarted.remove(&3);
+        let incs_being_managed = store.restore(&mut restarted).unwrap();
+        let mut following = incs_being_managed[&inc].clone();
+        following.sort();
+        assert_eq!(following, vec![1, 2]);
+        assert_eq!(restarted[&1].get_state(), CameraState::Active);
+        assert_eq!(restarted[&2].get_state(), CameraState::Fault);
+
+        // Y al resolverse el incidente, vuelve a ahorro de energía.
+        assert!(restarted.get_mut(&1).unwrap().remove_from_incs_being_managed(inc));
+        assert_eq!(restarted[&1].get_state(), CameraState::SavingMode);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
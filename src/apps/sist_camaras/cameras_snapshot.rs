@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::apps::{
+    payload_codec::Codec,
+    payload_version::{split_version_header, with_version_header},
+};
+
+use super::camera::Camera;
+
+/// Versión del formato en bytes del snapshot de cámaras.
+pub const CAMERAS_SNAPSHOT_VERSION: u8 = 1;
+
+/// La cámara no se había publicado antes.
+pub const CAMERA_ADDED: u8 = 0x01;
+/// Cambió el estado de la cámara.
+pub const STATE_CHANGED: u8 = 0x02;
+/// Cambió el rango de la cámara.
+pub const RANGE_CHANGED: u8 = 0x04;
+/// Cambiaron las cámaras lindantes de la cámara.
+pub const NEIGHBORS_CHANGED: u8 = 0x08;
+/// La cámara se eliminó.
+pub const CAMERA_DELETED: u8 = 0x10;
+
+/// Una cámara del snapshot, junto con qué cambió respecto de la última vez que se publicó (ver `CAMERA_ADDED`, etc).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CameraChange {
+    changes: u8,
+    camera: Camera,
+}
+
+impl CameraChange {
+    pub fn new(changes: u8, camera: Camera) -> Self {
+        Self { changes, camera }
+    }
+
+    /// Devuelve qué cambió respecto de la última vez que se publicó la cámara, qué flags de `changes` están en 1.
+    pub fn get_changes(&self) -> u8 {
+        self.changes
+    }
+
+    /// Devuelve si cambió lo indicado por el flag `change` (ie `STATE_CHANGED`).
+    pub fn has_changed(&self, change: u8) -> bool {
+        self.changes & change != 0
+    }
+
+    pub fn get_camera(&self) -> &Camera {
+        &self.camera
+    }
+}
+
+/// Varias cámaras en un solo mensaje, que Sistema Cámaras publica por el topic `cam-snapshot` en vez de publicar
+/// cada cámara por separado por `cam`: al inicio (con todas las cámaras, `full`), y cuando muchas cámaras
+/// cambian a la vez (ie se activan por un incidente grande).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CamerasSnapshot {
+    full: bool,
+    cameras: Vec<CameraChange>,
+}
+
+impl CamerasSnapshot {
+    pub fn new(full: bool, cameras: Vec<CameraChange>) -> Self {
+        Self { full, cameras }
+    }
+
+    /// Devuelve si el snapshot tiene todas las cámaras, por lo que las que no están ya no existen.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    pub fn get_cameras(&self) -> &[CameraChange] {
+        &self.cameras
+    }
+
+    /// Pasa el snapshot a bytes, precedidos por el encabezado con su versión: 1 si es completo o 0 si no, la
+    /// cantidad de cámaras (2 bytes), y por cada una sus cambios, el largo de la cámara en bytes (2 bytes) y la cámara.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.full as u8];
+        bytes.extend_from_slice(&(self.cameras.len() as u16).to_be_bytes());
+        for change in &self.cameras {
+            let camera_bytes = change.camera.to_bytes();
+            bytes.push(change.changes);
+            bytes.extend_from_slice(&(camera_bytes.len() as u16).to_be_bytes());
+            bytes.extend(camera_bytes);
+        }
+        with_version_header(CAMERAS_SNAPSHOT_VERSION, bytes)
+    }
+
+    /// Separa el snapshot en partes que, codificadas con `codec`, ocupan a lo sumo `max_len` bytes cada una, para
+    /// que cada parte entre en un publish. Solamente la primera conserva `full`: quien las recibe quita sus cámaras
+    /// con la primera, y agrega las demás con las siguientes. Devuelve error si una cámara sola no entra.
+    pub fn split(&self, max_len: usize, codec: &impl Codec<CamerasSnapshot>) -> Result<Vec<CamerasSnapshot>, Error> {
+        let mut parts = vec![];
+        let mut current = CamerasSnapshot::new(self.full, vec![]);
+        for change in &self.cameras {
+            let mut candidate = current.clone();
+            candidate.cameras.push(change.clone());
+            let mut len = codec.encode(&candidate)?.len();
+            if len > max_len && !current.cameras.is_empty() {
+                // No entra en la parte actual: se la cierra y la cámara empieza la siguiente.
+                parts.push(std::mem::replace(&mut current, CamerasSnapshot::new(false, vec![])));
+                candidate = CamerasSnapshot::new(false, vec![change.clone()]);
+                len = codec.encode(&candidate)?.len();
+            }
+            if len > max_len {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("La cámara {} no entra en un publish ({} bytes).", change.camera.get_id(), len),
+                ));
+            }
+            current = candidate;
+        }
+        if !current.cameras.is_empty() {
+            parts.push(current);
+        }
+        Ok(parts)
+    }
+
+    /// Lee el snapshot a partir de bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (_version, mut reader) = split_version_header(bytes);
+        let full = reader.read_u8()? == 1;
+        let len = u16::from_be_bytes([reader.read_u8()?, reader.read_u8()?]);
+        let mut cameras = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let changes = reader.read_u8()?;
+            let camera_len = u16::from_be_bytes([reader.read_u8()?, reader.read_u8()?]);
+            let camera = Camera::from_bytes(reader.read_bytes(camera_len as usize)?)?;
+            cameras.push(CameraChange::new(changes, camera));
+        }
+        Ok(Self::new(full, cameras))
+    }
+}
+
+/// Devuelve qué cambió de `camera` respecto de cómo se publicó la última vez, `previous` (`None` si nunca se publicó).
+pub fn camera_changes(previous: Option<&Camera>, camera: &Camera) -> u8 {
+    let Some(previous) = previous else {
+        return if camera.is_not_deleted() { CAMERA_ADDED } else { CAMERA_DELETED };
+    };
+    let mut changes = 0;
+    if previous.get_state() != camera.get_state() {
+        changes |= STATE_CHANGED;
+    }
+    if previous.get_range() != camera.get_range() {
+        changes |= RANGE_CHANGED;
+    }
+    if previous.get_border_cameras() != camera.get_border_cameras() {
+        changes |= NEIGHBORS_CHANGED;
+    }
+    if previous.is_not_deleted() && !camera.is_not_deleted() {
+        changes |= CAMERA_DELETED;
+    }
+    if !previous.is_not_deleted() && camera.is_not_deleted() {
+        changes |= CAMERA_ADDED;
+    }
+    changes
+}
+
+/// Arma el snapshot con las cámaras `cameras` que cambiaron respecto de las últimas publicadas, `published`, y
+/// actualiza `published`. Si una cámara aparece más de una vez, se toma la última. Es completo si todavía no se
+/// había publicado ninguna cámara.
+pub fn build_snapshot(published: &mut HashMap<u8, Camera>, cameras: Vec<Camera>) -> CamerasSnapshot {
+    let full = published.is_empty();
+    let mut latest: Vec<Camera> = vec![];
+    for camera in cameras {
+        latest.retain(|other| other.get_id() != camera.get_id());
+        latest.push(camera);
+    }
+
+    let mut changes = vec![];
+    for camera in latest {
+        let camera_changes = camera_changes(published.get(&camera.get_id()), &camera);
+        if camera_changes != 0 || full {
+            changes.push(CameraChange::new(camera_changes, camera.clone()));
+        }
+        published.insert(camera.get_id(), camera);
+    }
+    CamerasSnapshot::new(full, changes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{build_snapshot, CamerasSnapshot, CAMERA_ADDED, RANGE_CHANGED, STATE_CHANGED};
+    use crate::apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        payload_codec::{decode_payload, Codec, PayloadCodec},
+        sist_camaras::{camera::Camera, camera_state::CameraState, manage_stored_cameras::create_cameras},
+    };
+    use crate::mqtt::messages::{
+        publish_flags::PublishFlags,
+        publish_message::{PublishMessage, MAX_REMAINING_LENGTH},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_el_snapshot_lleva_solamente_las_camaras_que_cambiaron() {
+        let mut published = HashMap::new();
+        let cameras: Vec<Camera> = (1..=3).map(|id| Camera::new(id, position(-34.6, -58.4), 1)).collect();
+
+        // Al inicio, se publican todas.
+        let snapshot = build_snapshot(&mut published, cameras.clone());
+        assert!(snapshot.is_full());
+        assert_eq!(snapshot.get_cameras().len(), 3);
+        assert!(snapshot.get_cameras().iter().all(|change| change.get_changes() == CAMERA_ADDED));
+        assert_eq!(CamerasSnapshot::from_bytes(&snapshot.to_bytes()).unwrap(), snapshot);
+
+        // Luego, solamente las que cambiaron, una vez cada una, con lo que cambió.
+        let mut active = cameras[0].clone();
+        active.set_state_to(CameraState::Active);
+        let mut wider = cameras[1].clone();
+        wider.set_range(500.0).unwrap();
+        let snapshot = build_snapshot(
+            &mut published,
+            vec![cameras[0].clone(), wider, cameras[2].clone(), active],
+        );
+        assert!(!snapshot.is_full());
+        let changes: Vec<(u8, u8)> = snapshot
+            .get_cameras()
+            .iter()
+            .map(|change| (change.get_camera().get_id(), change.get_changes()))
+            .collect();
+        assert_eq!(changes, vec![(2, RANGE_CHANGED), (1, STATE_CHANGED)]);
+        assert!(snapshot.get_cameras()[1].has_changed(STATE_CHANGED));
+        assert_eq!(CamerasSnapshot::from_bytes(&snapshot.to_bytes()).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_2_el_snapshot_de_las_camaras_del_repo_se_publica_en_partes_que_entran_en_un_publish() {
+        let topic = AppsMqttTopics::CameraSnapshotTopic.to_str();
+        let max_len = PublishMessage::max_payload_len(topic);
+        let mut cameras: Vec<Camera> = create_cameras().unwrap().lock().unwrap().values().cloned().collect();
+        cameras.sort_by_key(Camera::get_id);
+        let snapshot = build_snapshot(&mut HashMap::new(), cameras.clone());
+        // Entero, no entraría en un publish.
+        assert!(snapshot.to_bytes().len() > max_len);
+
+        for codec in [PayloadCodec::Binary, PayloadCodec::Json] {
+            let parts = snapshot.split(max_len, &codec).unwrap();
+            assert!(parts.len() > 1);
+            let mut received = vec![];
+            for (i, part) in parts.iter().enumerate() {
+                let payload = codec.encode(part).unwrap();
+                let flags = PublishFlags::new(0, 1, 0).unwrap();
+                let bytes = PublishMessage::new(flags, topic, Some(1), &payload).unwrap().to_bytes();
+                assert!(bytes.len() - 2 <= MAX_REMAINING_LENGTH);
+                assert_eq!(bytes[1] as usize, bytes.len() - 2);
+
+                let publish = PublishMessage::from_bytes(bytes).unwrap();
+                let part: CamerasSnapshot = decode_payload(&publish.get_payload()).unwrap();
+                // Solamente la primera parte es completa.
+                assert_eq!(part.is_full(), i == 0);
+                received.extend(part.get_cameras().iter().map(|change| change.get_camera().clone()));
+            }
+            assert_eq!(received, cameras);
+        }
+    }
+}
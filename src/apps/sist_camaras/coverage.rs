@@ -0,0 +1,370 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Error, ErrorKind},
+};
+
+use crate::apps::{geo_position::GeoPosition, properties::Properties, sist_dron::calculations::METERS_PER_DEGREE};
+
+use super::camera::Camera;
+
+/// Lado en metros de cada celda en que se divide la región, si el archivo no define `region-cell-meters`.
+const DEFAULT_CELL_SIZE: f64 = 50.0;
+/// Máxima cantidad de celdas que se analizan, para que una región enorme o celdas diminutas no bloqueen el sistema.
+const MAX_CELLS: usize = 250_000;
+/// Bytes del reporte sin sus zonas: la proporción sin cubrir y la cantidad de zonas.
+const REPORT_HEADER_LEN: usize = 8 + 2;
+/// Bytes de cada zona del reporte: sus posiciones mínima y máxima, y su superficie.
+const GAP_LEN: usize = 16 + 16 + 8;
+
+/// Región que se quiere vigilar con las cámaras, entre las posiciones `min` y `max`. Para buscar las
+/// zonas sin cobertura se la divide en celdas cuadradas de `cell_size` metros de lado.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SurveillanceRegion {
+    min: GeoPosition,
+    max: GeoPosition,
+    cell_size: f64, // en metros
+}
+
+impl SurveillanceRegion {
+    pub fn new(min: GeoPosition, max: GeoPosition, cell_size: f64) -> Result<Self, Error> {
+        let is_valid = min.get_latitude() < max.get_latitude()
+            && min.get_longitude() < max.get_longitude()
+            && cell_size.is_finite()
+            && cell_size > 0.0;
+        if !is_valid {
+            return Err(invalid_region());
+        }
+        Ok(Self { min, max, cell_size })
+    }
+
+    /// Carga la región de las properties `region=lat_min,lon_min,lat_max,lon_max` y `region-cell-meters` de
+    /// `properties_file`. Si el archivo no existe o no define la región, devuelve `None`.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        let Some(region) = properties.get("region") else {
+            return Ok(None);
+        };
+        let bounds = region
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid_region())?;
+        let [lat_min, lon_min, lat_max, lon_max] = bounds[..] else {
+            return Err(invalid_region());
+        };
+        let cell_size = match properties.get("region-cell-meters") {
+            Some(value) => value.parse().map_err(|_| invalid_region())?,
+            None => DEFAULT_CELL_SIZE,
+        };
+        let min = GeoPosition::new(lat_min, lon_min).map_err(|_| invalid_region())?;
+        let max = GeoPosition::new(lat_max, lon_max).map_err(|_| invalid_region())?;
+        Self::new(min, max, cell_size).map(Some)
+    }
+
+    /// Devuelve el alto y el ancho de cada celda, en grados de latitud y de longitud.
+    fn cell_degrees(&self) -> (f64, f64) {
+        let lat_step = self.cell_size / METERS_PER_DEGREE;
+        let mid_latitude = (self.min.get_latitude() + self.max.get_latitude()) / 2.0;
+        (lat_step, lat_step / mid_latitude.to_radians().cos())
+    }
+
+    /// Devuelve la cantidad de filas y columnas de celdas.
+    fn grid_size(&self) -> (usize, usize) {
+        let (lat_step, lon_step) = self.cell_degrees();
+        let rows = ((self.max.get_latitude() - self.min.get_latitude()) / lat_step).ceil() as usize;
+        let cols = ((self.max.get_longitude() - self.min.get_longitude()) / lon_step).ceil() as usize;
+        (rows, cols)
+    }
+
+    /// Devuelve la posición del centro de la celda de la fila `row` y la columna `col`.
+    fn cell_center(&self, row: usize, col: usize) -> GeoPosition {
+        let (lat_step, lon_step) = self.cell_degrees();
+        self.min
+            .offset((row as f64 + 0.5) * lat_step, (col as f64 + 0.5) * lon_step)
+    }
+}
+
+fn invalid_region() -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        "Región de vigilancia inválida, debe ser region=lat_min,lon_min,lat_max,lon_max con region-cell-meters positivo.",
+    )
+}
+
+/// Zona de la región que no cubre ninguna cámara: el rectángulo que la contiene, entre las posiciones `min` y
+/// `max`, y su superficie aproximada en metros cuadrados.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CoverageGap {
+    min: GeoPosition,
+    max: GeoPosition,
+    area: f64,
+}
+
+impl CoverageGap {
+    pub fn new(min: GeoPosition, max: GeoPosition, area: f64) -> Self {
+        Self { min, max, area }
+    }
+
+    pub fn get_min(&self) -> GeoPosition {
+        self.min
+    }
+
+    pub fn get_max(&self) -> GeoPosition {
+        self.max
+    }
+
+    /// Devuelve la superficie de la zona, en metros cuadrados.
+    pub fn get_area(&self) -> f64 {
+        self.area
+    }
+
+    /// Devuelve el centro del rectángulo que contiene a la zona, como referencia para reubicar cámaras.
+    pub fn get_center(&self) -> GeoPosition {
+        self.min.offset(
+            (self.max.get_latitude() - self.min.get_latitude()) / 2.0,
+            (self.max.get_longitude() - self.min.get_longitude()) / 2.0,
+        )
+    }
+}
+
+/// Resultado del análisis de cobertura: las zonas sin cobertura, de mayor a menor, y qué proporción de la región
+/// no está cubierta. Lo publica Sistema Cámaras por el topic `cam-gaps`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CoverageReport {
+    gaps: Vec<CoverageGap>,
+    uncovered_ratio: f64,
+}
+
+impl CoverageReport {
+    pub fn new(gaps: Vec<CoverageGap>, uncovered_ratio: f64) -> Self {
+        Self {
+            gaps,
+            uncovered_ratio,
+        }
+    }
+
+    pub fn get_gaps(&self) -> &[CoverageGap] {
+        &self.gaps
+    }
+
+    /// Devuelve la proporción de la región que no cubre ninguna cámara, entre 0 y 1.
+    pub fn get_uncovered_ratio(&self) -> f64 {
+        self.uncovered_ratio
+    }
+
+    /// Devuelve el reporte con solamente las zonas más grandes que entran en `max_len` bytes, para publicarlo en un
+    /// solo publish. La proporción sin cubrir sigue contando todas las zonas.
+    pub fn with_largest_gaps(&self, max_len: usize) -> CoverageReport {
+        let max_gaps = max_len.saturating_sub(REPORT_HEADER_LEN) / GAP_LEN;
+        let gaps = self.gaps.iter().take(max_gaps).cloned().collect();
+        CoverageReport::new(gaps, self.uncovered_ratio)
+    }
+
+    /// Convierte el reporte a bytes: la proporción sin cubrir, la cantidad de zonas, y por cada una sus
+    /// posiciones mínima y máxima y su superficie.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let gaps_len = self.gaps.len().min(u16::MAX as usize);
+        let mut bytes = self.uncovered_ratio.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&(gaps_len as u16).to_be_bytes());
+        for gap in self.gaps.iter().take(gaps_len) {
+            bytes.extend_from_slice(&gap.min.to_bytes());
+            bytes.extend_from_slice(&gap.max.to_bytes());
+            bytes.extend_from_slice(&gap.area.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Obtiene el reporte a partir de bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "Reporte de cobertura inválido.");
+        if bytes.len() < REPORT_HEADER_LEN {
+            return Err(invalid());
+        }
+        let read_f64 = |start: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[start..start + 8]);
+            f64::from_be_bytes(value)
+        };
+        let gaps_len = u16::from_be_bytes([bytes[8], bytes[9]]) as usize;
+        if bytes.len() != REPORT_HEADER_LEN + gaps_len * GAP_LEN {
+            return Err(invalid());
+        }
+        let read_position = |start: usize| GeoPosition::new(read_f64(start), read_f64(start + 8)).map_err(|_| invalid());
+        let gaps = (0..gaps_len)
+            .map(|i| {
+                let start = REPORT_HEADER_LEN + i * GAP_LEN;
+                Ok(CoverageGap::new(
+                    read_position(start)?,
+                    read_position(start + 16)?,
+                    read_f64(start + 32),
+                ))
+            })
+            .collect::<Result<Vec<CoverageGap>, Error>>()?;
+        Ok(Self::new(gaps, read_f64(0)))
+    }
+}
+
+/// Busca las zonas de `region` que no cubre ninguna de las cámaras `cams`: divide la región en celdas, marca como
+/// descubiertas aquellas cuyo centro no registra ninguna cámara, y agrupa las descubiertas contiguas en zonas.
+pub fn find_coverage_gaps(
+    region: &SurveillanceRegion,
+    cams: &HashMap<u8, Camera>,
+) -> Result<CoverageReport, Error> {
+    let (rows, cols) = region.grid_size();
+    if rows * cols > MAX_CELLS {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "La región de vigilancia tiene demasiadas celdas, aumente region-cell-meters.",
+        ));
+    }
+    let cameras: Vec<&Camera> = cams
+        .values()
+        .filter(|cam| cam.is_not_deleted() && cam.is_operational())
+        .collect();
+    let mut uncovered: Vec<Vec<bool>> = (0..rows)
+        .map(|row| {
+            (0..cols)
+                .map(|col| {
+                    let center = region.cell_center(row, col);
+                    !cameras.iter().any(|cam| cam.will_register(center))
+                })
+                .collect()
+        })
+        .collect();
+    let uncovered_cells = uncovered.iter().flatten().filter(|cell| **cell).count();
+
+    let mut gaps = vec![];
+    for row in 0..rows {
+        for col in 0..cols {
+            if uncovered[row][col] {
+                gaps.push(take_gap(region, &mut uncovered, row, col));
+            }
+        }
+    }
+    gaps.sort_by(|a, b| b.area.total_cmp(&a.area));
+
+    let total_cells = (rows * cols).max(1);
+    Ok(CoverageReport::new(gaps, uncovered_cells as f64 / total_cells as f64))
+}
+
+/// Recorre las celdas descubiertas contiguas a la de `(row, col)`, marcándolas como ya visitadas, y devuelve la zona
+/// que forman.
+fn take_gap(
+    region: &SurveillanceRegion,
+    uncovered: &mut [Vec<bool>],
+    row: usize,
+    col: usize,
+) -> CoverageGap {
+    let (lat_step, lon_step) = region.cell_degrees();
+    let (mut min_cell, mut max_cell) = ((row, col), (row, col));
+    let mut cells = 0;
+    let mut pending = VecDeque::from([(row, col)]);
+    uncovered[row][col] = false;
+
+    while let Some((r, c)) = pending.pop_front() {
+        cells += 1;
+        min_cell = (min_cell.0.min(r), min_cell.1.min(c));
+        max_cell = (max_cell.0.max(r), max_cell.1.max(c));
+        let neighbours = [
+            (r.wrapping_sub(1), c),
+            (r + 1, c),
+            (r, c.wrapping_sub(1)),
+            (r, c + 1),
+        ];
+        for (nr, nc) in neighbours {
+            if let Some(cell) = uncovered.get_mut(nr).and_then(|cells_row| cells_row.get_mut(nc)) {
+                if *cell {
+                    *cell = false;
+                    pending.push_back((nr, nc));
+                }
+            }
+        }
+    }
+
+    let min = region
+        .min
+        .offset(min_cell.0 as f64 * lat_step, min_cell.1 as f64 * lon_step);
+    let max_corner = region
+        .min
+        .offset((max_cell.0 + 1) as f64 * lat_step, (max_cell.1 + 1) as f64 * lon_step);
+    let max = GeoPosition::new(
+        max_corner.get_latitude().min(region.max.get_latitude()),
+        max_corner.get_longitude().min(region.max.get_longitude()),
+    )
+    .unwrap_or(region.max);
+    CoverageGap::new(min, max, cells as f64 * region.cell_size * region.cell_size)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{find_coverage_gaps, CoverageGap, CoverageReport, SurveillanceRegion};
+    use crate::apps::{apps_mqtt_topics::AppsMqttTopics, geo_position::GeoPosition, sist_camaras::camera::Camera};
+    use crate::mqtt::messages::{
+        publish_flags::PublishFlags,
+        publish_message::{PublishMessage, MAX_REMAINING_LENGTH},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_se_encuentran_las_zonas_que_no_cubre_ninguna_camara() {
+        // Región de ~1100m x ~900m, con una cámara de rango 1 (~280m de radio) en el centro.
+        let region = SurveillanceRegion::new(position(-34.610, -58.390), position(-34.600, -58.380), 50.0).unwrap();
+        let mut cams = HashMap::new();
+        cams.insert(1, Camera::new(1, position(-34.605, -58.385), 1));
+
+        // Sin cámaras no cubre nada, y es una sola zona.
+        let report = find_coverage_gaps(&region, &HashMap::new()).unwrap();
+        assert_eq!(report.get_gaps().len(), 1);
+        assert_eq!(report.get_uncovered_ratio(), 1.0);
+
+        // Con la cámara, queda descubierto el anillo alrededor de ella: una sola zona, sin el centro.
+        let report = find_coverage_gaps(&region, &cams).unwrap();
+        assert_eq!(report.get_gaps().len(), 1);
+        assert!(report.get_uncovered_ratio() > 0.5 && report.get_uncovered_ratio() < 0.9);
+
+        // Con una cámara que cubre todo, no hay zonas descubiertas.
+        cams.insert(2, Camera::new(2, position(-34.605, -58.385), 10));
+        let report = find_coverage_gaps(&region, &cams).unwrap();
+        assert!(report.get_gaps().is_empty());
+        assert_eq!(report.get_uncovered_ratio(), 0.0);
+
+        // El reporte se convierte a bytes y de vuelta.
+        let report = find_coverage_gaps(&region, &HashMap::new()).unwrap();
+        assert_eq!(CoverageReport::from_bytes(&report.to_bytes()).unwrap(), report);
+        assert!(SurveillanceRegion::new(position(-34.600, -58.390), position(-34.610, -58.380), 50.0).is_err());
+    }
+
+    #[test]
+    fn test_2_el_reporte_publicado_lleva_las_zonas_mas_grandes_que_entran_en_un_publish() {
+        let gaps: Vec<CoverageGap> = (0..20)
+            .map(|i| CoverageGap::new(position(-34.61, -58.39), position(-34.60, -58.38), 1000.0 - i as f64))
+            .collect();
+        let report = CoverageReport::new(gaps.clone(), 0.4);
+        let topic = AppsMqttTopics::CameraCoverageTopic.to_str();
+        let max_len = PublishMessage::max_payload_len(topic);
+        assert!(report.to_bytes().len() > max_len);
+
+        let published = report.with_largest_gaps(max_len);
+        assert!(!published.get_gaps().is_empty());
+        assert_eq!(published.get_gaps(), &gaps[..published.get_gaps().len()]);
+        assert_eq!(published.get_uncovered_ratio(), 0.4);
+
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let bytes = PublishMessage::new(flags, topic, Some(1), &published.to_bytes()).unwrap().to_bytes();
+        assert!(bytes.len() - 2 <= MAX_REMAINING_LENGTH);
+        assert_eq!(bytes[1] as usize, bytes.len() - 2);
+        let publish = PublishMessage::from_bytes(bytes).unwrap();
+        assert_eq!(CoverageReport::from_bytes(&publish.get_payload()).unwrap(), published);
+
+        // Un reporte que ya entra se publica completo.
+        let small = CoverageReport::new(gaps[..2].to_vec(), 0.1);
+        assert_eq!(small.with_largest_gaps(max_len), small);
+    }
+}
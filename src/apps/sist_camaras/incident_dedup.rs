@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    time::{Duration, Instant},
+};
+
+use crate::apps::{geo_position::GeoPosition, incident_data::incident_info::IncidentInfo, properties::Properties};
+
+/// Distancia máxima, en metros, entre dos incidentes para considerarlos el mismo, si el archivo no define
+/// `dedup-distance-m`.
+pub const DEFAULT_DEDUP_DISTANCE_METERS: f64 = 50.0;
+/// Tiempo máximo entre dos incidentes para considerarlos el mismo, si el archivo no define `dedup-window-secs`.
+pub const DEFAULT_DEDUP_WINDOW_SECS: u64 = 10;
+
+/// Qué hacer con un incidente recibido, luego de buscar si es un duplicado.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DedupOutcome {
+    /// Procesar el incidente con la info indicada: la suya, o la del incidente con el que se unió.
+    Process(IncidentInfo),
+    /// El incidente es un duplicado del indicado, que las cámaras ya siguen.
+    Merged(IncidentInfo),
+    /// Se resolvió uno de los incidentes unidos al indicado, pero quedan otros sin resolver.
+    StillPending(IncidentInfo),
+}
+
+/// Incidentes unidos bajo el primero que se recibió.
+#[derive(Debug, PartialEq, Clone)]
+struct MergedIncidents {
+    position: GeoPosition,
+    received_at: Instant,
+    pending: Vec<IncidentInfo>, // los incidentes unidos que todavía no se resolvieron, incluido el primero.
+}
+
+/// Une los incidentes que llegan casi en las mismas coordenadas con poco tiempo de diferencia (ie el operador de
+/// monitoreo y el detector automático informan el mismo hecho), para que las cámaras lo sigan una sola vez, bajo la
+/// info del primero. Las cámaras dejan de seguirlo cuando se resuelven todos los incidentes unidos.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentDeduplicator {
+    max_distance: f64, // en metros
+    window: Duration,
+    merged: HashMap<IncidentInfo, MergedIncidents>, // primer incidente -> incidentes unidos a él
+    aliases: HashMap<IncidentInfo, IncidentInfo>,   // incidente -> primer incidente con el que se unió
+}
+
+impl IncidentDeduplicator {
+    pub fn new(max_distance: f64, window: Duration) -> Self {
+        Self {
+            max_distance,
+            window,
+            merged: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Carga la distancia de la property `dedup-distance-m` (en metros) y el tiempo de `dedup-window-secs` (en
+    /// segundos) de `properties_file`. Si el archivo no existe o no las define, usa los valores por defecto.
+    pub fn from_file(properties_file: &str) -> Result<Self, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(Self::default());
+        };
+        let max_distance = match properties.get("dedup-distance-m") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(meters) if meters.is_finite() && meters >= 0.0 => meters,
+                _ => return Err(invalid_property("dedup-distance-m", value)),
+            },
+            None => DEFAULT_DEDUP_DISTANCE_METERS,
+        };
+        let window = match properties.get("dedup-window-secs") {
+            Some(value) => value
+                .parse::<u64>()
+                .map_err(|_| invalid_property("dedup-window-secs", value))?,
+            None => DEFAULT_DEDUP_WINDOW_SECS,
+        };
+        Ok(Self::new(max_distance, Duration::from_secs(window)))
+    }
+
+    /// Busca si el incidente `info`, de posición `position`, recibido en el instante `now`, es un duplicado.
+    /// Si no está resuelto y es nuevo, se une al primer incidente sin resolver recibido a menos de `max_distance`
+    /// y dentro de `window`. Si está resuelto, lo quita de los incidentes unidos.
+    pub fn deduplicate(
+        &mut self,
+        info: IncidentInfo,
+        position: GeoPosition,
+        resolved: bool,
+        now: Instant,
+    ) -> DedupOutcome {
+        if resolved {
+            return self.resolve(info);
+        }
+        if let Some(first) = self.aliases.get(&info) {
+            return DedupOutcome::Process(*first);
+        }
+
+        let duplicated = self.merged.iter_mut().find(|(_, merged)| {
+            now.saturating_duration_since(merged.received_at) <= self.window
+                && merged.position.distance_to(&position) <= self.max_distance
+        });
+        match duplicated {
+            Some((first, merged)) => {
+                merged.pending.push(info);
+                self.aliases.insert(info, *first);
+                DedupOutcome::Merged(*first)
+            }
+            None => {
+                let merged = MergedIncidents {
+                    position,
+                    received_at: now,
+                    pending: vec![info],
+                };
+                self.merged.insert(info, merged);
+                self.aliases.insert(info, info);
+                DedupOutcome::Process(info)
+            }
+        }
+    }
+
+    /// Quita el incidente resuelto `info` de los incidentes unidos. Devuelve que se procese la resolución del primer
+    /// incidente si era el último sin resolver, o si `info` no se había recibido antes.
+    fn resolve(&mut self, info: IncidentInfo) -> DedupOutcome {
+        let Some(first) = self.aliases.remove(&info) else {
+            return DedupOutcome::Process(info);
+        };
+        if let Some(merged) = self.merged.get_mut(&first) {
+            merged.pending.retain(|pending| *pending != info);
+            if !merged.pending.is_empty() {
+                return DedupOutcome::StillPending(first);
+            }
+        }
+        self.merged.remove(&first);
+        DedupOutcome::Process(first)
+    }
+}
+
+impl Default for IncidentDeduplicator {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_DEDUP_DISTANCE_METERS,
+            Duration::from_secs(DEFAULT_DEDUP_WINDOW_SECS),
+        )
+    }
+}
+
+fn invalid_property(name: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Valor inválido para {}: {}.", name, value),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{DedupOutcome, IncidentDeduplicator};
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_los_incidentes_cercanos_en_el_tiempo_y_el_espacio_se_unen() {
+        let mut dedup = IncidentDeduplicator::new(50.0, Duration::from_secs(10));
+        let start = Instant::now();
+        let manual = IncidentInfo::new(1, IncidentSource::Manual);
+        let automated = IncidentInfo::new(1, IncidentSource::Automated);
+        let far = IncidentInfo::new(2, IncidentSource::Automated);
+        let late = IncidentInfo::new(3, IncidentSource::Automated);
+
+        assert_eq!(
+            dedup.deduplicate(manual, position(-34.6040, -58.3873), false, start),
+            DedupOutcome::Process(manual)
+        );
+        // A unos 20 m, 2 segundos después: es el mismo.
+        let later = start + Duration::from_secs(2);
+        assert_eq!(
+            dedup.deduplicate(automated, position(-34.6041, -58.3874), false, later),
+            DedupOutcome::Merged(manual)
+        );
+        // A 3 cuadras, o pasado el tiempo, no.
+        assert_eq!(
+            dedup.deduplicate(far, position(-34.6044, -58.3950), false, later),
+            DedupOutcome::Process(far)
+        );
+        let after_window = start + Duration::from_secs(11);
+        assert_eq!(
+            dedup.deduplicate(late, position(-34.6040, -58.3873), false, after_window),
+            DedupOutcome::Process(late)
+        );
+
+        // Recién cuando se resuelven ambos, las cámaras dejan de seguirlo.
+        assert_eq!(
+            dedup.deduplicate(manual, position(-34.6040, -58.3873), true, after_window),
+            DedupOutcome::StillPending(manual)
+        );
+        assert_eq!(
+            dedup.deduplicate(automated, position(-34.6041, -58.3874), true, after_window),
+            DedupOutcome::Process(manual)
+        );
+    }
+}
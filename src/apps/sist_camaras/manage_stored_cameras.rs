@@ -1,40 +1,96 @@
-use std::{collections::HashMap, fs, sync::{Arc, Mutex}};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, ErrorKind},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
-use super::camera::Camera;
+use crate::apps::geo_position::GeoPosition;
+
+use super::{camera::{parse_range, Camera}, neighbors::Neighbors};
 
 /// Crea el hashmap de cámaras bien inicializado envuelto en un arc mutex, listo para ser usado
 /// por sistema cámaras y sus módulos.
-pub fn create_cameras() -> Arc<Mutex<HashMap<u8, Camera>>> {
-    let cameras: HashMap<u8, Camera> = read_cameras_from_file("./cameras.properties");
-    Arc::new(Mutex::new(cameras))
+pub fn create_cameras() -> Result<Arc<Mutex<HashMap<u8, Camera>>>, Error> {
+    create_cameras_with_neighbors(&Neighbors::default())
+}
+
+/// Ídem `create_cameras`, calculando cuáles son lindantes con `neighbors`.
+pub fn create_cameras_with_neighbors(
+    neighbors: &Neighbors,
+) -> Result<Arc<Mutex<HashMap<u8, Camera>>>, Error> {
+    let mut cameras: HashMap<u8, Camera> = read_cameras_from_file("./cameras.properties")?;
+    neighbors.recompute_all(&mut cameras);
+    Ok(Arc::new(Mutex::new(cameras)))
 }
 
-/// Lee las cámaras desde el archivo `filename`, las parsea y las crea, configurando también cuáles
-/// son lindantes entre sí. Devuelve un hashmap con el id de cada cámara como clave y la cámara como valor.
-fn read_cameras_from_file(filename: &str) -> HashMap<u8, Camera> {
+/// Lee las cámaras desde el archivo `filename`, las parsea y las crea. El rango de cada una va en metros (ie `250m`)
+/// o en cuadras (ie `2`); devuelve error si no puede leer el archivo, o si alguna tiene un id, una posición o un rango
+/// inválidos (el rango, también si es cero o negativo).
+/// Devuelve un hashmap con el id de cada cámara como clave y la cámara como valor.
+fn read_cameras_from_file(filename: &str) -> Result<HashMap<u8, Camera>, Error> {
     let mut cameras: HashMap<u8, Camera> = HashMap::new();
-    let contents = fs::read_to_string(filename).expect("Error al leer el archivo de properties");
+    let contents = fs::read_to_string(filename).map_err(|e| {
+        Error::new(e.kind(), format!("Error al leer el archivo de cámaras {}: {}", filename, e))
+    })?;
 
     for line in contents.lines() {
         let parts: Vec<&str> = line.split(':').collect();
         if parts.len() == 4 {
             // Lee los atributos a cargar a la nueva cámara
-            let id: u8 = parts[0].trim().parse().expect("Id no válido");
-            let latitude = parts[1].trim().parse().expect("Latitud no válida");
-            let longitude = parts[2].trim().parse().expect("Longitud no válida");
-            let range = parts[3].trim().parse().expect("Rango no válido"); // []
-
-            let mut new_camera = Camera::new(id, latitude, longitude, range);
-
-            // Recorre las cámaras ya existentes, agregando la nueva cámara como lindante de la que corresponda y viceversa, terminando la creación
-            for camera in cameras.values_mut() {
-                camera.mutually_add_if_bordering(&mut new_camera);
-            }
+            let id: u8 = parse_field(parts[0], "Id no válido")?;
+            let latitude = parse_field(parts[1], "Latitud no válida")?;
+            let longitude = parse_field(parts[2], "Longitud no válida")?;
+            let position = GeoPosition::new(latitude, longitude).map_err(|e| {
+                Error::new(ErrorKind::InvalidInput, format!("Cámara {}: {}", id, e))
+            })?;
+            let range = parse_range(parts[3]).map_err(|e| {
+                Error::new(ErrorKind::InvalidInput, format!("Cámara {}: {}", id, e))
+            })?;
 
             // Guarda la nueva cámara
-            cameras.insert(id, new_camera);
+            cameras.insert(id, Camera::new_in_meters(id, position, range)?);
         }
     }
 
-    cameras
+    Ok(cameras)
+}
+
+/// Parsea un campo de una línea del archivo de cámaras. Devuelve error, con `msg`, si no es válido.
+fn parse_field<T: FromStr>(field: &str, msg: &str) -> Result<T, Error> {
+    field
+        .trim()
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("{}: {:?}", msg, field.trim())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::read_cameras_from_file;
+    use std::{fs, io::ErrorKind};
+
+    #[test]
+    fn test_1_una_linea_mal_formada_se_rechaza_sin_panic() {
+        let path = std::env::temp_dir().join(format!("rustx_cameras_{}.properties", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, "1:-34.6:-58.4:250m\n2:-34.6:-58.4:250m").unwrap();
+        assert_eq!(read_cameras_from_file(path_str).unwrap().len(), 2);
+
+        let malformed_lines = [
+            "x:-34.6:-58.4:250m",
+            "3:latitud:-58.4:250m",
+            "3:-34.6:longitud:250m",
+            "300:-34.6:-58.4:2",
+        ];
+        for malformed in malformed_lines {
+            fs::write(&path, format!("1:-34.6:-58.4:250m\n{}", malformed)).unwrap();
+            let err = read_cameras_from_file(path_str).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        let _ = fs::remove_file(&path);
+
+        // Si el archivo no existe, también se devuelve error.
+        assert!(read_cameras_from_file(path_str).is_err());
+    }
 }
@@ -1,8 +1,16 @@
 pub mod ai_detection;
 pub mod camara_errors;
 pub mod camera;
+pub mod camera_admin_command;
+pub mod camera_health;
+pub mod camera_schedule;
 pub mod camera_state;
+pub mod camera_state_store;
+pub mod cameras_snapshot;
+pub mod coverage;
+pub mod incident_dedup;
 pub mod manage_stored_cameras;
+pub mod neighbors;
 pub mod sist_cams_mqtt_properties;
 pub mod sistema_camaras;
 pub mod sistema_camaras_abm;
@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use crate::apps::properties::Properties;
+
+use super::camera::Camera;
+
+/// Distancia en metros entre los bordes de las áreas de cobertura de dos cámaras, hasta la cual se las considera
+/// lindantes, si el archivo no define `border-distance`: solamente las que se tocan o superponen.
+pub const DEFAULT_BORDER_DISTANCE: f64 = 0.0;
+
+/// Calcula qué cámaras son lindantes a partir de sus posiciones y rangos: lo son dos cámaras cuyas áreas de
+/// cobertura están a lo sumo a `border_distance` metros una de la otra.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Neighbors {
+    border_distance: f64, // en metros
+}
+
+impl Neighbors {
+    pub fn new(border_distance: f64) -> Self {
+        Self { border_distance }
+    }
+
+    /// Carga la distancia de la property `border-distance` de `properties_file`, en metros. Si el archivo no existe
+    /// o no la define, usa `DEFAULT_BORDER_DISTANCE`.
+    pub fn from_file(properties_file: &str) -> Result<Self, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(Self::default());
+        };
+        match properties.get("border-distance") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(distance) if distance.is_finite() && distance >= 0.0 => Ok(Self::new(distance)),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Valor inválido para border-distance: {}.", value),
+                )),
+            },
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn get_border_distance(&self) -> f64 {
+        self.border_distance
+    }
+
+    /// Devuelve si las cámaras `a` y `b` son lindantes.
+    pub fn are_bordering(&self, a: &Camera, b: &Camera) -> bool {
+        a.get_id() != b.get_id()
+            && a.coverage_gap_to(b) <= a.get_distance_model().meters_to_degrees(self.border_distance)
+    }
+
+    /// Recalcula las lindantes de todas las cámaras de `cams`.
+    pub fn recompute_all(&self, cams: &mut HashMap<u8, Camera>) {
+        let snapshot: Vec<Camera> = cams.values().cloned().collect();
+        for camera in cams.values_mut() {
+            let bordering = snapshot
+                .iter()
+                .filter(|other| other.is_not_deleted() && self.are_bordering(camera, other))
+                .map(Camera::get_id)
+                .collect();
+            *camera.get_bordering_cams() = bordering;
+        }
+    }
+
+    /// Recalcula las lindantes de `camera`, que no está en `cams`, y la agrega o quita como lindante de las demás
+    /// según corresponda. Devuelve los ids de las cámaras de `cams` cuya lista de lindantes cambió.
+    pub fn update(&self, cams: &mut HashMap<u8, Camera>, camera: &mut Camera) -> Vec<u8> {
+        let id = camera.get_id();
+        camera.get_bordering_cams().clear();
+        let mut changed = vec![];
+        for other in cams.values_mut() {
+            let is_bordering = self.are_bordering(camera, other);
+            if is_bordering {
+                camera.get_bordering_cams().push(other.get_id());
+            }
+            if set_bordering(other, id, is_bordering) {
+                changed.push(other.get_id());
+            }
+        }
+        changed
+    }
+
+    /// Quita a la cámara `id` como lindante de las de `cams`. Devuelve los ids de las cámaras que la tenían.
+    pub fn remove(&self, cams: &mut HashMap<u8, Camera>, id: u8) -> Vec<u8> {
+        cams.values_mut()
+            .filter_map(|other| set_bordering(other, id, false).then(|| other.get_id()))
+            .collect()
+    }
+}
+
+impl Default for Neighbors {
+    fn default() -> Self {
+        Self::new(DEFAULT_BORDER_DISTANCE)
+    }
+}
+
+/// Agrega o quita a `id` de las lindantes de `camera`. Devuelve si su lista cambió.
+fn set_bordering(camera: &mut Camera, id: u8, is_bordering: bool) -> bool {
+    let bordering = camera.get_bordering_cams();
+    match (bordering.iter().position(|bordering_id| *bordering_id == id), is_bordering) {
+        (None, true) => bordering.push(id),
+        (Some(pos), false) => {
+            bordering.remove(pos);
+        }
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::Neighbors;
+    use crate::apps::{geo_position::GeoPosition, sist_camaras::camera::Camera};
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_las_lindantes_se_recalculan_al_agregar_y_eliminar_camaras() {
+        // Áreas de ~280m de radio (rango 1), con centros a ~330m (cam 5 y 6) y a ~700m (cam 10 y 5).
+        let mut cams = HashMap::new();
+        cams.insert(5, Camera::new(5, position(-34.6040, -58.3873), 1));
+        cams.insert(6, Camera::new(6, position(-34.6039, -58.3837), 1));
+        Neighbors::default().recompute_all(&mut cams);
+        assert_eq!(cams.get_mut(&5).unwrap().get_bordering_cams(), &vec![6]);
+
+        // A 140m del área de la 5: es lindante solamente con una distancia configurada mayor.
+        let mut cam_10 = Camera::new(10, position(-34.6044, -58.3950), 1);
+        assert!(Neighbors::default().update(&mut cams, &mut cam_10).is_empty());
+        assert!(cam_10.get_bordering_cams().is_empty());
+
+        let changed = Neighbors::new(200.0).update(&mut cams, &mut cam_10);
+        assert_eq!(changed, vec![5]);
+        assert_eq!(cam_10.get_bordering_cams(), &vec![5]);
+        assert_eq!(cams.get_mut(&5).unwrap().get_bordering_cams(), &vec![6, 10]);
+
+        // Al eliminarla, deja de ser lindante de la 5.
+        assert_eq!(Neighbors::default().remove(&mut cams, 10), vec![5]);
+        assert_eq!(cams.get_mut(&5).unwrap().get_bordering_cams(), &vec![6]);
+    }
+}
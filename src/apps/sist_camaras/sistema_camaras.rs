@@ -1,75 +1,136 @@
 use crate::apps::{
     apps_mqtt_topics::AppsMqttTopics,
-    common_clients::{exit_when_asked, there_are_no_more_publish_msgs},
+    common_clients::exit_when_asked,
     incident_data::incident::Incident,
+    payload_codec::{decode_payload, Codec, TopicCodecs},
     sist_camaras::{
-        ai_detection::ai_detector_manager::AIDetectorManager, camera::Camera,
+        ai_detection::{ai_detector_manager::AIDetectorManager, detection_batcher::BatchSettings},
+        camera::Camera,
+        camera_admin_command::CameraAdminCommand,
+        camera_health::{CameraHealthMonitor, CameraHeartbeat, HEALTH_CHECK_INTERVAL},
+        camera_schedule::{CameraSchedule, SCHEDULE_CHECK_INTERVAL},
+        camera_state_store::CameraStateStore,
+        cameras_snapshot::build_snapshot,
+        coverage::{find_coverage_gaps, SurveillanceRegion},
+        incident_dedup::IncidentDeduplicator,
+        neighbors::Neighbors,
         sistema_camaras_abm::ABMCameras, sistema_camaras_logic::CamerasLogic,
-        types::shareable_cameras_type::ShCamerasType,
     },
 };
 use crate::logging::string_logger::StringLogger;
-use crate::mqtt::{client::mqtt_client::MQTTClient, messages::publish_message::PublishMessage};
+use crate::mqtt::{
+    client::mqtt_client::MQTTClient, messages::publish_message::PublishMessage,
+    mqtt_utils::mqtt_error::MqttError,
+};
 
+use chrono::Local;
 use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
 use std::{
-    fs,
-    io::{self, ErrorKind},
     sync::{
-        mpsc::{self, Receiver, Sender},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use super::types::channels_type::create_channels;
+/// Cuánto se espera, desde que cambia una cámara, a que cambien otras para publicarlas juntas en un snapshot.
+const CAMERAS_BATCH_WAIT: Duration = Duration::from_millis(50);
+
+use super::types::{channels_type::create_channels, hashmap_incs_type::HashmapIncsType};
 
 /// Sistema encargado de responder a incidentes cambiando las cámaras de estado,
-/// proveer un abm por consola, y ejecutar un detector automático de incidentes.
+/// proveer un abm por consola y por el topic `cam-admin`, vigilar el latido de las cámaras,
+/// y ejecutar un detector automático de incidentes.
 #[derive(Debug)]
 pub struct SistemaCamaras {
     cameras: Arc<Mutex<HashMap<u8, Camera>>>,
     qos: u8,
     logger: StringLogger,
+    topic_codecs: TopicCodecs, // con qué formato publica las cámaras y los incidentes.
+    neighbors: Neighbors,      // cómo recalcula las lindantes desde el abm.
+    region: Option<SurveillanceRegion>, // región en la que se buscan zonas sin cobertura.
+    health_monitor: Arc<Mutex<CameraHealthMonitor>>, // detecta las cámaras en falla o desconectadas.
+    state_store: Option<CameraStateStore>, // dónde persiste el estado de las cámaras entre reinicios.
+    incident_dedup: IncidentDeduplicator, // cómo une los incidentes duplicados.
+    schedule: Option<CameraSchedule>, // en qué estado de reposo quedan las cámaras según la hora.
 }
 
-fn leer_qos_desde_archivo(ruta_archivo: &str) -> Result<u8, io::Error> {
-    let contenido = fs::read_to_string(ruta_archivo)?;
-    let inicio = contenido.find("qos=").ok_or(io::Error::new(
-        ErrorKind::NotFound,
-        "No se encontró la etiqueta 'qos='",
-    ))?;
-    let valor_qos = contenido[inicio + 4..].trim().parse::<u8>().map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            "El valor de QoS no es un número válido",
-        )
-    })?;
-    Ok(valor_qos)
-}
 impl SistemaCamaras {
-    /// Crea un Sistema Cámaras.
+    /// Crea un Sistema Cámaras, que publica y se suscribe con el `qos` recibido.
     pub fn new(
         cameras: Arc<Mutex<HashMap<u8, Camera>>>,
+        qos: u8,
         logger: StringLogger,
     ) -> Self {
         println!("Sistema de Cámaras\n");
-        let qos =
-            leer_qos_desde_archivo("src/apps/sist_camaras/qos_sistema_camaras.properties").unwrap();
 
         let sistema_camaras: SistemaCamaras = Self {
             cameras,
             qos,
             logger,
+            topic_codecs: TopicCodecs::default(),
+            neighbors: Neighbors::default(),
+            region: None,
+            health_monitor: Arc::new(Mutex::new(CameraHealthMonitor::default())),
+            state_store: None,
+            incident_dedup: IncidentDeduplicator::default(),
+            schedule: None,
         };
 
         sistema_camaras
     }
 
+    /// Indica con qué formato publica por cada topic; por defecto, binario.
+    pub fn with_topic_codecs(mut self, topic_codecs: TopicCodecs) -> Self {
+        self.topic_codecs = topic_codecs;
+        self
+    }
+
+    /// Indica cómo recalcular las cámaras lindantes cuando se agregan, modifican o eliminan cámaras.
+    pub fn with_neighbors(mut self, neighbors: Neighbors) -> Self {
+        self.neighbors = neighbors;
+        self
+    }
+
+    /// Indica la región de vigilancia, en la que el comando `gaps` busca zonas sin cobertura.
+    pub fn with_surveillance_region(mut self, region: Option<SurveillanceRegion>) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Indica cómo vigilar el latido de las cámaras; por defecto, se las considera desconectadas luego de
+    /// `DEFAULT_CAMERA_OFFLINE_SECS` sin recibirlo.
+    pub fn with_camera_health(mut self, health_monitor: CameraHealthMonitor) -> Self {
+        self.health_monitor = Arc::new(Mutex::new(health_monitor));
+        self
+    }
+
+    /// Indica dónde persistir el estado de las cámaras y los incidentes que siguen, para restaurarlos al
+    /// reiniciar; por defecto, no se persisten.
+    pub fn with_state_store(mut self, state_store: Option<CameraStateStore>) -> Self {
+        self.state_store = state_store;
+        self
+    }
+
+    /// Indica cómo unir los incidentes recibidos casi en la misma posición y al mismo tiempo, para no activar las
+    /// cámaras dos veces.
+    pub fn with_incident_dedup(mut self, incident_dedup: IncidentDeduplicator) -> Self {
+        self.incident_dedup = incident_dedup;
+        self
+    }
+
+    /// Indica el horario de los estados de reposo de las cámaras; por defecto, las que no siguen incidentes quedan
+    /// siempre en ahorro de energía.
+    pub fn with_camera_schedule(mut self, schedule: Option<CameraSchedule>) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
     /// Inicializa las partes internas del Sistema Cámaras.
     pub fn spawn_threads(
         &mut self,
-        publish_msg_rx: Receiver<PublishMessage>,
         mqtt_client: MQTTClient,
     ) -> Vec<JoinHandle<()>> {
         let mut children: Vec<JoinHandle<()>> = vec![];
@@ -77,14 +138,31 @@ impl SistemaCamaras {
         let mqtt_sh = Arc::new(Mutex::new(mqtt_client));
         let (cameras_tx, cameras_rx, exit_tx, exit_rx, exit_detector_tx, exit_detector_rx) = create_channels();
 
+        // Restaura el estado anterior al reinicio, antes de que el abm publique las cámaras
+        let incs_being_managed = self.restore_state();
+
         // Recibe las cámaras que envía el abm y las publica por MQTT
         children.push(self.spawn_publish_to_topic_thread(mqtt_sh.clone(), cameras_rx));
 
         // ABM
-        children.push(self.spawn_abm_cameras_thread(&self.cameras, cameras_tx.clone(), exit_tx));
+        children.push(self.spawn_abm_cameras_thread(&self.cameras, cameras_tx.clone(), exit_tx.clone()));
 
         // Exit, cuando lo solicita el abm
-        children.push(spawn_exit_when_asked_thread(mqtt_sh.clone(), exit_rx, exit_detector_tx));
+        let (exit_health_tx, exit_health_rx) = mpsc::channel::<()>();
+        let (exit_schedule_tx, exit_schedule_rx) = mpsc::channel::<()>();
+        children.push(spawn_exit_when_asked_thread(
+            mqtt_sh.clone(),
+            exit_rx,
+            vec![exit_detector_tx, exit_health_tx, exit_schedule_tx],
+        ));
+
+        // Pone en Offline a las cámaras que dejan de enviar su latido
+        children.push(self.spawn_health_check_thread(cameras_tx.clone(), exit_health_rx));
+
+        // Cambia el estado de reposo de las cámaras según el horario
+        if let Some(schedule) = self.schedule.clone() {
+            children.push(self.spawn_schedule_thread(schedule, cameras_tx.clone(), exit_schedule_rx));
+        }
 
         // Incident detector (ai)
         let (inc_tx, inc_rx) = mpsc::channel::<Incident>();
@@ -92,11 +170,47 @@ impl SistemaCamaras {
         children.push(self.spawn_recv_and_publish_inc_thread(inc_rx, mqtt_sh.clone())); // recibe inc y publica
 
         // Suscribe y recibe mensajes por MQTT
-        children.push(self.spawn_subscribe_to_topics_thread(mqtt_sh.clone(), publish_msg_rx, cameras_tx));
+        self.subscribe_to_admin_topic(mqtt_sh.clone(), cameras_tx.clone(), exit_tx);
+        self.subscribe_to_health_topic(mqtt_sh.clone(), cameras_tx.clone());
+        self.subscribe_to_incident_topic(mqtt_sh.clone(), cameras_tx, incs_being_managed);
 
         children
     }
 
+    /// Restaura en las cámaras el estado guardado antes de reiniciar, y devuelve qué cámaras siguen a cada
+    /// incidente. Si no se puede restaurar, lo logguea y se parte de cero.
+    fn restore_state(&self) -> HashmapIncsType {
+        let Some(state_store) = &self.state_store else {
+            return HashMap::new();
+        };
+        let res = match self.cameras.lock() {
+            Ok(mut cams) => state_store.restore(&mut cams),
+            Err(_) => Err(Error::new(ErrorKind::Other, "Error al tomar lock de cámaras.")),
+        };
+        match res {
+            Ok(incs_being_managed) => {
+                self.logger.log(format!(
+                    "Estado de cámaras restaurado, siguiendo {} incidentes.",
+                    incs_being_managed.len()
+                ));
+                incs_being_managed
+            }
+            Err(e) => {
+                self.logger.log(format!("Error al restaurar el estado de las cámaras: {:?}.", e));
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Guarda el estado de las cámaras `cams`, si se configuró dónde. Si falla, lo logguea.
+    fn save_state(&self, cams: &HashMap<u8, Camera>) {
+        if let Some(state_store) = &self.state_store {
+            if let Err(e) = state_store.save(cams) {
+                self.logger.log(format!("Error al guardar el estado de las cámaras: {:?}.", e));
+            }
+        }
+    }
+
     /// Hilo que publica las cámaras.
     fn spawn_publish_to_topic_thread(
         &self,
@@ -105,11 +219,7 @@ impl SistemaCamaras {
     ) -> JoinHandle<()> {
         let self_clone = self.clone_ref();
         thread::spawn(move || {
-            self_clone.publish_to_topic(
-                mqtt_client_sh,
-                AppsMqttTopics::CameraTopic.to_str(),
-                cameras_rx,
-            );
+            self_clone.publish_cameras(mqtt_client_sh, cameras_rx);
         })
     }
 
@@ -124,9 +234,11 @@ impl SistemaCamaras {
         // Lanza el hilo para el abm
         let cameras_c = cameras.clone();
         let logger_c = self.logger.clone_ref();
+        let neighbors = self.neighbors;
         thread::spawn(move || {
             // Ejecuta el abm
-            let mut abm_cameras = ABMCameras::new(cameras_c, cameras_tx, exit_tx, logger_c);
+            let mut abm_cameras =
+                ABMCameras::new(cameras_c, cameras_tx, exit_tx, logger_c).with_neighbors(neighbors);
             abm_cameras.run();
         })
     }
@@ -149,15 +261,14 @@ impl SistemaCamaras {
         mqtt_client: Arc<Mutex<MQTTClient>>,
     ) -> JoinHandle<()> {
         let qos = self.qos;
+        let codec = self.topic_codecs.for_topic(&AppsMqttTopics::IncidentTopic);
         let logger_thread = self.logger.clone_ref();
         thread::spawn(move || {
             for inc in rx {
                 if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
-                    let res_publish = mqtt_client_lock.mqtt_publish(
-                        AppsMqttTopics::IncidentTopic.to_str(),
-                        &inc.to_bytes(),
-                        qos,
-                    );
+                    let res_publish = codec.encode(&inc).and_then(|payload| {
+                        mqtt_client_lock.mqtt_publish(AppsMqttTopics::IncidentTopic.to_str(), &payload, qos)
+                    });
                     match res_publish {
                         Ok(publish_message) => {
                             logger_thread.log(format!("Publico inc: {:?}", publish_message));
@@ -173,14 +284,29 @@ impl SistemaCamaras {
         })
     }
 
-    fn subscribe_to_topics(&self, mqtt_client: Arc<Mutex<MQTTClient>>, topics: Vec<(String, u8)>) {
-        let topics_log = topics.to_vec();
+    /// Se suscribe al topic de incidentes, y delega el procesamiento de cada incidente recibido a `CamerasLogic`.
+    fn subscribe_to_incident_topic(
+        &self,
+        mqtt_client: Arc<Mutex<MQTTClient>>,
+        cameras_tx: Sender<Vec<u8>>,
+        incs_being_managed: HashmapIncsType,
+    ) {
+        let topic = AppsMqttTopics::IncidentTopic.to_str();
+        let logic = Mutex::new(
+            CamerasLogic::new(self.cameras.clone(), cameras_tx, self.logger.clone_ref())
+                .with_incs_being_managed(incs_being_managed)
+                .with_state_store(self.state_store.clone())
+                .with_deduplicator(self.incident_dedup.clone()),
+        );
+        let self_clone = self.clone_ref();
+        let handler = move |msg: PublishMessage| self_clone.receive_message_from_incident_topic(msg, &logic);
+
         if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
-            let res_subscribe = mqtt_client_lock.mqtt_subscribe(topics);
+            let res_subscribe = mqtt_client_lock.mqtt_subscribe_with_handler(topic, self.qos, handler);
             match res_subscribe {
                 Ok(_) => {
                     self.logger
-                        .log(format!("Subscripto a topic: {:?}", topics_log));
+                        .log(format!("Subscripto a topic: {:?}", topic));
                 }
                 Err(e) => {
                     self.logger.log(format!("Error al subscribirse: {:?}", e));
@@ -189,68 +315,294 @@ impl SistemaCamaras {
         }
     }
 
-    /// Utiliza la librería MQTT para hacer publish,
-    fn publish_to_topic(
+    /// Cada `HEALTH_CHECK_INTERVAL` busca las cámaras que dejaron de enviar su latido, y envía las que pasaron a
+    /// `Offline` para que sean publicadas. Termina cuando se solicita salir.
+    fn spawn_health_check_thread(
         &self,
-        mqtt_client: Arc<Mutex<MQTTClient>>,
-        topic: &str,
-        rx: Receiver<Vec<u8>>,
-    ) {
-        while let Ok(cam_bytes) = rx.recv() {
-            if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
-                let res_publish = mqtt_client_lock.mqtt_publish(topic, &cam_bytes, self.qos);
-                match res_publish {
-                    Ok(publish_msg) => {
-                        self.logger.log(format!("Enviado msj: {:?}", publish_msg));
-                    }
-                    Err(e) => {
-                        println!("Error al hacer publish {:?}", e);
-                        self.logger.log(format!("Error al hacer publish {:?}", e));
-                    }
+        cameras_tx: Sender<Vec<u8>>,
+        exit_health_rx: Receiver<()>,
+    ) -> JoinHandle<()> {
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = exit_health_rx.recv_timeout(HEALTH_CHECK_INTERVAL) {
+                let (Ok(mut monitor), Ok(mut cams)) = (self_clone.health_monitor.lock(), self_clone.cameras.lock())
+                else {
+                    self_clone.logger.log("Error al tomar lock para vigilar latidos de cámaras.".to_string());
+                    break;
                 };
+                let changed = monitor.check_timeouts(&mut cams, Instant::now());
+                for cam_id in &changed {
+                    self_clone.logger.log(format!("Cámara {} sin latido, queda Offline.", cam_id));
+                    if let Some(camera) = cams.get(cam_id) {
+                        self_clone.send_camera(camera, &cameras_tx);
+                    }
+                }
+                if !changed.is_empty() {
+                    self_clone.save_state(&cams);
+                }
             }
-        }
+        })
     }
 
-    /// Hilo que se encarga de suscribirse a los topics y recibir los mensajes.
-    fn spawn_subscribe_to_topics_thread(
-        &mut self,
-        mqtt_client: Arc<Mutex<MQTTClient>>,
-        msg_rx: Receiver<PublishMessage>,
+    /// Aplica el horario `schedule` al iniciar y luego cada `SCHEDULE_CHECK_INTERVAL`, y envía las cámaras que
+    /// cambiaron de estado de reposo para que sean publicadas. Termina cuando se solicita salir.
+    fn spawn_schedule_thread(
+        &self,
+        schedule: CameraSchedule,
         cameras_tx: Sender<Vec<u8>>,
+        exit_schedule_rx: Receiver<()>,
     ) -> JoinHandle<()> {
-        let mut cameras_cloned = self.cameras.clone();
-        let mut self_clone = self.clone_ref();
-        let topic = AppsMqttTopics::IncidentTopic.to_str();
+        let self_clone = self.clone_ref();
         thread::spawn(move || {
-            self_clone.subscribe_to_topics(mqtt_client.clone(), vec![(String::from(topic), self_clone.qos)]);
-            self_clone.receive_messages_from_subscribed_topics(msg_rx, &mut cameras_cloned, cameras_tx);
+            self_clone.apply_schedule(&schedule, &cameras_tx);
+            while let Err(RecvTimeoutError::Timeout) = exit_schedule_rx.recv_timeout(SCHEDULE_CHECK_INTERVAL) {
+                self_clone.apply_schedule(&schedule, &cameras_tx);
+            }
         })
     }
 
-    /// Recibe mensajes de los topics a los que se ha suscrito, y delega el procesamiento a `CamerasLogic`.
-    fn receive_messages_from_subscribed_topics(
-        &mut self,
-        rx: Receiver<PublishMessage>,
-        cameras: &mut ShCamerasType,
+    /// Aplica a las cámaras el estado de reposo que corresponde a la hora actual según `schedule`, y envía las que
+    /// cambiaron para que sean publicadas.
+    fn apply_schedule(&self, schedule: &CameraSchedule, cameras_tx: &Sender<Vec<u8>>) {
+        let Ok(mut cams) = self.cameras.lock() else {
+            self.logger.log("Error al tomar lock para aplicar el horario de cámaras.".to_string());
+            return;
+        };
+        let changed = schedule.apply(&mut cams, Local::now().time());
+        for cam_id in &changed {
+            if let Some(camera) = cams.get(cam_id) {
+                self.logger.log(format!(
+                    "Cámara {} cambia a {:?} según el horario.",
+                    cam_id,
+                    camera.get_state()
+                ));
+                self.send_camera(camera, cameras_tx);
+            }
+        }
+        if !changed.is_empty() {
+            self.save_state(&cams);
+        }
+    }
+
+    /// Se suscribe al topic `cam-health`, y actualiza el estado de cada cámara según el latido que publica.
+    fn subscribe_to_health_topic(&self, mqtt_client: Arc<Mutex<MQTTClient>>, cameras_tx: Sender<Vec<u8>>) {
+        let topic = AppsMqttTopics::CameraHealthTopic.to_str();
+        let self_clone = self.clone_ref();
+        let cameras_tx = Mutex::new(cameras_tx);
+        let handler = move |msg: PublishMessage| self_clone.receive_message_from_health_topic(msg, &cameras_tx);
+
+        if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
+            match mqtt_client_lock.mqtt_subscribe_with_handler(topic, self.qos, handler) {
+                Ok(_) => self.logger.log(format!("Subscripto a topic: {:?}", topic)),
+                Err(e) => self.logger.log(format!("Error al subscribirse: {:?}", e)),
+            };
+        }
+    }
+
+    /// Recibe el latido de una cámara, y si la cámara cambió de estado la envía para que sea publicada.
+    fn receive_message_from_health_topic(&self, msg: PublishMessage, cameras_tx: &Mutex<Sender<Vec<u8>>>) {
+        let heartbeat = match CameraHeartbeat::from_bytes(msg.get_payload()) {
+            Ok(heartbeat) => heartbeat,
+            Err(e) => {
+                self.logger.log(format!("Latido de cámara inválido: {:?}.", e));
+                return;
+            }
+        };
+        let (Ok(mut monitor), Ok(mut cams), Ok(cameras_tx)) =
+            (self.health_monitor.lock(), self.cameras.lock(), cameras_tx.lock())
+        else {
+            self.logger.log("Error al tomar lock para procesar latido de cámara.".to_string());
+            return;
+        };
+        if monitor.register(&mut cams, &heartbeat, Instant::now()) {
+            if let Some(camera) = cams.get(&heartbeat.get_id()) {
+                self.logger.log(format!(
+                    "Cámara {} cambia a {:?} según su latido.",
+                    camera.get_id(),
+                    camera.get_state()
+                ));
+                self.send_camera(camera, &cameras_tx);
+            }
+            self.save_state(&cams);
+        }
+    }
+
+    /// Envía la cámara por el channel, para que sea publicada por MQTT.
+    fn send_camera(&self, camera: &Camera, cameras_tx: &Sender<Vec<u8>>) {
+        if cameras_tx.send(camera.to_bytes()).is_err() {
+            self.logger.log("Error al enviar cámara por tx.".to_string());
+        }
+    }
+
+    /// Se suscribe al topic `cam-admin`, y aplica cada comando del abm recibido, como el abm por consola.
+    fn subscribe_to_admin_topic(
+        &self,
+        mqtt_client: Arc<Mutex<MQTTClient>>,
         cameras_tx: Sender<Vec<u8>>,
+        exit_tx: Sender<bool>,
     ) {
-        let mut logic = CamerasLogic::new(
-            cameras.clone(),
-            cameras_tx.clone(),
+        let topic = AppsMqttTopics::CameraAdminTopic.to_str();
+        let abm = Mutex::new(ABMCameras::new(
+            self.cameras.clone(),
+            cameras_tx,
+            exit_tx,
             self.logger.clone_ref(),
-        );
+        )
+        .with_neighbors(self.neighbors));
+        let self_clone = self.clone_ref();
+        let mqtt_client_c = mqtt_client.clone();
+        let handler = move |msg: PublishMessage| {
+            self_clone.receive_message_from_admin_topic(msg, &abm, &mqtt_client_c)
+        };
+
+        if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
+            match mqtt_client_lock.mqtt_subscribe_with_handler(topic, self.qos, handler) {
+                Ok(_) => self.logger.log(format!("Subscripto a topic: {:?}", topic)),
+                Err(e) => self.logger.log(format!("Error al subscribirse: {:?}", e)),
+            };
+        }
+    }
+
+    /// Recibe un comando del topic `cam-admin` y lo aplica. Si es inválido o no se puede aplicar, lo logguea.
+    fn receive_message_from_admin_topic(
+        &self,
+        msg: PublishMessage,
+        abm: &Mutex<ABMCameras>,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+    ) {
+        let res = CameraAdminCommand::from_bytes(msg.get_payload()).and_then(|command| match command {
+            CameraAdminCommand::AnalyzeCoverage => self.publish_coverage_gaps(mqtt_client),
+            command => match abm.lock() {
+                Ok(mut abm) => abm.apply_command(command),
+                Err(_) => Err(Error::new(ErrorKind::Other, "Error al tomar lock de ABMCameras.")),
+            },
+        });
+        if let Err(e) = res {
+            self.logger.log(format!("Error al aplicar comando de abm de cámaras: {:?}.", e));
+        }
+    }
 
-        for msg in rx {
-            if let Ok(incident) = Incident::from_bytes(msg.get_payload()) {
-                self.logger.log(format!("Inc recibido: {:?}", incident));
+    /// Busca las zonas de la región de vigilancia que no cubre ninguna cámara, las logguea, y publica el reporte
+    /// por el topic `cam-gaps` para que los operadores puedan reubicar cámaras. Si son demasiadas para un publish,
+    /// se publican las más grandes.
+    fn publish_coverage_gaps(&self, mqtt_client: &Arc<Mutex<MQTTClient>>) -> Result<(), Error> {
+        let Some(region) = self.region else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "No hay región de vigilancia configurada (property region).",
+            ));
+        };
+        let report = match self.cameras.lock() {
+            Ok(cams) => find_coverage_gaps(&region, &cams)?,
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Error al tomar lock de cámaras.")),
+        };
+        self.logger.log(format!(
+            "Análisis de cobertura: {} zonas sin cobertura, {:.1}% de la región.",
+            report.get_gaps().len(),
+            report.get_uncovered_ratio() * 100.0
+        ));
+        for gap in report.get_gaps() {
+            self.logger.log(format!(
+                "Zona sin cobertura de {:.0} m2, entre {} y {}.",
+                gap.get_area(),
+                gap.get_min(),
+                gap.get_max()
+            ));
+        }
+        match mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let topic = AppsMqttTopics::CameraCoverageTopic.to_str();
+                let published = report.with_largest_gaps(PublishMessage::max_payload_len(topic));
+                if published.get_gaps().len() < report.get_gaps().len() {
+                    self.logger.log(format!(
+                        "Se publican solamente las {} zonas sin cobertura más grandes.",
+                        published.get_gaps().len()
+                    ));
+                }
+                mqtt_client.mqtt_publish(topic, &published.to_bytes(), self.qos)?;
+                Ok(())
+            }
+            Err(_) => Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        }
+    }
+
+    /// Utiliza la librería MQTT para publicar las cámaras que recibe por `rx`. Junta las que llegan casi a la vez:
+    /// si cambió una sola la publica por `cam`, y si cambiaron varias (ie al inicio, o por un incidente grande)
+    /// las publica juntas en un snapshot por `cam-snapshot`. No publica las que no cambiaron desde la última vez.
+    fn publish_cameras(&self, mqtt_client: Arc<Mutex<MQTTClient>>, rx: Receiver<Vec<u8>>) {
+        let camera_codec = self.topic_codecs.for_topic(&AppsMqttTopics::CameraTopic);
+        let snapshot_codec = self.topic_codecs.for_topic(&AppsMqttTopics::CameraSnapshotTopic);
+        let batcher = BatchSettings::new(usize::MAX, CAMERAS_BATCH_WAIT, 1);
+        let mut published: HashMap<u8, Camera> = HashMap::new();
+        while let Some(batch) = batcher.collect_batch(&rx) {
+            // Las cámaras llegan en binario
+            let mut cameras = vec![];
+            for cam_bytes in batch {
+                match Camera::from_bytes(&cam_bytes) {
+                    Ok(camera) => cameras.push(camera),
+                    Err(e) => self.logger.log(format!("Error al convertir la cámara: {:?}", e)),
+                }
+            }
+            let snapshot = build_snapshot(&mut published, cameras);
+            match snapshot.get_cameras() {
+                [] => continue,
+                [change] if !snapshot.is_full() => {
+                    let topic = AppsMqttTopics::CameraTopic.to_str();
+                    self.publish_payload(&mqtt_client, topic, camera_codec.encode(change.get_camera()));
+                }
+                _ => {
+                    // Un snapshot con muchas cámaras (ie el inicial) no entra en un solo publish.
+                    let topic = AppsMqttTopics::CameraSnapshotTopic.to_str();
+                    match snapshot.split(PublishMessage::max_payload_len(topic), &snapshot_codec) {
+                        Ok(parts) => {
+                            for part in parts {
+                                self.publish_payload(&mqtt_client, topic, snapshot_codec.encode(&part));
+                            }
+                        }
+                        Err(e) => self.logger.log(format!("Error al dividir el snapshot de cámaras: {:?}", e)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Publica por `topic` las cámaras ya convertidas a `payload`, o logguea el error al convertirlas.
+    fn publish_payload(&self, mqtt_client: &Arc<Mutex<MQTTClient>>, topic: &str, payload: Result<Vec<u8>, Error>) {
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(e) => {
+                self.logger.log(format!("Error al convertir las cámaras: {:?}", e));
+                return;
+            }
+        };
+        if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
+            let res_publish = mqtt_client_lock.mqtt_publish(topic, &payload, self.qos);
+            match res_publish {
+                Ok(publish_msg) => {
+                    self.logger.log(format!("Enviado msj: {:?}", publish_msg));
+                }
+                Err(e) => {
+                    println!("Error al hacer publish {:?}", e);
+                    self.logger.log(format!("Error al hacer publish {:?}", e));
+                }
+            };
+        }
+    }
+
+    /// Recibe un mensaje del topic de incidentes, y delega el procesamiento a `CamerasLogic`.
+    fn receive_message_from_incident_topic(&self, msg: PublishMessage, logic: &Mutex<CamerasLogic>) {
+        let Ok(incident) = decode_payload::<Incident>(&msg.get_payload()) else {
+            return;
+        };
+        self.logger.log(format!("Inc recibido: {:?}", incident));
+        match logic.lock() {
+            Ok(mut logic) => {
                 if let Err(e) = logic.manage_incident(incident) {
                     self.logger.log(format!("Error al procesar incidente: {:?}.", e));
                 }
             }
+            Err(_) => self.logger.log("Error al tomar lock de CamerasLogic.".to_string()),
         }
-
-        there_are_no_more_publish_msgs(&self.logger);
     }
 
     fn clone_ref(&self) -> Self {
@@ -258,6 +610,13 @@ impl SistemaCamaras {
             cameras: self.cameras.clone(),
             qos: self.qos,
             logger: self.logger.clone_ref(),
+            topic_codecs: self.topic_codecs,
+            neighbors: self.neighbors,
+            region: self.region,
+            health_monitor: self.health_monitor.clone(),
+            state_store: self.state_store.clone(),
+            incident_dedup: self.incident_dedup.clone(),
+            schedule: self.schedule.clone(),
         }
     }
 }
@@ -265,14 +624,16 @@ impl SistemaCamaras {
 fn spawn_exit_when_asked_thread(
     mqtt_client_sh: Arc<Mutex<MQTTClient>>,
     exit_rx: Receiver<bool>,
-    exit_detector_tx: Sender<()>,
+    exit_txs: Vec<Sender<()>>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         exit_when_asked(mqtt_client_sh, exit_rx);
-        println!("Hilo exit recibe pedido de exit. Por propagarlo al detector...");
-        if let Err(e) = exit_detector_tx.send(()) {
-            //logger.log(format!("Error al enviar por exit_detector_tx: {:?}.", e)); // podría recibir un logger quizás
-            println!("Error al enviar por exit_detector_tx: {:?}.", e);
+        println!("Hilo exit recibe pedido de exit. Por propagarlo al detector y a la vigilancia de latidos...");
+        for exit_tx in exit_txs {
+            if let Err(e) = exit_tx.send(()) {
+                //logger.log(format!("Error al enviar por exit_tx: {:?}.", e)); // podría recibir un logger quizás
+                println!("Error al enviar por exit_tx: {:?}.", e);
+            }
         }
         println!("Hilo exit: Listo.");
     })
@@ -1,19 +1,20 @@
 use std::{
-    collections::HashMap, io::{stdin, stdout, Error, Write}, sync::{
+    collections::HashMap, io::{stdin, stdout, Error, ErrorKind, Write}, sync::{
         mpsc::Sender,
         Arc, Mutex,
     }
 };
 
-use crate::logging::string_logger::StringLogger;
+use crate::{apps::geo_position::GeoPosition, logging::string_logger::StringLogger};
 
-use super::camera::Camera;
+use super::{camera::{parse_range, Camera}, camera_admin_command::CameraAdminCommand, neighbors::Neighbors};
 
 pub struct ABMCameras {
     cameras: Arc<Mutex<HashMap<u8, Camera>>>,
     camera_tx: Sender<Vec<u8>>,
     exit_tx: Sender<bool>,
     logger: StringLogger,
+    neighbors: Neighbors, // cómo recalcula las lindantes al agregar, modificar o eliminar cámaras.
 }
 
 impl ABMCameras {
@@ -29,9 +30,16 @@ impl ABMCameras {
             camera_tx,
             exit_tx,
             logger,
+            neighbors: Neighbors::default(),
         }
     }
 
+    /// Indica cómo recalcular las cámaras lindantes; por defecto, con `DEFAULT_BORDER_DISTANCE`.
+    pub fn with_neighbors(mut self, neighbors: Neighbors) -> Self {
+        self.neighbors = neighbors;
+        self
+    }
+
     /// Pone en funcionamiento el menú del abm para cámaras.
     /// Como cameras es un arc, quien haya llamado a esta función podrá ver reflejados los cambios.
     pub fn run(&mut self) {
@@ -48,7 +56,8 @@ impl ABMCameras {
                 }
                 "2" => self.show_cameras_abm(),
                 "3" => self.delete_camera_abm(),
-                "4" => {
+                "4" => self.modify_camera_range_abm(),
+                "5" => {
                     self.exit_program_abm();
                     break;
                 }
@@ -66,7 +75,8 @@ impl ABMCameras {
         1. Agregar cámara
         2. Mostrar cámaras
         3. Eliminar cámara
-        4. Salir
+        4. Modificar rango de cámara
+        5. Salir
         Ingrese una opción:"
         );
     }
@@ -75,7 +85,10 @@ impl ABMCameras {
     /// Procesa la cámara y la envía entre hilos para que sistema cámaras pueda publicarla.
     fn create_camera_abm(&mut self) {
         if let Ok(camera) = self.create_camera(){
-            self.process_and_send_camera(camera);
+            match self.process_and_send_camera(camera) {
+                Ok(_) => println!("Cámara agregada con éxito.\n"),
+                Err(e) => println!("Error al agregar la cámara: {}\n", e),
+            }
         }
     }
 
@@ -83,11 +96,10 @@ impl ABMCameras {
     fn create_camera(&self) -> Result<Camera, Error> {
 
         let id = self.read_input_and_parse_to_u8("el ID")?;
-        let latitude = self.read_input_and_parse_to_f64("la latitud")?;
-        let longitude = self.read_input_and_parse_to_f64("la longitud")?;
-        let range = self.read_input_and_parse_to_u8("el rango")?;
+        let position = self.read_input_position()?;
+        let range = self.read_input_range("el rango")?;
 
-        Ok(Camera::new(id, latitude, longitude, range))
+        Camera::new_in_meters(id, position, range)
     }
 
     /// Lee el input de teclado y devuelve el valor parseado a u8.
@@ -130,6 +142,35 @@ impl ABMCameras {
         Err(Error::new(std::io::ErrorKind::InvalidInput, "Error al parsear f64 (no debería darse)."))
     }
 
+    /// Lee la latitud y la longitud por teclado y devuelve la posición.
+    /// En caso de que no sea una posición válida, muestra el motivo y repregunta hasta obtener una válida.
+    fn read_input_position(&self) -> Result<GeoPosition, Error> {
+        loop {
+            let latitude = self.read_input_and_parse_to_f64("la latitud")?;
+            let longitude = self.read_input_and_parse_to_f64("la longitud")?;
+            match GeoPosition::new(latitude, longitude) {
+                Ok(position) => return Ok(position),
+                Err(e) => println!("Error: {} Intente nuevamente.", e),
+            }
+        }
+    }
+
+    /// Lee el input de teclado y devuelve el rango en metros (ver `parse_range`).
+    /// En caso de input inválido, muestra el motivo y repregunta hasta obtener un input válido.
+    fn read_input_range(&self, pm_name: &str) -> Result<f64, Error> {
+        let mut res = parse_range(&self.get_input_abm(Some(
+            format!("Ingrese {} de la cámara, en metros (ie 250m) o cuadras (ie 2): ", pm_name).as_str(),
+        )));
+
+        while let Err(e) = res {
+            res = parse_range(&self.get_input_abm(Some(
+                format!("Error: {} Ingrese {} de la cámara: ", e, pm_name).as_str(),
+            )));
+        }
+
+        res
+    }
+
     /// Obtiene el input por teclado.
     fn get_input_abm(&self, prompt: Option<&str>) -> String {
         if let Some(p) = prompt {
@@ -143,26 +184,71 @@ impl ABMCameras {
         input.trim().to_string()
     }
 
-    /// Procesa una nueva cámara (la inserta en el hashmap de cameras, maneja las lindantes), y la envía por un
-    /// channel para que desde el rx el sistema cámaras le pueda hacer publish. Además, logguea la operación.
-    fn process_and_send_camera(&mut self, new_camera: Camera) {
-        match self.cameras.lock() {
-            Ok(mut cams) => {
-                // Recorre las cámaras ya existentes, agregando la nueva cámara como lindante de la que corresponda y viceversa, terminando la creación
-                for camera in cams.values_mut() {
-                    camera.mutually_add_if_bordering(&mut new_camera.clone());
-                }
-                self.logger.log(format!("Sistema-Camaras: envió cámara: {:?}", new_camera));
-                // Envía la nueva cámara por tx, para ser publicada por el otro hilo
-                if self.camera_tx.send(new_camera.to_bytes()).is_err() {
-                    println!("Error al enviar cámara por tx desde hilo abm.");
-                }
-                // Guarda la nueva cámara
-                cams.insert(new_camera.get_id(), new_camera);
-                println!("Cámara agregada con éxito.\n");
+    /// Aplica un comando del abm recibido por el topic `cam-admin`, igual que si se lo hubiera ingresado por consola.
+    pub fn apply_command(&mut self, command: CameraAdminCommand) -> Result<(), Error> {
+        self.logger.log(format!("Sistema-Camaras: recibido comando de abm: {:?}", command));
+        match command {
+            CameraAdminCommand::Add { id, position, range } => {
+                self.process_and_send_camera(Camera::new_in_meters(id, position, range)?)
             }
-            Err(e) => println!("Error tomando lock en agregar cámara abm, {:?}.\n", e),
+            CameraAdminCommand::ModifyRange { id, range } => self.modify_camera_range(id, range),
+            CameraAdminCommand::Delete(id) => self.delete_camera(id),
+            // No modifica las cámaras; lo resuelve Sistema Cámaras, que conoce la región de vigilancia.
+            CameraAdminCommand::AnalyzeCoverage => Err(Error::new(
+                ErrorKind::Unsupported,
+                "El análisis de cobertura no es una operación del abm.",
+            )),
+        }
+    }
+
+    /// Procesa una nueva cámara (la inserta en el hashmap de cameras, maneja las lindantes), y la envía por un
+    /// channel para que desde el rx el sistema cámaras le pueda hacer publish, junto con las lindantes que cambiaron.
+    /// Además, logguea la operación. Devuelve error si ya existe una cámara con su id.
+    fn process_and_send_camera(&mut self, mut new_camera: Camera) -> Result<(), Error> {
+        let mut cams = self.lock_cameras()?;
+        if cams.contains_key(&new_camera.get_id()) {
+            return Err(Error::new(ErrorKind::AlreadyExists, "La cámara ya existe."));
+        }
+        // Recorre las cámaras ya existentes, agregando la nueva cámara como lindante de la que corresponda y viceversa, terminando la creación
+        let changed = self.neighbors.update(&mut cams, &mut new_camera);
+        // Envía la nueva cámara por tx, para ser publicada por el otro hilo
+        self.send_camera_bytes(&new_camera, &self.camera_tx);
+        self.send_changed_cameras(&cams, &changed);
+        // Guarda la nueva cámara
+        cams.insert(new_camera.get_id(), new_camera);
+        Ok(())
+    }
+
+    /// Opción Modificar rango de cámara, del abm.
+    fn modify_camera_range_abm(&mut self) {
+        let Ok(id) = self.read_input_and_parse_to_u8("el ID") else {
+            return;
+        };
+        let Ok(range) = self.read_input_range("el nuevo rango") else {
+            return;
+        };
+        match self.modify_camera_range(id, range) {
+            Ok(_) => println!("Rango modificado con éxito.\n"),
+            Err(e) => println!("Error al modificar el rango: {}\n", e),
+        }
+    }
+
+    /// Modifica el rango de la cámara del id recibido, recalcula sus lindantes, y la envía por tx para que rx
+    /// haga publish, junto con las lindantes que cambiaron.
+    fn modify_camera_range(&mut self, id: u8, range: f64) -> Result<(), Error> {
+        let mut cams = self.lock_cameras()?;
+        let Some(mut camera) = cams.remove(&id) else {
+            return Err(camera_not_found());
+        };
+        if let Err(e) = camera.set_range(range) {
+            cams.insert(id, camera);
+            return Err(e);
         }
+        let changed = self.neighbors.update(&mut cams, &mut camera);
+        self.send_camera_bytes(&camera, &self.camera_tx);
+        self.send_changed_cameras(&cams, &changed);
+        cams.insert(id, camera);
+        Ok(())
     }
 
     /// Opción Mostrar cámaras del abm. Lista todas las cámaras existentes.
@@ -186,38 +272,43 @@ impl ABMCameras {
     /// Elimina la cámara indicada, manejando sus lindantes, y la envía por tx para que rx haga publish.
     fn delete_camera_abm(&self) {
         if let Ok(id) = self.read_input_and_parse_to_u8("el ID") {
-            self.delete_camera(id);
+            match self.delete_camera(id) {
+                Ok(_) => println!("Cámara eliminada con éxito.\n"),
+                Err(e) => println!("Error al eliminar la cámara: {}\n", e),
+            }
         }
     }
 
     /// Elimina a la cámara del id recibido.
-    fn delete_camera(&self, id: u8) {
-        match self.cameras.lock() {
-            Ok(mut cams) => {
-                if let Some(mut camera_to_delete) = cams.remove(&id) {
-                    if camera_to_delete.is_not_deleted() {
-                        camera_to_delete.delete_camera();
-
-                        // Recorre las cámaras ya existentes, eliminando la cámara a eliminar como lindante de la que corresponda, terminando la eliminación
-                        for camera in cams.values_mut() {
-                            camera.remove_from_list_if_bordering(&mut camera_to_delete);
-                        }
-
-                        // Envía por el tx la cámara a eliminar para que se publique desde el otro hilo
-                        // (con eso es suficiente. Si bien se les eliminó una lindante, no es necesario publicar el cambio
-                        // de las demás ya que eso solo es relevante para sistema camaras)
-                        if self.camera_tx.send(camera_to_delete.to_bytes()).is_err() {
-                            println!("Error al enviar cámara por tx desde hilo abm.");
-                        } else {
-                            println!("Cámara eliminada con éxito.\n");
-                        }
-                    };
-                } else {
-                    println!("La cámara no existe.\n");
-                }
-            }
-            Err(e) => println!("Error tomando lock baja abm, {:?}.\n", e),
+    fn delete_camera(&self, id: u8) -> Result<(), Error> {
+        let mut cams = self.lock_cameras()?;
+        let Some(mut camera_to_delete) = cams.remove(&id) else {
+            return Err(camera_not_found());
         };
+        if camera_to_delete.is_not_deleted() {
+            camera_to_delete.delete_camera();
+
+            // Recorre las cámaras ya existentes, eliminando la cámara a eliminar como lindante de la que corresponda, terminando la eliminación
+            let changed = self.neighbors.remove(&mut cams, id);
+
+            // Envía por el tx la cámara a eliminar para que se publique desde el otro hilo, y las que dejaron de tenerla como lindante
+            self.send_camera_bytes(&camera_to_delete, &self.camera_tx);
+            self.send_changed_cameras(&cams, &changed);
+        };
+        Ok(())
+    }
+
+    /// Envía por tx, para que rx haga publish, las cámaras de ids `changed`.
+    fn send_changed_cameras(&self, cams: &HashMap<u8, Camera>, changed: &[u8]) {
+        for camera in changed.iter().filter_map(|id| cams.get(id)) {
+            self.send_camera_bytes(camera, &self.camera_tx);
+        }
+    }
+
+    fn lock_cameras(&self) -> Result<std::sync::MutexGuard<'_, HashMap<u8, Camera>>, Error> {
+        self.cameras
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::Other, "Error al tomar lock de cámaras en abm."))
     }
 
     /// Opción Salir, del abm.
@@ -255,6 +346,10 @@ impl ABMCameras {
     }
 }
 
+fn camera_not_found() -> Error {
+    Error::new(ErrorKind::NotFound, "La cámara no existe.")
+}
+
 #[cfg(test)]
 mod test {
     use std::{
@@ -262,10 +357,20 @@ mod test {
         sync::{mpsc, Arc, Mutex},
     };
 
-    use crate::{apps::sist_camaras::camera::Camera, logging::string_logger::StringLogger};
+    use crate::{
+        apps::{
+            geo_position::GeoPosition,
+            sist_camaras::{camera::Camera, camera_admin_command::CameraAdminCommand},
+        },
+        logging::string_logger::StringLogger,
+    };
 
     use super::ABMCameras;
 
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
     fn create_abm() -> ABMCameras {
         // Unos tx irrelevantes, para pasar al new de abm
         // (es necesario conservar las variables de rx en el test de todas formas, para que no se cierre el channel antes del assert)
@@ -289,8 +394,8 @@ mod test {
 
         // Se agrega la cámara
         let new_camera_id = 1;
-        let camera = Camera::new(new_camera_id, -34.0, -58.0, 5);
-        abm.process_and_send_camera(camera);
+        let camera = Camera::new(new_camera_id, position(-34.0, -58.0), 5);
+        abm.process_and_send_camera(camera).unwrap();
 
         // Se busca la cámara recién agregada
         let mut is_new_cam_stored = false;
@@ -308,11 +413,11 @@ mod test {
 
         // Se agrega la cámara
         let camera_to_remove_id = 1;
-        let camera = Camera::new(camera_to_remove_id, -34.0, -58.0, 5);
-        abm.process_and_send_camera(camera);
+        let camera = Camera::new(camera_to_remove_id, position(-34.0, -58.0), 5);
+        abm.process_and_send_camera(camera).unwrap();
 
         // Ahora se la elimina
-        abm.delete_camera(camera_to_remove_id);
+        abm.delete_camera(camera_to_remove_id).unwrap();
 
         // Se busca la cámara recién eliminada
         let mut is_cam_to_remove_stored = false;
@@ -322,4 +427,45 @@ mod test {
         // La cámara nueva se ha agregado a cameras
         assert!(!is_cam_to_remove_stored);
     }
+
+    #[test]
+    fn test_3_abm_por_comandos_recalcula_lindantes_y_envia_las_camaras_que_cambiaron() {
+        let (camera_tx, camera_rx) = mpsc::channel();
+        let (exit_tx, _exit_rx) = mpsc::channel();
+        let (string_logger_tx, _string_logger_rx) = mpsc::channel();
+        let cameras = Arc::new(Mutex::new(HashMap::new()));
+        let mut abm = ABMCameras::new(cameras.clone(), camera_tx, exit_tx, StringLogger::new(string_logger_tx));
+
+        // Se agregan dos cámaras lindantes: al agregar la segunda, se envía también la primera, que cambió
+        let add_1 = CameraAdminCommand::from_bytes(b"add 1 -34.6 -58.4 5".to_vec()).unwrap();
+        let add_2 = CameraAdminCommand::from_bytes(b"add 2 -34.601 -58.401 5".to_vec()).unwrap();
+        abm.apply_command(add_1).unwrap();
+        abm.apply_command(add_2).unwrap();
+        let sent: Vec<u8> = camera_rx
+            .try_iter()
+            .map(|bytes| Camera::from_bytes(&bytes).unwrap().get_id())
+            .collect();
+        assert_eq!(sent, vec![1, 2, 1]);
+        assert!(abm.apply_command(add_1).is_err());
+
+        // Se modifica el rango de la segunda, que se vuelve a enviar
+        assert!(abm.apply_command(CameraAdminCommand::ModifyRange { id: 2, range: 0.0 }).is_err());
+        assert!(camera_rx.try_recv().is_err());
+        abm.apply_command(CameraAdminCommand::ModifyRange { id: 2, range: 1200.0 }).unwrap();
+        let modified = Camera::from_bytes(&camera_rx.try_recv().unwrap()).unwrap();
+        assert_eq!(modified.get_range(), 1200.0);
+        if let Ok(mut cams) = cameras.lock() {
+            assert_eq!(cams.get_mut(&1).unwrap().get_bordering_cams(), &vec![2]);
+            assert_eq!(cams.get_mut(&2).unwrap().get_bordering_cams(), &vec![1]);
+        }
+
+        // Al eliminarla, se envía también la que la tenía como lindante
+        abm.apply_command(CameraAdminCommand::Delete(2)).unwrap();
+        let sent: Vec<u8> = camera_rx
+            .try_iter()
+            .map(|bytes| Camera::from_bytes(&bytes).unwrap().get_id())
+            .collect();
+        assert_eq!(sent, vec![2, 1]);
+        assert!(abm.apply_command(CameraAdminCommand::Delete(2)).is_err());
+    }
 }
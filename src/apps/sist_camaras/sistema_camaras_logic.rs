@@ -2,12 +2,15 @@ use std::{
     collections::HashMap,
     io::{Error, ErrorKind},
     sync::{mpsc::Sender, MutexGuard},
+    time::Instant,
 };
 
 use crate::{apps::incident_data::incident::Incident, logging::string_logger::StringLogger};
 
 use crate::apps::sist_camaras::{
     camera::Camera,
+    camera_state_store::CameraStateStore,
+    incident_dedup::{DedupOutcome, IncidentDeduplicator},
     types::{hashmap_incs_type::HashmapIncsType, shareable_cameras_type::ShCamerasType},
 };
 
@@ -17,6 +20,8 @@ pub struct CamerasLogic {
     incs_being_managed: HashmapIncsType,
     cameras_tx: Sender<Vec<u8>>,
     logger: StringLogger,
+    state_store: Option<CameraStateStore>, // dónde guarda el estado luego de procesar cada incidente.
+    dedup: IncidentDeduplicator, // une los incidentes duplicados, para no activar las cámaras dos veces.
 }
 
 impl CamerasLogic {
@@ -27,27 +32,96 @@ impl CamerasLogic {
             incs_being_managed: HashMap::new(),
             cameras_tx,
             logger,
+            state_store: None,
+            dedup: IncidentDeduplicator::default(),
         }
     }
 
+    /// Parte de los incidentes que ya se estaban siguiendo antes de reiniciar, y de qué cámaras los siguen.
+    pub fn with_incs_being_managed(mut self, incs_being_managed: HashmapIncsType) -> Self {
+        self.incs_being_managed = incs_being_managed;
+        self
+    }
+
+    /// Indica dónde guardar el estado de las cámaras luego de procesar cada incidente; por defecto, no se guarda.
+    pub fn with_state_store(mut self, state_store: Option<CameraStateStore>) -> Self {
+        self.state_store = state_store;
+        self
+    }
+
+    /// Indica cómo unir los incidentes duplicados; por defecto, los recibidos a menos de
+    /// `DEFAULT_DEDUP_DISTANCE_METERS` y `DEFAULT_DEDUP_WINDOW_SECS` de otro.
+    pub fn with_deduplicator(mut self, dedup: IncidentDeduplicator) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
     /// Procesa un Incidente recibido.
     pub fn manage_incident(&mut self, incident: Incident) -> Result<(), Error>{
+        let Some(incident) = self.deduplicate(incident) else {
+            return Ok(());
+        };
         // Proceso los incidentes
         if !self.incs_being_managed.contains_key(&incident.get_info()) {
-            self.process_first_time_incident(incident)
+            self.process_first_time_incident(incident)?;
         } else {
-            self.process_known_incident(incident)
+            self.process_known_incident(incident)?;
+        }
+        self.save_state()
+    }
+
+    /// Busca si el incidente es un duplicado de otro ya recibido. Devuelve el incidente a procesar, con la info del
+    /// primero de los incidentes unidos; o `None` si no hay que procesarlo, porque las cámaras ya lo siguen.
+    fn deduplicate(&mut self, incident: Incident) -> Option<Incident> {
+        let outcome = self.dedup.deduplicate(
+            incident.get_info(),
+            incident.get_position(),
+            incident.is_closed(),
+            Instant::now(),
+        );
+        match outcome {
+            DedupOutcome::Process(info) if info == incident.get_info() => Some(incident),
+            DedupOutcome::Process(info) => Some(incident.with_info(info)),
+            DedupOutcome::Merged(info) => {
+                self.logger.log(format!(
+                    "Se une el incidente {:?} al {:?}, en la misma posición.",
+                    incident.get_info(),
+                    info
+                ));
+                None
+            }
+            DedupOutcome::StillPending(info) => {
+                self.logger.log(format!(
+                    "Se resolvió el incidente {:?}, pero las cámaras siguen al {:?} hasta que se resuelvan todos los unidos a él.",
+                    incident.get_info(),
+                    info
+                ));
+                None
+            }
+        }
+    }
+
+    /// Guarda el estado de las cámaras, si se configuró dónde.
+    fn save_state(&self) -> Result<(), Error> {
+        let Some(state_store) = &self.state_store else {
+            return Ok(());
+        };
+        match self.cameras.lock() {
+            Ok(cams) => state_store.save(&cams),
+            Err(_) => Err(Error::new(ErrorKind::Other, "Error al tomar lock en save_state.")),
         }
     }
 
     // Aux: (condición "hasta que" del enunciado).
     /// Procesa un incidente cuando un incidente con ese mismo id ya fue recibido anteriormente.
-    /// Si su estado es resuelto, vuelve el estado de la/s cámara/s que lo atendían, a ahorro de energía.
+    /// Si se resolvió o se canceló, vuelve el estado de la/s cámara/s que lo atendían, a ahorro de energía.
+    /// Los demás cambios de estado no afectan a las cámaras.
     fn process_known_incident(&mut self, inc: Incident) -> Result<(), Error> {
-        if inc.is_resolved() {
+        if inc.is_closed() {
             self.logger.log(format!(
-                "Recibo el inc {} de nuevo, ahora con estado resuelto.",
-                inc.get_id()
+                "Recibo el inc {} de nuevo, ahora con estado {}.",
+                inc.get_id(),
+                inc.get_state().to_str()
             ));
             // Busco la/s cámara/s que atendían este incidente
             if let Some(cams_managing_inc) = self.incs_being_managed.get(&inc.get_info()) {
@@ -98,7 +172,7 @@ impl CamerasLogic {
     /// Para cada cámara ve si inc.pos está dentro de alcance de dicha cámara o sus lindantes,
     /// en caso afirmativo, se encarga de lo necesario para que la cámara y sus lindanes cambien su estado a activo.
     fn process_first_time_incident(&mut self, inc: Incident) -> Result<(), Error> {
-        if !inc.is_resolved() {
+        if !inc.is_closed() {
             // inc no resuelto
             match self.cameras.lock() {
                 Ok(mut cams) => {
@@ -132,6 +206,8 @@ impl CamerasLogic {
     }
 
     /// Devuelve un vector de u8 con los ids de todas las cámaras que darán seguimiento al incidente `inc`.
+    /// Las cámaras rotas (en falla o desconectadas) no lo siguen; pero si el incidente está en su rango, sus
+    /// lindantes sí, para cubrir la zona.
     fn get_id_of_cams_that_will_change_state_to_active(
         &self,
         cams: &mut MutexGuard<'_, HashMap<u8, Camera>>,
@@ -157,6 +233,7 @@ impl CamerasLogic {
                     .log(format!(" la cám queda: cam id y lista de incs: {:?}", info));
             }
         }
+        cameras_that_follow_inc.retain(|cam_id| cams.get(cam_id).is_some_and(Camera::is_operational));
         cameras_that_follow_inc
     }
 
@@ -1,16 +1,23 @@
 use std::io::Error;
 
 use rustx::logging::string_logger::StringLogger;
-use rustx::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
 use rustx::mqtt::mqtt_utils::will_message_utils::{app_type::AppType, will_content::WillContent};
 use rustx::{
     apps::{
-        common_clients::{get_app_will_topic, get_broker_address, join_all_threads},
-        sist_camaras::{manage_stored_cameras::create_cameras, sistema_camaras::SistemaCamaras},
+        common_clients::{get_app_will_topic, get_broker_address, get_topic_codecs, join_all_threads},
+        sist_camaras::{
+            camera_health::CameraHealthMonitor, camera_schedule::CameraSchedule,
+            camera_state_store::CameraStateStore,
+            coverage::SurveillanceRegion, incident_dedup::IncidentDeduplicator,
+            manage_stored_cameras::create_cameras_with_neighbors, neighbors::Neighbors,
+            sistema_camaras::SistemaCamaras,
+        },
     },
-    mqtt::client::mqtt_client::MQTTClient,
+    mqtt::client::mqtt_client_builder::MqttClientBuilder,
 };
 
+const PROPERTIES_FILE: &str = "sistema_camaras.properties";
+
 fn get_formatted_app_id() -> String {
     String::from("Sistema-Camaras")
 }
@@ -21,7 +28,14 @@ fn get_app_will_msg_content() -> WillContent {
 
 fn main() -> Result<(), Error> {
     let broker_addr = get_broker_address();
-    let cameras = create_cameras();
+    let neighbors = Neighbors::from_file(PROPERTIES_FILE)?;
+    let cameras = create_cameras_with_neighbors(&neighbors)?;
+    let topic_codecs = get_topic_codecs(PROPERTIES_FILE)?;
+    let region = SurveillanceRegion::from_file(PROPERTIES_FILE)?;
+    let health_monitor = CameraHealthMonitor::from_file(PROPERTIES_FILE)?;
+    let state_store = CameraStateStore::from_file(PROPERTIES_FILE)?;
+    let incident_dedup = IncidentDeduplicator::from_file(PROPERTIES_FILE)?;
+    let schedule = CameraSchedule::from_file(PROPERTIES_FILE)?;
 
     // Se crean y configuran ambos extremos del string logger
     let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id());
@@ -29,16 +43,23 @@ fn main() -> Result<(), Error> {
     let qos = 1; // []
     let client_id = get_formatted_app_id();
     let will_msg_content = get_app_will_msg_content();
-    let will_msg_data =
-        WillMessageData::new(will_msg_content.to_str(), get_app_will_topic(), qos, 1);
+    let mqtt_client_builder = MqttClientBuilder::new(&client_id)
+        .will(&get_app_will_topic(), &will_msg_content.to_str(), qos, true);
 
-    match MQTTClient::mqtt_connect_to_broker(client_id, &broker_addr, Some(will_msg_data), logger.clone_ref()) {
-        Ok((mqtt_client, publish_msg_rx, handle)) => {
+    match mqtt_client_builder.connect(&broker_addr, logger.clone_ref()) {
+        Ok((mqtt_client, _publish_msg_rx, handle)) => {
             println!("Conectado al broker MQTT.");
             logger.log("Conectado al broker MQTT".to_string());
 
-            let mut sistema_camaras = SistemaCamaras::new(cameras, logger.clone_ref());
-            let mut handles = sistema_camaras.spawn_threads(publish_msg_rx, mqtt_client);
+            let mut sistema_camaras = SistemaCamaras::new(cameras, qos, logger.clone_ref())
+                .with_topic_codecs(topic_codecs)
+                .with_neighbors(neighbors)
+                .with_surveillance_region(region)
+                .with_camera_health(health_monitor)
+                .with_state_store(state_store)
+                .with_incident_dedup(incident_dedup)
+                .with_camera_schedule(schedule);
+            let mut handles = sistema_camaras.spawn_threads(mqtt_client);
 
             handles.push(handle);
             join_all_threads(handles);
@@ -1,28 +1,47 @@
-use std::{io::Error, sync::mpsc::{self, Sender}, thread::sleep, time::Duration};
+use std::{io::Error, sync::mpsc::{self, SyncSender}, time::{Duration, Instant}};
 
-use crate::{apps::sist_dron::calculations::{calculate_direction, calculate_distance}, logging::string_logger::StringLogger};
+use crate::{apps::geo_position::GeoPosition, logging::string_logger::StringLogger};
 
-use super::{data::Data, dron_current_info::DronCurrentInfo, dron_state::DronState, sist_dron_properties::SistDronProperties};
+use super::{charging_stations::{ChargingStation, ChargingStations, StationSelection}, data::Data, dron_current_info::DronCurrentInfo, dron_state::DronState, handoff::Handoff, sist_dron_properties::SistDronProperties};
+
+/// Cada cuánto se descuenta la batería consumida.
+const BATTERY_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+/// Cada cuánto se vuelve a buscar una estación libre, mientras las alcanzables están ocupadas.
+const WAIT_FOR_STATION_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub struct BatteryManager {
     current_data: Data,
     dron_properties: SistDronProperties,
+    charging_stations: ChargingStations,
     logger: StringLogger,
-    ci_tx: Sender<DronCurrentInfo>,
-    process_inc_tx: mpsc::Sender<()>
+    ci_tx: SyncSender<DronCurrentInfo>,
+    process_inc_tx: mpsc::Sender<()>,
+    // Posición y momento de la última actualización de la batería, para calcular el consumo desde entonces
+    last_position: Option<GeoPosition>,
+    last_update: Instant,
+    pending_consumption: f64, // consumo todavía no descontado, por ser menor a una unidad de batería.
+    handoff: Option<Handoff>, // para pedir que lo releven en el incidente que atiende, antes de ir a cargarse.
 }
 
 impl BatteryManager {
 
-    pub fn new(current_data: Data, dron_properties: SistDronProperties, logger: StringLogger, ci_tx: Sender<DronCurrentInfo>, process_inc_tx: mpsc::Sender<()>) -> Self {
-        Self { current_data, dron_properties, logger, ci_tx, process_inc_tx }
+    pub fn new(current_data: Data, dron_properties: SistDronProperties, charging_stations: ChargingStations, logger: StringLogger, ci_tx: SyncSender<DronCurrentInfo>, process_inc_tx: mpsc::Sender<()>) -> Self {
+        let last_update = current_data.now();
+        Self { current_data, dron_properties, charging_stations, logger, ci_tx, process_inc_tx, last_position: None, last_update, pending_consumption: 0.0, handoff: None }
+    }
+
+    /// Indica cómo pedir que lo releven si debe ir a cargarse mientras atiende un incidente (ver `Handoff`).
+    pub fn with_handoff(mut self, handoff: Handoff) -> Self {
+        self.handoff = Some(handoff);
+        self
     }
 
+    /// Actualiza la batería periódicamente, hasta que el dron se apague.
     pub fn run(&mut self) {
-        loop {
-            sleep(Duration::from_secs(5));
-            
+        while !self.current_data.is_shutting_down() {
+            self.current_data.sleep(BATTERY_UPDATE_INTERVAL);
+
             //Actualizar batería
             if let Err(e) = self.decrement_and_check_battery_lvl(){
                 self.logger.log(format!("Error en BatteryManager: {:?}.", e));
@@ -32,15 +51,17 @@ impl BatteryManager {
 
     fn decrement_and_check_battery_lvl(&mut self) -> Result<(), Error> {
                 
-        let min_battery = self.dron_properties.get_min_operational_battery_lvl(); //20
+        let min_battery = self.current_data.get_min_operational_battery_lvl()?; //20
 
-        let should_go_to_maintanence = self.current_data.decrement_and_check_battery_lvl(min_battery)?;
+        let should_go_to_maintanence = self.consume_battery(min_battery)?;
         
         if should_go_to_maintanence {
             self.logger
                 .log("Batería baja, debo ir a mantenimiento.".to_string());
-            // Se determina a qué posición volver después de cargarse
+            // Se determina a qué posición volver después de cargarse. Si atiende un incidente, primero pide que lo
+            // releven; si lo relevaron, ya no vuelve al incidente.
             let (position_to_go, state_to_set) = if self.current_data.get_state()? == DronState::ManagingIncident
+                && !self.hand_off_incident()?
             {
                 (self.current_data.get_current_position()?, DronState::ManagingIncident)
             } else {
@@ -49,50 +70,200 @@ impl BatteryManager {
                     DronState::ExpectingToRecvIncident,
                 )
             };
-            // Vuela a mantenimiento
+            // Vuela a la estación de carga; al llegar la ocupa, en estado de mantenimiento, mientras se carga
             self.current_data.set_state(DronState::Mantainance, true)?;
-            let maintanence_position = self.dron_properties.get_mantainance_position();
-            self.fly_to_mantainance(maintanence_position, true)?;
+            let station = self.choose_charging_station()?;
+            self.fly_to_mantainance(station.get_position(), true)?;
 
-            sleep(Duration::from_secs(3));
+            self.current_data.sleep(Duration::from_secs(3));
             self.recharge_battery()?;
             self.logger.log("Recargando batería al 100%.".to_string());
+            // El vuelo de regreso se descuenta de la batería recargada.
+            self.last_position = Some(station.get_position());
+            self.last_update = self.current_data.now();
+            self.pending_consumption = 0.0;
 
             // Vuelve a la posición correspondiente
             self.fly_to_mantainance(position_to_go, true)?;
             self.current_data.set_state(state_to_set, true)?;
+            self.publish_current_info()?;
             if let Err(e) = self.process_inc_tx.send(()) {
                 self.logger.log(format!("Error al enviar señal desde mantenimiento: {:?}.", e));
             }
+        } else if self.should_go_to_scheduled_maintenance()? {
+            self.go_to_scheduled_maintenance(min_battery)?;
         }
         Ok(())
     }
 
+    /// Devuelve si el desgaste acumulado alcanzó el umbral configurado y el dron está ocioso: esperando incidentes,
+    /// sin ninguno pendiente, y sin que lo hayan detenido o llamado a la base.
+    fn should_go_to_scheduled_maintenance(&self) -> Result<bool, Error> {
+        let schedule = self.dron_properties.get_maintenance_schedule();
+        if !schedule.is_enabled() || !self.current_data.needs_maintenance(&schedule)? {
+            return Ok(false);
+        }
+        Ok(self.current_data.get_state()? == DronState::ExpectingToRecvIncident
+            && self.current_data.get_inc_id_to_resolve()?.is_none()
+            && self.current_data.get_pending_incidents()?.is_empty()
+            && !self.current_data.is_on_standby())
+    }
+
+    /// Vuela al lugar de mantenimiento, permanece allí el tiempo configurado, y vuelve al centro de su rango con el
+    /// desgaste en cero. Mientras tanto está en estado `ScheduledMaintenance`, y no toma incidentes.
+    fn go_to_scheduled_maintenance(&mut self, min_battery: u8) -> Result<(), Error> {
+        let wear = self.current_data.get_wear()?;
+        self.logger.log(format!(
+            "Desgaste de {:.0} m y {:?} de vuelo, voy a mantenimiento programado.",
+            wear.get_flight_distance(),
+            wear.get_flight_time()
+        ));
+        self.current_data.set_state(DronState::ScheduledMaintenance, true)?;
+        self.publish_current_info()?;
+        self.fly_to_mantainance(self.dron_properties.get_mantainance_position(), true)?;
+        // El vuelo se descuenta de la batería; si queda por debajo del mínimo, se carga al terminar.
+        self.consume_battery(min_battery)?;
+
+        self.current_data
+            .sleep(self.dron_properties.get_maintenance_schedule().get_duration());
+        self.current_data.reset_wear()?;
+        self.logger.log("Mantenimiento programado terminado.".to_string());
+
+        self.fly_to_mantainance(self.dron_properties.get_range_center_position(), true)?;
+        self.consume_battery(min_battery)?;
+        self.current_data.set_state(DronState::ExpectingToRecvIncident, true)?;
+        self.publish_current_info()?;
+        if let Err(e) = self.process_inc_tx.send(()) {
+            self.logger.log(format!("Error al enviar señal desde mantenimiento: {:?}.", e));
+        }
+        Ok(())
+    }
+
+    /// Pide que otro dron lo releve en el incidente que atiende, y espera a que llegue. Devuelve si dejó el incidente.
+    fn hand_off_incident(&self) -> Result<bool, Error> {
+        let Some(handoff) = &self.handoff else {
+            return Ok(false);
+        };
+        self.logger.log("Pido que me releven en el incidente que atiendo.".to_string());
+        if !handoff.request_and_wait(&self.current_data)? {
+            self.logger.log("No llegó un reemplazo, vuelvo al incidente luego de cargarme.".to_string());
+            return Ok(false);
+        }
+        self.logger.log("Me relevaron en el incidente, ya no vuelvo a él.".to_string());
+        handoff.leave_incident(&self.current_data)?;
+        Ok(true)
+    }
+
+    /// Descuenta la batería consumida desde la última actualización, según la distancia volada y el tiempo
+    /// detenido en cada estado; y devuelve si quedó por debajo de `min_battery`.
+    fn consume_battery(&mut self, min_battery: u8) -> Result<bool, Error> {
+        let ci = self.current_data.get_current_info()?;
+        let position = ci.get_current_position();
+        let distance = self
+            .last_position
+            .map_or(0.0, |last_position| self.current_data.get_distance_model().distance(last_position, position));
+        let distance_in_meters = self
+            .last_position
+            .map_or(0.0, |last_position| last_position.distance_to(&position));
+        let now = self.current_data.now();
+        let elapsed = now.duration_since(self.last_update);
+        self.last_position = Some(position);
+        self.last_update = now;
+
+        // El desgaste cuenta la distancia volada, y el tiempo transcurrido si está volando.
+        let flight_time = if ci.get_flying_info().is_some() { elapsed } else { Duration::ZERO };
+        self.current_data.record_wear(distance_in_meters, flight_time)?;
+
+        self.pending_consumption += self.dron_properties.get_battery_model().consumption(
+            ci.get_state(),
+            ci.get_flying_info().is_some(),
+            distance,
+            elapsed,
+        );
+        // Se descuentan las unidades enteras, el resto se acumula para la próxima actualización.
+        let consumed = self.pending_consumption.floor().min(u8::MAX as f64);
+        self.pending_consumption -= consumed;
+        self.current_data
+            .decrement_and_check_battery_lvl(consumed as u8, min_battery)
+    }
+
+    /// Elige la estación libre más cercana que alcanza con la batería restante. Si las alcanzables están
+    /// ocupadas, espera su turno hasta que alguna se libere; y si no alcanza ninguna, va a la más cercana.
+    fn choose_charging_station(&self) -> Result<ChargingStation, Error> {
+        let mut waiting = false;
+        loop {
+            let position = self.current_data.get_current_position()?;
+            let max_distance = self
+                .dron_properties
+                .get_battery_model()
+                .flight_distance_with(self.current_data.get_battery_lvl()?);
+            match self.charging_stations.select(position, max_distance)? {
+                StationSelection::Free(station) => {
+                    self.logger.log(format!(
+                        "Voy a cargarme a la estación {} en {}.",
+                        station.get_name(),
+                        station.get_position()
+                    ));
+                    return Ok(station);
+                }
+                StationSelection::Unreachable(station) => {
+                    self.logger.log(format!(
+                        "Ninguna estación de carga está al alcance de la batería restante, voy a la más cercana: {} en {}.",
+                        station.get_name(),
+                        station.get_position()
+                    ));
+                    return Ok(station);
+                }
+                StationSelection::Occupied => {
+                    if !waiting {
+                        self.logger.log(
+                            "Las estaciones de carga al alcance están ocupadas, espero a que se libere alguna."
+                                .to_string(),
+                        );
+                        waiting = true;
+                    }
+                    self.current_data.sleep(WAIT_FOR_STATION_INTERVAL);
+                }
+            }
+        }
+    }
+
     fn fly_to_mantainance(
         &mut self,
-        destination: (f64, f64),
+        destination: GeoPosition,
         flag_maintanance: bool,
     ) -> Result<(), Error> {
         let origin = self.current_data.get_current_position()?;
-        let dir = calculate_direction(origin, destination);
+        let distance_model = self.current_data.get_distance_model();
+        let dir = distance_model.direction(origin, destination);
+        let mut speed = self.current_data.get_cruise_speed()?;
         println!("Fly_to: volando"); // se puede borrar
         self.logger.log(format!(
-            "Fly_to: dir: {:?}, vel: {}",
+            "Fly_to: dir: {:?}, vel: {} m/s",
             dir,
-            self.dron_properties.get_speed()
+            speed
         ));
 
         // self.current_data.set_state(DronState::Flying, flag_maintanance)?; // diferencia en caso mantenimiento
-        self.current_data.set_flying_info_values(dir, self.dron_properties.get_speed(), flag_maintanance)?;
+        self.current_data.set_flying_info_values(dir, speed, flag_maintanance)?;
 
+        let tick = self.dron_properties.get_flight_tick();
         let mut current_pos = origin;
-        let threshold = 0.001; // Define un umbral adecuado para tu aplicación
-        while calculate_distance(current_pos, destination) > threshold {
-            current_pos = self.current_data.increment_current_position_in(dir, flag_maintanance)?;
-
-            // Simular el vuelo, el dron se desplaza
-            let a = 4/5; // aux
-            sleep(Duration::from_secs(a));
+        let mut last_tick = self.current_data.now();
+        while current_pos != destination {
+            self.current_data.check_not_shutting_down()?;
+            // Simular el vuelo, el dron se desplaza según el tiempo real transcurrido desde el paso anterior
+            self.current_data.sleep(tick);
+            let now = self.current_data.now();
+            // La velocidad puede haberse ajustado con un comando de control durante el vuelo.
+            let current_speed = self.current_data.get_cruise_speed()?;
+            if current_speed != speed {
+                speed = current_speed;
+                self.current_data.set_flying_info_values(dir, speed, flag_maintanance)?;
+            }
+            let displacement = distance_model.flight_displacement(speed, now.duration_since(last_tick));
+            last_tick = now;
+            current_pos = self.current_data.move_towards(destination, displacement, flag_maintanance)?;
             self.logger.log(format!(
                 "   incrementada la posición actual: {:?}",
                 self.current_data.get_current_position()
@@ -102,9 +273,6 @@ impl BatteryManager {
             self.publish_current_info()?;
         }
 
-        // Salió del while porque está a muy poca distancia del destino. Hace ahora el paso final.
-        self.current_data.set_current_position(destination)?;
-
         // Al llegar, el dron ya no se encuentra en desplazamiento.
         self.current_data.unset_flying_info_values()?;
         self.logger.log(format!(
@@ -112,8 +280,8 @@ impl BatteryManager {
             self.current_data.get_current_position()
         ));
 
-        // Llegue a destino entonces debo cambiar a estado --> Manejando Incidente
-        self.current_data.set_state(DronState::ManagingIncident, true)?;
+        // El estado lo establece quien llama: al llegar a la estación sigue en mantenimiento, para que
+        // los demás drones vean que la ocupa.
 
         // Publica
         self.publish_current_info()?;
@@ -125,7 +293,8 @@ impl BatteryManager {
     }
 
     fn recharge_battery(&mut self) -> Result<(), Error> {
-        self.current_data.set_battery_lvl(self.dron_properties.get_max_battery_lvl())?;
+        let max_battery = self.current_data.get_max_battery_lvl()?;
+        self.current_data.set_battery_lvl(max_battery)?;
         Ok(())
     }
 
@@ -139,4 +308,56 @@ impl BatteryManager {
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::BatteryManager;
+    use crate::{
+        apps::{
+            geo_position::GeoPosition,
+            sist_dron::{
+                dron::CI_CHANNEL_CAPACITY,
+                dron_config::{DronConfig, DEFAULT_CONFIG_FILE},
+                dron_current_info::DronCurrentInfo,
+                dron_state::DronState,
+                simulation::Simulation,
+            },
+        },
+        logging::string_logger::StringLogger,
+    };
+
+    #[test]
+    fn test_1_con_el_reloj_de_la_simulacion_el_consumo_es_deterministico() {
+        let position = GeoPosition::new(-34.6, -58.4).unwrap();
+        let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, 1, position).unwrap();
+        let properties = config.get_properties();
+        let simulation = Simulation::new(1);
+        let ci = DronCurrentInfo::new(1, position, 31, DronState::ManagingIncident);
+        let data = simulation.create_data(ci, &properties);
+        let (str_logger_tx, _str_logger_rx) = mpsc::channel::<String>();
+        let (ci_tx, _ci_rx) = mpsc::sync_channel(CI_CHANNEL_CAPACITY);
+        let (process_inc_tx, _process_inc_rx) = mpsc::channel();
+        let mut battery_manager = BatteryManager::new(
+            data.clone_ref(),
+            properties,
+            config.get_charging_stations(),
+            StringLogger::new(str_logger_tx),
+            ci_tx,
+            process_inc_tx,
+        );
+        data.set_battery_thresholds(30, 100).unwrap();
+
+        // Trabajando en el incidente consume 0.25 por segundo: en 4 segundos, una unidad.
+        simulation.advance(Duration::from_secs(4));
+        let min_battery = data.get_min_operational_battery_lvl().unwrap();
+        assert!(!battery_manager.consume_battery(min_battery).unwrap());
+        assert_eq!(data.get_battery_lvl().unwrap(), 30);
+
+        simulation.advance(Duration::from_secs(6));
+        assert!(battery_manager.consume_battery(min_battery).unwrap());
+        // El resto no descontado se acumula para la próxima actualización.
+        assert_eq!(data.get_battery_lvl().unwrap(), 29);
+    }
+}
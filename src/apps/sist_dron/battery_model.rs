@@ -0,0 +1,116 @@
+use std::{io::Error, time::Duration};
+
+use super::{dron_config::DronProperties, dron_state::DronState};
+
+/// Cantidad de unidades de distancia (las mismas que el range) que hay en una unidad de latitud y longitud.
+const DISTANCE_UNITS_PER_DEGREE: f64 = 1000.0;
+
+/// Modelo de consumo de batería de un dron, en porcentaje de batería.
+/// Consume según la distancia que vuela mientras se desplaza, y según el tiempo mientras está detenido en el aire:
+/// más si está trabajando en un incidente que si solamente está suspendido esperando. Mientras está en
+/// mantenimiento, detenido en la estación de carga, no consume.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BatteryModel {
+    per_distance: f64, // por cada unidad de distancia volada (milésima de latitud y longitud, como el range).
+    hovering_per_sec: f64, // por segundo suspendido en el aire sin desplazarse.
+    incident_per_sec: f64, // por segundo trabajando en la ubicación de un incidente.
+}
+
+impl BatteryModel {
+    pub fn new(per_distance: f64, hovering_per_sec: f64, incident_per_sec: f64) -> Self {
+        Self {
+            per_distance,
+            hovering_per_sec,
+            incident_per_sec,
+        }
+    }
+
+    /// Carga los coeficientes `battery_per_distance`, `battery_hovering_per_sec` y `battery_incident_per_sec`,
+    /// que no pueden ser negativos.
+    pub fn from_properties(properties: &DronProperties) -> Result<Self, Error> {
+        let is_coefficient = |coefficient: &f64| coefficient.is_finite() && *coefficient >= 0.0;
+        Ok(Self::new(
+            properties.get_required("battery_per_distance", is_coefficient)?,
+            properties.get_required("battery_hovering_per_sec", is_coefficient)?,
+            properties.get_required("battery_incident_per_sec", is_coefficient)?,
+        ))
+    }
+
+    /// Devuelve cuánta batería consumió un dron en estado `state`, que en `elapsed` voló `distance` (en latitud
+    /// y longitud). `is_flying` indica si se está desplazando; si no, también consume por el tiempo detenido.
+    pub fn consumption(
+        &self,
+        state: DronState,
+        is_flying: bool,
+        distance: f64,
+        elapsed: Duration,
+    ) -> f64 {
+        let per_sec = match state {
+            _ if is_flying => 0.0,
+            DronState::Mantainance | DronState::ScheduledMaintenance => 0.0,
+            DronState::ManagingIncident => self.incident_per_sec,
+            _ => self.hovering_per_sec,
+        };
+        self.trip_consumption(distance) + per_sec * elapsed.as_secs_f64()
+    }
+
+    /// Devuelve cuánta batería consume volar `distance` (en latitud y longitud).
+    pub fn trip_consumption(&self, distance: f64) -> f64 {
+        distance * DISTANCE_UNITS_PER_DEGREE * self.per_distance
+    }
+
+    /// Devuelve si con `battery_lvl` de batería alcanza para volar `distance` (en latitud y longitud).
+    pub fn has_battery_for_trip(&self, battery_lvl: u8, distance: f64) -> bool {
+        self.trip_consumption(distance) <= battery_lvl as f64
+    }
+
+    /// Devuelve la distancia (en latitud y longitud) que puede volar con `battery_lvl` de batería;
+    /// o infinito si volar no consume batería.
+    pub fn flight_distance_with(&self, battery_lvl: u8) -> f64 {
+        if self.per_distance == 0.0 {
+            return f64::INFINITY;
+        }
+        battery_lvl as f64 / self.per_distance / DISTANCE_UNITS_PER_DEGREE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BatteryModel;
+    use crate::apps::sist_dron::dron_state::DronState;
+    use std::time::Duration;
+
+    #[test]
+    fn test_1_el_consumo_depende_de_la_distancia_volada_y_del_estado() {
+        let model = BatteryModel::new(0.5, 0.1, 0.25);
+        let ten_secs = Duration::from_secs(10);
+
+        // Volando, consume por distancia: 0.01 de latitud son 10 unidades.
+        let flying = model.consumption(DronState::Flying, true, 0.01, ten_secs);
+        assert!((flying - 5.0).abs() < 1e-9);
+        // Detenido, consume por tiempo, más si trabaja en un incidente.
+        let hovering = model.consumption(DronState::ExpectingToRecvIncident, false, 0.0, ten_secs);
+        assert!((hovering - 1.0).abs() < 1e-9);
+        let at_incident = model.consumption(DronState::ManagingIncident, false, 0.0, ten_secs);
+        assert!((at_incident - 2.5).abs() < 1e-9);
+        // En la estación de carga no consume.
+        assert_eq!(
+            model.consumption(DronState::Mantainance, false, 0.0, ten_secs),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_2_la_distancia_que_puede_volar_es_proporcional_a_la_bateria() {
+        let model = BatteryModel::new(0.5, 0.1, 0.25);
+        assert!((model.flight_distance_with(50) - 0.1).abs() < 1e-9);
+        assert!((model.trip_consumption(model.flight_distance_with(20)) - 20.0).abs() < 1e-9);
+
+        // 0.04 de latitud consumen 20 de batería.
+        assert!(model.has_battery_for_trip(20, 0.04));
+        assert!(!model.has_battery_for_trip(19, 0.04));
+
+        let free_flight = BatteryModel::new(0.0, 0.1, 0.25);
+        assert_eq!(free_flight.flight_distance_with(20), f64::INFINITY);
+    }
+}
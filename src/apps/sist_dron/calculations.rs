@@ -1,16 +1,99 @@
 
 // Funciones que realizan cálculos matemáticos.
 
-pub fn calculate_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
-    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use crate::apps::geo_position::{GeoPosition, EARTH_RADIUS_METERS};
+
+/// Metros que hay, aproximadamente, en una unidad de latitud y longitud.
+pub const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Cómo se calculan las distancias entre posiciones (lat, lon): sobre la superficie de la Tierra, o tratando a
+/// latitud y longitud como un plano, que exagera las distancias en longitud lejos del ecuador. El plano se conserva
+/// para los tests calibrados con él.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum DistanceModel {
+    #[default]
+    Geodesic,
+    Flat,
+}
+
+impl DistanceModel {
+    /// Obtiene el modelo a partir del valor de la propiedad `distance_model`: `geodesic` o `flat`.
+    pub fn from_property(prop: &str) -> Result<Self, Error> {
+        match prop {
+            "geodesic" => Ok(DistanceModel::Geodesic),
+            "flat" => Ok(DistanceModel::Flat),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Modelo de distancia inválido, debe ser geodesic o flat.",
+            )),
+        }
+    }
+
+    /// Devuelve la distancia entre `a` y `b`, en grados, comparable con los rangos (en milésimas de grado).
+    /// La geodésica es el ángulo entre ambas posiciones visto desde el centro de la Tierra.
+    pub fn distance(&self, a: GeoPosition, b: GeoPosition) -> f64 {
+        match self {
+            DistanceModel::Geodesic => (a.distance_to(&b) / EARTH_RADIUS_METERS).to_degrees(),
+            DistanceModel::Flat => calculate_distance(a, b),
+        }
+    }
+
+    /// Convierte `meters` a la unidad en que se miden las distancias con este modelo.
+    pub fn meters_to_degrees(&self, meters: f64) -> f64 {
+        match self {
+            DistanceModel::Geodesic => (meters / EARTH_RADIUS_METERS).to_degrees(),
+            DistanceModel::Flat => meters / METERS_PER_DEGREE,
+        }
+    }
+
+    /// Devuelve la dirección en la que debe volar desde `origin` hasta `destination`, como vector unitario con
+    /// componentes lat y lon. La geodésica es la del rumbo inicial, con sus componentes hacia el norte y el este.
+    pub fn direction(&self, origin: GeoPosition, destination: GeoPosition) -> (f64, f64) {
+        match self {
+            DistanceModel::Geodesic => {
+                let bearing = origin.bearing_to(&destination).to_radians();
+                (bearing.cos(), bearing.sin())
+            }
+            DistanceModel::Flat => calculate_direction(origin, destination),
+        }
+    }
+
+    /// Devuelve la posición a la que llega desde `position` quien se desplaza `displacement` (en la unidad de
+    /// `distance`) en la dirección `direction`, calculada con `direction`.
+    pub fn step(&self, position: GeoPosition, direction: (f64, f64), displacement: f64) -> GeoPosition {
+        let (delta_lat, delta_lon) = (direction.0 * displacement, direction.1 * displacement);
+        match self {
+            // Un grado de longitud mide menos cuanto más lejos está del ecuador.
+            DistanceModel::Geodesic => {
+                let parallel_scale = position.get_latitude().to_radians().cos().max(f64::EPSILON);
+                position.offset(delta_lat, delta_lon / parallel_scale)
+            }
+            DistanceModel::Flat => position.offset(delta_lat, delta_lon),
+        }
+    }
+
+    /// Devuelve cuánto se desplaza (en la unidad de `distance`) en `elapsed` un dron que vuela a `speed` metros
+    /// por segundo.
+    pub fn flight_displacement(&self, speed: f64, elapsed: Duration) -> f64 {
+        self.meters_to_degrees(speed * elapsed.as_secs_f64())
+    }
 }
 
-/// Calcula la dirección en la que debe volar desde una posición `origin` hasta `destination`.
-// Aux: esto estaría mejor en un struct posicion quizás? [] ver.
-pub fn calculate_direction(origin: (f64, f64), destination: (f64, f64)) -> (f64, f64) {
+pub fn calculate_distance(a: GeoPosition, b: GeoPosition) -> f64 {
+    ((b.get_latitude() - a.get_latitude()).powi(2) + (b.get_longitude() - a.get_longitude()).powi(2)).sqrt()
+}
+
+/// Calcula la dirección en la que debe volar desde una posición `origin` hasta `destination`, como vector unitario
+/// con componentes lat y lon.
+pub fn calculate_direction(origin: GeoPosition, destination: GeoPosition) -> (f64, f64) {
     // calcular la distancia ('en diagonal') entre los dos puntos
-    let (origin_lat, origin_lon) = (origin.0, origin.1);
-    let (dest_lat, dest_lon) = (destination.0, destination.1);
+    let (origin_lat, origin_lon) = (origin.get_latitude(), origin.get_longitude());
+    let (dest_lat, dest_lon) = (destination.get_latitude(), destination.get_longitude());
 
     // Cálculo de distancia
     let lat_dist = dest_lat - origin_lat;
@@ -23,4 +106,41 @@ pub fn calculate_direction(origin: (f64, f64), destination: (f64, f64)) -> (f64,
     let direction: (f64, f64) = (unit_lat, unit_lon);
 
     direction
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::DistanceModel;
+    use crate::apps::geo_position::GeoPosition;
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_la_distancia_geodesica_acorta_la_longitud_lejos_del_ecuador() {
+        // Del Obelisco a la Plaza de Mayo, unos 1.1 km.
+        let obelisco = position(-34.6037, -58.3816);
+        let plaza_de_mayo = position(-34.6083, -58.3712);
+        let meters = obelisco.distance_to(&plaza_de_mayo);
+        assert!((meters - 1_080.0).abs() < 5.0);
+
+        // Sobre un meridiano ambos modelos coinciden; sobre un paralelo, el plano exagera la distancia.
+        let north = position(-34.5937, -58.3816);
+        let geodesic = DistanceModel::Geodesic;
+        let flat = DistanceModel::Flat;
+        assert!((geodesic.distance(obelisco, north) - flat.distance(obelisco, north)).abs() < 1e-9);
+        let east = position(-34.6037, -58.3716);
+        let ratio = geodesic.distance(obelisco, east) / flat.distance(obelisco, east);
+        assert!((ratio - (34.6037_f64).to_radians().cos()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_2_el_rumbo_se_mide_desde_el_norte_en_sentido_horario() {
+        let origin = position(-34.6037, -58.3816);
+        assert!(origin.bearing_to(&position(-34.5937, -58.3816)).abs() < 1e-6);
+        assert!((origin.bearing_to(&position(-34.6037, -58.3716)) - 90.0).abs() < 0.1);
+        assert!((origin.bearing_to(&position(-34.6137, -58.3816)) - 180.0).abs() < 1e-6);
+        assert!((origin.bearing_to(&position(-34.6037, -58.3916)) - 270.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    apps::{geo_position::GeoPosition, sist_dron::calculations::DistanceModel},
+    mqtt::mqtt_utils::mqtt_error::MqttError,
+};
+
+use super::{
+    dron_config::DronProperties,
+    dron_current_info::DronCurrentInfo,
+};
+
+/// Nombre de la estación que se usa cuando el archivo de configuración no define `charging_stations`,
+/// ubicada en la posición de mantenimiento.
+const DEFAULT_STATION_NAME: &str = "mantenimiento";
+/// Distancia por debajo de la cual se considera que un dron está en la posición de una estación.
+const AT_STATION_THRESHOLD: f64 = 0.001;
+
+/// Estación de carga a la que un dron puede ir a recargar su batería.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargingStation {
+    name: String,
+    position: GeoPosition,
+}
+
+impl ChargingStation {
+    pub fn new(name: &str, position: GeoPosition) -> Self {
+        Self {
+            name: name.to_string(),
+            position,
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.to_string()
+    }
+
+    /// Devuelve la posición de la estación.
+    pub fn get_position(&self) -> GeoPosition {
+        self.position
+    }
+
+    /// Parsea una estación de la forma `nombre:lat,lon`.
+    fn from_str(entry: &str) -> Result<Self, Error> {
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Estación de carga inválida: {}.", entry),
+            )
+        };
+        let (name, position) = entry.split_once(':').ok_or_else(invalid)?;
+        let (lat, lon) = position.split_once(',').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let lat = lat.trim().parse().map_err(|_| invalid())?;
+        let lon = lon.trim().parse().map_err(|_| invalid())?;
+        let position = GeoPosition::new(lat, lon).map_err(|_| invalid())?;
+        Ok(Self::new(name, position))
+    }
+}
+
+/// Resultado de buscar una estación a la cual ir a cargarse.
+#[derive(Debug, PartialEq)]
+pub enum StationSelection {
+    /// La estación libre más cercana que se alcanza con la batería restante.
+    Free(ChargingStation),
+    /// Hay estaciones alcanzables, pero están todas ocupadas; el dron debe esperar su turno.
+    Occupied,
+    /// Ninguna estación se alcanza con la batería restante; se devuelve la más cercana.
+    Unreachable(ChargingStation),
+}
+
+/// Estaciones de carga del sistema, y cuáles están ocupadas por otros drones.
+/// La ocupación se conoce por las current_info que los drones publican en el topic `dron`: un dron en estado
+/// de mantenimiento, detenido en la posición de una estación, la ocupa hasta que se va o cambia de estado.
+#[derive(Debug)]
+pub struct ChargingStations {
+    stations: Arc<Vec<ChargingStation>>,
+    occupied_by: Arc<Mutex<HashMap<u8, String>>>, // (dron_id, nombre de la estación que ocupa)
+    distance_model: DistanceModel, // cómo mide la distancia hasta cada estación.
+}
+
+impl ChargingStations {
+    pub fn new(stations: Vec<ChargingStation>) -> Self {
+        Self {
+            stations: Arc::new(stations),
+            occupied_by: Arc::new(Mutex::new(HashMap::new())),
+            distance_model: DistanceModel::default(),
+        }
+    }
+
+    /// Indica cómo se mide la distancia hasta cada estación (por defecto, sobre la superficie de la Tierra).
+    pub fn with_distance_model(mut self, distance_model: DistanceModel) -> Self {
+        self.distance_model = distance_model;
+        self
+    }
+
+    /// Carga las estaciones de la propiedad `charging_stations`, de la forma `nombre:lat,lon;nombre:lat,lon`.
+    /// Si no está definida, hay una única estación en `default_position`.
+    pub fn from_properties(
+        properties: &DronProperties,
+        default_position: GeoPosition,
+    ) -> Result<Self, Error> {
+        match properties.get("charging_stations") {
+            Some((key, prop)) => {
+                Self::parse(prop).map_err(|e| Error::new(e.kind(), format!("{}: {}", key, e)))
+            }
+            None => Ok(Self::new(vec![ChargingStation::new(
+                DEFAULT_STATION_NAME,
+                default_position,
+            )])),
+        }
+    }
+
+    /// Parsea una lista de estaciones de la forma `nombre:lat,lon;nombre:lat,lon`.
+    fn parse(prop: &str) -> Result<Self, Error> {
+        let stations = prop
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(ChargingStation::from_str)
+            .collect::<Result<Vec<ChargingStation>, Error>>()?;
+        if stations.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "charging_stations no tiene ninguna estación.",
+            ));
+        }
+        Ok(Self::new(stations))
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            stations: self.stations.clone(),
+            occupied_by: self.occupied_by.clone(),
+            distance_model: self.distance_model,
+        }
+    }
+
+    /// Actualiza qué estación ocupa el dron cuya current_info se recibió.
+    pub fn update_occupancy(&self, ci: &DronCurrentInfo) -> Result<(), Error> {
+        let station = if ci.get_state().is_in_maintenance() && ci.get_flying_info().is_none()
+        {
+            self.station_at(ci.get_current_position())
+        } else {
+            None
+        };
+        let mut occupied_by = self.lock_occupied_by()?;
+        match station {
+            Some(station) => occupied_by.insert(ci.get_id(), station.get_name()),
+            None => occupied_by.remove(&ci.get_id()),
+        };
+        Ok(())
+    }
+
+    /// Busca la estación libre más cercana a `position` entre las que están a no más de `max_distance`.
+    pub fn select(
+        &self,
+        position: GeoPosition,
+        max_distance: f64,
+    ) -> Result<StationSelection, Error> {
+        let mut by_distance: Vec<(f64, &ChargingStation)> = self
+            .stations
+            .iter()
+            .map(|station| (self.distance_model.distance(position, station.position), station))
+            .collect();
+        by_distance.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let occupied_by = self.lock_occupied_by()?;
+        let is_occupied =
+            |station: &ChargingStation| occupied_by.values().any(|name| *name == station.name);
+        let mut reachable = by_distance
+            .iter()
+            .filter(|(distance, _)| *distance <= max_distance)
+            .peekable();
+        if reachable.peek().is_none() {
+            // Hay al menos una estación, por cómo se construye.
+            return Ok(StationSelection::Unreachable(by_distance[0].1.clone()));
+        }
+        match reachable.find(|(_, station)| !is_occupied(station)) {
+            Some((_, station)) => Ok(StationSelection::Free((*station).clone())),
+            None => Ok(StationSelection::Occupied),
+        }
+    }
+
+    /// Devuelve la distancia desde `position` hasta la estación más cercana, esté o no ocupada.
+    pub fn get_nearest_distance(&self, position: GeoPosition) -> f64 {
+        self.stations
+            .iter()
+            .map(|station| self.distance_model.distance(position, station.position))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Devuelve la estación en cuya posición se encuentra `position`, si la hay.
+    fn station_at(&self, position: GeoPosition) -> Option<&ChargingStation> {
+        self.stations
+            .iter()
+            .find(|station| self.distance_model.distance(position, station.position) <= AT_STATION_THRESHOLD)
+    }
+
+    fn lock_occupied_by(&self) -> Result<std::sync::MutexGuard<'_, HashMap<u8, String>>, Error> {
+        self.occupied_by
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("charging_stations".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChargingStation, ChargingStations, StationSelection};
+    use crate::apps::{
+        geo_position::GeoPosition,
+        sist_dron::{
+            battery_model::BatteryModel, calculations::DistanceModel, dron_current_info::DronCurrentInfo,
+            dron_state::DronState,
+        },
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn create_stations() -> ChargingStations {
+        ChargingStations::parse(
+            "central:-34.6037,-58.3816; norte:-34.5990,-58.3920;sur:-34.6180,-58.3850",
+        )
+        .unwrap()
+        .with_distance_model(DistanceModel::Flat)
+    }
+
+    #[test]
+    fn test_1_se_parsean_las_estaciones_y_se_rechazan_las_invalidas() {
+        let stations = create_stations();
+        assert_eq!(stations.stations.len(), 3);
+        assert_eq!(
+            stations.stations[1],
+            ChargingStation::new("norte", position(-34.5990, -58.3920))
+        );
+
+        assert!(ChargingStations::parse("central-34.6,-58.3").is_err());
+        assert!(ChargingStations::parse("central:-34.6").is_err());
+        assert!(ChargingStations::parse(":-34.6,-58.3").is_err());
+        assert!(ChargingStations::parse("").is_err());
+    }
+
+    #[test]
+    fn test_2_se_elige_la_estacion_libre_mas_cercana() {
+        let stations = create_stations();
+        let origin = position(-34.6000, -58.3900); // cerca de norte.
+
+        let selection = stations.select(origin, 1.0).unwrap();
+        assert_eq!(
+            selection,
+            StationSelection::Free(ChargingStation::new("norte", position(-34.5990, -58.3920)))
+        );
+
+        // Otro dron se carga en norte, entonces se elige la siguiente más cercana.
+        let mut ci = DronCurrentInfo::new(2, position(-34.5990, -58.3920), 10, DronState::Mantainance);
+        stations.update_occupancy(&ci).unwrap();
+        let selection = stations.select(origin, 1.0).unwrap();
+        assert_eq!(
+            selection,
+            StationSelection::Free(ChargingStation::new("central", position(-34.6037, -58.3816)))
+        );
+
+        // Al terminar de cargarse, el dron deja libre la estación.
+        ci.set_state(DronState::ExpectingToRecvIncident);
+        stations.update_occupancy(&ci).unwrap();
+        let selection = stations.select(origin, 1.0).unwrap();
+        assert_eq!(
+            selection,
+            StationSelection::Free(ChargingStation::new("norte", position(-34.5990, -58.3920)))
+        );
+    }
+
+    #[test]
+    fn test_3_si_las_alcanzables_estan_ocupadas_se_espera_y_si_no_hay_alcanzables_se_elige_la_mas_cercana(
+    ) {
+        let stations = create_stations();
+        let origin = position(-34.6000, -58.3900);
+
+        // Solamente norte está al alcance, y está ocupada.
+        let ci = DronCurrentInfo::new(2, position(-34.5990, -58.3920), 10, DronState::Mantainance);
+        stations.update_occupancy(&ci).unwrap();
+        assert_eq!(
+            stations.select(origin, 0.005).unwrap(),
+            StationSelection::Occupied
+        );
+
+        // Ninguna está al alcance; la más cercana es norte, esté o no ocupada.
+        let to_norte = stations.get_nearest_distance(origin);
+        assert!((to_norte - 0.001f64.hypot(0.002)).abs() < 1e-9);
+        assert_eq!(
+            stations.select(origin, 0.001).unwrap(),
+            StationSelection::Unreachable(ChargingStation::new("norte", position(-34.5990, -58.3920)))
+        );
+    }
+
+    #[test]
+    fn test_4_la_estacion_elegida_y_si_la_bateria_alcanza_dependen_del_modelo_de_distancia() {
+        let origin = position(-34.6037, -58.3816);
+        let norte = ChargingStation::new("norte", origin.offset(0.0085, 0.0));
+        let este = ChargingStation::new("este", origin.offset(0.0, 0.0095));
+        // Con 8 de batería se vuelan 0.008 grados.
+        let max_distance = BatteryModel::new(1.0, 0.0, 0.0).flight_distance_with(8);
+
+        // En el plano norte es la más cercana, y no se alcanza ninguna.
+        let flat = ChargingStations::new(vec![norte.clone(), este.clone()]).with_distance_model(DistanceModel::Flat);
+        assert_eq!(flat.select(origin, max_distance).unwrap(), StationSelection::Unreachable(norte.clone()));
+        assert!(flat.get_nearest_distance(origin) > max_distance);
+
+        // Sobre la Tierra, un grado de longitud mide menos que uno de latitud: este está más cerca y se alcanza.
+        let geodesic = ChargingStations::new(vec![norte, este.clone()]);
+        assert_eq!(geodesic.select(origin, max_distance).unwrap(), StationSelection::Free(este));
+        assert!(geodesic.get_nearest_distance(origin) < max_distance);
+    }
+}
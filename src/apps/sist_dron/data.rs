@@ -1,18 +1,72 @@
-use std::{io::{Error, ErrorKind}, sync::{Arc, Mutex}};
+use std::{io::{Error, ErrorKind}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
 
+use crate::apps::geo_position::GeoPosition;
 use crate::apps::incident_data::incident_info::IncidentInfo;
 
-use super::{dron_current_info::DronCurrentInfo, dron_flying_info::DronFlyingInfo, dron_state::DronState};
+use super::{calculations::DistanceModel, dron_current_info::DronCurrentInfo, dron_flying_info::DronFlyingInfo, dron_state::DronState, dron_wear::{DronWear, MaintenanceSchedule}, flight_settings::FlightSettings, sim_clock::SimClock, telemetry_history::TelemetryHistory};
+
+/// Cantidad de current_info que conserva el historial del dron si no se indica otra (ver `with_history_size`).
+pub const DEFAULT_HISTORY_SIZE: usize = 60;
 
 #[derive(Debug)]
 pub struct Data {
     current_info: Arc<Mutex<DronCurrentInfo>>, // Aux: lo hago pub solo por un momento, lo usa solamente el battery en una línea, dsp lo ponemos privado otra vez. [].
+    confirmed_info: Arc<Mutex<Option<DronCurrentInfo>>>, // última current_info cuya recepción confirmó el server.
+    shutting_down: Arc<AtomicBool>, // indica a los hilos del dron que terminen, porque se está apagando.
+    settings: Arc<Mutex<FlightSettings>>, // parámetros de vuelo, ajustables con comandos de control.
+    paused: Arc<AtomicBool>, // indica que se detenga donde se encuentra, sin tomar incidentes.
+    recalled: Arc<AtomicBool>, // indica que vuelva al centro de su rango, sin tomar incidentes.
+    clock: SimClock, // reloj con el que los hilos del dron miden el tiempo y esperan.
+    history: Arc<Mutex<TelemetryHistory>>, // últimas current_info, para reenviar las no publicadas al reconectarse.
+    wear: Arc<Mutex<DronWear>>, // desgaste acumulado desde el último mantenimiento programado.
+    distance_model: DistanceModel, // cómo mide las distancias y desplaza al dron al volar.
 }
 
 impl Data {
-    pub fn new(ci: DronCurrentInfo) -> Self {
+    pub fn new(ci: DronCurrentInfo, settings: FlightSettings) -> Self {
         let current_info = Arc::new(Mutex::new(ci));
-        Self { current_info }
+        let confirmed_info = Arc::new(Mutex::new(None));
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        let settings = Arc::new(Mutex::new(settings));
+        let paused = Arc::new(AtomicBool::new(false));
+        let recalled = Arc::new(AtomicBool::new(false));
+        let clock = SimClock::real();
+        let history = Arc::new(Mutex::new(TelemetryHistory::new(DEFAULT_HISTORY_SIZE)));
+        let wear = Arc::new(Mutex::new(DronWear::new()));
+        let distance_model = DistanceModel::default();
+        Self { current_info, confirmed_info, shutting_down, settings, paused, recalled, clock, history, wear, distance_model }
+    }
+
+    /// Indica cómo se miden las distancias y se desplaza al dron al volar (por defecto, sobre la superficie de la Tierra).
+    pub fn with_distance_model(mut self, distance_model: DistanceModel) -> Self {
+        self.distance_model = distance_model;
+        self
+    }
+
+    pub fn get_distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+
+    /// Indica el reloj con el que los hilos del dron miden el tiempo y esperan, en vez del real (ver `SimClock`).
+    pub fn with_clock(mut self, clock: SimClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Indica cuántas de sus últimas current_info conserva el dron en su historial.
+    pub fn with_history_size(mut self, size: usize) -> Self {
+        self.history = Arc::new(Mutex::new(TelemetryHistory::new(size)));
+        self
+    }
+
+    /// Devuelve el instante actual según el reloj del dron.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Espera a que transcurra `duration` según el reloj del dron.
+    pub fn sleep(&self, duration: Duration) {
+        self.clock.sleep(duration)
     }
 
     /// Toma lock y obtiene el id del dron.
@@ -43,7 +97,7 @@ impl Data {
         if let Ok(mut ci) = self.current_info.lock() {
             let is_mantainance_set = flag_maintanance;
             let is_not_maintainance_set =
-                ci.get_state() != DronState::Mantainance && !flag_maintanance;
+                !ci.get_state().is_in_maintenance() && !flag_maintanance;
             if is_mantainance_set || is_not_maintainance_set {
                 ci.set_state(new_state);
                 return Ok(());
@@ -71,7 +125,7 @@ impl Data {
     ) -> Result<(), Error> {
         let is_mantainance_set = flag_maintanance;
         let is_not_maintainance_set =
-            self.get_state()? != DronState::Mantainance && !flag_maintanance;
+            !self.get_state()?.is_in_maintenance() && !flag_maintanance;
         if is_mantainance_set || is_not_maintainance_set {
             let info = DronFlyingInfo::new(dir, speed);
             self.set_flying_info(info)?;
@@ -118,11 +172,11 @@ impl Data {
             "Error al tomar lock de current info.",
         ))
     }
-    /// Decrementa la batería, establece el inc_id_to_resolve en None si la misma se encuentra por debajo del mínimo,
+    /// Decrementa la batería en `consumed`, establece el inc_id_to_resolve en None si la misma se encuentra por debajo del mínimo,
     /// y devuelve si la misma se encuentra por debajo de `min_battery`.
-    pub fn decrement_and_check_battery_lvl(&mut self, min_battery: u8) -> Result<bool, Error> {
+    pub fn decrement_and_check_battery_lvl(&mut self, consumed: u8, min_battery: u8) -> Result<bool, Error> {
         if let Ok(mut ci) = self.current_info.lock() {
-            Ok(ci.decrement_and_check_battery_lvl(min_battery))
+            Ok(ci.decrement_and_check_battery_lvl(consumed, min_battery))
         } else {
             Err(Error::new(
                 ErrorKind::Other,
@@ -179,7 +233,7 @@ impl Data {
     }
 
     /// Toma lock y obtiene la `current_position`, posición en la que el dron se encuentra actualmente.
-    pub fn get_current_position(&self) -> Result<(f64, f64), Error> {
+    pub fn get_current_position(&self) -> Result<GeoPosition, Error> {
         if let Ok(ci) = self.current_info.lock() {
             return Ok(ci.get_current_position());
         }
@@ -188,20 +242,21 @@ impl Data {
             "Error al tomar lock de current info.",
         ))
     }
-    /// Toma lock, incrementa la `current_position` en la dirección recibida, y la devuelve actualizada.
+    /// Toma lock, desplaza la `current_position` `displacement` hacia `destination`, y la devuelve actualizada.
     /// El flag de mantenimiento indica si quien llama a esta función es el módulo encargado del mantenimiento,
     /// y se utiliza para otorgar permisos.
-    pub fn increment_current_position_in(
+    pub fn move_towards(
         &self,
-        dir: (f64, f64),
+        destination: GeoPosition,
+        displacement: f64,
         flag_maintanance: bool,
-    ) -> Result<(f64, f64), Error> {
+    ) -> Result<GeoPosition, Error> {
         if let Ok(mut ci) = self.current_info.lock() {
             let is_mantainance_set = flag_maintanance;
             let is_not_maintainance_set =
-                ci.get_state() != DronState::Mantainance && !flag_maintanance;
+                !ci.get_state().is_in_maintenance() && !flag_maintanance;
             if is_mantainance_set || is_not_maintainance_set {
-                Ok(ci.increment_current_position_in(dir))
+                Ok(ci.move_towards(destination, displacement, self.distance_model))
             } else {
                 Err(Error::new(
                     ErrorKind::InvalidData,
@@ -215,8 +270,27 @@ impl Data {
             ))
         }
     }
+    /// Toma lock y, solamente si el dron está esperando incidentes, desplaza la `current_position` `displacement`
+    /// hacia `destination` y la devuelve actualizada. Si está en otro estado no lo desplaza, y devuelve None,
+    /// para que el patrullaje no interfiera con el vuelo a un incidente o a mantenimiento.
+    pub fn patrol_towards(
+        &self,
+        destination: GeoPosition,
+        displacement: f64,
+    ) -> Result<Option<GeoPosition>, Error> {
+        if let Ok(mut ci) = self.current_info.lock() {
+            if ci.get_state() != DronState::ExpectingToRecvIncident {
+                return Ok(None);
+            }
+            return Ok(Some(ci.move_towards(destination, displacement, self.distance_model)));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de current info.",
+        ))
+    }
     /// Toma lock y establece la `current_position` en la recibida por parámetro.
-    pub fn set_current_position(&self, new_position: (f64, f64)) -> Result<(), Error> {
+    pub fn set_current_position(&self, new_position: GeoPosition) -> Result<(), Error> {
         if let Ok(mut ci) = self.current_info.lock() {
             ci.set_current_position(new_position);
             return Ok(());
@@ -230,7 +304,140 @@ impl Data {
     pub fn clone_ref(&self) -> Self {
         Self {
             current_info: self.current_info.clone(),
+            confirmed_info: self.confirmed_info.clone(),
+            shutting_down: self.shutting_down.clone(),
+            settings: self.settings.clone(),
+            paused: self.paused.clone(),
+            recalled: self.recalled.clone(),
+            clock: self.clock.clone_ref(),
+            history: self.history.clone(),
+            wear: self.wear.clone(),
+            distance_model: self.distance_model,
+        }
+    }
+
+    /// Indica a los hilos del dron que terminen, porque se está apagando.
+    pub fn request_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    /// Devuelve si el dron se está apagando.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Devuelve error si el dron se está apagando, para interrumpir lo que esté haciendo (ie un vuelo).
+    pub fn check_not_shutting_down(&self) -> Result<(), Error> {
+        if self.is_shutting_down() {
+            return Err(Error::new(
+                ErrorKind::ConnectionAborted,
+                "El dron se está apagando.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Toma lock y aplica `f` a los parámetros de vuelo.
+    fn with_settings<T>(&self, f: impl FnOnce(&mut FlightSettings) -> T) -> Result<T, Error> {
+        if let Ok(mut settings) = self.settings.lock() {
+            return Ok(f(&mut settings));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de los parámetros de vuelo.",
+        ))
+    }
+
+    /// Toma lock y devuelve la velocidad de crucero actual.
+    pub fn get_cruise_speed(&self) -> Result<f64, Error> {
+        self.with_settings(|settings| settings.get_cruise_speed())
+    }
+
+    /// Toma lock y establece la velocidad de crucero.
+    pub fn set_cruise_speed(&self, cruise_speed: f64) -> Result<(), Error> {
+        self.with_settings(|settings| settings.set_cruise_speed(cruise_speed))
+    }
+
+    /// Toma lock y devuelve el nivel de batería por debajo del cual debe ir a cargarse.
+    pub fn get_min_operational_battery_lvl(&self) -> Result<u8, Error> {
+        self.with_settings(|settings| settings.get_min_operational_battery_lvl())
+    }
+
+    /// Toma lock y devuelve el nivel de batería hasta el cual se carga.
+    pub fn get_max_battery_lvl(&self) -> Result<u8, Error> {
+        self.with_settings(|settings| settings.get_max_battery_lvl())
+    }
+
+    /// Toma lock y establece los niveles de batería mínimo operativo y máximo.
+    pub fn set_battery_thresholds(&self, min_operational: u8, max: u8) -> Result<(), Error> {
+        self.with_settings(|settings| settings.set_battery_thresholds(min_operational, max))
+    }
+
+    /// Indica que el dron se detenga donde se encuentra, o que vuelva a funcionar.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// Devuelve si el dron está detenido donde se encuentra.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Indica que el dron vuelva al centro de su rango, o que vuelva a funcionar.
+    pub fn set_recalled(&self, recalled: bool) {
+        self.recalled.store(recalled, Ordering::SeqCst);
+    }
+
+    /// Devuelve si el dron fue llamado a la base.
+    pub fn is_recalled(&self) -> bool {
+        self.recalled.load(Ordering::SeqCst)
+    }
+
+    /// Devuelve si el dron no debe tomar incidentes, por estar detenido o llamado a la base.
+    pub fn is_on_standby(&self) -> bool {
+        self.is_paused() || self.is_recalled()
+    }
+
+    /// Toma lock, pasa al dron a estado `Offline`, detenido, y devuelve su última current_info.
+    pub fn set_offline(&self) -> Result<DronCurrentInfo, Error> {
+        if let Ok(mut ci) = self.current_info.lock() {
+            ci.set_state(DronState::Offline);
+            ci.unset_flying_info();
+            return Ok(ci.clone());
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de current info.",
+        ))
+    }
+
+    /// Registra `ci` como la última current_info publicada cuya recepción confirmó el server.
+    pub fn set_confirmed_info(&self, ci: DronCurrentInfo) -> Result<(), Error> {
+        if let Ok(mut confirmed_info) = self.confirmed_info.lock() {
+            *confirmed_info = Some(ci);
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de confirmed info.",
+        ))
+    }
+
+    /// Devuelve la última current_info publicada cuya recepción confirmó el server, si hay alguna.
+    pub fn get_confirmed_info(&self) -> Result<Option<DronCurrentInfo>, Error> {
+        if let Ok(confirmed_info) = self.confirmed_info.lock() {
+            return Ok(confirmed_info.clone());
         }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de confirmed info.",
+        ))
+    }
+
+    /// Devuelve si el server confirmó la recepción de la current_info actual.
+    pub fn is_current_info_confirmed(&self) -> Result<bool, Error> {
+        let current_info = self.get_current_info()?;
+        Ok(self.get_confirmed_info()? == Some(current_info))
     }
     
     /// Devuelve una copia del valor actual de la `current_info`. Utilizado para enviar por channel.
@@ -244,10 +451,94 @@ impl Data {
         ))
     }
 
+    /// Toma lock y establece los incidentes activos en su rango que todavía puede atender.
+    pub fn set_pending_incidents(&self, pending_incs: Vec<IncidentInfo>) -> Result<(), Error> {
+        if let Ok(mut ci) = self.current_info.lock() {
+            ci.set_pending_incidents(pending_incs);
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de current info.",
+        ))
+    }
+
+    /// Toma lock y obtiene los incidentes activos en su rango que todavía puede atender.
+    pub fn get_pending_incidents(&self) -> Result<Vec<IncidentInfo>, Error> {
+        if let Ok(ci) = self.current_info.lock() {
+            return Ok(ci.get_pending_incidents().to_vec());
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de current info.",
+        ))
+    }
+
+    /// Toma lock y aplica `f` al historial de current_info.
+    fn with_history<T>(&self, f: impl FnOnce(&mut TelemetryHistory) -> T) -> Result<T, Error> {
+        if let Ok(mut history) = self.history.lock() {
+            return Ok(f(&mut history));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock del historial de current info.",
+        ))
+    }
+
+    /// Registra `ci` en el historial, indicando si se publicó.
+    pub fn record_sample(&self, ci: DronCurrentInfo, sent: bool) -> Result<(), Error> {
+        self.with_history(|history| history.record(ci, sent))
+    }
+
+    /// Devuelve si hay current_info del historial que no se publicaron.
+    pub fn has_unsent_samples(&self) -> Result<bool, Error> {
+        self.with_history(|history| history.has_unsent())
+    }
+
+    /// Devuelve las current_info del historial que no se publicaron, y las da por publicadas.
+    pub fn take_unsent_samples(&self) -> Result<Vec<DronCurrentInfo>, Error> {
+        self.with_history(|history| history.take_unsent())
+    }
+
+    /// Devuelve las últimas current_info del dron, de la más vieja a la más nueva.
+    pub fn get_history(&self) -> Result<Vec<DronCurrentInfo>, Error> {
+        self.with_history(|history| history.get_samples())
+    }
+
+    /// Suma al desgaste del dron `distance` metros volados en `flight_time`.
+    pub fn record_wear(&self, distance: f64, flight_time: Duration) -> Result<(), Error> {
+        self.with_wear(|wear| wear.record(distance, flight_time))
+    }
+
+    /// Devuelve si el desgaste del dron alcanzó alguno de los umbrales de `schedule`.
+    pub fn needs_maintenance(&self, schedule: &MaintenanceSchedule) -> Result<bool, Error> {
+        self.with_wear(|wear| wear.needs_maintenance(schedule))
+    }
+
+    /// Vuelve a cero el desgaste del dron, luego de un mantenimiento.
+    pub fn reset_wear(&self) -> Result<(), Error> {
+        self.with_wear(|wear| wear.reset())
+    }
+
+    pub fn get_wear(&self) -> Result<DronWear, Error> {
+        self.with_wear(|wear| *wear)
+    }
+
+    /// Toma lock y aplica `f` al desgaste del dron.
+    fn with_wear<T>(&self, f: impl FnOnce(&mut DronWear) -> T) -> Result<T, Error> {
+        if let Ok(mut wear) = self.wear.lock() {
+            return Ok(f(&mut wear));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock del desgaste del dron.",
+        ))
+    }
+
     // []
-    pub fn get_distance_to(&self, destination: (f64, f64)) -> Result<f64, Error> {
+    pub fn get_distance_to(&self, destination: GeoPosition) -> Result<f64, Error> {
         if let Ok(ci) = self.current_info.lock() {
-            return Ok(ci.get_distance_to(destination));
+            return Ok(ci.get_distance_to(destination, self.distance_model));
         }
         Err(Error::new(
             ErrorKind::Other,
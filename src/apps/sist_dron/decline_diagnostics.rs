@@ -0,0 +1,169 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    apps::{apps_mqtt_topics::AppsMqttTopics, incident_data::incident_info::IncidentInfo},
+    mqtt::{client::mqtt_client::MQTTClient, mqtt_utils::mqtt_error::MqttError},
+};
+
+/// Motivo por el que un dron no atiende un incidente.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DeclineReason {
+    OutOfRange,       // el incidente está fuera de su radio de operación
+    NotEnoughBattery, // no le alcanza la batería para ir al incidente y volver
+    OnStandby,        // mientras se elegía, lo detuvieron o lo llamaron a la base
+}
+
+impl DeclineReason {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            DeclineReason::OutOfRange => 1,
+            DeclineReason::NotEnoughBattery => 2,
+            DeclineReason::OnStandby => 3,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            1 => Ok(DeclineReason::OutOfRange),
+            2 => Ok(DeclineReason::NotEnoughBattery),
+            3 => Ok(DeclineReason::OnStandby),
+            _ => Err(invalid_declined()),
+        }
+    }
+
+    /// Devuelve el motivo, tal como se lo muestra en los logs.
+    pub fn description(&self) -> &'static str {
+        match self {
+            DeclineReason::OutOfRange => "está fuera de mi radio de operación",
+            DeclineReason::NotEnoughBattery => "no alcanza la batería para ir y volver",
+            DeclineReason::OnStandby => "me detuvieron o me llamaron a la base",
+        }
+    }
+}
+
+/// Aviso de que un dron no atiende un incidente, con el motivo y su distancia en metros al centro de su rango.
+/// Lo publica el dron por el topic `dron-diagnostics`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DeclinedIncident {
+    dron_id: u8,
+    inc_info: IncidentInfo,
+    reason: DeclineReason,
+    distance_to_range_center: f64,
+}
+
+impl DeclinedIncident {
+    pub fn new(
+        dron_id: u8,
+        inc_info: IncidentInfo,
+        reason: DeclineReason,
+        distance_to_range_center: f64,
+    ) -> Self {
+        Self {
+            dron_id,
+            inc_info,
+            reason,
+            distance_to_range_center,
+        }
+    }
+
+    pub fn get_dron_id(&self) -> u8 {
+        self.dron_id
+    }
+
+    pub fn get_inc_info(&self) -> IncidentInfo {
+        self.inc_info
+    }
+
+    pub fn get_reason(&self) -> DeclineReason {
+        self.reason
+    }
+
+    /// Devuelve la distancia, en metros, del incidente al centro del rango del dron.
+    pub fn get_distance_to_range_center(&self) -> f64 {
+        self.distance_to_range_center
+    }
+
+    /// Convierte el aviso a bytes: el id del dron, el incidente, el motivo y la distancia.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.dron_id];
+        bytes.extend(self.inc_info.to_bytes());
+        bytes.push(self.reason.to_byte());
+        bytes.extend_from_slice(&self.distance_to_range_center.to_be_bytes());
+        bytes
+    }
+
+    /// Obtiene el aviso a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() != 12 {
+            return Err(invalid_declined());
+        }
+        let inc_info =
+            IncidentInfo::from_bytes(bytes[1..3].to_vec())?.ok_or_else(invalid_declined)?;
+        let reason = DeclineReason::from_byte(bytes[3])?;
+        let mut distance = [0; 8];
+        distance.copy_from_slice(&bytes[4..]);
+        Ok(Self::new(bytes[0], inc_info, reason, f64::from_be_bytes(distance)))
+    }
+}
+
+fn invalid_declined() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Aviso de incidente declinado inválido.",
+    )
+}
+
+/// Publica por el topic `dron-diagnostics` los incidentes que el dron no atiende, y por qué.
+/// Son solamente informativos, por lo que se publican con qos 0.
+#[derive(Debug)]
+pub struct DeclineDiagnostics {
+    mqtt_client: Arc<Mutex<MQTTClient>>,
+}
+
+impl DeclineDiagnostics {
+    pub fn new(mqtt_client: Arc<Mutex<MQTTClient>>) -> Self {
+        Self { mqtt_client }
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            mqtt_client: self.mqtt_client.clone(),
+        }
+    }
+
+    pub fn publish(&self, declined: &DeclinedIncident) -> Result<(), Error> {
+        match self.mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let topic = AppsMqttTopics::DronDiagnosticsTopic.to_str();
+                mqtt_client.mqtt_publish(topic, &declined.to_bytes(), 0)?;
+                Ok(())
+            }
+            Err(_) => Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeclineReason, DeclinedIncident};
+    use crate::apps::incident_data::{
+        incident_info::IncidentInfo, incident_source::IncidentSource,
+    };
+
+    #[test]
+    fn test_1_el_aviso_se_convierte_a_bytes_y_de_vuelta() {
+        let inc_info = IncidentInfo::new(4, IncidentSource::Automated);
+        let declined = DeclinedIncident::new(2, inc_info, DeclineReason::OutOfRange, 1520.5);
+
+        let recovered = DeclinedIncident::from_bytes(declined.to_bytes()).unwrap();
+        assert_eq!(recovered, declined);
+        assert_eq!(recovered.get_reason(), DeclineReason::OutOfRange);
+        assert!(DeclinedIncident::from_bytes(vec![2, 4, 1, 1]).is_err());
+        let mut invalid_reason = declined.to_bytes();
+        invalid_reason[3] = 9;
+        assert!(DeclinedIncident::from_bytes(invalid_reason).is_err());
+    }
+}
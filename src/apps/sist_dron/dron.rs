@@ -1,25 +1,28 @@
 use std::{
-    collections::HashMap, fs, io::{self, Error, ErrorKind}, sync::{mpsc, Arc, Mutex}, thread::{self, JoinHandle}
+    collections::HashMap, io::Error, sync::{mpsc, Arc, Mutex}, thread::{self, sleep, JoinHandle}, time::{Duration, Instant}
 };
 
-use std::sync::mpsc::Receiver as MpscReceiver;
-
-use crate::apps::{
-    apps_mqtt_topics::AppsMqttTopics, common_clients::join_all_threads,
-    sist_dron::dron_state::DronState,
-};
-use crate::apps::{
-    common_clients::there_are_no_more_publish_msgs, incident_data::incident_info::IncidentInfo,
-};
+use crate::apps::{apps_mqtt_topics::AppsMqttTopics, sist_dron::dron_state::DronState};
+use crate::apps::geo_position::GeoPosition;
+use crate::apps::incident_data::incident_info::IncidentInfo;
 use crate::logging::string_logger::StringLogger;
-use crate::mqtt::{client::mqtt_client::MQTTClient, messages::publish_message::PublishMessage};
+use crate::mqtt::{client::{mqtt_client::MQTTClient, mqtt_client_delivery_token::DeliveryOutcome}, messages::publish_message::PublishMessage};
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 
 use super::{
-    battery_manager::BatteryManager, data::Data, dron_current_info::DronCurrentInfo,
-    dron_logic::DronLogic, sist_dron_properties::SistDronProperties,
+    battery_manager::BatteryManager, charging_stations::ChargingStations, data::Data, dron_current_info::DronCurrentInfo,
+    decline_diagnostics::DeclineDiagnostics, dron_config::DronConfig, dron_heartbeat::DronHeartbeat, dron_logic::DronLogic, fleet_state::FleetState, flight_settings::FlightSettings, handoff::Handoff, sim_clock::SimClock, telemetry_history::TelemetryBatch,
+    incident_arbitration::{CoordinationMode, IncidentCandidates}, incident_attended::IncidentAttended, incident_presence::IncidentPresence,
+    patrol_manager::PatrolManager, pending_incidents::PendingIncidents, patrol_route::PatrolRoute, sist_dron_properties::SistDronProperties, supervisor::Supervisor,
 };
 
-type DistancesType = Arc<Mutex<HashMap<IncidentInfo, ((f64, f64), Vec<(u8, f64)>)>>>; // (inc_info, ( (inc_pos),(dron_id, distance_to_incident)) )
+type DistancesType = Arc<Mutex<HashMap<IncidentInfo, IncidentCandidates>>>; // (inc_info, (inc_pos, postulaciones de los drones))
+
+/// Cada cuánto los hilos que esperan mensajes por un channel verifican si el dron se está apagando.
+pub const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Cantidad de current_info que pueden esperar a ser publicadas. Si la publicación se demora y se llena, los
+/// componentes que las envían esperan a que se libere lugar.
+pub const CI_CHANNEL_CAPACITY: usize = 32;
 
 /// Struct que representa a cada uno de los drones del sistema de vigilancia.
 /// Posee componentes para manejar su lógica de procesamiento de incidentes, y gestionar su batería y
@@ -33,16 +36,25 @@ pub struct Dron {
     // Constantes cargadas desde un arch de configuración
     dron_properties: SistDronProperties,
 
+    // Estaciones de carga, y cuáles ocupan otros drones
+    charging_stations: ChargingStations,
+
+    // Recorrido que patrulla mientras espera incidentes, si está configurado
+    patrol_route: Option<PatrolRoute>,
+
     logger: StringLogger,
 
     drone_distances_by_inc: DistancesType,
+    // Desde cuándo se encuentra cada dron en el incidente que atiende
+    incident_presence: IncidentPresence,
     qos: u8,
 }
 
 impl Dron {
-    /// Crea un Dron. Dron se inicia con batería al 100%, desde la posición del range_center, con estado activo.
-    pub fn new(id: u8, lat: f64, lon: f64, logger: StringLogger) -> Result<Self, Error> {
-        let dron = Self::new_internal(id, lat, lon, logger)?;
+    /// Crea un Dron con la configuración `config`, cargada para él. Dron se inicia con batería al 100%,
+    /// desde la posición del range_center, con estado activo.
+    pub fn new(id: u8, initial_position: GeoPosition, config: &DronConfig, logger: StringLogger) -> Result<Self, Error> {
+        let dron = Self::new_internal(id, initial_position, config, logger)?;
         dron.logger.log(format!("Dron: Iniciado dron {:?}", id));
 
         Ok(dron)
@@ -57,10 +69,11 @@ impl Dron {
     }
 
     /// Publica su posición inicial y lanza los hilos necesarios para el funcionamiento del dron.
+    /// Cada hilo corre su componente supervisado: si entra en pánico se lo reinicia, y si sigue fallando se apaga
+    /// el dron (ver `Supervisor`).
     pub fn spawn_threads(
         &mut self,
         mqtt_client: MQTTClient,
-        mqtt_rx: MpscReceiver<PublishMessage>,
     ) -> Result<Vec<JoinHandle<()>>, Error> {
         let mut children: Vec<JoinHandle<()>> = vec![];
         let mqtt_client_sh = Arc::new(Mutex::new(mqtt_client));
@@ -70,41 +83,195 @@ impl Dron {
 
         // Lanza hilos
         let (process_inc_tx, process_inc_rx) = mpsc::channel::<()>();
-        let (ci_tx, ci_rx) = mpsc::channel::<DronCurrentInfo>();
-        children.push(self.spawn_for_update_battery(ci_tx.clone(), process_inc_tx.clone()));
+        let (ci_tx, ci_rx) = mpsc::sync_channel::<DronCurrentInfo>(CI_CHANNEL_CAPACITY);
+        // Para pedir que lo releven si debe ir a cargarse mientras atiende un incidente, y relevar a otros.
+        let handoff = Handoff::new(
+            FleetState::new(&self.dron_properties),
+            Arc::new(Mutex::new(PendingIncidents::new())),
+            self.dron_properties.get_coordination_mode(),
+            mqtt_client_sh.clone(),
+            self.qos,
+        );
+        children.push(self.spawn_for_update_battery(ci_tx.clone(), process_inc_tx.clone(), Some(handoff.clone_ref())));
+        if let Some(handle) = self.spawn_for_patrol(ci_tx.clone()) {
+            children.push(handle);
+        }
 
         children.push(self.spawn_recv_ci_and_publish(ci_rx, mqtt_client_sh.clone()));
-        self.subscribe_to_topics(mqtt_client_sh.clone(), mqtt_rx, ci_tx, process_inc_tx, process_inc_rx)?;
+        children.push(self.spawn_for_heartbeat(mqtt_client_sh.clone()));
+        if self.dron_properties.reports_attended() {
+            children.push(self.spawn_for_attended_report(mqtt_client_sh.clone()));
+        }
+        children.push(self.subscribe_to_topics(mqtt_client_sh.clone(), ci_tx, process_inc_tx, process_inc_rx, handoff)?);
 
         Ok(children)
     }
 
-    /// Hilo que se encarga de actualizar la batería del dron.
-    fn spawn_for_update_battery(&self, ci_tx: mpsc::Sender<DronCurrentInfo>, process_inc_tx: mpsc::Sender<()>) -> JoinHandle<()> {
+    /// Apaga el dron: sus hilos terminan, y antes de desconectarse del broker publica una última current_info
+    /// en estado `Offline`, para que los demás drones y monitoreo sepan que ya no está disponible.
+    pub fn shutdown(&self) {
+        self.logger.log("Dron: apagando.".to_string());
+        self.data.request_shutdown();
+    }
+
+    /// Hilo que se encarga de actualizar la batería del dron. Si recibe `handoff`, antes de ir a cargarse mientras
+    /// atiende un incidente pide que lo releven.
+    fn spawn_for_update_battery(&self, ci_tx: mpsc::SyncSender<DronCurrentInfo>, process_inc_tx: mpsc::Sender<()>, handoff: Option<Handoff>) -> JoinHandle<()> {
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            self_clone.supervisor().run("batería", || {
+                let mut battery_manager = BatteryManager::new(
+                    self_clone.data.clone_ref(),
+                    self_clone.dron_properties,
+                    self_clone.charging_stations.clone_ref(),
+                    self_clone.logger.clone_ref(),
+                    ci_tx.clone(),
+                    process_inc_tx.clone()
+                );
+                if let Some(handoff) = &handoff {
+                    battery_manager = battery_manager.with_handoff(handoff.clone_ref());
+                }
+                battery_manager.run();
+            });
+        })
+    }
+
+    /// Hilo que se encarga de patrullar el recorrido mientras el dron espera incidentes, si tiene uno configurado.
+    fn spawn_for_patrol(&self, ci_tx: mpsc::SyncSender<DronCurrentInfo>) -> Option<JoinHandle<()>> {
+        let route = self.patrol_route.as_ref()?.clone_ref();
+        let self_clone = self.clone_ref();
+        Some(thread::spawn(move || {
+            self_clone.supervisor().run("patrullaje", || {
+                let mut patrol_manager = PatrolManager::new(
+                    self_clone.data.clone_ref(),
+                    self_clone.dron_properties,
+                    route.clone_ref(),
+                    self_clone.logger.clone_ref(),
+                    ci_tx.clone(),
+                );
+                patrol_manager.run();
+            });
+        }))
+    }
+
+    /// Hilo que publica el latido del dron por el topic `dron-health`, cada el intervalo configurado, hasta que se apague.
+    fn spawn_for_heartbeat(&self, mqtt_client: Arc<Mutex<MQTTClient>>) -> JoinHandle<()> {
         let self_clone = self.clone_ref();
+        let started = Instant::now();
         thread::spawn(move || {
-            let mut battery_manager = BatteryManager::new(
-                self_clone.data,
-                self_clone.dron_properties,
-                self_clone.logger,
-                ci_tx,
-                process_inc_tx
-            );
-            battery_manager.run();
+            let interval = self_clone.dron_properties.get_heartbeat_interval();
+            self_clone.supervisor().run("latido", || {
+                while !self_clone.data.is_shutting_down() {
+                    if let Err(e) = self_clone.publish_heartbeat(started.elapsed(), &mqtt_client) {
+                        self_clone.logger.log(format!("Error al publicar el latido: {:?}.", e));
+                    }
+                    // Espera el intervalo de a partes, para notar enseguida si el dron se apaga.
+                    let next_heartbeat = Instant::now() + interval;
+                    while !self_clone.data.is_shutting_down() && Instant::now() < next_heartbeat {
+                        sleep(SHUTDOWN_CHECK_INTERVAL.min(next_heartbeat - Instant::now()));
+                    }
+                }
+            });
         })
     }
 
+    /// Publica el latido del dron, con qos 0: es liviano, y si se pierde alguno lo reemplaza el siguiente.
+    fn publish_heartbeat(&self, uptime: Duration, mqtt_client: &Arc<Mutex<MQTTClient>>) -> Result<(), Error> {
+        let heartbeat = DronHeartbeat::new(
+            self.data.get_id()?,
+            self.data.get_battery_lvl()?,
+            self.data.get_state()?,
+            uptime,
+            self.dron_properties.get_heartbeat_interval(),
+        );
+        match mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let topic = AppsMqttTopics::DronHealthTopic.to_str();
+                mqtt_client.mqtt_publish(topic, &heartbeat.to_bytes(), 0)?;
+                Ok(())
+            }
+            Err(_) => Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        }
+    }
+
+    /// Hilo que espera a que el dron y el otro que atiende su incidente permanezcan en él `stay_at_inc_time`, y
+    /// entonces avisa por el topic `inc-attended` que se lo atendió. Lo avisa solamente el de menor id, para que
+    /// monitoreo reciba un único aviso por incidente.
+    fn spawn_for_attended_report(&self, mqtt_client: Arc<Mutex<MQTTClient>>) -> JoinHandle<()> {
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            self_clone.supervisor().run("aviso de atendidos", || {
+                let mut reported = None;
+                while !self_clone.data.is_shutting_down() {
+                    if let Err(e) = self_clone.report_attended_if_stayed(&mut reported, &mqtt_client) {
+                        self_clone.logger.log(format!("Error al avisar incidente atendido: {:?}.", e));
+                    }
+                    self_clone.data.sleep(SHUTDOWN_CHECK_INTERVAL);
+                }
+            });
+        })
+    }
+
+    /// Registra su propia presencia en el incidente que atiende, y si él y el otro dron permanecieron en él el tiempo
+    /// configurado, avisa que se lo atendió. `reported` es el último incidente que avisó, para no repetir el aviso.
+    fn report_attended_if_stayed(
+        &self,
+        reported: &mut Option<IncidentInfo>,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+    ) -> Result<(), Error> {
+        let ci = self.data.get_current_info()?;
+        let now = self.data.now();
+        self.incident_presence.update(&ci, now)?;
+        let current_inc = ci
+            .get_inc_id_to_resolve()
+            .filter(|_| ci.get_state() == DronState::ManagingIncident);
+        let Some(inc_info) = current_inc else {
+            *reported = None;
+            return Ok(());
+        };
+        if *reported == Some(inc_info) {
+            return Ok(());
+        }
+        let stay = self.dron_properties.get_stay_at_inc_time();
+        let Some(dron_ids) = self.incident_presence.attended_by(&inc_info, stay, now)? else {
+            return Ok(());
+        };
+        *reported = Some(inc_info);
+        if dron_ids.first() != Some(&ci.get_id()) {
+            return Ok(());
+        }
+
+        self.logger.log(format!(
+            "Los drones {:?} atendieron el inc {}, aviso a monitoreo.",
+            dron_ids,
+            inc_info.get_inc_id()
+        ));
+        let attended = IncidentAttended::new(inc_info, dron_ids);
+        match mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let topic = AppsMqttTopics::IncidentAttendedTopic.to_str();
+                mqtt_client.mqtt_publish(topic, &attended.to_bytes(), self.qos)?;
+                Ok(())
+            }
+            Err(_) => Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        }
+    }
+
     pub fn clone_ref(&self) -> Self {
         Self {
             data: self.data.clone_ref(),
             dron_properties: self.dron_properties,
+            charging_stations: self.charging_stations.clone_ref(),
+            patrol_route: self.patrol_route.as_ref().map(|route| route.clone_ref()),
             logger: self.logger.clone_ref(),
             drone_distances_by_inc: Arc::clone(&self.drone_distances_by_inc),
+            incident_presence: self.incident_presence.clone_ref(),
             qos: self.qos,
         }
     }
 
     /// Recibe por rx la current_info que se desea publicar, y la publica por MQTT.
+    /// Al apagarse el dron, publica la última current_info, en estado `Offline`, y se desconecta del broker.
     pub fn spawn_recv_ci_and_publish(
         &self,
         ci_rx: mpsc::Receiver<DronCurrentInfo>,
@@ -112,110 +279,238 @@ impl Dron {
     ) -> JoinHandle<()> {
         let self_clone = self.clone_ref();
         thread::spawn(move || {
-            for ci in ci_rx {
-                if let Err(e) = self_clone.publish_current_info(ci, &mqtt_client) {
-                    self_clone
-                        .logger
-                        .log(format!("Error al publicar la current_info: {:?}.", e));
-                }
-            }
+            self_clone.supervisor().run("publicación", || {
+                self_clone.recv_ci_and_publish(&ci_rx, &mqtt_client)
+            });
+            self_clone.go_offline(&mqtt_client);
         })
     }
 
-    /// Hace publish de su current info.
-    /// Le servirá a otros drones para ver la condición de los dos drones más cercanos y a monitoreo para mostrarlo en mapa.
-    pub fn publish_current_info(
+    /// Publica cada current_info que recibe por `ci_rx`, hasta que el dron se apague.
+    fn recv_ci_and_publish(
         &self,
-        ci: DronCurrentInfo,
+        ci_rx: &mpsc::Receiver<DronCurrentInfo>,
         mqtt_client: &Arc<Mutex<MQTTClient>>,
-    ) -> Result<(), Error> {
-        if let Ok(mut mqtt_client_lock) = mqtt_client.lock() {
-            let topic = AppsMqttTopics::DronTopic.to_str();
-            println!("[DEBUG TEMA ACK]: Por hacer publish:");
-            mqtt_client_lock.mqtt_publish(topic, &ci.to_bytes(), self.qos)?;
-            println!("[DEBUG TEMA ACK]: hecho el publish:");
-        };
-        Ok(())
+    ) {
+        while !self.data.is_shutting_down() {
+            let ci = match ci_rx.recv_timeout(SHUTDOWN_CHECK_INTERVAL) {
+                Ok(ci) => ci,
+                // Si se reconectó mientras no se movía, también completa su recorrido en monitoreo.
+                Err(mpsc::RecvTimeoutError::Timeout) => match self.data.has_unsent_samples() {
+                    Ok(true) if is_connected(mqtt_client) => match self.data.get_current_info() {
+                        Ok(ci) => ci,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                },
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            // Si la publicación se demoró y se acumularon current_info, se publican solamente las más recientes.
+            let pending = coalesce_pending(ci, ci_rx);
+            for ci in pending {
+                self.publish_or_record(ci, mqtt_client);
+            }
+        }
     }
 
-    /// Se suscribe a topics inc y dron, y lanza la recepción de mensajes y finalización.
-    fn subscribe_to_topics(
-        &mut self,
-        mqtt_client: Arc<Mutex<MQTTClient>>,
-        mqtt_rx: MpscReceiver<PublishMessage>,
-        ci_tx: mpsc::Sender<DronCurrentInfo>,
-        process_inc_tx: mpsc::Sender<()>,
-        process_inc_rx: mpsc::Receiver<()>,
-    ) -> Result<(), Error> {
-        self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::IncidentTopic.to_str())?;
-        self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::DronTopic.to_str())?;
-        self.receive_messages_from_subscribed_topics(mqtt_rx, ci_tx, process_inc_tx, process_inc_rx);
+    /// Publica la current_info; o si no tiene conexión, la guarda para publicarla al reconectarse.
+    fn publish_or_record(&self, ci: DronCurrentInfo, mqtt_client: &Arc<Mutex<MQTTClient>>) {
+        if !is_connected(mqtt_client) {
+            // Sin conexión no la publica, la guarda para publicarla junto con las demás al reconectarse.
+            if let Err(e) = self.data.record_sample(ci, false) {
+                self.logger.log(format!("Error al guardar la current_info: {:?}.", e));
+            }
+            return;
+        }
+        self.publish_unsent_history(mqtt_client);
+        if let Err(e) = self.data.record_sample(ci.clone(), true) {
+            self.logger.log(format!("Error al guardar la current_info: {:?}.", e));
+        }
+        if let Err(e) = self.publish_current_info(ci, mqtt_client) {
+            match MqttError::from_io_error(&e) {
+                // No se reintenta, la próxima current_info ya reemplaza a esta
+                Some(MqttError::AckTimeout(_)) => self
+                    .logger
+                    .log("No llegó el ack de la current_info, se publicará la siguiente.".to_string()),
+                _ => self
+                    .logger
+                    .log(format!("Error al publicar la current_info: {:?}.", e)),
+            }
+        }
+    }
 
-        Ok(())
+    /// Devuelve el supervisor de los componentes que corren en los hilos del dron.
+    fn supervisor(&self) -> Supervisor {
+        Supervisor::new(self.data.clone_ref(), self.logger.clone_ref())
     }
 
-    /// Se suscribe al topic recibido.
-    fn subscribe_to_topic(
+    /// Publica por el topic `dron-history` las current_info que no publicó mientras no tenía conexión con el server,
+    /// para que monitoreo complete el recorrido que hizo mientras tanto. Las divide en los lotes que hagan falta
+    /// para que cada uno entre en un publish, y los publica en orden.
+    fn publish_unsent_history(&self, mqtt_client: &Arc<Mutex<MQTTClient>>) {
+        let samples = match self.data.take_unsent_samples() {
+            Ok(samples) if !samples.is_empty() => samples,
+            Ok(_) => return,
+            Err(e) => {
+                self.logger.log(format!("Error al obtener el historial: {:?}.", e));
+                return;
+            }
+        };
+        let Ok(id) = self.data.get_id() else {
+            return;
+        };
+        let topic = AppsMqttTopics::DronHistoryTopic.to_str();
+        let batches = TelemetryBatch::new(id, samples).split(PublishMessage::max_payload_len(topic));
+        match mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let mut published = 0;
+                for batch in batches {
+                    match mqtt_client.mqtt_publish(topic, &batch.to_bytes(), self.qos) {
+                        Ok(_) => published += batch.get_samples().len(),
+                        Err(e) => {
+                            self.logger.log(format!("Error al publicar el historial: {:?}.", e));
+                            break;
+                        }
+                    }
+                }
+                self.logger.log(format!(
+                    "Dron: publicadas {} current_info no enviadas durante la desconexión.",
+                    published
+                ));
+            }
+            Err(_) => self.logger.log("Error al tomar lock del cliente mqtt.".to_string()),
+        }
+    }
+
+    /// Publica la última current_info, en estado `Offline`, y envía disconnect al broker.
+    fn go_offline(&self, mqtt_client: &Arc<Mutex<MQTTClient>>) {
+        match self.data.set_offline() {
+            Ok(ci) => {
+                if let Err(e) = self.publish_current_info(ci, mqtt_client) {
+                    self.logger
+                        .log(format!("Error al publicar la última current_info: {:?}.", e));
+                }
+            }
+            Err(e) => self.logger.log(format!("Error al pasar a Offline: {:?}.", e)),
+        }
+        if let Ok(mut mqtt_client) = mqtt_client.lock() {
+            match mqtt_client.mqtt_disconnect() {
+                Ok(_) => self.logger.log("Dron: desconectado del broker.".to_string()),
+                Err(e) => self.logger.log(format!("Error al desconectarse: {:?}.", e)),
+            }
+        }
+    }
+
+    /// Hace publish de su current info.
+    /// Le servirá a otros drones para ver la condición de los dos drones más cercanos y a monitoreo para mostrarlo en mapa.
+    /// Espera el resultado de la entrega, y si el server la confirmó, la registra como confirmada.
+    pub fn publish_current_info(
         &self,
+        ci: DronCurrentInfo,
         mqtt_client: &Arc<Mutex<MQTTClient>>,
-        topic: &str,
     ) -> Result<(), Error> {
-        if let Ok(mut mqtt_client) = mqtt_client.lock() {
-            mqtt_client.mqtt_subscribe(vec![((String::from(topic)), self.qos)])?;
-            self.logger
-                .log(format!("Dron: Suscripto a topic: {}", topic));
+        let token = match mqtt_client.lock() {
+            Ok(mut mqtt_client_lock) => {
+                let topic = AppsMqttTopics::DronTopic;
+                mqtt_client_lock.mqtt_publish_with_token(topic.to_str(), &ci.to_bytes(), self.qos)?
+            }
+            Err(_) => return Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        };
+        // Se espera sin el lock del cliente, para no frenar a los demás hilos que lo usan.
+        match token.wait()? {
+            outcome if outcome.is_confirmed() => self.data.set_confirmed_info(ci)?,
+            // Con qos 0 el server no confirma la recepción.
+            DeliveryOutcome::Sent => {}
+            outcome => self
+                .logger
+                .log(format!("El server no confirmó la current_info: {:?}.", outcome)),
         }
         Ok(())
     }
 
-    /// Recibe mensajes de los topics a los que se ha suscrito: inc y dron.
+    /// Se suscribe a topics inc, dron, dron-admin, a su propio dron-control/<id> y, si lo coordina el coordinador de la flota, dron-assign, o si no, dron-handoff; registrando el procesamiento de los mensajes que se reciban de ellos.
+    /// Devuelve el hilo que procesa los incidentes, que termina al apagarse el dron.
     /// (aux sist monitoreo actualiza el estado del incidente y hace publish a inc; dron hace publish a dron)
-    /// Lanza un hilo por cada mensaje recibido, para procesarlo, y espera a sus hijos.
-    fn receive_messages_from_subscribed_topics(
+    fn subscribe_to_topics(
         &mut self,
-        mqtt_rx: MpscReceiver<PublishMessage>,
-        ci_tx: mpsc::Sender<DronCurrentInfo>,
+        mqtt_client: Arc<Mutex<MQTTClient>>,
+        ci_tx: mpsc::SyncSender<DronCurrentInfo>,
         process_inc_tx: mpsc::Sender<()>,
         process_inc_rx: mpsc::Receiver<()>,
-    ) {
+        handoff: Handoff,
+    ) -> Result<JoinHandle<()>, Error> {
         // Módulo encargado de la lógica del dron al recibir PublishMessage'self_clone.
         let self_clone = self.clone_ref();
         let dron_logic = DronLogic::new(
             self_clone.data,
             self_clone.dron_properties,
+            self_clone.charging_stations,
             self_clone.logger,
             self_clone.drone_distances_by_inc.clone(),
             ci_tx,
-        );
+        )
+        .with_patrol(self_clone.patrol_route.is_some())
+        .with_incident_presence(self_clone.incident_presence)
+        .with_handoff(handoff)
+        .with_decline_diagnostics(DeclineDiagnostics::new(mqtt_client.clone()));
 
         //let (process_inc_tx, process_inc_rx) = mpsc::channel::<()>();
 
         // Hilo para controlar el vuelo del dron para ir a los incidentes [] aux: hilo nuevo
         let mut logic_clone = dron_logic.clone_ref();
         let logger_c = self.logger.clone_ref();
-        thread::spawn(move || {
-            if let Err(e) = logic_clone.listen_for_and_process_new_active_incident(process_inc_rx) {
-                logger_c.log(format!(
-                    "Error al procesar mensage recibido, process_rcvd_msg: {:?}.",
-                    e
-                ));
-            }
+        let supervisor = self.supervisor();
+        let handle = thread::spawn(move || {
+            supervisor.run("lógica de incidentes", || {
+                if let Err(e) = logic_clone.listen_for_and_process_new_active_incident(&process_inc_rx) {
+                    logger_c.log(format!(
+                        "Error al procesar mensage recibido, process_rcvd_msg: {:?}.",
+                        e
+                    ));
+                }
+            });
         });
 
-        // Recibe de mqtt
-        let mut children = vec![];
-        for publish_msg in mqtt_rx {
-            self.logger
+        self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::IncidentTopic.to_str(), &dron_logic, &process_inc_tx)?;
+        self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::DronTopic.to_str(), &dron_logic, &process_inc_tx)?;
+        self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::DronAdminTopic.to_str(), &dron_logic, &process_inc_tx)?;
+        let control_topic = AppsMqttTopics::dron_control_topic(self.data.get_id()?);
+        self.subscribe_to_topic(&mqtt_client, &control_topic, &dron_logic, &process_inc_tx)?;
+        // Si los incidentes los asigna el coordinador de la flota, recibe sus asignaciones.
+        if self.dron_properties.get_coordination_mode() == CoordinationMode::Central {
+            self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::DronAssignmentTopic.to_str(), &dron_logic, &process_inc_tx)?;
+        } else {
+            // Si no, el reemplazo de un dron que debe ir a cargarse lo elige él, y lo nombra en su pedido de relevo.
+            self.subscribe_to_topic(&mqtt_client, AppsMqttTopics::DronHandoffTopic.to_str(), &dron_logic, &process_inc_tx)?;
+        }
+
+        Ok(handle)
+    }
+
+    /// Se suscribe al topic recibido. Por cada mensaje que se reciba de él, lanza un hilo para procesarlo.
+    fn subscribe_to_topic(
+        &self,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+        topic: &str,
+        dron_logic: &DronLogic,
+        process_inc_tx: &mpsc::Sender<()>,
+    ) -> Result<(), Error> {
+        let self_clone = self.clone_ref();
+        let dron_logic = dron_logic.clone_ref();
+        let process_inc_tx = process_inc_tx.clone();
+        let handler = move |publish_msg: PublishMessage| {
+            self_clone
+                .logger
                 .log(format!("Dron: Recibo mensaje Publish: {:?}", publish_msg));
+            self_clone.spawn_process_recvd_msg_thread(publish_msg, dron_logic.clone_ref(), process_inc_tx.clone());
+        };
 
-            // Lanza un hilo para procesar el mensaje, y luego lo espera correctamente
-            let handle_thread =
-                self.spawn_process_recvd_msg_thread(publish_msg, dron_logic.clone_ref(), process_inc_tx.clone());
-            children.push(handle_thread);
+        if let Ok(mut mqtt_client) = mqtt_client.lock() {
+            mqtt_client.mqtt_subscribe_with_handler(topic, self.qos, handler)?;
+            self.logger
+                .log(format!("Dron: Suscripto a topic: {}", topic));
         }
-        there_are_no_more_publish_msgs(&self.logger);
-
-        join_all_threads(children);
+        Ok(())
     }
 
     /// Delega el procesamiento del `PublishMessage` recibido, al módulo `DronLogic`.
@@ -237,56 +532,47 @@ impl Dron {
         })
     }
 
-    fn leer_qos_desde_archivo(ruta_archivo: &str) -> Result<u8, io::Error> {
-        let contenido = fs::read_to_string(ruta_archivo)?;
-        let inicio = contenido.find("qos=").ok_or(io::Error::new(
-            ErrorKind::NotFound,
-            "No se encontró la etiqueta 'qos='",
-        ))?;
-        let valor_qos = contenido[inicio + 4..].trim().parse::<u8>().map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "El valor de QoS no es un número válido",
-            )
-        })?;
-        Ok(valor_qos)
-    }
-
     /// Dron se inicia con batería al 100%, desde la posición del range_center, con estado activo.
     /// Función utilizada para testear, no necesita broker address.
     fn new_internal(
         id: u8,
-        initial_lat: f64,
-        initial_lon: f64,
+        initial_position: GeoPosition,
+        config: &DronConfig,
         logger: StringLogger,
     ) -> Result<Self, Error> {
-        let qos = Dron::leer_qos_desde_archivo("src/apps/sist_dron/qos_dron.properties")?;
-        // Se cargan las constantes desde archivo de config.
-        let properties_file = "src/apps/sist_dron/sistema_dron.properties";
-        let mut dron_properties = SistDronProperties::new(properties_file)?;
+        // Las constantes se cargaron del archivo de config, con el range_center en la posición inicial.
+        let dron_properties = config.get_properties();
+        let charging_stations = config.get_charging_stations();
+        let patrol_route = config.get_patrol_route();
+        let qos = config.get_qos();
 
         let drone_distances_by_incident = Arc::new(Mutex::new(HashMap::new()));
         // Inicia desde el range_center, por lo cual tiene estado activo; y con batería al 100%.
-        dron_properties.set_range_center_position(initial_lat, initial_lon);
 
         let current_info = DronCurrentInfo::new(
             id,
-            initial_lat,
-            initial_lon,
+            initial_position,
             100,
             DronState::ExpectingToRecvIncident,
         );
-        let data = Data::new(current_info);
+        // Con time_scale distinto de 1, su tiempo transcurre más rápido que el real, para simulaciones.
+        let data = Data::new(current_info, FlightSettings::new(&dron_properties))
+            .with_clock(SimClock::scaled(dron_properties.get_time_scale()))
+            .with_history_size(dron_properties.get_telemetry_history_size())
+            .with_distance_model(dron_properties.get_distance_model());
 
         logger.log(format!(
-            "Dron {} creado en posición (lat, lon): {}, {}.",
-            id, initial_lat, initial_lon
+            "Dron {} creado en posición (lat, lon): {}.",
+            id, initial_position
         ));
         let dron = Dron {
             data,
             dron_properties,
+            charging_stations,
+            patrol_route,
             logger,
             drone_distances_by_inc: drone_distances_by_incident,
+            incident_presence: IncidentPresence::new(),
             qos,
         };
 
@@ -294,24 +580,54 @@ impl Dron {
     }
 }
 
+/// Junta `ci` con las current_info que esperan en `ci_rx` a ser publicadas. De las que solamente actualizan la
+/// posición, la batería o el vuelo de la anterior conserva la más reciente, para que el server reciba siempre la
+/// última posición; las que cambian el estado, el incidente o los pendientes del dron se conservan todas, porque
+/// los demás drones y monitoreo deben ver cada cambio.
+fn coalesce_pending(ci: DronCurrentInfo, ci_rx: &mpsc::Receiver<DronCurrentInfo>) -> Vec<DronCurrentInfo> {
+    let mut pending = vec![ci];
+    while let Ok(next) = ci_rx.try_recv() {
+        match pending.last_mut() {
+            Some(last) if next.supersedes(last) => *last = next,
+            _ => pending.push(next),
+        }
+    }
+    pending
+}
+
+/// Devuelve si el cliente está conectado al server.
+fn is_connected(mqtt_client: &Arc<Mutex<MQTTClient>>) -> bool {
+    mqtt_client
+        .lock()
+        .map(|mqtt_client| mqtt_client.is_connected())
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 
 mod test {
-    use super::Dron;
+    use super::{coalesce_pending, Dron, CI_CHANNEL_CAPACITY};
+    use crate::apps::geo_position::GeoPosition;
+    use crate::apps::sist_dron::dron_current_info::DronCurrentInfo;
+    use crate::apps::sist_dron::dron_config::{DronConfig, DEFAULT_CONFIG_FILE};
     use crate::apps::sist_dron::calculations::calculate_direction;
     use crate::apps::sist_dron::dron_state::DronState;
     use crate::logging::string_logger::StringLogger;
     use std::sync::mpsc;
 
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
     fn create_dron_4() -> Dron {
         let (str_logger_tx, _str_logger_rx) = mpsc::channel::<String>();
         let logger = StringLogger::new(str_logger_tx); // para testing alcanza con crearlo así.
 
         // Dron 4 inicia en: -34.60282, -58.38730
-        let lat = -34.60282;
-        let lon = -58.38730;
+        let initial_position = position(-34.60282, -58.38730);
 
-        Dron::new_internal(4, lat, lon, logger).unwrap()
+        let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, 4, initial_position).unwrap();
+        Dron::new_internal(4, initial_position, &config, logger).unwrap()
     }
 
     #[test]
@@ -339,8 +655,8 @@ mod test {
     #[test]
     fn test_3a_calculate_direction_da_la_direccion_esperada() {
         // Dados destino y origen
-        let origin = (0.0, 0.0); // desde el (0,0)
-        let destination = (4.0, -3.0);
+        let origin = position(0.0, 0.0); // desde el (0,0)
+        let destination = position(4.0, -3.0);
         let hip = 5.0; // hipotenusa da 5;
 
         let dir = calculate_direction(origin, destination);
@@ -349,8 +665,8 @@ mod test {
         let expected_dir = (4.0 / hip, -3.0 / hip);
         assert_eq!(dir, expected_dir);
         // En "hip" cantidad de pasos, se llega a la posición de destino
-        assert_eq!(origin.0 + dir.0 * hip, destination.0);
-        assert_eq!(origin.1 + dir.1 * hip, destination.1);
+        assert_eq!(origin.get_latitude() + dir.0 * hip, destination.get_latitude());
+        assert_eq!(origin.get_longitude() + dir.1 * hip, destination.get_longitude());
     }
 
     #[test]
@@ -359,7 +675,7 @@ mod test {
 
         // Dados destino y origen
         let origin = dron.data.get_current_position().unwrap(); // desde (incident_position, candidate_dron) que no es el (0,0)
-        let destination = (origin.0 + 4.0, origin.1 - 3.0);
+        let destination = position(origin.get_latitude() + 4.0, origin.get_longitude() - 3.0);
         let hip = 5.0; // hipotenusa da 5;
 
         let dir = calculate_direction(origin, destination);
@@ -368,7 +684,44 @@ mod test {
         let expected_dir = (4.0 / hip, -3.0 / hip);
         assert_eq!(dir, expected_dir);
         // En "hip" cantidad de pasos, se llega a la posición de destino
-        assert_eq!(origin.0 + dir.0 * hip, destination.0);
-        assert_eq!(origin.1 + dir.1 * hip, destination.1);
+        assert_eq!(origin.get_latitude() + dir.0 * hip, destination.get_latitude());
+        assert_eq!(origin.get_longitude() + dir.1 * hip, destination.get_longitude());
+    }
+
+    #[test]
+    fn test_4_al_apagarse_el_dron_terminan_sus_hilos_y_queda_offline() {
+        let dron = create_dron_4();
+        let (ci_tx, _ci_rx) = mpsc::sync_channel(CI_CHANNEL_CAPACITY);
+        let (process_inc_tx, _process_inc_rx) = mpsc::channel();
+        let battery_handle = dron.spawn_for_update_battery(ci_tx, process_inc_tx, None);
+
+        dron.shutdown();
+
+        assert!(battery_handle.join().is_ok());
+        let last_ci = dron.data.set_offline().unwrap();
+        assert_eq!(last_ci.get_state(), DronState::Offline);
+        assert!(last_ci.get_flying_info().is_none());
+    }
+
+    #[test]
+    fn test_5_de_las_current_info_acumuladas_se_publican_las_ultimas_posiciones_y_cada_cambio_de_estado() {
+        let (ci_tx, ci_rx) = mpsc::sync_channel(CI_CHANNEL_CAPACITY);
+        let flying = |lon| DronCurrentInfo::new(4, position(-34.6, lon), 90, DronState::Flying);
+        for lon in [-58.401, -58.402] {
+            ci_tx.send(flying(lon)).unwrap();
+        }
+        let managing = DronCurrentInfo::new(4, position(-34.6, -58.403), 90, DronState::ManagingIncident);
+        ci_tx.send(managing.clone()).unwrap();
+        ci_tx.send(DronCurrentInfo::new(4, position(-34.6, -58.403), 89, DronState::ManagingIncident)).unwrap();
+
+        let pending = coalesce_pending(flying(-58.400), &ci_rx);
+        assert_eq!(
+            pending,
+            vec![
+                flying(-58.402),
+                DronCurrentInfo::new(4, position(-34.6, -58.403), 89, DronState::ManagingIncident)
+            ]
+        );
+        assert!(managing.supersedes(&pending[1]));
     }
 }
@@ -0,0 +1,72 @@
+use std::io::{Error, ErrorKind};
+
+/// Comando de administración que se le envía a los drones por el topic `dron-admin`, como texto.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DronAdminCommand {
+    /// `shutdown` apaga a todos los drones, y `shutdown <dron_id>` solamente al indicado. El dron publica una última
+    /// current_info en estado `Offline`, se desconecta del broker y termina sus hilos.
+    Shutdown(Option<u8>),
+}
+
+impl DronAdminCommand {
+    pub fn from_bytes(payload: Vec<u8>) -> Result<Self, Error> {
+        let command = String::from_utf8(payload).map_err(|_| invalid_command())?;
+        let mut words = command.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("shutdown"), None) => DronAdminCommand::Shutdown(None),
+            (Some("shutdown"), Some(id)) => {
+                DronAdminCommand::Shutdown(Some(id.parse().map_err(|_| invalid_command())?))
+            }
+            _ => return Err(invalid_command()),
+        };
+        if words.next().is_some() {
+            return Err(invalid_command());
+        }
+        Ok(command)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            DronAdminCommand::Shutdown(None) => "shutdown".to_string().into_bytes(),
+            DronAdminCommand::Shutdown(Some(id)) => format!("shutdown {}", id).into_bytes(),
+        }
+    }
+
+    /// Devuelve si el comando es para el dron `dron_id`.
+    pub fn applies_to(&self, dron_id: u8) -> bool {
+        match self {
+            DronAdminCommand::Shutdown(target) => target.is_none_or(|id| id == dron_id),
+        }
+    }
+}
+
+fn invalid_command() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Comando de administración de dron inválido.",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::DronAdminCommand;
+
+    #[test]
+    fn test_1_se_parsea_el_comando_y_se_sabe_a_que_drones_aplica() {
+        let to_all = DronAdminCommand::from_bytes(b"shutdown".to_vec()).unwrap();
+        assert!(to_all.applies_to(1) && to_all.applies_to(7));
+
+        let to_dron_3 = DronAdminCommand::from_bytes(b" shutdown 3\n".to_vec()).unwrap();
+        assert_eq!(to_dron_3, DronAdminCommand::Shutdown(Some(3)));
+        assert!(to_dron_3.applies_to(3));
+        assert!(!to_dron_3.applies_to(1));
+        assert_eq!(
+            DronAdminCommand::from_bytes(to_dron_3.to_bytes()).unwrap(),
+            to_dron_3
+        );
+
+        assert!(DronAdminCommand::from_bytes(b"reboot".to_vec()).is_err());
+        assert!(DronAdminCommand::from_bytes(b"shutdown tres".to_vec()).is_err());
+        assert!(DronAdminCommand::from_bytes(b"shutdown 3 4".to_vec()).is_err());
+    }
+}
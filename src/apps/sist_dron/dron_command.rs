@@ -0,0 +1,100 @@
+use std::io::{Error, ErrorKind};
+
+/// Comando de control que se le envía a un dron por su topic `dron-control/<dron_id>`, como texto, para ajustar su
+/// comportamiento mientras funciona.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DronCommand {
+    /// `speed <m/s>` establece su velocidad de crucero, que usa desde el siguiente paso de vuelo.
+    SetSpeed(f64),
+    /// `battery <min> <max>` establece el nivel de batería por debajo del cual va a cargarse, y hasta el cual se carga.
+    SetBatteryThresholds { min_operational: u8, max: u8 },
+    /// `recall` lo llama a la base: deja el incidente que atiende, vuelve al centro de su rango y no toma incidentes
+    /// hasta recibir `resume`.
+    Recall,
+    /// `pause` lo detiene donde se encuentra, y no toma incidentes hasta recibir `resume`.
+    Pause,
+    /// `resume` lo vuelve a poner en funcionamiento, luego de `pause` o de `recall`.
+    Resume,
+}
+
+impl DronCommand {
+    pub fn from_bytes(payload: Vec<u8>) -> Result<Self, Error> {
+        let command = String::from_utf8(payload).map_err(|_| invalid_command())?;
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["speed", speed] => {
+                let speed: f64 = speed.parse().map_err(|_| invalid_command())?;
+                if !speed.is_finite() || speed <= 0.0 {
+                    return Err(invalid_command());
+                }
+                Ok(DronCommand::SetSpeed(speed))
+            }
+            ["battery", min_operational, max] => {
+                let min_operational: u8 = min_operational.parse().map_err(|_| invalid_command())?;
+                let max: u8 = max.parse().map_err(|_| invalid_command())?;
+                if max == 0 || min_operational >= max {
+                    return Err(invalid_command());
+                }
+                Ok(DronCommand::SetBatteryThresholds { min_operational, max })
+            }
+            ["recall"] => Ok(DronCommand::Recall),
+            ["pause"] => Ok(DronCommand::Pause),
+            ["resume"] => Ok(DronCommand::Resume),
+            _ => Err(invalid_command()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let command = match self {
+            DronCommand::SetSpeed(speed) => format!("speed {}", speed),
+            DronCommand::SetBatteryThresholds { min_operational, max } => {
+                format!("battery {} {}", min_operational, max)
+            }
+            DronCommand::Recall => "recall".to_string(),
+            DronCommand::Pause => "pause".to_string(),
+            DronCommand::Resume => "resume".to_string(),
+        };
+        command.into_bytes()
+    }
+}
+
+fn invalid_command() -> Error {
+    Error::new(ErrorKind::InvalidData, "Comando de control de dron inválido.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::DronCommand;
+
+    #[test]
+    fn test_1_se_parsean_los_comandos_y_se_rechazan_los_invalidos() {
+        assert_eq!(
+            DronCommand::from_bytes(b" speed 12.5\n".to_vec()).unwrap(),
+            DronCommand::SetSpeed(12.5)
+        );
+        let thresholds = DronCommand::SetBatteryThresholds {
+            min_operational: 30,
+            max: 90,
+        };
+        assert_eq!(
+            DronCommand::from_bytes(b"battery 30 90".to_vec()).unwrap(),
+            thresholds
+        );
+        for command in [
+            DronCommand::SetSpeed(12.5),
+            thresholds,
+            DronCommand::Recall,
+            DronCommand::Pause,
+            DronCommand::Resume,
+        ] {
+            assert_eq!(DronCommand::from_bytes(command.to_bytes()).unwrap(), command);
+        }
+
+        assert!(DronCommand::from_bytes(b"speed 0".to_vec()).is_err());
+        assert!(DronCommand::from_bytes(b"speed rapido".to_vec()).is_err());
+        assert!(DronCommand::from_bytes(b"battery 90 30".to_vec()).is_err());
+        assert!(DronCommand::from_bytes(b"battery 30".to_vec()).is_err());
+        assert!(DronCommand::from_bytes(b"pause 3".to_vec()).is_err());
+        assert!(DronCommand::from_bytes(b"land".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    io::{Error, ErrorKind},
+    str::FromStr,
+};
+
+use crate::apps::{geo_position::GeoPosition, properties::Properties};
+
+use super::{
+    charging_stations::ChargingStations, patrol_route::PatrolRoute,
+    sist_dron_properties::SistDronProperties,
+};
+
+/// Archivo de configuración que usan los drones si no se indica otro.
+pub const DEFAULT_CONFIG_FILE: &str = "src/apps/sist_dron/sistema_dron.properties";
+/// Qos con que el dron publica y se suscribe, si el archivo no define `qos`.
+const DEFAULT_QOS: u8 = 1;
+
+/// Properties del archivo de configuración, vistas por un dron en particular: cada propiedad puede definirse para
+/// el dron `dron_id` como `<propiedad>.<dron_id>`, que tiene prioridad sobre la común a todos los drones.
+/// Los errores nombran la propiedad, tal como figura en el archivo, cuyo valor falta o es inválido.
+pub struct DronProperties {
+    properties: Properties,
+    dron_id: Option<u8>,
+}
+
+impl DronProperties {
+    pub fn new(properties: Properties, dron_id: u8) -> Self {
+        Self {
+            properties,
+            dron_id: Some(dron_id),
+        }
+    }
+
+    /// Carga del archivo `config_file` las properties comunes a todos los drones, sin las propias de ninguno.
+    pub fn common_from_file(config_file: &str) -> Result<Self, Error> {
+        Ok(Self {
+            properties: read_properties(config_file)?,
+            dron_id: None,
+        })
+    }
+
+    /// Devuelve con qué clave se definió la propiedad `key` para el dron, y su valor; o None si no está definida.
+    pub fn get(&self, key: &str) -> Option<(String, &str)> {
+        if let Some(dron_id) = self.dron_id {
+            let dron_key = format!("{}.{}", key, dron_id);
+            if let Some(value) = self.properties.get(&dron_key) {
+                return Some((dron_key, value));
+            }
+        }
+        self.properties
+            .get(key)
+            .map(|value| (key.to_string(), value.as_str()))
+    }
+
+    /// Devuelve el valor de la propiedad `key`, que debe estar definida y cumplir `is_valid`.
+    pub fn get_required<T: FromStr>(
+        &self,
+        key: &str,
+        is_valid: impl Fn(&T) -> bool,
+    ) -> Result<T, Error> {
+        self.get_optional(key, is_valid)?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Falta la propiedad {}.", key),
+            )
+        })
+    }
+
+    /// Devuelve el valor de la propiedad `key`, que si está definida debe cumplir `is_valid`; o None si no lo está.
+    pub fn get_optional<T: FromStr>(
+        &self,
+        key: &str,
+        is_valid: impl Fn(&T) -> bool,
+    ) -> Result<Option<T>, Error> {
+        let Some((used_key, value)) = self.get(key) else {
+            return Ok(None);
+        };
+        match value.parse::<T>() {
+            Ok(parsed) if is_valid(&parsed) => Ok(Some(parsed)),
+            _ => Err(invalid_value(&used_key, value)),
+        }
+    }
+}
+
+/// Lee el archivo de configuración `config_file`.
+fn read_properties(config_file: &str) -> Result<Properties, Error> {
+    Properties::new(config_file).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("No se pudo leer la configuración {}: {}", config_file, e),
+        )
+    })
+}
+
+/// Devuelve el qos con que se publica y se suscribe, o `DEFAULT_QOS` si no está definido.
+pub fn get_qos(properties: &DronProperties) -> Result<u8, Error> {
+    Ok(properties
+        .get_optional("qos", |qos: &u8| *qos <= 2)?
+        .unwrap_or(DEFAULT_QOS))
+}
+
+/// Error para el valor `value`, inválido para la propiedad `key`.
+pub fn invalid_value(key: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Valor inválido para {}: {:?}", key, value),
+    )
+}
+
+/// Configuración de un dron, cargada de un único archivo de properties con las propiedades comunes a todos los
+/// drones y las propias de cada uno (ver `DronProperties`): sus constantes de vuelo y batería, las estaciones de
+/// carga, su recorrido de patrullaje y el qos con que se comunica.
+#[derive(Debug)]
+pub struct DronConfig {
+    properties: SistDronProperties,
+    charging_stations: ChargingStations,
+    patrol_route: Option<PatrolRoute>,
+    qos: u8,
+}
+
+impl DronConfig {
+    /// Carga la configuración del dron `dron_id`, que inicia en `initial_position`, del archivo `config_file`.
+    /// Devuelve error si el archivo no puede abrirse, o si falta o es inválida alguna propiedad.
+    pub fn from_file(
+        config_file: &str,
+        dron_id: u8,
+        initial_position: GeoPosition,
+    ) -> Result<Self, Error> {
+        let properties = read_properties(config_file)?;
+        Self::from_properties(DronProperties::new(properties, dron_id), initial_position)
+    }
+
+    /// Carga la configuración de las properties de un dron, que inicia en `initial_position`.
+    pub fn from_properties(
+        properties: DronProperties,
+        initial_position: GeoPosition,
+    ) -> Result<Self, Error> {
+        let mut dron_properties = SistDronProperties::from_properties(&properties)?;
+        // Inicia desde el centro de su rango.
+        dron_properties.set_range_center_position(initial_position);
+
+        let charging_stations = ChargingStations::from_properties(
+            &properties,
+            dron_properties.get_mantainance_position(),
+        )?
+        .with_distance_model(dron_properties.get_distance_model());
+        // El recorrido de patrullaje se define respecto del centro del rango.
+        let patrol_route = PatrolRoute::from_properties(
+            &properties,
+            dron_properties.get_range_center_position(),
+            dron_properties.get_range(),
+            dron_properties.get_distance_model(),
+        )?;
+        let qos = get_qos(&properties)?;
+
+        Ok(Self {
+            properties: dron_properties,
+            charging_stations,
+            patrol_route,
+            qos,
+        })
+    }
+
+    /// Devuelve las constantes de vuelo y batería del dron.
+    pub fn get_properties(&self) -> SistDronProperties {
+        self.properties
+    }
+
+    /// Devuelve las estaciones de carga.
+    pub fn get_charging_stations(&self) -> ChargingStations {
+        self.charging_stations.clone_ref()
+    }
+
+    /// Devuelve el recorrido que patrulla mientras espera incidentes, o None si no patrulla.
+    pub fn get_patrol_route(&self) -> Option<PatrolRoute> {
+        self.patrol_route.as_ref().map(|route| route.clone_ref())
+    }
+
+    /// Devuelve el qos con que el dron publica y se suscribe.
+    pub fn get_qos(&self) -> u8 {
+        self.qos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DronConfig, DronProperties, DEFAULT_CONFIG_FILE};
+    use crate::apps::{geo_position::GeoPosition, properties::Properties};
+
+    const BASE_CONFIG: &str = "max_battery_lvl=100
+min_operational_battery_lvl=20
+range=60
+stay_at_inc_time=200
+range_center_lat=-34.6090
+range_center_lon=-58.3873
+mantainance_lat=-34.6037
+mantainance_lon=-58.3816
+cruise_speed=40.0
+flight_tick_ms=250
+battery_per_distance=0.5
+battery_hovering_per_sec=0.1
+battery_incident_per_sec=0.25";
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn load(extra: &str, dron_id: u8) -> Result<DronConfig, std::io::Error> {
+        let contents = format!("{}\n{}", BASE_CONFIG, extra);
+        let properties = Properties::from_contents(contents.trim_end()).unwrap();
+        DronConfig::from_properties(DronProperties::new(properties, dron_id), position(-34.6, -58.4))
+    }
+
+    #[test]
+    fn test_1_las_propiedades_del_dron_tienen_prioridad_sobre_las_comunes() {
+        let extra = "cruise_speed.3=25.5\nmin_operational_battery_lvl.3=35\nrange.3=80\nqos.3=0";
+        let config = load(extra, 3).unwrap();
+        let properties = config.get_properties();
+        assert_eq!(properties.get_cruise_speed(), 25.5);
+        assert_eq!(properties.get_min_operational_battery_lvl(), 35);
+        assert_eq!(properties.get_range(), 80.0);
+        assert_eq!(config.get_qos(), 0);
+        assert_eq!(properties.get_range_center_position(), position(-34.6, -58.4));
+
+        // Otro dron usa las comunes.
+        let config = load(extra, 4).unwrap();
+        assert_eq!(config.get_properties().get_cruise_speed(), 40.0);
+        assert_eq!(config.get_qos(), 1);
+    }
+
+    #[test]
+    fn test_2_los_errores_nombran_la_propiedad_faltante_o_invalida() {
+        let err = load("cruise_speed.3=rapido", 3).unwrap_err();
+        assert!(err.to_string().contains("cruise_speed.3"));
+
+        let err = load("min_operational_battery_lvl.2=100", 2).unwrap_err();
+        assert!(err.to_string().contains("min_operational_battery_lvl.2"));
+
+        let err = load("qos=3", 1).unwrap_err();
+        assert!(err.to_string().contains("qos"));
+
+        let properties = Properties::from_contents("max_battery_lvl=100").unwrap();
+        let err = DronConfig::from_properties(DronProperties::new(properties, 1), position(0.0, 0.0))
+            .unwrap_err();
+        assert!(err.to_string().contains("min_operational_battery_lvl"));
+    }
+
+    #[test]
+    fn test_3_se_carga_el_archivo_de_configuracion_por_defecto() {
+        assert!(DronConfig::from_file(DEFAULT_CONFIG_FILE, 1, position(-34.6, -58.4)).is_ok());
+        assert!(DronConfig::from_file("no_existe.properties", 1, position(-34.6, -58.4)).is_err());
+    }
+}
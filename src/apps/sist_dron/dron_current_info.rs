@@ -1,45 +1,58 @@
 use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
 
+use crate::apps::geo_position::GeoPosition;
 use crate::apps::incident_data::incident_info::IncidentInfo;
+use crate::apps::payload_version::{split_version_header, with_version_header};
 
+use super::calculations::DistanceModel;
 use super::dron_flying_info::DronFlyingInfo;
 use super::dron_state::DronState;
 
+/// Versión del formato en bytes de la current_info. La 1 agregó el encabezado de versión a la legacy, que ya
+/// incluía los incidentes pendientes.
+pub const CURRENT_INFO_VERSION: u8 = 1;
+/// Largo en bytes del info de un incidente.
+const INC_INFO_LEN: usize = 2;
+/// Largo en bytes de la flying_info: dirección (lat, lon) y velocidad.
+const FLYING_INFO_LEN: usize = 24;
+
 /// Struct que contiene los campos que identifican al Dron (el id) y que pueden modificarse durante su funcionamiento.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct DronCurrentInfo {
     id: u8,
     // Posición actual
-    latitude: f64,
-    longitude: f64,
+    #[serde(flatten)]
+    position: GeoPosition,
     battery_lvl: u8,
     state: DronState,
     inc_info_to_resolve: Option<IncidentInfo>,
     // Dirección y velocidad de vuelo
     flying_info: Option<DronFlyingInfo>,
+    // Incidentes activos en su rango que todavía puede atender, además del que atiende
+    pending_incs: Vec<IncidentInfo>,
 }
 
 impl DronCurrentInfo {
     /// Inicia con los parámetros recibidos; con ningún incidente en resolución y sin flying_info
-    /// (es decir, inicia con estos dos últimos atributos en None).
-    pub fn new(id: u8, latitude: f64, longitude: f64, battery_lvl: u8, state: DronState) -> Self {
+    /// (es decir, inicia con estos dos últimos atributos en None), y sin incidentes pendientes.
+    pub fn new(id: u8, position: GeoPosition, battery_lvl: u8, state: DronState) -> Self {
         DronCurrentInfo {
             id,
-            latitude,
-            longitude,
+            position,
             battery_lvl,
             state,
             inc_info_to_resolve: None,
             flying_info: None,
+            pending_incs: vec![],
         }
     }
 
-    /// Pasa un struct `DronCurrentInfo` a bytes.
+    /// Pasa un struct `DronCurrentInfo` a bytes, precedidos por el encabezado con su versión.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.extend_from_slice(&self.id.to_be_bytes());
-        bytes.extend_from_slice(&self.latitude.to_be_bytes());
-        bytes.extend_from_slice(&self.longitude.to_be_bytes());
+        bytes.extend_from_slice(&self.position.to_bytes());
         //println!("BYTES ID LAT Y LONG, ENCODEANDO: {:?}", bytes); //aux [] debug
         bytes.extend_from_slice(&self.battery_lvl.to_be_bytes());
         //bytes.push(self.state.to_byte()[0]); // <-- así sería si fuera un enum en vez de un u8.
@@ -60,86 +73,55 @@ impl DronCurrentInfo {
         } else {
             bytes.extend_from_slice(&0_u8.to_be_bytes()); // avisa que No se enviará más bytes
         }
-        bytes
+
+        // Los incidentes pendientes: la cantidad, y el info de cada uno
+        bytes.push(self.pending_incs.len() as u8);
+        for inc_info in &self.pending_incs {
+            bytes.extend_from_slice(&inc_info.to_bytes());
+        }
+        with_version_header(CURRENT_INFO_VERSION, bytes)
     }
 
-    /// Obtiene un struct `DronCurrentInfo` a partir de bytes.
+    /// Obtiene un struct `DronCurrentInfo` a partir de bytes, con o sin encabezado de versión.
+    /// Ignora los campos de versiones más nuevas que no conoce, y usa valores por defecto para los que agregaron
+    /// versiones posteriores a la recibida.
     pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
-        let mut idx = 0;
-        let b_size: usize = 1;
-
-        let id = u8::from_be_bytes([bytes[idx]]);
-        idx += b_size;
-
-        let latitude = f64::from_be_bytes([
-            bytes[idx],
-            bytes[idx + b_size],
-            bytes[idx + 2 * b_size],
-            bytes[idx + 3 * b_size],
-            bytes[idx + 4 * b_size],
-            bytes[idx + 5 * b_size],
-            bytes[idx + 6 * b_size],
-            bytes[idx + 7 * b_size],
-        ]);
-        idx += 8 * b_size;
-
-        let longitude = f64::from_be_bytes([
-            bytes[idx],
-            bytes[idx + b_size],
-            bytes[idx + 2 * b_size],
-            bytes[idx + 3 * b_size],
-            bytes[idx + 4 * b_size],
-            bytes[idx + 5 * b_size],
-            bytes[idx + 6 * b_size],
-            bytes[idx + 7 * b_size],
-        ]);
-        idx += 8 * b_size;
+        let (_version, mut reader) = split_version_header(&bytes);
 
-        let battery_lvl = u8::from_be_bytes([bytes[idx]]);
-        idx += b_size;
+        let id = reader.read_u8()?;
+        let position = GeoPosition::read_from(&mut reader)?;
+        let battery_lvl = reader.read_u8()?;
+        let state = DronState::from_byte([reader.read_u8()?])
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Error al leer el state"))?;
 
-        let state_res = DronState::from_byte([bytes[idx]]);
-        idx += b_size;
-
-        let mut inc_info_to_resolve = None;
-        let inc_info_to_resolve_option = IncidentInfo::from_bytes([bytes[idx], bytes[idx+b_size]].to_vec())?;
-        if let Some(inc_info) = inc_info_to_resolve_option {
-            inc_info_to_resolve = Some(inc_info);
-        }
-        // Leo el inc id to resolve
-        /*let mut inc_id_to_resolve = None;
-        let read_inc_id = u8::from_be_bytes([bytes[idx]]);
-        if read_inc_id != 0 {
-            inc_id_to_resolve = Some(read_inc_id);
-        }*/
-        idx += 2 * b_size;
+        let inc_info_to_resolve = IncidentInfo::from_bytes(reader.read_bytes(INC_INFO_LEN)?.to_vec())?;
 
         // Leo dir y velocidad de vuelo
         let mut flying_info = None;
-        let is_there_flying_info = u8::from_be_bytes([bytes[idx]]);
-        idx += b_size;
-
-        if is_there_flying_info == 1 {
-            flying_info = Some(DronFlyingInfo::from_bytes(bytes[idx..].to_vec())?);
+        if reader.read_u8()? == 1 {
+            let flying_info_bytes = reader.read_bytes(FLYING_INFO_LEN)?;
+            flying_info = Some(DronFlyingInfo::from_bytes(flying_info_bytes.to_vec())?);
         }
 
-        //idx += b_size; // comentado porque warning is never read. quizás en el futuro agregamos más campos.
-
-        match state_res {
-            Ok(state) => Ok(DronCurrentInfo {
-                id,
-                latitude,
-                longitude,
-                battery_lvl,
-                state,
-                inc_info_to_resolve,
-                flying_info,
-            }),
-            Err(_) => Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Error al leer el state",
-            )),
+        // Leo los incidentes pendientes; las current_info publicadas antes de que existieran no los incluyen.
+        let mut pending_incs = vec![];
+        if reader.has_remaining() {
+            for _ in 0..reader.read_u8()? {
+                if let Some(inc_info) = IncidentInfo::from_bytes(reader.read_bytes(INC_INFO_LEN)?.to_vec())? {
+                    pending_incs.push(inc_info);
+                }
+            }
         }
+
+        Ok(DronCurrentInfo {
+            id,
+            position,
+            battery_lvl,
+            state,
+            inc_info_to_resolve,
+            flying_info,
+            pending_incs,
+        })
     }
 
     // Getters
@@ -147,9 +129,9 @@ impl DronCurrentInfo {
     pub fn get_id(&self) -> u8 {
         self.id
     }
-    /// Devuelve latitud y longitud en las que dron se encuentra actualmente.
-    pub fn get_current_position(&self) -> (f64, f64) {
-        (self.latitude, self.longitude)
+    /// Devuelve la posición en la que dron se encuentra actualmente.
+    pub fn get_current_position(&self) -> GeoPosition {
+        self.position
     }
     /// Devuelve el nivel de batería actual.
     pub fn get_battery_lvl(&self) -> u8 {
@@ -179,23 +161,50 @@ impl DronCurrentInfo {
         self.inc_info_to_resolve = None;
     }
 
+    /// Devuelve los incidentes activos en su rango que todavía puede atender, además del que atiende.
+    pub fn get_pending_incidents(&self) -> &[IncidentInfo] {
+        &self.pending_incs
+    }
+
+    /// Setea los incidentes activos en su rango que todavía puede atender.
+    pub fn set_pending_incidents(&mut self, pending_incs: Vec<IncidentInfo>) {
+        self.pending_incs = pending_incs;
+    }
+
+    /// Devuelve si esta current_info solamente actualiza la posición, la batería o el vuelo de `previous`, del mismo
+    /// dron: su estado, su incidente y sus incidentes pendientes son los mismos.
+    pub fn supersedes(&self, previous: &DronCurrentInfo) -> bool {
+        self.id == previous.id
+            && self.state == previous.state
+            && self.inc_info_to_resolve == previous.inc_info_to_resolve
+            && self.pending_incs == previous.pending_incs
+    }
+
     /// Setea la flying_info recibida.
     pub fn set_flying_info(&mut self, info: DronFlyingInfo) {
         self.flying_info = Some(info);
     }
 
     /// Setea la posición actual del dron.
-    pub fn set_current_position(&mut self, new_position: (f64, f64)) {
-        self.latitude = new_position.0;
-        self.longitude = new_position.1;
+    pub fn set_current_position(&mut self, new_position: GeoPosition) {
+        self.position = new_position;
     }
 
-    /// Incrementa la posición actual en la dirección recibida, y devuelve la nueva posición actual.
-    pub fn increment_current_position_in(&mut self, dir: (f64, f64)) -> (f64, f64) {
-        // La dirección es un vector unitario, pero para poder sumarlo a la lat y long y que tenga sentido
-        // hay que escalarla.
-        self.latitude += dir.0 / 10000.0;
-        self.longitude += dir.1 / 10000.0;
+    /// Desplaza la posición actual `displacement` (en la unidad de `distance_model`) hacia `destination`, sin
+    /// pasarse, y devuelve la nueva posición actual.
+    pub fn move_towards(
+        &mut self,
+        destination: GeoPosition,
+        displacement: f64,
+        distance_model: DistanceModel,
+    ) -> GeoPosition {
+        if self.get_distance_to(destination, distance_model) <= displacement {
+            self.set_current_position(destination);
+        } else {
+            // La dirección es un vector unitario, se la escala por el desplazamiento.
+            let dir = distance_model.direction(self.position, destination);
+            self.position = distance_model.step(self.position, dir, displacement);
+        }
 
         self.get_current_position()
     }
@@ -218,22 +227,16 @@ impl DronCurrentInfo {
         self.flying_info = None;
     }
 
-    pub fn get_distance_to(&self, destination: (f64, f64)) -> f64 {
-        let origin = self.get_current_position();
-        let lat_dist = destination.0 - origin.0;
-        let lon_dist = destination.1 - origin.1;
-        f64::sqrt(lat_dist.powi(2) + lon_dist.powi(2))
+    /// Devuelve la distancia hasta `destination`, según `distance_model`.
+    pub fn get_distance_to(&self, destination: GeoPosition, distance_model: DistanceModel) -> f64 {
+        distance_model.distance(self.position, destination)
     }
 
-    /// Decrementa la batería, y chequea y devuelve si la batería está por debajo del mínimo.
-    pub fn decrement_and_check_battery_lvl(&mut self, min_battery: u8) -> bool {
+    /// Decrementa la batería en `consumed`, y chequea y devuelve si la batería está por debajo del mínimo.
+    pub fn decrement_and_check_battery_lvl(&mut self, consumed: u8, min_battery: u8) -> bool {
         let mut should_charge = false;
         // Decrementa
-        if self.battery_lvl >= 5 {
-            self.battery_lvl -= 5;
-        } else {
-            self.battery_lvl = 0;
-        }
+        self.battery_lvl = self.battery_lvl.saturating_sub(consumed);
         // Analiza
         if self.battery_lvl < min_battery {
             should_charge = true;
@@ -248,19 +251,21 @@ impl DronCurrentInfo {
 
 #[cfg(test)]
 mod test {
-    use crate::apps::sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState};
+    use crate::apps::sist_dron::{calculations::DistanceModel, dron_current_info::DronCurrentInfo, dron_flying_info::DronFlyingInfo, dron_state::DronState};
+    use std::time::Duration;
+    use crate::apps::geo_position::GeoPosition;
     use crate::apps::incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource};
 
     #[test]
     fn test_1a_dron_to_y_from_bytes() {
         let dron = DronCurrentInfo {
             id: 1,
-            latitude: -34.0,
-            longitude: -58.0,
+            position: GeoPosition::new(-34.0, -58.0).unwrap(),
             battery_lvl: 100,
             state: DronState::ExpectingToRecvIncident,
             inc_info_to_resolve: None,
             flying_info: None,
+            pending_incs: vec![],
         };
 
         let bytes = dron.to_bytes();
@@ -273,12 +278,12 @@ mod test {
     fn test_1b_dron_to_y_from_bytes() {
         let dron = DronCurrentInfo {
             id: 1,
-            latitude: -34.0,
-            longitude: -58.0,
+            position: GeoPosition::new(-34.0, -58.0).unwrap(),
             battery_lvl: 100,
             state: DronState::ExpectingToRecvIncident,
             inc_info_to_resolve: Some(IncidentInfo::new(18, IncidentSource::Manual)),
             flying_info: None,
+            pending_incs: vec![],
         };
 
         let bytes = dron.to_bytes();
@@ -286,4 +291,78 @@ mod test {
 
         assert_eq!(reconstructed_dron.unwrap(), dron);
     }
+
+    #[test]
+    fn test_1c_dron_con_flying_info_e_incidentes_pendientes_to_y_from_bytes() {
+        let mut dron = DronCurrentInfo::new(1, GeoPosition::new(-34.0, -58.0).unwrap(), 80, DronState::Flying);
+        dron.set_inc_id_to_resolve(IncidentInfo::new(18, IncidentSource::Manual));
+        dron.set_flying_info(DronFlyingInfo::new((0.6, 0.8), 40.0));
+        dron.set_pending_incidents(vec![
+            IncidentInfo::new(19, IncidentSource::Automated),
+            IncidentInfo::new(20, IncidentSource::Manual),
+        ]);
+
+        let reconstructed_dron = DronCurrentInfo::from_bytes(dron.to_bytes()).unwrap();
+        assert_eq!(reconstructed_dron, dron);
+
+        // Una current_info sin incidentes pendientes, como las publicadas antes de que existieran, se lee sin ellos.
+        let mut bytes = dron.to_bytes();
+        bytes.truncate(bytes.len() - 5);
+        assert!(DronCurrentInfo::from_bytes(bytes).unwrap().get_pending_incidents().is_empty());
+    }
+
+    #[test]
+    fn test_1d_se_leen_current_info_sin_encabezado_y_con_campos_desconocidos() {
+        let mut dron = DronCurrentInfo::new(1, GeoPosition::new(-34.0, -58.0).unwrap(), 80, DronState::Flying);
+        dron.set_flying_info(DronFlyingInfo::new((0.6, 0.8), 40.0));
+        let bytes = dron.to_bytes();
+
+        // Una legacy, publicada antes de que existiera el encabezado de versión.
+        let legacy_bytes = bytes[3..].to_vec();
+        assert_eq!(DronCurrentInfo::from_bytes(legacy_bytes).unwrap(), dron);
+
+        // Una de una versión más nueva, con un campo agregado al final que esta versión no conoce.
+        let mut newer_bytes = bytes.clone();
+        newer_bytes[2] += 1;
+        newer_bytes.push(42);
+        assert_eq!(DronCurrentInfo::from_bytes(newer_bytes).unwrap(), dron);
+
+        // Una incompleta se rechaza, en vez de entrar en pánico.
+        assert!(DronCurrentInfo::from_bytes(bytes[..10].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_2_el_dron_se_desplaza_hacia_el_destino_segun_el_tiempo_transcurrido_sin_pasarse() {
+        let origin = GeoPosition::new(0.0, 0.0).unwrap();
+        let mut dron = DronCurrentInfo::new(1, origin, 100, DronState::Flying);
+        let destination = GeoPosition::new(0.003, 0.004).unwrap(); // a 0.005 de distancia
+
+        // A 40 m/s, en 5 segundos recorre 200 metros.
+        let flat = DistanceModel::Flat;
+        let displacement = flat.flight_displacement(40.0, Duration::from_secs(5));
+        let position = dron.move_towards(destination, displacement, flat);
+        assert!((dron.get_distance_to(origin, flat) - 200.0 / 111_320.0).abs() < 1e-12);
+        assert!((position.get_latitude() / position.get_longitude() - 0.75).abs() < 1e-9);
+
+        // Un desplazamiento mayor a lo que falta lo deja exactamente en el destino.
+        assert_eq!(dron.move_towards(destination, 1.0, flat), destination);
+    }
+
+    #[test]
+    fn test_3_con_el_modelo_geodesico_recorre_los_metros_que_vuela_tambien_hacia_el_este() {
+        let origin = GeoPosition::new(-34.6037, -58.3816).unwrap();
+        let destination = GeoPosition::new(-34.6037, -58.3616).unwrap();
+        let elapsed = Duration::from_secs(5);
+
+        // A 40 m/s, en 5 segundos vuela 200 metros; en el plano, un grado de longitud se toma como uno de latitud.
+        let mut meters_flown = vec![];
+        for model in [DistanceModel::Geodesic, DistanceModel::Flat] {
+            let mut dron = DronCurrentInfo::new(1, origin, 100, DronState::Flying);
+            let position = dron.move_towards(destination, model.flight_displacement(40.0, elapsed), model);
+            assert!((position.get_latitude() - origin.get_latitude()).abs() < 1e-6);
+            meters_flown.push(origin.distance_to(&position));
+        }
+        assert!((meters_flown[0] - 200.0).abs() < 0.5);
+        assert!((meters_flown[1] - 200.0 * 34.6037_f64.to_radians().cos()).abs() < 0.5);
+    }
 }
@@ -1,7 +1,8 @@
 use std::io::Error;
+use serde::{Deserialize, Serialize};
 
 /// Dirección y velocidad con las que vuela el dron.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct DronFlyingInfo {
     direction: (f64, f64), // vector unitario de dirección al volar, con componentes lat y lon
     speed: f64,            // velocidad de desplazamiento al volar
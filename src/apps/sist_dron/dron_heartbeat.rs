@@ -0,0 +1,111 @@
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use super::dron_state::DronState;
+
+/// Latido que cada dron publica periódicamente por el topic `dron-health`, para que monitoreo detecte a los drones
+/// que dejaron de responder. Es más liviano que la current_info: no incluye posición ni incidente.
+/// Incluye cada cuánto se publica, para que quien lo recibe sepa cuándo considerarlo atrasado.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DronHeartbeat {
+    id: u8,
+    battery_lvl: u8,
+    state: DronState,
+    uptime: Duration,
+    interval: Duration,
+}
+
+impl DronHeartbeat {
+    pub fn new(
+        id: u8,
+        battery_lvl: u8,
+        state: DronState,
+        uptime: Duration,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            id,
+            battery_lvl,
+            state,
+            uptime,
+            interval,
+        }
+    }
+
+    pub fn get_id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn get_battery_lvl(&self) -> u8 {
+        self.battery_lvl
+    }
+
+    pub fn get_state(&self) -> DronState {
+        self.state
+    }
+
+    /// Devuelve hace cuánto está encendido el dron.
+    pub fn get_uptime(&self) -> Duration {
+        self.uptime
+    }
+
+    /// Devuelve cada cuánto publica el dron su latido.
+    pub fn get_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Convierte el latido a bytes: id, batería, estado, uptime en segundos (4 bytes) e intervalo en milisegundos
+    /// (4 bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.id, self.battery_lvl, self.state.to_byte()[0]];
+        bytes.extend_from_slice(&(self.uptime.as_secs() as u32).to_be_bytes());
+        bytes.extend_from_slice(&(self.interval.as_millis() as u32).to_be_bytes());
+        bytes
+    }
+
+    /// Obtiene el latido a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() != 11 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Latido de dron inválido.",
+            ));
+        }
+        let uptime_secs = u32::from_be_bytes([bytes[3], bytes[4], bytes[5], bytes[6]]);
+        let interval_ms = u32::from_be_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]);
+        Ok(Self::new(
+            bytes[0],
+            bytes[1],
+            DronState::from_byte([bytes[2]])?,
+            Duration::from_secs(uptime_secs as u64),
+            Duration::from_millis(interval_ms as u64),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::DronHeartbeat;
+    use crate::apps::sist_dron::dron_state::DronState;
+
+    #[test]
+    fn test_1_el_latido_se_convierte_a_bytes_y_de_vuelta() {
+        let heartbeat = DronHeartbeat::new(
+            3,
+            87,
+            DronState::Flying,
+            Duration::from_secs(3725),
+            Duration::from_millis(5000),
+        );
+
+        assert_eq!(
+            DronHeartbeat::from_bytes(heartbeat.to_bytes()).unwrap(),
+            heartbeat
+        );
+        assert!(DronHeartbeat::from_bytes(vec![3, 87]).is_err());
+    }
+}
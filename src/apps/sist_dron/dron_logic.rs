@@ -1,22 +1,28 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::HashMap,
     io::{Error, ErrorKind},
-    sync::{mpsc::{self, Sender}, Arc, Mutex}, thread::{self, sleep}, time::Duration,
+    sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, SyncSender}, Arc, Mutex, MutexGuard}, time::Duration,
 };
 
 use crate::{
     apps::{
         apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        payload_codec::decode_payload,
         incident_data::{
             incident::Incident, incident_info::IncidentInfo, incident_state::IncidentState,
-        }, sist_dron::calculations::{calculate_direction, calculate_distance},
+        }, sist_dron::calculations::DistanceModel,
     },
     logging::string_logger::StringLogger,
     mqtt::messages::publish_message::PublishMessage,
 };
 
 use super::{
-    data::Data, dron_current_info::DronCurrentInfo, dron_state::DronState,
+    charging_stations::ChargingStations, data::Data, decline_diagnostics::{DeclineDiagnostics, DeclineReason, DeclinedIncident}, handoff::{Handoff, HandoffRequest}, dron::SHUTDOWN_CHECK_INTERVAL, dron_admin_command::DronAdminCommand, dron_command::DronCommand,
+    dron_current_info::DronCurrentInfo, dron_state::DronState,
+    incident_arbitration::{CoordinationMode, IncidentCandidates, CANDIDACY_REPUBLISH_INTERVAL, CANDIDACY_TIMEOUT, CONFIRMATION_TIMEOUT, DRONES_PER_INCIDENT},
+    incident_assignment::IncidentAssignment, incident_presence::IncidentPresence,
+    pending_incidents::PendingIncidents,
     sist_dron_properties::SistDronProperties,
 };
 
@@ -25,41 +31,91 @@ use super::{
 pub struct DronLogic {
     current_data: Data,
     dron_properties: SistDronProperties,
+    charging_stations: ChargingStations,
     logger: StringLogger,
     drone_distances_by_incident: DistancesType, // ya es arc mutex.
-    ci_tx: Sender<DronCurrentInfo>,
-    active_incs: Arc<Mutex<VecDeque<(IncidentInfo, Incident, u8)>>>, // el u8 es un contador de cuántos drones recibí que ya están yendo hacia ese inc.
+    ci_tx: SyncSender<DronCurrentInfo>,
+    active_incs: Arc<Mutex<PendingIncidents>>, // incidentes pendientes de procesar, y el que está atendiendo.
+    preempted: Arc<AtomicBool>, // indica al vuelo hacia el incidente que lo deje, para atender otro.
+    patrolling: bool, // si patrulla un recorrido mientras espera incidentes, en vez de quedarse en el centro del rango.
+    incident_presence: IncidentPresence, // desde cuándo está cada dron en el incidente que atiende.
+    handoff: Option<Handoff>, // para relevar a otros drones que deben ir a cargarse.
+    decline_diagnostics: Option<DeclineDiagnostics>, // para publicar los incidentes que no atiende, y por qué.
 }
 
-type DistancesType = Arc<Mutex<HashMap<IncidentInfo, ((f64, f64), Vec<(u8, f64)>)>>>; // (inc_info, ( (inc_pos),(dron_id, distance_to_incident)) )
+type DistancesType = Arc<Mutex<HashMap<IncidentInfo, IncidentCandidates>>>; // (inc_info, (inc_pos, postulaciones de los drones))
 
 impl DronLogic {
     /// Crea un DronLogic.
     pub fn new(
         current_data: Data,
         dron_properties: SistDronProperties,
+        charging_stations: ChargingStations,
         logger: StringLogger,
         distances: DistancesType,
-        ci_tx: Sender<DronCurrentInfo>,
+        ci_tx: SyncSender<DronCurrentInfo>,
     ) -> Self {
         Self {
             current_data,
             dron_properties,
+            charging_stations,
             logger,
             drone_distances_by_incident: distances,
             ci_tx,
-            active_incs: Arc::new(Mutex::new(VecDeque::new())),
+            active_incs: Arc::new(Mutex::new(PendingIncidents::new())),
+            preempted: Arc::new(AtomicBool::new(false)),
+            patrolling: false,
+            incident_presence: IncidentPresence::new(),
+            handoff: None,
+            decline_diagnostics: None,
         }
     }
 
+    /// Indica si el dron patrulla un recorrido mientras espera incidentes. En ese caso, al terminar de atender
+    /// un incidente retoma el recorrido desde donde se encuentra, en vez de volver al centro de su rango.
+    pub fn with_patrol(mut self, patrolling: bool) -> Self {
+        self.patrolling = patrolling;
+        self
+    }
+
+    /// Indica dónde registrar desde cuándo se encuentra cada dron recibido en el incidente que atiende, para avisar
+    /// cuando se atendió (ver `IncidentPresence`).
+    pub fn with_incident_presence(mut self, incident_presence: IncidentPresence) -> Self {
+        self.incident_presence = incident_presence;
+        self
+    }
+
+    /// Indica cómo relevar a otros drones (ver `Handoff`). Comparte con él los incidentes pendientes, para que
+    /// sepa cuál atiende este dron si debe pedir que lo releven.
+    pub fn with_handoff(mut self, handoff: Handoff) -> Self {
+        self.active_incs = handoff.get_active_incs();
+        self.handoff = Some(handoff);
+        self
+    }
+
+    /// Indica dónde publicar los incidentes que no atiende, con el motivo (ver `DeclineDiagnostics`).
+    pub fn with_decline_diagnostics(mut self, decline_diagnostics: DeclineDiagnostics) -> Self {
+        self.decline_diagnostics = Some(decline_diagnostics);
+        self
+    }
+
     pub fn clone_ref(&self) -> Self {
         Self {
             current_data: self.current_data.clone_ref(),
             dron_properties: self.dron_properties,
+            charging_stations: self.charging_stations.clone_ref(),
             logger: self.logger.clone_ref(),
             drone_distances_by_incident: self.drone_distances_by_incident.clone(),
             ci_tx: self.ci_tx.clone(),
             active_incs: self.active_incs.clone(),
+            preempted: self.preempted.clone(),
+            patrolling: self.patrolling,
+            incident_presence: self.incident_presence.clone_ref(),
+            handoff: self.handoff.as_ref().map(|handoff| handoff.clone_ref()),
+            decline_diagnostics: self
+                .decline_diagnostics
+                .as_ref()
+                .map(|diagnostics| diagnostics.clone_ref()),
         }
     }
 
@@ -74,7 +130,7 @@ impl DronLogic {
         match enum_topic {
             AppsMqttTopics::IncidentTopic => self.process_valid_inc(msg.get_payload(), process_inc_tx),
             AppsMqttTopics::DronTopic => {
-                let received_ci = DronCurrentInfo::from_bytes(msg.get_payload())?;
+                let received_ci: DronCurrentInfo = decode_payload(&msg.get_payload())?;
                 let not_myself = self.current_data.get_id()? != received_ci.get_id();
                 let recvd_dron_is_not_flying = received_ci.get_state() != DronState::Flying;
                 let recvd_dron_is_not_managing_incident =
@@ -82,24 +138,47 @@ impl DronLogic {
 
                 let recvd_dron_is_analyzing_if_should_move = received_ci.get_state() == DronState::RespondingToIncident;
                 let recvd_dron_must_move = received_ci.get_state() == DronState::MustRespondToIncident;
+                // Un dron que declinó o dejó un incidente, o que se apagó, ya no es candidato para el mismo.
+                let recvd_dron_declined = matches!(
+                    received_ci.get_state(),
+                    DronState::IncidentDeclined | DronState::IncidentReassigned | DronState::Offline
+                );
                 
                 // Si la current_info recibida es de mi propio publish, no me interesa compararme conmigo mismo.
                 // Si el current_info recibida es de un dron que está volando, tampoco me interesa, esos publish serán para sistema de moniteo.
                 // Si el current_info recibida es de un dron que está en la ubicación de un incidente, tampoco me interesa, esos publish serán para sistema de moniteo.
                 if not_myself {
-                  
+                  // Registra si el dron recibido ocupa o dejó libre una estación de carga.
+                  self.charging_stations.update_occupancy(&received_ci)?;
+                  // Registra si el dron recibido confirmó que atiende un incidente.
+                  self.register_confirmation(&received_ci)?;
+                  // Registra si el dron recibido llegó al incidente que atiende, o lo dejó.
+                  if self.dron_properties.reports_attended() {
+                      self.incident_presence.update(&received_ci, self.current_data.now())?;
+                  }
+                  // Lo registra en la vista de la flota, para elegir quién lo releva si debe ir a cargarse.
+                  if let Some(handoff) = &self.handoff {
+                      handoff.update_dron(received_ci.clone())?;
+                  }
+
                   if recvd_dron_is_not_flying && recvd_dron_is_not_managing_incident {
                     if recvd_dron_is_analyzing_if_should_move {
                         self.process_valid_dron(received_ci)?;
+                    } else if recvd_dron_declined {
+                        self.remove_declined_candidate(received_ci)?;
                     }
 
-                  } else if recvd_dron_must_move {
+                  } else if recvd_dron_must_move && !self.is_centrally_coordinated() {
                     self.remove_from_active_incs_if_two_drones_already_flying(received_ci)?;
                   }                                
 
                 }
                 Ok(())
             }
+            AppsMqttTopics::DronAdminTopic => self.process_admin_command(msg.get_payload()),
+            AppsMqttTopics::DronAssignmentTopic => self.process_assignment(msg.get_payload(), process_inc_tx),
+            AppsMqttTopics::DronHandoffTopic => self.process_handoff_request(msg.get_payload(), process_inc_tx),
+            AppsMqttTopics::DronControlTopic => self.process_control_command(msg.get_payload(), process_inc_tx),
             _ => Err(Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Topic no conocido",
@@ -107,12 +186,19 @@ impl DronLogic {
         }
     }
 
-    pub fn listen_for_and_process_new_active_incident(&mut self, rx: mpsc::Receiver<()>) -> Result<(), Error> {        
-        for _ in rx {
+    pub fn listen_for_and_process_new_active_incident(&mut self, rx: &mpsc::Receiver<()>) -> Result<(), Error> {        
+        // Escucha hasta que el dron se apague; el timeout es para notarlo aunque no lleguen señales.
+        while !self.current_data.is_shutting_down() {
+            match rx.recv_timeout(SHUTDOWN_CHECK_INTERVAL) {
+                Ok(()) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
             // Desencolo un incidente activo para procesarlo
             // Escucha por rx, for escucha algo por rx, hace esto:
-            if self.current_data.get_state()? == DronState::ExpectingToRecvIncident {
-                if let Some((_inc_info, inc, _dron_amount)) = self.pop_from_active_incs()? {
+            // Si lo detuvieron o lo llamaron a la base, no toma incidentes hasta que vuelva a funcionar.
+            if self.current_data.get_state()? == DronState::ExpectingToRecvIncident && !self.current_data.is_on_standby() {
+                if let Some(inc) = self.pop_from_active_incs()? {
                     println!("DEBUG QUEUE: desacolé, voy a procesar el inc: {:?}", inc.get_source());
                     self.logger.log(format!("DEBUG QUEUE: desacolé, voy a procesar el inc: {:?}", inc.get_source()));
                     // Manda a ejecutar. Si falla no quiero cortar el loop, solo lo loggueo.
@@ -120,6 +206,9 @@ impl DronLogic {
                         println!("DEBUG QUEUE: error en manage para inc: {:?}, {:?}", inc.get_source(), e);
                         self.logger.log(format!("DEBUG QUEUE: error en manage para inc: {:?}, {:?}", inc.get_source(), e));
                     }
+                    if let Err(e) = self.go_back_if_idle() {
+                        self.logger.log(format!("Error al volver al centro del rango: {:?}", e));
+                    }
                 }
             }
         }
@@ -127,20 +216,157 @@ impl DronLogic {
         Ok(())        
     }
 
-    /// Recibe un incidente, analiza si está o no resuelto y actúa acorde.
+    /// Recibe un comando de administración, y si es para este dron lo ejecuta.
+    fn process_admin_command(&self, payload: Vec<u8>) -> Result<(), Error> {
+        let command = DronAdminCommand::from_bytes(payload)?;
+        if !command.applies_to(self.current_data.get_id()?) {
+            return Ok(());
+        }
+        match command {
+            DronAdminCommand::Shutdown(_) => {
+                self.logger.log("Recibido comando de apagado.".to_string());
+                self.current_data.request_shutdown();
+            }
+        }
+        Ok(())
+    }
+
+    /// Recibe un comando de control por su propio topic, y lo ejecuta.
+    fn process_control_command(
+        &mut self,
+        payload: Vec<u8>,
+        process_inc_tx: mpsc::Sender<()>,
+    ) -> Result<(), Error> {
+        let command = DronCommand::from_bytes(payload)?;
+        self.logger.log(format!("Recibido comando de control: {:?}.", command));
+        match command {
+            DronCommand::SetSpeed(speed) => self.current_data.set_cruise_speed(speed),
+            DronCommand::SetBatteryThresholds { min_operational, max } => {
+                self.current_data.set_battery_thresholds(min_operational, max)
+            }
+            DronCommand::Pause => {
+                self.current_data.set_paused(true);
+                Ok(())
+            }
+            DronCommand::Resume => {
+                self.current_data.set_paused(false);
+                self.current_data.set_recalled(false);
+                // Procesa los incidentes que quedaron pendientes mientras no los tomaba.
+                let _ = process_inc_tx.send(());
+                Ok(())
+            }
+            DronCommand::Recall => self.recall(),
+        }
+    }
+
+    /// Vuelve a la base: deja el incidente que atiende, vuelve al centro de su rango, y no toma incidentes hasta que
+    /// vuelva a funcionar. Si todavía volaba hacia el incidente, se interrumpe el vuelo, y quien lo procesaba lo deja
+    /// y vuelve.
+    fn recall(&mut self) -> Result<(), Error> {
+        self.current_data.set_paused(false);
+        self.current_data.set_recalled(true);
+        let attending = self.lock_active_incs()?.get_attending().is_some();
+        match self.current_data.get_state()? {
+            DronState::ManagingIncident if attending => {
+                self.leave_current_incident()?;
+                self.go_back_to_range_center_position()
+            }
+            DronState::MustRespondToIncident | DronState::Flying if attending => {
+                self.preempted.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            DronState::ExpectingToRecvIncident => self.go_back_if_idle(),
+            // Si ya vuelve, está en mantenimiento, o se está eligiendo quién atiende un incidente, lo resuelve
+            // quien lo está haciendo.
+            _ => Ok(()),
+        }
+    }
+
+    /// Recibe una asignación del coordinador de la flota, y si incluye a este dron encola el incidente para atenderlo.
+    fn process_assignment(
+        &mut self,
+        payload: Vec<u8>,
+        process_inc_tx: mpsc::Sender<()>,
+    ) -> Result<(), Error> {
+        let assignment = IncidentAssignment::from_bytes(payload)?;
+        if !assignment.includes(self.current_data.get_id()?) {
+            return Ok(());
+        }
+        let inc = assignment.get_incident();
+        // El coordinador vuelve a publicar la asignación al reemplazar a otro dron; si ya lo atiende, no lo encola de nuevo.
+        if self.current_data.get_inc_id_to_resolve()? == Some(inc.get_info()) {
+            return Ok(());
+        }
+        self.push_to_active_incs(inc)?;
+        let _ = process_inc_tx.send(());
+        self.logger.log(format!("El coordinador me asignó el inc {}, encolado.", inc.get_id()));
+        Ok(())
+    }
+
+    /// Recibe un pedido de relevo de otro dron, y si lo eligió a este dron como reemplazo encola el incidente
+    /// adelante de los demás, para atenderlo sin elegirse con los otros drones.
+    fn process_handoff_request(
+        &mut self,
+        payload: Vec<u8>,
+        process_inc_tx: mpsc::Sender<()>,
+    ) -> Result<(), Error> {
+        let request = HandoffRequest::from_bytes(payload)?;
+        let Some(handoff) = &self.handoff else {
+            return Ok(());
+        };
+        if request.get_replacement_id() != Some(self.current_data.get_id()?) {
+            return Ok(());
+        }
+        let inc = request.get_incident().clone();
+        handoff.add_handed_off(inc.get_info())?;
+        self.logger.log(format!(
+            "El dron {} me pidió que lo releve en el inc {}, encolado.",
+            request.get_leaving_dron_id(),
+            inc.get_id()
+        ));
+        self.update_active_incs(|active_incs| active_incs.push_front(inc))?;
+        let _ = process_inc_tx.send(());
+        Ok(())
+    }
+
+    /// Devuelve si se le pidió a este dron relevar a otro en el incidente, y en ese caso lo olvida.
+    fn take_handed_off(&self, inc: &Incident) -> Result<bool, Error> {
+        match &self.handoff {
+            Some(handoff) => handoff.take_handed_off(&inc.get_info()),
+            None => Ok(false),
+        }
+    }
+
+    /// Devuelve si los incidentes los asigna el coordinador de la flota, en vez de elegirse entre los drones.
+    fn is_centrally_coordinated(&self) -> bool {
+        self.dron_properties.get_coordination_mode() == CoordinationMode::Central
+    }
+
+    /// Recibe un incidente, analiza en qué estado está y actúa acorde.
     fn process_valid_inc(
         &mut self,
         payload: Vec<u8>,
         process_inc_tx: mpsc::Sender<()>,
     ) -> Result<(), Error> {
-        let inc = Incident::from_bytes(payload)?;
+        let inc: Incident = decode_payload(&payload)?;
 
         match *inc.get_state() {
-            IncidentState::ActiveIncident => {
-                // Encolo el inc activo recibido
+            IncidentState::Reported => {
+                // Si los asigna el coordinador de la flota, lo encola recién cuando se lo asigne.
+                if self.is_centrally_coordinated() {
+                    return Ok(());
+                }
+                // Si no está en su rango, no lo va a atender
+                if !self.is_within_range_from_self(inc.get_position(), self.dron_properties.get_range()) {
+                    return self.report_declined(&inc, DeclineReason::OutOfRange);
+                }
+                // Encolo el inc activo recibido, y publico que lo tengo pendiente
                 self.push_to_active_incs(&inc)?;
+                self.publish_current_info()?;
                 // Se agrega la info del inc encolado, al distances, para que se haga el cálculo de las distancias para él tambiém
                 self.add_incident_to_hashmap(&inc)?;
+                // Si según su política debe dejar el inc que atiende por este, lo deja
+                self.preempt_current_incident_if_needed(&inc)?;
                 // Al incio, y si recibe un inc estando en su pos inicial, va a estar en estado Expecting
                 // Aviso al otro hilo que se puede desacolar y procesar el incidente activo
                 let _ = process_inc_tx.send(());
@@ -148,8 +374,10 @@ impl DronLogic {
                 self.logger.log(format!("DEBUG QUEUE: encolado el inc: {:?}", inc.get_source()));
                 
             }
-            IncidentState::ResolvedIncident => {
-                // Primero remuevo el incidente resuelto de la queue de incs a procesar, para no procesarlo luego
+            // Monitoreo publica cuándo se asignó y cuándo se empezó a atender; los drones ya lo saben por sí mismos.
+            IncidentState::Assigned | IncidentState::InAttention => {}
+            IncidentState::Resolved | IncidentState::Cancelled => {
+                // Primero remuevo el incidente resuelto (o cancelado) de la queue de incs a procesar, para no procesarlo luego
                 self.remove_from_active_incs(inc.get_info())?;
                 // Vuelvo a la posición inicial
                 self.go_back_if_my_inc_was_resolved(&inc)?;
@@ -180,6 +408,16 @@ impl DronLogic {
                         inc.get_info()
                     ));
                     Ok(())
+                // Si fue de este tipo, el vuelo se interrumpió para dejar el incidente y atender otro.
+                } else if e.kind() == ErrorKind::Interrupted {
+                    self.leave_current_incident()
+                // Si fue de este tipo, el dron se está apagando, y deja el incidente sin más.
+                } else if e.kind() == ErrorKind::ConnectionAborted {
+                    self.logger.log(format!(
+                        "Se interrumpe procesamiento de inc {:?} porque el dron se apaga.",
+                        inc.get_info()
+                    ));
+                    Ok(())
                 // Caso contrario sí fue un error real, y se devuelve.
                 } else {
                     Err(e)
@@ -189,40 +427,91 @@ impl DronLogic {
     }
 
     fn push_to_active_incs(&mut self, inc: &Incident) -> Result<(), Error> {
-        if let Ok(mut queue) = self.active_incs.lock(){
-            queue.push_back((inc.get_info(), inc.clone(), 0));
-            return Ok(());
-        } 
-        Err(Error::new(
-            ErrorKind::Other,
-            "Error al tomar lock de active_incs.",
-        ))
-
+        self.update_active_incs(|active_incs| active_incs.push(inc.clone()))?;
+        Ok(())
     }
 
-    /// Hace pop de la estructure de incidentes activos a manejar, si la misma está vacía devuelve Ok(None).
+    /// Hace pop del siguiente incidente activo a manejar según la política de desalojo, si no hay ninguno devuelve Ok(None).
     /// Y devuelve error si no se pudo tomar el lock.
-    fn pop_from_active_incs(&mut self) -> Result<Option<(IncidentInfo, Incident, u8)>, Error>   {
-        if let Ok(mut queue) = self.active_incs.lock(){
-            return Ok(queue.pop_front());
-        }
-        Err(Error::new(
-            ErrorKind::Other,
-            "Error al tomar lock de active_incs.",
-        ))
+    fn pop_from_active_incs(&mut self) -> Result<Option<Incident>, Error>   {
+        let position = self.current_data.get_current_position()?;
+        let policy = self.dron_properties.get_preemption_policy();
+        let distance_model = self.distance_model();
+        self.update_active_incs(|active_incs| active_incs.pop_next(policy, position, distance_model))
     }
 
     fn remove_from_active_incs(&mut self, inc_info: IncidentInfo) -> Result<(), Error> {
-        if let Ok(mut queue) = self.active_incs.lock(){
-            if let Some(pos) = queue.iter().position(|(info, _, _)| *info == inc_info) {
-                queue.remove(pos);
-            }
+        self.update_active_incs(|active_incs| active_incs.remove(&inc_info))?;
+        Ok(())
+    }
+
+    fn lock_active_incs(&self) -> Result<MutexGuard<'_, PendingIncidents>, Error> {
+        self.active_incs.lock().map_err(|_| {
+            Error::new(
+                ErrorKind::Other,
+                "Error al tomar lock de active_incs.",
+            )
+        })
+    }
+
+    /// Toma el lock de los incidentes pendientes y les aplica `f`. Luego registra en la current_info los que quedaron
+    /// pendientes, para publicarlos con ella.
+    fn update_active_incs<T>(&self, f: impl FnOnce(&mut PendingIncidents) -> T) -> Result<T, Error> {
+        let mut active_incs = self.lock_active_incs()?;
+        let result = f(&mut active_incs);
+        self.current_data.set_pending_incidents(active_incs.infos())?;
+        Ok(result)
+    }
+
+    /// Si está atendiendo un incidente y según su política de desalojo debe dejarlo por el incidente `inc`, que está
+    /// en su rango, lo deja: si ya había llegado lo deja ahora, y si todavía volaba hacia él se interrumpe el vuelo.
+    fn preempt_current_incident_if_needed(&mut self, inc: &Incident) -> Result<(), Error> {
+        if !self.is_within_range_from_self(inc.get_position(), self.dron_properties.get_range()) {
             return Ok(());
-        } 
-        Err(Error::new(
-            ErrorKind::Other,
-            "Error al tomar lock de active_incs.",
-        ))        
+        }
+        let state = self.current_data.get_state()?;
+        let arrived = state == DronState::ManagingIncident;
+        let flying_to_incident = matches!(state, DronState::MustRespondToIncident | DronState::Flying);
+        if !arrived && !flying_to_incident {
+            return Ok(());
+        }
+        let position = self.current_data.get_current_position()?;
+        let policy = self.dron_properties.get_preemption_policy();
+        if !self
+            .lock_active_incs()?
+            .should_preempt(policy, inc, position, self.distance_model(), arrived)
+        {
+            return Ok(());
+        }
+
+        self.logger.log(format!(
+            "Según la política {:?}, dejo el inc que atiendo para atender el inc {}.",
+            policy,
+            inc.get_id()
+        ));
+        if arrived {
+            self.leave_current_incident()
+        } else {
+            self.preempted.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Deja el incidente que está atendiendo para atender otro: lo vuelve a encolar, para atenderlo luego, y publica
+    /// el cambio para que monitoreo vea la reasignación. Queda esperando para procesar el siguiente incidente.
+    fn leave_current_incident(&mut self) -> Result<(), Error> {
+        let Some(left_inc) = self.lock_active_incs()?.take_attending() else {
+            return Ok(());
+        };
+        self.logger.log(format!("Dejo el inc {}, vuelve a quedar pendiente.", left_inc.get_id()));
+        self.current_data.set_state(DronState::IncidentReassigned, false)?;
+        self.publish_current_info()?;
+
+        self.update_active_incs(|active_incs| active_incs.push_front(left_inc))?;
+        self.current_data.unset_inc_id_to_resolve()?;
+        self.current_data
+            .set_state(DronState::ExpectingToRecvIncident, false)?;
+        self.publish_current_info()
     }
 
     /// Actualiza el contador de drones que ya están volando hacia el incidente del `ci` del dron recibido,
@@ -230,24 +519,9 @@ impl DronLogic {
     fn remove_from_active_incs_if_two_drones_already_flying(&mut self, ci: DronCurrentInfo) -> Result<(), Error> {
         // Obtiene el inc al que el dron recibido va a volar.
         if let Some(inc_info) = ci.get_inc_id_to_resolve() {
-            if let Ok(mut queue) = self.active_incs.lock(){
-                // Encuentra la posición del elemento (incidente) en la queue, y obtiene el elemento
-                if let Some(pos) = queue.iter().position(|(info, _, _)| *info == inc_info) {
-                    if let Some((_, _, mut amount_of_flying_drones)) = queue.get_mut(pos) {
-                        // Suma uno al contador de drones que ya están volando hacia el inc
-                        amount_of_flying_drones += 1;
-                        // Si la cantidad vale 2, lo remuevo
-                        if amount_of_flying_drones == 2 {
-                            queue.remove(pos);
-                        }
-                    }
-                }
-                return Ok(());
-            }
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Error al tomar lock de active_incs.",
-            ));
+            // Suma uno al contador de drones que ya están volando hacia el inc, y si ya van todos los que lo atienden, lo remuevo
+            self.update_active_incs(|active_incs| active_incs.count_flying_drone(&inc_info, DRONES_PER_INCIDENT))?;
+            return Ok(());
         }
 
         Err(Error::new(
@@ -256,69 +530,163 @@ impl DronLogic {
         ))        
     }
 
-    /// Por cada dron recibido si tenemos un incidente en comun se actualiza el hashmap con la menor distancia al incidente entre los drones (self_distance y recibido_distance).
+    /// Por cada dron recibido que se postula para un incidente en común, registra su postulación con su distancia al incidente.
     fn process_valid_dron(&self, received_dron: DronCurrentInfo) -> Result<(), Error> {
         // Obtengo el ID del incidente que el dron recibido está atendiendo
         if let Some(inc_info) = received_dron.get_inc_id_to_resolve() {
             if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
-                // Si el incidente ya está en el hashmap, agrego la menor distancia al incidente entre los dos drones. Si no, lo ignoro porque la rama "topic inc" no lo marco como de interés.
-                if let Some((incident_position, candidate_drones)) = distances.get_mut(&inc_info) {
-                    let received_dron_distance = received_dron.get_distance_to(*incident_position);
+                // Si el incidente ya está en el hashmap, registro la postulación. Si no, lo ignoro porque la rama "topic inc" no lo marco como de interés;
+                // el dron recibido vuelve a publicar su postulación mientras dura la elección.
+                if let Some(candidates) = distances.get_mut(&inc_info) {
+                    let received_dron_distance =
+                        self.distance(received_dron.get_current_position(), candidates.get_position());
+                    candidates.add_candidate(received_dron.get_id(), received_dron_distance);
+                }
+            }
+        }
 
-                    let self_distance = self.current_data.get_distance_to(*incident_position)?;
+        Ok(())
+    }
 
-                    // Agrego al vector la menor distancia entre los dos drones al incidente
-                    if self_distance <= received_dron_distance {
-                        candidate_drones.push((self.current_data.get_id()?, self_distance));
-                    } else {
-                        candidate_drones.push((received_dron.get_id(), received_dron_distance));
-                    }
+    /// Si el dron recibido va o ya llegó a un incidente, registra que confirmó que lo atiende.
+    fn register_confirmation(&self, received_dron: &DronCurrentInfo) -> Result<(), Error> {
+        let is_attending = matches!(
+            received_dron.get_state(),
+            DronState::MustRespondToIncident | DronState::Flying | DronState::ManagingIncident
+        );
+        if let (true, Some(inc_info)) = (is_attending, received_dron.get_inc_id_to_resolve()) {
+            if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
+                if let Some(candidates) = distances.get_mut(&inc_info) {
+                    candidates.confirm(received_dron.get_id());
                 }
+                return Ok(());
             }
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Error al tomar lock de drone_distances_by_incident.",
+            ));
         }
+        Ok(())
+    }
+
+    /// Registra su propia postulación para el incidente, con su distancia al mismo.
+    fn add_self_candidacy(&self, inc: &Incident) -> Result<(), Error> {
+        let self_id = self.current_data.get_id()?;
+        let self_distance = self.distance(self.current_data.get_current_position()?, inc.get_position());
+        self.with_candidates(inc, |candidates| candidates.add_candidate(self_id, self_distance))?;
+        Ok(())
+    }
+
+    /// Toma el lock y aplica `f` a las postulaciones del incidente, si el mismo está registrado.
+    fn with_candidates<T>(
+        &self,
+        inc: &Incident,
+        f: impl FnOnce(&mut IncidentCandidates) -> T,
+    ) -> Result<Option<T>, Error> {
+        if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
+            return Ok(distances.get_mut(&inc.get_info()).map(f));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de drone_distances_by_incident.",
+        ))
+    }
 
+    /// Quita al dron recibido de los candidatos del incidente que declinó por no tener batería suficiente,
+    /// para que lo atienda el siguiente más cercano.
+    fn remove_declined_candidate(&self, received_dron: DronCurrentInfo) -> Result<(), Error> {
+        if let Some(inc_info) = received_dron.get_inc_id_to_resolve() {
+            if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
+                if let Some(candidates) = distances.get_mut(&inc_info) {
+                    candidates.remove_candidate(received_dron.get_id());
+                }
+                return Ok(());
+            }
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Error al tomar lock de drone_distances_by_incident.",
+            ));
+        }
         Ok(())
     }
 
+    /// Devuelve si la batería actual alcanza para volar hasta el incidente y, desde allí, volver al centro de
+    /// su rango o a la estación de carga más cercana.
+    fn has_battery_for_round_trip(&self, inc_position: GeoPosition) -> Result<bool, Error> {
+        let to_incident = self.distance(self.current_data.get_current_position()?, inc_position);
+        let to_range_center = self.distance(inc_position, self.dron_properties.get_range_center_position());
+        let back = to_range_center.min(self.charging_stations.get_nearest_distance(inc_position));
+
+        let battery_lvl = self.current_data.get_battery_lvl()?;
+        Ok(self
+            .dron_properties
+            .get_battery_model()
+            .has_battery_for_trip(battery_lvl, to_incident + back))
+    }
+
+    /// Avisa que declina el incidente por el motivo `reason`, y vuelve a esperar incidentes.
+    fn decline_incident(&mut self, inc: &Incident, reason: DeclineReason) -> Result<(), Error> {
+        self.report_declined(inc, reason)?;
+        self.current_data.set_inc_id_to_resolve(inc.get_info())?;
+        self.current_data.set_state(DronState::IncidentDeclined, false)?;
+        self.publish_current_info()?;
+
+        self.remove_incident_from_hashmap(inc)?;
+        self.current_data.unset_inc_id_to_resolve()?;
+        self.current_data
+            .set_state(DronState::ExpectingToRecvIncident, false)?;
+        self.publish_current_info()
+    }
+
+    /// Elige, junto con los demás drones que se postularon, a los que atenderán el incidente.
+    /// Mientras dura la elección vuelve a publicar su postulación, por si alguna se perdió; al terminar elige a los más
+    /// cercanos. Si no quedó elegido, espera a que los elegidos confirmen, y si alguno no lo hace vuelve a elegir.
     fn decide_if_should_move_to_incident(
         &self,
         incident: &Incident,
     ) -> Result<bool, Error> {
-        let mut should_move = false;
+        let mut elapsed = Duration::ZERO;
+        while elapsed + CANDIDACY_REPUBLISH_INTERVAL < CANDIDACY_TIMEOUT {
+            self.current_data.sleep(CANDIDACY_REPUBLISH_INTERVAL);
+            elapsed += CANDIDACY_REPUBLISH_INTERVAL;
+            // Si mientras tanto dejó de postularse (por ejemplo, se fue a mantenimiento), no se lo elige.
+            if self.current_data.get_state()? != DronState::RespondingToIncident {
+                return Ok(false);
+            }
+            self.publish_current_info()?;
+        }
+        self.current_data.sleep(CANDIDACY_TIMEOUT - elapsed);
 
-        //eSTE THREAD ES NECESARI. NO QUITAR
-        thread::sleep(Duration::from_millis(3500)); // Aux Probando
-        if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
-            if let Some((_incident_position, candidate_drones)) =
-                distances.get_mut(&incident.get_info())
-            {
-                // Ordenar por el valor f64 de la tupla, de menor a mayor
-                candidate_drones.sort_by(|a, b| a.1.total_cmp(&b.1));
-
-                // Seleccionar los primeros dos elementos después de ordenar
-                let closest_two_drones: Vec<u8> =
-                    candidate_drones.iter().take(2).map(|&(id, _)| id).collect();
-
-                // Si el id del dron actual está en la lista de los dos más cercanos, entonces se mueve
-                should_move = closest_two_drones.contains(&self.current_data.get_id()?);
-                self.logger.log(format!(
-                    "Lado topic dron, evaluando distancias, debería moverme: {}",
-                    should_move
-                ));
+        let self_id = self.current_data.get_id()?;
+        let Some(winners) = self.with_candidates(incident, |candidates| candidates.select_winners())? else {
+            self.logger.log("Lado topic dron, esta condición no debería darse. Debería moverme: false".to_string());
+            return Ok(false);
+        };
+        self.logger.log(format!(
+            "Lado topic dron, elegidos para el inc {}: {:?}",
+            incident.get_id(),
+            winners
+        ));
+        if winners.contains(&self_id) {
+            return Ok(true);
+        }
 
-                // Si está vacío, no se recibió aviso de un dron más cercano, entonces voy yo
-                if closest_two_drones.is_empty() || closest_two_drones.len() == 1 {
-                    should_move = true; // ()
-                    self.logger.log(format!("Lado topic dron, evaluando distancias, debería moverme porque no hay nadie más: {}", should_move));
-                }
+        // No quedó elegido: si algún elegido no confirma que va, se vuelve a elegir sin él.
+        self.current_data.sleep(CONFIRMATION_TIMEOUT);
+        let winners = self.with_candidates(incident, |candidates| {
+            if candidates.is_fully_confirmed() {
+                vec![]
             } else {
-                self.logger.log(format!(
-                    "Lado topic dron, esta condición no debería darse. Debería moverme: {}",
-                    should_move
-                ));
+                candidates.reselect_without_unconfirmed()
             }
+        })?;
+        let should_move = winners.is_some_and(|winners| winners.contains(&self_id));
+        if should_move {
+            self.logger.log(format!(
+                "Lado topic dron, un elegido no confirmó el inc {}, lo reemplazo.",
+                incident.get_id()
+            ));
         }
-
         Ok(should_move)
     }
 
@@ -335,14 +703,15 @@ impl DronLogic {
         // Analizar condiciones para saber si se desplazará a la pos del incidente
         //  - batería es mayor al nivel bateria minima
         let batery_lvl = self.current_data.get_battery_lvl()?;
-        let enough_battery = batery_lvl >= self.dron_properties.get_min_operational_battery_lvl();
+        let enough_battery = batery_lvl >= self.current_data.get_min_operational_battery_lvl()?;
         //  - inc.pos dentro del rango
-        let (inc_lat, inc_lon) = inc_id.get_position();
         let inc_in_range =
-            self.is_within_range_from_self(inc_lat, inc_lon, self.dron_properties.get_range());
+            self.is_within_range_from_self(inc_id.get_position(), self.dron_properties.get_range());
 
         if enough_battery {
-            if inc_in_range {
+            if inc_in_range && !self.has_battery_for_round_trip(inc_id.get_position())? {
+                self.decline_incident(inc_id, DeclineReason::NotEnoughBattery)?;
+            } else if inc_in_range {
                 println!(
                     "  está en rango, evaluando si desplazarme a inc {}",
                     inc_id.get_id()
@@ -352,35 +721,48 @@ impl DronLogic {
                     inc_id.get_id()
                 ));
                 self.current_data.set_inc_id_to_resolve(inc_id.get_info())?; //
-                self.add_incident_to_hashmap(inc_id)?;
 
-                self.current_data
-                    .set_state(DronState::RespondingToIncident, false)?;
+                let should_move = if self.is_centrally_coordinated() || self.take_handed_off(inc_id)? {
+                    // Ya se lo asignó el coordinador de la flota, o lo releva a otro dron: no hace falta elegir.
+                    true
+                } else {
+                    self.add_incident_to_hashmap(inc_id)?;
 
-                // Publica su estado (su current info) para que otros drones vean la condición b, y monitoreo lo muestre en mapa
-                self.publish_current_info()?;
+                    self.current_data
+                        .set_state(DronState::RespondingToIncident, false)?;
+
+                    // Publica su estado (su current info) como postulación, para que otros drones vean la condición b, y monitoreo lo muestre en mapa
+                    self.add_self_candidacy(inc_id)?;
+                    self.publish_current_info()?;
 
-                let should_move =
-                    self.decide_if_should_move_to_incident(inc_id)?;
+                    self.decide_if_should_move_to_incident(inc_id)?
+                };
                 println!("   debería ir al incidente según cercanía: {}", should_move); // se puede borrar
                 self.logger.log(format!(
                     "   debería ir al incidente según cercanía: {}",
                     should_move
                 ));
-                if should_move {
+                if should_move && self.current_data.is_on_standby() {
+                    // Mientras se elegía, lo detuvieron o lo llamaron a la base: no va.
+                    self.decline_incident(inc_id, DeclineReason::OnStandby)?;
+                } else if should_move {
                     // Setea estado y avisa que quedó como ganador y se moverá al incidente
                     self.current_data.set_state(DronState::MustRespondToIncident, false)?;
                     self.publish_current_info()?;
 
                     // Volar hasta la posición del incidente
+                    self.lock_active_incs()?.set_attending(Some(inc_id.clone()));
+                    self.preempted.store(false, Ordering::SeqCst);
                     let destination = inc_id.get_position();
                     self.fly_to(destination)?;
                     self.remove_incident_from_hashmap(inc_id)?;
                 }
             } else {
                 println!("   el inc No está en mi rango."); // se puede borrar
-                self.logger
-                    .log(format!("  el inc {} No está en rango.", inc_id.get_id()));
+                // Aunque se lo haya asignado el coordinador o se le haya pedido un relevo, no sale de su radio de
+                // operación: lo declina, para que lo atienda otro dron.
+                self.take_handed_off(inc_id)?;
+                self.decline_incident(inc_id, DeclineReason::OutOfRange)?;
             }
         } else {
             // No tiene suficiente batería, por lo que debe ir a mantenimiento a recargarse
@@ -394,12 +776,43 @@ impl DronLogic {
         Ok(())
     }
 
+    /// Registra en el log que no atiende el incidente por el motivo `reason`, y lo publica por el topic de
+    /// diagnósticos, con la distancia del incidente al centro de su rango.
+    fn report_declined(&self, inc: &Incident, reason: DeclineReason) -> Result<(), Error> {
+        let distance = self
+            .dron_properties
+            .get_range_center_position()
+            .distance_to(&inc.get_position());
+        self.logger.log(format!(
+            "  {} ({:?}, a {:.0} m del centro de mi rango), declino el inc {}.",
+            reason.description(),
+            reason,
+            distance,
+            inc.get_id()
+        ));
+        if let Some(diagnostics) = &self.decline_diagnostics {
+            let declined = DeclinedIncident::new(self.current_data.get_id()?, inc.get_info(), reason, distance);
+            if let Err(e) = diagnostics.publish(&declined) {
+                self.logger.log(format!("Error al publicar diagnóstico: {:?}.", e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve el modelo de distancia configurado.
+    fn distance_model(&self) -> DistanceModel {
+        self.dron_properties.get_distance_model()
+    }
+
+    /// Devuelve la distancia entre `a` y `b`, según el modelo de distancia configurado.
+    fn distance(&self, a: GeoPosition, b: GeoPosition) -> f64 {
+        self.distance_model().distance(a, b)
+    }
+
     /// Calcula si se encuentra las coordenadas pasadas se encuentran dentro de su rango.
-    fn is_within_range_from_self(&self, latitude: f64, longitude: f64, range: f64) -> bool {
-        let (center_lat, center_lon) = self.dron_properties.get_range_center_position();
-        let lat_dist = center_lat - latitude;
-        let long_dist = center_lon - longitude;
-        let rad = f64::sqrt(lat_dist.powi(2) + long_dist.powi(2));
+    fn is_within_range_from_self(&self, position: GeoPosition, range: f64) -> bool {
+        let range_center = self.dron_properties.get_range_center_position();
+        let rad = self.distance(range_center, position);
 
         // Ajuste para aprox dos manzanas en diagonal
         let adjusted_range = range / 1000.0; // hay que modificar el range de las cámaras, ahora que son latitudes de verdad y no "3 4".
@@ -426,6 +839,7 @@ impl DronLogic {
                     inc.get_id()
                 ));
                 self.current_data.unset_inc_id_to_resolve()?; // [lo he subido una línea] [] aux
+                self.lock_active_incs()?.set_attending(None);
                 self.go_back_to_range_center_position()?;
                 
             }
@@ -434,11 +848,35 @@ impl DronLogic {
         Ok(())
     }
 
+    /// Si quedó esperando incidentes lejos del centro de su rango, sin más incidentes pendientes (por ejemplo, porque
+    /// fue directamente de un incidente resuelto al siguiente pendiente, y no quedó elegido para él), vuelve al centro.
+    /// Si lo llamaron a la base, vuelve aunque patrulle o tenga incidentes pendientes.
+    fn go_back_if_idle(&mut self) -> Result<(), Error> {
+        let recalled = self.current_data.is_recalled();
+        let is_idle = self.current_data.get_state()? == DronState::ExpectingToRecvIncident
+            && (self.current_data.get_pending_incidents()?.is_empty() || recalled);
+        let is_away = self.current_data.get_current_position()?
+            != self.dron_properties.get_range_center_position();
+        if is_idle && is_away && (!self.patrolling || recalled) {
+            self.go_back_to_range_center_position()?;
+        }
+        Ok(())
+    }
+
     /// Vuelve al centro de su rango (su posición inicial), y una vez que llega actualiza su estado
-    /// para continuar escuchando incidentes.
+    /// para continuar escuchando incidentes. Si patrulla, en cambio, vuelve a esperar incidentes desde donde
+    /// se encuentra, y el patrullaje lo lleva de regreso a su recorrido. Si tiene incidentes pendientes, también
+    /// vuelve a esperar desde donde se encuentra, para ir directamente al siguiente; salvo que lo hayan llamado a la base.
     fn go_back_to_range_center_position(
         &mut self,
     ) -> Result<(), Error> {
+        let stays = self.patrolling || !self.current_data.get_pending_incidents()?.is_empty();
+        if stays && !self.current_data.is_recalled() {
+            self.current_data
+                .set_state(DronState::ExpectingToRecvIncident, false)?;
+            return self.publish_current_info();
+        }
+
         // Volver, volar al range center
         let destination = self.dron_properties.get_range_center_position();
         self.fly_to(destination)?;
@@ -452,30 +890,56 @@ impl DronLogic {
 
     fn fly_to(
         &mut self,
-        destination: (f64, f64),
+        destination: GeoPosition,
     ) -> Result<(), Error> {
         let origin = self.current_data.get_current_position()?;
-        let dir = calculate_direction(origin, destination);
+        let dir = self.distance_model().direction(origin, destination);
+        let mut speed = self.current_data.get_cruise_speed()?;
         println!("Fly_to: volando"); // se puede borrar
         self.logger.log(format!(
-            "Fly_to: dir: {:?}, vel: {}",
+            "Fly_to: dir: {:?}, rumbo: {:.0}°, vel: {} m/s",
             dir,
-            self.dron_properties.get_speed()
+            origin.bearing_to(&destination),
+            speed
         ));
 
         self.current_data.set_state(DronState::Flying, false)?;
         self.current_data
-            .set_flying_info_values(dir, self.dron_properties.get_speed(), false)?;
+            .set_flying_info_values(dir, speed, false)?;
+        let tick = self.dron_properties.get_flight_tick();
         let mut current_pos = origin;
-        let threshold = 0.001; //
-        while calculate_distance(current_pos, destination) > threshold {
+        let mut last_tick = self.current_data.now();
+        while current_pos != destination {
+            // Si el dron se está apagando, interrumpe el vuelo.
+            self.current_data.check_not_shutting_down()?;
+            // Si debe dejar el incidente hacia el que vuela para atender otro, interrumpe el vuelo.
+            if self.preempted.swap(false, Ordering::SeqCst) {
+                self.current_data.unset_flying_info_values()?;
+                return Err(Error::new(
+                    ErrorKind::Interrupted,
+                    "Vuelo interrumpido para atender otro incidente.",
+                ));
+            }
+            // Simula el vuelo, el dron se desplaza según el tiempo real transcurrido desde el paso anterior
+            self.current_data.sleep(tick);
+            let now = self.current_data.now();
+            let elapsed = now.duration_since(last_tick);
+            last_tick = now;
+            // Si lo detuvieron, espera donde se encuentra hasta que vuelva a funcionar.
+            if self.current_data.is_paused() {
+                continue;
+            }
+            // La velocidad puede haberse ajustado con un comando de control durante el vuelo.
+            let current_speed = self.current_data.get_cruise_speed()?;
+            if current_speed != speed {
+                speed = current_speed;
+                self.current_data.set_flying_info_values(dir, speed, false)?;
+            }
+            let displacement = self.distance_model().flight_displacement(speed, elapsed);
             current_pos = self
                 .current_data
-                .increment_current_position_in(dir, false)?;
+                .move_towards(destination, displacement, false)?;
 
-            // Simula el vuelo, el dron se desplaza
-            let a = 4/5; // aux
-            sleep(Duration::from_secs(a));
             self.logger.log(format!(
                 "   incrementada la posición actual: {:?}",
                 self.current_data.get_current_position()
@@ -485,9 +949,6 @@ impl DronLogic {
             self.publish_current_info()?;
         }
 
-        // Salió del while porque está a muy poca distancia del destino. Hace ahora el paso final.
-        self.current_data.set_current_position(destination)?;
-
         // Al llegar, el dron ya no se encuentra en desplazamiento.
         self.current_data.unset_flying_info_values()?;
         self.logger.log(format!(
@@ -510,7 +971,10 @@ impl DronLogic {
 
     fn add_incident_to_hashmap(&self, inc: &Incident) -> Result<(), Error> {
         if let Ok(mut distances) = self.drone_distances_by_incident.lock() {
-            distances.insert(inc.get_info(), (inc.get_position(), Vec::new()));
+            // Si ya estaba registrado, se conservan las postulaciones recibidas hasta el momento.
+            distances
+                .entry(inc.get_info())
+                .or_insert_with(|| IncidentCandidates::new(inc.get_position()));
             return Ok(());
         }
         Err(Error::new(
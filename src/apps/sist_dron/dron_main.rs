@@ -1,12 +1,17 @@
-use std::io::Error;
+use std::{
+    io::{stdin, BufRead, Error},
+    thread,
+};
 
 use rustx::apps::{
     common_clients::{get_app_will_topic, join_all_threads},
-    sist_dron::{dron::Dron, utils::get_id_lat_long_and_broker_address},
+    geo_position::GeoPosition,
+    sist_dron::{
+        dron::Dron, dron_config::DronConfig, utils::get_id_lat_long_broker_address_and_config_file,
+    },
 };
 use rustx::logging::string_logger::StringLogger;
-use rustx::mqtt::client::mqtt_client::MQTTClient;
-use rustx::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
+use rustx::mqtt::client::mqtt_client_builder::MqttClientBuilder;
 use rustx::mqtt::mqtt_utils::will_message_utils::{app_type::AppType, will_content::WillContent};
 
 fn get_formatted_app_id(id: u8) -> String {
@@ -17,28 +22,49 @@ fn get_app_will_msg_content(id: u8) -> WillContent {
     WillContent::new(AppType::Dron, Some(id))
 }
 
+/// Lanza un hilo que lee comandos por teclado, y apaga el dron al ingresar `shutdown`.
+/// El hilo no se espera al salir, porque queda bloqueado leyendo.
+fn spawn_read_shutdown_command(dron: Dron) {
+    thread::spawn(move || {
+        println!("Ingrese 'shutdown' para apagar el dron.");
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            if line.trim() == "shutdown" {
+                dron.shutdown();
+                break;
+            }
+            println!("Comando desconocido. Ingrese 'shutdown' para apagar el dron.");
+        }
+    });
+}
+
 fn main() -> Result<(), Error> {
-    let (id, lat, lon, broker_addr) = get_id_lat_long_and_broker_address()?;
+    let (id, lat, lon, broker_addr, config_file) = get_id_lat_long_broker_address_and_config_file()?;
+    // Se carga la configuración antes de conectarse, para no conectarse si es inválida
+    let initial_position = GeoPosition::new(lat, lon)?;
+    let config = DronConfig::from_file(&config_file, id, initial_position)?;
 
     // Se crean y configuran ambos extremos del string logger
     let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id(id));
 
     // Se inicializa la conexión mqtt y el dron
-    let qos = 1; // []
+    let qos = config.get_qos();
     let client_id = get_formatted_app_id(id);
     let will_msg_content = get_app_will_msg_content(id);
-    let will_msg_data = WillMessageData::new(will_msg_content.to_str(), get_app_will_topic(), qos, 1);
-    
-    match MQTTClient::mqtt_connect_to_broker(client_id, &broker_addr, Some(will_msg_data), logger.clone_ref()) {
-        Ok((mqtt_client, publish_msg_rx, handle)) => {            
+    let mqtt_client_builder = MqttClientBuilder::new(&client_id)
+        .will(&get_app_will_topic(), &will_msg_content.to_str(), qos, true);
+
+    match mqtt_client_builder.connect(&broker_addr, logger.clone_ref()) {
+        Ok((mqtt_client, _publish_msg_rx, handle)) => {            
             println!("Conectado al broker MQTT.");
             logger.log("Conectado al broker MQTT".to_string());
 
-            let mut dron = Dron::new(id, lat, lon, logger.clone_ref())?;
+            let mut dron = Dron::new(id, initial_position, &config, logger.clone_ref())?;
 
-            let mut handles = dron.spawn_threads(mqtt_client, publish_msg_rx)?;
+            let mut handles = dron.spawn_threads(mqtt_client)?;
+            spawn_read_shutdown_command(dron.clone_ref());
             handles.push(handle);
             join_all_threads(handles);
+            println!("Dron {} apagado.", id);
         }
         Err(e) => println!("Dron ID {} : Error al conectar al broker MQTT: {:?}", id, e),
     }
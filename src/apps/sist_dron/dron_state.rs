@@ -1,14 +1,19 @@
 use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum DronState {
     ExpectingToRecvIncident,
     RespondingToIncident, // analizando si se va a mover (se evalúa la condición de los dos más cercanos)
     MustRespondToIncident, // confirmado que se va a mover al incidente
     Flying,
-    Mantainance,
+    Mantainance, // yendo a cargarse a una estación, o cargándose
     ManagingIncident, // llegó al incidente
     IncidentResolved,
+    IncidentDeclined, // no le alcanza la batería para ir al incidente y volver, lo deja para el siguiente más cercano
+    IncidentReassigned, // dejó el incidente que atendía para atender otro, según su política de desalojo
+    Offline, // se está apagando; es la última current_info que publica antes de desconectarse
+    ScheduledMaintenance, // en mantenimiento programado, por el desgaste acumulado; no toma incidentes
 }
 
 impl DronState {
@@ -21,6 +26,10 @@ impl DronState {
             DronState::Mantainance => 5_u8.to_be_bytes(),
             DronState::ManagingIncident => 6_u8.to_be_bytes(),
             DronState::IncidentResolved => 7_u8.to_be_bytes(),
+            DronState::IncidentDeclined => 8_u8.to_be_bytes(),
+            DronState::IncidentReassigned => 9_u8.to_be_bytes(),
+            DronState::Offline => 10_u8.to_be_bytes(),
+            DronState::ScheduledMaintenance => 11_u8.to_be_bytes(),
         }
     }
 
@@ -33,10 +42,19 @@ impl DronState {
             5 => Ok(DronState::Mantainance),
             6 => Ok(DronState::ManagingIncident),
             7 => Ok(DronState::IncidentResolved),
+            8 => Ok(DronState::IncidentDeclined),
+            9 => Ok(DronState::IncidentReassigned),
+            10 => Ok(DronState::Offline),
+            11 => Ok(DronState::ScheduledMaintenance),
             _ => Err(Error::new(
                 ErrorKind::InvalidInput,
                 "Estado de dron no válido",
             )),
         }
     }
+
+    /// Devuelve si el dron está en mantenimiento, ya sea para cargarse o programado por su desgaste.
+    pub fn is_in_maintenance(&self) -> bool {
+        matches!(self, DronState::Mantainance | DronState::ScheduledMaintenance)
+    }
 }
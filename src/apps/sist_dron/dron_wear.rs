@@ -0,0 +1,137 @@
+use std::{io::Error, time::Duration};
+
+use super::dron_config::DronProperties;
+
+/// Cuánto dura el mantenimiento programado, si el archivo no define `maintenance_duration_secs`.
+const DEFAULT_MAINTENANCE_DURATION_SECS: u64 = 30;
+
+/// Desgaste acumulado del dron desde su último mantenimiento programado: la distancia que voló, en metros, y el
+/// tiempo que estuvo volando.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct DronWear {
+    flight_distance: f64,
+    flight_time: Duration,
+}
+
+impl DronWear {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Suma al desgaste `distance` metros volados en `flight_time`.
+    pub fn record(&mut self, distance: f64, flight_time: Duration) {
+        self.flight_distance += distance;
+        self.flight_time += flight_time;
+    }
+
+    /// Devuelve la distancia volada, en metros.
+    pub fn get_flight_distance(&self) -> f64 {
+        self.flight_distance
+    }
+
+    pub fn get_flight_time(&self) -> Duration {
+        self.flight_time
+    }
+
+    /// Devuelve si el desgaste alcanzó alguno de los umbrales de `schedule`.
+    pub fn needs_maintenance(&self, schedule: &MaintenanceSchedule) -> bool {
+        let distance_reached = schedule
+            .max_flight_distance
+            .is_some_and(|max_distance| self.flight_distance >= max_distance);
+        let time_reached = schedule
+            .max_flight_time
+            .is_some_and(|max_time| self.flight_time >= max_time);
+        distance_reached || time_reached
+    }
+
+    /// Vuelve a cero el desgaste, luego de un mantenimiento.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Umbrales de desgaste a partir de los cuales el dron va a mantenimiento, y cuánto dura el mismo.
+/// Sin umbrales configurados, el dron nunca va a mantenimiento programado.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MaintenanceSchedule {
+    max_flight_distance: Option<f64>, // en metros
+    max_flight_time: Option<Duration>,
+    duration: Duration,
+}
+
+impl MaintenanceSchedule {
+    pub fn new(
+        max_flight_distance: Option<f64>,
+        max_flight_time: Option<Duration>,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            max_flight_distance,
+            max_flight_time,
+            duration,
+        }
+    }
+
+    /// Carga los umbrales de las properties opcionales `maintenance_after_km` y `maintenance_after_flight_hours`,
+    /// y la duración de `maintenance_duration_secs`.
+    pub fn from_properties(properties: &DronProperties) -> Result<Self, Error> {
+        let is_positive = |value: &f64| value.is_finite() && *value > 0.0;
+        let max_flight_km = properties.get_optional("maintenance_after_km", is_positive)?;
+        let max_flight_hours =
+            properties.get_optional("maintenance_after_flight_hours", is_positive)?;
+        let duration_secs = properties
+            .get_optional("maintenance_duration_secs", |_: &u64| true)?
+            .unwrap_or(DEFAULT_MAINTENANCE_DURATION_SECS);
+        Ok(Self::new(
+            max_flight_km.map(|km| km * 1000.0),
+            max_flight_hours.map(|hours| Duration::from_secs_f64(hours * 3600.0)),
+            Duration::from_secs(duration_secs),
+        ))
+    }
+
+    /// Devuelve si hay algún umbral configurado.
+    pub fn is_enabled(&self) -> bool {
+        self.max_flight_distance.is_some() || self.max_flight_time.is_some()
+    }
+
+    /// Devuelve cuánto permanece el dron en mantenimiento.
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl Default for MaintenanceSchedule {
+    fn default() -> Self {
+        Self::new(
+            None,
+            None,
+            Duration::from_secs(DEFAULT_MAINTENANCE_DURATION_SECS),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{DronWear, MaintenanceSchedule};
+
+    #[test]
+    fn test_1_el_dron_necesita_mantenimiento_al_alcanzar_algun_umbral() {
+        let schedule =
+            MaintenanceSchedule::new(Some(1000.0), Some(Duration::from_secs(60)), Duration::ZERO);
+        let mut wear = DronWear::new();
+        wear.record(600.0, Duration::from_secs(20));
+        assert!(!wear.needs_maintenance(&schedule));
+        wear.record(400.0, Duration::from_secs(20));
+        assert!(wear.needs_maintenance(&schedule));
+
+        wear.reset();
+        wear.record(10.0, Duration::from_secs(60));
+        assert!(wear.needs_maintenance(&schedule));
+
+        // Sin umbrales, nunca lo necesita.
+        assert!(!MaintenanceSchedule::default().is_enabled());
+        assert!(!wear.needs_maintenance(&MaintenanceSchedule::default()));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    apps::{
+        apps_mqtt_topics::AppsMqttTopics, payload_codec::decode_payload,
+        sist_monitoreo::order_checker::OrderChecker,
+    },
+    logging::string_logger::StringLogger,
+    mqtt::{client::mqtt_client::MQTTClient, messages::publish_message::PublishMessage},
+};
+
+use super::{
+    fleet_state::FleetState, handoff::HandoffRequest,
+    incident_assignment::IncidentAssignment,
+};
+
+/// Coordinador central de la flota de drones, para cuando los drones se configuran con
+/// `incident_coordination=central`. Se suscribe a los topics `dron` e `inc`, mantiene una vista global de la
+/// disponibilidad y batería de los drones (ver `FleetState`), y publica por el topic `dron-assign` qué drones
+/// atienden cada incidente. También se suscribe a `dron-handoff`, para reemplazar a los drones que deben ir a
+/// cargarse mientras atienden un incidente. No depende de que los drones se pongan de acuerdo entre ellos, por lo que es útil
+/// cuando la pérdida de mensajes vuelve poco confiable a la elección distribuida.
+#[derive(Debug)]
+pub struct FleetCoordinator {
+    fleet: Arc<Mutex<FleetState>>,
+    qos: u8,
+    logger: StringLogger,
+}
+
+impl FleetCoordinator {
+    pub fn new(fleet: FleetState, qos: u8, logger: StringLogger) -> Self {
+        Self {
+            fleet: Arc::new(Mutex::new(fleet)),
+            qos,
+            logger,
+        }
+    }
+
+    fn clone_ref(&self) -> Self {
+        Self {
+            fleet: self.fleet.clone(),
+            qos: self.qos,
+            logger: self.logger.clone_ref(),
+        }
+    }
+
+    /// Se suscribe a los topics `dron`, `inc` y `dron-handoff`, y lanza el hilo que procesa los mensajes recibidos y publica
+    /// las asignaciones.
+    pub fn spawn_threads(&self, mqtt_client: MQTTClient) -> Result<Vec<JoinHandle<()>>, Error> {
+        let mqtt_client = Arc::new(Mutex::new(mqtt_client));
+        let (msg_tx, msg_rx) = mpsc::channel::<PublishMessage>();
+        self.subscribe_to_topics(&mqtt_client, msg_tx)?;
+
+        let self_clone = self.clone_ref();
+        Ok(vec![thread::spawn(move || {
+            self_clone.process_recvd_msgs(msg_rx, &mqtt_client);
+        })])
+    }
+
+    /// Se suscribe a los topics de drones e incidentes, enviando los mensajes recibidos por `msg_tx` para
+    /// procesarlos en otro hilo, ya que procesarlos implica publicar.
+    fn subscribe_to_topics(
+        &self,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+        msg_tx: Sender<PublishMessage>,
+    ) -> Result<(), Error> {
+        let mut mqtt_client = mqtt_client.lock().map_err(|_| {
+            Error::new(ErrorKind::Other, "Error al obtener el lock del mqtt_client")
+        })?;
+        for topic in [
+            AppsMqttTopics::DronTopic,
+            AppsMqttTopics::IncidentTopic,
+            AppsMqttTopics::DronHandoffTopic,
+        ] {
+            let msg_tx = msg_tx.clone();
+            mqtt_client.mqtt_subscribe_with_handler(topic.to_str(), self.qos, move |pub_msg| {
+                let _ = msg_tx.send(pub_msg);
+            })?;
+        }
+        self.logger
+            .log("Suscripto a los topics dron, inc y dron-handoff.".to_string());
+        Ok(())
+    }
+
+    /// Procesa los mensajes recibidos hasta que se cierre la conexión. Descarta las current_info de cada dron más
+    /// viejas que la última procesada, ya que con qos 1 los mensajes pueden llegar desordenados.
+    fn process_recvd_msgs(
+        &self,
+        msg_rx: Receiver<PublishMessage>,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+    ) {
+        let mut order_checker = OrderChecker::new();
+        for msg in msg_rx {
+            match order_checker.is_newest(&msg) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    self.logger.log(format!("Error en OrderChecker: {:?}", e));
+                    continue;
+                }
+            }
+            match self.process_recvd_msg(msg) {
+                Ok(assignments) => {
+                    for assignment in assignments {
+                        self.publish_assignment(&assignment, mqtt_client);
+                    }
+                }
+                Err(e) => self
+                    .logger
+                    .log(format!("Error al procesar mensaje recibido: {:?}", e)),
+            }
+        }
+    }
+
+    /// Actualiza la vista de la flota con el mensaje recibido, y devuelve las asignaciones que cambiaron.
+    fn process_recvd_msg(&self, msg: PublishMessage) -> Result<Vec<IncidentAssignment>, Error> {
+        let mut fleet = self
+            .fleet
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::Other, "Error al tomar lock de la flota."))?;
+        match AppsMqttTopics::topic_from_str(&msg.get_topic())? {
+            AppsMqttTopics::DronTopic => {
+                Ok(fleet.update_dron(decode_payload(&msg.get_payload())?))
+            }
+            AppsMqttTopics::IncidentTopic => {
+                Ok(fleet.update_incident(decode_payload(&msg.get_payload())?))
+            }
+            AppsMqttTopics::DronHandoffTopic => {
+                let request = HandoffRequest::from_bytes(msg.get_payload())?;
+                let inc_info = request.get_incident().get_info();
+                Ok(fleet.hand_off(&inc_info, request.get_leaving_dron_id()))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "Topic no conocido")),
+        }
+    }
+
+    /// Publica la asignación por el topic `dron-assign`.
+    fn publish_assignment(
+        &self,
+        assignment: &IncidentAssignment,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+    ) {
+        self.logger.log(format!(
+            "Asignando el inc {} a los drones {:?}.",
+            assignment.get_incident().get_id(),
+            assignment.get_dron_ids()
+        ));
+        if let Ok(mut mqtt_client) = mqtt_client.lock() {
+            if let Err(e) = mqtt_client.mqtt_publish(
+                AppsMqttTopics::DronAssignmentTopic.to_str(),
+                &assignment.to_bytes(),
+                self.qos,
+            ) {
+                self.logger
+                    .log(format!("Error al publicar asignación: {:?}", e));
+            }
+        }
+    }
+}
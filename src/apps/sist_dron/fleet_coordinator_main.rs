@@ -0,0 +1,55 @@
+use std::io::Error;
+
+use rustx::apps::{
+    common_clients::join_all_threads,
+    sist_dron::{
+        dron_config::{get_qos, DronProperties},
+        fleet_coordinator::FleetCoordinator,
+        fleet_state::FleetState,
+        sist_dron_properties::SistDronProperties,
+        utils::get_broker_address_and_config_file,
+    },
+};
+use rustx::logging::string_logger::StringLogger;
+use rustx::mqtt::client::mqtt_client_builder::MqttClientBuilder;
+
+fn get_formatted_app_id() -> String {
+    String::from("Coordinador-Flota")
+}
+
+fn main() -> Result<(), Error> {
+    let (broker_addr, config_file) = get_broker_address_and_config_file()?;
+    // Se carga la configuración común de los drones antes de conectarse, para no conectarse si es inválida
+    let properties = DronProperties::common_from_file(&config_file)?;
+    let fleet = FleetState::new(&SistDronProperties::from_properties(&properties)?);
+    let qos = get_qos(&properties)?;
+
+    // Se crean y configuran ambos extremos del string logger
+    let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id());
+
+    let coordinator = FleetCoordinator::new(fleet, qos, logger.clone_ref());
+    match MqttClientBuilder::new(&get_formatted_app_id()).connect(&broker_addr, logger.clone_ref())
+    {
+        Ok((mqtt_client, _publish_msg_rx, handle)) => {
+            println!("Conectado al broker MQTT.");
+            logger.log("Conectado al broker MQTT".to_string());
+
+            let mut handles = coordinator.spawn_threads(mqtt_client)?;
+            handles.push(handle);
+            join_all_threads(handles);
+        }
+        Err(e) => println!(
+            "Coordinador-Flota: Error al conectar al broker MQTT: {:?}",
+            e
+        ),
+    }
+    logger.stop_logging();
+    drop(coordinator); // porque le hicimos clone_ref al logger.
+
+    // Se espera al hijo para el logger writer
+    if handle_logger.join().is_err() {
+        println!("Error al esperar al hijo para string logger writer.")
+    }
+
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::apps::{
+    geo_position::GeoPosition,
+    incident_data::{incident::Incident, incident_info::IncidentInfo},
+};
+
+use super::{
+    calculations::DistanceModel, dron_current_info::DronCurrentInfo, dron_state::DronState,
+    incident_arbitration::DRONES_PER_INCIDENT, incident_assignment::IncidentAssignment,
+    sist_dron_properties::SistDronProperties,
+};
+
+/// Vista global de la flota que mantiene el coordinador: la última current_info de cada dron, y los incidentes
+/// activos con los drones asignados a cada uno.
+/// Asigna a cada incidente los `DRONES_PER_INCIDENT` drones disponibles más cercanos, atendiendo primero a los de
+/// mayor prioridad. Un dron está disponible si espera incidentes, tiene al menos la batería mínima, el incidente está
+/// a su alcance y no tiene otro asignado. Si un dron declina el incidente, se va a mantenimiento o se apaga, se lo
+/// reemplaza por el siguiente disponible; y si no hay suficientes, el incidente queda pendiente hasta que lo estén.
+#[derive(Debug)]
+pub struct FleetState {
+    drones: HashMap<u8, DronCurrentInfo>,
+    incidents: HashMap<IncidentInfo, AssignedIncident>,
+    range: f64,
+    distance_model: DistanceModel,
+    min_operational_battery_lvl: u8,
+}
+
+/// Incidente activo, los drones asignados a él, y los que lo declinaron y no deben volver a asignarse.
+#[derive(Debug)]
+struct AssignedIncident {
+    incident: Incident,
+    dron_ids: Vec<u8>,
+    declined: HashSet<u8>,
+}
+
+impl FleetState {
+    /// Crea la vista de la flota. El alcance y la batería mínima de los drones se toman de sus `properties`.
+    pub fn new(properties: &SistDronProperties) -> Self {
+        Self {
+            drones: HashMap::new(),
+            incidents: HashMap::new(),
+            range: properties.get_range(),
+            distance_model: properties.get_distance_model(),
+            min_operational_battery_lvl: properties.get_min_operational_battery_lvl(),
+        }
+    }
+
+    /// Registra la current_info recibida de un dron. Devuelve las asignaciones que cambiaron por ello.
+    pub fn update_dron(&mut self, ci: DronCurrentInfo) -> Vec<IncidentAssignment> {
+        let dron_id = ci.get_id();
+        let mut changed = HashSet::new();
+        match ci.get_state() {
+            // Deja el incidente, y no se le vuelve a asignar.
+            DronState::IncidentDeclined | DronState::IncidentReassigned => {
+                if let Some(inc_info) = ci.get_inc_id_to_resolve() {
+                    if let Some(assigned) = self.incidents.get_mut(&inc_info) {
+                        assigned.declined.insert(dron_id);
+                        if assigned.unassign(dron_id) {
+                            changed.insert(inc_info);
+                        }
+                    }
+                }
+            }
+            // Deja cualquier incidente que tuviera asignado.
+            DronState::Mantainance | DronState::ScheduledMaintenance | DronState::Offline => {
+                for (inc_info, assigned) in self.incidents.iter_mut() {
+                    if assigned.unassign(dron_id) {
+                        changed.insert(*inc_info);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if ci.get_state() == DronState::Offline {
+            self.drones.remove(&dron_id);
+        } else {
+            self.drones.insert(dron_id, ci);
+        }
+        self.assign_pending(changed)
+    }
+
+    /// Registra el incidente recibido: si está activo lo asigna, y si se resolvió o se canceló lo olvida.
+    /// Devuelve las asignaciones que cambiaron por ello.
+    pub fn update_incident(&mut self, inc: Incident) -> Vec<IncidentAssignment> {
+        if inc.is_closed() {
+            self.incidents.remove(&inc.get_info());
+        } else {
+            self.incidents
+                .entry(inc.get_info())
+                .or_insert_with(|| AssignedIncident::new(inc));
+        }
+        self.assign_pending(HashSet::new())
+    }
+
+    /// Registra que el dron `dron_id` pidió que lo releven en el incidente, porque debe ir a cargarse: se lo quita de
+    /// los asignados, sin volver a asignárselo, y se lo reemplaza por el siguiente disponible.
+    /// Devuelve las asignaciones que cambiaron por ello.
+    pub fn hand_off(&mut self, inc_info: &IncidentInfo, dron_id: u8) -> Vec<IncidentAssignment> {
+        let mut changed = HashSet::new();
+        if let Some(assigned) = self.incidents.get_mut(inc_info) {
+            assigned.declined.insert(dron_id);
+            if assigned.unassign(dron_id) {
+                changed.insert(*inc_info);
+            }
+        }
+        self.assign_pending(changed)
+    }
+
+    /// Devuelve los drones que se encuentran en la posición del incidente, atendiéndolo.
+    pub fn drones_at(&self, inc_info: &IncidentInfo) -> Vec<u8> {
+        self.drones
+            .values()
+            .filter(|ci| ci.get_state() == DronState::ManagingIncident)
+            .filter(|ci| ci.get_inc_id_to_resolve() == Some(*inc_info))
+            .map(|ci| ci.get_id())
+            .collect()
+    }
+
+    /// Devuelve los drones asignados al incidente, o None si no es un incidente activo.
+    pub fn get_assigned(&self, inc_info: &IncidentInfo) -> Option<&[u8]> {
+        self.incidents
+            .get(inc_info)
+            .map(|assigned| assigned.dron_ids.as_slice())
+    }
+
+    /// Completa los drones de los incidentes a los que les faltan, del de mayor prioridad (y gravedad) al de menor.
+    /// Devuelve las asignaciones de esos incidentes, y de los de `changed`, cuyos drones cambiaron.
+    fn assign_pending(&mut self, mut changed: HashSet<IncidentInfo>) -> Vec<IncidentAssignment> {
+        let mut pending: Vec<(IncidentInfo, Incident)> = self
+            .incidents
+            .iter()
+            .filter(|(_, assigned)| assigned.dron_ids.len() < DRONES_PER_INCIDENT)
+            .map(|(inc_info, assigned)| (*inc_info, assigned.incident.clone()))
+            .collect();
+        pending.sort_by(|(_, a), (_, b)| {
+            b.get_urgency()
+                .cmp(&a.get_urgency())
+                .then(a.get_id().cmp(&b.get_id()))
+        });
+
+        for (inc_info, incident) in pending {
+            let mut dron_ids = self.incidents[&inc_info].dron_ids.clone();
+            while dron_ids.len() < DRONES_PER_INCIDENT {
+                let Some(dron_id) = self.nearest_available(&inc_info, &incident) else {
+                    break;
+                };
+                dron_ids.push(dron_id);
+                if let Some(assigned) = self.incidents.get_mut(&inc_info) {
+                    assigned.dron_ids.push(dron_id);
+                }
+                changed.insert(inc_info);
+            }
+        }
+
+        changed
+            .iter()
+            .filter_map(|inc_info| self.incidents.get(inc_info))
+            .map(|assigned| {
+                IncidentAssignment::new(assigned.incident.clone(), assigned.dron_ids.clone())
+            })
+            .collect()
+    }
+
+    /// Devuelve el dron disponible más cercano al incidente, desempatando por id; o None si no hay ninguno.
+    fn nearest_available(&self, inc_info: &IncidentInfo, incident: &Incident) -> Option<u8> {
+        let mut excluded: HashSet<u8> = self
+            .incidents
+            .values()
+            .flat_map(|assigned| assigned.dron_ids.iter().copied())
+            .collect();
+        excluded.extend(&self.incidents[inc_info].declined);
+        self.nearest_available_to(incident.get_position(), &excluded)
+    }
+
+    /// Devuelve el dron disponible más cercano a `position`, sin contar a los de `excluded` y desempatando por id;
+    /// o None si no hay ninguno. Un dron está disponible si espera incidentes, tiene al menos la batería mínima y
+    /// la posición está a su alcance.
+    pub fn nearest_available_to(&self, position: GeoPosition, excluded: &HashSet<u8>) -> Option<u8> {
+        // El range se expresa en milésimas de latitud y longitud.
+        let reach = self.range / 1000.0;
+
+        self.drones
+            .values()
+            .filter(|ci| !excluded.contains(&ci.get_id()))
+            .filter(|ci| ci.get_state() == DronState::ExpectingToRecvIncident)
+            .filter(|ci| ci.get_battery_lvl() >= self.min_operational_battery_lvl)
+            .map(|ci| (ci.get_id(), self.distance_model.distance(ci.get_current_position(), position)))
+            .filter(|(_, distance)| *distance <= reach)
+            .min_by(|(id_a, dist_a), (id_b, dist_b)| dist_a.total_cmp(dist_b).then(id_a.cmp(id_b)))
+            .map(|(dron_id, _)| dron_id)
+    }
+}
+
+impl AssignedIncident {
+    fn new(incident: Incident) -> Self {
+        Self {
+            incident,
+            dron_ids: vec![],
+            declined: HashSet::new(),
+        }
+    }
+
+    /// Quita al dron de los asignados al incidente. Devuelve si estaba asignado.
+    fn unassign(&mut self, dron_id: u8) -> bool {
+        let assigned = self.dron_ids.contains(&dron_id);
+        self.dron_ids.retain(|id| *id != dron_id);
+        assigned
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FleetState;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{
+            incident::Incident, incident_priority::IncidentPriority,
+            incident_source::IncidentSource, incident_state::IncidentState,
+        },
+        sist_dron::{
+            dron_config::{DronConfig, DEFAULT_CONFIG_FILE},
+            dron_current_info::DronCurrentInfo,
+            dron_state::DronState,
+        },
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn create_fleet() -> FleetState {
+        let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, 1, position(-34.6, -58.4)).unwrap();
+        let mut fleet = FleetState::new(&config.get_properties());
+        // Drones esperando incidentes, a distintas distancias del punto (-34.6, -58.4).
+        for (id, lon) in [(1, -58.41), (2, -58.402), (3, -58.404), (4, -58.5)] {
+            let ci = DronCurrentInfo::new(id, position(-34.6, lon), 100, DronState::ExpectingToRecvIncident);
+            fleet.update_dron(ci);
+        }
+        fleet
+    }
+
+    fn create_incident(id: u8) -> Incident {
+        Incident::new(id, position(-34.6, -58.4), IncidentSource::Manual)
+    }
+
+    #[test]
+    fn test_1_se_asignan_los_dos_drones_disponibles_mas_cercanos() {
+        let mut fleet = create_fleet();
+        let assignments = fleet.update_incident(create_incident(1));
+
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].get_dron_ids(), &[2, 3]);
+
+        // El siguiente incidente recibe a los que quedan a su alcance: el dron 4 está fuera de su alcance.
+        let assignments = fleet.update_incident(create_incident(2));
+        assert_eq!(assignments[0].get_dron_ids(), &[1]);
+    }
+
+    #[test]
+    fn test_2_si_un_dron_declina_se_lo_reemplaza_y_no_se_le_vuelve_a_asignar() {
+        let mut fleet = create_fleet();
+        let incident = create_incident(1);
+        fleet.update_incident(incident.clone());
+
+        let mut declined =
+            DronCurrentInfo::new(2, position(-34.6, -58.402), 100, DronState::IncidentDeclined);
+        declined.set_inc_id_to_resolve(incident.get_info());
+        let assignments = fleet.update_dron(declined);
+        assert_eq!(assignments[0].get_dron_ids(), &[3, 1]);
+
+        // Vuelve a esperar incidentes, pero no se le reasigna el que declinó.
+        let expecting =
+            DronCurrentInfo::new(2, position(-34.6, -58.402), 100, DronState::ExpectingToRecvIncident);
+        fleet.update_dron(expecting);
+        let offline = DronCurrentInfo::new(1, position(-34.6, -58.41), 100, DronState::Offline);
+        let assignments = fleet.update_dron(offline);
+        assert_eq!(assignments[0].get_dron_ids(), &[3]);
+    }
+
+    #[test]
+    fn test_3_los_incidentes_pendientes_se_asignan_por_prioridad_al_liberarse_drones() {
+        let mut fleet = create_fleet();
+        fleet.update_incident(create_incident(1));
+        fleet.update_incident(create_incident(2));
+        let urgent = create_incident(3).with_priority(IncidentPriority::High);
+        assert!(fleet.update_incident(urgent.clone()).is_empty());
+
+        // Al resolverse el primer incidente, sus drones se liberan y van al más prioritario.
+        let mut resolved = create_incident(1);
+        for state in [IncidentState::Assigned, IncidentState::InAttention, IncidentState::Resolved] {
+            resolved.transition_to(state).unwrap();
+        }
+        fleet.update_incident(resolved);
+        assert_eq!(fleet.get_assigned(&create_incident(1).get_info()), None);
+        assert_eq!(fleet.get_assigned(&urgent.get_info()), Some(&[2, 3][..]));
+    }
+
+    #[test]
+    fn test_4_el_dron_que_pide_relevo_se_reemplaza_por_el_siguiente_disponible() {
+        let mut fleet = create_fleet();
+        let incident = create_incident(1);
+        fleet.update_incident(incident.clone());
+
+        let assignments = fleet.hand_off(&incident.get_info(), 2);
+        assert_eq!(assignments[0].get_dron_ids(), &[3, 1]);
+
+        // El reemplazo se sabe que llegó al ver su current_info en el incidente.
+        assert!(fleet.drones_at(&incident.get_info()).is_empty());
+        let mut arrived = DronCurrentInfo::new(1, position(-34.6, -58.4), 90, DronState::ManagingIncident);
+        arrived.set_inc_id_to_resolve(incident.get_info());
+        fleet.update_dron(arrived);
+        assert_eq!(fleet.drones_at(&incident.get_info()), vec![1]);
+    }
+}
@@ -0,0 +1,41 @@
+use super::sist_dron_properties::SistDronProperties;
+
+/// Parámetros de vuelo del dron que pueden ajustarse mientras funciona, con comandos de control (ver `DronCommand`).
+/// Inician con los valores del archivo de configuración.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FlightSettings {
+    cruise_speed: f64,
+    min_operational_battery_lvl: u8,
+    max_battery_lvl: u8,
+}
+
+impl FlightSettings {
+    pub fn new(properties: &SistDronProperties) -> Self {
+        Self {
+            cruise_speed: properties.get_cruise_speed(),
+            min_operational_battery_lvl: properties.get_min_operational_battery_lvl(),
+            max_battery_lvl: properties.get_max_battery_lvl(),
+        }
+    }
+
+    pub fn get_cruise_speed(&self) -> f64 {
+        self.cruise_speed
+    }
+
+    pub fn set_cruise_speed(&mut self, cruise_speed: f64) {
+        self.cruise_speed = cruise_speed;
+    }
+
+    pub fn get_min_operational_battery_lvl(&self) -> u8 {
+        self.min_operational_battery_lvl
+    }
+
+    pub fn get_max_battery_lvl(&self) -> u8 {
+        self.max_battery_lvl
+    }
+
+    pub fn set_battery_thresholds(&mut self, min_operational_battery_lvl: u8, max_battery_lvl: u8) {
+        self.min_operational_battery_lvl = min_operational_battery_lvl;
+        self.max_battery_lvl = max_battery_lvl;
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        incident_data::{incident::Incident, incident_info::IncidentInfo},
+    },
+    mqtt::{client::mqtt_client::MQTTClient, mqtt_utils::mqtt_error::MqttError},
+};
+
+use super::{
+    data::Data, dron_current_info::DronCurrentInfo, fleet_state::FleetState,
+    incident_arbitration::CoordinationMode, pending_incidents::PendingIncidents,
+};
+
+/// Largo mínimo de un incidente en bytes: id, latitud, longitud, estado y origen.
+const INCIDENT_MIN_LEN: usize = 19;
+/// Tiempo máximo que el dron que pidió el relevo espera a que llegue su reemplazo, antes de irse a cargar igual.
+pub const HANDOFF_TIMEOUT: Duration = Duration::from_secs(30);
+/// Cada cuánto se fija, mientras espera, si ya llegó su reemplazo.
+const HANDOFF_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Pedido de relevo que publica por el topic `dron-handoff` un dron que atiende un incidente y debe ir a cargarse.
+/// Si los drones se eligen entre ellos, el pedido nombra al reemplazo, que elige quien lo pide; y si los asigna el
+/// coordinador de la flota, no lo nombra, y el coordinador asigna uno.
+#[derive(Debug, Clone)]
+pub struct HandoffRequest {
+    incident: Incident,
+    leaving_dron_id: u8,
+    replacement_id: Option<u8>,
+}
+
+impl HandoffRequest {
+    pub fn new(incident: Incident, leaving_dron_id: u8, replacement_id: Option<u8>) -> Self {
+        Self {
+            incident,
+            leaving_dron_id,
+            replacement_id,
+        }
+    }
+
+    pub fn get_incident(&self) -> &Incident {
+        &self.incident
+    }
+
+    /// Devuelve el dron que pide que lo releven.
+    pub fn get_leaving_dron_id(&self) -> u8 {
+        self.leaving_dron_id
+    }
+
+    /// Devuelve el dron elegido para reemplazarlo, si lo eligió quien pide el relevo.
+    pub fn get_replacement_id(&self) -> Option<u8> {
+        self.replacement_id
+    }
+
+    /// Convierte el pedido a bytes: el dron que se va, si nombra reemplazo, el reemplazo, y luego el incidente.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![
+            self.leaving_dron_id,
+            self.replacement_id.is_some() as u8,
+            self.replacement_id.unwrap_or(0),
+        ];
+        bytes.extend(self.incident.to_bytes());
+        bytes
+    }
+
+    /// Obtiene el pedido a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < 3 + INCIDENT_MIN_LEN {
+            return Err(invalid_request());
+        }
+        let replacement_id = match bytes[1] {
+            0 => None,
+            1 => Some(bytes[2]),
+            _ => return Err(invalid_request()),
+        };
+        let incident = Incident::from_bytes(bytes[3..].to_vec())?;
+        Ok(Self::new(incident, bytes[0], replacement_id))
+    }
+}
+
+fn invalid_request() -> Error {
+    Error::new(ErrorKind::InvalidData, "Pedido de relevo inválido.")
+}
+
+/// Relevo de un dron que atiende un incidente y debe ir a cargarse: pide que otro dron lo reemplace, y espera a
+/// que llegue antes de irse, para no dejar el incidente sin atender.
+/// Lleva una vista de la flota con las current_info recibidas, para elegir al reemplazo y ver cuándo llega; y los
+/// incidentes que se le pidió relevar a este dron, que atiende sin elegirse con los demás.
+#[derive(Debug)]
+pub struct Handoff {
+    fleet: Arc<Mutex<FleetState>>,
+    active_incs: Arc<Mutex<PendingIncidents>>,
+    handed_off: Arc<Mutex<HashSet<IncidentInfo>>>,
+    coordination_mode: CoordinationMode,
+    mqtt_client: Arc<Mutex<MQTTClient>>,
+    qos: u8,
+}
+
+impl Handoff {
+    /// Crea el relevo. `active_incs` son los incidentes pendientes del dron, que indican cuál atiende.
+    pub fn new(
+        fleet: FleetState,
+        active_incs: Arc<Mutex<PendingIncidents>>,
+        coordination_mode: CoordinationMode,
+        mqtt_client: Arc<Mutex<MQTTClient>>,
+        qos: u8,
+    ) -> Self {
+        Self {
+            fleet: Arc::new(Mutex::new(fleet)),
+            active_incs,
+            handed_off: Arc::new(Mutex::new(HashSet::new())),
+            coordination_mode,
+            mqtt_client,
+            qos,
+        }
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            fleet: self.fleet.clone(),
+            active_incs: self.active_incs.clone(),
+            handed_off: self.handed_off.clone(),
+            coordination_mode: self.coordination_mode,
+            mqtt_client: self.mqtt_client.clone(),
+            qos: self.qos,
+        }
+    }
+
+    /// Devuelve los incidentes pendientes del dron, compartidos con quien los procesa.
+    pub fn get_active_incs(&self) -> Arc<Mutex<PendingIncidents>> {
+        self.active_incs.clone()
+    }
+
+    /// Registra la current_info recibida de otro dron, en la vista de la flota.
+    pub fn update_dron(&self, ci: DronCurrentInfo) -> Result<(), Error> {
+        self.lock(&self.fleet)?.update_dron(ci);
+        Ok(())
+    }
+
+    /// Registra que se le pidió a este dron relevar a otro en el incidente.
+    pub fn add_handed_off(&self, inc_info: IncidentInfo) -> Result<(), Error> {
+        self.lock(&self.handed_off)?.insert(inc_info);
+        Ok(())
+    }
+
+    /// Devuelve si se le pidió a este dron relevar a otro en el incidente, y lo olvida.
+    pub fn take_handed_off(&self, inc_info: &IncidentInfo) -> Result<bool, Error> {
+        Ok(self.lock(&self.handed_off)?.remove(inc_info))
+    }
+
+    /// Pide que otro dron lo releve en el incidente que atiende, y espera a que llegue, hasta `HANDOFF_TIMEOUT`.
+    /// Devuelve si dejó de atender el incidente: porque llegó su reemplazo, o porque mientras tanto se resolvió.
+    /// Si no hay ningún dron disponible para reemplazarlo, o el reemplazo no llega a tiempo, sigue atendiéndolo.
+    pub fn request_and_wait(&self, data: &Data) -> Result<bool, Error> {
+        let Some(incident) = self.lock(&self.active_incs)?.get_attending() else {
+            return Ok(false);
+        };
+        let inc_info = incident.get_info();
+        let dron_id = data.get_id()?;
+        let (already_there, replacement_id) = {
+            let fleet = self.lock(&self.fleet)?;
+            let already_there = fleet.drones_at(&inc_info);
+            let replacement_id = match self.coordination_mode {
+                CoordinationMode::Distributed => {
+                    let excluded = HashSet::from([dron_id]);
+                    match fleet.nearest_available_to(incident.get_position(), &excluded) {
+                        Some(id) => Some(id),
+                        None => return Ok(false),
+                    }
+                }
+                // El reemplazo lo elige el coordinador de la flota.
+                CoordinationMode::Central => None,
+            };
+            (already_there, replacement_id)
+        };
+        self.publish(&HandoffRequest::new(incident, dron_id, replacement_id))?;
+
+        let deadline = data.now() + HANDOFF_TIMEOUT;
+        while data.now() < deadline {
+            data.check_not_shutting_down()?;
+            if data.get_inc_id_to_resolve()? != Some(inc_info) {
+                return Ok(true);
+            }
+            let arrived = self
+                .lock(&self.fleet)?
+                .drones_at(&inc_info)
+                .iter()
+                .any(|id| !already_there.contains(id));
+            if arrived {
+                return Ok(true);
+            }
+            data.sleep(HANDOFF_CHECK_INTERVAL);
+        }
+        Ok(false)
+    }
+
+    /// Deja de atender el incidente, porque lo relevó otro dron.
+    pub fn leave_incident(&self, data: &Data) -> Result<(), Error> {
+        self.lock(&self.active_incs)?.set_attending(None);
+        data.unset_inc_id_to_resolve()
+    }
+
+    fn publish(&self, request: &HandoffRequest) -> Result<(), Error> {
+        match self.mqtt_client.lock() {
+            Ok(mut mqtt_client) => {
+                let topic = AppsMqttTopics::DronHandoffTopic.to_str();
+                mqtt_client.mqtt_publish(topic, &request.to_bytes(), self.qos)?;
+                Ok(())
+            }
+            Err(_) => Err(MqttError::LockPoisoned("mqtt_client".to_string()).into()),
+        }
+    }
+
+    fn lock<'a, T>(&self, mutex: &'a Mutex<T>) -> Result<MutexGuard<'a, T>, Error> {
+        mutex
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::Other, "Error al tomar lock del relevo."))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HandoffRequest;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident::Incident, incident_source::IncidentSource},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_el_pedido_de_relevo_se_convierte_a_bytes_y_de_vuelta() {
+        let incident = Incident::new(6, position(-34.6, -58.4), IncidentSource::Automated);
+        let request = HandoffRequest::new(incident.clone(), 2, Some(5));
+
+        let received = HandoffRequest::from_bytes(request.to_bytes()).unwrap();
+        assert_eq!(received.get_leaving_dron_id(), 2);
+        assert_eq!(received.get_replacement_id(), Some(5));
+        assert_eq!(received.get_incident().get_info(), incident.get_info());
+
+        let to_coordinator = HandoffRequest::new(incident, 2, None);
+        let received = HandoffRequest::from_bytes(to_coordinator.to_bytes()).unwrap();
+        assert_eq!(received.get_replacement_id(), None);
+
+        assert!(HandoffRequest::from_bytes(vec![2, 1, 5]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use crate::apps::geo_position::GeoPosition;
+
+/// Cantidad de drones que atienden cada incidente.
+pub const DRONES_PER_INCIDENT: usize = 2;
+/// Tiempo durante el cual un dron, luego de postularse para un incidente, recibe las postulaciones de los demás
+/// antes de decidir si es uno de los que lo atienden.
+pub const CANDIDACY_TIMEOUT: Duration = Duration::from_millis(3500);
+/// Cada cuánto se vuelve a publicar la postulación mientras dura la elección, por si alguna se perdió.
+pub const CANDIDACY_REPUBLISH_INTERVAL: Duration = Duration::from_millis(1000);
+/// Tiempo que espera un dron que no fue elegido, a que los elegidos confirmen que van al incidente.
+/// Si alguno no lo confirma, los que no fueron elegidos vuelven a elegir entre los que quedan.
+pub const CONFIRMATION_TIMEOUT: Duration = Duration::from_millis(3000);
+
+/// Cómo se decide qué drones atienden cada incidente. Se configura con la propiedad `incident_coordination`,
+/// opcional, por defecto distributed.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CoordinationMode {
+    /// Los drones eligen entre ellos a los más cercanos, postulándose (ver `IncidentCandidates`).
+    #[default]
+    Distributed,
+    /// El coordinador de la flota asigna los incidentes, publicándolo por el topic `dron-assign`; cada dron
+    /// atiende los que se le asignan, o los declina si no puede, para que el coordinador asigne otro.
+    Central,
+}
+
+impl CoordinationMode {
+    pub fn from_property(mode: &str) -> Result<Self, Error> {
+        match mode {
+            "distributed" => Ok(CoordinationMode::Distributed),
+            "central" => Ok(CoordinationMode::Central),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "incident_coordination debe ser distributed o central.",
+            )),
+        }
+    }
+}
+
+/// Elección de los drones que atienden un incidente.
+/// Cada dron que puede atenderlo publica su postulación (su current_info en estado `RespondingToIncident`), y todos
+/// registran las postulaciones que reciben. Al terminar el `CANDIDACY_TIMEOUT`, cada uno elige de la misma forma
+/// determinística a los `DRONES_PER_INCIDENT` más cercanos, desempatando por id; los elegidos confirman publicando su
+/// current_info en estado `MustRespondToIncident`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncidentCandidates {
+    position: GeoPosition,
+    candidates: Vec<(u8, f64)>, // (dron_id, distancia al incidente)
+    confirmed: HashSet<u8>,     // drones que confirmaron que van al incidente.
+}
+
+impl IncidentCandidates {
+    pub fn new(position: GeoPosition) -> Self {
+        Self {
+            position,
+            candidates: vec![],
+            confirmed: HashSet::new(),
+        }
+    }
+
+    /// Devuelve la posición del incidente.
+    pub fn get_position(&self) -> GeoPosition {
+        self.position
+    }
+
+    /// Registra la postulación del dron `dron_id`. Si ya se había postulado, actualiza su distancia,
+    /// para que las postulaciones que se vuelven a publicar no se cuenten dos veces.
+    pub fn add_candidate(&mut self, dron_id: u8, distance: f64) {
+        match self.candidates.iter_mut().find(|(id, _)| *id == dron_id) {
+            Some(candidate) => candidate.1 = distance,
+            None => self.candidates.push((dron_id, distance)),
+        }
+    }
+
+    /// Quita la postulación del dron `dron_id`, por ejemplo porque declinó el incidente.
+    pub fn remove_candidate(&mut self, dron_id: u8) {
+        self.candidates.retain(|(id, _)| *id != dron_id);
+        self.confirmed.remove(&dron_id);
+    }
+
+    /// Registra que el dron `dron_id` confirmó que va al incidente.
+    pub fn confirm(&mut self, dron_id: u8) {
+        self.confirmed.insert(dron_id);
+    }
+
+    /// Devuelve si ya confirmaron todos los drones que atienden el incidente.
+    pub fn is_fully_confirmed(&self) -> bool {
+        self.confirmed.len() >= DRONES_PER_INCIDENT
+    }
+
+    /// Devuelve los ids de los drones elegidos: los `DRONES_PER_INCIDENT` más cercanos al incidente, y en caso de
+    /// igual distancia, los de menor id. Todos los drones que recibieron las mismas postulaciones eligen a los mismos.
+    pub fn select_winners(&self) -> Vec<u8> {
+        let mut candidates = self.candidates.clone();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        candidates
+            .iter()
+            .take(DRONES_PER_INCIDENT)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Vuelve a elegir descartando a los elegidos que no confirmaron, que se considera que no irán al incidente.
+    /// Los que sí confirmaron siguen elegidos, y los lugares libres se completan con los siguientes más cercanos.
+    pub fn reselect_without_unconfirmed(&mut self) -> Vec<u8> {
+        let unconfirmed: Vec<u8> = self
+            .select_winners()
+            .into_iter()
+            .filter(|id| !self.confirmed.contains(id))
+            .collect();
+        for id in unconfirmed {
+            self.candidates.retain(|(candidate, _)| *candidate != id);
+        }
+        self.select_winners()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentCandidates;
+    use crate::apps::geo_position::GeoPosition;
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_se_eligen_los_dos_mas_cercanos_desempatando_por_id() {
+        let mut candidates = IncidentCandidates::new(position(-34.6, -58.4));
+        candidates.add_candidate(5, 0.003);
+        candidates.add_candidate(2, 0.001);
+        candidates.add_candidate(4, 0.002);
+        candidates.add_candidate(3, 0.002);
+        // La postulación que se vuelve a recibir no se cuenta dos veces.
+        candidates.add_candidate(2, 0.001);
+
+        assert_eq!(candidates.select_winners(), vec![2, 3]);
+
+        // Sin importar el orden en que se recibieron, se elige a los mismos.
+        let mut other_order = IncidentCandidates::new(position(-34.6, -58.4));
+        for (id, distance) in [(3, 0.002), (4, 0.002), (5, 0.003), (2, 0.001)] {
+            other_order.add_candidate(id, distance);
+        }
+        assert_eq!(other_order.select_winners(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_2_si_un_elegido_no_confirma_lo_reemplaza_el_siguiente_mas_cercano() {
+        let mut candidates = IncidentCandidates::new(position(-34.6, -58.4));
+        candidates.add_candidate(1, 0.001);
+        candidates.add_candidate(2, 0.002);
+        candidates.add_candidate(3, 0.003);
+
+        candidates.confirm(1);
+        assert!(!candidates.is_fully_confirmed());
+
+        assert_eq!(candidates.reselect_without_unconfirmed(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_3_el_que_declina_deja_de_ser_candidato() {
+        let mut candidates = IncidentCandidates::new(position(-34.6, -58.4));
+        candidates.add_candidate(1, 0.001);
+        candidates.add_candidate(2, 0.002);
+        candidates.add_candidate(3, 0.003);
+
+        candidates.remove_candidate(1);
+
+        assert_eq!(candidates.select_winners(), vec![2, 3]);
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::{Error, ErrorKind};
+
+use crate::apps::incident_data::incident::Incident;
+
+/// Largo mínimo de un incidente en bytes: id, latitud, longitud, estado y origen.
+const INCIDENT_MIN_LEN: usize = 19;
+
+/// Asignación de un incidente a drones, que publica el coordinador de la flota por el topic `dron-assign`.
+/// Cada publicación indica todos los drones asignados al incidente hasta el momento: si alguno lo declina, el
+/// coordinador vuelve a publicar la asignación con su reemplazo.
+#[derive(Debug, Clone)]
+pub struct IncidentAssignment {
+    incident: Incident,
+    dron_ids: Vec<u8>,
+}
+
+impl IncidentAssignment {
+    pub fn new(incident: Incident, dron_ids: Vec<u8>) -> Self {
+        Self { incident, dron_ids }
+    }
+
+    pub fn get_incident(&self) -> &Incident {
+        &self.incident
+    }
+
+    pub fn get_dron_ids(&self) -> &[u8] {
+        &self.dron_ids
+    }
+
+    /// Devuelve si el incidente está asignado al dron `dron_id`.
+    pub fn includes(&self, dron_id: u8) -> bool {
+        self.dron_ids.contains(&dron_id)
+    }
+
+    /// Convierte la asignación a bytes: la cantidad de drones, sus ids, y luego el incidente.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.dron_ids.len() as u8];
+        bytes.extend_from_slice(&self.dron_ids);
+        bytes.extend(self.incident.to_bytes());
+        bytes
+    }
+
+    /// Obtiene la asignación a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        let Some((&count, rest)) = bytes.split_first() else {
+            return Err(invalid_assignment());
+        };
+        let count = count as usize;
+        if rest.len() < count + INCIDENT_MIN_LEN {
+            return Err(invalid_assignment());
+        }
+        let (dron_ids, incident_bytes) = rest.split_at(count);
+        let incident = Incident::from_bytes(incident_bytes.to_vec())?;
+        Ok(Self::new(incident, dron_ids.to_vec()))
+    }
+}
+
+fn invalid_assignment() -> Error {
+    Error::new(ErrorKind::InvalidData, "Asignación de incidente inválida.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentAssignment;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident::Incident, incident_source::IncidentSource},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_la_asignacion_se_convierte_a_bytes_y_de_vuelta() {
+        let incident = Incident::new(5, position(-34.6, -58.4), IncidentSource::Manual);
+        let assignment = IncidentAssignment::new(incident.clone(), vec![3, 7]);
+
+        let received = IncidentAssignment::from_bytes(assignment.to_bytes()).unwrap();
+        assert_eq!(received.get_dron_ids(), &[3, 7]);
+        assert_eq!(received.get_incident().get_info(), incident.get_info());
+        assert_eq!(received.get_incident().get_position(), position(-34.6, -58.4));
+        assert!(received.includes(7) && !received.includes(4));
+
+        assert!(IncidentAssignment::from_bytes(vec![]).is_err());
+        assert!(IncidentAssignment::from_bytes(vec![3, 1]).is_err());
+    }
+}
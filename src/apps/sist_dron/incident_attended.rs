@@ -0,0 +1,73 @@
+use std::io::{Error, ErrorKind};
+
+use crate::apps::incident_data::incident_info::IncidentInfo;
+
+/// Aviso de que un incidente fue atendido: los drones que lo atienden permanecieron en su posición el tiempo
+/// configurado. Lo publica uno de ellos por el topic `inc-attended`, para que monitoreo lo marque como resuelto.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentAttended {
+    inc_info: IncidentInfo,
+    dron_ids: Vec<u8>,
+}
+
+impl IncidentAttended {
+    pub fn new(inc_info: IncidentInfo, dron_ids: Vec<u8>) -> Self {
+        Self { inc_info, dron_ids }
+    }
+
+    pub fn get_inc_info(&self) -> IncidentInfo {
+        self.inc_info
+    }
+
+    /// Devuelve los drones que permanecieron en el incidente.
+    pub fn get_dron_ids(&self) -> &[u8] {
+        &self.dron_ids
+    }
+
+    /// Convierte el aviso a bytes: el incidente, la cantidad de drones, y sus ids.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.inc_info.to_bytes();
+        bytes.push(self.dron_ids.len() as u8);
+        bytes.extend_from_slice(&self.dron_ids);
+        bytes
+    }
+
+    /// Obtiene el aviso a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < 3 || bytes.len() != 3 + bytes[2] as usize {
+            return Err(invalid_attended());
+        }
+        let inc_info =
+            IncidentInfo::from_bytes(bytes[..2].to_vec())?.ok_or_else(invalid_attended)?;
+        Ok(Self::new(inc_info, bytes[3..].to_vec()))
+    }
+}
+
+fn invalid_attended() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Aviso de incidente atendido inválido.",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::IncidentAttended;
+    use crate::apps::incident_data::{
+        incident_info::IncidentInfo, incident_source::IncidentSource,
+    };
+
+    #[test]
+    fn test_1_el_aviso_se_convierte_a_bytes_y_de_vuelta() {
+        let inc_info = IncidentInfo::new(4, IncidentSource::Automated);
+        let attended = IncidentAttended::new(inc_info, vec![2, 5]);
+
+        assert_eq!(
+            IncidentAttended::from_bytes(attended.to_bytes()).unwrap(),
+            attended
+        );
+        assert!(IncidentAttended::from_bytes(vec![4, 1]).is_err());
+        assert!(IncidentAttended::from_bytes(vec![4, 1, 2, 5]).is_err());
+        assert!(IncidentAttended::from_bytes(vec![0, 1, 0]).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::apps::incident_data::incident_info::IncidentInfo;
+
+use super::{
+    dron_current_info::DronCurrentInfo, dron_state::DronState,
+    incident_arbitration::DRONES_PER_INCIDENT,
+};
+
+/// Registra desde cuándo se encuentra cada dron en la posición del incidente que atiende, según las current_info
+/// recibidas, incluidas las propias. Permite saber cuándo los drones que atienden un incidente permanecieron
+/// juntos en él el tiempo necesario para darlo por atendido.
+#[derive(Debug)]
+pub struct IncidentPresence {
+    arrivals: Arc<Mutex<ArrivalsType>>,
+}
+
+type ArrivalsType = HashMap<IncidentInfo, HashMap<u8, Instant>>; // (inc_info, (dron_id, llegada))
+
+impl IncidentPresence {
+    pub fn new() -> Self {
+        Self {
+            arrivals: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            arrivals: self.arrivals.clone(),
+        }
+    }
+
+    /// Registra la current_info de un dron, recibida en el instante `now`. Si está en la posición del incidente que
+    /// atiende, y no se lo tenía registrado allí, llegó en ese instante; y si no, ya no está en ningún incidente.
+    pub fn update(&self, ci: &DronCurrentInfo, now: Instant) -> Result<(), Error> {
+        let mut arrivals = self.lock_arrivals()?;
+        let dron_id = ci.get_id();
+        let current_inc = ci
+            .get_inc_id_to_resolve()
+            .filter(|_| ci.get_state() == DronState::ManagingIncident);
+
+        for (inc_info, drones) in arrivals.iter_mut() {
+            if Some(*inc_info) != current_inc {
+                drones.remove(&dron_id);
+            }
+        }
+        arrivals.retain(|_, drones| !drones.is_empty());
+        if let Some(inc_info) = current_inc {
+            arrivals
+                .entry(inc_info)
+                .or_default()
+                .entry(dron_id)
+                .or_insert(now);
+        }
+        Ok(())
+    }
+
+    /// Si los drones que atienden el incidente están todos en su posición desde hace al menos `stay`, devuelve sus
+    /// ids ordenados; y si no, None.
+    pub fn attended_by(
+        &self,
+        inc_info: &IncidentInfo,
+        stay: Duration,
+        now: Instant,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let arrivals = self.lock_arrivals()?;
+        let Some(drones) = arrivals.get(inc_info) else {
+            return Ok(None);
+        };
+        let stayed = drones
+            .values()
+            .all(|arrival| now.saturating_duration_since(*arrival) >= stay);
+        if drones.len() < DRONES_PER_INCIDENT || !stayed {
+            return Ok(None);
+        }
+        let mut dron_ids: Vec<u8> = drones.keys().copied().collect();
+        dron_ids.sort();
+        Ok(Some(dron_ids))
+    }
+
+    fn lock_arrivals(&self) -> Result<MutexGuard<'_, ArrivalsType>, Error> {
+        self.arrivals.lock().map_err(|_| {
+            Error::new(
+                ErrorKind::Other,
+                "Error al tomar lock de la presencia en incidentes.",
+            )
+        })
+    }
+}
+
+impl Default for IncidentPresence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::IncidentPresence;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident_info::IncidentInfo, incident_source::IncidentSource},
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn create_ci(id: u8, state: DronState, inc_info: IncidentInfo) -> DronCurrentInfo {
+        let mut ci = DronCurrentInfo::new(id, position(-34.6, -58.4), 100, state);
+        ci.set_inc_id_to_resolve(inc_info);
+        ci
+    }
+
+    #[test]
+    fn test_1_el_incidente_se_atiende_cuando_ambos_drones_permanecen_el_tiempo_indicado() {
+        let presence = IncidentPresence::new();
+        let inc_info = IncidentInfo::new(1, IncidentSource::Manual);
+        let stay = Duration::from_secs(10);
+        let start = Instant::now();
+
+        presence
+            .update(&create_ci(5, DronState::ManagingIncident, inc_info), start)
+            .unwrap();
+        let second_arrival = start + Duration::from_secs(4);
+        presence
+            .update(
+                &create_ci(2, DronState::ManagingIncident, inc_info),
+                second_arrival,
+            )
+            .unwrap();
+        // Volver a recibir la current_info de un dron que ya estaba no cambia su llegada.
+        presence
+            .update(
+                &create_ci(5, DronState::ManagingIncident, inc_info),
+                second_arrival,
+            )
+            .unwrap();
+
+        let attended = |elapsed| {
+            presence
+                .attended_by(&inc_info, stay, start + elapsed)
+                .unwrap()
+        };
+        assert_eq!(attended(Duration::from_secs(12)), None);
+        assert_eq!(attended(Duration::from_secs(14)), Some(vec![2, 5]));
+
+        // Si uno de ellos se va, deja de estar atendido.
+        presence
+            .update(&create_ci(2, DronState::Flying, inc_info), start)
+            .unwrap();
+        assert_eq!(attended(Duration::from_secs(14)), None);
+    }
+}
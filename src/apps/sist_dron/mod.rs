@@ -1,10 +1,33 @@
 pub mod battery_manager;
+pub mod battery_model;
 pub mod calculations;
+pub mod charging_stations;
 pub mod data;
+pub mod decline_diagnostics;
 pub mod dron;
+pub mod dron_admin_command;
+pub mod dron_command;
+pub mod dron_config;
 pub mod dron_current_info;
 pub mod dron_flying_info;
+pub mod dron_heartbeat;
 pub mod dron_logic;
 pub mod dron_state;
+pub mod dron_wear;
+pub mod fleet_coordinator;
+pub mod fleet_state;
+pub mod flight_settings;
+pub mod handoff;
+pub mod incident_arbitration;
+pub mod incident_assignment;
+pub mod incident_attended;
+pub mod incident_presence;
+pub mod patrol_manager;
+pub mod patrol_route;
+pub mod pending_incidents;
+pub mod sim_clock;
+pub mod simulation;
 pub mod sist_dron_properties;
+pub mod supervisor;
+pub mod telemetry_history;
 pub mod utils;
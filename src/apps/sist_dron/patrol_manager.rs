@@ -0,0 +1,116 @@
+use std::{io::Error, sync::mpsc::SyncSender};
+
+use crate::logging::string_logger::StringLogger;
+
+use super::{
+    data::Data, dron_current_info::DronCurrentInfo, patrol_route::PatrolRoute,
+    sist_dron_properties::SistDronProperties,
+};
+
+/// Componente encargado del patrullaje del dron: mientras espera incidentes, recorre en ciclo los puntos de su
+/// recorrido, publicando su posición. Al pasar a atender un incidente, o a mantenimiento, deja de desplazarlo; y al
+/// volver a esperar incidentes, retoma el recorrido desde el punto más cercano a donde se encuentre.
+/// Mientras patrulla, consume batería por la distancia que vuela y por el tiempo que está en el aire.
+#[derive(Debug)]
+pub struct PatrolManager {
+    current_data: Data,
+    dron_properties: SistDronProperties,
+    route: PatrolRoute,
+    logger: StringLogger,
+    ci_tx: SyncSender<DronCurrentInfo>,
+}
+
+impl PatrolManager {
+    pub fn new(
+        current_data: Data,
+        dron_properties: SistDronProperties,
+        route: PatrolRoute,
+        logger: StringLogger,
+        ci_tx: SyncSender<DronCurrentInfo>,
+    ) -> Self {
+        Self {
+            current_data,
+            dron_properties,
+            route,
+            logger,
+            ci_tx,
+        }
+    }
+
+    /// Patrulla hasta que el dron se apague.
+    pub fn run(&mut self) {
+        let tick = self.dron_properties.get_flight_tick();
+        let mut next_waypoint: Option<usize> = None;
+        let mut last_tick = self.current_data.now();
+        while !self.current_data.is_shutting_down() {
+            self.current_data.sleep(tick);
+            let now = self.current_data.now();
+            let elapsed = now.duration_since(last_tick);
+            last_tick = now;
+            // Si lo detuvieron o lo llamaron a la base, no patrulla; al volver a funcionar, retoma desde el punto
+            // más cercano.
+            if self.current_data.is_on_standby() {
+                next_waypoint = None;
+                continue;
+            }
+
+            let speed = match self.current_data.get_cruise_speed() {
+                Ok(speed) => speed,
+                Err(e) => {
+                    self.logger.log(format!("Error en PatrolManager: {:?}.", e));
+                    continue;
+                }
+            };
+            let displacement = self.current_data.get_distance_model().flight_displacement(speed, elapsed);
+            match self.patrol_step(next_waypoint, displacement) {
+                Ok(next) => next_waypoint = next,
+                Err(e) => self.logger.log(format!("Error en PatrolManager: {:?}.", e)),
+            }
+        }
+    }
+
+    /// Desplaza al dron `displacement` hacia el punto `next_waypoint` del recorrido, o hacia el más cercano si no está
+    /// patrullando. Devuelve el punto hacia el que debe seguir, o None si dejó de patrullar por no estar esperando incidentes.
+    fn patrol_step(
+        &self,
+        next_waypoint: Option<usize>,
+        displacement: f64,
+    ) -> Result<Option<usize>, Error> {
+        let previous_position = self.current_data.get_current_position()?;
+        let index = match next_waypoint {
+            Some(index) => index,
+            None => self.route.nearest_index(previous_position),
+        };
+        let waypoint = self.route.get_waypoint(index);
+        let Some(position) = self.current_data.patrol_towards(waypoint, displacement)? else {
+            return Ok(None);
+        };
+        if next_waypoint.is_none() {
+            self.logger
+                .log(format!("Patrullando, voy hacia el punto {:?}.", waypoint));
+        }
+        // Si el recorrido tiene un único punto, una vez allí queda detenido y no hace falta volver a publicar.
+        if position != previous_position {
+            self.publish_current_info()?;
+        }
+
+        if position == waypoint {
+            Ok(Some(self.route.next_index(index)))
+        } else {
+            Ok(Some(index))
+        }
+    }
+
+    /// Envía la current_info por un channel para que la parte receptora le haga publish.
+    fn publish_current_info(&self) -> Result<(), Error> {
+        let ci = self.current_data.get_current_info()?;
+        if let Err(e) = self.ci_tx.send(ci) {
+            println!("Error al enviar current_info para ser publicada: {:?}", e);
+            self.logger.log(format!(
+                "Error al enviar current_info para ser publicada: {:?}.",
+                e
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::Arc,
+};
+
+use crate::apps::{geo_position::GeoPosition, sist_dron::calculations::DistanceModel};
+
+use super::dron_config::DronProperties;
+
+/// Recorrido de patrullaje de un dron: los puntos que recorre, en ciclo, mientras espera incidentes.
+/// Se configura con la propiedad `patrol_route`, de la forma `lat,lon;lat,lon`, donde cada punto se expresa como
+/// desplazamiento respecto del centro del rango del dron, y debe quedar dentro del rango.
+#[derive(Debug)]
+pub struct PatrolRoute {
+    waypoints: Arc<Vec<GeoPosition>>,
+    distance_model: DistanceModel, // cómo mide la distancia hasta cada punto.
+}
+
+impl PatrolRoute {
+    pub fn new(waypoints: Vec<GeoPosition>, distance_model: DistanceModel) -> Self {
+        Self {
+            waypoints: Arc::new(waypoints),
+            distance_model,
+        }
+    }
+
+    /// Carga el recorrido del dron, cuyo rango tiene centro `range_center` y radio `range` (en las mismas
+    /// unidades que la propiedad `range`, medido según `distance_model`). Si no está definido, el dron no patrulla
+    /// y devuelve None.
+    pub fn from_properties(
+        properties: &DronProperties,
+        range_center: GeoPosition,
+        range: f64,
+        distance_model: DistanceModel,
+    ) -> Result<Option<Self>, Error> {
+        let Some((key, prop)) = properties.get("patrol_route") else {
+            return Ok(None);
+        };
+        Self::parse(prop, range_center, range, distance_model)
+            .map(Some)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", key, e)))
+    }
+
+    /// Parsea un recorrido de la forma `lat,lon;lat,lon`, relativo a `range_center`.
+    fn parse(
+        prop: &str,
+        range_center: GeoPosition,
+        range: f64,
+        distance_model: DistanceModel,
+    ) -> Result<Self, Error> {
+        let waypoints = prop
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| Self::parse_waypoint(entry, range_center, range, distance_model))
+            .collect::<Result<Vec<GeoPosition>, Error>>()?;
+        if waypoints.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "patrol_route no tiene ningún punto.",
+            ));
+        }
+        Ok(Self::new(waypoints, distance_model))
+    }
+
+    fn parse_waypoint(
+        entry: &str,
+        range_center: GeoPosition,
+        range: f64,
+        distance_model: DistanceModel,
+    ) -> Result<GeoPosition, Error> {
+        let invalid = |reason: &str| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Punto de patrullaje {}: {}.", entry.trim(), reason),
+            )
+        };
+        let (lat, lon) = entry
+            .split_once(',')
+            .ok_or_else(|| invalid("debe ser de la forma lat,lon"))?;
+        let lat: f64 = lat
+            .trim()
+            .parse()
+            .map_err(|_| invalid("latitud inválida"))?;
+        let lon: f64 = lon
+            .trim()
+            .parse()
+            .map_err(|_| invalid("longitud inválida"))?;
+        let waypoint = range_center.offset(lat, lon);
+        // El range se expresa en milésimas de latitud y longitud.
+        if distance_model.distance(range_center, waypoint) > range / 1000.0 {
+            return Err(invalid("queda fuera del rango del dron"));
+        }
+        Ok(waypoint)
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            waypoints: self.waypoints.clone(),
+            distance_model: self.distance_model,
+        }
+    }
+
+    /// Devuelve la posición del punto `index` del recorrido.
+    pub fn get_waypoint(&self, index: usize) -> GeoPosition {
+        self.waypoints[index % self.waypoints.len()]
+    }
+
+    /// Devuelve el índice del punto al que se va luego del punto `index`, volviendo al primero al terminar.
+    pub fn next_index(&self, index: usize) -> usize {
+        (index + 1) % self.waypoints.len()
+    }
+
+    /// Devuelve el índice del punto más cercano a `position`, desde el cual retomar el recorrido.
+    pub fn nearest_index(&self, position: GeoPosition) -> usize {
+        self.waypoints
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let distance_to = |waypoint: &GeoPosition| self.distance_model.distance(position, *waypoint);
+                distance_to(a).total_cmp(&distance_to(b))
+            })
+            .map_or(0, |(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PatrolRoute;
+    use crate::apps::{geo_position::GeoPosition, sist_dron::calculations::DistanceModel};
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_se_parsea_el_recorrido_relativo_al_centro_del_rango() {
+        let flat = DistanceModel::Flat;
+        let route = PatrolRoute::parse("0.01,0.0; 0.0,0.01;-0.01,0.0", position(-34.6, -58.4), 60.0, flat).unwrap();
+
+        assert_eq!(route.get_waypoint(1), position(-34.6, -58.4).offset(0.0, 0.01));
+        assert_eq!(route.get_waypoint(0), position(-34.6, -58.4).offset(0.01, 0.0));
+
+        assert!(PatrolRoute::parse("0.01", position(-34.6, -58.4), 60.0, flat).is_err());
+        assert!(PatrolRoute::parse("0.01,a", position(-34.6, -58.4), 60.0, flat).is_err());
+        assert!(PatrolRoute::parse("", position(-34.6, -58.4), 60.0, flat).is_err());
+    }
+
+    #[test]
+    fn test_2_se_rechazan_los_puntos_fuera_del_rango() {
+        let err =
+            PatrolRoute::parse("0.01,0.0;0.05,0.05", position(-34.6, -58.4), 60.0, DistanceModel::Flat).unwrap_err();
+        assert!(err.to_string().contains("0.05,0.05"));
+    }
+
+    #[test]
+    fn test_3_el_recorrido_se_retoma_desde_el_punto_mas_cercano_y_es_ciclico() {
+        let route =
+            PatrolRoute::parse("0.01,0.0;0.0,0.01;-0.01,0.0", position(0.0, 0.0), 60.0, DistanceModel::Flat).unwrap();
+
+        assert_eq!(route.nearest_index(position(0.0, 0.009)), 1);
+        assert_eq!(route.next_index(1), 2);
+        assert_eq!(route.next_index(2), 0);
+    }
+}
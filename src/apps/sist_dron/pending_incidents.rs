@@ -0,0 +1,337 @@
+use std::io::{Error, ErrorKind};
+
+use crate::apps::{
+    geo_position::GeoPosition,
+    incident_data::{incident::Incident, incident_info::IncidentInfo},
+    sist_dron::calculations::DistanceModel,
+};
+
+/// Política con la que un dron decide qué incidente pendiente atender, y si deja el que está atendiendo
+/// cuando aparece otro en su rango.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PreemptionPolicy {
+    /// Nunca deja el incidente que atiende. Los pendientes se atienden por prioridad, y luego por orden de llegada.
+    #[default]
+    Never,
+    /// Deja el incidente que atiende si aparece uno de mayor prioridad. Los pendientes se atienden como en `Never`.
+    HigherPriority,
+    /// Mientras vuela hacia un incidente, lo deja si aparece uno de al menos la misma prioridad que le queda más
+    /// cerca. Los pendientes se atienden del más cercano al más lejano.
+    NearestFirst,
+}
+
+impl PreemptionPolicy {
+    pub fn from_property(policy: &str) -> Result<Self, Error> {
+        match policy {
+            "never" => Ok(PreemptionPolicy::Never),
+            "higher_priority" => Ok(PreemptionPolicy::HigherPriority),
+            "nearest_first" => Ok(PreemptionPolicy::NearestFirst),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "incident_preemption debe ser never, higher_priority o nearest_first.",
+            )),
+        }
+    }
+}
+
+/// Incidentes activos que un dron todavía no procesó, y el incidente que está atendiendo.
+#[derive(Debug, Default)]
+pub struct PendingIncidents {
+    queue: Vec<(Incident, u8)>, // en orden de llegada; el u8 cuenta cuántos drones ya vuelan hacia el incidente.
+    attending: Option<Incident>,
+}
+
+impl PendingIncidents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encola el incidente, si no estaba encolado.
+    pub fn push(&mut self, inc: Incident) {
+        if self.position_of(&inc.get_info()).is_none() {
+            self.queue.push((inc, 0));
+        }
+    }
+
+    /// Vuelve a encolar un incidente que se dejó de atender, adelante de los que llegaron después que él.
+    pub fn push_front(&mut self, inc: Incident) {
+        if self.position_of(&inc.get_info()).is_none() {
+            self.queue.insert(0, (inc, 0));
+        }
+    }
+
+    /// Desencola el incidente, por ejemplo porque ya se resolvió.
+    pub fn remove(&mut self, inc_info: &IncidentInfo) {
+        if let Some(pos) = self.position_of(inc_info) {
+            self.queue.remove(pos);
+        }
+    }
+
+    /// Suma uno a los drones que ya vuelan hacia el incidente, y si ya van todos los que lo atienden
+    /// (`drones_per_incident`), lo desencola para no procesarlo.
+    pub fn count_flying_drone(&mut self, inc_info: &IncidentInfo, drones_per_incident: usize) {
+        if let Some(pos) = self.position_of(inc_info) {
+            let flying_drones = &mut self.queue[pos].1;
+            *flying_drones += 1;
+            if *flying_drones as usize >= drones_per_incident {
+                self.queue.remove(pos);
+            }
+        }
+    }
+
+    /// Desencola el siguiente incidente a procesar según la política, estando el dron en `position`, y midiendo las
+    /// distancias según `distance_model`.
+    pub fn pop_next(
+        &mut self,
+        policy: PreemptionPolicy,
+        position: GeoPosition,
+        distance_model: DistanceModel,
+    ) -> Option<Incident> {
+        let next = match policy {
+            PreemptionPolicy::Never | PreemptionPolicy::HigherPriority => self
+                .queue
+                .iter()
+                .enumerate()
+                // El de mayor prioridad (y a igual prioridad, el más grave); entre los iguales, el primero que llegó.
+                .max_by(|(i, (a, _)), (j, (b, _))| {
+                    a.get_urgency().cmp(&b.get_urgency()).then(j.cmp(i))
+                })
+                .map(|(pos, _)| pos),
+            PreemptionPolicy::NearestFirst => self
+                .queue
+                .iter()
+                .enumerate()
+                .min_by(|(_, (a, _)), (_, (b, _))| {
+                    distance_model
+                        .distance(position, a.get_position())
+                        .total_cmp(&distance_model.distance(position, b.get_position()))
+                })
+                .map(|(pos, _)| pos),
+        }?;
+        Some(self.queue.remove(next).0)
+    }
+
+    /// Devuelve los incidentes encolados, en orden de llegada.
+    pub fn infos(&self) -> Vec<IncidentInfo> {
+        self.queue.iter().map(|(inc, _)| inc.get_info()).collect()
+    }
+
+    /// Establece el incidente que el dron está atendiendo.
+    pub fn set_attending(&mut self, inc: Option<Incident>) {
+        self.attending = inc;
+    }
+
+    /// Devuelve el incidente que el dron está atendiendo.
+    pub fn get_attending(&self) -> Option<Incident> {
+        self.attending.clone()
+    }
+
+    /// Devuelve el incidente que el dron estaba atendiendo, dejando de atenderlo.
+    pub fn take_attending(&mut self) -> Option<Incident> {
+        self.attending.take()
+    }
+
+    /// Devuelve si, según la política, el dron en `position` debe dejar el incidente que atiende para atender `inc`,
+    /// midiendo las distancias según `distance_model`. `arrived` indica si ya llegó al incidente que atiende.
+    pub fn should_preempt(
+        &self,
+        policy: PreemptionPolicy,
+        inc: &Incident,
+        position: GeoPosition,
+        distance_model: DistanceModel,
+        arrived: bool,
+    ) -> bool {
+        let Some(attending) = &self.attending else {
+            return false;
+        };
+        if attending.get_info() == inc.get_info() {
+            return false;
+        }
+        match policy {
+            PreemptionPolicy::Never => false,
+            PreemptionPolicy::HigherPriority => inc.get_priority() > attending.get_priority(),
+            PreemptionPolicy::NearestFirst => {
+                !arrived
+                    && inc.get_priority() >= attending.get_priority()
+                    && distance_model.distance(position, inc.get_position())
+                        < distance_model.distance(position, attending.get_position())
+            }
+        }
+    }
+
+    fn position_of(&self, inc_info: &IncidentInfo) -> Option<usize> {
+        self.queue
+            .iter()
+            .position(|(inc, _)| inc.get_info() == *inc_info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PendingIncidents, PreemptionPolicy};
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{
+            incident::Incident, incident_priority::IncidentPriority, incident_severity::IncidentSeverity,
+            incident_source::IncidentSource,
+        },
+        sist_dron::calculations::DistanceModel,
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn incident(id: u8, position: GeoPosition, priority: IncidentPriority) -> Incident {
+        Incident::new(id, position, IncidentSource::Manual).with_priority(priority)
+    }
+
+    #[test]
+    fn test_1_los_pendientes_se_atienden_por_prioridad_o_por_cercania() {
+        let origin = position(0.0, 0.0);
+        let mut pending = PendingIncidents::new();
+        pending.push(incident(1, position(0.03, 0.0), IncidentPriority::Medium));
+        pending.push(incident(2, position(0.02, 0.0), IncidentPriority::High));
+        pending.push(incident(3, position(0.01, 0.0), IncidentPriority::Medium));
+        pending.push(incident(3, position(0.01, 0.0), IncidentPriority::Medium));
+
+        let next = pending.pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic).unwrap();
+        assert_eq!(next.get_id(), 2);
+        let next = pending
+            .pop_next(PreemptionPolicy::HigherPriority, origin, DistanceModel::Geodesic)
+            .unwrap();
+        assert_eq!(next.get_id(), 1);
+        assert_eq!(
+            pending
+                .pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic)
+                .unwrap()
+                .get_id(),
+            3
+        );
+        assert!(pending
+            .pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic)
+            .is_none());
+
+        pending.push(incident(1, position(0.03, 0.0), IncidentPriority::Medium));
+        pending.push(incident(2, position(0.02, 0.0), IncidentPriority::High));
+        pending.push(incident(3, position(0.01, 0.0), IncidentPriority::Low));
+        let next = pending
+            .pop_next(PreemptionPolicy::NearestFirst, origin, DistanceModel::Geodesic)
+            .unwrap();
+        assert_eq!(next.get_id(), 3);
+    }
+
+    #[test]
+    fn test_2_se_deja_el_incidente_atendido_segun_la_politica() {
+        let origin = position(0.0, 0.0);
+        let mut pending = PendingIncidents::new();
+        let far_high = incident(2, position(0.05, 0.0), IncidentPriority::High);
+        let near_medium = incident(3, position(0.01, 0.0), IncidentPriority::Medium);
+
+        // Sin incidente en atención, no hay nada que dejar.
+        assert!(!pending.should_preempt(
+            PreemptionPolicy::HigherPriority,
+            &far_high,
+            origin,
+            DistanceModel::Geodesic,
+            false
+        ));
+
+        pending.set_attending(Some(incident(1, position(0.02, 0.0), IncidentPriority::Medium)));
+
+        assert!(!pending.should_preempt(PreemptionPolicy::Never, &far_high, origin, DistanceModel::Geodesic, false));
+        assert!(pending.should_preempt(
+            PreemptionPolicy::HigherPriority,
+            &far_high,
+            origin,
+            DistanceModel::Geodesic,
+            true
+        ));
+        assert!(!pending.should_preempt(
+            PreemptionPolicy::HigherPriority,
+            &near_medium,
+            origin,
+            DistanceModel::Geodesic,
+            false
+        ));
+        assert!(pending.should_preempt(
+            PreemptionPolicy::NearestFirst,
+            &near_medium,
+            origin,
+            DistanceModel::Geodesic,
+            false
+        ));
+        // Una vez que llegó, el más cercano es el que atiende.
+        assert!(!pending.should_preempt(
+            PreemptionPolicy::NearestFirst,
+            &near_medium,
+            origin,
+            DistanceModel::Geodesic,
+            true
+        ));
+
+        // El incidente que se deja vuelve a encolarse, primero.
+        let left = pending.take_attending().unwrap();
+        pending.push(near_medium);
+        pending.push_front(left);
+        assert_eq!(
+            pending
+                .pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic)
+                .unwrap()
+                .get_id(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_3_un_incidente_se_desencola_cuando_ya_vuelan_todos_los_drones_que_lo_atienden() {
+        let origin = position(0.0, 0.0);
+        let mut pending = PendingIncidents::new();
+        let inc = incident(1, position(0.01, 0.0), IncidentPriority::Medium);
+        pending.push(inc.clone());
+
+        pending.count_flying_drone(&inc.get_info(), 2);
+        pending.push(inc.clone());
+        pending.count_flying_drone(&inc.get_info(), 2);
+
+        assert!(pending
+            .pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic)
+            .is_none());
+    }
+    #[test]
+    fn test_4_a_igual_prioridad_se_atiende_primero_el_mas_grave() {
+        let origin = position(0.0, 0.0);
+        let mut pending = PendingIncidents::new();
+        pending.push(incident(1, position(0.01, 0.0), IncidentPriority::High));
+        pending.push(
+            incident(2, position(0.02, 0.0), IncidentPriority::High).with_severity(IncidentSeverity::Critical),
+        );
+        pending.push(
+            incident(3, position(0.03, 0.0), IncidentPriority::Medium).with_severity(IncidentSeverity::Critical),
+        );
+
+        let next = pending.pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic).unwrap();
+        assert_eq!(next.get_id(), 2);
+        let next = pending.pop_next(PreemptionPolicy::Never, origin, DistanceModel::Geodesic).unwrap();
+        assert_eq!(next.get_id(), 1);
+    }
+
+    #[test]
+    fn test_5_el_mas_cercano_depende_del_modelo_de_distancia() {
+        let origin = position(-34.6037, -58.3816);
+        let mut pending = PendingIncidents::new();
+        for model in [DistanceModel::Flat, DistanceModel::Geodesic] {
+            pending.push(incident(1, origin.offset(0.0085, 0.0), IncidentPriority::Medium));
+            pending.push(incident(2, origin.offset(0.0, 0.0095), IncidentPriority::Medium));
+            pending.set_attending(Some(incident(3, origin.offset(0.009, 0.0), IncidentPriority::Medium)));
+            let east = incident(4, origin.offset(0.0, 0.01), IncidentPriority::Medium);
+
+            // En el plano el incidente al norte queda más cerca; sobre la Tierra, el que está al este.
+            let flat = model == DistanceModel::Flat;
+            let next = pending.pop_next(PreemptionPolicy::NearestFirst, origin, model).unwrap();
+            assert_eq!(next.get_id(), if flat { 1 } else { 2 });
+            assert_eq!(pending.should_preempt(PreemptionPolicy::NearestFirst, &east, origin, model, false), !flat);
+            pending.pop_next(PreemptionPolicy::Never, origin, model);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Reloj con el que el dron mide el tiempo y espera. Puede ser el reloj real; uno acelerado, en el que el tiempo
+/// transcurre `factor` veces más rápido (por ejemplo, 60 para mostrar una hora en un minuto); o uno manual, que
+/// solamente avanza al llamar a `advance`, para simular el dron de forma determinística en los tests.
+#[derive(Debug)]
+pub struct SimClock {
+    start: Instant,
+    mode: ClockMode,
+    virtual_elapsed: Arc<(Mutex<Duration>, Condvar)>, // tiempo transcurrido en el reloj manual.
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ClockMode {
+    Real,
+    Scaled(f64),
+    Manual,
+}
+
+impl SimClock {
+    pub fn real() -> Self {
+        Self::with_mode(ClockMode::Real)
+    }
+
+    /// Crea un reloj en el que el tiempo transcurre `factor` veces más rápido que en el real.
+    pub fn scaled(factor: f64) -> Self {
+        if factor == 1.0 {
+            return Self::real();
+        }
+        Self::with_mode(ClockMode::Scaled(factor))
+    }
+
+    /// Crea un reloj que solamente avanza al llamar a `advance`.
+    pub fn manual() -> Self {
+        Self::with_mode(ClockMode::Manual)
+    }
+
+    fn with_mode(mode: ClockMode) -> Self {
+        Self {
+            start: Instant::now(),
+            mode,
+            virtual_elapsed: Arc::new((Mutex::new(Duration::ZERO), Condvar::new())),
+        }
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            start: self.start,
+            mode: self.mode,
+            virtual_elapsed: self.virtual_elapsed.clone(),
+        }
+    }
+
+    /// Devuelve el instante actual según este reloj.
+    pub fn now(&self) -> Instant {
+        match self.mode {
+            ClockMode::Real => Instant::now(),
+            ClockMode::Scaled(factor) => self.start + self.start.elapsed().mul_f64(factor),
+            ClockMode::Manual => self.start + self.get_virtual_elapsed(),
+        }
+    }
+
+    /// Espera a que transcurra `duration` según este reloj. Con el reloj manual, espera a que se lo haga avanzar.
+    pub fn sleep(&self, duration: Duration) {
+        match self.mode {
+            ClockMode::Real => thread::sleep(duration),
+            ClockMode::Scaled(factor) => thread::sleep(duration.div_f64(factor)),
+            ClockMode::Manual => {
+                let (elapsed, advanced) = &*self.virtual_elapsed;
+                let Ok(mut elapsed) = elapsed.lock() else {
+                    return;
+                };
+                let wake_up = *elapsed + duration;
+                while *elapsed < wake_up {
+                    elapsed = match advanced.wait(elapsed) {
+                        Ok(elapsed) => elapsed,
+                        Err(_) => return,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Hace avanzar `duration` al reloj manual, despertando a quienes esperaban hasta entonces. Los demás relojes
+    /// no se pueden avanzar.
+    pub fn advance(&self, duration: Duration) {
+        if self.mode != ClockMode::Manual {
+            return;
+        }
+        let (elapsed, advanced) = &*self.virtual_elapsed;
+        if let Ok(mut elapsed) = elapsed.lock() {
+            *elapsed += duration;
+            advanced.notify_all();
+        }
+    }
+
+    fn get_virtual_elapsed(&self) -> Duration {
+        let (elapsed, _) = &*self.virtual_elapsed;
+        elapsed.lock().map_or(Duration::ZERO, |elapsed| *elapsed)
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::real()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::SimClock;
+
+    #[test]
+    fn test_1_el_reloj_manual_solamente_avanza_al_pedirlo() {
+        let clock = SimClock::manual();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        let sleeping_clock = clock.clone_ref();
+        let sleeper = thread::spawn(move || {
+            let before = sleeping_clock.now();
+            sleeping_clock.sleep(Duration::from_secs(60));
+            sleeping_clock.now() - before
+        });
+        // Avanza de a un segundo hasta que quien espera se despierta.
+        let mut advanced = Duration::ZERO;
+        while !sleeper.is_finished() {
+            clock.advance(Duration::from_secs(1));
+            advanced += Duration::from_secs(1);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(sleeper.join().unwrap() >= Duration::from_secs(60));
+        assert_eq!(clock.now(), start + advanced);
+    }
+}
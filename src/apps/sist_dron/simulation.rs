@@ -0,0 +1,85 @@
+use std::{f64::consts::TAU, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::apps::incident_data::{incident::Incident, incident_source::IncidentSource};
+
+use super::{
+    data::Data, dron_current_info::DronCurrentInfo, flight_settings::FlightSettings,
+    sim_clock::SimClock, sist_dron_properties::SistDronProperties,
+};
+
+/// Entorno para simular drones de forma determinística: el tiempo de sus componentes avanza solamente al pedirlo,
+/// con un reloj manual, y los incidentes se generan al azar a partir de una semilla. Con la misma semilla y los mismos
+/// avances del reloj, se obtiene siempre la misma simulación.
+#[derive(Debug)]
+pub struct Simulation {
+    clock: SimClock,
+    rng: StdRng,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            clock: SimClock::manual(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Devuelve el reloj de la simulación, compartido con quien lo reciba.
+    pub fn get_clock(&self) -> SimClock {
+        self.clock.clone_ref()
+    }
+
+    /// Crea los datos de un dron con la current_info `ci`, cuyo tiempo avanza con el de la simulación.
+    pub fn create_data(&self, ci: DronCurrentInfo, properties: &SistDronProperties) -> Data {
+        Data::new(ci, FlightSettings::new(properties))
+            .with_clock(self.get_clock())
+            .with_distance_model(properties.get_distance_model())
+    }
+
+    /// Hace avanzar el tiempo de la simulación.
+    pub fn advance(&self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+
+    /// Genera un incidente en una posición al azar dentro del rango de un dron con las `properties`.
+    pub fn random_incident(&mut self, id: u8, properties: &SistDronProperties) -> Incident {
+        let center = properties.get_range_center_position();
+        // El rango está en milésimas de grado, medidas según el modelo de distancia, como lo evalúa el dron.
+        let radius = properties.get_range() / 1000.0 * self.rng.gen_range(0.0..1.0f64).sqrt();
+        let angle = self.rng.gen_range(0.0..TAU);
+        let position = properties.get_distance_model().step(center, (angle.sin(), angle.cos()), radius);
+        Incident::new(id, position, IncidentSource::Automated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Simulation;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        sist_dron::dron_config::{DronConfig, DEFAULT_CONFIG_FILE},
+    };
+
+    #[test]
+    fn test_1_con_la_misma_semilla_se_generan_los_mismos_incidentes_dentro_del_rango() {
+        let initial_position = GeoPosition::new(-34.6, -58.4).unwrap();
+        let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, 1, initial_position).unwrap();
+        let properties = config.get_properties();
+        let generate = |seed| {
+            let mut simulation = Simulation::new(seed);
+            (1..=20)
+                .map(|id| simulation.random_incident(id, &properties).get_position())
+                .collect::<Vec<GeoPosition>>()
+        };
+
+        let positions = generate(7);
+        assert_eq!(positions, generate(7));
+        assert_ne!(positions, generate(8));
+        for position in positions {
+            let distance = properties.get_distance_model().distance(properties.get_range_center_position(), position);
+            assert!(distance <= properties.get_range() / 1000.0 + 1e-9);
+        }
+    }
+}
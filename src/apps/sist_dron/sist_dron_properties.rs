@@ -1,6 +1,17 @@
-use std::io::{Error, ErrorKind};
+use std::{io::Error, time::Duration};
 
-use super::super::properties::Properties;
+use crate::apps::geo_position::GeoPosition;
+
+use super::battery_model::BatteryModel;
+use super::calculations::DistanceModel;
+use super::{data::DEFAULT_HISTORY_SIZE, telemetry_history::MAX_HISTORY_SIZE};
+use super::dron_config::{invalid_value, DronProperties};
+use super::dron_wear::MaintenanceSchedule;
+use super::incident_arbitration::CoordinationMode;
+use super::pending_incidents::PreemptionPolicy;
+
+/// Cada cuánto publica el dron su latido, si el archivo no define `heartbeat_interval_secs`.
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 5;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct SistDronProperties {
@@ -9,114 +20,91 @@ pub struct SistDronProperties {
     range: u8,
     stay_at_inc_time: u8, // Tiempo a permanencer en la ubicación del incidente, desde la llegada, en segundos.
     // Range center, porque un dron se mueve, al terminar de atender incidente vuelve a este range center
-    range_center_lat: f64, // Aux: #ToDo: Capaz es mejor tener una Posicion, para no tener mil f64s sueltos []
-    range_center_lon: f64,
+    range_center: GeoPosition,
     // Posicion de la central, para volver a cargarse la batería cuando se alcanza el min_operational_battery_lvl
-    mantainance_lat: f64,
-    mantainance_lon: f64,
-    // Velocidad de vuelo, en km/h
-    speed: f64,
+    mantainance: GeoPosition,
+    // Velocidad de vuelo crucero, en m/s
+    cruise_speed: f64,
+    // Cada cuánto se actualiza la posición del dron mientras vuela, en milisegundos
+    flight_tick_ms: u64,
+    // Coeficientes de consumo de batería del dron
+    battery_model: BatteryModel,
+    // Política para elegir el próximo incidente pendiente, y para dejar el que atiende por otro. Opcional, por defecto never.
+    preemption_policy: PreemptionPolicy,
+    // Si los drones eligen entre ellos quién atiende cada incidente, o se los asigna el coordinador. Opcional, por defecto distributed.
+    coordination_mode: CoordinationMode,
+    // Cada cuánto publica su latido por el topic dron-health, en segundos. Opcional.
+    heartbeat_interval_secs: u64,
+    // Si avisa por el topic inc-attended cuando él y el otro dron permanecieron stay_at_inc_time en el incidente. Opcional.
+    report_attended: bool,
+    // Cuántas veces más rápido que el real transcurre el tiempo del dron, para simulaciones. Opcional, por defecto 1.
+    time_scale: f64,
+    // Cuántas de sus últimas current_info conserva, para reenviar las no publicadas al reconectarse. Opcional.
+    telemetry_history_size: usize,
+    // Si las distancias se calculan sobre la superficie de la Tierra o en el plano lat/lon. Opcional, por defecto geodesic.
+    distance_model: DistanceModel,
+    // Desgaste a partir del cual va a mantenimiento programado, y cuánto dura. Opcional, por defecto nunca va.
+    maintenance_schedule: MaintenanceSchedule,
 }
 
 impl SistDronProperties {
-    pub fn new(properties_file: &str) -> Result<Self, Error> {
-        // Cargamos todas las properties (constantes) del archivo, a este global_properties que es genérico
-        let global_properties = Properties::new(properties_file)?;
-
-        // Y ahora buscamos las properties específicas que usará el dron, y las cargamos a los campos de este struct
-        let max_battery_lvl: u8;
-        if let Some(prop) = global_properties.get("max_battery_lvl") {
-            max_battery_lvl = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "max_battery_lvl"))?;
-        } else {
-            println!("No se encontró la propiedad 'max_battery_lvl");
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Falta propiedad max_battery_lvl.",
-            ));
-        }
-
-        let min_operational_battery_lvl: u8;
-        if let Some(prop) = global_properties.get("min_operational_battery_lvl") {
-            min_operational_battery_lvl = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "min_operational_battery_lvl"))?;
-        } else {
-            println!("No se encontró la propiedad 'min_operational_battery_lvl");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let range: u8;
-        if let Some(prop) = global_properties.get("range") {
-            range = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "range"))?;
-        } else {
-            println!("No se encontró la propiedad 'range");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let stay_at_inc_time: u8;
-        if let Some(prop) = global_properties.get("stay_at_inc_time") {
-            stay_at_inc_time = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "stay_at_inc_time"))?;
-        } else {
-            println!("No se encontró la propiedad 'stay_at_inc_time");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        //
-        let range_center_lat: f64;
-        if let Some(prop) = global_properties.get("range_center_lat") {
-            range_center_lat = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "range_center_lat"))?;
-        } else {
-            println!("No se encontró la propiedad 'range_center_lat");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let range_center_lon: f64;
-        if let Some(prop) = global_properties.get("range_center_lon") {
-            range_center_lon = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "range_center_lon"))?;
-        } else {
-            println!("No se encontró la propiedad 'range_center_lon");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let mantainance_lat: f64;
-        if let Some(prop) = global_properties.get("mantainance_lat") {
-            mantainance_lat = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "mantainance_lat"))?;
-        } else {
-            println!("No se encontró la propiedad 'mantainance_lat");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let mantainance_lon: f64;
-        if let Some(prop) = global_properties.get("mantainance_lon") {
-            mantainance_lon = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "mantainance_lon"))?;
-        } else {
-            println!("No se encontró la propiedad 'mantainance_lon");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
-
-        let speed: f64;
-        if let Some(prop) = global_properties.get("speed") {
-            speed = prop
-                .parse()
-                .map_err(|_| Error::new(ErrorKind::InvalidInput, "speed"))?;
-        } else {
-            println!("No se encontró la propiedad 'speed");
-            return Err(Error::new(ErrorKind::Other, "Falta propiedad sist dron."));
-        }
+    /// Carga las properties del dron, que puede tener valores propios para cada una (ver `DronProperties`).
+    /// Devuelve error, nombrando la propiedad, si alguna falta o tiene un valor inválido.
+    pub fn from_properties(properties: &DronProperties) -> Result<Self, Error> {
+        let max_battery_lvl = properties.get_required("max_battery_lvl", |lvl: &u8| *lvl > 0)?;
+        let min_operational_battery_lvl = properties
+            .get_required("min_operational_battery_lvl", |lvl: &u8| *lvl < max_battery_lvl)?;
+        let range = properties.get_required("range", |range: &u8| *range > 0)?;
+        let stay_at_inc_time = properties.get_required("stay_at_inc_time", |_: &u8| true)?;
+
+        let range_center = GeoPosition::new(
+            properties.get_required("range_center_lat", is_latitude)?,
+            properties.get_required("range_center_lon", is_longitude)?,
+        )?;
+        let mantainance = GeoPosition::new(
+            properties.get_required("mantainance_lat", is_latitude)?,
+            properties.get_required("mantainance_lon", is_longitude)?,
+        )?;
+
+        let cruise_speed = properties
+            .get_required("cruise_speed", |speed: &f64| speed.is_finite() && *speed > 0.0)?;
+        let flight_tick_ms = properties.get_required("flight_tick_ms", |tick: &u64| *tick > 0)?;
+
+        let battery_model = BatteryModel::from_properties(properties)?;
+
+        let preemption_policy = match properties.get("incident_preemption") {
+            Some((key, prop)) => {
+                PreemptionPolicy::from_property(prop).map_err(|_| invalid_value(&key, prop))?
+            }
+            None => PreemptionPolicy::default(),
+        };
+
+        let coordination_mode = match properties.get("incident_coordination") {
+            Some((key, prop)) => {
+                CoordinationMode::from_property(prop).map_err(|_| invalid_value(&key, prop))?
+            }
+            None => CoordinationMode::default(),
+        };
+
+        let heartbeat_interval_secs = properties
+            .get_optional("heartbeat_interval_secs", |secs: &u64| *secs > 0)?
+            .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS);
+        let report_attended = properties
+            .get_optional("report_attended", |_: &bool| true)?
+            .unwrap_or(false);
+        let time_scale = properties
+            .get_optional("time_scale", |scale: &f64| scale.is_finite() && *scale > 0.0)?
+            .unwrap_or(1.0);
+        let distance_model = match properties.get("distance_model") {
+            Some((key, prop)) => {
+                DistanceModel::from_property(prop).map_err(|_| invalid_value(&key, prop))?
+            }
+            None => DistanceModel::default(),
+        };
+        let maintenance_schedule = MaintenanceSchedule::from_properties(properties)?;
+        let telemetry_history_size = properties
+            .get_optional("telemetry_history_size", |size: &usize| (1..=MAX_HISTORY_SIZE).contains(size))?
+            .unwrap_or(DEFAULT_HISTORY_SIZE);
 
         Ok(Self {
             max_battery_lvl,
@@ -124,19 +112,27 @@ impl SistDronProperties {
             range,
             stay_at_inc_time,
 
-            range_center_lat,
-            range_center_lon,
-
-            mantainance_lat,
-            mantainance_lon,
-
-            speed,
+            range_center,
+
+            mantainance,
+
+            cruise_speed,
+            flight_tick_ms,
+            battery_model,
+            preemption_policy,
+            coordination_mode,
+            heartbeat_interval_secs,
+            report_attended,
+            time_scale,
+            telemetry_history_size,
+            distance_model,
+            maintenance_schedule,
         })
     }
 
-    /// Devuelve latitud y longitud del centro del rango, a la que volverá el dron luego de terminar de resolver un incidente
-    pub fn get_range_center_position(&self) -> (f64, f64) {
-        (self.range_center_lat, self.range_center_lon)
+    /// Devuelve la posición del centro del rango, a la que volverá el dron luego de terminar de resolver un incidente
+    pub fn get_range_center_position(&self) -> GeoPosition {
+        self.range_center
     }
 
     /// Devuelve el nivel mínimo de batería para poder funcionar
@@ -149,21 +145,84 @@ impl SistDronProperties {
         self.range as f64
     }
 
-    /// Devuelve latitud y longitud del lugar de Mantenimiento, al que irá para recargar su batería
-    pub fn get_mantainance_position(&self) -> (f64, f64) {
-        (self.mantainance_lat, self.mantainance_lon)
+    /// Devuelve la posición del lugar de Mantenimiento, al que irá para recargar su batería
+    pub fn get_mantainance_position(&self) -> GeoPosition {
+        self.mantainance
+    }
+
+    /// Devuelve la velocidad de vuelo crucero del dron, en m/s
+    pub fn get_cruise_speed(&self) -> f64 {
+        self.cruise_speed
+    }
+
+    /// Devuelve cada cuánto se actualiza la posición del dron mientras vuela
+    pub fn get_flight_tick(&self) -> Duration {
+        Duration::from_millis(self.flight_tick_ms)
+    }
+
+    /// Devuelve el modelo de consumo de batería del dron
+    pub fn get_battery_model(&self) -> BatteryModel {
+        self.battery_model
+    }
+
+    /// Devuelve la política con la que elige el próximo incidente, y con la que deja el que atiende por otro
+    pub fn get_preemption_policy(&self) -> PreemptionPolicy {
+        self.preemption_policy
+    }
+
+    /// Devuelve cómo se decide qué drones atienden cada incidente
+    pub fn get_coordination_mode(&self) -> CoordinationMode {
+        self.coordination_mode
+    }
+
+    /// Devuelve cada cuánto publica el dron su latido
+    pub fn get_heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    /// Devuelve cuánto deben permanecer los drones en la posición del incidente para darlo por atendido
+    pub fn get_stay_at_inc_time(&self) -> Duration {
+        Duration::from_secs(self.stay_at_inc_time as u64)
+    }
+
+    /// Devuelve si el dron avisa por el topic `inc-attended` cuando se atendió su incidente
+    pub fn reports_attended(&self) -> bool {
+        self.report_attended
+    }
+
+    /// Devuelve cuántas veces más rápido que el real transcurre el tiempo del dron
+    pub fn get_time_scale(&self) -> f64 {
+        self.time_scale
     }
 
-    /// Devuelve la velocidad de vuelo del dron
-    pub fn get_speed(&self) -> f64 {
-        self.speed
+    /// Devuelve cuántas de sus últimas current_info conserva el dron
+    pub fn get_telemetry_history_size(&self) -> usize {
+        self.telemetry_history_size
     }
 
-    pub fn set_range_center_position(&mut self, lat_inicial: f64, lon_inicial: f64) {
-        self.range_center_lat = lat_inicial;
-        self.range_center_lon = lon_inicial;
+    /// Devuelve cómo calcula las distancias
+    pub fn get_distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+
+    /// Devuelve a partir de qué desgaste va a mantenimiento programado, y cuánto dura el mismo
+    pub fn get_maintenance_schedule(&self) -> MaintenanceSchedule {
+        self.maintenance_schedule
+    }
+
+    pub fn set_range_center_position(&mut self, initial_position: GeoPosition) {
+        self.range_center = initial_position;
     }
     pub fn get_max_battery_lvl(&self) -> u8 {
         self.max_battery_lvl
     }
 }
+
+/// Devuelve si `value` es una latitud o longitud válida.
+fn is_latitude(value: &f64) -> bool {
+    (-90.0..=90.0).contains(value)
+}
+
+fn is_longitude(value: &f64) -> bool {
+    (-180.0..=180.0).contains(value)
+}
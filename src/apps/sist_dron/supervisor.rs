@@ -0,0 +1,144 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::logging::string_logger::StringLogger;
+
+use super::data::Data;
+
+/// Cantidad de veces que se reinicia un componente del dron que entró en pánico, antes de apagar el dron.
+pub const MAX_RESTARTS: u32 = 3;
+
+/// Supervisa los componentes que corren en los hilos del dron (batería, patrullaje, publicación, lógica, etc).
+/// Si uno entra en pánico, lo registra en el log y lo reinicia; y si vuelve a hacerlo más de `MAX_RESTARTS` veces,
+/// apaga el dron de forma ordenada, para que no quede publicando información desactualizada.
+#[derive(Debug)]
+pub struct Supervisor {
+    data: Data,
+    logger: StringLogger,
+    max_restarts: u32,
+}
+
+impl Supervisor {
+    pub fn new(data: Data, logger: StringLogger) -> Self {
+        Self {
+            data,
+            logger,
+            max_restarts: MAX_RESTARTS,
+        }
+    }
+
+    /// Indica cuántas veces reiniciar un componente antes de apagar el dron.
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Corre el componente `component`, cuyo cuerpo es `body`, hasta que termine. Si entra en pánico, lo vuelve a
+    /// correr, salvo que el dron se esté apagando o ya se lo haya reiniciado `max_restarts` veces; en ese caso
+    /// apaga el dron. Devuelve si el componente terminó sin entrar en pánico.
+    pub fn run(&self, component: &str, mut body: impl FnMut()) -> bool {
+        let mut restarts = 0;
+        loop {
+            let Err(panic) = panic::catch_unwind(AssertUnwindSafe(&mut body)) else {
+                return true;
+            };
+            self.logger.log(format!(
+                "Dron: el componente {} entró en pánico: {}.",
+                component,
+                panic_message(&panic)
+            ));
+            if self.data.is_shutting_down() {
+                return false;
+            }
+            if restarts >= self.max_restarts {
+                self.logger.log(format!(
+                    "Dron: el componente {} se reinició {} veces, apago el dron.",
+                    component, restarts
+                ));
+                self.data.request_shutdown();
+                return false;
+            }
+            restarts += 1;
+            self.logger.log(format!(
+                "Dron: reinicio el componente {} ({}/{}).",
+                component, restarts, self.max_restarts
+            ));
+        }
+    }
+
+    pub fn clone_ref(&self) -> Self {
+        Self {
+            data: self.data.clone_ref(),
+            logger: self.logger.clone_ref(),
+            max_restarts: self.max_restarts,
+        }
+    }
+}
+
+/// Devuelve el mensaje con el que se entró en pánico, si es un texto.
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "sin mensaje"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::Supervisor;
+    use crate::{
+        apps::{
+            geo_position::GeoPosition,
+            sist_dron::{
+                data::Data,
+                dron_config::{DronConfig, DEFAULT_CONFIG_FILE},
+                dron_current_info::DronCurrentInfo,
+                dron_state::DronState,
+                flight_settings::FlightSettings,
+            },
+        },
+        logging::string_logger::StringLogger,
+    };
+
+    fn create_data() -> Data {
+        let position = GeoPosition::new(-34.6, -58.4).unwrap();
+        let config = DronConfig::from_file(DEFAULT_CONFIG_FILE, 1, position).unwrap();
+        let ci = DronCurrentInfo::new(1, position, 100, DronState::ExpectingToRecvIncident);
+        Data::new(ci, FlightSettings::new(&config.get_properties()))
+    }
+
+    #[test]
+    fn test_1_se_reinicia_el_componente_y_si_sigue_fallando_se_apaga_el_dron() {
+        let data = create_data();
+        let (logger_tx, logger_rx) = mpsc::channel::<String>();
+        let supervisor = Supervisor::new(data.clone_ref(), StringLogger::new(logger_tx)).with_max_restarts(2);
+
+        // Falla dos veces, y la tercera termina bien: no se apaga.
+        let mut runs = 0;
+        assert!(supervisor.run("prueba", || {
+            runs += 1;
+            if runs < 3 {
+                panic!("falla {}", runs);
+            }
+        }));
+        assert_eq!(runs, 3);
+        assert!(!data.is_shutting_down());
+        assert!(logger_rx.try_iter().any(|log| log.contains("falla 2")));
+
+        // Falla siempre: luego de reiniciarlo dos veces, apaga el dron.
+        let mut runs = 0;
+        assert!(!supervisor.run("prueba", || {
+            runs += 1;
+            panic!("falla siempre");
+        }));
+        assert_eq!(runs, 3);
+        assert!(data.is_shutting_down());
+    }
+}
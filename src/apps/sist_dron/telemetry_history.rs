@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind},
+};
+
+use super::dron_current_info::DronCurrentInfo;
+
+/// Cantidad máxima de publish con los que el dron envía su historial al reconectarse.
+pub const MAX_HISTORY_BATCHES: usize = 20;
+/// Cantidad máxima de current_info que puede guardar el historial: las que, en vuelo y sin incidentes pendientes,
+/// entran en `MAX_HISTORY_BATCHES` publish por el topic `dron-history`.
+pub const MAX_HISTORY_SIZE: usize = 60;
+
+/// Historial de las últimas current_info del dron, hasta `capacity`. Registra cuántas de las últimas no se
+/// publicaron, por no tener conexión con el server, para publicarlas juntas al reconectarse.
+#[derive(Debug)]
+pub struct TelemetryHistory {
+    samples: VecDeque<DronCurrentInfo>,
+    capacity: usize,
+    unsent: usize, // cuántas de las últimas muestras no se publicaron.
+}
+
+impl TelemetryHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            unsent: 0,
+        }
+    }
+
+    /// Registra la current_info `ci`, y si se publicó. Si el historial está lleno, descarta la más vieja.
+    pub fn record(&mut self, ci: DronCurrentInfo, sent: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ci);
+        if !sent {
+            self.unsent = (self.unsent + 1).min(self.capacity);
+        }
+    }
+
+    /// Devuelve las últimas current_info, de la más vieja a la más nueva.
+    pub fn get_samples(&self) -> Vec<DronCurrentInfo> {
+        self.samples.iter().cloned().collect()
+    }
+
+    /// Devuelve si hay current_info que no se publicaron.
+    pub fn has_unsent(&self) -> bool {
+        self.unsent > 0
+    }
+
+    /// Devuelve las current_info que no se publicaron, de la más vieja a la más nueva, y las da por publicadas.
+    pub fn take_unsent(&mut self) -> Vec<DronCurrentInfo> {
+        let unsent = self
+            .samples
+            .iter()
+            .skip(self.samples.len() - self.unsent)
+            .cloned()
+            .collect();
+        self.unsent = 0;
+        unsent
+    }
+}
+
+/// Lote de current_info de un dron que no se publicaron mientras no tenía conexión con el server. Lo publica
+/// por el topic `dron-history` al reconectarse, para que monitoreo complete el recorrido que hizo mientras tanto.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TelemetryBatch {
+    dron_id: u8,
+    samples: Vec<DronCurrentInfo>,
+}
+
+impl TelemetryBatch {
+    pub fn new(dron_id: u8, samples: Vec<DronCurrentInfo>) -> Self {
+        Self { dron_id, samples }
+    }
+
+    pub fn get_dron_id(&self) -> u8 {
+        self.dron_id
+    }
+
+    /// Devuelve las current_info del lote, de la más vieja a la más nueva.
+    pub fn get_samples(&self) -> &[DronCurrentInfo] {
+        &self.samples
+    }
+
+    /// Convierte el lote a bytes: el id del dron, la cantidad de current_info, y cada una precedida por su largo
+    /// (2 bytes). Incluye como máximo las últimas 255.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let samples = &self.samples[self.samples.len().saturating_sub(u8::MAX as usize)..];
+        let mut bytes = vec![self.dron_id, samples.len() as u8];
+        for ci in samples {
+            let ci_bytes = ci.to_bytes();
+            bytes.extend_from_slice(&(ci_bytes.len() as u16).to_be_bytes());
+            bytes.extend(ci_bytes);
+        }
+        bytes
+    }
+
+    /// Separa el lote en lotes del mismo dron que, convertidos a bytes, ocupan a lo sumo `max_len` bytes cada uno,
+    /// para que cada uno entre en un publish. Conservan el orden de las current_info. Las que no entran ni
+    /// siquiera solas en un lote (ie por tener muchísimos incidentes pendientes) se descartan.
+    pub fn split(&self, max_len: usize) -> Vec<TelemetryBatch> {
+        let mut batches = vec![];
+        let mut current = TelemetryBatch::new(self.dron_id, vec![]);
+        let mut current_len = 2; // id del dron y cantidad de current_info.
+        for ci in &self.samples {
+            let ci_len = 2 + ci.to_bytes().len();
+            if 2 + ci_len > max_len {
+                continue;
+            }
+            if current_len + ci_len > max_len || current.samples.len() == u8::MAX as usize {
+                batches.push(std::mem::replace(&mut current, TelemetryBatch::new(self.dron_id, vec![])));
+                current_len = 2;
+            }
+            current.samples.push(ci.clone());
+            current_len += ci_len;
+        }
+        if !current.samples.is_empty() {
+            batches.push(current);
+        }
+        batches
+    }
+
+    /// Obtiene el lote a partir de bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(invalid_batch());
+        }
+        let mut samples = Vec::with_capacity(bytes[1] as usize);
+        let mut index = 2;
+        for _ in 0..bytes[1] {
+            let len_bytes = bytes.get(index..index + 2).ok_or_else(invalid_batch)?;
+            let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+            index += 2;
+            let ci_bytes = bytes.get(index..index + len).ok_or_else(invalid_batch)?;
+            samples.push(DronCurrentInfo::from_bytes(ci_bytes.to_vec())?);
+            index += len;
+        }
+        if index != bytes.len() {
+            return Err(invalid_batch());
+        }
+        Ok(Self::new(bytes[0], samples))
+    }
+}
+
+fn invalid_batch() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Lote de current_info de dron inválido.",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TelemetryBatch, TelemetryHistory, MAX_HISTORY_BATCHES, MAX_HISTORY_SIZE};
+    use crate::apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_flying_info::DronFlyingInfo, dron_state::DronState},
+    };
+    use crate::mqtt::messages::{
+        publish_flags::PublishFlags,
+        publish_message::{PublishMessage, MAX_REMAINING_LENGTH},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    fn create_ci(lon: f64) -> DronCurrentInfo {
+        DronCurrentInfo::new(3, position(-34.6, lon), 90, DronState::Flying)
+    }
+
+    #[test]
+    fn test_1_el_historial_conserva_las_ultimas_y_devuelve_las_no_publicadas() {
+        let mut history = TelemetryHistory::new(3);
+        history.record(create_ci(-58.1), true);
+        history.record(create_ci(-58.2), true);
+        assert!(!history.has_unsent());
+
+        for lon in [-58.3, -58.4, -58.5, -58.6] {
+            history.record(create_ci(lon), false);
+        }
+        // Se conservan las últimas 3, todas sin publicar.
+        assert_eq!(
+            history.get_samples(),
+            vec![create_ci(-58.4), create_ci(-58.5), create_ci(-58.6)]
+        );
+        assert_eq!(history.take_unsent(), history.get_samples());
+        assert!(!history.has_unsent());
+        assert!(history.take_unsent().is_empty());
+    }
+
+    #[test]
+    fn test_2_el_lote_se_convierte_a_bytes_y_de_vuelta() {
+        let batch = TelemetryBatch::new(3, vec![create_ci(-58.3), create_ci(-58.4)]);
+
+        assert_eq!(TelemetryBatch::from_bytes(batch.to_bytes()).unwrap(), batch);
+        let mut truncated = batch.to_bytes();
+        truncated.pop();
+        assert!(TelemetryBatch::from_bytes(truncated).is_err());
+        assert!(TelemetryBatch::from_bytes(vec![3]).is_err());
+    }
+
+    #[test]
+    fn test_3_el_historial_completo_se_publica_en_lotes_que_entran_en_un_publish() {
+        let topic = AppsMqttTopics::DronHistoryTopic.to_str();
+        let samples: Vec<DronCurrentInfo> = (0..MAX_HISTORY_SIZE)
+            .map(|i| {
+                let mut ci = create_ci(-58.0 - i as f64 / 1000.0);
+                ci.set_flying_info(DronFlyingInfo::new((0.6, 0.8), 25.0));
+                ci
+            })
+            .collect();
+        let batch = TelemetryBatch::new(3, samples.clone());
+
+        let batches = batch.split(PublishMessage::max_payload_len(topic));
+        assert!(batches.len() > 1 && batches.len() <= MAX_HISTORY_BATCHES);
+        let mut received = vec![];
+        for batch in batches {
+            let flags = PublishFlags::new(0, 1, 0).unwrap();
+            let bytes = PublishMessage::new(flags, topic, Some(1), &batch.to_bytes()).unwrap().to_bytes();
+            assert!(bytes.len() - 2 <= MAX_REMAINING_LENGTH);
+            assert_eq!(bytes[1] as usize, bytes.len() - 2);
+
+            let publish = PublishMessage::from_bytes(bytes).unwrap();
+            let batch = TelemetryBatch::from_bytes(publish.get_payload()).unwrap();
+            assert_eq!(batch.get_dron_id(), 3);
+            received.extend_from_slice(batch.get_samples());
+        }
+        assert_eq!(received, samples);
+    }
+}
@@ -6,14 +6,17 @@ use std::{
     net::SocketAddr,
 };
 
+use super::dron_config::DEFAULT_CONFIG_FILE;
+
 /// Lee y devuelve, de los argumentos ingresados al correr el programa,
-/// el id del dron, y la IP y el puerto del servidor al que el cliente se va a conectar.
-fn load_id_lat_long_ip_and_port() -> Result<(u8, f64, f64, String, u16), Error> {
+/// el id del dron, y la IP y el puerto del servidor al que el cliente se va a conectar;
+/// y el archivo de configuración del dron, que es opcional.
+fn load_id_lat_long_ip_port_and_config_file() -> Result<(u8, f64, f64, String, u16, String), Error> {
     let argv = std::env::args().collect::<Vec<String>>();
-    if argv.len() != 6 {
+    if argv.len() != 6 && argv.len() != 7 {
         return Err(Error::new(
             ErrorKind::InvalidInput,
-            "Cantidad de argumentos inválida. Debe ingresar el ID, latitud, longitud, dirección IP y el puerto del servidor.",
+            "Cantidad de argumentos inválida. Debe ingresar el ID, latitud, longitud, dirección IP y el puerto del servidor, y opcionalmente el archivo de configuración.",
         ));
     }
 
@@ -35,20 +38,49 @@ fn load_id_lat_long_ip_and_port() -> Result<(u8, f64, f64, String, u16), Error>
         )
     })?;
 
-    Ok((id, latitud, longitud, ip.to_string(), port))
+    let config_file = argv.get(6).map_or(DEFAULT_CONFIG_FILE, |file| file.as_str());
+
+    Ok((id, latitud, longitud, ip.to_string(), port, config_file.to_string()))
 }
 
 /// Construye y devuelve la broker_address necesaria para conectarse al servidor mqtt,
 /// a partir de los argumentos recibidos de id, latitud, longitud, ip y puerto.
-/// También devuelve la latitud y longitud.
-pub fn get_id_lat_long_and_broker_address() -> Result<(u8, f64, f64, SocketAddr), Error> {
-    let (id, latitud, longitud, ip, puerto) = load_id_lat_long_ip_and_port()?;
+/// También devuelve la latitud y longitud, y el archivo de configuración del dron.
+pub fn get_id_lat_long_broker_address_and_config_file(
+) -> Result<(u8, f64, f64, SocketAddr, String), Error> {
+    let (id, latitud, longitud, ip, puerto, config_file) = load_id_lat_long_ip_port_and_config_file()?;
     let addr: String = format!("{}:{}", ip, puerto);
     let broker_addr = addr
         .parse()
         .map_err(|_| Error::new(ErrorKind::InvalidInput, "Dirección no válida"))?;
 
-    Ok((id, latitud, longitud, broker_addr))
+    Ok((id, latitud, longitud, broker_addr, config_file))
+}
+
+/// Lee de los argumentos ingresados al correr el coordinador de la flota la IP y el puerto del servidor, y el
+/// archivo de configuración de los drones, que es opcional. Devuelve la broker_address y el archivo.
+pub fn get_broker_address_and_config_file() -> Result<(SocketAddr, String), Error> {
+    let argv = std::env::args().collect::<Vec<String>>();
+    if argv.len() != 3 && argv.len() != 4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Cantidad de argumentos inválida. Debe ingresar la dirección IP y el puerto del servidor, y opcionalmente el archivo de configuración de los drones.",
+        ));
+    }
+
+    let port = argv[2].parse::<u16>().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "El puerto proporcionado no es válido",
+        )
+    })?;
+    let broker_addr = format!("{}:{}", argv[1], port)
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Dirección no válida"))?;
+
+    let config_file = argv.get(3).map_or(DEFAULT_CONFIG_FILE, |file| file.as_str());
+
+    Ok((broker_addr, config_file.to_string()))
 }
 
 // Función no usada al menos por ahora
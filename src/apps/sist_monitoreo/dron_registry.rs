@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use crate::apps::properties::Properties;
+
+/// Prefijo de las properties que registran a un dron, seguido por su id (ie `dron-4`).
+const DRON_KEY_PREFIX: &str = "dron-";
+
+/// Nombre con el que los operadores conocen a un dron, y sus datos.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DronInfo {
+    call_sign: String,
+    model: Option<String>,
+    max_speed: Option<f64>, // en m/s
+}
+
+impl DronInfo {
+    pub fn new(call_sign: String, model: Option<String>, max_speed: Option<f64>) -> Self {
+        Self {
+            call_sign,
+            model,
+            max_speed,
+        }
+    }
+
+    pub fn get_call_sign(&self) -> &str {
+        &self.call_sign
+    }
+
+    pub fn get_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn get_max_speed(&self) -> Option<f64> {
+        self.max_speed
+    }
+
+    /// Obtiene los datos a partir de `nombre[,modelo[,velocidad máxima]]` (ie `Halcón-4,Matrice 300,23`).
+    fn from_property(value: &str) -> Option<Self> {
+        let mut fields = value.split(',').map(str::trim);
+        let call_sign = fields.next().filter(|call_sign| !call_sign.is_empty())?;
+        let model = fields.next().filter(|model| !model.is_empty());
+        let max_speed = match fields.next() {
+            Some(speed) => Some(speed.parse::<f64>().ok().filter(|speed| speed.is_finite() && *speed > 0.0)?),
+            None => None,
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(Self::new(call_sign.to_string(), model.map(str::to_string), max_speed))
+    }
+}
+
+/// Registro opcional de los drones, para mostrarlos en la interfaz por su nombre en vez de por su id.
+/// Se carga de las properties `dron-<id>=nombre[,modelo[,velocidad máxima]]`; los drones no registrados se
+/// muestran por su id.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DronRegistry {
+    drones: HashMap<u8, DronInfo>,
+}
+
+impl DronRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Carga el registro de `properties_file`. Si el archivo no existe, el registro queda vacío.
+    pub fn from_file(properties_file: &str) -> Result<Self, Error> {
+        match Properties::new(properties_file) {
+            Ok(properties) => Self::from_properties(&properties),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    /// Carga el registro de las properties `dron-<id>`, ignorando las demás. Devuelve error si alguna es inválida.
+    pub fn from_properties(properties: &Properties) -> Result<Self, Error> {
+        let mut registry = Self::new();
+        for (key, value) in properties.iter() {
+            let Some(id) = key.strip_prefix(DRON_KEY_PREFIX) else {
+                continue;
+            };
+            let (Ok(id), Some(info)) = (id.parse::<u8>(), DronInfo::from_property(value)) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Registro de dron inválido: {}={}.", key, value),
+                ));
+            };
+            registry.add(id, info);
+        }
+        Ok(registry)
+    }
+
+    pub fn add(&mut self, dron_id: u8, info: DronInfo) {
+        self.drones.insert(dron_id, info);
+    }
+
+    pub fn get(&self, dron_id: u8) -> Option<&DronInfo> {
+        self.drones.get(&dron_id)
+    }
+
+    /// Devuelve cómo se muestra el dron: por su nombre y su id si está registrado (ie `Halcón-4 (ID 4)`), y si no,
+    /// solamente por su id.
+    pub fn label(&self, dron_id: u8) -> String {
+        match self.get(dron_id) {
+            Some(info) => format!("{} (ID {})", info.get_call_sign(), dron_id),
+            None => format!("Dron {}", dron_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DronInfo, DronRegistry};
+    use crate::apps::properties::Properties;
+
+    #[test]
+    fn test_1_se_muestra_a_los_drones_registrados_por_su_nombre() {
+        let properties = Properties::from_contents(
+            "ip-server-mqtt=127.0.0.1\ndron-4=Halcón-4, Matrice 300, 23\ndron-7=Cóndor",
+        )
+        .unwrap();
+        let registry = DronRegistry::from_properties(&properties).unwrap();
+
+        assert_eq!(registry.label(4), "Halcón-4 (ID 4)");
+        assert_eq!(
+            registry.get(4),
+            Some(&DronInfo::new("Halcón-4".to_string(), Some("Matrice 300".to_string()), Some(23.0)))
+        );
+        assert_eq!(registry.get(7).unwrap().get_model(), None);
+        assert_eq!(registry.label(5), "Dron 5");
+
+        for invalid in ["dron-x=Halcón", "dron-4=", "dron-4=Halcón,Matrice,rápido", "dron-4=a,b,1,c"] {
+            let properties = Properties::from_contents(invalid).unwrap();
+            assert!(DronRegistry::from_properties(&properties).is_err());
+        }
+    }
+}
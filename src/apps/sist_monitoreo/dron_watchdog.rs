@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::apps::sist_dron::dron_heartbeat::DronHeartbeat;
+
+/// Cantidad de intervalos sin recibir el latido de un dron a partir de la cual se lo considera atrasado.
+const STALE_AFTER_INTERVALS: u32 = 2;
+/// Cantidad de intervalos sin recibir el latido de un dron a partir de la cual se lo considera perdido.
+const LOST_AFTER_INTERVALS: u32 = 4;
+
+/// Estado de un dron según qué tan reciente es su último latido.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DronHealth {
+    Alive,
+    Stale,
+    Lost,
+}
+
+/// Vigila los latidos que publican los drones por el topic `dron-health`, para detectar a los que dejaron de
+/// responder aunque no se hayan desconectado (y por lo tanto no se haya publicado su will message).
+#[derive(Debug, Default)]
+pub struct DronWatchdog {
+    last_heartbeats: HashMap<u8, (Instant, Duration)>, // dron_id -> (cuándo se recibió el último latido, intervalo)
+}
+
+impl DronWatchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra el latido recibido en el instante `now`.
+    pub fn register(&mut self, heartbeat: &DronHeartbeat, now: Instant) {
+        self.last_heartbeats
+            .insert(heartbeat.get_id(), (now, heartbeat.get_interval()));
+    }
+
+    /// Deja de vigilar al dron, porque se apagó o se desconectó.
+    pub fn forget(&mut self, dron_id: u8) {
+        self.last_heartbeats.remove(&dron_id);
+    }
+
+    /// Devuelve el estado de cada dron vigilado en el instante `now`.
+    pub fn healths(&self, now: Instant) -> Vec<(u8, DronHealth)> {
+        self.last_heartbeats
+            .iter()
+            .map(|(dron_id, (last, interval))| {
+                let elapsed = now.saturating_duration_since(*last);
+                let health = if elapsed >= *interval * LOST_AFTER_INTERVALS {
+                    DronHealth::Lost
+                } else if elapsed >= *interval * STALE_AFTER_INTERVALS {
+                    DronHealth::Stale
+                } else {
+                    DronHealth::Alive
+                };
+                (*dron_id, health)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{DronHealth, DronWatchdog};
+    use crate::apps::sist_dron::{dron_heartbeat::DronHeartbeat, dron_state::DronState};
+
+    fn heartbeat(id: u8) -> DronHeartbeat {
+        DronHeartbeat::new(
+            id,
+            90,
+            DronState::ExpectingToRecvIncident,
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        )
+    }
+
+    #[test]
+    fn test_1_un_dron_sin_latidos_recientes_queda_atrasado_y_luego_perdido() {
+        let mut watchdog = DronWatchdog::new();
+        let start = Instant::now();
+        watchdog.register(&heartbeat(1), start);
+
+        let health_at = |secs| watchdog.healths(start + Duration::from_secs(secs));
+        assert_eq!(health_at(6), vec![(1, DronHealth::Alive)]);
+        assert_eq!(health_at(10), vec![(1, DronHealth::Stale)]);
+        assert_eq!(health_at(21), vec![(1, DronHealth::Lost)]);
+
+        // Al recibir un nuevo latido vuelve a estar al día, y al apagarse deja de vigilarse.
+        watchdog.register(&heartbeat(1), start + Duration::from_secs(21));
+        assert_eq!(
+            watchdog.healths(start + Duration::from_secs(22)),
+            vec![(1, DronHealth::Alive)]
+        );
+        watchdog.forget(1);
+        assert!(watchdog.healths(start).is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, VecDeque};
+
+use egui::{Color32, Pos2, Rect, Sense, Stroke};
+
+use crate::apps::{
+    incident_data::{incident::Incident, incident_info::IncidentInfo},
+    sist_camaras::camera::Camera,
+    sist_dron::dron_current_info::DronCurrentInfo,
+};
+
+use super::{dron_registry::DronRegistry, ui_sistema_monitoreo::format_millis};
+
+/// Cantidad de niveles de batería recientes que se conservan de cada dron, para su gráfico.
+const MAX_BATTERY_HISTORY_LEN: usize = 120;
+
+/// Elemento del mapa que se puede inspeccionar.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Entity {
+    Dron(u8),
+    Camera(u8),
+    Incident(IncidentInfo),
+}
+
+/// Ventana abierta del inspector. Las ventanas no fijadas se reemplazan al clickear otro elemento; las fijadas
+/// quedan abiertas hasta cerrarlas.
+#[derive(Debug)]
+struct InspectorWindow {
+    entity: Entity,
+    pinned: bool,
+}
+
+/// Inspector de los elementos del mapa: al clickear un dron, una cámara o un incidente muestra una ventana con todo
+/// lo que se sabe de él, cuándo se actualizó por última vez y, para los drones, un gráfico de su batería reciente.
+#[derive(Debug, Default)]
+pub struct EntityInspector {
+    windows: Vec<InspectorWindow>,
+    drones: HashMap<u8, DronCurrentInfo>,
+    cameras: HashMap<u8, Camera>,
+    incidents: HashMap<IncidentInfo, Incident>,
+    last_updates: HashMap<Entity, u64>, // en milisegundos desde el epoch
+    battery_histories: HashMap<u8, VecDeque<u8>>,
+}
+
+impl EntityInspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Guarda la última current_info del dron, recibida en el instante `now`.
+    pub fn record_dron(&mut self, dron: &DronCurrentInfo, now: u64) {
+        let dron_id = dron.get_id();
+        let history = self.battery_histories.entry(dron_id).or_default();
+        if history.len() == MAX_BATTERY_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(dron.get_battery_lvl());
+        self.drones.insert(dron_id, dron.clone());
+        self.last_updates.insert(Entity::Dron(dron_id), now);
+    }
+
+    /// Guarda el último estado de la cámara, recibido en el instante `now`.
+    pub fn record_camera(&mut self, camera: &Camera, now: u64) {
+        self.cameras.insert(camera.get_id(), camera.clone());
+        self.last_updates.insert(Entity::Camera(camera.get_id()), now);
+    }
+
+    /// Guarda el último estado del incidente, que cambió en el instante `now`.
+    pub fn record_incident(&mut self, incident: &Incident, now: u64) {
+        self.incidents.insert(incident.get_info(), incident.clone());
+        self.last_updates.insert(Entity::Incident(incident.get_info()), now);
+    }
+
+    /// Abre la ventana de `entity`, en lugar de las que no están fijadas. Si ya estaba abierta, no hace nada.
+    pub fn open(&mut self, entity: Entity) {
+        if self.windows.iter().any(|window| window.entity == entity) {
+            return;
+        }
+        self.windows.retain(|window| window.pinned);
+        self.windows.push(InspectorWindow { entity, pinned: false });
+    }
+
+    /// Muestra las ventanas abiertas.
+    pub fn show(&mut self, ctx: &egui::Context, dron_registry: &DronRegistry) {
+        let mut windows = std::mem::take(&mut self.windows);
+        windows.retain_mut(|window| {
+            let mut open = true;
+            egui::Window::new(self.title(window.entity, dron_registry))
+                .id(egui::Id::new(("inspector", window.entity)))
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.checkbox(&mut window.pinned, "Fijar");
+                    ui.separator();
+                    self.details(ui, window.entity);
+                    let last_update = self.last_updates.get(&window.entity).copied().unwrap_or(0);
+                    ui.label(format!("Última actualización: {}", format_millis(last_update)));
+                });
+            open
+        });
+        self.windows = windows;
+    }
+
+    fn title(&self, entity: Entity, dron_registry: &DronRegistry) -> String {
+        match entity {
+            Entity::Dron(dron_id) => format!("Dron {}", dron_registry.label(dron_id)),
+            Entity::Camera(camera_id) => format!("Cámara {}", camera_id),
+            Entity::Incident(inc_info) => format!("Incidente {} ({:?})", inc_info.get_inc_id(), inc_info.get_src()),
+        }
+    }
+
+    fn details(&self, ui: &mut egui::Ui, entity: Entity) {
+        match entity {
+            Entity::Dron(dron_id) => match self.drones.get(&dron_id) {
+                Some(dron) => self.dron_details(ui, dron),
+                None => no_data(ui),
+            },
+            Entity::Camera(camera_id) => match self.cameras.get(&camera_id) {
+                Some(camera) => camera_details(ui, camera),
+                None => no_data(ui),
+            },
+            Entity::Incident(inc_info) => match self.incidents.get(&inc_info) {
+                Some(incident) => incident_details(ui, incident),
+                None => no_data(ui),
+            },
+        }
+    }
+
+    fn dron_details(&self, ui: &mut egui::Ui, dron: &DronCurrentInfo) {
+        ui.label(format!("Posición: {}", dron.get_current_position()));
+        ui.label(format!("Estado: {:?}", dron.get_state()));
+        ui.label(format!("Batería: {}%", dron.get_battery_lvl()));
+        match dron.get_flying_info() {
+            Some(((dir_lat, dir_lon), speed)) => {
+                ui.label(format!("Dirección: ({:.2}, {:.2}), velocidad: {} m/s", dir_lat, dir_lon, speed))
+            }
+            None => ui.label("No está volando."),
+        };
+        match dron.get_inc_id_to_resolve() {
+            Some(inc_info) => ui.label(format!("Incidente a resolver: {}", inc_info.get_inc_id())),
+            None => ui.label("Sin incidente a resolver."),
+        };
+        let pending: Vec<String> = dron
+            .get_pending_incidents()
+            .iter()
+            .map(|inc_info| inc_info.get_inc_id().to_string())
+            .collect();
+        if !pending.is_empty() {
+            ui.label(format!("Incidentes pendientes: {}", pending.join(", ")));
+        }
+        if let Some(history) = self.battery_histories.get(&dron.get_id()) {
+            ui.label("Batería reciente:");
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(200., 40.), Sense::hover());
+            let levels: Vec<u8> = history.iter().copied().collect();
+            ui.painter().rect_filled(rect, 2., Color32::from_gray(30));
+            ui.painter().add(egui::Shape::line(
+                sparkline_points(&levels, rect),
+                Stroke::new(1.5, Color32::from_rgb(0, 200, 0)),
+            ));
+        }
+    }
+}
+
+fn no_data(ui: &mut egui::Ui) {
+    ui.label("No se recibieron datos.");
+}
+
+fn camera_details(ui: &mut egui::Ui, camera: &Camera) {
+    ui.label(format!("Posición: {}", camera.get_position()));
+    ui.label(format!("Estado: {:?}", camera.get_state()));
+    ui.label(format!("Rango: {:.0} m", camera.get_range()));
+    if !camera.is_not_deleted() {
+        ui.label("Eliminada.");
+    }
+    ui.label(format!("Cámaras lindantes: {:?}", camera.get_border_cameras()));
+    let incidents: Vec<String> = camera
+        .get_incs_being_managed()
+        .iter()
+        .map(|inc_info| inc_info.get_inc_id().to_string())
+        .collect();
+    ui.label(format!("Incidentes que sigue: {}", incidents.join(", ")));
+}
+
+fn incident_details(ui: &mut egui::Ui, incident: &Incident) {
+    ui.label(format!("Posición: {}", incident.get_position()));
+    ui.label(format!("Estado: {}", incident.get_state().to_str()));
+    ui.label(format!("Tipo: {}", incident.get_category().to_str()));
+    ui.label(format!("Gravedad: {}", incident.get_severity().to_str()));
+    ui.label(format!("Prioridad: {}", incident.get_priority().to_str()));
+    if !incident.get_description().is_empty() {
+        ui.label(format!("Descripción: {}", incident.get_description()));
+    }
+    ui.label(format!("Creado: {}", format_millis(incident.get_created_at())));
+    if let Some(resolved_at) = incident.get_resolved_at() {
+        ui.label(format!("Resuelto: {}", format_millis(resolved_at)));
+    }
+    for transition in incident.get_history() {
+        ui.label(format!(
+            "{}: {}",
+            transition.get_state().to_str(),
+            format_millis(transition.get_at())
+        ));
+    }
+}
+
+/// Devuelve los puntos del gráfico de los niveles de batería `levels` (de 0 a 100) dentro de `rect`: el primero a la
+/// izquierda, el último a la derecha, y 100% arriba.
+pub fn sparkline_points(levels: &[u8], rect: Rect) -> Vec<Pos2> {
+    let steps = levels.len().saturating_sub(1).max(1) as f32;
+    levels
+        .iter()
+        .enumerate()
+        .map(|(i, level)| {
+            let x = rect.left() + rect.width() * i as f32 / steps;
+            let y = rect.bottom() - rect.height() * f32::from((*level).min(100)) / 100.;
+            Pos2::new(x, y)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use egui::{Pos2, Rect};
+
+    use super::{sparkline_points, Entity, EntityInspector};
+
+    #[test]
+    fn test_1_el_grafico_de_bateria_ocupa_el_rectangulo() {
+        let rect = Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(100., 50.));
+        let points = sparkline_points(&[100, 50, 0], rect);
+        assert_eq!(points, vec![Pos2::new(0., 0.), Pos2::new(50., 25.), Pos2::new(100., 50.)]);
+        assert!(sparkline_points(&[], rect).is_empty());
+    }
+
+    #[test]
+    fn test_2_abrir_un_elemento_reemplaza_a_las_ventanas_no_fijadas() {
+        let mut inspector = EntityInspector::new();
+        inspector.open(Entity::Dron(1));
+        inspector.windows[0].pinned = true;
+        inspector.open(Entity::Camera(2));
+        inspector.open(Entity::Camera(3));
+        inspector.open(Entity::Dron(1));
+
+        let open: Vec<Entity> = inspector.windows.iter().map(|window| window.entity).collect();
+        assert_eq!(open, vec![Entity::Dron(1), Entity::Camera(3)]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Local};
+
+use crate::apps::{
+    geo_position::GeoPosition,
+    incident_data::{incident::Incident, incident_info::IncidentInfo, incident_state::IncidentState},
+    properties::Properties,
+};
+
+/// Guarda en disco cada incidente que conoce Sistema Monitoreo, y cada uno de sus cambios de estado, para poder
+/// consultar el historial de incidentes aunque se reinicie. Cada cambio agrega una línea con el incidente en json al
+/// final del archivo, sin modificar las anteriores; al leerlo, queda la última línea de cada incidente.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentHistoryStore {
+    path: PathBuf,
+}
+
+impl IncidentHistoryStore {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+
+    /// Carga la ruta del archivo de historial de la property `incident-history-file` de `properties_file`. Si el
+    /// archivo de properties no existe o no la define, el historial no se guarda.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        match properties.get("incident-history-file").map(|path| path.trim()) {
+            Some("") => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Valor inválido para incident-history-file.",
+            )),
+            Some(path) => Ok(Some(Self::new(path))),
+            None => Ok(None),
+        }
+    }
+
+    /// Agrega al historial el estado actual de `incident`.
+    pub fn record(&self, incident: &Incident) -> Result<(), Error> {
+        let mut line = serde_json::to_string(incident)?;
+        line.push('\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// Devuelve todos los incidentes del historial, en su último estado, en el orden en que se registraron por
+    /// primera vez. Las líneas que no se pueden leer (ie la última, si se cortó a mitad de escribirla) se ignoran.
+    pub fn load(&self) -> Result<Vec<Incident>, Error> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        // Los ids se reutilizan al reiniciar monitoreo, por lo que un incidente se identifica también por su creación.
+        let mut positions: HashMap<(IncidentInfo, u64), usize> = HashMap::new();
+        let mut incidents: Vec<Incident> = vec![];
+        for incident in content.lines().filter_map(|line| serde_json::from_str::<Incident>(line).ok()) {
+            match positions.get(&(incident.get_info(), incident.get_created_at())) {
+                Some(pos) => incidents[*pos] = incident,
+                None => {
+                    positions.insert((incident.get_info(), incident.get_created_at()), incidents.len());
+                    incidents.push(incident);
+                }
+            }
+        }
+        Ok(incidents)
+    }
+
+    /// Devuelve los incidentes del historial que cumplen con `query`.
+    pub fn query(&self, query: &IncidentQuery) -> Result<Vec<Incident>, Error> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .filter(|incident| query.matches(incident))
+            .collect())
+    }
+}
+
+/// Filtros para consultar el historial de incidentes. Sin filtros, incluye a todos.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct IncidentQuery {
+    created_from: Option<u64>,
+    created_until: Option<u64>,
+    area: Option<(GeoPosition, GeoPosition)>,
+    state: Option<IncidentState>,
+}
+
+impl IncidentQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Incluye solamente a los incidentes creados desde `from` (inclusive) hasta `until` (exclusive), en
+    /// milisegundos desde el epoch.
+    pub fn with_created_between(mut self, from: Option<u64>, until: Option<u64>) -> Self {
+        self.created_from = from;
+        self.created_until = until;
+        self
+    }
+
+    /// Incluye solamente a los incidentes en el rectángulo entre las posiciones `min` y `max`.
+    pub fn with_area(mut self, min: GeoPosition, max: GeoPosition) -> Self {
+        self.area = Some((min, max));
+        self
+    }
+
+    /// Incluye solamente a los incidentes en el estado `state`.
+    pub fn with_state(mut self, state: IncidentState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Devuelve si `incident` cumple con todos los filtros.
+    pub fn matches(&self, incident: &Incident) -> bool {
+        let created_at = incident.get_created_at();
+        let position = incident.get_position();
+        self.created_from.is_none_or(|from| created_at >= from)
+            && self.created_until.is_none_or(|until| created_at < until)
+            && self.area.is_none_or(|(min, max)| {
+                (min.get_latitude()..=max.get_latitude()).contains(&position.get_latitude())
+                    && (min.get_longitude()..=max.get_longitude()).contains(&position.get_longitude())
+            })
+            && self.state.is_none_or(|state| *incident.get_state() == state)
+    }
+}
+
+/// Convierte los incidentes a csv, con una fila de encabezado. Los instantes van en hora local, y vacíos si no se
+/// conocen.
+pub fn incidents_to_csv(incidents: &[Incident]) -> String {
+    let mut csv = String::from(
+        "id,origen,latitud,longitud,estado,prioridad,gravedad,tipo,creado,resuelto,descripcion\n",
+    );
+    for incident in incidents {
+        let position = incident.get_position();
+        csv.push_str(&format!(
+            "{},{:?},{},{},{},{},{},{},{},{},{}\n",
+            incident.get_id(),
+            incident.get_source(),
+            position.get_latitude(),
+            position.get_longitude(),
+            incident.get_state().to_str(),
+            incident.get_priority().to_str(),
+            incident.get_severity().to_str(),
+            incident.get_category().to_str(),
+            format_csv_millis(incident.get_created_at()),
+            incident.get_resolved_at().map(format_csv_millis).unwrap_or_default(),
+            escape_csv(incident.get_description())
+        ));
+    }
+    csv
+}
+
+fn format_csv_millis(millis: u64) -> String {
+    DateTime::from_timestamp_millis(millis as i64)
+        .filter(|_| millis > 0)
+        .map(|at| at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Encierra el campo entre comillas si tiene comas, comillas o saltos de línea, duplicando las comillas.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{incidents_to_csv, IncidentHistoryStore, IncidentQuery};
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident::Incident, incident_source::IncidentSource, incident_state::IncidentState},
+    };
+
+    fn position(latitude: f64, longitude: f64) -> GeoPosition {
+        GeoPosition::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_1_el_historial_guarda_el_ultimo_estado_y_se_consulta_por_estado_y_zona() {
+        let path = std::env::temp_dir().join(format!("rustx_incident_history_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = IncidentHistoryStore::new(path.to_str().unwrap());
+        assert!(store.load().unwrap().is_empty());
+
+        let mut centro = Incident::new(1, position(-34.60, -58.38), IncidentSource::Manual)
+            .with_description("Choque, con \"heridos\"");
+        let lejos = Incident::new(2, position(-34.70, -58.50), IncidentSource::Automated);
+        store.record(&centro).unwrap();
+        store.record(&lejos).unwrap();
+        centro.transition_to(IncidentState::Cancelled).unwrap();
+        store.record(&centro).unwrap();
+        // Una línea cortada a mitad se ignora.
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "{\"id\":3,").unwrap();
+
+        let incidents = store.load().unwrap();
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(*incidents[0].get_state(), IncidentState::Cancelled);
+        assert_eq!(incidents[0].get_history().len(), 2);
+
+        let cancelled = store.query(&IncidentQuery::new().with_state(IncidentState::Cancelled)).unwrap();
+        assert_eq!(cancelled.len(), 1);
+        let in_area = IncidentQuery::new().with_area(position(-34.65, -58.40), position(-34.55, -58.35));
+        let found = store.query(&in_area).unwrap();
+        assert_eq!(found.iter().map(Incident::get_id).collect::<Vec<u8>>(), vec![1]);
+        let created_after = IncidentQuery::new().with_created_between(Some(lejos.get_created_at() + 1), None);
+        assert!(store.query(&created_after).unwrap().is_empty());
+
+        let csv = incidents_to_csv(&found);
+        assert!(csv.starts_with("id,origen,"));
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,Manual,-34.6,-58.38,Cancelado,"));
+        assert!(csv.contains(",\"Choque, con \"\"heridos\"\"\"\n"));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs;
+
+use chrono::{Days, Local, NaiveDate};
+
+use crate::apps::{
+    geo_position::GeoPosition,
+    incident_data::{incident::Incident, incident_state::IncidentState},
+};
+
+use super::incident_history::{incidents_to_csv, IncidentHistoryStore, IncidentQuery};
+
+const DATE_FORMAT: &str = "%d/%m/%Y";
+const DEFAULT_CSV_PATH: &str = "incidentes.csv";
+
+/// Ventana de la ui para consultar el historial de incidentes, filtrando por fecha de creación, zona y estado, y
+/// exportar el resultado a csv.
+#[derive(Debug)]
+pub struct IncidentHistoryWindow {
+    open: bool,
+    from: String,  // dd/mm/aaaa
+    until: String, // dd/mm/aaaa, inclusive
+    area: String,  // lat_min,lon_min,lat_max,lon_max
+    state: Option<IncidentState>,
+    csv_path: String,
+    results: Vec<Incident>,
+    message: Option<String>,
+}
+
+impl IncidentHistoryWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            from: String::new(),
+            until: String::new(),
+            area: String::new(),
+            state: None,
+            csv_path: DEFAULT_CSV_PATH.to_string(),
+            results: vec![],
+            message: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Muestra la ventana, si está abierta. Si no se configuró dónde se guarda el historial, lo avisa.
+    pub fn show(&mut self, ctx: &egui::Context, store: Option<&IncidentHistoryStore>) {
+        if !self.open {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Historial de incidentes")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some(store) = store else {
+                    ui.label("El historial no se guarda: falta la property incident-history-file.");
+                    return;
+                };
+                self.filter_inputs(ui);
+                ui.horizontal(|ui| {
+                    if ui.button("Buscar").clicked() {
+                        self.search(store);
+                    }
+                    ui.add_sized([150.0, 20.0], egui::TextEdit::singleline(&mut self.csv_path));
+                    if ui.button("Exportar CSV").clicked() {
+                        self.export();
+                    }
+                });
+                if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for incident in &self.results {
+                        ui.label(format!(
+                            "Incidente {} ({:?}) en {}: {}, {}",
+                            incident.get_id(),
+                            incident.get_source(),
+                            incident.get_position(),
+                            incident.get_category().to_str(),
+                            incident.get_state().to_str()
+                        ));
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn filter_inputs(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Desde:");
+            ui.add_sized([80.0, 20.0], egui::TextEdit::singleline(&mut self.from));
+            ui.label("Hasta:");
+            ui.add_sized([80.0, 20.0], egui::TextEdit::singleline(&mut self.until));
+            ui.label("(dd/mm/aaaa)");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Zona:");
+            ui.add_sized([250.0, 20.0], egui::TextEdit::singleline(&mut self.area));
+            ui.label("(lat_min,lon_min,lat_max,lon_max)");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Estado:");
+            ui.selectable_value(&mut self.state, None, "Todos");
+            for state in [
+                IncidentState::Reported,
+                IncidentState::Assigned,
+                IncidentState::InAttention,
+                IncidentState::Resolved,
+                IncidentState::Cancelled,
+            ] {
+                ui.selectable_value(&mut self.state, Some(state), state.to_str());
+            }
+        });
+    }
+
+    fn search(&mut self, store: &IncidentHistoryStore) {
+        let result = parse_query(&self.from, &self.until, &self.area, self.state)
+            .and_then(|query| store.query(&query).map_err(|e| format!("Error al leer el historial: {}", e)));
+        match result {
+            Ok(incidents) => {
+                self.message = Some(format!("{} incidentes encontrados.", incidents.len()));
+                self.results = incidents;
+            }
+            Err(e) => self.message = Some(e),
+        }
+    }
+
+    fn export(&mut self) {
+        let path = self.csv_path.trim();
+        self.message = Some(match fs::write(path, incidents_to_csv(&self.results)) {
+            Ok(_) => format!("Exportados {} incidentes a {}.", self.results.len(), path),
+            Err(e) => format!("Error al exportar a {}: {}", path, e),
+        });
+    }
+}
+
+impl Default for IncidentHistoryWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Arma la consulta a partir de los filtros ingresados; los vacíos no filtran. Las fechas se toman en hora local, y
+/// la de fin incluye a todo ese día.
+pub fn parse_query(
+    from: &str,
+    until: &str,
+    area: &str,
+    state: Option<IncidentState>,
+) -> Result<IncidentQuery, String> {
+    let from = parse_day_start(from, 0)?;
+    let until = parse_day_start(until, 1)?;
+    let mut query = IncidentQuery::new().with_created_between(from, until);
+    if !area.trim().is_empty() {
+        let coordinates: Vec<f64> = area
+            .split(',')
+            .map(|number| number.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Zona inválida: {}.", area))?;
+        let [lat_min, lon_min, lat_max, lon_max] = coordinates[..] else {
+            return Err(format!("Zona inválida: {}.", area));
+        };
+        let min = GeoPosition::new(lat_min, lon_min).map_err(|e| e.to_string())?;
+        let max = GeoPosition::new(lat_max, lon_max).map_err(|e| e.to_string())?;
+        query = query.with_area(min, max);
+    }
+    if let Some(state) = state {
+        query = query.with_state(state);
+    }
+    Ok(query)
+}
+
+/// Devuelve el comienzo, en milisegundos desde el epoch, del día `days_after` días después de la fecha `date`, o
+/// `None` si está vacía.
+fn parse_day_start(date: &str, days_after: u64) -> Result<Option<u64>, String> {
+    if date.trim().is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(date.trim(), DATE_FORMAT)
+        .ok()
+        .and_then(|day| day.checked_add_days(Days::new(days_after)))
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|start| start.and_local_timezone(Local).earliest())
+        .map(|start| Some(start.timestamp_millis().max(0) as u64))
+        .ok_or_else(|| format!("Fecha inválida: {}.", date))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_query;
+    use crate::apps::{
+        geo_position::GeoPosition,
+        incident_data::{incident::Incident, incident_source::IncidentSource, incident_state::IncidentState},
+    };
+
+    #[test]
+    fn test_1_los_filtros_ingresados_se_convierten_en_la_consulta() {
+        let incident = Incident::new(1, GeoPosition::new(-34.6, -58.4).unwrap(), IncidentSource::Manual);
+        assert!(parse_query("", "", "", None).unwrap().matches(&incident));
+        assert!(parse_query("", "", "-34.7,-58.5,-34.5,-58.3", Some(IncidentState::Reported))
+            .unwrap()
+            .matches(&incident));
+        assert!(!parse_query("", "", "", Some(IncidentState::Resolved)).unwrap().matches(&incident));
+        // Se creó hoy, antes de que termine el día y después de que empiece.
+        let today = chrono::Local::now().format("%d/%m/%Y").to_string();
+        assert!(parse_query(&today, &today, "", None).unwrap().matches(&incident));
+        assert!(!parse_query("01/01/2000", "31/12/2000", "", None).unwrap().matches(&incident));
+
+        assert!(parse_query("2000-01-01", "", "", None).is_err());
+        assert!(parse_query("", "", "-34.7,-58.5", None).is_err());
+        assert!(parse_query("", "", "-94.7,-58.5,-34.5,-58.3", None).is_err());
+    }
+}
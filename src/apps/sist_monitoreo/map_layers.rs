@@ -0,0 +1,138 @@
+use crate::apps::{incident_data::incident_state::IncidentState, place_type::PlaceType};
+
+/// Nivel de batería, en porcentaje, hasta el cual se considera que un dron tiene batería baja.
+pub const LOW_BATTERY_LVL: u8 = 20;
+/// Cantidad máxima de días hacia atrás que puede abarcar el mapa de calor.
+const MAX_HEATMAP_DAYS: u32 = 365;
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Qué capas del mapa se muestran, y qué filtros se aplican a los elementos, para que el mapa siga siendo legible
+/// cuando hay muchos drones, cámaras e incidentes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapLayers {
+    pub drones: bool,
+    pub cameras: bool,
+    pub incidents: bool,
+    pub trails: bool,
+    pub coverage: bool,
+    pub only_incidents_in_progress: bool, // solamente los asignados o en atención
+    pub only_low_battery_drones: bool,
+    pub heatmap: bool, // mapa de calor de los incidentes del historial
+    pub heatmap_days: u32, // cuántos días hacia atrás abarca el mapa de calor
+}
+
+impl MapLayers {
+    pub fn new() -> Self {
+        Self {
+            drones: true,
+            cameras: true,
+            incidents: true,
+            trails: true,
+            coverage: true,
+            only_incidents_in_progress: false,
+            only_low_battery_drones: false,
+            heatmap: false,
+            heatmap_days: 30,
+        }
+    }
+
+    /// Devuelve si se muestra un marcador de tipo `place_type`. Para los incidentes, `incident_state` es su estado.
+    pub fn shows_place(&self, place_type: &PlaceType, incident_state: Option<IncidentState>) -> bool {
+        match place_type {
+            PlaceType::Camera => self.cameras,
+            PlaceType::Dron => self.drones,
+            PlaceType::ManualIncident | PlaceType::AutomatedIncident => {
+                self.incidents
+                    && (!self.only_incidents_in_progress
+                        || matches!(incident_state, Some(IncidentState::Assigned | IncidentState::InAttention)))
+            }
+            PlaceType::Mantainance => true,
+        }
+    }
+
+    /// Devuelve si se muestra un dron con nivel de batería `battery_lvl`.
+    pub fn shows_dron(&self, battery_lvl: u8) -> bool {
+        self.drones && (!self.only_low_battery_drones || battery_lvl <= LOW_BATTERY_LVL)
+    }
+
+    /// Devuelve desde cuándo, en milisegundos desde el epoch, se incluyen los incidentes en el mapa de calor, si
+    /// ahora es `now`.
+    pub fn heatmap_from(&self, now: u64) -> u64 {
+        now.saturating_sub(u64::from(self.heatmap_days) * MILLIS_PER_DAY)
+    }
+
+    /// Muestra las casillas para elegir las capas y los filtros. El mapa de calor se puede elegir solamente si
+    /// `heatmap_available` (es decir, si se guarda el historial de incidentes). Devuelve si hay que volver a
+    /// calcular el mapa de calor, porque se lo activó, cambió su período, o se pidió actualizarlo.
+    pub fn show_menu(&mut self, ui: &mut egui::Ui, heatmap_available: bool) -> bool {
+        ui.checkbox(&mut self.drones, "Drones");
+        ui.checkbox(&mut self.cameras, "Cámaras");
+        ui.checkbox(&mut self.incidents, "Incidentes");
+        ui.checkbox(&mut self.trails, "Recorridos");
+        ui.checkbox(&mut self.coverage, "Cobertura");
+        ui.separator();
+        ui.checkbox(&mut self.only_incidents_in_progress, "Solo incidentes en curso");
+        ui.checkbox(
+            &mut self.only_low_battery_drones,
+            format!("Solo drones con batería baja (hasta {}%)", LOW_BATTERY_LVL),
+        );
+        ui.separator();
+        ui.add_enabled_ui(heatmap_available, |ui| {
+            let mut changed = ui.checkbox(&mut self.heatmap, "Mapa de calor de incidentes").changed() && self.heatmap;
+            ui.add_enabled_ui(self.heatmap, |ui| {
+                let days = egui::Slider::new(&mut self.heatmap_days, 1..=MAX_HEATMAP_DAYS).text("días");
+                changed |= ui.add(days).changed();
+                changed |= ui.button("Actualizar").clicked();
+            });
+            changed
+        })
+        .inner
+    }
+}
+
+impl Default for MapLayers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MapLayers, LOW_BATTERY_LVL, MILLIS_PER_DAY};
+    use crate::apps::{incident_data::incident_state::IncidentState, place_type::PlaceType};
+
+    #[test]
+    fn test_1_por_defecto_se_muestra_todo() {
+        let layers = MapLayers::new();
+        assert!(layers.shows_place(&PlaceType::Camera, None));
+        assert!(layers.shows_place(&PlaceType::ManualIncident, Some(IncidentState::Reported)));
+        assert!(layers.shows_dron(100));
+    }
+
+    #[test]
+    fn test_2_las_capas_ocultas_no_se_muestran() {
+        let layers = MapLayers { cameras: false, incidents: false, drones: false, ..MapLayers::new() };
+        assert!(!layers.shows_place(&PlaceType::Camera, None));
+        assert!(!layers.shows_place(&PlaceType::AutomatedIncident, Some(IncidentState::Assigned)));
+        assert!(!layers.shows_dron(10));
+        assert!(layers.shows_place(&PlaceType::Mantainance, None));
+    }
+
+    #[test]
+    fn test_3_los_filtros_dejan_solo_los_incidentes_en_curso_y_los_drones_con_bateria_baja() {
+        let layers = MapLayers { only_incidents_in_progress: true, only_low_battery_drones: true, ..MapLayers::new() };
+        assert!(!layers.shows_place(&PlaceType::ManualIncident, Some(IncidentState::Reported)));
+        assert!(layers.shows_place(&PlaceType::ManualIncident, Some(IncidentState::Assigned)));
+        assert!(layers.shows_place(&PlaceType::ManualIncident, Some(IncidentState::InAttention)));
+        assert!(layers.shows_dron(LOW_BATTERY_LVL));
+        assert!(!layers.shows_dron(LOW_BATTERY_LVL + 1));
+    }
+
+    #[test]
+    fn test_4_el_mapa_de_calor_abarca_los_dias_elegidos() {
+        let layers = MapLayers { heatmap_days: 2, ..MapLayers::new() };
+        let now = 10 * MILLIS_PER_DAY;
+        assert_eq!(layers.heatmap_from(now), 8 * MILLIS_PER_DAY);
+        assert_eq!(layers.heatmap_from(MILLIS_PER_DAY), 0);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod dron_registry;
+pub mod dron_watchdog;
+pub mod entity_inspector;
+pub mod incident_history;
+pub mod incident_history_window;
+pub mod map_layers;
 pub mod monitoreo_errors;
+pub mod operator_login;
 pub mod order_checker;
+pub mod resolution_mode;
+pub mod session_log;
+pub mod session_replay;
 pub mod sist_monit_ui_properties;
 pub mod sistema_monitoreo;
+pub mod topic_feed;
+pub mod ui_event;
 pub mod ui_sistema_monitoreo; //
\ No newline at end of file
@@ -0,0 +1,217 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::Arc,
+};
+
+use crate::{apps::properties::Properties, mqtt::server::credentials_store::CredentialsStore};
+
+/// Qué puede hacer en monitoreo quien inició sesión. Cada rol puede hacer todo lo que hacen los anteriores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OperatorRole {
+    Viewer,   // solamente observa el mapa
+    Operator, // además da de alta, resuelve y cancela incidentes
+    Admin,    // además envía comandos de control a los drones
+}
+
+impl OperatorRole {
+    /// Interpreta el rol indicado en el archivo de credenciales.
+    pub fn from_name(role: &str) -> Result<Self, Error> {
+        match role {
+            "viewer" => Ok(OperatorRole::Viewer),
+            "operator" => Ok(OperatorRole::Operator),
+            "admin" => Ok(OperatorRole::Admin),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Rol inválido: {:?}. Debe ser viewer, operator o admin.", role),
+            )),
+        }
+    }
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            OperatorRole::Viewer => "observador",
+            OperatorRole::Operator => "operador",
+            OperatorRole::Admin => "administrador",
+        }
+    }
+
+    /// Devuelve si puede dar de alta, resolver y cancelar incidentes.
+    pub fn can_manage_incidents(&self) -> bool {
+        *self >= OperatorRole::Operator
+    }
+
+    /// Devuelve si puede enviar comandos de control a los drones.
+    pub fn can_command_drones(&self) -> bool {
+        *self >= OperatorRole::Admin
+    }
+}
+
+/// Usuario que inició sesión en monitoreo, con su rol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operator {
+    user: String,
+    role: OperatorRole,
+}
+
+impl Operator {
+    pub fn new(user: &str, role: OperatorRole) -> Self {
+        Self {
+            user: user.to_string(),
+            role,
+        }
+    }
+
+    pub fn get_user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn get_role(&self) -> OperatorRole {
+        self.role
+    }
+}
+
+/// Verifica los usuarios que inician sesión en monitoreo contra el mismo archivo de credenciales que usa el broker,
+/// del que toma además el rol de cada uno (la cuarta columna; si no la tiene, es observador).
+#[derive(Debug, Clone)]
+pub struct OperatorLogin {
+    credentials: Arc<CredentialsStore>,
+}
+
+impl OperatorLogin {
+    pub fn new(credentials: CredentialsStore) -> Self {
+        Self {
+            credentials: Arc::new(credentials),
+        }
+    }
+
+    /// Carga el archivo de credenciales indicado por la property `credentials-file` de `properties_file`. Si el
+    /// archivo de properties no existe o no la define, monitoreo no pide iniciar sesión.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        match properties.get("credentials-file").map(|path| path.trim()) {
+            Some("") => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Valor inválido para credentials-file.",
+            )),
+            Some(path) => Ok(Some(Self::new(CredentialsStore::from_file(path)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Inicia la sesión de `user` con la contraseña `passwd`. Devuelve error si las credenciales no son correctas,
+    /// o si el rol del usuario no es válido.
+    pub fn login(&self, user: &str, passwd: &str) -> Result<Operator, Error> {
+        if !self.credentials.verify(user, passwd) {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Usuario o contraseña incorrectos.",
+            ));
+        }
+        let role = match self.credentials.get_role(user) {
+            Some(role) => OperatorRole::from_name(role)?,
+            None => OperatorRole::Viewer,
+        };
+        Ok(Operator::new(user, role))
+    }
+}
+
+/// Pantalla de inicio de sesión, que se muestra en lugar del mapa hasta que el usuario ingresa credenciales válidas.
+#[derive(Debug, Default)]
+pub struct LoginForm {
+    user: String,
+    passwd: String,
+    error: Option<String>,
+}
+
+impl LoginForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Muestra el formulario, y devuelve el operador si en este frame inició sesión con credenciales válidas según
+    /// `login`.
+    pub fn show(&mut self, ctx: &egui::Context, login: &OperatorLogin) -> Option<Operator> {
+        let mut submitted = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 4.0);
+                ui.heading("Sistema Monitoreo");
+                ui.label("Iniciar sesión");
+                ui.add_space(10.0);
+                egui::Grid::new("login_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Usuario:");
+                    ui.text_edit_singleline(&mut self.user);
+                    ui.end_row();
+                    ui.label("Contraseña:");
+                    let passwd = ui.add(egui::TextEdit::singleline(&mut self.passwd).password(true));
+                    submitted = passwd.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+                });
+                submitted |= ui.button("Ingresar").clicked();
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+        });
+        if !submitted {
+            return None;
+        }
+        let result = login.login(self.user.trim(), &self.passwd);
+        self.passwd.clear();
+        match result {
+            Ok(operator) => {
+                self.error = None;
+                Some(operator)
+            }
+            Err(e) => {
+                self.error = Some(e.to_string());
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OperatorLogin, OperatorRole};
+    use crate::mqtt::server::credentials_store::{hash_password, CredentialsStore};
+    use std::fs;
+
+    fn create_login() -> OperatorLogin {
+        let path = std::env::temp_dir().join(format!("rustx_operator_login_{}.txt", std::process::id()));
+        let lines = [
+            format!("observador {} Sistema-Monitoreo", hash_password("clave").unwrap()),
+            format!("operador {} Sistema-Monitoreo operator", hash_password("clave").unwrap()),
+            format!("admin {} * admin", hash_password("clave").unwrap()),
+            format!("otro {} * jefe", hash_password("clave").unwrap()),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        let store = CredentialsStore::from_file(path.to_str().unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        OperatorLogin::new(store)
+    }
+
+    #[test]
+    fn test_1_el_rol_se_toma_del_archivo_de_credenciales() {
+        let login = create_login();
+        assert_eq!(login.login("observador", "clave").unwrap().get_role(), OperatorRole::Viewer);
+        assert_eq!(login.login("operador", "clave").unwrap().get_role(), OperatorRole::Operator);
+        assert_eq!(login.login("admin", "clave").unwrap().get_role(), OperatorRole::Admin);
+        // Credenciales incorrectas, o un rol desconocido, no inician sesión.
+        assert!(login.login("admin", "otra").is_err());
+        assert!(login.login("nadie", "clave").is_err());
+        assert!(login.login("otro", "clave").is_err());
+    }
+
+    #[test]
+    fn test_2_cada_rol_puede_hacer_lo_de_los_anteriores() {
+        assert!(!OperatorRole::Viewer.can_manage_incidents());
+        assert!(!OperatorRole::Viewer.can_command_drones());
+        assert!(OperatorRole::Operator.can_manage_incidents());
+        assert!(!OperatorRole::Operator.can_command_drones());
+        assert!(OperatorRole::Admin.can_manage_incidents());
+        assert!(OperatorRole::Admin.can_command_drones());
+    }
+}
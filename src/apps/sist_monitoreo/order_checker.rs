@@ -2,7 +2,7 @@ use std::{collections::HashMap, io::Error};
 
 use crate::{
     apps::{
-        apps_mqtt_topics::AppsMqttTopics, sist_camaras::camera::Camera,
+        apps_mqtt_topics::AppsMqttTopics, payload_codec::decode_payload, sist_camaras::camera::Camera,
         sist_dron::dron_current_info::DronCurrentInfo,
     },
     mqtt::messages::publish_message::PublishMessage,
@@ -30,12 +30,12 @@ impl OrderChecker {
 
         match AppsMqttTopics::topic_from_str(&msg_topic)? {
             AppsMqttTopics::DronTopic => {
-                let current_info = DronCurrentInfo::from_bytes(payload)?;
+                let current_info: DronCurrentInfo = decode_payload(&payload)?;
                 let id: u8 = current_info.get_id();
                 self.update_timestamp_if_newest(msg_topic, id, recvd_timestamp)
             }
             AppsMqttTopics::CameraTopic => {
-                let camera = Camera::from_bytes(&payload);
+                let camera: Camera = decode_payload(&payload)?;
                 let id: u8 = camera.get_id();
                 self.update_timestamp_if_newest(msg_topic, id, recvd_timestamp)
             }
@@ -0,0 +1,56 @@
+use std::io::{Error, ErrorKind};
+
+/// Cómo decide monitoreo que un incidente está resuelto.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ResolutionMode {
+    /// Lo resuelve apenas llegan a él los drones que lo atienden.
+    #[default]
+    OnArrival,
+    /// Al recibir por el topic `inc-attended` que los drones permanecieron en él, le pide al operador que lo marque
+    /// como resuelto.
+    OperatorConfirmation,
+    /// Al recibir por el topic `inc-attended` que los drones permanecieron en él, lo resuelve sin intervención del
+    /// operador. Pensado para simulaciones.
+    Simulation,
+}
+
+impl ResolutionMode {
+    /// Obtiene el modo a partir del argumento con que se corre monitoreo: `arrival`, `operator` o `simulation`.
+    pub fn from_arg(arg: &str) -> Result<Self, Error> {
+        match arg {
+            "arrival" => Ok(ResolutionMode::OnArrival),
+            "operator" => Ok(ResolutionMode::OperatorConfirmation),
+            "simulation" => Ok(ResolutionMode::Simulation),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Modo de resolución inválido: {}. Debe ser arrival, operator o simulation.",
+                    arg
+                ),
+            )),
+        }
+    }
+
+    /// Devuelve si los incidentes se resuelven al recibir el aviso de que fueron atendidos.
+    pub fn waits_for_attended(&self) -> bool {
+        *self != ResolutionMode::OnArrival
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResolutionMode;
+
+    #[test]
+    fn test_1_el_modo_se_obtiene_del_argumento() {
+        assert_eq!(
+            ResolutionMode::from_arg("operator").unwrap(),
+            ResolutionMode::OperatorConfirmation
+        );
+        assert!(ResolutionMode::from_arg("simulation")
+            .unwrap()
+            .waits_for_attended());
+        assert!(!ResolutionMode::default().waits_for_attended());
+        assert!(ResolutionMode::from_arg("manual").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::apps::properties::Properties;
+
+/// Silencio, en milisegundos, a partir del cual se considera que dos mensajes son de sesiones distintas (ie entre
+/// ellos se cerró monitoreo, o se cortó la conexión con el broker).
+pub const SESSION_GAP_MILLIS: u64 = 5 * 60 * 1000;
+
+/// Mensaje recibido por monitoreo, tal como se guarda en el registro de la sesión.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LoggedMessage {
+    at: u64, // en milisegundos desde el epoch
+    topic: String,
+    payload: Vec<u8>,
+}
+
+impl LoggedMessage {
+    pub fn new(at: u64, topic: &str, payload: Vec<u8>) -> Self {
+        Self {
+            at,
+            topic: topic.to_string(),
+            payload,
+        }
+    }
+
+    pub fn get_at(&self) -> u64 {
+        self.at
+    }
+
+    pub fn get_topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// Guarda en disco cada mensaje que recibe monitoreo, para poder reproducir después lo que pasó en el mapa durante
+/// una sesión. Cada mensaje agrega una línea en json al final del archivo.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SessionLog {
+    path: PathBuf,
+}
+
+impl SessionLog {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+
+    /// Carga la ruta del archivo de registro de la property `session-log-file` de `properties_file`. Si el archivo
+    /// de properties no existe o no la define, no se registran los mensajes.
+    pub fn from_file(properties_file: &str) -> Result<Option<Self>, Error> {
+        let Ok(properties) = Properties::new(properties_file) else {
+            return Ok(None);
+        };
+        match properties.get("session-log-file").map(|path| path.trim()) {
+            Some("") => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Valor inválido para session-log-file.",
+            )),
+            Some(path) => Ok(Some(Self::new(path))),
+            None => Ok(None),
+        }
+    }
+
+    /// Agrega `message` al registro.
+    pub fn record(&self, message: &LoggedMessage) -> Result<(), Error> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// Devuelve todos los mensajes registrados, ordenados por el instante en que se recibieron. Las líneas que no se
+    /// pueden leer se ignoran.
+    pub fn load(&self) -> Result<Vec<LoggedMessage>, Error> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut messages: Vec<LoggedMessage> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        messages.sort_by_key(LoggedMessage::get_at);
+        Ok(messages)
+    }
+
+    /// Devuelve los mensajes registrados, separados en sesiones: entre el último mensaje de una y el primero de la
+    /// siguiente pasaron más de `SESSION_GAP_MILLIS`.
+    pub fn load_sessions(&self) -> Result<Vec<Vec<LoggedMessage>>, Error> {
+        Ok(split_sessions(self.load()?, SESSION_GAP_MILLIS))
+    }
+}
+
+/// Separa los `messages`, ordenados por instante, en sesiones: empieza una nueva cada vez que entre dos mensajes
+/// consecutivos pasaron más de `max_gap` milisegundos.
+pub fn split_sessions(messages: Vec<LoggedMessage>, max_gap: u64) -> Vec<Vec<LoggedMessage>> {
+    let mut sessions: Vec<Vec<LoggedMessage>> = vec![];
+    for message in messages {
+        match sessions.last_mut() {
+            Some(session) if session.last().is_some_and(|last| message.at - last.at <= max_gap) => {
+                session.push(message)
+            }
+            _ => sessions.push(vec![message]),
+        }
+    }
+    sessions
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{split_sessions, LoggedMessage, SessionLog};
+
+    #[test]
+    fn test_1_el_registro_devuelve_los_mensajes_ordenados() {
+        let path = std::env::temp_dir().join(format!("rustx_session_log_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let log = SessionLog::new(path.to_str().unwrap());
+        assert!(log.load().unwrap().is_empty());
+
+        log.record(&LoggedMessage::new(20, "dron", vec![1, 2])).unwrap();
+        log.record(&LoggedMessage::new(10, "cam", vec![3])).unwrap();
+        // Una línea cortada a mitad se ignora.
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "{\"at\":30,").unwrap();
+
+        let messages = log.load().unwrap();
+        assert_eq!(messages, vec![LoggedMessage::new(10, "cam", vec![3]), LoggedMessage::new(20, "dron", vec![1, 2])]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_2_los_silencios_largos_separan_las_sesiones() {
+        let messages = [0, 5, 10, 100, 104].map(|at| LoggedMessage::new(at, "dron", vec![])).to_vec();
+        let sessions = split_sessions(messages, 5);
+        let ats: Vec<Vec<u64>> = sessions
+            .iter()
+            .map(|session| session.iter().map(LoggedMessage::get_at).collect())
+            .collect();
+        assert_eq!(ats, vec![vec![0, 5, 10], vec![100, 104]]);
+        assert!(split_sessions(vec![], 5).is_empty());
+    }
+}
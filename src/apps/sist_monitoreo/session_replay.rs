@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::apps::{
+    incident_data::{incident::Incident, incident_info::IncidentInfo, incident_state::IncidentState},
+    place_type::PlaceType,
+    plugins::{CameraCoverage, DronMarkers, DronTrails},
+    sist_camaras::camera::Camera,
+    sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState},
+    vendor::Places,
+};
+
+use super::{
+    dron_registry::DronRegistry,
+    session_log::LoggedMessage,
+    ui_event::UiEvent,
+    ui_sistema_monitoreo::{format_millis, map_position, UISistemaMonitoreo},
+};
+
+/// Velocidades a las que se puede reproducir una sesión.
+const SPEEDS: [f32; 6] = [0.5, 1., 2., 5., 10., 30.];
+
+/// Reproducción de una sesión pasada de monitoreo, para revisar qué pasó durante un incidente. Vuelve a aplicar, en
+/// orden y al ritmo elegido, los mensajes de drones y cámaras que se registraron, y muestra los incidentes del
+/// historial en el estado que tenían en cada momento. Tiene sus propios marcadores, por lo que no modifica lo que
+/// monitoreo sigue recibiendo mientras tanto.
+pub struct SessionReplay {
+    messages: Vec<LoggedMessage>,
+    incidents: Vec<Incident>,
+    start: u64, // en milisegundos desde el epoch, como `end` y `current`
+    end: u64,
+    current: u64,
+    next: usize, // índice del próximo mensaje a aplicar
+    playing: bool,
+    speed: f32,
+    last_tick: Option<Instant>,
+    dron_registry: DronRegistry,
+    dron_markers: DronMarkers,
+    dron_trails: DronTrails,
+    camera_coverage: CameraCoverage,
+    cameras: HashMap<u8, Camera>,
+}
+
+impl SessionReplay {
+    /// Crea la reproducción de la sesión con los `messages` recibidos, ordenados por instante, y los `incidents` del
+    /// historial. Devuelve `None` si la sesión no tiene mensajes.
+    pub fn new(messages: Vec<LoggedMessage>, mut incidents: Vec<Incident>, dron_registry: DronRegistry) -> Option<Self> {
+        let start = messages.first()?.get_at();
+        let end = messages.last()?.get_at();
+        incidents.retain(|incident| incident.get_created_at() <= end);
+        let mut replay = Self {
+            messages,
+            incidents,
+            start,
+            end,
+            current: start,
+            next: 0,
+            playing: false,
+            speed: 1.,
+            last_tick: None,
+            dron_registry,
+            dron_markers: DronMarkers::default(),
+            dron_trails: DronTrails::default(),
+            camera_coverage: CameraCoverage::default(),
+            cameras: HashMap::new(),
+        };
+        replay.seek(start);
+        Some(replay)
+    }
+
+    /// Avanza la reproducción lo que pasó desde el tick anterior, si se está reproduciendo.
+    pub fn tick(&mut self, now: Instant) {
+        let elapsed = self.last_tick.map_or(Duration::ZERO, |last_tick| now - last_tick);
+        self.last_tick = Some(now);
+        self.advance(elapsed);
+    }
+
+    /// Avanza la reproducción `elapsed`, multiplicado por la velocidad elegida. Al llegar al final, se pausa.
+    pub fn advance(&mut self, elapsed: Duration) {
+        if !self.playing {
+            return;
+        }
+        let step = (elapsed.as_millis() as f32 * self.speed) as u64;
+        self.seek(self.current + step);
+        if self.current == self.end {
+            self.playing = false;
+        }
+    }
+
+    /// Lleva la reproducción al instante `at`. Si es anterior al actual, vuelve a aplicar los mensajes desde el
+    /// principio de la sesión.
+    pub fn seek(&mut self, at: u64) {
+        let at = at.clamp(self.start, self.end);
+        if at < self.current {
+            self.next = 0;
+            self.dron_markers = DronMarkers::default();
+            self.dron_trails = DronTrails::default();
+            self.camera_coverage.clear();
+            self.cameras.clear();
+        }
+        while self.next < self.messages.len() && self.messages[self.next].get_at() <= at {
+            let message = self.messages[self.next].clone();
+            self.apply(&message);
+            self.next += 1;
+        }
+        self.current = at;
+    }
+
+    /// Reproduce, o pausa. Si ya había llegado al final, vuelve a empezar.
+    pub fn toggle_play(&mut self) {
+        if !self.playing && self.current == self.end {
+            self.seek(self.start);
+        }
+        self.playing = !self.playing;
+    }
+
+    fn apply(&mut self, message: &LoggedMessage) {
+        match UiEvent::decode(message.get_topic(), message.get_payload()) {
+            Ok(UiEvent::Dron(dron)) => self.update_dron(&dron),
+            Ok(UiEvent::DronHistory(batch)) => {
+                for dron in batch.get_samples() {
+                    self.update_dron(dron);
+                }
+            }
+            Ok(UiEvent::Camera(camera)) => self.update_camera(camera),
+            Ok(UiEvent::CamerasSnapshot(snapshot)) => {
+                if snapshot.is_full() {
+                    self.cameras.clear();
+                    self.camera_coverage.clear();
+                }
+                for change in snapshot.get_cameras() {
+                    self.update_camera(change.get_camera().clone());
+                }
+            }
+            // Los incidentes se toman del historial, que tiene también los cambios de estado que hizo el operador.
+            _ => {}
+        }
+    }
+
+    fn update_dron(&mut self, dron: &DronCurrentInfo) {
+        let dron_id = dron.get_id();
+        if dron.get_state() == DronState::Offline {
+            self.dron_markers.remove(dron_id);
+            self.dron_trails.remove(dron_id);
+            return;
+        }
+        let position = map_position(dron.get_current_position());
+        self.dron_trails.add_position(dron_id, position);
+        self.dron_markers.update(dron, position, self.dron_registry.label(dron_id));
+    }
+
+    fn update_camera(&mut self, camera: Camera) {
+        self.camera_coverage.update(&camera);
+        if camera.is_not_deleted() {
+            self.cameras.insert(camera.get_id(), camera);
+        } else {
+            self.cameras.remove(&camera.get_id());
+        }
+    }
+
+    /// Devuelve los incidentes que estaban abiertos en el instante reproducido, con el estado que tenían.
+    pub fn open_incidents(&self) -> Vec<(&Incident, IncidentState)> {
+        self.incidents
+            .iter()
+            .filter_map(|incident| {
+                state_at(incident, self.current)
+                    .filter(|state| !state.is_final())
+                    .map(|state| (incident, state))
+            })
+            .collect()
+    }
+
+    /// Devuelve el estado, en el instante reproducido, del incidente del marcador de tipo `place_type` e id `id`, o
+    /// `None` si no es un incidente abierto.
+    pub fn incident_state(&self, place_type: &PlaceType, id: u8) -> Option<IncidentState> {
+        let inc_info = IncidentInfo::new(id, place_type.to_inc_source()?);
+        self.open_incidents()
+            .into_iter()
+            .find(|(incident, _)| incident.get_info() == inc_info)
+            .map(|(_, state)| state)
+    }
+
+    /// Devuelve los marcadores de las cámaras y los incidentes abiertos en el instante reproducido.
+    pub fn places(&self) -> Places {
+        let mut places = UISistemaMonitoreo::initialize_places();
+        for camera in self.cameras.values() {
+            let style = UISistemaMonitoreo::create_camera_style(camera.get_state());
+            places.add_place(UISistemaMonitoreo::create_camera_place(camera, style));
+        }
+        let style = UISistemaMonitoreo::create_style_with_color(255, 0, 0);
+        for (incident, state) in self.open_incidents() {
+            let mut place = UISistemaMonitoreo::create_place_for_incident(incident, &style);
+            place.tooltip = Some(format!("Estado: {}", state.to_str()));
+            places.add_place(place);
+        }
+        places
+    }
+
+    pub fn get_dron_markers(&self) -> &DronMarkers {
+        &self.dron_markers
+    }
+
+    pub fn get_dron_trails(&self) -> &DronTrails {
+        &self.dron_trails
+    }
+
+    pub fn get_camera_coverage(&self) -> &CameraCoverage {
+        &self.camera_coverage
+    }
+
+    /// Muestra los controles de la reproducción en un panel inferior. Devuelve `false` si se pidió salir de ella.
+    pub fn show_controls(&mut self, ctx: &egui::Context) -> bool {
+        let mut keep_open = true;
+        egui::TopBottomPanel::bottom("session_replay").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.strong("Reproducción");
+                let play_text = if self.playing { "⏸ Pausar" } else { "▶ Reproducir" };
+                if ui.button(play_text).clicked() {
+                    self.toggle_play();
+                }
+                egui::ComboBox::from_id_source("replay_speed")
+                    .selected_text(format!("x{}", self.speed))
+                    .width(60.)
+                    .show_ui(ui, |ui| {
+                        for speed in SPEEDS {
+                            ui.selectable_value(&mut self.speed, speed, format!("x{}", speed));
+                        }
+                    });
+                let mut at = self.current;
+                ui.spacing_mut().slider_width = 300.;
+                if ui.add(egui::Slider::new(&mut at, self.start..=self.end).show_value(false)).changed() {
+                    self.seek(at);
+                }
+                ui.label(format!("{} / {}", format_millis(self.current), format_millis(self.end)));
+                if ui.button("Salir de la reproducción").clicked() {
+                    keep_open = false;
+                }
+            });
+        });
+        keep_open
+    }
+}
+
+/// Devuelve el estado que tenía `incident` en el instante `at`, o `None` si todavía no se había creado.
+pub fn state_at(incident: &Incident, at: u64) -> Option<IncidentState> {
+    incident
+        .get_history()
+        .iter()
+        .take_while(|transition| transition.get_at() <= at)
+        .last()
+        .map(|transition| transition.get_state())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::{state_at, SessionReplay};
+    use crate::apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        incident_data::{incident::Incident, incident_source::IncidentSource, incident_state::IncidentState},
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState},
+        sist_monitoreo::{dron_registry::DronRegistry, session_log::LoggedMessage},
+    };
+
+    fn dron_message(at: u64, dron_id: u8, state: DronState) -> LoggedMessage {
+        let dron = DronCurrentInfo::new(dron_id, GeoPosition::new(-34.6, -58.4).unwrap(), 80, state);
+        LoggedMessage::new(at, AppsMqttTopics::DronTopic.to_str(), dron.to_bytes())
+    }
+
+    #[test]
+    fn test_1_el_estado_del_incidente_es_el_de_su_ultima_transicion_hasta_ese_instante() {
+        let mut incident = Incident::new(1, GeoPosition::new(-34.6, -58.4).unwrap(), IncidentSource::Manual);
+        thread::sleep(Duration::from_millis(2));
+        incident.transition_to(IncidentState::Assigned).unwrap();
+        let created_at = incident.get_created_at();
+        let assigned_at = incident.get_history()[1].get_at();
+
+        assert_eq!(state_at(&incident, created_at.saturating_sub(1)), None);
+        assert_eq!(state_at(&incident, created_at), Some(IncidentState::Reported));
+        assert_eq!(state_at(&incident, assigned_at), Some(IncidentState::Assigned));
+    }
+
+    #[test]
+    fn test_2_la_reproduccion_aplica_los_mensajes_hasta_el_instante_elegido() {
+        let messages = vec![
+            dron_message(1_000, 1, DronState::Flying),
+            dron_message(2_000, 2, DronState::Flying),
+            dron_message(3_000, 1, DronState::Offline),
+        ];
+        let mut replay = SessionReplay::new(messages, vec![], DronRegistry::new()).unwrap();
+        assert_eq!(replay.get_dron_markers().positions().len(), 1);
+
+        replay.seek(2_500);
+        assert_eq!(replay.get_dron_markers().positions().len(), 2);
+        replay.seek(3_000);
+        assert_eq!(replay.get_dron_markers().positions().len(), 1);
+        // Volver atrás reconstruye el mapa desde el principio.
+        replay.seek(0);
+        assert_eq!(replay.get_dron_markers().positions().len(), 1);
+
+        assert!(SessionReplay::new(vec![], vec![], DronRegistry::new()).is_none());
+    }
+
+    #[test]
+    fn test_3_reproducir_avanza_segun_la_velocidad_y_se_pausa_al_final() {
+        let messages = vec![dron_message(1_000, 1, DronState::Flying), dron_message(5_000, 2, DronState::Flying)];
+        let mut replay = SessionReplay::new(messages, vec![], DronRegistry::new()).unwrap();
+        replay.advance(Duration::from_secs(1));
+        assert_eq!(replay.current, 1_000);
+
+        replay.toggle_play();
+        replay.speed = 2.;
+        replay.advance(Duration::from_secs(1));
+        assert_eq!(replay.current, 3_000);
+        replay.advance(Duration::from_secs(10));
+        assert_eq!(replay.current, 5_000);
+        assert!(!replay.playing);
+        assert_eq!(replay.get_dron_markers().positions().len(), 2);
+    }
+}
@@ -1,24 +1,34 @@
 use std::{
-    io::{self, ErrorKind},
+    io::ErrorKind,
     sync::{mpsc, Arc, Mutex},
     thread::{self, JoinHandle},
 };
 
-use crate::mqtt::{client::mqtt_client::MQTTClient, messages::publish_message::PublishMessage};
-use crossbeam_channel::{unbounded, Receiver as CrossbeamReceiver, Sender as CrossbeamSender};
+use crate::mqtt::{
+    client::{mqtt_client::MQTTClient, mqtt_client_connection_status::ConnectionStatus},
+    messages::publish_message::PublishMessage,
+};
 use std::sync::mpsc::{Receiver as MpscReceiver, Sender as MpscSender};
 
 use crate::{
     apps::{
         apps_mqtt_topics::AppsMqttTopics,
-        common_clients::{exit_when_asked, there_are_no_more_publish_msgs},
+        common_clients::exit_when_asked,
         incident_data::incident::Incident,
-        sist_monitoreo::{order_checker::OrderChecker, ui_sistema_monitoreo::UISistemaMonitoreo},
+        payload_codec::{Codec, TopicCodecs},
+        sist_dron::dron_command::DronCommand,
+        sist_monitoreo::{
+            dron_registry::DronRegistry, incident_history::IncidentHistoryStore, order_checker::OrderChecker,
+            resolution_mode::ResolutionMode,
+            operator_login::OperatorLogin,
+            session_log::{LoggedMessage, SessionLog},
+            ui_event::UiEventQueue,
+            ui_sistema_monitoreo::{now_millis, UISistemaMonitoreo},
+        },
     },
     logging::string_logger::StringLogger,
 };
 
-use std::fs;
 use std::io::Error;
 
 /// Sistema encargado de permitir la publicación de incidentes, determinar su estado; recibir información
@@ -29,60 +39,102 @@ pub struct SistemaMonitoreo {
     qos: u8,
     logger: StringLogger,
     topics: Vec<(String, u8)>,
-}
-
-fn leer_qos_desde_archivo(ruta_archivo: &str) -> Result<u8, io::Error> {
-    let contenido = fs::read_to_string(ruta_archivo)?;
-    let inicio = contenido.find("qos=").ok_or(io::Error::new(
-        ErrorKind::NotFound,
-        "No se encontró la etiqueta 'qos='",
-    ))?;
-
-    let valor_qos = contenido[inicio + 4..].trim().parse::<u8>().map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            "El valor de QoS no es un número válido",
-        )
-    })?;
-    println!("Valor de QoS: {}", valor_qos);
-    Ok(valor_qos)
+    resolution_mode: ResolutionMode,
+    topic_codecs: TopicCodecs, // con qué formato publica los incidentes.
+    dron_registry: DronRegistry, // nombres con los que la ui muestra a los drones.
+    incident_history: Option<IncidentHistoryStore>, // dónde la ui guarda los incidentes y sus cambios de estado.
+    session_log: Option<SessionLog>, // dónde se registran los mensajes recibidos, para reproducir las sesiones.
+    operator_login: Option<OperatorLogin>, // credenciales y roles de quienes pueden usar la ui.
 }
 
 impl SistemaMonitoreo {
-    /// Crea un Sistema Monitoreo.
-    pub fn new(logger: StringLogger) -> Self {
-        let qos =
-            leer_qos_desde_archivo("src/apps/sist_monitoreo/qos_sistema_monitoreo.properties")
-                .unwrap_or(0);
-        println!("valor de QoS: {}", qos);
+    /// Crea un Sistema Monitoreo, que publica y se suscribe con el `qos` recibido.
+    pub fn new(qos: u8, logger: StringLogger) -> Self {
         let topics = vec![
             (AppsMqttTopics::CameraTopic.to_str().to_string(), qos),
+            // Varias cámaras en un solo mensaje, al inicio y cuando muchas cambian a la vez.
+            (AppsMqttTopics::CameraSnapshotTopic.to_str().to_string(), qos),
             (AppsMqttTopics::DronTopic.to_str().to_string(), qos),
+            // Current_info que los drones no publicaron mientras no tenían conexión.
+            (AppsMqttTopics::DronHistoryTopic.to_str().to_string(), qos),
             (AppsMqttTopics::IncidentTopic.to_str().to_string(), qos),
             (AppsMqttTopics::DescTopic.to_str().to_string(), qos),
+            // Los drones publican sus latidos con qos 0.
+            (AppsMqttTopics::DronHealthTopic.to_str().to_string(), 0),
         ];
         let sistema_monitoreo: SistemaMonitoreo = Self {
             incidents: Arc::new(Mutex::new(Vec::new())), // []
             qos,
             logger,
             topics,
+            resolution_mode: ResolutionMode::default(),
+            topic_codecs: TopicCodecs::default(),
+            dron_registry: DronRegistry::new(),
+            incident_history: None,
+            session_log: None,
+            operator_login: None,
         };
 
         sistema_monitoreo
     }
 
+    /// Indica cómo decidir que un incidente está resuelto. Si es al recibir el aviso de que fue atendido, se suscribe
+    /// también al topic `inc-attended`.
+    pub fn with_resolution_mode(mut self, resolution_mode: ResolutionMode) -> Self {
+        if resolution_mode.waits_for_attended() {
+            let topic = AppsMqttTopics::IncidentAttendedTopic.to_str().to_string();
+            self.topics.push((topic, self.qos));
+        }
+        self.resolution_mode = resolution_mode;
+        self
+    }
+
+    /// Indica con qué formato publica por cada topic; por defecto, binario.
+    pub fn with_topic_codecs(mut self, topic_codecs: TopicCodecs) -> Self {
+        self.topic_codecs = topic_codecs;
+        self
+    }
+
+    /// Indica los nombres con los que la ui muestra a los drones registrados; por defecto, se los muestra por su id.
+    pub fn with_dron_registry(mut self, dron_registry: DronRegistry) -> Self {
+        self.dron_registry = dron_registry;
+        self
+    }
+
+    /// Indica dónde guardar el historial de incidentes; por defecto, no se guarda.
+    pub fn with_incident_history(mut self, incident_history: Option<IncidentHistoryStore>) -> Self {
+        self.incident_history = incident_history;
+        self
+    }
+
+    /// Indica dónde registrar los mensajes recibidos, para poder reproducir las sesiones desde la ui; por defecto,
+    /// no se registran.
+    pub fn with_session_log(mut self, session_log: Option<SessionLog>) -> Self {
+        self.session_log = session_log;
+        self
+    }
+
+    /// Indica contra qué credenciales se inicia sesión en la ui, y con ellas qué puede hacer cada usuario; por
+    /// defecto, no se pide iniciar sesión.
+    pub fn with_operator_login(mut self, operator_login: Option<OperatorLogin>) -> Self {
+        self.operator_login = operator_login;
+        self
+    }
+
     /// Lanza las partes internas del sistema monitoreo y las inicializa.
     pub fn spawn_threads(
         &self,
-        publish_message_rx: MpscReceiver<PublishMessage>,
         mqtt_client: MQTTClient,
     ) -> Vec<JoinHandle<()>> {
         let (incident_tx, incident_rx) = mpsc::channel::<Incident>();
+        let (command_tx, command_rx) = mpsc::channel::<(u8, DronCommand)>();
         let (exit_tx, exit_rx) = mpsc::channel::<bool>();
+        let (reconnect_tx, reconnect_rx) = mpsc::channel::<()>();
+        let status_rx = mqtt_client.subscribe_to_connection_status();
 
         let mut children: Vec<JoinHandle<()>> = vec![];
         let mqtt_client_sh = Arc::new(Mutex::new(mqtt_client));
-        let (egui_tx, egui_rx) = unbounded::<PublishMessage>();
+        let ui_events = UiEventQueue::new();
 
         // Exit, cuando ui lo solicite
         children.push(self.spawn_exit_thread(mqtt_client_sh.clone(), exit_rx));
@@ -90,15 +142,20 @@ impl SistemaMonitoreo {
         // Recibe inc de la ui y hace publish
         children.push(self.spawn_publish_incs_thread(mqtt_client_sh.clone(), incident_rx));
 
+        // Recibe comandos de control de la ui y los publica al dron correspondiente
+        children.push(self.spawn_publish_commands_thread(mqtt_client_sh.clone(), command_rx));
+
+        // Reconecta al cliente cuando el operador lo pide desde la ui
+        children.push(self.spawn_reconnect_thread(mqtt_client_sh.clone(), reconnect_rx));
+
         // Recibe msgs por MQTT y los envía para mostrarse en la ui
-        children.push(self.spawn_subscribe_to_topics_thread(
-            mqtt_client_sh.clone(),
-            publish_message_rx,
-            egui_tx,
-        ));
+        if let Err(e) = self.subscribe_to_topics(&mqtt_client_sh, &ui_events) {
+            self.logger
+                .log(format!("Error al suscribirse a los topics de MQTT: {:?}.", e));
+        }
 
         // UI
-        self.spawn_ui_thread(incident_tx, egui_rx, exit_tx);
+        self.spawn_ui_thread(incident_tx, command_tx, ui_events, exit_tx, (status_rx, reconnect_tx));
 
         children
     }
@@ -106,23 +163,38 @@ impl SistemaMonitoreo {
         self.qos
     }
 
-    /// Hilo encargado de lanzar la UI.
+    /// Lanza la UI en el hilo que la llama (eframe necesita correr en el hilo principal), hasta que se cierre.
+    /// Los mensajes le llegan ya decodificados por `ui_events`, para que sus frames no dependan de cuántos lleguen.
     fn spawn_ui_thread(
         &self,
         incident_tx: MpscSender<Incident>,
-        publish_message_rx: CrossbeamReceiver<PublishMessage>,
+        command_tx: MpscSender<(u8, DronCommand)>,
+        ui_events: UiEventQueue,
         exit_tx: MpscSender<bool>,
+        (status_rx, reconnect_tx): (MpscReceiver<ConnectionStatus>, MpscSender<()>),
     ) {
+        let resolution_mode = self.resolution_mode;
+        let dron_registry = self.dron_registry.clone();
+        let incident_history = self.incident_history.clone();
+        let session_log = self.session_log.clone();
+        let operator_login = self.operator_login.clone();
         if let Err(e) = eframe::run_native(
             "Sistema Monitoreo",
             Default::default(),
-            Box::new(|cc| {
+            Box::new(move |cc| {
                 Box::new(UISistemaMonitoreo::new(
                     cc.egui_ctx.clone(),
                     incident_tx,
-                    publish_message_rx,
+                    command_tx,
+                    ui_events,
                     exit_tx,
-                ))
+                    resolution_mode,
+                )
+                .with_dron_registry(dron_registry)
+                .with_incident_history(incident_history)
+                .with_session_log(session_log)
+                .with_operator_login(operator_login)
+                .with_connection_status(status_rx, reconnect_tx))
             }),
         ) {
             self.logger.log(format!("Error en hilo para UI: {:?}.", e));
@@ -147,51 +219,78 @@ impl SistemaMonitoreo {
         })
     }
 
-    fn clone_ref(&self) -> Self {
-        Self {
-            incidents: self.incidents.clone(),
-            qos: self.qos,
-            logger: self.logger.clone_ref(),
-            topics: self.topics.clone(),
-        }
-    }
-
-    /// Se suscribe a los topics y queda recibiendo PublishMessages de esos topics.
-    /// Delega el procesamiento de cada mensaje recibido por MQTT a otra parte del Sistema Cámaras, enviándolo por un channel.
-    fn spawn_subscribe_to_topics_thread(
+    /// Recibe comandos de control desde la UI, y los publica por MQTT al topic de control del dron indicado.
+    fn spawn_publish_commands_thread(
         &self,
         mqtt_client: Arc<Mutex<MQTTClient>>,
-        mqtt_rx: MpscReceiver<PublishMessage>,
-        egui_tx: CrossbeamSender<PublishMessage>,
+        rx: MpscReceiver<(u8, DronCommand)>,
     ) -> JoinHandle<()> {
-        let mut self_clone = self.clone_ref();
+        let self_clone = self.clone_ref();
         thread::spawn(move || {
-            if let Err(e) = self_clone.subscribe_and_receive_msgs(&mqtt_client, mqtt_rx, egui_tx) {
+            while let Ok((dron_id, command)) = rx.recv() {
                 self_clone.logger.log(format!(
-                    "Error en hilo para suscribir y recibir mensajes de MQTT: {:?}.",
-                    e
+                    "Sistema-Monitoreo: envío comando {:?} al dron {}",
+                    command, dron_id
                 ));
+                self_clone.publish_dron_command(dron_id, command, &mqtt_client);
             }
         })
     }
 
-    /// Se suscribe a los topics de interés y permanece escuchando mensajes recibidos de los mismos.
-    fn subscribe_and_receive_msgs(
-        &mut self,
+    /// Recibe los pedidos de reconexión desde la UI, y reconecta al cliente MQTT.
+    fn spawn_reconnect_thread(
+        &self,
+        mqtt_client: Arc<Mutex<MQTTClient>>,
+        rx: MpscReceiver<()>,
+    ) -> JoinHandle<()> {
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            while rx.recv().is_ok() {
+                self_clone.logger.log("Sistema-Monitoreo: reconexión pedida por el operador.".to_string());
+                if let Ok(mqtt_client) = mqtt_client.lock() {
+                    if let Err(e) = mqtt_client.mqtt_reconnect() {
+                        self_clone.logger.log(format!("Error al reconectar: {:?}", e));
+                    }
+                }
+            }
+        })
+    }
+
+    fn clone_ref(&self) -> Self {
+        Self {
+            incidents: self.incidents.clone(),
+            qos: self.qos,
+            logger: self.logger.clone_ref(),
+            topics: self.topics.clone(),
+            resolution_mode: self.resolution_mode,
+            topic_codecs: self.topic_codecs,
+            dron_registry: self.dron_registry.clone(),
+            incident_history: self.incident_history.clone(),
+            session_log: self.session_log.clone(),
+            operator_login: self.operator_login.clone(),
+        }
+    }
+
+    /// Utiliza la librería MQTT para subscribirse a los topics de interés.
+    /// Delega el procesamiento de cada mensaje recibido por MQTT a la ui, decodificándolo y encolándolo en `ui_events`.
+    fn subscribe_to_topics(
+        &self,
         mqtt_client: &Arc<Mutex<MQTTClient>>,
-        mqtt_rx: MpscReceiver<PublishMessage>,
-        egui_tx: CrossbeamSender<PublishMessage>,
+        ui_events: &UiEventQueue,
     ) -> Result<(), Error> {
-        self.subscribe_to_topics(mqtt_client)?;
-        self.logger.log(format!("Suscripto a {:?}", &self.topics));
-        self.receive_messages_from_subscribed_topics(mqtt_rx, egui_tx);
-        Ok(())
-    }
+        // Compartido por los handlers de todos los topics
+        let time_order_checker = Arc::new(Mutex::new(OrderChecker::new()));
 
-    /// Utiliza la librería MQTT para subscribirse a los topics.
-    fn subscribe_to_topics(&self, mqtt_client: &Arc<Mutex<MQTTClient>>) -> Result<(), Error> {
         if let Ok(mut mqtt_client) = mqtt_client.lock() {
-            mqtt_client.mqtt_subscribe(self.topics.clone())?;
+            for (topic, qos) in &self.topics {
+                let self_clone = self.clone_ref();
+                let order_checker = time_order_checker.clone();
+                let ui_events = ui_events.clone();
+                mqtt_client.mqtt_subscribe_with_handler(topic, *qos, move |pub_msg| {
+                    self_clone.receive_message_from_subscribed_topic(pub_msg, &order_checker, &ui_events);
+                })?;
+            }
+            self.logger.log(format!("Suscripto a {:?}", &self.topics));
             Ok(())
         } else {
             Err(Error::new(
@@ -202,37 +301,37 @@ impl SistemaMonitoreo {
     }
 
     /// Si el mensaje publish recibido por MQTT es más nuevo que el último procesado, entonces
-    /// envía a otra parte del sistema de monitoreo, para ser procesado.
-    fn receive_messages_from_subscribed_topics(
-        &mut self,
-        mqtt_rx: MpscReceiver<PublishMessage>,
-        egui_tx: CrossbeamSender<PublishMessage>,
+    /// lo decodifica y lo encola para que la ui lo procese.
+    fn receive_message_from_subscribed_topic(
+        &self,
+        pub_msg: PublishMessage,
+        time_order_checker: &Mutex<OrderChecker>,
+        ui_events: &UiEventQueue,
     ) {
-        let mut time_order_checker = OrderChecker::new();
-
-        for pub_msg in mqtt_rx {
-            self.logger.log(format!("Publish recibido: {:?}", pub_msg));
-            // Chequeo el timestamp del publish_msg, si es nuevo, lo mando a la ui
-            // Uso un match, no quiero retornar si fue error xq cortaría el loop, solo lo loggueo
-            match time_order_checker.is_newest(&pub_msg) {
-                Ok(true) => self.send_publish_message_to_ui(pub_msg, egui_tx.clone()),
-                Ok(false) => {}, // No se lo procesa porque no es el más nuevo
-                Err(e) => self.logger.log(format!("Error en OrderChecker: {:?}", e)),                
+        self.logger.log(format!("Publish recibido: {:?}", pub_msg));
+        let Ok(mut time_order_checker) = time_order_checker.lock() else {
+            self.logger.log("Error al tomar lock del OrderChecker.".to_string());
+            return;
+        };
+        // Chequeo el timestamp del publish_msg, si es nuevo, lo mando a la ui
+        // Uso un match, no quiero retornar si fue error, solo lo loggueo
+        match time_order_checker.is_newest(&pub_msg) {
+            Ok(true) => {
+                self.record_in_session_log(&pub_msg);
+                ui_events.push_message(&pub_msg.get_topic_name(), &pub_msg.get_payload(), now_millis());
             }
+            Ok(false) => {}, // No se lo procesa porque no es el más nuevo
+            Err(e) => self.logger.log(format!("Error en OrderChecker: {:?}", e)),
         }
-
-        there_are_no_more_publish_msgs(&self.logger);
     }
 
-    fn send_publish_message_to_ui(
-        &self,
-        msg: PublishMessage,
-        egui_tx: CrossbeamSender<PublishMessage>,
-    ) {
-        let res_send = egui_tx.send(msg);
-        match res_send {
-            Ok(_) => println!("Enviado mensaje a la UI"),
-            Err(e) => println!("Error al enviar mensaje a la UI: {:?}", e),
+    /// Registra el mensaje recibido, si se configuró dónde, para poder reproducir la sesión.
+    fn record_in_session_log(&self, pub_msg: &PublishMessage) {
+        if let Some(session_log) = &self.session_log {
+            let message = LoggedMessage::new(now_millis(), &pub_msg.get_topic_name(), pub_msg.get_payload());
+            if let Err(e) = session_log.record(&message) {
+                self.logger.log(format!("Error al registrar el mensaje en la sesión: {:?}", e));
+            }
         }
     }
 
@@ -254,11 +353,12 @@ impl SistemaMonitoreo {
 
         // Hago el publish
         if let Ok(mut mqtt_client) = mqtt_client.lock() {
-            let res_publish = mqtt_client.mqtt_publish(
-                AppsMqttTopics::IncidentTopic.to_str(),
-                &incident.to_bytes(),
-                self.get_qos(),
-            );
+            let topic = AppsMqttTopics::IncidentTopic;
+            let res_publish = self
+                .topic_codecs
+                .for_topic(&topic)
+                .encode(&incident)
+                .and_then(|payload| mqtt_client.mqtt_publish(topic.to_str(), &payload, self.get_qos()));
             match res_publish {
                 Ok(publish_msg) => {
                     self.logger
@@ -270,4 +370,19 @@ impl SistemaMonitoreo {
             };
         }
     }
+
+    /// Utiliza la librería MQTT para publicar el `command` al topic de control del dron `dron_id`.
+    fn publish_dron_command(
+        &self,
+        dron_id: u8,
+        command: DronCommand,
+        mqtt_client: &Arc<Mutex<MQTTClient>>,
+    ) {
+        if let Ok(mut mqtt_client) = mqtt_client.lock() {
+            let topic = AppsMqttTopics::dron_control_topic(dron_id);
+            if let Err(e) = mqtt_client.mqtt_publish(&topic, &command.to_bytes(), self.get_qos()) {
+                self.logger.log(format!("Error al enviar comando al dron {}: {:?}", dron_id, e));
+            }
+        }
+    }
 }
@@ -1,30 +1,47 @@
 use std::io::Error;
 
 use rustx::apps::{
-    common_clients::{get_broker_address, join_all_threads},
-    sist_monitoreo::sistema_monitoreo::SistemaMonitoreo,
+    common_clients::{get_broker_address_and_option, get_topic_codecs, join_all_threads},
+    sist_monitoreo::{
+        dron_registry::DronRegistry, incident_history::IncidentHistoryStore, operator_login::OperatorLogin,
+        resolution_mode::ResolutionMode, session_log::SessionLog, sistema_monitoreo::SistemaMonitoreo,
+    },
 };
 use rustx::logging::string_logger::StringLogger;
-use rustx::mqtt::client::mqtt_client::MQTTClient;
+use rustx::mqtt::client::mqtt_client_builder::MqttClientBuilder;
+
+const PROPERTIES_FILE: &str = "sistema_monitoreo.properties";
 
 fn get_formatted_app_id() -> String {
     String::from("Sistema-Monitoreo")
 }
 
 fn main() -> Result<(), Error> {
-    let broker_addr = get_broker_address();
+    // Opcionalmente, se indica cómo decidir que un incidente está resuelto.
+    let (broker_addr, resolution_arg) = get_broker_address_and_option(true);
+    let resolution_mode = match resolution_arg {
+        Some(arg) => ResolutionMode::from_arg(&arg)?,
+        None => ResolutionMode::default(),
+    };
 
     // Se crean y configuran ambos extremos del string logger
     let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id());
 
+    let qos = 1; // []
     let client_id = get_formatted_app_id();
-    let sistema_monitoreo = SistemaMonitoreo::new(logger.clone_ref());
-    match MQTTClient::mqtt_connect_to_broker(client_id, &broker_addr, None, logger.clone_ref()) {
-        Ok((mqtt_client, publish_message_rx, handle)) => {
+    let sistema_monitoreo = SistemaMonitoreo::new(qos, logger.clone_ref())
+        .with_resolution_mode(resolution_mode)
+        .with_topic_codecs(get_topic_codecs(PROPERTIES_FILE)?)
+        .with_dron_registry(DronRegistry::from_file(PROPERTIES_FILE)?)
+        .with_incident_history(IncidentHistoryStore::from_file(PROPERTIES_FILE)?)
+        .with_session_log(SessionLog::from_file(PROPERTIES_FILE)?)
+        .with_operator_login(OperatorLogin::from_file(PROPERTIES_FILE)?);
+    match MqttClientBuilder::new(&client_id).connect(&broker_addr, logger.clone_ref()) {
+        Ok((mqtt_client, _publish_message_rx, handle)) => {
             println!("Conectado al broker MQTT.");
             logger.log("Conectado al broker MQTT".to_string());
 
-            let mut handles = sistema_monitoreo.spawn_threads(publish_message_rx, mqtt_client);
+            let mut handles = sistema_monitoreo.spawn_threads(mqtt_client);
 
             handles.push(handle);
             join_all_threads(handles);
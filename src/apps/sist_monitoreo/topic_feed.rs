@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use super::ui_sistema_monitoreo::format_millis;
+
+/// Cantidad de mensajes que se conservan en el registro; al superarla se descartan los más viejos.
+const MAX_FEED_LEN: usize = 500;
+
+/// Mensaje recibido, tal como se muestra en el registro.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    at: u64, // en milisegundos desde el epoch
+    topic: String,
+    summary: String,
+}
+
+impl FeedEntry {
+    pub fn new(at: u64, topic: &str, summary: String) -> Self {
+        Self { at, topic: topic.to_string(), summary }
+    }
+
+    /// Devuelve si el topic o el resumen contienen `search`, sin distinguir mayúsculas de minúsculas.
+    fn matches(&self, search: &str) -> bool {
+        let search = search.trim().to_lowercase();
+        self.topic.to_lowercase().contains(&search) || self.summary.to_lowercase().contains(&search)
+    }
+}
+
+/// Registro de los mensajes mqtt que recibe monitoreo, que se muestra en un panel inferior para ver, por ejemplo,
+/// por qué un dron no reacciona sin leer la salida del servidor. Se lo puede pausar, y filtrar buscando un texto.
+#[derive(Debug, Default)]
+pub struct TopicFeed {
+    entries: VecDeque<FeedEntry>,
+    pending: Vec<FeedEntry>, // recibidos mientras está pausado, que se agregan al reanudarlo
+    paused: bool,
+    search: String,
+}
+
+impl TopicFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Agrega un mensaje recibido.
+    pub fn push(&mut self, entry: FeedEntry) {
+        if self.paused {
+            self.pending.push(entry);
+            return;
+        }
+        if self.entries.len() == MAX_FEED_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Pausa el registro, o lo reanuda agregando los mensajes recibidos mientras estaba pausado.
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            for entry in std::mem::take(&mut self.pending) {
+                self.push(entry);
+            }
+        }
+    }
+
+    /// Devuelve los mensajes que contienen el texto buscado, del más viejo al más nuevo.
+    pub fn visible_entries(&self) -> Vec<&FeedEntry> {
+        self.entries.iter().filter(|entry| entry.matches(&self.search)).collect()
+    }
+
+    /// Muestra el registro en un panel inferior, que se puede colapsar.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("topic_feed").show(ctx, |ui| {
+            egui::CollapsingHeader::new("Mensajes recibidos")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        let pause_text = if self.paused {
+                            format!("Reanudar ({} nuevos)", self.pending.len())
+                        } else {
+                            "Pausar".to_string()
+                        };
+                        if ui.button(pause_text).clicked() {
+                            self.toggle_pause();
+                        }
+                        ui.label("Buscar:");
+                        ui.add_sized([200.0, 20.0], egui::TextEdit::singleline(&mut self.search));
+                        if ui.button("Limpiar").clicked() {
+                            self.entries.clear();
+                        }
+                    });
+                    egui::ScrollArea::vertical()
+                        .max_height(150.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for entry in self.visible_entries() {
+                                ui.label(format!("{} [{}] {}", format_millis(entry.at), entry.topic, entry.summary));
+                            }
+                        });
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FeedEntry, TopicFeed};
+
+    #[test]
+    fn test_1_pausado_no_agrega_mensajes_hasta_reanudarlo() {
+        let mut feed = TopicFeed::new();
+        feed.push(FeedEntry::new(1, "dron", "1 bytes".to_string()));
+        feed.toggle_pause();
+        feed.push(FeedEntry::new(2, "inc", "2 bytes".to_string()));
+        assert_eq!(feed.visible_entries().len(), 1);
+
+        feed.toggle_pause();
+        assert_eq!(feed.visible_entries().len(), 2);
+    }
+
+    #[test]
+    fn test_2_la_busqueda_filtra_por_topic_y_resumen() {
+        let mut feed = TopicFeed::new();
+        feed.push(FeedEntry::new(1, "dron", "1 bytes".to_string()));
+        feed.push(FeedEntry::new(2, "inc", "2 bytes".to_string()));
+
+        feed.search = "INC".to_string();
+        assert_eq!(feed.visible_entries().len(), 1);
+        feed.search = "bytes".to_string();
+        assert_eq!(feed.visible_entries().len(), 2);
+        feed.search = "cam".to_string();
+        assert!(feed.visible_entries().is_empty());
+    }
+}
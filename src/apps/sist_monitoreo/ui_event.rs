@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    str::from_utf8,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        incident_data::incident::Incident,
+        payload_codec::decode_payload,
+        sist_camaras::{camera::Camera, cameras_snapshot::CamerasSnapshot},
+        sist_dron::{
+            dron_current_info::DronCurrentInfo, dron_heartbeat::DronHeartbeat, incident_attended::IncidentAttended,
+            telemetry_history::TelemetryBatch,
+        },
+    },
+    mqtt::mqtt_utils::will_message_utils::{app_type::AppType, will_content::WillContent},
+};
+
+use super::topic_feed::FeedEntry;
+
+/// Mensaje recibido por monitoreo, ya decodificado según su topic, listo para que la ui lo procese.
+#[derive(Debug)]
+pub enum UiEvent {
+    Camera(Camera),
+    CamerasSnapshot(CamerasSnapshot),
+    Dron(DronCurrentInfo),
+    DronHistory(TelemetryBatch),
+    Incident(Incident),
+    Disconnection(WillContent),
+    Heartbeat(DronHeartbeat),
+    IncidentAttended(IncidentAttended),
+}
+
+impl UiEvent {
+    /// Decodifica el `payload` recibido por `topic`. Devuelve error si no se puede leer, o si monitoreo no procesa
+    /// los mensajes de ese topic.
+    pub fn decode(topic: &str, payload: &[u8]) -> Result<Self, Error> {
+        match AppsMqttTopics::topic_from_str(topic)? {
+            AppsMqttTopics::CameraTopic => decode_payload(payload).map(UiEvent::Camera),
+            AppsMqttTopics::CameraSnapshotTopic => decode_payload(payload).map(UiEvent::CamerasSnapshot),
+            AppsMqttTopics::DronTopic => decode_payload(payload).map(UiEvent::Dron),
+            AppsMqttTopics::DronHistoryTopic => TelemetryBatch::from_bytes(payload.to_vec()).map(UiEvent::DronHistory),
+            AppsMqttTopics::IncidentTopic => decode_payload(payload).map(UiEvent::Incident),
+            AppsMqttTopics::DescTopic => {
+                let will_content = from_utf8(payload).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                WillContent::will_content_from_string(will_content).map(UiEvent::Disconnection)
+            }
+            AppsMqttTopics::DronHealthTopic => DronHeartbeat::from_bytes(payload.to_vec()).map(UiEvent::Heartbeat),
+            AppsMqttTopics::IncidentAttendedTopic => {
+                IncidentAttended::from_bytes(payload.to_vec()).map(UiEvent::IncidentAttended)
+            }
+            // Monitoreo no se suscribe a los comandos, las asignaciones, los relevos ni los diagnósticos de los drones,
+            // ni al abm, los análisis de cobertura y los latidos de cámaras (recibe las cámaras que cambiaron por el topic de cámaras).
+            AppsMqttTopics::DronAdminTopic
+            | AppsMqttTopics::DronAssignmentTopic
+            | AppsMqttTopics::DronHandoffTopic
+            | AppsMqttTopics::DronControlTopic
+            | AppsMqttTopics::DronDiagnosticsTopic
+            | AppsMqttTopics::CameraAdminTopic
+            | AppsMqttTopics::CameraCoverageTopic
+            | AppsMqttTopics::CameraHealthTopic => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Monitoreo no procesa los mensajes de {}.", topic),
+            )),
+        }
+    }
+
+    /// Devuelve un resumen del evento, para el registro de mensajes recibidos: qué incidente, cámara o dron llegó y
+    /// en qué estado.
+    pub fn summary(&self) -> String {
+        match self {
+            UiEvent::Incident(incident) => format!(
+                "Incidente {} ({:?}) en {}: {}",
+                incident.get_id(),
+                incident.get_source(),
+                incident.get_position(),
+                incident.get_state().to_str()
+            ),
+            UiEvent::Camera(camera) => format!("Cámara {}: {:?}", camera.get_id(), camera.get_state()),
+            UiEvent::CamerasSnapshot(snapshot) => format!("{} cámaras", snapshot.get_cameras().len()),
+            UiEvent::Dron(dron) => format!(
+                "Dron {} en {}: {:?}, batería {}%",
+                dron.get_id(),
+                dron.get_current_position(),
+                dron.get_state(),
+                dron.get_battery_lvl()
+            ),
+            UiEvent::DronHistory(batch) => format!(
+                "Dron {}: {} posiciones de mientras no tenía conexión",
+                batch.get_dron_id(),
+                batch.get_samples().len()
+            ),
+            UiEvent::Disconnection(will_content) => match will_content.get_id() {
+                Some(id) => format!("Desconexión de {:?} {}", will_content.get_app_type_identifier(), id),
+                None => format!("Desconexión de {:?}", will_content.get_app_type_identifier()),
+            },
+            UiEvent::Heartbeat(heartbeat) => {
+                format!("Latido del dron {}: {:?}", heartbeat.get_id(), heartbeat.get_state())
+            }
+            UiEvent::IncidentAttended(attended) => {
+                format!("Incidente {} atendido", attended.get_inc_info().get_inc_id())
+            }
+        }
+    }
+
+    /// Devuelve el id del dron al que se refiere el evento, si es un dron.
+    fn dron_id(&self) -> Option<u8> {
+        match self {
+            UiEvent::Dron(dron) => Some(dron.get_id()),
+            UiEvent::DronHistory(batch) => Some(batch.get_dron_id()),
+            UiEvent::Disconnection(will_content) if will_content.get_app_type_identifier() == AppType::Dron => {
+                will_content.get_id()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Eventos que esperan a que la ui los procese. Los mensajes se decodifican al recibirlos, en el hilo del cliente
+/// mqtt, y la ui procesa todos los pendientes en cada frame, por lo que sus frames no dependen de cuántos mensajes
+/// lleguen. Si llegan varias current_info de un dron antes de que la ui las procese, y solamente cambió su posición
+/// (o su batería), se queda con la última; los cambios de estado nunca se descartan.
+#[derive(Debug, Clone, Default)]
+pub struct UiEventQueue {
+    pending: Arc<Mutex<PendingEvents>>,
+}
+
+#[derive(Debug, Default)]
+struct PendingEvents {
+    events: Vec<UiEvent>,
+    last_dron_events: HashMap<u8, usize>, // índice en `events` de la última current_info de cada dron
+    feed: Vec<FeedEntry>,
+}
+
+impl UiEventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodifica el `payload` recibido por `topic` en el instante `at`, y lo encola junto con su entrada para el
+    /// registro de mensajes recibidos. Los mensajes que no se pueden leer van solamente al registro.
+    pub fn push_message(&self, topic: &str, payload: &[u8], at: u64) {
+        let event = UiEvent::decode(topic, payload);
+        let summary = match &event {
+            Ok(event) => event.summary(),
+            Err(_) => format!("{} bytes", payload.len()),
+        };
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.feed.push(FeedEntry::new(at, topic, summary));
+            if let Ok(event) = event {
+                pending.push(event);
+            }
+        }
+    }
+
+    /// Devuelve los eventos pendientes, en el orden en que llegaron, y las entradas para el registro, vaciando la
+    /// cola.
+    pub fn take(&self) -> (Vec<UiEvent>, Vec<FeedEntry>) {
+        match self.pending.lock() {
+            Ok(mut pending) => {
+                pending.last_dron_events.clear();
+                (std::mem::take(&mut pending.events), std::mem::take(&mut pending.feed))
+            }
+            Err(_) => (vec![], vec![]),
+        }
+    }
+}
+
+impl PendingEvents {
+    fn push(&mut self, event: UiEvent) {
+        let Some(dron_id) = event.dron_id() else {
+            self.events.push(event);
+            return;
+        };
+        if let UiEvent::Dron(dron) = &event {
+            if let Some(&index) = self.last_dron_events.get(&dron_id) {
+                if let UiEvent::Dron(previous) = &self.events[index] {
+                    if only_moved(previous, dron) {
+                        self.events[index] = event;
+                        return;
+                    }
+                }
+            }
+            self.last_dron_events.insert(dron_id, self.events.len());
+        } else {
+            // Lo que llegue después del lote o de la desconexión se tiene que procesar después de ellos.
+            self.last_dron_events.remove(&dron_id);
+        }
+        self.events.push(event);
+    }
+}
+
+/// Devuelve si entre las current_info `previous` y `current` del mismo dron solamente cambió su posición o su
+/// batería, y no su estado ni los incidentes que atiende.
+fn only_moved(previous: &DronCurrentInfo, current: &DronCurrentInfo) -> bool {
+    previous.get_state() == current.get_state()
+        && previous.get_inc_id_to_resolve() == current.get_inc_id_to_resolve()
+        && previous.get_pending_incidents() == current.get_pending_incidents()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{UiEvent, UiEventQueue};
+    use crate::apps::{
+        apps_mqtt_topics::AppsMqttTopics,
+        geo_position::GeoPosition,
+        sist_dron::{dron_current_info::DronCurrentInfo, dron_state::DronState},
+    };
+
+    fn dron_bytes(dron_id: u8, latitude: f64, state: DronState) -> Vec<u8> {
+        DronCurrentInfo::new(dron_id, GeoPosition::new(latitude, -58.4).unwrap(), 80, state).to_bytes()
+    }
+
+    fn dron_events(events: &[UiEvent]) -> Vec<(u8, f64, DronState)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                UiEvent::Dron(dron) => {
+                    Some((dron.get_id(), dron.get_current_position().get_latitude(), dron.get_state()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_1_los_mensajes_se_decodifican_y_resumen_segun_su_topic() {
+        let dron_topic = AppsMqttTopics::DronTopic.to_str();
+        let summary = UiEvent::decode(dron_topic, &dron_bytes(3, -34.6, DronState::Flying))
+            .unwrap()
+            .summary();
+        assert!(summary.starts_with("Dron 3"));
+        assert!(summary.ends_with("Flying, batería 80%"));
+
+        assert!(UiEvent::decode(dron_topic, &[1, 2]).is_err());
+        assert!(UiEvent::decode("otro", &[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_2_se_conserva_solamente_la_ultima_posicion_de_cada_dron() {
+        let queue = UiEventQueue::new();
+        let dron_topic = AppsMqttTopics::DronTopic.to_str();
+        queue.push_message(dron_topic, &dron_bytes(1, -34.1, DronState::Flying), 1);
+        queue.push_message(dron_topic, &dron_bytes(2, -34.2, DronState::Flying), 2);
+        queue.push_message(dron_topic, &dron_bytes(1, -34.3, DronState::Flying), 3);
+        queue.push_message("otro", &[1], 4);
+
+        let (events, feed) = queue.take();
+        assert_eq!(
+            dron_events(&events),
+            vec![(1, -34.3, DronState::Flying), (2, -34.2, DronState::Flying)]
+        );
+        // El registro tiene todos los mensajes, incluso los que no se pudieron leer.
+        assert_eq!(feed.len(), 4);
+        assert!(queue.take().0.is_empty());
+    }
+
+    #[test]
+    fn test_3_los_cambios_de_estado_de_un_dron_no_se_descartan() {
+        let queue = UiEventQueue::new();
+        let dron_topic = AppsMqttTopics::DronTopic.to_str();
+        queue.push_message(dron_topic, &dron_bytes(1, -34.1, DronState::Flying), 1);
+        queue.push_message(dron_topic, &dron_bytes(1, -34.2, DronState::ManagingIncident), 2);
+        queue.push_message(dron_topic, &dron_bytes(1, -34.3, DronState::ManagingIncident), 3);
+
+        let (events, _) = queue.take();
+        assert_eq!(
+            dron_events(&events),
+            vec![(1, -34.1, DronState::Flying), (1, -34.3, DronState::ManagingIncident)]
+        );
+    }
+}
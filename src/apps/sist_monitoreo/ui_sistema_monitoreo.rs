@@ -1,29 +1,49 @@
 use std::collections::HashMap;
-use std::str::{from_utf8, Utf8Error};
 use std::time::{Duration, Instant};
 
-use crate::apps::apps_mqtt_topics::AppsMqttTopics;
+use crate::apps::geo_position::GeoPosition;
+use crate::apps::incident_data::incident_category::IncidentCategory;
+use crate::apps::incident_data::incident_priority::IncidentPriority;
+use crate::apps::incident_data::incident_severity::IncidentSeverity;
 use crate::apps::incident_data::incident_state::IncidentState;
 use crate::apps::incident_data::{
-    incident::Incident, incident_info::IncidentInfo, incident_source::IncidentSource,
+    incident::{Incident, MAX_DESCRIPTION_LEN}, incident_info::IncidentInfo, incident_source::IncidentSource,
 };
 use crate::apps::place_type::PlaceType;
 use crate::apps::sist_camaras::camera_state::CameraState;
+use crate::apps::sist_dron::dron_command::DronCommand;
 use crate::apps::sist_dron::dron_current_info::DronCurrentInfo;
+use crate::apps::sist_dron::dron_heartbeat::DronHeartbeat;
+use crate::apps::sist_dron::incident_attended::IncidentAttended;
 use crate::apps::sist_dron::dron_state::DronState;
-use crate::mqtt::messages::publish_message::PublishMessage;
+use crate::apps::sist_monitoreo::dron_registry::DronRegistry;
+use crate::apps::sist_monitoreo::dron_watchdog::DronWatchdog;
+use crate::apps::sist_monitoreo::entity_inspector::{Entity, EntityInspector};
+use crate::apps::sist_monitoreo::incident_history::{IncidentHistoryStore, IncidentQuery};
+use crate::apps::sist_monitoreo::incident_history_window::IncidentHistoryWindow;
+use crate::apps::sist_monitoreo::map_layers::MapLayers;
+use crate::apps::sist_monitoreo::operator_login::{LoginForm, Operator, OperatorLogin, OperatorRole};
+use crate::apps::sist_monitoreo::resolution_mode::ResolutionMode;
+use crate::apps::sist_monitoreo::session_log::{LoggedMessage, SessionLog};
+use crate::apps::sist_monitoreo::session_replay::SessionReplay;
+use crate::apps::sist_monitoreo::topic_feed::TopicFeed;
+use crate::apps::sist_monitoreo::ui_event::{UiEvent, UiEventQueue};
+use crate::mqtt::client::mqtt_client_connection_status::ConnectionStatus;
 
 use crate::apps::sist_camaras::camera::Camera;
+use crate::apps::sist_camaras::cameras_snapshot::CamerasSnapshot;
+use crate::apps::sist_dron::telemetry_history::TelemetryBatch;
 use crate::apps::vendor::{
     HttpOptions, Map, MapMemory, Place, Places, Position, Style, Tiles, TilesManager,
 };
-use crate::apps::{places, plugins::ImagesPluginData};
+use crate::apps::{places, plugins::{CameraCoverage, DronMarkers, DronTrails, IncidentHeatmap, MarkerPicker}};
 use crate::mqtt::mqtt_utils::will_message_utils::app_type::AppType;
 use crate::mqtt::mqtt_utils::will_message_utils::will_content::WillContent;
+use chrono::{DateTime, Local};
 use crossbeam_channel::{unbounded, Receiver as CrossbeamReceiver, Sender as CrossbeamSender};
 use egui::Color32;
 use egui::Context;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Provider {
@@ -46,6 +66,47 @@ fn http_options() -> HttpOptions {
     }
 }
 
+/// Convierte una posición a la del mapa.
+pub(super) fn map_position(position: GeoPosition) -> Position {
+    Position::from_lon_lat(position.get_longitude(), position.get_latitude())
+}
+
+/// Formatea un instante en milisegundos desde el epoch, en la hora local. Si no se conoce (0), devuelve "-".
+pub(super) fn format_millis(millis: u64) -> String {
+    DateTime::from_timestamp_millis(millis as i64)
+        .filter(|_| millis > 0)
+        .map(|at| at.with_timezone(&Local).format("%d/%m/%Y %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Formatea cuánto tiempo pasó desde un instante, ie "2h 05m", "3m 10s" o "45s".
+fn format_age(elapsed_millis: u64) -> String {
+    let secs = elapsed_millis / 1000;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, secs) => format!("{}s", secs),
+        (0, mins, secs) => format!("{}m {:02}s", mins, secs),
+        (hours, mins, _) => format!("{}h {:02}m", hours, mins),
+    }
+}
+
+/// Devuelve el texto con el que se pide confirmar la salida, avisando si quedan `open_incidents` incidentes sin
+/// cerrar, y si no hay conexión con el broker (en cuyo caso no se le puede avisar que se sale).
+fn exit_confirmation_text(open_incidents: usize, connection_status: &ConnectionStatus) -> String {
+    let mut text = "¿Salir de Sistema Monitoreo? Se dejarán de recibir los drones, las cámaras y los incidentes.".to_string();
+    if open_incidents > 0 {
+        text = format!("{}\nQuedan {} incidentes sin cerrar.", text, open_incidents);
+    }
+    if *connection_status != ConnectionStatus::Connected {
+        text = format!("{}\nNo hay conexión con el broker: se saldrá sin avisarle.", text);
+    }
+    text
+}
+
+/// Devuelve el instante actual, en milisegundos desde el epoch.
+pub(super) fn now_millis() -> u64 {
+    Local::now().timestamp_millis().max(0) as u64
+}
+
 fn providers(egui_ctx: Context) -> HashMap<Provider, Box<dyn TilesManager + Send>> {
     let mut providers: HashMap<Provider, Box<dyn TilesManager + Send>> = HashMap::default();
 
@@ -119,13 +180,21 @@ pub struct UISistemaMonitoreo {
     providers: HashMap<Provider, Box<dyn TilesManager + Send>>,
     selected_provider: Provider,
     map_memory: MapMemory,
-    images_plugin_data: ImagesPluginData,
     click_watcher: super::super::plugins::ClickWatcher,
     incident_dialog_open: bool,
+    incidents_panel_open: bool,
     latitude: String,
     longitude: String,
+    incident_priority: IncidentPriority,
+    incident_severity: IncidentSeverity,
+    incident_category: IncidentCategory,
+    incident_description: String,
     publish_incident_tx: Sender<Incident>,
-    publish_message_rx: CrossbeamReceiver<PublishMessage>,
+    publish_command_tx: Sender<(u8, DronCommand)>,
+    command_dialog_open: bool,
+    command_dron_id: String,
+    command_text: String,
+    ui_events: UiEventQueue, // mensajes recibidos, ya decodificados, que se procesan en cada frame.
     places: Places,
     last_incident_id: u8,
     exit_tx: Sender<bool>,
@@ -135,18 +204,45 @@ pub struct UISistemaMonitoreo {
     error_rx: CrossbeamReceiver<String>,
     error_message: Option<String>,
     error_display_start: Option<Instant>,
+    dron_watchdog: DronWatchdog,
+    resolution_mode: ResolutionMode,
+    attended_incidents: Vec<IncidentAttended>, // atendidos, que esperan que el operador los marque como resueltos.
+    dron_trails: DronTrails, // recorridos recientes de los drones, que se dibujan en el mapa.
+    dron_markers: DronMarkers, // posición, estado y batería de cada dron, que se dibujan en el mapa.
+    camera_coverage: CameraCoverage, // rango de cada cámara, que se dibuja en el mapa.
+    marker_picker: MarkerPicker, // detecta los clicks sobre los marcadores, para inspeccionarlos.
+    inspector: EntityInspector,
+    topic_feed: TopicFeed, // registro de los mensajes recibidos, para depurar.
+    map_layers: MapLayers, // qué capas y elementos se muestran en el mapa.
+    incident_heatmap: IncidentHeatmap, // de los incidentes del historial, en el período elegido en las capas.
+    connection_status: ConnectionStatus, // de la conexión con el broker, que se muestra en la barra superior.
+    connection_status_rx: Option<Receiver<ConnectionStatus>>,
+    reconnect_tx: Option<Sender<()>>,
+    dron_registry: DronRegistry, // nombres de los drones, para mostrarlos en vez de sus ids.
+    incident_history: Option<IncidentHistoryStore>, // dónde se guardan los incidentes y sus cambios de estado.
+    history_window: IncidentHistoryWindow,
+    dron_assignments: HashMap<u8, IncidentInfo>, // incidente al que va o que atiende cada dron.
+    session_log: Option<SessionLog>, // dónde se registran los mensajes recibidos, para reproducir las sesiones.
+    replay_sessions: Vec<Vec<LoggedMessage>>, // sesiones registradas, para elegir cuál reproducir.
+    replay: Option<SessionReplay>, // si se está reproduciendo una sesión, el mapa la muestra en vez de la actual.
+    exit_dialog_open: bool, // se pidió salir, y se espera que el operador lo confirme.
+    exiting: bool, // el operador confirmó que quiere salir, y ya se avisó para desconectarse.
+    operator_login: Option<OperatorLogin>, // si no se indica, no se pide iniciar sesión y se puede hacer todo.
+    login_form: LoginForm,
+    operator: Option<Operator>, // quien inició sesión, con su rol.
 }
 
 impl UISistemaMonitoreo {
     pub fn new(
         egui_ctx: Context,
         tx: Sender<Incident>,
-        publish_message_rx: CrossbeamReceiver<PublishMessage>,
+        command_tx: Sender<(u8, DronCommand)>,
+        ui_events: UiEventQueue,
         exit_tx: Sender<bool>,
+        resolution_mode: ResolutionMode,
     ) -> Self {
         egui_extras::install_image_loaders(&egui_ctx);
 
-        let images_plugin_data = ImagesPluginData::new(egui_ctx.to_owned());
         let places = Self::initialize_places();
         let (error_tx, error_rx) = unbounded();
 
@@ -154,13 +250,21 @@ impl UISistemaMonitoreo {
             providers: providers(egui_ctx.to_owned()),
             selected_provider: Provider::OpenStreetMap,
             map_memory: MapMemory::default(),
-            images_plugin_data,
             click_watcher: Default::default(),
             incident_dialog_open: false,
+            incidents_panel_open: true,
             latitude: String::new(),
             longitude: String::new(),
+            incident_priority: IncidentPriority::default(),
+            incident_severity: IncidentSeverity::default(),
+            incident_category: IncidentCategory::default(),
+            incident_description: String::new(),
             publish_incident_tx: tx,
-            publish_message_rx,
+            publish_command_tx: command_tx,
+            command_dialog_open: false,
+            command_dron_id: String::new(),
+            command_text: String::new(),
+            ui_events,
             places,
             last_incident_id: 0,
             exit_tx,
@@ -170,17 +274,80 @@ impl UISistemaMonitoreo {
             error_rx,
             error_message: None,
             error_display_start: None,
+            dron_watchdog: DronWatchdog::new(),
+            resolution_mode,
+            attended_incidents: Vec::new(),
+            dron_trails: DronTrails::default(),
+            dron_markers: DronMarkers::default(),
+            camera_coverage: CameraCoverage::default(),
+            marker_picker: MarkerPicker::default(),
+            inspector: EntityInspector::new(),
+            topic_feed: TopicFeed::new(),
+            map_layers: MapLayers::new(),
+            incident_heatmap: IncidentHeatmap::default(),
+            connection_status: ConnectionStatus::Connected,
+            connection_status_rx: None,
+            reconnect_tx: None,
+            dron_registry: DronRegistry::new(),
+            incident_history: None,
+            history_window: IncidentHistoryWindow::new(),
+            dron_assignments: HashMap::new(),
+            session_log: None,
+            replay_sessions: Vec::new(),
+            replay: None,
+            exit_dialog_open: false,
+            exiting: false,
+            operator_login: None,
+            login_form: LoginForm::new(),
+            operator: None,
         }
     }
 
-    fn create_style_with_color(r: u8, g: u8, b: u8) -> Style {
+    /// Indica dónde guardar el historial de incidentes; sin él, no se guarda ni se puede consultar.
+    pub fn with_incident_history(mut self, incident_history: Option<IncidentHistoryStore>) -> Self {
+        self.incident_history = incident_history;
+        self
+    }
+
+    /// Indica dónde se registran los mensajes recibidos; sin él, no se pueden reproducir las sesiones pasadas.
+    pub fn with_session_log(mut self, session_log: Option<SessionLog>) -> Self {
+        self.session_log = session_log;
+        self
+    }
+
+    /// Indica por dónde se reciben los cambios de estado de la conexión con el broker, para mostrarlo, y por dónde
+    /// pedir que se reconecte.
+    pub fn with_connection_status(
+        mut self,
+        connection_status_rx: Receiver<ConnectionStatus>,
+        reconnect_tx: Sender<()>,
+    ) -> Self {
+        self.connection_status_rx = Some(connection_status_rx);
+        self.reconnect_tx = Some(reconnect_tx);
+        self
+    }
+
+    /// Indica contra qué credenciales se inicia sesión antes de ver el mapa; el rol de cada usuario decide qué puede
+    /// hacer. Sin ellas, no se pide iniciar sesión.
+    pub fn with_operator_login(mut self, operator_login: Option<OperatorLogin>) -> Self {
+        self.operator_login = operator_login;
+        self
+    }
+
+    /// Indica los nombres con los que se muestra a los drones registrados.
+    pub fn with_dron_registry(mut self, dron_registry: DronRegistry) -> Self {
+        self.dron_registry = dron_registry;
+        self
+    }
+
+    pub(super) fn create_style_with_color(r: u8, g: u8, b: u8) -> Style {
         Style {
             symbol_color: Color32::from_rgb(r, g, b),
             ..Default::default()
         }
     }
 
-    fn initialize_places() -> Places {
+    pub(super) fn initialize_places() -> Places {
         let mantainance_style = Self::create_style_with_color(255, 165, 0); // Color naranja
         let mantainance_ui = Self::create_maintenance_place(mantainance_style);
         let mut places = Places::new();
@@ -196,6 +363,7 @@ impl UISistemaMonitoreo {
             style,
             id: 0,
             place_type: PlaceType::Mantainance,
+            tooltip: None,
         }
     }
 
@@ -205,32 +373,46 @@ impl UISistemaMonitoreo {
         let _ = self.publish_incident_tx.send(incident);
     }
 
-    fn create_camera_style(camera_state: CameraState) -> Style {
+    pub(super) fn create_camera_style(camera_state: CameraState) -> Style {
         match camera_state {
             CameraState::Active => Style {
                 symbol_color: Color32::from_rgb(0, 255, 0), // Color verde
                 ..Default::default()
             },
             CameraState::SavingMode => Style::default(),
+            CameraState::Fault => Style {
+                symbol_color: Color32::from_rgb(255, 0, 0), // Color rojo
+                ..Default::default()
+            },
+            CameraState::Offline => Style {
+                symbol_color: Color32::GRAY,
+                ..Default::default()
+            },
+            CameraState::HighSensitivity => Style {
+                symbol_color: Color32::from_rgb(0, 150, 255), // Color celeste
+                ..Default::default()
+            },
         }
     }
 
-    fn create_camera_place(camera: &Camera, style: Style) -> Place {
+    pub(super) fn create_camera_place(camera: &Camera, style: Style) -> Place {
         let camera_id = camera.get_id();
-        let (latitude, longitude) = (camera.get_latitude(), camera.get_longitude());
 
         Place {
-            position: Position::from_lon_lat(longitude, latitude),
+            position: map_position(camera.get_position()),
             label: format!("Camera {}", camera_id),
             symbol: '📷',
             style,
             id: camera_id,
             place_type: PlaceType::Camera,
+            tooltip: None,
         }
     }
 
     fn update_camera_on_map(&mut self, camera: Camera) {
         let camera_id = camera.get_id();
+        self.camera_coverage.update(&camera);
+        self.inspector.record_camera(&camera, now_millis());
 
         if camera.is_not_deleted() {
             self.places.remove_place(camera_id, PlaceType::Camera);
@@ -244,8 +426,7 @@ impl UISistemaMonitoreo {
     }
 
     /// Se encarga de procesar y agregar o eliminar una cámara recibida al mapa.
-    fn handle_camera_message(&mut self, publish_message: PublishMessage) {
-        let camera = Camera::from_bytes(&publish_message.get_payload());
+    fn handle_camera_message(&mut self, camera: Camera) {
         println!(
             "UI: recibida cámara: {:?}, estado: {:?}",
             camera,
@@ -255,102 +436,336 @@ impl UISistemaMonitoreo {
         self.update_camera_on_map(camera);
     }
 
-    /// Se encarga de procesar y agregar un dron recibido al mapa.
-    fn handle_drone_message(&mut self, msg: PublishMessage) {
-        if let Ok(dron) = DronCurrentInfo::from_bytes(msg.get_payload()) {
-            /*println!(
-                "UI: recibido dron: {:?}, estado: {:?}",
-                dron,
-                dron.get_state()
-            );*/
-            // Si ya existía el dron, se lo elimina, porque que me llegue nuevamente significa que se está moviendo.
-            let dron_id = dron.get_id();
-            self.places.remove_place(dron_id, PlaceType::Dron);
-
-            if dron.get_state() == DronState::ManagingIncident {
-                // Llegó a la posición del inc.
-                if let Some(inc_info) = dron.get_inc_id_to_resolve() {
-                    // Busca el incidente en el vector.
-                    let incident_index = self
-                        .incidents_to_resolve
-                        .iter()
-                        .position(|incident| incident.incident_info == inc_info);
-                    //.position(|incident| incident.incident_info.get_inc_id() == inc_id); // <--pre refactor decía esto
-
-                    match incident_index {
-                        Some(index) => {
-                            // Si el incidente ya existe, agrega el dron al vector de drones del incidente.
-                            self.incidents_to_resolve[index].drones.push(dron.clone());
-                        }
-                        None => {
-                            // Si no tengo guardado el inc_id_to_res, crea una nueva posicion con el dron respectivo.
-                            self.incidents_to_resolve.push(IncidentWithDrones {
-                                incident_info: inc_info,
-                                drones: vec![dron.clone()],
-                            });
-                        }
-                    }
+    /// Actualiza en el mapa todas las cámaras de un snapshot. Si es completo, antes quita las cámaras que ya
+    /// estaban en el mapa, porque las que no vienen en él ya no existen.
+    fn handle_cameras_snapshot_message(&mut self, snapshot: CamerasSnapshot) {
+        println!("UI: recibido snapshot de {} cámaras.", snapshot.get_cameras().len());
+        if snapshot.is_full() {
+            self.places.remove_places(PlaceType::Camera);
+            self.camera_coverage.clear();
+        }
+        for change in snapshot.get_cameras() {
+            self.update_camera_on_map(change.get_camera().clone());
+        }
+    }
+
+    /// Procesa las current_info que un dron no publicó mientras no tenía conexión, en el orden en que ocurrieron,
+    /// para completar su recorrido en el mapa en vez de mostrarlo saltar a su posición actual.
+    fn handle_history_message(&mut self, batch: TelemetryBatch) {
+        for dron in batch.get_samples() {
+            self.handle_dron_current_info(dron.clone());
+        }
+    }
+
+    fn handle_dron_current_info(&mut self, dron: DronCurrentInfo) {
+        /*println!(
+            "UI: recibido dron: {:?}, estado: {:?}",
+            dron,
+            dron.get_state()
+        );*/
+        let dron_id = dron.get_id();
+        self.inspector.record_dron(&dron, now_millis());
+        // Si se apagó, ya no se lo muestra.
+        if dron.get_state() == DronState::Offline {
+            self.dron_markers.remove(dron_id);
+            self.dron_watchdog.forget(dron_id);
+            self.dron_trails.remove(dron_id);
+            self.dron_assignments.remove(&dron_id);
+            return;
+        }
+        self.update_dron_assignment(&dron);
+
+        // El incidente pasa a asignado cuando un dron sale hacia él, y a en atención cuando llega.
+        if let Some(inc_info) = dron.get_inc_id_to_resolve() {
+            match dron.get_state() {
+                DronState::MustRespondToIncident | DronState::Flying => {
+                    self.advance_incident(&inc_info, IncidentState::Assigned)
                 }
+                DronState::ManagingIncident => self.advance_incident(&inc_info, IncidentState::InAttention),
+                _ => {}
             }
+        }
 
-            for incident in self.incidents_to_resolve.iter() {
-                if incident.drones.len() == 2 {
-                    let inc_info = &incident.incident_info;
-                    if let Some(mut incident) = self.hashmap_incidents.remove(inc_info) {
-                        incident.set_resolved();
-                        // Obtengo el source del incidente, para pasarle un place_type acorde al remove_place
-                        // y lo remuevo de la lista de places a mostrar en el mapa.
-                        let place_type = PlaceType::from_inc_source(incident.get_source());
-                        self.places.remove_place(inc_info.get_inc_id(), place_type);
-
-                        self.send_incident_for_publish(incident);
+        // Si se resuelven al avisarse que fueron atendidos, no se los resuelve al llegar los drones.
+        if dron.get_state() == DronState::ManagingIncident && !self.resolution_mode.waits_for_attended() {
+            // Llegó a la posición del inc.
+            if let Some(inc_info) = dron.get_inc_id_to_resolve() {
+                // Busca el incidente en el vector.
+                let incident_index = self
+                    .incidents_to_resolve
+                    .iter()
+                    .position(|incident| incident.incident_info == inc_info);
+                //.position(|incident| incident.incident_info.get_inc_id() == inc_id); // <--pre refactor decía esto
+
+                match incident_index {
+                    Some(index) => {
+                        // Si el incidente ya existe, agrega el dron al vector de drones del incidente.
+                        self.incidents_to_resolve[index].drones.push(dron.clone());
+                    }
+                    None => {
+                        // Si no tengo guardado el inc_id_to_res, crea una nueva posicion con el dron respectivo.
+                        self.incidents_to_resolve.push(IncidentWithDrones {
+                            incident_info: inc_info,
+                            drones: vec![dron.clone()],
+                        });
                     }
                 }
             }
+        }
 
-            // Crea lo necesario para dibujar al dron
-            let (lat, lon) = dron.get_current_position();
-            let dron_pos = Position::from_lon_lat(lon, lat);
-
-            // Se crea el label a mostrar por pantalla, según si está o no volando.
-            let dron_label;
-            if let Some((dir, speed)) = dron.get_flying_info() {
-                let (dir_lat, dir_lon) = dir;
-                // El dron está volando.
-                dron_label = format!(
-                    "Dron {}\n   dir: ({:.2}, {:.2})\n   vel: {} km/h",
-                    dron_id, dir_lat, dir_lon, speed
-                );
-            } else {
-                dron_label = format!("Dron {}", dron_id);
+        let arrived_incidents: Vec<IncidentInfo> = self
+            .incidents_to_resolve
+            .iter()
+            .filter(|incident| incident.drones.len() == 2)
+            .map(|incident| incident.incident_info)
+            .collect();
+        for inc_info in arrived_incidents {
+            self.resolve_incident(&inc_info);
+        }
+
+        // Crea lo necesario para dibujar al dron
+        let dron_pos = map_position(dron.get_current_position());
+        self.dron_trails.add_position(dron_id, dron_pos);
+
+        // Se crea el label a mostrar por pantalla, según si está o no volando.
+        let mut dron_label;
+        if let Some((dir, speed)) = dron.get_flying_info() {
+            let (dir_lat, dir_lon) = dir;
+            // El dron está volando.
+            dron_label = format!(
+                "{}\n   dir: ({:.2}, {:.2})\n   vel: {} m/s",
+                self.dron_registry.label(dron_id), dir_lat, dir_lon, speed
+            );
+        } else {
+            dron_label = self.dron_registry.label(dron_id);
+        }
+        // Si está registrado, se muestran también su modelo y su velocidad máxima.
+        if let Some(info) = self.dron_registry.get(dron_id) {
+            if let Some(model) = info.get_model() {
+                dron_label = format!("{}\n   modelo: {}", dron_label, model);
             }
+            if let Some(max_speed) = info.get_max_speed() {
+                dron_label = format!("{}\n   vel. máx: {} m/s", dron_label, max_speed);
+            }
+        }
+        // Si tiene incidentes pendientes, además del que atiende, se muestra cuántos.
+        let pending_incs = dron.get_pending_incidents().len();
+        if pending_incs > 0 {
+            dron_label = format!("{}\n   pendientes: {}", dron_label, pending_incs);
+        }
+        if dron.get_state() == DronState::ScheduledMaintenance {
+            dron_label = format!("{}\n   en mantenimiento programado", dron_label);
+        }
 
-            // Se crea el place y se lo agrega al mapa.
-            let dron_ui = Place {
-                position: dron_pos,
-                label: dron_label,
-                symbol: '🚁',
-                style: Style::default(),
-                id: dron.get_id(),
-                place_type: PlaceType::Dron, // Para luego buscarlo en el places.
-            };
+        // Se mueve su marcador a la nueva posición (o se lo crea, si es la primera vez que llega).
+        self.dron_markers.update(&dron, dron_pos, dron_label);
+    }
+
+    /// Registra a qué incidente va o qué incidente atiende el dron, para mostrarlo en el panel de incidentes.
+    fn update_dron_assignment(&mut self, dron: &DronCurrentInfo) {
+        let assigned_to = dron.get_inc_id_to_resolve().filter(|_| {
+            matches!(
+                dron.get_state(),
+                DronState::MustRespondToIncident | DronState::Flying | DronState::ManagingIncident
+            )
+        });
+        match assigned_to {
+            Some(inc_info) => self.dron_assignments.insert(dron.get_id(), inc_info),
+            None => self.dron_assignments.remove(&dron.get_id()),
+        };
+    }
+
+    /// Marca como resuelto al incidente, lo quita del mapa y lo publica. Si no se sabía que los drones habían
+    /// llegado, antes lo pasa a en atención.
+    fn resolve_incident(&mut self, inc_info: &IncidentInfo) {
+        self.advance_incident(inc_info, IncidentState::InAttention);
+        self.close_incident(inc_info, IncidentState::Resolved);
+    }
 
-            self.places.add_place(dron_ui);
+    /// Pasa al incidente a `final_state` (resuelto o cancelado), lo quita del mapa y lo publica.
+    fn close_incident(&mut self, inc_info: &IncidentInfo, final_state: IncidentState) {
+        self.attended_incidents
+            .retain(|attended| attended.get_inc_info() != *inc_info);
+        self.incidents_to_resolve
+            .retain(|incident| incident.incident_info != *inc_info);
+        if self.transition_incident(inc_info, final_state) {
+            self.hashmap_incidents.remove(inc_info);
+            // Obtengo el source del incidente, para pasarle un place_type acorde al remove_place
+            // y lo remuevo de la lista de places a mostrar en el mapa.
+            let place_type = PlaceType::from_inc_source(inc_info.get_src());
+            self.places.remove_place(inc_info.get_inc_id(), place_type);
         }
-        //let _ = self.repaint_tx.send(true);
-        //let _ = self.repaint_tx.send(true);
     }
 
-    /// Recibe un PublishMessage de topic Inc, y procesa el incidente recibido
-    /// (se lo guarda para continuar procesándolo, y lo muestra en la ui).
-    fn handle_incident_message(&mut self, msg: PublishMessage) {
-        if let Ok(inc) = Incident::from_bytes(msg.get_payload()) {
-            // Agregamos el incidente (add_incident) solamente si él no fue creado por sist monitoreo.
-            if *inc.get_source() == IncidentSource::Automated
-                && *inc.get_state() == IncidentState::ActiveIncident
-            {
-                self.add_incident(&inc);
+    /// Avanza al incidente por su ciclo de vida hasta `target` (asignado o en atención), pasando por los estados
+    /// intermedios que no se vieron. Si ya estaba en `target` o más adelante, no hace nada.
+    fn advance_incident(&mut self, inc_info: &IncidentInfo, target: IncidentState) {
+        for state in [IncidentState::Assigned, IncidentState::InAttention] {
+            let can_transition = self
+                .hashmap_incidents
+                .get(inc_info)
+                .is_some_and(|incident| incident.get_state().can_transition_to(state));
+            if can_transition {
+                self.transition_incident(inc_info, state);
             }
+            if state == target {
+                break;
+            }
+        }
+    }
+
+    /// Cambia el estado del incidente, actualiza su tooltip y publica el cambio, para que los demás sistemas sepan
+    /// en qué estado está. Devuelve si se pudo cambiar.
+    fn transition_incident(&mut self, inc_info: &IncidentInfo, state: IncidentState) -> bool {
+        let Some(incident) = self.hashmap_incidents.get_mut(inc_info) else {
+            return false;
+        };
+        if let Err(e) = incident.transition_to(state) {
+            println!("{}", e);
+            return false;
+        }
+        let incident = incident.clone();
+        self.record_in_history(&incident);
+        let place_type = PlaceType::from_inc_source(inc_info.get_src());
+        self.places
+            .set_tooltip(inc_info.get_inc_id(), place_type, Self::incident_tooltip(&incident));
+        self.send_incident_for_publish(incident);
+        true
+    }
+
+    /// Muestra en un panel lateral los incidentes activos, con su posición, estado, drones asignados y antigüedad,
+    /// para que el operador pueda marcarlos como resueltos o cancelarlos.
+    fn incidents_panel(&mut self, ctx: &egui::Context) {
+        if !self.incidents_panel_open {
+            return;
+        }
+        let mut incidents: Vec<&Incident> = self.hashmap_incidents.values().collect();
+        incidents.sort_by_key(|incident| (incident.get_created_at(), incident.get_id()));
+        let now = now_millis();
+        let can_manage_incidents = self.role().can_manage_incidents();
+        let mut to_resolve = vec![];
+        let mut to_cancel = vec![];
+        egui::SidePanel::right("incidents_panel")
+            .default_width(250.0)
+            .show(ctx, |ui| {
+                ui.heading("Incidentes");
+                if incidents.is_empty() {
+                    ui.label("No hay incidentes activos.");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for incident in incidents {
+                        let inc_info = incident.get_info();
+                        ui.group(|ui| {
+                            ui.label(format!(
+                                "Incidente {} ({})",
+                                incident.get_id(),
+                                incident.get_category().to_str()
+                            ));
+                            ui.label(format!("Posición: {}", incident.get_position()));
+                            ui.label(format!("Estado: {}", incident.get_state().to_str()));
+                            ui.label(format!("Drones: {}", self.assigned_drones_label(&inc_info)));
+                            ui.label(format!(
+                                "Antigüedad: {}",
+                                format_age(now.saturating_sub(incident.get_created_at()))
+                            ));
+                            if can_manage_incidents {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Resolver").clicked() {
+                                        to_resolve.push(inc_info);
+                                    }
+                                    if ui.button("Cancelar").clicked() {
+                                        to_cancel.push(inc_info);
+                                    }
+                                });
+                            }
+                        });
+                    }
+                });
+            });
+        for inc_info in to_resolve {
+            self.resolve_incident(&inc_info);
+        }
+        for inc_info in to_cancel {
+            self.close_incident(&inc_info, IncidentState::Cancelled);
+        }
+    }
+
+    /// Devuelve los nombres de los drones asignados al incidente, separados por coma, o "-" si no tiene.
+    fn assigned_drones_label(&self, inc_info: &IncidentInfo) -> String {
+        let mut dron_ids: Vec<u8> = self
+            .dron_assignments
+            .iter()
+            .filter(|(_, assigned_to)| *assigned_to == inc_info)
+            .map(|(dron_id, _)| *dron_id)
+            .collect();
+        if dron_ids.is_empty() {
+            return "-".to_string();
+        }
+        dron_ids.sort();
+        let labels: Vec<String> = dron_ids.iter().map(|dron_id| self.dron_registry.label(*dron_id)).collect();
+        labels.join(", ")
+    }
+
+    /// Recibe el aviso de que los drones permanecieron en un incidente. En modo simulación lo resuelve, y si no,
+    /// lo agrega a los que el operador debe marcar como resueltos.
+    fn handle_attended_message(&mut self, attended: IncidentAttended) {
+        let inc_info = attended.get_inc_info();
+        let is_active = self.hashmap_incidents.contains_key(&inc_info);
+        let already_pending = self
+            .attended_incidents
+            .iter()
+            .any(|pending| pending.get_inc_info() == inc_info);
+        if !is_active || already_pending {
+            return;
+        }
+        match self.resolution_mode {
+            ResolutionMode::Simulation => self.resolve_incident(&inc_info),
+            ResolutionMode::OperatorConfirmation => self.attended_incidents.push(attended),
+            ResolutionMode::OnArrival => {}
+        }
+    }
+
+    /// Muestra los incidentes atendidos, para que el operador los marque como resueltos.
+    fn attended_incidents_window(&mut self, ctx: &egui::Context) {
+        if self.attended_incidents.is_empty() {
+            return;
+        }
+        let can_manage_incidents = self.role().can_manage_incidents();
+        let mut to_resolve = vec![];
+        egui::Window::new("Incidentes atendidos")
+            .collapsible(false)
+            .show(ctx, |ui| {
+                for attended in &self.attended_incidents {
+                    ui.horizontal(|ui| {
+                        let drones: Vec<String> = attended
+                            .get_dron_ids()
+                            .iter()
+                            .map(|dron_id| self.dron_registry.label(*dron_id))
+                            .collect();
+                        ui.label(format!(
+                            "Incidente {}, atendido por los drones {}",
+                            attended.get_inc_info().get_inc_id(),
+                            drones.join(", ")
+                        ));
+                        if can_manage_incidents && ui.button("Marcar resuelto").clicked() {
+                            to_resolve.push(attended.get_inc_info());
+                        }
+                    });
+                }
+            });
+        for inc_info in to_resolve {
+            self.resolve_incident(&inc_info);
+        }
+    }
+
+    /// Procesa el incidente recibido por el topic Inc
+    /// (se lo guarda para continuar procesándolo, y lo muestra en la ui).
+    fn handle_incident_message(&mut self, inc: Incident) {
+        // Agregamos el incidente (add_incident) solamente si él no fue creado por sist monitoreo.
+        if *inc.get_source() == IncidentSource::Automated
+            && *inc.get_state() == IncidentState::Reported
+        {
+            self.add_incident(&inc);
         }
     }
 
@@ -358,28 +773,63 @@ impl UISistemaMonitoreo {
     /// y lo agrega a un hashmap para continuar procesándolo (Aux: rever tema ids que quizás se pisen cuando camaras publiquen incs).
     fn add_incident(&mut self, incident: &Incident) {
         let custom_style = Self::create_style_with_color(255, 0, 0); // Color rojo
-        let new_place_incident = self.create_place_for_incident(incident, &custom_style);
+        let new_place_incident = Self::create_place_for_incident(incident, &custom_style);
         self.places.add_place(new_place_incident);
         self.store_incident_info(incident);
     }
 
-    fn create_place_for_incident(&self, incident: &Incident, custom_style: &Style) -> Place {
+    pub(super) fn create_place_for_incident(incident: &Incident, custom_style: &Style) -> Place {
         let place_type = PlaceType::from_inc_source(incident.get_source());
-        let (lat, lon) = incident.get_position();
         Place {
-            position: Position::from_lon_lat(lon, lat),
+            position: map_position(incident.get_position()),
             label: format!("Incident {}", incident.get_id()),
             symbol: '⚠',
             style: custom_style.clone(),
             id: incident.get_id(),
             place_type,
+            tooltip: Some(Self::incident_tooltip(incident)),
+        }
+    }
+
+    /// Devuelve el texto que se muestra al pasar el mouse sobre el incidente: su tipo, gravedad, prioridad,
+    /// cuándo se creó, su descripción, y cuándo pasó a cada estado.
+    fn incident_tooltip(incident: &Incident) -> String {
+        let mut tooltip = format!(
+            "Tipo: {}\nGravedad: {}\nPrioridad: {}\nCreado: {}",
+            incident.get_category().to_str(),
+            incident.get_severity().to_str(),
+            incident.get_priority().to_str(),
+            format_millis(incident.get_created_at())
+        );
+        if !incident.get_description().is_empty() {
+            tooltip = format!("{}\n{}", tooltip, incident.get_description());
+        }
+        for transition in incident.get_history() {
+            tooltip = format!(
+                "{}\n{}: {}",
+                tooltip,
+                transition.get_state().to_str(),
+                format_millis(transition.get_at())
+            );
         }
+        tooltip
     }
 
     fn store_incident_info(&mut self, incident: &Incident) {
         let inc_info = IncidentInfo::new(incident.get_id(), *incident.get_source());
         let inc_to_store = incident.clone();
         self.hashmap_incidents.insert(inc_info, inc_to_store);
+        self.record_in_history(incident);
+    }
+
+    /// Guarda el estado actual del incidente en el historial, si se configuró, y en el inspector.
+    fn record_in_history(&mut self, incident: &Incident) {
+        self.inspector.record_incident(incident, now_millis());
+        if let Some(incident_history) = &self.incident_history {
+            if let Err(e) = incident_history.record(incident) {
+                println!("Error al guardar el incidente {} en el historial: {:?}", incident.get_id(), e);
+            }
+        }
     }
 
     fn get_next_incident_id(&mut self) -> u8 {
@@ -387,70 +837,75 @@ impl UISistemaMonitoreo {
         self.last_incident_id
     }
 
-    fn handle_disconnection_message(
-        &mut self,
-        publish_message: PublishMessage,
-    ) -> Result<(), Utf8Error> {
-        let will_content_res = WillContent::will_content_from_string(from_utf8(&publish_message.get_payload())?);
-        
-        if let Ok(will_content) = will_content_res {
-            self.process_will_content(will_content)?;
-        }
-
-        Ok(())
-    }
-
-    fn process_will_content(&mut self, will_content: WillContent) -> Result<(), Utf8Error> {
+    fn process_will_content(&mut self, will_content: WillContent) {
         let app_type = will_content.get_app_type_identifier();
         let id_option = will_content.get_id(); // es un option porque solo dron tiene id en este contexto.
         let place_type = PlaceType::from_app_type_will_content(&app_type);
 
         match app_type {
             AppType::Cameras => self.handle_camera_disconnection(place_type),
-            AppType::Dron => self.handle_drone_disconnection(id_option, place_type),
+            AppType::Dron => self.handle_drone_disconnection(id_option),
             AppType::Monitoreo => {},
         }
-        Ok(())
     }
 
     fn handle_camera_disconnection(&mut self, place_type: PlaceType) {
         // Se eliminan Todas las cámaras
-        self.places.remove_places(place_type)
+        self.places.remove_places(place_type);
+        self.camera_coverage.clear();
     }
 
-    fn handle_drone_disconnection(&mut self, id_option: Option<u8>, place_type: PlaceType) {
+    fn handle_drone_disconnection(&mut self, id_option: Option<u8>) {
         if let Some(id) = id_option {
             // Se elimina el dron de id indicado, porque el mismo se desconectó.
-            self.places.remove_place(id, place_type)
+            self.dron_markers.remove(id);
+            self.dron_trails.remove(id);
+            self.dron_watchdog.forget(id);
+            self.dron_assignments.remove(&id);
         }
     }
 
-    fn handle_mqtt_messages(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |_ui| {
-            if let Ok(publish_message) = self.publish_message_rx.try_recv() {
-                self.route_message(publish_message);
-            }
-        });
+    /// Registra el latido recibido de un dron, para vigilar que siga respondiendo.
+    fn handle_heartbeat_message(&mut self, heartbeat: DronHeartbeat) {
+        if heartbeat.get_state() == DronState::Offline {
+            self.dron_watchdog.forget(heartbeat.get_id());
+        } else {
+            self.dron_watchdog.register(&heartbeat, Instant::now());
+        }
     }
 
-    fn route_message(&mut self, publish_message: PublishMessage) {
-        let topic_str = publish_message.get_topic_name();
-        if let Ok(topic) = AppsMqttTopics::topic_from_str(&topic_str) {
-            match topic {
-                AppsMqttTopics::CameraTopic => {
-                    self.handle_camera_message(publish_message)
-                },
-                AppsMqttTopics::DronTopic => {
-                    self.handle_drone_message(publish_message)
-                },
-                AppsMqttTopics::IncidentTopic => {
-                    self.handle_incident_message(publish_message)
-                },
-                AppsMqttTopics::DescTopic => {
-                    println!("Recibido mensaje de desconexión.");
-                    let _ = self.handle_disconnection_message(publish_message);
-                },
+    /// Colorea el fondo de cada dron según qué tan reciente es su último latido: gris si está atrasado, y rojo si
+    /// se lo considera perdido.
+    fn update_drones_health(&mut self) {
+        for (dron_id, health) in self.dron_watchdog.healths(Instant::now()) {
+            self.dron_markers.set_health(dron_id, health);
+        }
+    }
+
+    /// Procesa todos los mensajes recibidos desde el frame anterior, que ya llegan decodificados.
+    fn handle_ui_events(&mut self) {
+        let (events, feed_entries) = self.ui_events.take();
+        for entry in feed_entries {
+            self.topic_feed.push(entry);
+        }
+        for event in events {
+            self.route_event(event);
+        }
+    }
+
+    fn route_event(&mut self, event: UiEvent) {
+        match event {
+            UiEvent::Camera(camera) => self.handle_camera_message(camera),
+            UiEvent::CamerasSnapshot(snapshot) => self.handle_cameras_snapshot_message(snapshot),
+            UiEvent::Dron(dron) => self.handle_dron_current_info(dron),
+            UiEvent::DronHistory(batch) => self.handle_history_message(batch),
+            UiEvent::Incident(incident) => self.handle_incident_message(incident),
+            UiEvent::Disconnection(will_content) => {
+                println!("Recibido mensaje de desconexión.");
+                self.process_will_content(will_content);
             }
+            UiEvent::Heartbeat(heartbeat) => self.handle_heartbeat_message(heartbeat),
+            UiEvent::IncidentAttended(attended) => self.handle_attended_message(attended),
         }
     }
 
@@ -464,32 +919,103 @@ impl UISistemaMonitoreo {
             .frame(rimless)
             .show(ctx, |ui| {
                 let my_position = places::obelisco();
+                // Se dibujan, y se pueden clickear, solamente los elementos de las capas y filtros elegidos. Si se
+                // está reproduciendo una sesión, se dibujan los de la reproducción, que no se pueden inspeccionar.
+                let (visible_places, visible_drones, dron_trails, camera_coverage) = match &self.replay {
+                    Some(replay) => (
+                        replay.places().filtered(|place| {
+                            let incident_state = replay.incident_state(&place.place_type, place.id);
+                            self.map_layers.shows_place(&place.place_type, incident_state)
+                        }),
+                        replay
+                            .get_dron_markers()
+                            .filtered(|battery_lvl| self.map_layers.shows_dron(battery_lvl)),
+                        replay.get_dron_trails(),
+                        replay.get_camera_coverage(),
+                    ),
+                    None => (
+                        self.places.filtered(|place| {
+                            let incident_state = self.incident_state(&place.place_type, place.id);
+                            self.map_layers.shows_place(&place.place_type, incident_state)
+                        }),
+                        self.dron_markers.filtered(|battery_lvl| self.map_layers.shows_dron(battery_lvl)),
+                        &self.dron_trails,
+                        &self.camera_coverage,
+                    ),
+                };
+                let mut markers = vec![];
+                if self.replay.is_none() {
+                    markers = visible_places.markers();
+                    markers.extend(
+                        visible_drones
+                            .positions()
+                            .into_iter()
+                            .map(|(dron_id, position)| (PlaceType::Dron, dron_id, position)),
+                    );
+                }
+                self.marker_picker.set_markers(markers);
                 let tiles = self
                     .providers
                     .get_mut(&self.selected_provider)
                     .unwrap()
                     .as_mut();
-                let map = Map::new(Some(tiles), &mut self.map_memory, my_position)
-                    .with_plugin(self.places.clone())
-                    .with_plugin(super::super::plugins::images(&mut self.images_plugin_data))
-                    .with_plugin(super::super::plugins::CustomShapes {})
-                    .with_plugin(&mut self.click_watcher);
+                let mut map = Map::new(Some(tiles), &mut self.map_memory, my_position);
+                if self.map_layers.heatmap {
+                    map = map.with_plugin(&self.incident_heatmap);
+                }
+                if self.map_layers.coverage {
+                    map = map.with_plugin(camera_coverage);
+                }
+                map = map.with_plugin(visible_places);
+                if self.map_layers.trails {
+                    map = map.with_plugin(dron_trails);
+                }
+                let map = map
+                    .with_plugin(&visible_drones)
+                    .with_plugin(&mut self.click_watcher)
+                    .with_plugin(&mut self.marker_picker);
 
                 ui.add(map);
+                self.inspect_picked_marker();
                 self.setup_map_controls(ui);
             });
     }
 
+    /// Devuelve el estado del incidente del marcador de tipo `place_type` e id `id`, o `None` si no es un incidente
+    /// activo.
+    fn incident_state(&self, place_type: &PlaceType, id: u8) -> Option<IncidentState> {
+        let inc_info = IncidentInfo::new(id, place_type.to_inc_source()?);
+        self.hashmap_incidents.get(&inc_info).map(|incident| *incident.get_state())
+    }
+
+    /// Si se clickeó un dron, una cámara o un incidente, abre su ventana en el inspector.
+    fn inspect_picked_marker(&mut self) {
+        let entity = match self.marker_picker.take_picked() {
+            Some((PlaceType::Dron, id)) => Entity::Dron(id),
+            Some((PlaceType::Camera, id)) => Entity::Camera(id),
+            Some((place_type, id)) => match place_type.to_inc_source() {
+                Some(source) => Entity::Incident(IncidentInfo::new(id, source)),
+                None => return,
+            },
+            None => return,
+        };
+        self.inspector.open(entity);
+    }
+
     fn setup_map_controls(&mut self, ui: &mut egui::Ui) {
         use super::super::windows::*;
         zoom(ui, &mut self.map_memory);
         go_to_my_position(ui, &mut self.map_memory);
-        self.click_watcher.show_position(ui);
+        if self.click_watcher.show_position(ui, self.role().can_manage_incidents()) {
+            if let Some(clicked_at) = self.click_watcher.clicked_at {
+                self.fill_incident_position(clicked_at);
+            }
+            self.incident_dialog_open = true;
+        }
         controls(
             ui,
             &mut self.selected_provider,
             &mut self.providers.keys(),
-            &mut self.images_plugin_data,
         );
     }
 
@@ -497,30 +1023,93 @@ impl UISistemaMonitoreo {
         egui::TopBottomPanel::top("top_menu").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 self.incident_menu(ui);
-                self.exit_menu(ui, ctx);
+                if self.role().can_command_drones() {
+                    self.dron_command_menu(ui);
+                }
+                self.layers_menu(ui);
+                self.session_menu(ui);
+                self.exit_menu(ui);
+                ui.separator();
+                self.connection_status_indicator(ui);
+                self.operator_indicator(ui);
             });
         });
     }
 
     fn incident_menu(&mut self, ui: &mut egui::Ui) {
         ui.menu_button("Incidente", |ui| {
-            if !self.incident_dialog_open && ui.button("Alta Incidente").clicked() {
-                self.incident_dialog_open = true;
+            if ui.toggle_value(&mut self.incidents_panel_open, "Panel de incidentes").clicked() {
+                ui.close_menu();
             }
-            if self.incident_dialog_open {
-                self.incident_dialog(ui);
+            if ui.button("Historial").clicked() {
+                self.history_window.open();
+                ui.close_menu();
+            }
+            if self.role().can_manage_incidents() && ui.button("Alta Incidente").clicked() {
+                // Si no se escribió una posición, se propone la del último click en el mapa.
+                if self.latitude.is_empty() && self.longitude.is_empty() {
+                    if let Some(clicked_at) = self.click_watcher.clicked_at {
+                        self.fill_incident_position(clicked_at);
+                    }
+                }
+                self.incident_dialog_open = true;
+                ui.close_menu();
             }
         });
     }
 
-    fn incident_dialog(&mut self, ui: &mut egui::Ui) {
-        ui.add_space(5.0);
-        ui.horizontal(|ui| {
-            self.incident_position_inputs(ui);
-            if ui.button("OK").clicked() {
-                self.process_incident();
+    /// Muestra la ventana de alta de incidentes, si está abierta. Mientras lo está, cada click en el mapa completa
+    /// la latitud y longitud con la posición clickeada.
+    fn incident_dialog_window(&mut self, ctx: &egui::Context) {
+        if let Some(clicked_at) = self.click_watcher.take_new_click() {
+            if self.incident_dialog_open {
+                self.fill_incident_position(clicked_at);
             }
-        });
+        }
+        if !self.incident_dialog_open || !self.role().can_manage_incidents() {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Alta incidente")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Hacé click en el mapa para elegir la posición.");
+                ui.horizontal(|ui| self.incident_position_inputs(ui));
+                ui.horizontal(|ui| self.incident_detail_inputs(ui));
+                if ui.button("OK").clicked() {
+                    self.process_incident();
+                }
+            });
+        // Se cierra con la cruz, o al crear el incidente.
+        self.incident_dialog_open &= open;
+    }
+
+    /// Completa la latitud y longitud del alta de incidentes con `position`.
+    fn fill_incident_position(&mut self, position: Position) {
+        self.latitude = format!("{:.6}", position.lat());
+        self.longitude = format!("{:.6}", position.lon());
+    }
+
+    /// Muestra los campos para elegir el tipo y la gravedad del incidente, y escribir su descripción.
+    fn incident_detail_inputs(&mut self, ui: &mut egui::Ui) {
+        ui.label("Tipo:");
+        egui::ComboBox::from_id_source("incident_category")
+            .selected_text(self.incident_category.to_str())
+            .show_ui(ui, |ui| {
+                for category in IncidentCategory::ALL {
+                    ui.selectable_value(&mut self.incident_category, category, category.to_str());
+                }
+            });
+        ui.label("Gravedad:");
+        for severity in IncidentSeverity::ALL {
+            ui.selectable_value(&mut self.incident_severity, severity, severity.to_str());
+        }
+        ui.label("Descripción:");
+        ui.add_sized(
+            [200.0, 20.0],
+            egui::TextEdit::singleline(&mut self.incident_description).char_limit(MAX_DESCRIPTION_LEN),
+        );
     }
 
     fn incident_position_inputs(&mut self, ui: &mut egui::Ui) {
@@ -534,6 +1123,10 @@ impl UISistemaMonitoreo {
             [100.0, 20.0],
             egui::TextEdit::singleline(&mut self.longitude),
         );
+        ui.label("Prioridad:");
+        ui.selectable_value(&mut self.incident_priority, IncidentPriority::Low, "Baja");
+        ui.selectable_value(&mut self.incident_priority, IncidentPriority::Medium, "Media");
+        ui.selectable_value(&mut self.incident_priority, IncidentPriority::High, "Alta");
     }
 
     fn process_incident(&mut self) {
@@ -543,28 +1136,158 @@ impl UISistemaMonitoreo {
         }
     }
 
-    fn parse_location(&self) -> Result<(f64, f64), &'static str> {
-        let latitude_result = self.latitude.to_string().parse::<f64>();
-        let longitude_result = self.longitude.to_string().parse::<f64>();
+    fn parse_location(&self) -> Result<GeoPosition, &'static str> {
+        let latitude_result = self.latitude.trim().parse::<f64>();
+        let longitude_result = self.longitude.trim().parse::<f64>();
 
         match (latitude_result, longitude_result) {
-            (Ok(latitude), Ok(longitude)) => Ok((latitude, longitude)),
+            (Ok(latitude), Ok(longitude)) => GeoPosition::new(latitude, longitude).map_err(|_| {
+                "La latitud debe estar entre -90 y 90, y la longitud entre -180 y 180. Por favor, intente de nuevo."
+            }),
             (Err(_), _) => Err("Latitud ingresada incorrectamente. Por favor, intente de nuevo."),
             (_, Err(_)) => Err("Longitud ingresada incorrectamente. Por favor, intente de nuevo."),
         }
     }
 
-    fn handle_successful_parse(&mut self, location: (f64, f64)) {
+    fn handle_successful_parse(&mut self, location: GeoPosition) {
         let incident = Incident::new(
             self.get_next_incident_id(),
             location,
             IncidentSource::Manual,
-        );
+        )
+        .with_priority(self.incident_priority)
+        .with_severity(self.incident_severity)
+        .with_category(self.incident_category)
+        .with_description(self.incident_description.trim());
         self.add_incident(&incident);
         self.send_incident_for_publish(incident);
+        self.incident_description.clear();
+        self.latitude.clear();
+        self.longitude.clear();
         self.incident_dialog_open = false;
     }
 
+    /// Permite elegir las capas y filtros del mapa.
+    fn layers_menu(&mut self, ui: &mut egui::Ui) {
+        let heatmap_available = self.incident_history.is_some();
+        let update_heatmap = ui
+            .menu_button("Capas", |ui| self.map_layers.show_menu(ui, heatmap_available))
+            .inner
+            .unwrap_or(false);
+        if update_heatmap {
+            self.update_incident_heatmap();
+        }
+    }
+
+    /// Vuelve a calcular el mapa de calor con los incidentes del historial creados en el período elegido.
+    fn update_incident_heatmap(&mut self) {
+        let Some(incident_history) = &self.incident_history else {
+            return;
+        };
+        let query = IncidentQuery::new().with_created_between(Some(self.map_layers.heatmap_from(now_millis())), None);
+        match incident_history.query(&query) {
+            Ok(incidents) => {
+                let positions: Vec<GeoPosition> = incidents.iter().map(|incident| incident.get_position()).collect();
+                self.incident_heatmap = IncidentHeatmap::from_positions(&positions);
+            }
+            Err(e) => println!("Error al leer el historial para el mapa de calor: {:?}", e),
+        }
+    }
+
+    /// Permite elegir una sesión registrada y reproducirla en el mapa.
+    fn session_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Sesión", |ui| {
+            let Some(session_log) = &self.session_log else {
+                ui.label("Las sesiones no se registran: falta la property session-log-file.");
+                return;
+            };
+            if ui.button("Buscar sesiones").clicked() {
+                match session_log.load_sessions() {
+                    Ok(sessions) => self.replay_sessions = sessions,
+                    Err(e) => println!("Error al leer el registro de sesiones: {:?}", e),
+                }
+            }
+            let mut selected = None;
+            for (i, session) in self.replay_sessions.iter().enumerate().rev() {
+                let (Some(first), Some(last)) = (session.first(), session.last()) else {
+                    continue;
+                };
+                let text = format!(
+                    "Reproducir {} - {} ({} mensajes)",
+                    format_millis(first.get_at()),
+                    format_millis(last.get_at()),
+                    session.len()
+                );
+                if ui.button(text).clicked() {
+                    selected = Some(i);
+                    ui.close_menu();
+                }
+            }
+            if let Some(i) = selected {
+                self.start_replay(self.replay_sessions[i].clone());
+            }
+        });
+    }
+
+    /// Empieza a reproducir la sesión con los `messages` registrados, junto con los incidentes del historial.
+    fn start_replay(&mut self, messages: Vec<LoggedMessage>) {
+        let incidents = match self.incident_history.as_ref().map(IncidentHistoryStore::load) {
+            Some(Ok(incidents)) => incidents,
+            Some(Err(e)) => {
+                println!("Error al leer el historial para la reproducción: {:?}", e);
+                vec![]
+            }
+            None => vec![],
+        };
+        self.replay = SessionReplay::new(messages, incidents, self.dron_registry.clone());
+    }
+
+    /// Si se está reproduciendo una sesión, la avanza y muestra sus controles.
+    fn session_replay_controls(&mut self, ctx: &egui::Context) {
+        if let Some(replay) = &mut self.replay {
+            replay.tick(Instant::now());
+            if !replay.show_controls(ctx) {
+                self.replay = None;
+            }
+        }
+    }
+
+    /// Permite enviarle a un dron un comando de control (ver `DronCommand`), escrito como texto.
+    fn dron_command_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Dron", |ui| {
+            if !self.command_dialog_open && ui.button("Enviar comando").clicked() {
+                self.command_dialog_open = true;
+            }
+            if self.command_dialog_open {
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.label("Id del dron:");
+                    ui.add_sized([40.0, 20.0], egui::TextEdit::singleline(&mut self.command_dron_id));
+                    ui.label("Comando:");
+                    ui.add_sized([150.0, 20.0], egui::TextEdit::singleline(&mut self.command_text));
+                    if ui.button("OK").clicked() {
+                        self.process_dron_command();
+                    }
+                });
+                ui.label("speed <m/s>, battery <min> <max>, recall, pause o resume");
+            }
+        });
+    }
+
+    fn process_dron_command(&mut self) {
+        let Ok(dron_id) = self.command_dron_id.trim().parse::<u8>() else {
+            self.send_error_message("Id de dron ingresado incorrectamente. Por favor, intente de nuevo.");
+            return;
+        };
+        let Ok(command) = DronCommand::from_bytes(self.command_text.clone().into_bytes()) else {
+            self.send_error_message("Comando ingresado incorrectamente. Por favor, intente de nuevo.");
+            return;
+        };
+        let _ = self.publish_command_tx.send((dron_id, command));
+        self.command_dialog_open = false;
+        self.command_text.clear();
+    }
+
     fn send_error_message(&self, error_message: &'static str) {
         match self.error_tx.send(error_message.to_string()) {
             Ok(_) => println!("Mensaje de error enviado correctamente."),
@@ -572,28 +1295,123 @@ impl UISistemaMonitoreo {
         }
     }
 
+    /// Muestra el estado de la conexión con el broker (al pasar el mouse, el último error), y un botón para
+    /// reconectarse sin esperar al próximo intento.
+    fn connection_status_indicator(&mut self, ui: &mut egui::Ui) {
+        if let Some(connection_status_rx) = &self.connection_status_rx {
+            if let Some(status) = connection_status_rx.try_iter().last() {
+                self.connection_status = status;
+            }
+        }
+        let (text, color) = match self.connection_status {
+            ConnectionStatus::Connected => ("● Conectado", Color32::from_rgb(0, 180, 0)),
+            ConnectionStatus::Reconnecting { .. } => ("● Reconectando", Color32::from_rgb(255, 165, 0)),
+            ConnectionStatus::Offline { .. } => ("● Sin conexión", Color32::RED),
+        };
+        let label = ui.colored_label(color, text);
+        if let Some(last_error) = self.connection_status.get_last_error() {
+            label.on_hover_text(format!("Último error: {}", last_error));
+        }
+        if let Some(reconnect_tx) = &self.reconnect_tx {
+            if ui.button("Reconectar").clicked() {
+                let _ = reconnect_tx.send(());
+            }
+        }
+    }
+
+    /// Devuelve el rol de quien inició sesión. Si no se pide iniciar sesión, se puede hacer todo.
+    fn role(&self) -> OperatorRole {
+        match (&self.operator, &self.operator_login) {
+            (Some(operator), _) => operator.get_role(),
+            (None, Some(_)) => OperatorRole::Viewer,
+            (None, None) => OperatorRole::Admin,
+        }
+    }
+
+    /// Muestra quién inició sesión y con qué rol, y un botón para cerrar la sesión y volver a la pantalla de inicio.
+    fn operator_indicator(&mut self, ui: &mut egui::Ui) {
+        let Some(operator) = &self.operator else {
+            return;
+        };
+        ui.separator();
+        ui.label(format!("{} ({})", operator.get_user(), operator.get_role().to_str()));
+        if ui.button("Cerrar sesión").clicked() {
+            self.operator = None;
+            self.incident_dialog_open = false;
+            self.command_dialog_open = false;
+        }
+    }
+
+    /// Si hay que iniciar sesión y todavía no se lo hizo, muestra la pantalla de inicio en lugar del mapa, mientras
+    /// se siguen procesando los mensajes recibidos. Devuelve si se la mostró.
+    fn login_screen(&mut self, ctx: &egui::Context) -> bool {
+        let Some(operator_login) = &self.operator_login else {
+            return false;
+        };
+        if self.operator.is_some() {
+            return false;
+        }
+        self.operator = self.login_form.show(ctx, operator_login);
+        self.handle_ui_events();
+        self.update_drones_health();
+        ctx.request_repaint_after(Duration::from_millis(150));
+        self.exit_dialog_window(ctx);
+        self.check_if_window_is_closed(ctx);
+        true
+    }
+
     /// Se encarga de ver si se hizo click en el botón `Salir` del panel superior (arriba a la izquierda)
-    /// y en ese caso sale.
-    fn exit_menu(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+    /// y en ese caso pide confirmar la salida.
+    fn exit_menu(&mut self, ui: &mut egui::Ui) {
         if ui.button("Salir").clicked() {
-            self.exit(ctx);
+            self.exit_dialog_open = true;
         }
     }
 
-    /// Sale.
-    fn exit(&self, ctx: &egui::Context) {
-        if let Err(e) = self.exit_tx.send(true) {                
-            println!("Error al intentar salir: {:?}", e);
+    /// Muestra la ventana para confirmar la salida, si se pidió salir.
+    fn exit_dialog_window(&mut self, ctx: &egui::Context) {
+        if !self.exit_dialog_open {
             return;
         }
-        println!("Iniciando proceso para salir");
+        let open_incidents = self.hashmap_incidents.values().filter(|incident| !incident.is_closed()).count();
+        let text = exit_confirmation_text(open_incidents, &self.connection_status);
+        egui::Window::new("Salir")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+            .show(ctx, |ui| {
+                ui.label(text);
+                ui.horizontal(|ui| {
+                    if ui.button("Salir").clicked() {
+                        self.exit(ctx);
+                    }
+                    if ui.button("Cancelar").clicked() {
+                        self.exit_dialog_open = false;
+                    }
+                });
+            });
+    }
+
+    /// Sale: avisa al hilo de salida para que desconecte al cliente mqtt, y cierra la ventana. Si el aviso no se
+    /// puede enviar (ie el hilo de salida ya terminó), igualmente cierra la ventana.
+    fn exit(&mut self, ctx: &egui::Context) {
+        self.exit_dialog_open = false;
+        if !self.exiting {
+            self.exiting = true;
+            println!("Iniciando proceso para salir");
+            if let Err(e) = self.exit_tx.send(true) {
+                println!("Error al avisar que se sale: {:?}", e);
+            }
+        }
         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
     }
 
-    /// Se fija si se hizo click en la cruz roja de arriba a la derecha de la ventana, y sale.
-    fn check_if_window_is_closed(&self, ctx: &egui::Context) {
-        if ctx.input(|i| i.viewport().close_requested()) {
-            self.exit(ctx);
+    /// Se fija si se hizo click en la cruz roja de arriba a la derecha de la ventana, y en ese caso, salvo que ya
+    /// se haya confirmado la salida, cancela el cierre y pide confirmarla.
+    fn check_if_window_is_closed(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.viewport().close_requested()) && !self.exiting {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.exit_dialog_open = true;
         }
     }
 
@@ -654,11 +1472,49 @@ impl UISistemaMonitoreo {
 
 impl eframe::App for UISistemaMonitoreo {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.login_screen(ctx) {
+            return;
+        }
+        // Los paneles lateral e inferior se muestran antes que los paneles centrales, para que el mapa ocupe el
+        // resto de la ventana.
+        self.incidents_panel(ctx);
+        self.topic_feed.show(ctx);
+        self.session_replay_controls(ctx);
         self.request_repaint_after(150, ctx);
         self.draw_ui_wrapper(ctx);
-        self.handle_mqtt_messages(ctx);
+        self.handle_ui_events();
+        self.update_drones_health();
         self.setup_map(ctx);
         self.setup_top_menu(ctx);
+        self.attended_incidents_window(ctx);
+        self.history_window.show(ctx, self.incident_history.as_ref());
+        self.inspector.show(ctx, &self.dron_registry);
+        self.incident_dialog_window(ctx);
+        self.exit_dialog_window(ctx);
         self.check_if_window_is_closed(ctx);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{exit_confirmation_text, format_age};
+    use crate::mqtt::client::mqtt_client_connection_status::ConnectionStatus;
+
+    #[test]
+    fn test_1_la_antiguedad_se_muestra_en_la_unidad_mas_grande() {
+        assert_eq!(format_age(45_999), "45s");
+        assert_eq!(format_age(190_000), "3m 10s");
+        assert_eq!(format_age(7_500_000), "2h 05m");
+    }
+
+    #[test]
+    fn test_2_al_salir_se_avisa_de_los_incidentes_abiertos_y_de_la_falta_de_conexion() {
+        let text = exit_confirmation_text(0, &ConnectionStatus::Connected);
+        assert_eq!(text.lines().count(), 1);
+
+        let offline = ConnectionStatus::Offline { last_error: None };
+        let text = exit_confirmation_text(2, &offline);
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(lines, vec!["Quedan 2 incidentes sin cerrar.", "No hay conexión con el broker: se saldrá sin avisarle."]);
+    }
+}
@@ -1,5 +1,3 @@
-use super::plugins::ImagesPluginData;
-
 use super::vendor::sources::Attribution;
 use super::vendor::MapMemory;
 use crate::apps::sist_monitoreo::ui_sistema_monitoreo::Provider;
@@ -21,12 +19,11 @@ pub fn acknowledge(ui: &Ui, attribution: Attribution) {
         });
 }
 
-/// Controles para ajustar la rotación y escala de las imágenes.
+/// Controles para elegir el proveedor de tiles del mapa.
 pub fn controls(
     ui: &Ui,
     selected_provider: &mut Provider,
     possible_providers: &mut dyn Iterator<Item = &Provider>,
-    image: &mut ImagesPluginData,
 ) {
     Window::new("Satellite")
         .collapsible(false)
@@ -44,12 +41,6 @@ pub fn controls(
                         }
                     });
             });
-
-            ui.collapsing("Images plugin", |ui| {
-                ui.add(egui::Slider::new(&mut image.angle, 0.0..=360.0).text("Rotate"));
-                ui.add(egui::Slider::new(&mut image.x_scale, 0.1..=3.0).text("Scale X"));
-                ui.add(egui::Slider::new(&mut image.y_scale, 0.1..=3.0).text("Scale Y"));
-            });
         });
 }
 
@@ -0,0 +1,70 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Nivel de detalle del log: se escriben los eventos del nivel configurado y de los niveles más graves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn from_config_value(value: &str) -> Result<Self, Error> {
+        match value {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Valor inválido para log_level: {:?}", value),
+            )),
+        }
+    }
+
+    /// Etiqueta con la que se marcan en el log los eventos de este nivel.
+    pub fn label(&self) -> &str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warn,
+            2 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// Nivel compartido por un logger y sus clones, que puede cambiarse mientras se usan (ie al recargar la configuración).
+#[derive(Debug)]
+pub struct SharedLogLevel(AtomicU8);
+
+impl SharedLogLevel {
+    pub fn new(level: LogLevel) -> Self {
+        SharedLogLevel(AtomicU8::new(level.as_u8()))
+    }
+
+    pub fn get(&self) -> LogLevel {
+        LogLevel::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, level: LogLevel) {
+        self.0.store(level.as_u8(), Ordering::Relaxed);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod log_level;
 pub mod string_logger;
 pub mod string_logger_writer;
 pub mod time;
\ No newline at end of file
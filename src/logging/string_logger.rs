@@ -1,10 +1,15 @@
-use std::{sync::mpsc::{self, Sender}, thread::JoinHandle};
+use std::{sync::{mpsc::{self, Sender}, Arc}, thread::JoinHandle};
 
-use super::string_logger_writer::StringLoggerWriter;
+use super::{
+    log_level::{LogLevel, SharedLogLevel},
+    string_logger_writer::StringLoggerWriter,
+};
 
 #[derive(Debug)]
 pub struct StringLogger {
     tx: Option<Sender<String>>,
+    level: Arc<SharedLogLevel>, // compartido con sus clone_ref, para poder cambiarlo en ejecución.
+    context: Option<String>, // se antepone a cada evento, ie el cliente de una conexión del servidor.
 }
 
 impl StringLogger {
@@ -23,20 +28,72 @@ impl StringLogger {
     /// Extremo de envío del string logger.
     /// Es el encargado de enviar las strings a ser loggueadas.
     pub fn new(tx: Sender<String>) -> Self {
-        Self { tx: Some(tx) }
+        Self {
+            tx: Some(tx),
+            level: Arc::new(SharedLogLevel::new(LogLevel::default())),
+            context: None,
+        }
     }
 
     
     // Ejemplo: logger.log(format!("Ha ocurrido un evento: {}", string_event));
     /// Función a llamar para grabar en el log el evento pasado por parámetro.
     pub fn log(&self, event: String) {
+        self.send(self.add_context(event));
+    }
+
+    /// Graba el evento marcado con su nivel `level`, si el nivel configurado lo incluye.
+    pub fn log_with_level(&self, level: LogLevel, event: String) {
+        if level <= self.level.get() {
+            self.send(format!("{} {}", level.label(), self.add_context(event)));
+        }
+    }
+
+    fn add_context(&self, event: String) -> String {
+        match &self.context {
+            Some(context) => format!("[{}] {}", context, event),
+            None => event,
+        }
+    }
+
+    fn send(&self, event: String) {
         if let Some(tx) = &self.tx{
-            
             if let Err(e) = tx.send(event) {
                 println!("Error al intentar loggear: {:?}.", e);
             }
         }
     }
+
+    pub fn error(&self, event: String) {
+        self.log_with_level(LogLevel::Error, event);
+    }
+
+    pub fn warn(&self, event: String) {
+        self.log_with_level(LogLevel::Warn, event);
+    }
+
+    pub fn info(&self, event: String) {
+        self.log_with_level(LogLevel::Info, event);
+    }
+
+    pub fn debug(&self, event: String) {
+        self.log_with_level(LogLevel::Debug, event);
+    }
+
+    /// Cambia el nivel de este logger y de todos los que comparten su archivo por clone_ref.
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.set(level);
+    }
+
+    /// Devuelve un logger que escribe al mismo archivo, anteponiendo `context` (además del contexto propio) a cada evento.
+    pub fn with_context(&self, context: &str) -> StringLogger {
+        let mut logger = self.clone_ref();
+        logger.context = match &self.context {
+            Some(own_context) => Some(format!("{} {}", own_context, context)),
+            None => Some(context.to_string()),
+        };
+        logger
+    }
     
     /// Función que debe ser llamada antes del final de cada programa, para no impedir la finalización del mismo.
     pub fn stop_logging(&mut self) {
@@ -46,11 +103,58 @@ impl StringLogger {
     
     /// Devuelve una instancia de `Self` que escribirá al mismo archivo (usa clone de su tx interno).
     pub fn clone_ref(&self) -> StringLogger {
-        Self::new_for_internal_use(self.tx.clone())        
+        Self::new_for_internal_use(self.tx.clone(), self.level.clone(), self.context.clone())
     }
 
     /// Para ser utilizado por clone_ref, ahora que el tx es un option para poder dropearlo con el stop_logging.
-    fn new_for_internal_use(tx: Option<Sender<String>>) -> Self {
-        Self { tx }
+    fn new_for_internal_use(
+        tx: Option<Sender<String>>,
+        level: Arc<SharedLogLevel>,
+        context: Option<String>,
+    ) -> Self {
+        Self { tx, level, context }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StringLogger;
+    use crate::logging::log_level::LogLevel;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_1_los_eventos_mas_detallados_que_el_nivel_no_se_graban() {
+        let (tx, rx) = mpsc::channel::<String>();
+        let logger = StringLogger::new(tx);
+        let clone = logger.clone_ref();
+
+        logger.debug("detalle".to_string());
+        logger.warn("advertencia".to_string());
+        clone.set_level(LogLevel::Debug);
+        logger.debug("detalle".to_string());
+
+        let events: Vec<String> = rx.try_iter().collect();
+        assert_eq!(events, vec!["WARN advertencia", "DEBUG detalle"]);
+    }
+
+    #[test]
+    fn test_2_el_contexto_se_antepone_a_cada_evento() {
+        let (tx, rx) = mpsc::channel::<String>();
+        let logger = StringLogger::new(tx);
+        let connection_logger = logger
+            .with_context("addr=127.0.0.1:5000")
+            .with_context("client_id=dron-1");
+
+        connection_logger.info("conectado".to_string());
+        logger.log("sin contexto".to_string());
+
+        let events: Vec<String> = rx.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                "INFO [addr=127.0.0.1:5000 client_id=dron-1] conectado",
+                "sin contexto"
+            ]
+        );
     }
 }
@@ -1,11 +1,17 @@
 //use std::fmt;
 
-use crate::mqtt::messages::{puback_message::PubAckMessage, suback_message::SubAckMessage};
+use crate::mqtt::messages::{
+    puback_message::PubAckMessage, pubcomp_message::PubCompMessage,
+    pubrec_message::PubRecMessage, suback_message::SubAckMessage, unsuback_message::Unsuback,
+};
 
 #[derive(Debug)]
 pub enum ACKMessage {
     PubAck(PubAckMessage),
     SubAck(SubAckMessage),
+    PubRec(PubRecMessage),
+    PubComp(PubCompMessage),
+    UnsubAck(Unsuback),
 }
 
 impl ACKMessage {
@@ -13,6 +19,9 @@ impl ACKMessage {
         match self {
             ACKMessage::PubAck(pub_ack_message) => Some(pub_ack_message.get_packet_id()),
             ACKMessage::SubAck(sub_ack_message) => Some(sub_ack_message.get_packet_id()),
+            ACKMessage::PubRec(pub_rec_message) => Some(pub_rec_message.get_packet_id()),
+            ACKMessage::PubComp(pub_comp_message) => Some(pub_comp_message.get_packet_id()),
+            ACKMessage::UnsubAck(unsub_ack_message) => Some(unsub_ack_message.get_packet_id()),
         }
     }
 }
@@ -1,6 +1,13 @@
 pub mod mqtt_client;
+pub mod mqtt_client_builder;
+pub mod mqtt_client_connection_status;
 pub mod mqtt_client_listener;
 pub mod mqtt_client_connector;
 pub mod mqtt_client_msg_creator;
+pub mod mqtt_client_offline_queue;
+pub mod mqtt_client_packet_id_manager;
 pub mod ack_message;
-pub mod mqtt_client_retransmitter;
\ No newline at end of file
+pub mod mqtt_client_retransmitter;
+pub mod mqtt_client_pinger;
+pub mod mqtt_client_reconnector;
+pub mod mqtt_client_delivery_token;
\ No newline at end of file
@@ -1,60 +1,162 @@
 use crate::logging::string_logger::StringLogger;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use crate::mqtt::client::{
-    mqtt_client_listener::MQTTClientListener, mqtt_client_retransmitter::Retransmitter,
+    mqtt_client_builder::MqttClientOptions,
+    mqtt_client_connection_status::{ConnectionStatus, StatusListeners},
     mqtt_client_connector::MqttClientConnector,
+    mqtt_client_delivery_token::{DeliveryOutcome, DeliveryToken},
+    mqtt_client_listener::TopicHandlers,
     mqtt_client_msg_creator::MessageCreator,
+    mqtt_client_offline_queue::{lock_offline_queue, OfflineOverflowPolicy, OfflineQueue},
+    mqtt_client_packet_id_manager::PacketIdManager,
+    mqtt_client_reconnector::{ClientSession, ConnectionParams, Reconnector},
+    mqtt_client_retransmitter::{lock_retransmitter, Retransmitter, ShareableRetransmitter},
 };
 use crate::mqtt::messages::publish_message::PublishMessage;
-use crate::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
-use std::net::TcpStream;
+use crate::mqtt::mqtt_utils::packet_observer::{PacketObserver, PacketObservers};
+use crate::mqtt::stream_type::StreamType;
 use std::{
     io::Error,
     net::SocketAddr,
-    sync::mpsc::{self, Receiver},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
+    time::Instant,
 };
 
-pub type ClientStreamType = TcpStream; // Aux: que solo lo use el cliente por ahora, para hacer refactor más fácil.
+pub type ClientStreamType = StreamType;
 
 #[derive(Debug)]
 pub struct MQTTClient {
     msg_creator: MessageCreator,
-    retransmitter: Retransmitter,
+    retransmitter: ShareableRetransmitter,
+    session: ClientSession, // topics suscriptos y publish encolados, para retomar la sesión si se reconecta.
+    packet_observers: PacketObservers, // compartidos con las opciones, los usan también las reconexiones.
     logger: StringLogger,
 }
 
 impl MQTTClient {
-    /// Función de la librería de MQTTClient para conectarse al servidor.
+    /// Función de la librería de MQTTClient para conectarse al servidor, con las `options` configuradas mediante `MqttClientBuilder`.
     /// Devuelve el MQTTClient al que solicitarle los demás métodos, un rx por el que recibir los PublishMessages que
     /// se publiquen a los topics a los que nos suscribamos, y un joinhandle que debe ser 'esperado' para finalizar correctamente la ejecución.
+    /// Si la conexión se pierde, el cliente se reconecta automáticamente y retoma la sesión; el joinhandle
+    /// termina recién cuando el cliente se desconecta, o el server lo desconecta.
     pub fn mqtt_connect_to_broker(
-        client_id: String,
         addr: &SocketAddr,
-        will: Option<WillMessageData>,
+        mut options: MqttClientOptions,
         logger: StringLogger,
     ) -> Result<(Self, Receiver<PublishMessage>, JoinHandle<()>), Error> {
         // Efectúa la conexión al server
-        let stream = MqttClientConnector::mqtt_connect_to_broker(client_id, addr, will, logger.clone_ref())?;
+        let connection =
+            MqttClientConnector::mqtt_connect_to_broker(addr, &options, logger.clone_ref())?;
+        let (stream, protocol_version) = (connection.stream, connection.protocol_version);
+        // Las reconexiones y los mensajes usan la versión negociada
+        options.set_protocol_version(protocol_version);
         // Inicializa sus partes internas
-        let writer = MessageCreator::new();
+        let packet_ids = Arc::new(Mutex::new(PacketIdManager::new()));
+        let mut writer = MessageCreator::new(packet_ids.clone());
+        writer.set_protocol_version(protocol_version);
         let (publish_msg_tx, publish_msg_rx) = mpsc::channel::<PublishMessage>();
-        let (retransmitter, ack_tx) = Retransmitter::new(stream.try_clone()?, logger.clone_ref());
-        let mut listener = MQTTClientListener::new(stream.try_clone()?, publish_msg_tx, ack_tx);
-        
-        let logger_c = logger.clone_ref();
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+        let (mut retransmitter, ack_tx) = Retransmitter::new(stream.try_clone()?, logger.clone_ref(), last_activity.clone(), packet_ids.clone());
+        retransmitter.set_max_in_flight(options.get_max_in_flight());
+        retransmitter.set_ack_timeout(options.get_ack_timeout());
+        retransmitter.set_topic_alias_maximum(connection.topic_alias_maximum);
+        let packet_observers = options.get_packet_observers();
+        retransmitter.set_packet_observers(packet_observers.clone());
+        let retransmitter = Arc::new(Mutex::new(retransmitter));
+        let session = ClientSession {
+            subscribed_topics: Arc::new(Mutex::new(vec![])),
+            topic_handlers: TopicHandlers::default(),
+            offline_queue: Arc::new(Mutex::new(OfflineQueue::new(
+                options.get_offline_queue_capacity(),
+                options.get_offline_overflow_policy(),
+            ))),
+            disconnect_requested: Arc::new(AtomicBool::new(false)),
+            reconnect_requested: Arc::new(AtomicBool::new(false)),
+            status_listeners: StatusListeners::default(),
+        };
+
+        let connection_params = ConnectionParams { addr: *addr, options };
+        let mut reconnector = Reconnector::new(
+            connection_params,
+            packet_ids,
+            retransmitter.clone(),
+            session.clone(),
+            last_activity,
+            logger.clone_ref(),
+        );
         let mqtt_client = MQTTClient {
             msg_creator: writer,
             retransmitter,
+            session,
+            packet_observers,
             logger,
         };
 
-        let listener_handle = thread::spawn(move || {
-            if let Err(e) = listener.read_from_server(){
-                logger_c.log(format!("Error al leer, en read_from_server: {:?}", e));
+        // Hilo que atiende la conexión (lectura y pings), y reconecta si se pierde.
+        let logger_c = mqtt_client.logger.clone_ref();
+        let reconnector_handle = thread::spawn(move || {
+            if let Err(e) = reconnector.run(stream, publish_msg_tx, ack_tx) {
+                logger_c.log(format!("Error al atender la conexión con el server: {:?}", e));
             }
         });
 
-        Ok((mqtt_client, publish_msg_rx, listener_handle))
+        Ok((mqtt_client, publish_msg_rx, reconnector_handle))
+    }
+
+    /// Registra `observer` para que observe cada paquete que el cliente envíe o reciba desde ahora,
+    /// también en las siguientes conexiones si se reconecta. Ver `PacketObserver`.
+    pub fn add_packet_observer(&self, observer: Arc<dyn PacketObserver>) {
+        self.packet_observers.add(observer);
+    }
+
+    /// Establece el máximo de publish de qos 1 enviados al server sin haber recibido su puback.
+    /// Al alcanzarlo, los siguientes publish esperan a que lleguen los pubacks pendientes.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        if let Ok(mut retransmitter) = lock_retransmitter(&self.retransmitter) {
+            retransmitter.set_max_in_flight(max_in_flight);
+        }
+    }
+
+    /// Devuelve si el cliente está conectado al server. Mientras se reconecta, los publish se encolan.
+    pub fn is_connected(&self) -> bool {
+        lock_offline_queue(&self.session.offline_queue)
+            .map(|offline_queue| offline_queue.is_connected())
+            .unwrap_or(false)
+    }
+
+    /// Devuelve un rx por el que se recibe cada cambio de estado de la conexión con el server (ver
+    /// `ConnectionStatus`). Al registrarse el cliente ya está conectado, y solamente se reciben los cambios.
+    pub fn subscribe_to_connection_status(&self) -> Receiver<ConnectionStatus> {
+        self.session.status_listeners.add()
+    }
+
+    /// Reconecta al cliente ya: si está esperando para reintentar la reconexión, lo intenta en el momento, y si
+    /// está conectado, cierra la conexión para volver a conectarse (ie si la conexión dejó de responder).
+    /// Devuelve error si el cliente ya se desconectó.
+    pub fn mqtt_reconnect(&self) -> Result<(), Error> {
+        if self.session.disconnect_requested.load(Ordering::SeqCst) {
+            return Err(MqttError::NotConnected.into());
+        }
+        self.session.reconnect_requested.store(true, Ordering::SeqCst);
+        if self.is_connected() {
+            lock_retransmitter(&self.retransmitter)?.shutdown_stream()?;
+        }
+        Ok(())
+    }
+
+    /// Establece cuántos publish se encolan como máximo mientras el cliente está desconectado del server,
+    /// y qué hacer con un nuevo publish si la cola está llena. Los publish encolados se envían al reconectarse.
+    pub fn set_offline_queue(&mut self, capacity: usize, overflow_policy: OfflineOverflowPolicy) {
+        if let Ok(mut offline_queue) = lock_offline_queue(&self.session.offline_queue) {
+            for dropped in offline_queue.configure(capacity, overflow_policy) {
+                self.discard_publish_msg(&dropped);
+            }
+        }
     }
 
     /// Función de la librería de MQTTClient para realizar un publish.
@@ -66,21 +168,94 @@ impl MQTTClient {
     ) -> Result<PublishMessage, Error> {
         // Esto solamente crea y devuelve el mensaje
         let msg = self.msg_creator.create_publish_msg(topic, payload, qos)?;
+        self.mqtt_publish_msg(&msg)?;
+        Ok(msg)
+    }
+
+    /// Envía el publish `msg`, o lo encola si el cliente está desconectado del server.
+    fn mqtt_publish_msg(&mut self, msg: &PublishMessage) -> Result<(), Error> {
         // Se lo paso al retransmitter y que él se encargue de mandarlo, y retransmitirlo si es necesario
-        self.retransmitter.send_and_retransmit(&msg)?;
+        if self.enqueue_if_disconnected(msg)? {
+            return Ok(());
+        }
+        lock_retransmitter(&self.retransmitter)?.send_and_retransmit(msg)?;
 
         //println!("-----------------\n Mqtt: publish enviado: \n   {:?}", msg);
         self.logger.log(format!("-----------------\n Mqtt: publish enviado: \n   {:?}", msg));
 
+        Ok(())
+    }
+
+    /// Función de la librería de MQTTClient para realizar un publish, y conocer el resultado de su entrega
+    /// mediante el token devuelto: se resuelve al recibir el puback del server, con su reason code
+    /// (ie el server rechazó el publish por no estar autorizado), o al desistir de retransmitirlo.
+    /// Un publish de qos 0 no tiene ack, por lo que su token se resuelve como `Sent` al escribirse o encolarse.
+    pub fn mqtt_publish_with_token(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: u8,
+    ) -> Result<DeliveryToken, Error> {
+        let msg = self.msg_creator.create_publish_msg(topic, payload, qos)?;
+        let (token, notifier) = DeliveryToken::new(self.retransmitter.clone());
+        let Some(packet_id) = msg.get_packet_id() else {
+            self.mqtt_publish_msg(&msg)?;
+            let _ = notifier.send(DeliveryOutcome::Sent);
+            return Ok(token);
+        };
+        // Se registra antes de enviarlo, para que el resultado no se pierda si el ack llega enseguida.
+        lock_retransmitter(&self.retransmitter)?.register_delivery_notifier(packet_id, notifier);
+        if let Err(e) = self.mqtt_publish_msg(&msg) {
+            lock_retransmitter(&self.retransmitter)?.unregister_delivery_notifier(packet_id);
+            return Err(e);
+        }
+        Ok(token)
+    }
+
+    /// Función de la librería de MQTTClient para realizar un publish y esperar el resultado de su entrega.
+    /// Permite, por ejemplo, que un dron confirme que el server aceptó su posición antes de continuar.
+    pub fn mqtt_publish_and_wait(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: u8,
+    ) -> Result<DeliveryOutcome, Error> {
+        self.mqtt_publish_with_token(topic, payload, qos)?.wait()
+    }
+
+    /// Función de la librería de MQTTClient para realizar un publish con el flag de retain,
+    /// para que el server lo conserve como último estado conocido del topic y se lo envíe a quienes se suscriban luego.
+    pub fn mqtt_publish_retained(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: u8,
+    ) -> Result<PublishMessage, Error> {
+        let msg = self
+            .msg_creator
+            .create_publish_msg_with_retain(topic, payload, qos, true)?;
+        if self.enqueue_if_disconnected(&msg)? {
+            return Ok(msg);
+        }
+        lock_retransmitter(&self.retransmitter)?.send_and_retransmit(&msg)?;
+
+        self.logger.log(format!("-----------------\n Mqtt: publish retenido enviado: \n   {:?}", msg));
+
         Ok(msg)
     }
 
     /// Función de la librería de MQTTClient para realizar un subscribe.
     pub fn mqtt_subscribe(&mut self, topics: Vec<(String, u8)>) -> Result<(), Error> {
         // Esto solamente crea y devuelve el mensaje
-        let msg = self.msg_creator.create_subscribe_msg(topics)?;
+        let msg = self.msg_creator.create_subscribe_msg(topics.to_vec())?;
         // Se lo paso al retransmitter y que él se encargue de mandarlo, y retransmitirlo si es necesario
-        self.retransmitter.send_and_retransmit(&msg)?;
+        lock_retransmitter(&self.retransmitter)?.send_and_retransmit(&msg)?;
+        // Se recuerdan los topics, para volver a suscribirse si se reconecta
+        let mut subscribed_topics = self.lock_subscribed_topics()?;
+        for (topic, qos) in topics {
+            subscribed_topics.retain(|(t, _)| *t != topic);
+            subscribed_topics.push((topic, qos));
+        }
         
         println!("-----------------\n Mqtt: subscribe enviado: \n   {:?}", msg);
         self.logger.log(format!("-----------------\n Mqtt: subscribe enviado: \n   {:?}", msg));
@@ -88,10 +263,81 @@ impl MQTTClient {
         Ok(())
     }
 
+    /// Función de la librería de MQTTClient para suscribirse a un topic, procesando cada PublishMessage que se reciba de él
+    /// con `handler` en lugar de enviarlo por el rx. El handler se ejecuta en el hilo que lee del server,
+    /// por lo que si necesita publicar o hacer otro procesamiento largo, debe delegarlo a otro hilo.
+    pub fn mqtt_subscribe_with_handler(
+        &mut self,
+        topic: &str,
+        qos: u8,
+        handler: impl Fn(PublishMessage) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        // Se registra antes de suscribirse, para que lo procese aunque llegue un publish retenido enseguida
+        self.session.topic_handlers.insert(topic, Arc::new(handler))?;
+        if let Err(e) = self.mqtt_subscribe(vec![(topic.to_string(), qos)]) {
+            self.session.topic_handlers.remove(&[topic.to_string()])?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Función de la librería de MQTTClient para realizar un unsubscribe.
+    /// Luego de recibido el unsuback, el server deja de enviarnos los mensajes publicados en dichos topics.
+    pub fn mqtt_unsubscribe(&mut self, topics: Vec<String>) -> Result<(), Error> {
+        let msg = self.msg_creator.create_unsubscribe_msg(topics.to_vec())?;
+        lock_retransmitter(&self.retransmitter)?.send_and_retransmit(&msg)?;
+        self.lock_subscribed_topics()?
+            .retain(|(topic, _)| !topics.contains(topic));
+        self.session.topic_handlers.remove(&topics)?;
+
+        self.logger.log(format!("-----------------\n Mqtt: unsubscribe enviado: \n   {:?}", msg));
+
+        Ok(())
+    }
+
     /// Función de la librería de MQTTClient para terminar de manera voluntaria la conexión con el server.
     pub fn mqtt_disconnect(&mut self) -> Result<(), Error> {
         let msg = self.msg_creator.create_disconnect_msg()?;
-        self.retransmitter.send_and_shutdown_stream(msg)?;
+        // Se marca antes de cerrar la conexión, para que al cerrarse no se intente reconectar.
+        self.session.disconnect_requested.store(true, Ordering::SeqCst);
+        lock_retransmitter(&self.retransmitter)?.send_and_shutdown_stream(msg)?;
         Ok(())
     }
+
+    /// Si el cliente está desconectado del server, encola el publish para enviarlo al reconectarse, y devuelve true.
+    /// Si la cola está llena, descarta un publish según la política configurada, o devuelve error.
+    fn enqueue_if_disconnected(&self, msg: &PublishMessage) -> Result<bool, Error> {
+        let mut offline_queue = lock_offline_queue(&self.session.offline_queue)?;
+        if offline_queue.is_connected() {
+            return Ok(false);
+        }
+        match offline_queue.push(msg.clone()) {
+            Ok(Some(dropped)) => {
+                self.discard_publish_msg(&dropped);
+                self.logger.log(format!("Mqtt: cola sin conexión llena, publish descartado: \n   {:?}", dropped));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.discard_publish_msg(msg);
+                return Err(e);
+            }
+        }
+
+        self.logger.log(format!("-----------------\n Mqtt: sin conexión, publish encolado: \n   {:?}", msg));
+        Ok(true)
+    }
+
+    /// Descarta el publish `msg` sin enviarlo, e informa el descarte a quien espere el resultado de su entrega.
+    fn discard_publish_msg(&self, msg: &PublishMessage) {
+        self.msg_creator.discard_publish_msg(msg);
+        if let (Some(packet_id), Ok(mut retransmitter)) = (msg.get_packet_id(), lock_retransmitter(&self.retransmitter)) {
+            retransmitter.notify_delivery(packet_id, DeliveryOutcome::Discarded);
+        }
+    }
+
+    fn lock_subscribed_topics(&self) -> Result<std::sync::MutexGuard<'_, Vec<(String, u8)>>, Error> {
+        self.session.subscribed_topics
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("subscribed_topics".to_string()).into())
+    }
 }
@@ -0,0 +1,353 @@
+use std::{
+    io::Error,
+    net::SocketAddr,
+    sync::{mpsc::Receiver, Arc},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::loopback::LoopbackConnector;
+use crate::mqtt::messages::{protocol_version::ProtocolVersion, publish_message::PublishMessage};
+use crate::mqtt::mqtt_utils::packet_observer::{PacketObserver, PacketObservers};
+use crate::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
+
+use super::{
+    mqtt_client::MQTTClient,
+    mqtt_client_offline_queue::{OfflineOverflowPolicy, DEFAULT_OFFLINE_QUEUE_CAPACITY},
+    mqtt_client_reconnector::ReconnectPolicy,
+    mqtt_client_retransmitter::{DEFAULT_ACK_TIMEOUT, DEFAULT_MAX_IN_FLIGHT},
+};
+
+/// Keep alive, en segundos, que el cliente negocia con el server en el connect si no se configura otro.
+/// Si el server no recibe ningún mensaje durante 1.5 veces este tiempo, considera al cliente desconectado.
+pub const DEFAULT_KEEP_ALIVE_SECS: u16 = 10;
+const DEFAULT_USERNAME: &str = "usuario0";
+const DEFAULT_PASSWORD: &str = "rustx123";
+
+/// Opciones con las que `MQTTClient` se conecta al server, y vuelve a conectarse si se pierde la conexión.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttClientOptions {
+    client_id: String,
+    keep_alive: u16,
+    clean_session: bool,
+    will: Option<WillMessageData>,
+    username: Option<String>,
+    password: Option<String>,
+    reconnect_policy: ReconnectPolicy,
+    connect_timeout: Option<Duration>, // si es None, se espera lo que demore el sistema operativo.
+    write_timeout: Option<Duration>,   // si es None, la escritura bloquea hasta completarse.
+    max_in_flight: usize,              // publish qos 1 enviados sin puback, ver `Retransmitter`.
+    ack_timeout: Duration,             // lo que se espera por un ack antes de retransmitir.
+    offline_queue_capacity: usize,     // publish encolados mientras no hay conexión.
+    offline_overflow_policy: OfflineOverflowPolicy,
+    protocol_version: ProtocolVersion, // si el server no acepta mqtt 5, se negocia 3.1.1.
+    session_expiry_interval: Option<u32>, // solamente en mqtt 5.
+    topic_alias_maximum: u16, // solamente en mqtt 5, 0 si no se usan topic aliases.
+    loopback: Option<LoopbackConnector>, // si no es None, se conecta en memoria a un server del mismo proceso.
+    packet_observers: PacketObservers, // observan los paquetes de todas las conexiones del cliente.
+}
+
+impl MqttClientOptions {
+    /// Crea las opciones por defecto para el cliente `client_id`.
+    pub fn new(client_id: &str) -> Self {
+        MqttClientOptions {
+            client_id: client_id.to_string(),
+            keep_alive: DEFAULT_KEEP_ALIVE_SECS,
+            clean_session: true,
+            will: None,
+            username: Some(DEFAULT_USERNAME.to_string()),
+            password: Some(DEFAULT_PASSWORD.to_string()),
+            reconnect_policy: ReconnectPolicy::default(),
+            connect_timeout: None,
+            write_timeout: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            offline_queue_capacity: DEFAULT_OFFLINE_QUEUE_CAPACITY,
+            offline_overflow_policy: OfflineOverflowPolicy::default(),
+            protocol_version: ProtocolVersion::default(),
+            session_expiry_interval: None,
+            topic_alias_maximum: 0,
+            loopback: None,
+            packet_observers: PacketObservers::default(),
+        }
+    }
+
+    pub fn get_client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn get_keep_alive(&self) -> u16 {
+        self.keep_alive
+    }
+
+    pub fn is_clean_session(&self) -> bool {
+        self.clean_session
+    }
+
+    pub fn get_will(&self) -> Option<WillMessageData> {
+        self.will.clone()
+    }
+
+    pub fn get_username(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    pub fn get_password(&self) -> Option<String> {
+        self.password.clone()
+    }
+
+    pub fn get_reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy
+    }
+
+    pub fn get_connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    pub fn get_write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    pub fn get_max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub fn get_ack_timeout(&self) -> Duration {
+        self.ack_timeout
+    }
+
+    pub fn get_offline_queue_capacity(&self) -> usize {
+        self.offline_queue_capacity
+    }
+
+    pub fn get_offline_overflow_policy(&self) -> OfflineOverflowPolicy {
+        self.offline_overflow_policy
+    }
+
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// Establece la versión de protocolo, ie la negociada con el server, para usarla al reconectarse.
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.protocol_version = protocol_version;
+    }
+
+    pub fn get_session_expiry_interval(&self) -> Option<u32> {
+        self.session_expiry_interval
+    }
+
+    pub fn get_topic_alias_maximum(&self) -> u16 {
+        self.topic_alias_maximum
+    }
+
+    pub fn get_loopback(&self) -> Option<&LoopbackConnector> {
+        self.loopback.as_ref()
+    }
+
+    /// Devuelve los packet observers, que informan a los paquetes como del cliente `client_id`.
+    pub fn get_packet_observers(&self) -> PacketObservers {
+        self.packet_observers.with_client_id(&self.client_id)
+    }
+}
+
+/// Permite configurar las opciones de conexión de `MQTTClient` antes de conectarse al server.
+/// Las opciones no configuradas toman los valores por defecto de `MqttClientOptions::new`.
+#[derive(Debug, Clone)]
+pub struct MqttClientBuilder {
+    options: MqttClientOptions,
+}
+
+impl MqttClientBuilder {
+    pub fn new(client_id: &str) -> Self {
+        MqttClientBuilder {
+            options: MqttClientOptions::new(client_id),
+        }
+    }
+
+    /// Segundos sin enviar mensajes tras los cuales el cliente envía un PingReq.
+    pub fn keep_alive(mut self, keep_alive_secs: u16) -> Self {
+        self.options.keep_alive = keep_alive_secs;
+        self
+    }
+
+    pub fn clean_session(mut self, clean_session: bool) -> Self {
+        self.options.clean_session = clean_session;
+        self
+    }
+
+    /// Mensaje que el server publicará en `topic` si el cliente se desconecta sin enviar disconnect.
+    pub fn will(mut self, topic: &str, payload: &str, qos: u8, retain: bool) -> Self {
+        let will = WillMessageData::new(payload.to_string(), topic.to_string(), qos, retain as u8);
+        self.options.will = Some(will);
+        self
+    }
+
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.options.username = Some(username.to_string());
+        self.options.password = Some(password.to_string());
+        self
+    }
+
+    pub fn reconnect_policy(mut self, reconnect_policy: ReconnectPolicy) -> Self {
+        self.options.reconnect_policy = reconnect_policy;
+        self
+    }
+
+    /// Tiempo máximo a esperar para establecer la conexión tcp con el server.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Tiempo máximo a esperar para escribir un mensaje al server.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.options.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Máximo de publish de qos 1 enviados al server sin haber recibido su puback.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.options.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Tiempo a esperar por el ack de un mensaje (incluido el connack) antes de retransmitirlo. Luego de
+    /// retransmitirlo varias veces sin recibirlo, se desiste.
+    pub fn ack_timeout(mut self, timeout: Duration) -> Self {
+        self.options.ack_timeout = timeout;
+        self
+    }
+
+    /// Cuántos publish se encolan mientras no hay conexión, y qué hacer con un nuevo publish si la cola está llena.
+    pub fn offline_queue(
+        mut self,
+        capacity: usize,
+        overflow_policy: OfflineOverflowPolicy,
+    ) -> Self {
+        self.options.offline_queue_capacity = capacity;
+        self.options.offline_overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Versión de protocolo a pedir en el connect. Con mqtt 5, si el server no lo acepta se reintenta con 3.1.1.
+    pub fn protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.options.protocol_version = protocol_version;
+        self
+    }
+
+    /// Segundos que el server debe conservar la sesión luego de la desconexión (solamente en mqtt 5).
+    pub fn session_expiry_interval(mut self, seconds: u32) -> Self {
+        self.options.session_expiry_interval = Some(seconds);
+        self
+    }
+
+    /// Cantidad de topic aliases a usar en cada sentido (solamente en mqtt 5): el cliente reemplaza los topics
+    /// de sus publish por aliases de 2 bytes, hasta los que acepte el server según su connack, y acepta
+    /// hasta `maximum` aliases en los publish que recibe. Con un server que no los acepta, se envían los topics.
+    pub fn topic_alias_maximum(mut self, maximum: u16) -> Self {
+        self.options.topic_alias_maximum = maximum;
+        self
+    }
+
+    /// Conectarse en memoria, mediante `connector`, a un server que corre en el mismo proceso (ver `LoopbackListener`),
+    /// en lugar de por tcp. Las reconexiones también se hacen mediante el connector.
+    pub fn loopback(mut self, connector: &LoopbackConnector) -> Self {
+        self.options.loopback = Some(connector.clone());
+        self
+    }
+
+    /// Registra `observer` para que observe cada paquete que el cliente envíe o reciba, ver `PacketObserver`.
+    pub fn packet_observer(self, observer: Arc<dyn PacketObserver>) -> Self {
+        self.options.packet_observers.add(observer);
+        self
+    }
+
+    /// Devuelve las opciones configuradas.
+    pub fn build(self) -> MqttClientOptions {
+        self.options
+    }
+
+    /// Conecta al server con las opciones configuradas, ver `MQTTClient::mqtt_connect_to_broker`.
+    pub fn connect(
+        self,
+        addr: &SocketAddr,
+        logger: StringLogger,
+    ) -> Result<(MQTTClient, Receiver<PublishMessage>, JoinHandle<()>), Error> {
+        MQTTClient::mqtt_connect_to_broker(addr, self.build(), logger)
+    }
+
+    /// Conecta en memoria, mediante `connector`, al server que corre en el mismo proceso.
+    pub fn connect_loopback(
+        self,
+        connector: &LoopbackConnector,
+        logger: StringLogger,
+    ) -> Result<(MQTTClient, Receiver<PublishMessage>, JoinHandle<()>), Error> {
+        self.loopback(connector).connect(&connector.get_addr(), logger)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MqttClientBuilder, DEFAULT_ACK_TIMEOUT, DEFAULT_KEEP_ALIVE_SECS};
+    use crate::mqtt::client::{
+        mqtt_client_offline_queue::OfflineOverflowPolicy, mqtt_client_reconnector::ReconnectPolicy,
+    };
+    use crate::mqtt::messages::protocol_version::ProtocolVersion;
+    use std::time::Duration;
+
+    #[test]
+    fn test_1_las_opciones_no_configuradas_toman_los_valores_por_defecto() {
+        let options = MqttClientBuilder::new("dron-1").build();
+
+        assert_eq!(options.get_client_id(), "dron-1");
+        assert_eq!(options.get_keep_alive(), DEFAULT_KEEP_ALIVE_SECS);
+        assert!(options.is_clean_session());
+        assert!(options.get_will().is_none());
+        assert!(options.get_username().is_some());
+        assert_eq!(options.get_reconnect_policy(), ReconnectPolicy::default());
+        assert_eq!(options.get_connect_timeout(), None);
+        assert_eq!(options.get_ack_timeout(), DEFAULT_ACK_TIMEOUT);
+        assert_eq!(options.get_protocol_version(), ProtocolVersion::Mqtt311);
+        assert_eq!(options.get_topic_alias_maximum(), 0);
+        assert!(options.get_loopback().is_none());
+    }
+
+    #[test]
+    fn test_2_el_builder_configura_las_opciones_indicadas() {
+        let options = MqttClientBuilder::new("camaras")
+            .keep_alive(30)
+            .clean_session(false)
+            .will("desc", "camaras", 1, true)
+            .credentials("usuario1", "clave")
+            .reconnect_policy(ReconnectPolicy::disabled())
+            .connect_timeout(Duration::from_secs(2))
+            .max_in_flight(5)
+            .ack_timeout(Duration::from_secs(3))
+            .offline_queue(20, OfflineOverflowPolicy::DropNew)
+            .protocol_version(ProtocolVersion::Mqtt5)
+            .session_expiry_interval(600)
+            .topic_alias_maximum(10)
+            .build();
+
+        assert_eq!(options.get_keep_alive(), 30);
+        assert!(!options.is_clean_session());
+        let will = options.get_will().unwrap();
+        assert_eq!(will.get_will_topic(), "desc");
+        assert_eq!(will.get_will_retain(), 1);
+        assert_eq!(options.get_username(), Some("usuario1".to_string()));
+        assert!(!options.get_reconnect_policy().is_enabled());
+        assert_eq!(options.get_connect_timeout(), Some(Duration::from_secs(2)));
+        assert_eq!(options.get_max_in_flight(), 5);
+        assert_eq!(options.get_ack_timeout(), Duration::from_secs(3));
+        assert_eq!(options.get_offline_queue_capacity(), 20);
+        assert_eq!(
+            options.get_offline_overflow_policy(),
+            OfflineOverflowPolicy::DropNew
+        );
+        assert_eq!(options.get_protocol_version(), ProtocolVersion::Mqtt5);
+        assert_eq!(options.get_session_expiry_interval(), Some(600));
+        assert_eq!(options.get_topic_alias_maximum(), 10);
+    }
+}
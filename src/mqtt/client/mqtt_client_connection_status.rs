@@ -0,0 +1,73 @@
+use std::sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc, Mutex,
+};
+
+/// Estado de la conexión del cliente con el server, que el `Reconnector` avisa cada vez que cambia.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    /// Se perdió la conexión y se está reconectando; `last_error` es el motivo del último intento fallido, si hubo.
+    Reconnecting { last_error: Option<String> },
+    /// El cliente ya no está conectado ni se va a reconectar.
+    Offline { last_error: Option<String> },
+}
+
+impl ConnectionStatus {
+    /// Devuelve el motivo de la última falla de la conexión, si se conoce.
+    pub fn get_last_error(&self) -> Option<&str> {
+        match self {
+            ConnectionStatus::Connected => None,
+            ConnectionStatus::Reconnecting { last_error } | ConnectionStatus::Offline { last_error } => {
+                last_error.as_deref()
+            }
+        }
+    }
+}
+
+/// Interesados en los cambios de estado de la conexión, compartidos entre `MQTTClient`, que los registra,
+/// y el `Reconnector`, que les avisa. Los que ya no escuchan se descartan al avisar.
+#[derive(Debug, Clone, Default)]
+pub struct StatusListeners {
+    listeners: Arc<Mutex<Vec<Sender<ConnectionStatus>>>>,
+}
+
+impl StatusListeners {
+    /// Registra un nuevo interesado, y devuelve el rx por el que recibe cada cambio de estado.
+    pub fn add(&self) -> Receiver<ConnectionStatus> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(tx);
+        }
+        rx
+    }
+
+    /// Avisa el nuevo `status` a todos los interesados.
+    pub fn notify(&self, status: ConnectionStatus) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.retain(|listener| listener.send(status.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConnectionStatus, StatusListeners};
+
+    #[test]
+    fn test_1_se_avisa_a_cada_interesado_y_se_descarta_a_los_que_no_escuchan() {
+        let listeners = StatusListeners::default();
+        let rx_1 = listeners.add();
+        let rx_2 = listeners.add();
+        drop(rx_2);
+
+        let reconnecting = ConnectionStatus::Reconnecting { last_error: Some("timeout".to_string()) };
+        listeners.notify(reconnecting.clone());
+        listeners.notify(ConnectionStatus::Connected);
+
+        assert_eq!(rx_1.try_iter().collect::<Vec<_>>(), vec![reconnecting.clone(), ConnectionStatus::Connected]);
+        assert_eq!(listeners.listeners.lock().unwrap().len(), 1);
+        assert_eq!(reconnecting.get_last_error(), Some("timeout"));
+        assert_eq!(ConnectionStatus::Connected.get_last_error(), None);
+    }
+}
@@ -1,68 +1,113 @@
-use std::net::{SocketAddr, TcpStream};
+use std::net::SocketAddr;
 
 use std::io::{self, Error, ErrorKind, Read};
 use std::time::Duration;
 
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use crate::logging::string_logger::StringLogger;
+use crate::mqtt::stream_type::StreamType;
 use crate::mqtt::messages::{
     connack_message::ConnackMessage, connect_message::ConnectMessage,
-    connect_return_code::ConnectReturnCode, packet_type::PacketType,
+    connect_return_code::ConnectReturnCode, packet_type::PacketType, properties::Properties,
+    protocol_version::ProtocolVersion,
 };
 use crate::mqtt::mqtt_utils::fixed_header::FixedHeader;
+use crate::mqtt::mqtt_utils::packet_observer::{PacketDirection, PacketObservers};
 use crate::mqtt::mqtt_utils::utils::{
-    get_whole_message_in_bytes_from_stream, write_message_to_stream,
+    get_whole_message_in_bytes_from_stream, read_exact_or_eof, write_message_to_stream,
 };
-use crate::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
-
-use super::mqtt_client::ClientStreamType;
+use super::{mqtt_client::ClientStreamType, mqtt_client_builder::MqttClientOptions};
 
 pub struct MqttClientConnector {
     stream: ClientStreamType,
+    server_topic_alias_maximum: u16, // topic aliases que acepta el server, según su connack.
+    ack_timeout: Duration,           // lo que se espera por el connack antes de retransmitir el connect.
+    packet_observers: PacketObservers,
     logger: StringLogger,
 }
 
+/// Conexión establecida con el server, con lo negociado en el connect.
+#[derive(Debug)]
+pub struct BrokerConnection {
+    pub stream: ClientStreamType,
+    pub protocol_version: ProtocolVersion,
+    pub topic_alias_maximum: u16, // topic aliases a usar en los publish: los pedidos, si el server los acepta.
+}
+
 impl MqttClientConnector {
+    /// Establece la conexión con el server en `addr`, y envía el connect según las `options` del cliente.
+    /// Si se pide mqtt 5 y el server no lo acepta, vuelve a conectarse con mqtt 3.1.1.
+    /// Devuelve la conexión, con la versión de protocolo negociada.
     pub fn mqtt_connect_to_broker(
-        client_id: String,
         addr: &SocketAddr,
-        will: Option<WillMessageData>,
+        options: &MqttClientOptions,
+        logger: StringLogger,
+    ) -> Result<BrokerConnection, Error> {
+        let protocol_version = options.get_protocol_version();
+        match Self::connect_with_version(addr, options, protocol_version, logger.clone_ref()) {
+            Err(e) if protocol_version == ProtocolVersion::Mqtt5 && is_protocol_refused(&e) => {
+                logger.log("Mqtt: el server no acepta mqtt 5, reintentando con 3.1.1.".to_string());
+                Self::connect_with_version(addr, options, ProtocolVersion::Mqtt311, logger)
+            }
+            res => res,
+        }
+    }
+
+    /// Establece la conexión y envía el connect con la versión de protocolo `protocol_version`, sin negociar otra.
+    /// Lo usan las reconexiones, que conservan la versión negociada en la primera conexión.
+    pub fn connect_with_version(
+        addr: &SocketAddr,
+        options: &MqttClientOptions,
+        protocol_version: ProtocolVersion,
         logger: StringLogger,
-    ) -> Result<ClientStreamType, Error> {
+    ) -> Result<BrokerConnection, Error> {
         // Intenta conectar al servidor MQTT
-        let stream = TcpStream::connect(addr)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Error para establecer conexión con servidor."))?;
+        let stream = match (options.get_loopback(), options.get_connect_timeout()) {
+            (Some(connector), _) => connector.connect().map(StreamType::from),
+            (None, Some(timeout)) => StreamType::connect_timeout(addr, timeout),
+            (None, None) => StreamType::connect(addr),
+        }
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Error para establecer conexión con servidor."))?;
+        stream.set_write_timeout(options.get_write_timeout())?;
         let mut connector = Self {
             stream: stream.try_clone()?, // obs: como no devuelvo Self, esta copia del stream se dropea al salir de esta función y no molesta.
+            server_topic_alias_maximum: 0,
+            ack_timeout: options.get_ack_timeout(),
+            packet_observers: options.get_packet_observers(),
             logger,
         };
 
-        // Aux: sintaxis es let (a, b) = if condicion { (a_si_true, b_si_true) } else { (a_si_false, b_si_false) };
-        let (will_msg_content, will_topic, will_qos, _will_retain) = if let Some(will) = will {
-            (
-                Some(will.get_will_msg_content()),
-                Some(will.get_will_topic()),
-                will.get_qos(),
-                will.get_will_retain(),
-            )
-        } else {
-            (None, None, 1, 1)
-        };
-
         // Crea el mensaje tipo Connect y lo pasa a bytes
         let mut msg = ConnectMessage::new(
-            client_id,
-            will_topic,
-            will_msg_content,
-            Some("usuario0".to_string()),
-            Some("rustx123".to_string()),
-            will_qos,
+            options.get_client_id().to_string(),
+            options.get_will(),
+            options.get_username(),
+            options.get_password(),
+            options.get_keep_alive(),
         );
+        msg.set_clean_session(options.is_clean_session());
+        msg.set_protocol_version(protocol_version);
+        let mut properties = Properties::default();
+        if let Some(seconds) = options.get_session_expiry_interval() {
+            properties = properties.with_session_expiry_interval(seconds);
+        }
+        // Indica al server cuántos topic aliases acepta el cliente en los publish que recibe.
+        if options.get_topic_alias_maximum() > 0 {
+            properties = properties.with_topic_alias_maximum(options.get_topic_alias_maximum());
+        }
+        msg.set_properties(properties);
 
         connector.logger.log("Mqtt: Enviando connect msg.".to_string());
         connector.send_and_retransmit(&mut msg)?;
         connector.logger.log("Mqtt: connack recibido.".to_string());
 
-        Ok(stream)
+        Ok(BrokerConnection {
+            stream,
+            protocol_version,
+            topic_alias_maximum: connector
+                .server_topic_alias_maximum
+                .min(options.get_topic_alias_maximum()),
+        })
     }
     
     /// Envía el mensaje `msg` recibido una vez, espera por el ack, y si es necesario lo retransmite una cierta
@@ -77,6 +122,7 @@ impl MqttClientConnector {
     /// enviarse por el stream a server.
     fn send_msg(&mut self, bytes_msg: Vec<u8>) -> Result<(), Error> {
         write_message_to_stream(&bytes_msg, &mut self.stream)?;
+        self.packet_observers.notify(PacketDirection::Outbound, &bytes_msg);
         Ok(())
     }
     
@@ -103,10 +149,7 @@ impl MqttClientConnector {
 
         if !received_ack {
             // Ya salí del while, retransmití muchas veces y nunca recibí el ack, desisto.
-            return Err(Error::new(
-                ErrorKind::Other,
-                "MAXRETRIES, se retransmitió el connect sin éxito.",
-            ));
+            return Err(MqttError::AckTimeout("connect".to_string()).into());
         }
 
         Ok(())
@@ -118,14 +161,17 @@ impl MqttClientConnector {
         const FIXED_HEADER_LEN: usize = FixedHeader::fixed_header_len();
         let mut fixed_header_buf: [u8; 2] = [0; FIXED_HEADER_LEN];
 
-        // Espero recibir un connack en como mucho un cierto tiempo constante.
-        const ACK_WAITING_INTERVAL: u64 = 1000;
-        let max_waiting_interval = Duration::from_millis(ACK_WAITING_INTERVAL);
-        self.stream.set_read_timeout(Some(max_waiting_interval))?;
+        // Espero recibir un connack en como mucho el tiempo configurado.
+        self.stream.set_read_timeout(Some(self.ack_timeout))?;
         // Leo
         let was_there_connack = self.stream.read(&mut fixed_header_buf);
         match was_there_connack {
-            Ok(_) => {
+            Ok(0) => Err(MqttError::ConnectionClosed.into()),
+            Ok(read) => {
+                // Si llegó solamente el primer byte, el segundo está por llegar.
+                if !read_exact_or_eof(&mut self.stream, &mut fixed_header_buf[read..])? {
+                    return Err(MqttError::ConnectionClosed.into());
+                }
                 // He leído bytes de un fixed_header, tengo que ver de qué tipo es.
                 let fixed_header = FixedHeader::from_bytes(fixed_header_buf.to_vec());
                 if fixed_header.get_message_type() == PacketType::Connack {
@@ -170,17 +216,31 @@ impl MqttClientConnector {
             &mut self.stream,
             &fixed_header_buf,
         )?;
+        self.packet_observers.notify(PacketDirection::Inbound, &recvd_bytes);
         // Entonces tengo el mensaje completo
         let msg = ConnackMessage::from_bytes(&recvd_bytes)?; //
         println!("   Mensaje conn ack completo recibido: {:?}", msg);
         let ret = msg.get_connect_return_code();
         if ret == ConnectReturnCode::ConnectionAccepted {
+            if msg.is_session_present() {
+                self.logger.log("Mqtt: el server retoma la sesión anterior.".to_string());
+            }
+            // Un server que no informa cuántos topic aliases acepta (ie de mqtt 3.1.1) no acepta ninguno.
+            self.server_topic_alias_maximum = msg
+                .get_properties()
+                .and_then(|properties| properties.get_topic_alias_maximum())
+                .unwrap_or(0);
             Ok(())
         } else {
-            Err(Error::new(
-                ErrorKind::InvalidData,
-                "La conexión no fue aceptada.",
-            ))
+            Err(MqttError::ConnectionRefused(ret).into())
         }
     }
 }
+
+/// Devuelve si el error `error` se debe a que el server rechazó la versión de protocolo del connect.
+fn is_protocol_refused(error: &Error) -> bool {
+    matches!(
+        MqttError::from_io_error(error),
+        Some(MqttError::ConnectionRefused(ConnectReturnCode::ProtocolError))
+    )
+}
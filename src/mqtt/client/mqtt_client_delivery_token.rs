@@ -0,0 +1,213 @@
+use std::{
+    io::Error,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+use super::mqtt_client_retransmitter::{lock_retransmitter, ShareableRetransmitter};
+
+/// Tiempo que se esperan acks en cada vuelta, mientras se espera el resultado de una entrega.
+const ACK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Resultado de la entrega de un publish al server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryOutcome {
+    /// Publish de qos 0: se escribió, pero el server no confirma su recepción.
+    Sent,
+    /// El server respondió con el reason code `0` si lo aceptó, o con el motivo por el que no
+    /// (ie `0x87` si el cliente no está autorizado a publicar en el topic).
+    Acknowledged(u8),
+    /// Se retransmitió la cantidad máxima de veces sin recibir su ack.
+    RetriesExhausted,
+    /// Se descartó sin enviarse, ie por llenarse la cola de publish sin conexión.
+    Discarded,
+}
+
+impl DeliveryOutcome {
+    /// Devuelve si el server confirmó haber aceptado el publish.
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self, DeliveryOutcome::Acknowledged(reason_code) if *reason_code < 0x80)
+    }
+}
+
+/// Extremo con el que el `Retransmitter` informa el resultado de la entrega de un publish.
+pub type DeliveryNotifier = Sender<DeliveryOutcome>;
+
+/// Handle de un publish enviado, que se resuelve cuando llega su ack o se desiste de retransmitirlo.
+/// Mientras se espera su resultado, procesa los acks que llegan y retransmite los publish que no lo recibieron
+/// a tiempo, por lo que se resuelve aunque la aplicación no vuelva a publicar.
+#[derive(Debug)]
+pub struct DeliveryToken {
+    outcome_rx: Receiver<DeliveryOutcome>,
+    outcome: Option<DeliveryOutcome>,
+    retransmitter: ShareableRetransmitter,
+}
+
+impl DeliveryToken {
+    /// Crea el token, y el notifier por el que se le informará el resultado.
+    pub fn new(retransmitter: ShareableRetransmitter) -> (Self, DeliveryNotifier) {
+        let (outcome_tx, outcome_rx) = channel();
+        let token = DeliveryToken {
+            outcome_rx,
+            outcome: None,
+            retransmitter,
+        };
+        (token, outcome_tx)
+    }
+
+    /// Devuelve el resultado de la entrega si ya se conoce, sin esperar.
+    pub fn try_outcome(&mut self) -> Result<Option<DeliveryOutcome>, Error> {
+        if self.outcome.is_none() {
+            self.receive_outcome()?;
+        }
+        if self.outcome.is_none() {
+            lock_retransmitter(&self.retransmitter)?.process_acks(Duration::ZERO)?;
+            self.receive_outcome()?;
+        }
+        Ok(self.outcome)
+    }
+
+    /// Espera a conocer el resultado de la entrega.
+    pub fn wait(mut self) -> Result<DeliveryOutcome, Error> {
+        loop {
+            if let Some(outcome) = self.wait_timeout(ACK_POLL_INTERVAL)? {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Espera a lo sumo `timeout` a conocer el resultado de la entrega. Devuelve None si no se conoció a tiempo.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<DeliveryOutcome>, Error> {
+        let deadline = Instant::now() + timeout;
+        while self.try_outcome()?.is_none() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            // Se suelta el lock entre vueltas, para no frenar a quien publica mientras tanto.
+            lock_retransmitter(&self.retransmitter)?
+                .process_acks(remaining.min(ACK_POLL_INTERVAL))?;
+        }
+        Ok(self.outcome)
+    }
+
+    fn receive_outcome(&mut self) -> Result<(), Error> {
+        match self.outcome_rx.try_recv() {
+            Ok(outcome) => self.outcome = Some(outcome),
+            Err(TryRecvError::Empty) => {}
+            // Se descartó el notifier sin informar el resultado, ie porque falló el envío.
+            Err(TryRecvError::Disconnected) => return Err(MqttError::NotConnected.into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeliveryOutcome, DeliveryToken};
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::client::{
+        ack_message::ACKMessage,
+        mqtt_client_packet_id_manager::PacketIdManager,
+        mqtt_client_retransmitter::{lock_retransmitter, Retransmitter, ShareableRetransmitter},
+    };
+    use crate::mqtt::messages::{
+        puback_message::{PubAckMessage, PUBACK_NOT_AUTHORIZED},
+        publish_flags::PublishFlags,
+        publish_message::PublishMessage,
+    };
+    use std::{
+        net::{TcpListener, TcpStream},
+        sync::{
+            mpsc::{self, Sender},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    /// Devuelve un retransmitter conectado a un server local que no responde, el tx por el que llegan
+    /// sus acks, y el extremo del server de la conexión.
+    fn create_retransmitter() -> (ShareableRetransmitter, Sender<ACKMessage>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        let (log_tx, _log_rx) = mpsc::channel();
+        let (retransmitter, ack_tx) = Retransmitter::new(
+            stream.into(),
+            StringLogger::new(log_tx),
+            Arc::new(Mutex::new(Instant::now())),
+            Arc::new(Mutex::new(PacketIdManager::new())),
+        );
+        (Arc::new(Mutex::new(retransmitter)), ack_tx, server_stream)
+    }
+
+    fn create_publish(packet_id: u16) -> PublishMessage {
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        PublishMessage::new(flags, "dron", Some(packet_id), b"posicion").unwrap()
+    }
+
+    #[test]
+    fn test_1_el_token_se_resuelve_con_el_reason_code_del_puback() {
+        let (retransmitter, ack_tx, _server_stream) = create_retransmitter();
+        let (token, notifier) = DeliveryToken::new(retransmitter.clone());
+        {
+            let mut retransmitter = lock_retransmitter(&retransmitter).unwrap();
+            retransmitter.register_delivery_notifier(1, notifier);
+            retransmitter
+                .send_and_retransmit(&create_publish(1))
+                .unwrap();
+        }
+
+        ack_tx
+            .send(ACKMessage::PubAck(PubAckMessage::new(
+                1,
+                PUBACK_NOT_AUTHORIZED,
+            )))
+            .unwrap();
+
+        let outcome = token.wait().unwrap();
+        assert_eq!(
+            outcome,
+            DeliveryOutcome::Acknowledged(PUBACK_NOT_AUTHORIZED)
+        );
+        assert!(!outcome.is_confirmed());
+    }
+
+    #[test]
+    fn test_2_el_token_no_se_resuelve_hasta_conocer_el_resultado() {
+        let (retransmitter, ack_tx, _server_stream) = create_retransmitter();
+        let (mut token, notifier) = DeliveryToken::new(retransmitter.clone());
+        {
+            let mut retransmitter = lock_retransmitter(&retransmitter).unwrap();
+            retransmitter.register_delivery_notifier(2, notifier);
+            retransmitter
+                .send_and_retransmit(&create_publish(2))
+                .unwrap();
+        }
+
+        assert_eq!(token.try_outcome().unwrap(), None);
+        assert_eq!(token.wait_timeout(Duration::from_millis(10)).unwrap(), None);
+
+        ack_tx
+            .send(ACKMessage::PubAck(PubAckMessage::new(2, 0)))
+            .unwrap();
+        let outcome = token.wait_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(outcome, Some(DeliveryOutcome::Acknowledged(0)));
+        assert!(outcome.unwrap().is_confirmed());
+    }
+
+    #[test]
+    fn test_3_el_token_de_un_publish_descartado_se_resuelve_como_descartado() {
+        let (retransmitter, _ack_tx, _server_stream) = create_retransmitter();
+        let (token, notifier) = DeliveryToken::new(retransmitter.clone());
+        {
+            let mut retransmitter = lock_retransmitter(&retransmitter).unwrap();
+            retransmitter.register_delivery_notifier(3, notifier);
+            retransmitter.notify_delivery(3, DeliveryOutcome::Discarded);
+        }
+
+        assert_eq!(token.wait().unwrap(), DeliveryOutcome::Discarded);
+    }
+}
@@ -1,26 +1,83 @@
-use std::sync::mpsc::Sender;
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc::Sender, Arc, Mutex};
 
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use crate::mqtt::messages::{
-    packet_type::PacketType, puback_message::PubAckMessage, publish_message::PublishMessage,
-    suback_message::SubAckMessage,
+    packet_type::PacketType, pingresp_message::PingRespMessage, protocol_version::ProtocolVersion,
+    puback_message::PubAckMessage, pubcomp_message::PubCompMessage, publish_message::PublishMessage, pubrec_message::PubRecMessage,
+    pubrel_message::PubRelMessage, suback_message::SubAckMessage, unsuback_message::Unsuback,
 };
 
 use crate::mqtt::client::ack_message::ACKMessage;
 use crate::mqtt::mqtt_utils::fixed_header::FixedHeader;
+use crate::mqtt::mqtt_utils::packet_observer::{PacketDirection, PacketObservers};
+use crate::mqtt::mqtt_utils::topic_aliases::IncomingTopicAliases;
 use crate::mqtt::mqtt_utils::utils::{
     get_fixed_header_from_stream, get_whole_message_in_bytes_from_stream, is_disconnect_msg,
-    send_puback, shutdown,
+    shutdown, write_message_to_stream,
 };
 
 use super::mqtt_client::ClientStreamType;
 
+/// Función que la app registra para procesar los PublishMessage recibidos de un topic.
+pub type PublishHandler = Arc<dyn Fn(PublishMessage) + Send + Sync>;
+
+/// Handlers registrados por topic. Los publish de topics sin handler se envían a la app por el rx.
+#[derive(Clone, Default)]
+pub struct TopicHandlers {
+    handlers: Arc<Mutex<HashMap<String, PublishHandler>>>,
+}
+
+impl TopicHandlers {
+    /// Registra `handler` para los publish recibidos del topic `topic`, reemplazando al anterior si lo había.
+    pub fn insert(&self, topic: &str, handler: PublishHandler) -> Result<(), Error> {
+        self.lock()?.insert(topic.to_string(), handler);
+        Ok(())
+    }
+
+    /// Quita los handlers de los topics `topics`.
+    pub fn remove(&self, topics: &[String]) -> Result<(), Error> {
+        let mut handlers = self.lock()?;
+        for topic in topics {
+            handlers.remove(topic);
+        }
+        Ok(())
+    }
+
+    /// Devuelve el handler registrado para el topic `topic`, si lo hay.
+    pub fn get(&self, topic: &str) -> Result<Option<PublishHandler>, Error> {
+        Ok(self.lock()?.get(topic).cloned())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, PublishHandler>>, Error> {
+        self.handlers
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("los handlers".to_string()).into())
+    }
+}
+
+impl std::fmt::Debug for TopicHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let topics: Vec<String> = match self.handlers.lock() {
+            Ok(handlers) => handlers.keys().cloned().collect(),
+            Err(_) => vec![],
+        };
+        f.debug_struct("TopicHandlers").field("topics", &topics).finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct MQTTClientListener {
     stream: ClientStreamType,
     client_tx: Sender<PublishMessage>,
     ack_tx: Sender<ACKMessage>,
+    topic_handlers: TopicHandlers,
+    qos2_packet_ids_awaiting_pubrel: HashSet<u16>, // publish qos 2 recibidos, cuyo pubrel aún no llegó.
+    protocol_version: ProtocolVersion, // negociada con el server, define cómo se interpretan los publish.
+    topic_aliases: IncomingTopicAliases, // asignados por el server en los publish de esta conexión.
+    packet_observers: PacketObservers, // observan los mensajes leídos, y los acks que se responden.
 }
 
 impl MQTTClientListener {
@@ -28,16 +85,36 @@ impl MQTTClientListener {
         stream: ClientStreamType,
         client_tx: Sender<PublishMessage>,
         ack_tx: Sender<ACKMessage>,
+        topic_handlers: TopicHandlers,
     ) -> Self {
         MQTTClientListener {
             stream,
             client_tx,
             ack_tx,
+            topic_handlers,
+            qos2_packet_ids_awaiting_pubrel: HashSet::new(),
+            protocol_version: ProtocolVersion::default(),
+            topic_aliases: IncomingTopicAliases::default(),
+            packet_observers: PacketObservers::default(),
         }
     }
 
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.protocol_version = protocol_version;
+    }
+
+    /// Establece cuántos topic aliases se aceptan en los publish del server, los indicados en el connect.
+    pub fn set_topic_alias_maximum(&mut self, topic_alias_maximum: u16) {
+        self.topic_aliases = IncomingTopicAliases::new(topic_alias_maximum);
+    }
+
+    pub fn set_packet_observers(&mut self, packet_observers: PacketObservers) {
+        self.packet_observers = packet_observers;
+    }
+
     /// Función que ejecutará un hilo de MQTTClient, dedicado exclusivamente a la lectura.
-    pub fn read_from_server(&mut self) -> Result<(), Error> {
+    /// Termina al cerrarse la conexión, y devuelve si fue el server quien la cerró enviando un disconnect.
+    pub fn read_from_server(&mut self) -> Result<bool, Error> {
         let mut fixed_header_info: ([u8; 2], FixedHeader);
 
         loop {
@@ -48,21 +125,21 @@ impl MQTTClientListener {
                     // Caso se recibe un disconnect
                     if is_disconnect_msg(&fixed_header_info.1) {
                         println!("Mqtt cliente leyendo: recibo disconnect");
+                        self.packet_observers
+                            .notify(PacketDirection::Inbound, &fixed_header_info.0);
                         shutdown(&self.stream);
-                        break;
+                        return Ok(true);
                     }
 
                     self.read_a_message(&fixed_header_info)?; // esta función lee UN mensaje.
                 }
                 Ok(None) => {
                     println!("Se cerró la conexión con server.");
-                    break;
+                    return Ok(false);
                 }
-                Err(_) => todo!(),
+                Err(e) => return Err(e),
             }
         }
-
-        Ok(())
     }
 
     /// Función interna que lee un mensaje, analiza su tipo, y lo procesa acorde a él.
@@ -75,17 +152,23 @@ impl MQTTClientListener {
             &mut self.stream,
             fixed_header_bytes,
         )?;
+        self.packet_observers.notify(PacketDirection::Inbound, &msg_bytes);
 
         match tipo {
             PacketType::Publish => self.handle_publish(msg_bytes)?,
             PacketType::Puback => self.handle_puback(msg_bytes)?,
             PacketType::Suback => self.handle_suback(msg_bytes)?,
+            PacketType::Unsuback => self.handle_unsuback(msg_bytes)?,
+            PacketType::Pubrec => self.handle_pubrec(msg_bytes)?,
+            PacketType::Pubrel => self.handle_pubrel(msg_bytes)?,
+            PacketType::Pubcomp => self.handle_pubcomp(msg_bytes)?,
+            PacketType::Pingresp => self.handle_pingresp(msg_bytes)?,
             _ => {
                 println!(
                     "   ERROR: tipo desconocido: recibido: \n   {:?}",
                     fixed_header
                 );
-                return Err(Error::new(ErrorKind::Other, "Tipo desconocido."));
+                return Err(MqttError::MalformedPacket(format!("tipo desconocido {:?}", tipo)).into());
             }
         };
 
@@ -94,9 +177,32 @@ impl MQTTClientListener {
 
     fn handle_publish(&mut self, msg_bytes: Vec<u8>) -> Result<(), Error> {
         println!("Mqtt cliente leyendo: RECIBO MENSAJE TIPO PUBLISH");
-        let msg = PublishMessage::from_bytes(msg_bytes)?;
-        send_puback(&msg, &mut self.stream)?;
-        // Envía PublishMessage a la app
+        let msg = PublishMessage::from_bytes_with_version(msg_bytes, self.protocol_version)?;
+        let msg = self.topic_aliases.resolve(msg)?;
+        if msg.get_qos() == 2 {
+            return self.handle_qos2_publish(msg);
+        }
+        if let Some(packet_id) = msg.get_packet_id() {
+            self.write_to_server(&PubAckMessage::new(packet_id, 0).to_bytes())?;
+            println!("   tipo publish: Enviado el ack para packet_id: {:?}", packet_id);
+        }
+        self.deliver_to_app(msg)
+    }
+
+    /// Escribe al server el ack `msg_bytes`, y lo informa a los packet observers.
+    fn write_to_server(&mut self, msg_bytes: &[u8]) -> Result<(), Error> {
+        write_message_to_stream(msg_bytes, &mut self.stream)?;
+        self.packet_observers.notify(PacketDirection::Outbound, msg_bytes);
+        Ok(())
+    }
+
+    /// Entrega el PublishMessage a la app: al handler de su topic si hay uno registrado, o si no, por el tx.
+    /// El handler se ejecuta en este hilo, por lo que no debe quedarse esperando acks del server.
+    fn deliver_to_app(&self, msg: PublishMessage) -> Result<(), Error> {
+        if let Some(handler) = self.topic_handlers.get(&msg.get_topic())? {
+            handler(msg);
+            return Ok(());
+        }
         match self.client_tx.send(msg) {
             Ok(_) => println!("Mqtt cliente leyendo: se envía por tx exitosamente."),
             Err(_) => println!("Mqtt cliente leyendo: error al enviar por tx."),
@@ -104,6 +210,56 @@ impl MQTTClientListener {
         Ok(())
     }
 
+    /// Responde con pubrec, y envía el PublishMessage a la app solamente la primera vez que se recibe
+    /// su packet_id (hasta que llegue el pubrel), para que cada mensaje de qos 2 llegue exactamente una vez.
+    fn handle_qos2_publish(&mut self, msg: PublishMessage) -> Result<(), Error> {
+        let packet_id = msg.get_packet_id().unwrap_or(0);
+        let is_new = self.qos2_packet_ids_awaiting_pubrel.insert(packet_id);
+        self.write_to_server(&PubRecMessage::new(packet_id).to_bytes())?;
+        if is_new {
+            self.deliver_to_app(msg)?;
+        } else {
+            println!("Mqtt cliente leyendo: publish qos 2 duplicado, packet_id: {:?}", packet_id);
+        }
+        Ok(())
+    }
+
+    /// Server liberó el packet_id de un publish de qos 2 que nos envió, respondemos con pubcomp.
+    fn handle_pubrel(&mut self, msg_bytes: Vec<u8>) -> Result<(), Error> {
+        let msg = PubRelMessage::msg_from_bytes(msg_bytes)?;
+        let packet_id = msg.get_packet_id();
+        self.qos2_packet_ids_awaiting_pubrel.remove(&packet_id);
+        self.write_to_server(&PubCompMessage::new(packet_id).to_bytes())?;
+        Ok(())
+    }
+
+    fn handle_pubrec(&self, msg_bytes: Vec<u8>) -> Result<(), Error> {
+        let msg = PubRecMessage::msg_from_bytes(msg_bytes)?;
+        // Avisa que llegó el ack
+        match self.ack_tx.send(ACKMessage::PubRec(msg)) {
+            Ok(_) => println!("PubRec enviado por tx exitosamente."),
+            Err(_) => println!("Error al enviar PubRec por tx."),
+        }
+        Ok(())
+    }
+
+    fn handle_pubcomp(&self, msg_bytes: Vec<u8>) -> Result<(), Error> {
+        let msg = PubCompMessage::msg_from_bytes(msg_bytes)?;
+        // Avisa que llegó el ack
+        match self.ack_tx.send(ACKMessage::PubComp(msg)) {
+            Ok(_) => println!("PubComp enviado por tx exitosamente."),
+            Err(_) => println!("Error al enviar PubComp por tx."),
+        }
+        Ok(())
+    }
+
+    /// Server respondió a nuestro PingReq, la conexión sigue activa.
+    fn handle_pingresp(&self, msg_bytes: Vec<u8>) -> Result<(), Error> {
+        PingRespMessage::msg_from_bytes(msg_bytes)?;
+        println!("Mqtt cliente leyendo: recibo ping resp");
+        Ok(())
+    }
+
     fn handle_puback(&self, msg_bytes: Vec<u8>) -> Result<(), Error> {
         let msg = PubAckMessage::msg_from_bytes(msg_bytes)?;
         // Avisa que llegó el ack
@@ -123,6 +279,16 @@ impl MQTTClientListener {
         }
         Ok(())
     }
+
+    fn handle_unsuback(&self, msg_bytes: Vec<u8>) -> Result<(), Error> {
+        let msg = Unsuback::from_bytes(&msg_bytes)?;
+        // Avisa que llegó el ack
+        match self.ack_tx.send(ACKMessage::UnsubAck(msg)) {
+            Ok(_) => println!("UnsubAck enviado por tx exitosamente."),
+            Err(_) => println!("Error al enviar UnsubAck por tx."),
+        }
+        Ok(())
+    }
 }
 
 /*impl Clone for MQTTClientListener {
@@ -133,3 +299,28 @@ impl MQTTClientListener {
         MQTTClientListener { stream, client_tx , ack_tx }
     }
 }*/
+
+#[cfg(test)]
+mod test {
+    use super::TopicHandlers;
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use std::sync::{mpsc, Arc};
+
+    #[test]
+    fn test_1_se_obtiene_el_handler_del_topic_hasta_quitarlo() {
+        let handlers = TopicHandlers::default();
+        let (tx, rx) = mpsc::channel::<String>();
+        handlers
+            .insert("inc", Arc::new(move |msg: PublishMessage| tx.send(msg.get_topic()).unwrap()))
+            .unwrap();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(1), "incidente".as_bytes()).unwrap();
+
+        assert!(handlers.get("dron").unwrap().is_none());
+        handlers.get("inc").unwrap().unwrap()(msg);
+        assert_eq!(rx.recv().unwrap(), "inc");
+
+        handlers.remove(&["inc".to_string()]).unwrap();
+        assert!(handlers.get("inc").unwrap().is_none());
+    }
+}
@@ -1,22 +1,31 @@
 use crate::mqtt::messages::{
-    disconnect_message::DisconnectMessage, publish_flags::PublishFlags,
+    disconnect_message::DisconnectMessage, protocol_version::ProtocolVersion, publish_flags::PublishFlags,
     publish_message::PublishMessage, subscribe_message::SubscribeMessage,
+    unsubscribe_message::UnsubscribeMessage,
 };
 
 use std::io::Error;
 
+use super::mqtt_client_packet_id_manager::{allocate_packet_id, release_packet_id, PacketIds};
+
 #[derive(Debug)]
 pub struct MessageCreator {
-    available_packet_id: u16,
+    packet_ids: PacketIds,
+    protocol_version: ProtocolVersion, // negociada con el server, define cómo se codifican los publish.
 }
 
 impl MessageCreator {
-    pub fn new() -> MessageCreator {
+    pub fn new(packet_ids: PacketIds) -> MessageCreator {
         MessageCreator {
-            available_packet_id: 0,
+            packet_ids,
+            protocol_version: ProtocolVersion::default(),
         }
     }
 
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.protocol_version = protocol_version;
+    }
+
     /// Crea y devuelve el PublishMessage.
     pub fn create_publish_msg(
         &mut self,
@@ -24,10 +33,27 @@ impl MessageCreator {
         payload: &[u8],
         qos: u8,
     ) -> Result<PublishMessage, Error> {
-        let packet_id = self.generate_packet_id();
+        self.create_publish_msg_with_retain(topic, payload, qos, false)
+    }
+
+    /// Crea y devuelve el PublishMessage, con el flag de retain seteado según `retain`.
+    pub fn create_publish_msg_with_retain(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: u8,
+        retain: bool,
+    ) -> Result<PublishMessage, Error> {
+        let flags = PublishFlags::new(0, qos, retain as u8)?;
+        // Por protocolo, un publish de qos 0 no lleva packet_id.
+        let packet_id = if qos > 0 {
+            Some(self.generate_packet_id()?)
+        } else {
+            None
+        };
         // Creo un msj publish
-        let flags = PublishFlags::new(0, qos, 0)?;
-        let publish_msg = PublishMessage::new(flags, topic, Some(packet_id), payload)?;
+        let publish_msg = PublishMessage::new(flags, topic, packet_id, payload)?
+            .for_protocol_version(self.protocol_version);
 
         Ok(publish_msg)
     }
@@ -38,30 +64,41 @@ impl MessageCreator {
         &mut self,
         topics_to_subscribe: Vec<(String, u8)>,
     ) -> Result<SubscribeMessage, Error> {
-        let packet_id = self.generate_packet_id();
+        let packet_id = self.generate_packet_id()?;
         // Construyo subscribe
         let subscribe_msg = SubscribeMessage::new(packet_id, topics_to_subscribe);        
 
         Ok(subscribe_msg)
     }
 
+    /// Recibe un vector de topics de los cuales el cliente desea desuscribirse.
+    /// Crea y devuelve el UnsubscribeMessage.
+    pub fn create_unsubscribe_msg(
+        &mut self,
+        topics_to_unsubscribe: Vec<String>,
+    ) -> Result<UnsubscribeMessage, Error> {
+        let packet_id = self.generate_packet_id()?;
+        let unsubscribe_msg = UnsubscribeMessage::new(packet_id, topics_to_unsubscribe);
+
+        Ok(unsubscribe_msg)
+    }
+
     /// Crea y devuelve un DisconnectMessage.
     pub fn create_disconnect_msg(&mut self) -> Result<DisconnectMessage, Error> {
         let msg = DisconnectMessage::new();
         Ok(msg)
     }
 
-    /// Devuelve el packet_id a usar para el siguiente mensaje enviado.
-    /// Incrementa en 1 el atributo correspondiente, debido a la llamada anterior, y devuelve el valor a ser usado
-    /// en el envío para el cual fue llamada esta función.
-    fn generate_packet_id(&mut self) -> u16 {
-        self.available_packet_id += 1;
-        self.available_packet_id
+    /// Libera el packet_id de un publish creado que finalmente no se envía (ie descartado de la cola sin conexión).
+    pub fn discard_publish_msg(&self, msg: &PublishMessage) {
+        if let Some(packet_id) = msg.get_packet_id() {
+            release_packet_id(&self.packet_ids, packet_id);
+        }
     }
-}
 
-impl Default for MessageCreator {
-    fn default() -> Self {
-        Self::new()
+    /// Devuelve el packet_id a usar para el siguiente mensaje enviado: uno que no esté esperando su ack.
+    /// El `Retransmitter` lo libera al recibir el ack, o al desistir de retransmitir el mensaje.
+    fn generate_packet_id(&mut self) -> Result<u16, Error> {
+        allocate_packet_id(&self.packet_ids)
     }
 }
@@ -0,0 +1,163 @@
+use std::{
+    collections::VecDeque,
+    io::Error,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+/// Capacidad por defecto de la cola de publish mientras el cliente está desconectado.
+pub const DEFAULT_OFFLINE_QUEUE_CAPACITY: usize = 100;
+
+/// Cola de publish compartida entre `MQTTClient`, que encola mientras no hay conexión,
+/// y el `Reconnector`, que la vacía al reconectarse.
+pub type ShareableOfflineQueue = Arc<Mutex<OfflineQueue>>;
+
+/// Qué hacer con un publish cuando la cola de publish sin conexión está llena.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OfflineOverflowPolicy {
+    /// Se descarta el publish más antiguo de la cola, para encolar el nuevo
+    /// (ie la posición más reciente de un dron es la que importa).
+    #[default]
+    DropOldest,
+    /// Se descarta el publish nuevo, y la cola queda como estaba.
+    DropNew,
+    /// El publish nuevo no se encola, y se devuelve error a quien publica.
+    Error,
+}
+
+/// Publish que `MQTTClient` no pudo enviar por estar desconectado del server, a enviar al reconectarse.
+#[derive(Debug)]
+pub struct OfflineQueue {
+    connected: bool,
+    capacity: usize,
+    overflow_policy: OfflineOverflowPolicy,
+    messages: VecDeque<PublishMessage>,
+}
+
+impl OfflineQueue {
+    pub fn new(capacity: usize, overflow_policy: OfflineOverflowPolicy) -> Self {
+        OfflineQueue {
+            connected: true,
+            capacity,
+            overflow_policy,
+            messages: VecDeque::new(),
+        }
+    }
+
+    /// Establece la capacidad de la cola y qué hacer al llenarse.
+    /// Devuelve los publish más antiguos que se descartan por exceder la nueva capacidad.
+    pub fn configure(
+        &mut self,
+        capacity: usize,
+        overflow_policy: OfflineOverflowPolicy,
+    ) -> Vec<PublishMessage> {
+        self.capacity = capacity;
+        self.overflow_policy = overflow_policy;
+        let excess = self.messages.len().saturating_sub(capacity);
+        self.messages.drain(..excess).collect()
+    }
+
+    /// Devuelve si el cliente está conectado al server.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Registra si el cliente está conectado al server.
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    /// Encola el publish según la política de la cola si está llena.
+    /// Devuelve el publish descartado para hacerle lugar (o el mismo `msg`, si es el que se descarta),
+    /// o error si la política es `Error` y la cola está llena.
+    pub fn push(&mut self, msg: PublishMessage) -> Result<Option<PublishMessage>, Error> {
+        if self.messages.len() < self.capacity {
+            self.messages.push_back(msg);
+            return Ok(None);
+        }
+        match self.overflow_policy {
+            OfflineOverflowPolicy::DropOldest => {
+                let dropped = self.messages.pop_front();
+                self.messages.push_back(msg);
+                // Con capacidad 0 no hay lugar ni para el nuevo.
+                if self.messages.len() > self.capacity {
+                    return Ok(self.messages.pop_front());
+                }
+                Ok(dropped)
+            }
+            OfflineOverflowPolicy::DropNew => Ok(Some(msg)),
+            OfflineOverflowPolicy::Error => {
+                Err(MqttError::QueueFull("publish sin conexión".to_string()).into())
+            }
+        }
+    }
+
+    /// Quita y devuelve todos los publish encolados, en el orden en que se publicaron.
+    pub fn drain(&mut self) -> Vec<PublishMessage> {
+        self.messages.drain(..).collect()
+    }
+}
+
+impl Default for OfflineQueue {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_OFFLINE_QUEUE_CAPACITY,
+            OfflineOverflowPolicy::default(),
+        )
+    }
+}
+
+/// Toma el lock de la cola de publish sin conexión.
+pub fn lock_offline_queue(
+    offline_queue: &ShareableOfflineQueue,
+) -> Result<MutexGuard<'_, OfflineQueue>, Error> {
+    offline_queue
+        .lock()
+        .map_err(|_| MqttError::LockPoisoned("la cola de publish sin conexión".to_string()).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OfflineOverflowPolicy, OfflineQueue};
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+
+    fn create_publish(packet_id: u16) -> PublishMessage {
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        PublishMessage::new(flags, "dron", Some(packet_id), "posicion".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_1_drop_oldest_descarta_el_publish_mas_antiguo() {
+        let mut queue = OfflineQueue::new(2, OfflineOverflowPolicy::DropOldest);
+        assert_eq!(queue.push(create_publish(1)).unwrap(), None);
+        assert_eq!(queue.push(create_publish(2)).unwrap(), None);
+
+        let dropped = queue.push(create_publish(3)).unwrap();
+
+        assert_eq!(dropped.unwrap().get_packet_id(), Some(1));
+        let ids: Vec<_> = queue.drain().iter().map(|m| m.get_packet_id()).collect();
+        assert_eq!(ids, vec![Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_2_drop_new_descarta_el_publish_nuevo() {
+        let mut queue = OfflineQueue::new(1, OfflineOverflowPolicy::DropNew);
+        queue.push(create_publish(1)).unwrap();
+
+        let dropped = queue.push(create_publish(2)).unwrap();
+
+        assert_eq!(dropped.unwrap().get_packet_id(), Some(2));
+        assert_eq!(queue.drain().len(), 1);
+    }
+
+    #[test]
+    fn test_3_con_politica_error_la_cola_llena_da_error() {
+        let mut queue = OfflineQueue::new(1, OfflineOverflowPolicy::Error);
+        queue.push(create_publish(1)).unwrap();
+
+        assert!(queue.push(create_publish(2)).is_err());
+        assert_eq!(queue.drain().len(), 1);
+    }
+}
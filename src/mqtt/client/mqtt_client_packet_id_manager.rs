@@ -0,0 +1,104 @@
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+use std::{
+    collections::HashSet,
+    io::Error,
+    sync::{Arc, Mutex},
+};
+
+/// Packet ids del cliente, compartidos entre quien crea los mensajes y quien espera sus acks.
+pub type PacketIds = Arc<Mutex<PacketIdManager>>;
+
+/// Asigna a los mensajes del cliente packet ids distintos de cero y que no estén esperando un ack,
+/// y los recicla cuando llega el ack correspondiente.
+#[derive(Debug, Default)]
+pub struct PacketIdManager {
+    last_packet_id: u16,
+    outstanding: HashSet<u16>, // packet ids de mensajes enviados cuyo ack aún no llegó.
+}
+
+impl PacketIdManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Devuelve el siguiente packet id libre, y lo registra como pendiente de ack.
+    /// Devuelve error si todos los packet ids están esperando un ack.
+    pub fn allocate(&mut self) -> Result<u16, Error> {
+        for _ in 0..u16::MAX {
+            // El packet id 0 no es válido, al llegar al máximo se vuelve a empezar desde 1.
+            self.last_packet_id = self.last_packet_id.checked_add(1).unwrap_or(1);
+            if self.outstanding.insert(self.last_packet_id) {
+                return Ok(self.last_packet_id);
+            }
+        }
+        Err(MqttError::PacketIdsExhausted.into())
+    }
+
+    /// Libera el packet id, para que pueda volver a asignarse.
+    pub fn release(&mut self, packet_id: u16) {
+        self.outstanding.remove(&packet_id);
+    }
+
+    /// Devuelve si el packet id está esperando su ack.
+    pub fn is_outstanding(&self, packet_id: u16) -> bool {
+        self.outstanding.contains(&packet_id)
+    }
+}
+
+/// Asigna un packet id libre, tomando el lock de `packet_ids`.
+pub fn allocate_packet_id(packet_ids: &PacketIds) -> Result<u16, Error> {
+    match packet_ids.lock() {
+        Ok(mut packet_ids) => packet_ids.allocate(),
+        Err(_) => Err(MqttError::LockPoisoned("packet_ids".to_string()).into()),
+    }
+}
+
+/// Libera el packet id, tomando el lock de `packet_ids`.
+pub fn release_packet_id(packet_ids: &PacketIds, packet_id: u16) {
+    if let Ok(mut packet_ids) = packet_ids.lock() {
+        packet_ids.release(packet_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PacketIdManager;
+
+    #[test]
+    fn test_1_los_packet_ids_asignados_son_distintos_y_no_nulos() {
+        let mut manager = PacketIdManager::new();
+        let first = manager.allocate().unwrap();
+        let second = manager.allocate().unwrap();
+
+        assert_ne!(first, 0);
+        assert_ne!(first, second);
+        assert!(manager.is_outstanding(first));
+    }
+
+    #[test]
+    fn test_2_al_dar_la_vuelta_se_saltean_los_que_esperan_ack() {
+        let mut manager = PacketIdManager::new();
+        let first = manager.allocate().unwrap();
+        for _ in 1..u16::MAX {
+            let packet_id = manager.allocate().unwrap();
+            manager.release(packet_id);
+        }
+
+        // Al dar la vuelta no se repite el 0 ni el primero, que sigue sin ack
+        let packet_id = manager.allocate().unwrap();
+        assert_eq!(packet_id, first + 1);
+    }
+
+    #[test]
+    fn test_3_un_packet_id_liberado_puede_reasignarse() {
+        let mut manager = PacketIdManager::new();
+        for _ in 0..u16::MAX {
+            manager.allocate().unwrap();
+        }
+        assert!(manager.allocate().is_err());
+
+        manager.release(7);
+        assert!(!manager.is_outstanding(7));
+        assert_eq!(manager.allocate().unwrap(), 7);
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::mqtt::{
+    messages::pingreq_message::PingReqMessage,
+    mqtt_utils::{
+        packet_observer::{PacketDirection, PacketObservers},
+        utils::write_message_to_stream,
+    },
+};
+
+use super::mqtt_client::ClientStreamType;
+
+/// Momento en que se envió el último mensaje al server, compartido entre quienes escriben en el stream.
+pub type LastActivity = Arc<Mutex<Instant>>;
+
+/// Parte interna de `MQTTClient` encargada de mantener viva la conexión, enviando PingReq
+/// cuando el cliente no envió ningún otro mensaje al server durante la mitad del keep alive.
+#[derive(Debug)]
+pub struct Pinger {
+    stream: ClientStreamType,
+    keep_alive: u16,
+    last_activity: LastActivity,
+    packet_observers: PacketObservers,
+}
+
+impl Pinger {
+    pub fn new(stream: ClientStreamType, keep_alive: u16, last_activity: LastActivity) -> Self {
+        Pinger {
+            stream,
+            keep_alive,
+            last_activity,
+            packet_observers: PacketObservers::default(),
+        }
+    }
+
+    /// Establece los observers a los que informar cada PingReq enviado.
+    pub fn set_packet_observers(&mut self, packet_observers: PacketObservers) {
+        self.packet_observers = packet_observers;
+    }
+
+    /// Función que ejecutará un hilo de MQTTClient, dedicado a enviar PingReq mientras el cliente esté ocioso.
+    /// Termina cuando falla la escritura, es decir cuando se cerró la conexión con el server.
+    pub fn run(&mut self) -> Result<(), Error> {
+        // Un keep alive de 0 significa que no se usa el mecanismo.
+        if self.keep_alive == 0 {
+            return Ok(());
+        }
+        let ping_interval = Duration::from_secs(self.keep_alive as u64) / 2;
+
+        loop {
+            thread::sleep(ping_interval);
+            if self.get_idle_time()? >= ping_interval {
+                let pingreq_bytes = PingReqMessage::new().to_bytes();
+                write_message_to_stream(&pingreq_bytes, &mut self.stream)?;
+                self.packet_observers
+                    .notify(PacketDirection::Outbound, &pingreq_bytes);
+                self.update_last_activity()?;
+            }
+        }
+    }
+
+    /// Devuelve cuánto tiempo pasó desde el último mensaje enviado al server.
+    fn get_idle_time(&self) -> Result<Duration, Error> {
+        match self.last_activity.lock() {
+            Ok(last_activity) => Ok(last_activity.elapsed()),
+            Err(_) => Err(Error::new(
+                ErrorKind::Other,
+                "Error al tomar lock de last_activity.",
+            )),
+        }
+    }
+
+    fn update_last_activity(&self) -> Result<(), Error> {
+        update_last_activity(&self.last_activity)
+    }
+}
+
+/// Registra que en este momento se envió un mensaje al server.
+pub fn update_last_activity(last_activity: &LastActivity) -> Result<(), Error> {
+    match last_activity.lock() {
+        Ok(mut last_activity) => {
+            *last_activity = Instant::now();
+            Ok(())
+        }
+        Err(_) => Err(Error::new(
+            ErrorKind::Other,
+            "Error al tomar lock de last_activity.",
+        )),
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::messages::publish_message::PublishMessage;
+use crate::mqtt::mqtt_utils::utils::shutdown;
+
+use super::{
+    ack_message::ACKMessage,
+    mqtt_client::ClientStreamType,
+    mqtt_client_builder::MqttClientOptions,
+    mqtt_client_connection_status::{ConnectionStatus, StatusListeners},
+    mqtt_client_connector::{BrokerConnection, MqttClientConnector},
+    mqtt_client_listener::{MQTTClientListener, TopicHandlers},
+    mqtt_client_msg_creator::MessageCreator,
+    mqtt_client_offline_queue::{lock_offline_queue, ShareableOfflineQueue},
+    mqtt_client_packet_id_manager::PacketIds,
+    mqtt_client_pinger::{LastActivity, Pinger},
+    mqtt_client_retransmitter::{lock_retransmitter, ShareableRetransmitter},
+};
+
+/// Espera antes del primer intento de reconexión, en milisegundos. Se duplica en cada intento fallido.
+const INITIAL_RECONNECT_DELAY_MS: u64 = 500;
+/// Espera máxima entre intentos de reconexión, en milisegundos.
+const MAX_RECONNECT_DELAY_MS: u64 = 30_000;
+/// Cada cuánto se revisa, mientras se espera para reintentar, si se pidió reconectar ya o desconectarse.
+const RECONNECT_WAIT_SLICE: Duration = Duration::from_millis(100);
+
+/// Si el cliente se reconecta al perder la conexión, y cuánto espera entre intentos:
+/// `initial_delay` antes del primero, duplicándose en cada intento fallido hasta `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    enabled: bool,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ReconnectPolicy {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        ReconnectPolicy {
+            enabled: true,
+            initial_delay,
+            max_delay: max_delay.max(initial_delay),
+        }
+    }
+
+    /// Política para no reconectarse: al perder la conexión, el cliente termina.
+    pub fn disabled() -> Self {
+        ReconnectPolicy {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn get_initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    /// Devuelve la espera para el próximo intento de reconexión: el doble de `delay`, sin superar el máximo.
+    pub fn next_delay(&self, delay: Duration) -> Duration {
+        (delay * 2).min(self.max_delay)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_millis(INITIAL_RECONNECT_DELAY_MS),
+            Duration::from_millis(MAX_RECONNECT_DELAY_MS),
+        )
+    }
+}
+
+/// Topics (y su qos) a los que el cliente está suscripto, para volver a suscribirse al reconectarse.
+pub type SubscribedTopics = Arc<Mutex<Vec<(String, u8)>>>;
+
+/// Estado de la sesión compartido entre `MQTTClient` y el `Reconnector`.
+#[derive(Debug, Clone)]
+pub struct ClientSession {
+    pub subscribed_topics: SubscribedTopics,
+    pub topic_handlers: TopicHandlers, // handlers de la app para los publish de cada topic.
+    pub offline_queue: ShareableOfflineQueue, // publish realizados mientras no había conexión.
+    pub disconnect_requested: Arc<AtomicBool>, // para que no se reconecte luego de un disconnect voluntario.
+    pub reconnect_requested: Arc<AtomicBool>, // para reconectarse ya, sin esperar entre intentos.
+    pub status_listeners: StatusListeners, // a quienes avisar cada cambio de estado de la conexión.
+}
+
+/// Datos con los que el cliente se conecta al server, para repetir el connect al reconectarse.
+#[derive(Debug)]
+pub struct ConnectionParams {
+    pub addr: SocketAddr,
+    pub options: MqttClientOptions,
+}
+
+/// Parte interna de `MQTTClient` que supervisa la conexión: lanza el listener y el pinger de cada conexión,
+/// y si la conexión se pierde sin que el cliente se haya desconectado, vuelve a conectarse con backoff exponencial,
+/// se suscribe nuevamente a sus topics y reenvía los publish que no habían sido confirmados.
+#[derive(Debug)]
+pub struct Reconnector {
+    connection_params: ConnectionParams,
+    msg_creator: MessageCreator,
+    retransmitter: ShareableRetransmitter,
+    session: ClientSession,
+    last_activity: LastActivity,
+    logger: StringLogger,
+}
+
+impl Reconnector {
+    pub fn new(
+        connection_params: ConnectionParams,
+        packet_ids: PacketIds,
+        retransmitter: ShareableRetransmitter,
+        session: ClientSession,
+        last_activity: LastActivity,
+        logger: StringLogger,
+    ) -> Self {
+        Reconnector {
+            connection_params,
+            msg_creator: MessageCreator::new(packet_ids),
+            retransmitter,
+            session,
+            last_activity,
+            logger,
+        }
+    }
+
+    /// Función que ejecutará un hilo de MQTTClient. Atiende la conexión `stream` y las siguientes reconexiones,
+    /// hasta que el cliente se desconecte, o el server lo desconecte con un mensaje disconnect.
+    /// Los listeners de cada conexión envían los publish recibidos por `publish_msg_tx`, y los acks por `ack_tx`.
+    pub fn run(
+        &mut self,
+        mut stream: ClientStreamType,
+        publish_msg_tx: Sender<PublishMessage>,
+        ack_tx: Sender<ACKMessage>,
+    ) -> Result<(), Error> {
+        let mut is_reconnection = false;
+        let mut topic_alias_maximum = 0; // de la última reconexión, la primera conexión ya lo estableció.
+        loop {
+            let listener_handle =
+                self.spawn_connection_threads(&stream, &publish_msg_tx, &ack_tx)?;
+            if is_reconnection {
+                if let Err(e) = self.resume_session(&stream, topic_alias_maximum) {
+                    self.logger
+                        .log(format!("Error al retomar la sesión: {:?}", e));
+                }
+                if let Err(e) = self.flush_offline_queue() {
+                    self.logger.log(format!(
+                        "Error al enviar los publish encolados sin conexión: {:?}",
+                        e
+                    ));
+                }
+                self.session.status_listeners.notify(ConnectionStatus::Connected);
+            }
+
+            let (closed_by_server, last_error) = match listener_handle.join() {
+                Ok(Ok(closed_by_server)) => (closed_by_server, None),
+                Ok(Err(e)) => {
+                    self.logger
+                        .log(format!("Error al leer, en read_from_server: {:?}", e));
+                    (false, Some(e.to_string()))
+                }
+                Err(_) => (false, None),
+            };
+            // Si se pidió reconectar, se reconecta aunque la política no lo haga al perder la conexión.
+            let reconnect_policy = self.connection_params.options.get_reconnect_policy();
+            let reconnect_requested = self.session.reconnect_requested.load(Ordering::SeqCst);
+            if closed_by_server
+                || self.is_disconnect_requested()
+                || !(reconnect_policy.is_enabled() || reconnect_requested)
+            {
+                let last_error = last_error.or_else(|| {
+                    closed_by_server.then(|| "El server cerró la conexión.".to_string())
+                });
+                self.session
+                    .status_listeners
+                    .notify(ConnectionStatus::Offline { last_error });
+                return Ok(());
+            }
+
+            self.logger
+                .log("Mqtt: se perdió la conexión con el server, reconectando.".to_string());
+            self.session
+                .status_listeners
+                .notify(ConnectionStatus::Reconnecting { last_error });
+            // Mientras tanto, los publish se encolan hasta reconectarse
+            lock_offline_queue(&self.session.offline_queue)?.set_connected(false);
+            shutdown(&stream);
+            match self.reconnect_with_backoff() {
+                Some(connection) => {
+                    stream = connection.stream;
+                    topic_alias_maximum = connection.topic_alias_maximum;
+                }
+                None => {
+                    self.session
+                        .status_listeners
+                        .notify(ConnectionStatus::Offline { last_error: None });
+                    return Ok(());
+                }
+            }
+            is_reconnection = true;
+        }
+    }
+
+    /// Lanza el hilo que lee del server y el que envía PingReq, para la conexión `stream`.
+    /// Devuelve el handle del hilo listener, que termina cuando se cierra la conexión.
+    fn spawn_connection_threads(
+        &self,
+        stream: &ClientStreamType,
+        publish_msg_tx: &Sender<PublishMessage>,
+        ack_tx: &Sender<ACKMessage>,
+    ) -> Result<JoinHandle<Result<bool, Error>>, Error> {
+        let mut listener = MQTTClientListener::new(
+            stream.try_clone()?,
+            publish_msg_tx.clone(),
+            ack_tx.clone(),
+            self.session.topic_handlers.clone(),
+        );
+        listener.set_protocol_version(self.connection_params.options.get_protocol_version());
+        listener.set_topic_alias_maximum(self.connection_params.options.get_topic_alias_maximum());
+        listener.set_packet_observers(self.connection_params.options.get_packet_observers());
+        let mut pinger = Pinger::new(
+            stream.try_clone()?,
+            self.connection_params.options.get_keep_alive(),
+            self.last_activity.clone(),
+        );
+        pinger.set_packet_observers(self.connection_params.options.get_packet_observers());
+
+        // Hilo que envía PingReq mientras el cliente no envíe otros mensajes. Termina al cerrarse la conexión.
+        let logger_p = self.logger.clone_ref();
+        thread::spawn(move || {
+            if let Err(e) = pinger.run() {
+                logger_p.log(format!("Pinger finalizado: {:?}", e));
+            }
+        });
+
+        Ok(thread::spawn(move || listener.read_from_server()))
+    }
+
+    /// Intenta conectarse nuevamente al server, esperando entre intentos un tiempo que se duplica en cada fallo.
+    /// Si se pide reconectar, deja de esperar e intenta en el momento.
+    /// Devuelve None si el cliente se desconectó mientras tanto.
+    fn reconnect_with_backoff(&self) -> Option<BrokerConnection> {
+        let params = &self.connection_params;
+        let reconnect_policy = params.options.get_reconnect_policy();
+        let mut delay = reconnect_policy.get_initial_delay();
+        while !self.is_disconnect_requested() {
+            self.wait_before_retry(delay);
+            if self.is_disconnect_requested() {
+                break;
+            }
+            match MqttClientConnector::connect_with_version(
+                &params.addr,
+                &params.options,
+                params.options.get_protocol_version(),
+                self.logger.clone_ref(),
+            ) {
+                Ok(connection) => {
+                    self.logger.log("Mqtt: reconectado al server.".to_string());
+                    return Some(connection);
+                }
+                Err(e) => {
+                    self.logger
+                        .log(format!("Mqtt: falló la reconexión: {:?}", e));
+                    self.session.status_listeners.notify(ConnectionStatus::Reconnecting {
+                        last_error: Some(e.to_string()),
+                    });
+                    delay = reconnect_policy.next_delay(delay);
+                }
+            }
+        }
+        None
+    }
+
+    /// Retoma la sesión sobre la nueva conexión: reenvía los publish sin confirmar y los encolados,
+    /// y vuelve a suscribirse a los topics a los que el cliente estaba suscripto.
+    fn resume_session(
+        &mut self,
+        stream: &ClientStreamType,
+        topic_alias_maximum: u16,
+    ) -> Result<(), Error> {
+        let topics = match self.session.subscribed_topics.lock() {
+            Ok(topics) => topics.clone(),
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "Error al tomar lock de subscribed_topics.",
+                ))
+            }
+        };
+        let mut retransmitter = lock_retransmitter(&self.retransmitter)?;
+        retransmitter.resume_with(stream.try_clone()?, topic_alias_maximum)?;
+        if !topics.is_empty() {
+            let subscribe_msg = self.msg_creator.create_subscribe_msg(topics)?;
+            retransmitter.send_and_retransmit(&subscribe_msg)?;
+        }
+        Ok(())
+    }
+
+    /// Envía los publish que se realizaron mientras no había conexión, y vuelve a marcar al cliente como conectado.
+    /// Mantiene el lock de la cola mientras tanto, para que los nuevos publish no se adelanten a los encolados.
+    fn flush_offline_queue(&mut self) -> Result<(), Error> {
+        let mut offline_queue = lock_offline_queue(&self.session.offline_queue)?;
+        let queued_msgs = offline_queue.drain();
+        offline_queue.set_connected(true);
+        for msg in queued_msgs {
+            lock_retransmitter(&self.retransmitter)?.send_and_retransmit(&msg)?;
+        }
+        Ok(())
+    }
+
+    /// Espera `delay` antes de reintentar, salvo que mientras tanto se pida reconectar ya o desconectarse.
+    fn wait_before_retry(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        while Instant::now() < until && !self.is_disconnect_requested() {
+            if self.session.reconnect_requested.swap(false, Ordering::SeqCst) {
+                return;
+            }
+            thread::sleep(RECONNECT_WAIT_SLICE.min(until.saturating_duration_since(Instant::now())));
+        }
+        self.session.reconnect_requested.store(false, Ordering::SeqCst);
+    }
+
+    fn is_disconnect_requested(&self) -> bool {
+        self.session.disconnect_requested.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReconnectPolicy, MAX_RECONNECT_DELAY_MS};
+    use std::time::Duration;
+
+    #[test]
+    fn test_1_la_espera_entre_reconexiones_se_duplica_hasta_el_maximo() {
+        let policy = ReconnectPolicy::default();
+        let delay = Duration::from_millis(500);
+        assert_eq!(policy.next_delay(delay), Duration::from_millis(1000));
+
+        let max_delay = Duration::from_millis(MAX_RECONNECT_DELAY_MS);
+        assert_eq!(policy.next_delay(Duration::from_millis(20_000)), max_delay);
+        assert_eq!(policy.next_delay(max_delay), max_delay);
+    }
+
+    #[test]
+    fn test_2_la_politica_configurada_respeta_su_propio_maximo() {
+        let policy = ReconnectPolicy::new(Duration::from_millis(100), Duration::from_millis(300));
+
+        assert!(policy.is_enabled());
+        assert_eq!(policy.get_initial_delay(), Duration::from_millis(100));
+        assert_eq!(
+            policy.next_delay(Duration::from_millis(200)),
+            Duration::from_millis(300)
+        );
+        assert!(!ReconnectPolicy::disabled().is_enabled());
+    }
+}
@@ -1,38 +1,305 @@
-use std::{io::{Error, ErrorKind}, net::Shutdown, sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender}, time::Duration};
+use std::{collections::{HashMap, VecDeque}, io::{Error, ErrorKind}, net::Shutdown, sync::{mpsc::{channel, Receiver, RecvTimeoutError, Sender}, Arc, Mutex, MutexGuard}, time::{Duration, Instant}};
 
-use crate::{logging::string_logger::StringLogger, mqtt::{messages::{disconnect_message::DisconnectMessage, message::Message, packet_type::PacketType, publish_message::PublishMessage}, mqtt_utils::utils::write_message_to_stream}};
+use crate::mqtt::messages::puback_message::PUBACK_SUCCESS;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+use crate::mqtt::mqtt_utils::packet_observer::{PacketDirection, PacketObservers};
+use crate::mqtt::mqtt_utils::topic_aliases::OutgoingTopicAliases;
+use crate::{logging::string_logger::StringLogger, mqtt::{messages::{disconnect_message::DisconnectMessage, message::Message, packet_type::PacketType, publish_message::PublishMessage, pubrel_message::PubRelMessage}, mqtt_utils::utils::write_message_to_stream}};
 
-use super::{ack_message::ACKMessage, mqtt_client::ClientStreamType};
+use super::{
+    ack_message::ACKMessage,
+    mqtt_client_delivery_token::{DeliveryNotifier, DeliveryOutcome},
+    mqtt_client::ClientStreamType,
+    mqtt_client_packet_id_manager::{release_packet_id, PacketIds},
+    mqtt_client_pinger::{update_last_activity, LastActivity},
+};
+
+/// Tiempo que se espera por defecto por un ack antes de retransmitir.
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Cantidad de veces que se retransmite un mensaje, hasta que se desista y se dé error.
+const AMOUNT_OF_RETRIES: u8 = 5;
+/// Máximo por defecto de publish de qos 1 enviados sin haber recibido su puback.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 10;
+
+/// Retransmitter compartido entre `MQTTClient` y el `Reconnector`, que lo usa para retomar la sesión.
+pub type ShareableRetransmitter = Arc<Mutex<Retransmitter>>;
+
+/// Publish de qos 1 enviado al server, cuyo puback todavía no llegó.
+#[derive(Debug)]
+struct InFlightPublish {
+    msg: PublishMessage,
+    sent_at: Instant,
+    remaining_retries: u8,
+}
 
 /// Parte interna de `MQTTClient` encargada de manejar los ack y las retransmisiones.
 /// Conserva el extramo receptor de un channel (`ack_rx`).
+/// Los publish de qos 1 se envían sin esperar su ack mientras haya menos de `max_in_flight` sin confirmar;
+/// los siguientes se encolan hasta que lleguen los pubacks que liberen lugar.
 #[derive(Debug)]
 pub struct Retransmitter {
     ack_rx: Receiver<ACKMessage>,
     stream: ClientStreamType,
     logger: StringLogger,
+    last_activity: LastActivity,
+    max_in_flight: usize,
+    ack_timeout: Duration, // lo que se espera por un ack antes de retransmitir.
+    in_flight: Vec<InFlightPublish>,
+    pending: VecDeque<PublishMessage>,
+    packet_ids: PacketIds,
+    topic_aliases: OutgoingTopicAliases, // de la conexión actual; las retransmisiones llevan el topic completo.
+    delivery_notifiers: HashMap<u16, DeliveryNotifier>, // u16 = packet_id de un publish cuyo resultado se espera.
+    packet_observers: PacketObservers,
 }
 
 impl Retransmitter {
     /// Crea y devuelve un Retransmitter, encargado del envío y las retransmisiones, y el extremo de envío de un channel.
-    pub fn new(stream: ClientStreamType, logger: StringLogger) -> (Self, Sender<ACKMessage>) {
+    pub fn new(
+        stream: ClientStreamType,
+        logger: StringLogger,
+        last_activity: LastActivity,
+        packet_ids: PacketIds,
+    ) -> (Self, Sender<ACKMessage>) {
         let (ack_tx, ack_rx) = channel::<ACKMessage>();
-        (Self { ack_rx , stream , logger }, ack_tx)
+        (
+            Self {
+                ack_rx,
+                stream,
+                logger,
+                last_activity,
+                max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+                ack_timeout: DEFAULT_ACK_TIMEOUT,
+                in_flight: Vec::new(),
+                pending: VecDeque::new(),
+                packet_ids,
+                topic_aliases: OutgoingTopicAliases::default(),
+                delivery_notifiers: HashMap::new(),
+                packet_observers: PacketObservers::default(),
+            },
+            ack_tx,
+        )
     }
     
+    /// Establece el máximo de publish de qos 1 que pueden enviarse sin haber recibido su puback (al menos uno).
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+    }
+
+    /// Establece cuánto se espera por un ack antes de retransmitir el mensaje.
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.ack_timeout = ack_timeout;
+    }
+
+    /// Establece cuántos topic aliases pueden usarse en los publish de la conexión actual, y descarta los asignados.
+    pub fn set_topic_alias_maximum(&mut self, topic_alias_maximum: u16) {
+        self.topic_aliases = OutgoingTopicAliases::new(topic_alias_maximum);
+    }
+
+    /// Establece los observers a los que informar cada mensaje enviado al server.
+    pub fn set_packet_observers(&mut self, packet_observers: PacketObservers) {
+        self.packet_observers = packet_observers;
+    }
+
+    /// Registra el `notifier` por el que informar el resultado de la entrega del publish con packet id `packet_id`.
+    pub fn register_delivery_notifier(&mut self, packet_id: u16, notifier: DeliveryNotifier) {
+        self.delivery_notifiers.insert(packet_id, notifier);
+    }
+
+    /// Deja de esperar el resultado de la entrega del publish con packet id `packet_id`, sin informarlo.
+    pub fn unregister_delivery_notifier(&mut self, packet_id: u16) {
+        self.delivery_notifiers.remove(&packet_id);
+    }
+
+    /// Informa el resultado de la entrega del publish con packet id `packet_id`, si alguien lo espera.
+    pub fn notify_delivery(&mut self, packet_id: u16, outcome: DeliveryOutcome) {
+        if let Some(notifier) = self.delivery_notifiers.remove(&packet_id) {
+            // Si el token se dropeó, nadie espera el resultado.
+            let _ = notifier.send(outcome);
+        }
+    }
+
     /// Envía el mensaje `msg` recibido una vez, espera por el ack, y si es necesario lo retransmite una cierta
-    /// cantidad de veces.
+    /// cantidad de veces. Los publish de qos 1 no esperan su ack, se envían según la ventana de mensajes sin confirmar.
     pub fn send_and_retransmit<T: Message>(&mut self, msg: &T) -> Result<(), Error> {
+        if let Some(pub_msg) = msg.as_any().downcast_ref::<PublishMessage>() {
+            if pub_msg.get_qos() == 1 {
+                return self.send_within_in_flight_window(pub_msg);
+            }
+        }
         self.logger.log("Mqtt: Enviando msg.".to_string());
-        self.send_msg(msg.to_bytes())?;
-        if let Err(e) = self.wait_for_ack_and_retransmit(msg) {
-            println!("Error al esperar ack: {:?}", e);
-            self.logger.log(format!("Error al esperar ack: {:?}", e));
+        let msg_bytes = match msg.as_any().downcast_ref::<PublishMessage>() {
+            Some(pub_msg) => self.topic_aliases.apply(pub_msg).to_bytes(),
+            None => msg.to_bytes(),
         };
+        self.send_msg(msg_bytes)?;
+        let outcome = match self.wait_for_ack_and_retransmit(msg) {
+            Ok(()) => DeliveryOutcome::Acknowledged(PUBACK_SUCCESS),
+            Err(e) => {
+                println!("Error al esperar ack: {:?}", e);
+                self.logger.log(format!("Error al esperar ack: {:?}", e));
+                DeliveryOutcome::RetriesExhausted
+            }
+        };
+        // Terminó de esperar su ack (o desistió), el packet id puede volver a usarse.
+        if let Some(packet_id) = msg.get_packet_id() {
+            release_packet_id(&self.packet_ids, packet_id);
+            self.notify_delivery(packet_id, outcome);
+        }
         self.logger.log("Mqtt: recibido ack.".to_string());
         Ok(())
     }
 
+    /// Encola el publish de qos 1 y envía los encolados mientras haya lugar en la ventana.
+    /// Si la ventana está llena, espera (retransmitiendo si es necesario) a que lleguen pubacks que liberen lugar.
+    fn send_within_in_flight_window(&mut self, pub_msg: &PublishMessage) -> Result<(), Error> {
+        self.pending.push_back(pub_msg.clone());
+        self.receive_available_acks();
+        self.send_pending()?;
+        while !self.pending.is_empty() {
+            self.logger.log("Mqtt: Ventana de publish sin confirmar llena, esperando pubacks.".to_string());
+            self.wait_for_in_flight_acks()?;
+            self.send_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Envía los publish encolados mientras haya lugar en la ventana.
+    fn send_pending(&mut self) -> Result<(), Error> {
+        while self.in_flight.len() < self.max_in_flight {
+            let Some(msg) = self.pending.pop_front() else {
+                break;
+            };
+            self.logger.log("Mqtt: Enviando msg.".to_string());
+            let msg_bytes = self.topic_aliases.apply(&msg).to_bytes();
+            if let Err(e) = self.send_msg(msg_bytes) {
+                // Sigue encolado, para enviarlo al retomar la sesión si el cliente se reconecta.
+                self.pending.push_front(msg);
+                return Err(e);
+            }
+            self.in_flight.push(InFlightPublish {
+                msg,
+                sent_at: Instant::now(),
+                remaining_retries: AMOUNT_OF_RETRIES,
+            });
+        }
+        Ok(())
+    }
+
+    /// Espera a lo sumo `timeout` por un ack, y procesa los que hayan llegado. Luego retransmite los publish en vuelo
+    /// que no recibieron su puback a tiempo, y envía los encolados que entren en la ventana.
+    /// Permite que los acks se procesen y se informen aunque no se vuelva a publicar.
+    pub fn process_acks(&mut self, timeout: Duration) -> Result<(), Error> {
+        match self.ack_rx.recv_timeout(timeout) {
+            Ok(ack_message) => {
+                self.acknowledge_in_flight(&ack_message);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(Error::new(ErrorKind::Other, "Se cerró el channel de acks."));
+            }
+        }
+        self.receive_available_acks();
+        self.retransmit_expired_in_flight()?;
+        self.send_pending()
+    }
+
+    /// Procesa los acks que ya llegaron, sin esperar.
+    fn receive_available_acks(&mut self) {
+        while let Ok(ack_message) = self.ack_rx.try_recv() {
+            self.acknowledge_in_flight(&ack_message);
+        }
+    }
+
+    /// Espera un intervalo por los pubacks de los publish en vuelo, y retransmite los que no lo recibieron a tiempo.
+    fn wait_for_in_flight_acks(&mut self) -> Result<(), Error> {
+        match self.ack_rx.recv_timeout(self.ack_timeout) {
+            Ok(ack_message) => {
+                self.acknowledge_in_flight(&ack_message);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(Error::new(ErrorKind::Other, "Se cerró el channel de acks."));
+            }
+        }
+        self.retransmit_expired_in_flight()
+    }
+
+    /// Si `ack_message` es el puback de un publish en vuelo, lo quita de la ventana. Devuelve si lo era.
+    fn acknowledge_in_flight(&mut self, ack_message: &ACKMessage) -> bool {
+        let ACKMessage::PubAck(puback) = ack_message else {
+            return false;
+        };
+        let packet_id = Some(puback.get_packet_id());
+        match self.in_flight.iter().position(|p| p.msg.get_packet_id() == packet_id) {
+            Some(pos) => {
+                self.in_flight.remove(pos);
+                release_packet_id(&self.packet_ids, puback.get_packet_id());
+                self.notify_delivery(puback.get_packet_id(), DeliveryOutcome::Acknowledged(puback.get_reason_code()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Retransmite los publish en vuelo cuyo puback no llegó dentro del intervalo de espera,
+    /// y desiste de los que ya se retransmitieron la cantidad máxima de veces.
+    fn retransmit_expired_in_flight(&mut self) -> Result<(), Error> {
+        let waiting_interval = self.ack_timeout;
+        let mut to_retransmit = vec![];
+        let mut given_up = vec![];
+        self.in_flight.retain_mut(|in_flight| {
+            if in_flight.sent_at.elapsed() < waiting_interval {
+                return true;
+            }
+            if in_flight.remaining_retries == 0 {
+                given_up.push(in_flight.msg.get_packet_id());
+                return false;
+            }
+            in_flight.remaining_retries -= 1;
+            in_flight.sent_at = Instant::now();
+            to_retransmit.push(in_flight.msg.as_duplicate().to_bytes());
+            true
+        });
+        for packet_id in given_up {
+            if let Some(packet_id) = packet_id {
+                release_packet_id(&self.packet_ids, packet_id);
+                self.notify_delivery(packet_id, DeliveryOutcome::RetriesExhausted);
+            }
+            self.logger.log(format!("Error al esperar ack: MAXRETRIES, se retransmitió sin éxito el publish {:?}.", packet_id));
+        }
+        for msg_bytes in to_retransmit {
+            self.logger.log("Mqtt: Retransmitiendo...".to_string());
+            self.send_msg(msg_bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Retoma la sesión sobre el stream de una nueva conexión: reenvía, con el flag de dup,
+    /// los publish en vuelo cuyo puback no llegó, y luego los encolados que entren en la ventana.
+    /// Los topic aliases de la conexión anterior no valen en la nueva, que acepta `topic_alias_maximum`.
+    pub fn resume_with(&mut self, stream: ClientStreamType, topic_alias_maximum: u16) -> Result<(), Error> {
+        self.stream = stream;
+        self.set_topic_alias_maximum(topic_alias_maximum);
+        let mut to_retransmit = vec![];
+        for in_flight in self.in_flight.iter_mut() {
+            in_flight.sent_at = Instant::now();
+            in_flight.remaining_retries = AMOUNT_OF_RETRIES;
+            to_retransmit.push(in_flight.msg.as_duplicate().to_bytes());
+        }
+        for msg_bytes in to_retransmit {
+            self.send_msg(msg_bytes)?;
+        }
+        self.send_pending()
+    }
+
+    /// Espera hasta recibir los pubacks de todos los publish en vuelo, o desistir de ellos.
+    fn wait_for_all_in_flight_acks(&mut self) -> Result<(), Error> {
+        while !self.in_flight.is_empty() {
+            self.wait_for_in_flight_acks()?;
+        }
+        Ok(())
+    }
+
     /// Espera por el ack y si no lo recibe retransmite, teniendo en cuenta el tipo de paquete,
     /// para el publish considera su nivel de qos.
     fn wait_for_ack_and_retransmit<T: Message>(&mut self, msg: &T) -> Result<(), Error> {
@@ -41,14 +308,14 @@ impl Retransmitter {
             PacketType::Publish => {
                 if let Some(pub_msg) = msg.as_any().downcast_ref::<PublishMessage>() {
                     let qos = pub_msg.get_qos();
-                    if qos == 1 {
-                        return self.wait_and_retransmit(pub_msg);
-                    } else {
-                        return Ok(());
+                    match qos {
+                        1 => return self.wait_and_retransmit(pub_msg),
+                        2 => return self.wait_for_qos2_handshake(pub_msg),
+                        _ => return Ok(()),
                     }
                 }
             }
-            PacketType::Subscribe => {
+            PacketType::Subscribe | PacketType::Unsubscribe => {
                 return self.wait_and_retransmit(msg);
             }
             _ => {}
@@ -57,6 +324,21 @@ impl Retransmitter {
         Ok(())
     }
 
+    /// Handshake de qos 2: espera el pubrec retransmitiendo el publish, luego envía el pubrel
+    /// y espera el pubcomp retransmitiendo el pubrel.
+    fn wait_for_qos2_handshake(&mut self, pub_msg: &PublishMessage) -> Result<(), Error> {
+        // Espera el pubrec
+        self.wait_and_retransmit(pub_msg)?;
+
+        if let Some(packet_id) = pub_msg.get_packet_id() {
+            let pubrel_msg = PubRelMessage::new(packet_id);
+            self.send_msg(pubrel_msg.to_bytes())?;
+            // Espera el pubcomp
+            self.wait_and_retransmit(&pubrel_msg)?;
+        }
+        Ok(())
+    }
+
     /// Espera a recibir el ack para el packet_id del mensaje `msg`, si no lo recibe, retransmite.
     fn wait_and_retransmit<T: Message>(&mut self, msg: &T) -> Result<(), Error> {
         let packet_id = msg.get_packet_id();
@@ -67,13 +349,12 @@ impl Retransmitter {
         }
 
         // No recibí ack, entonces tengo que continuar retransmitiendo, hasta un máx de veces.
-        const AMOUNT_OF_RETRIES: u8 = 5; // cant de veces que va a reintentar, hasta que desista y dé error.
         let mut remaining_retries = AMOUNT_OF_RETRIES;
 
         while !received_ack && remaining_retries > 0 {
             // Lo vuelvo a enviar, y a verificar si llega el ack.
             
-            self.send_msg(msg.to_bytes())?;
+            self.send_msg(retransmission_bytes(msg))?;
             received_ack = self.has_ack_arrived(packet_id)?;
             self.logger.log("Mqtt: Retransmitiendo...".to_string());
 
@@ -82,10 +363,7 @@ impl Retransmitter {
 
         if !received_ack {
             // Ya salí del while, retransmití muchas veces y nunca recibí el ack, desisto.
-            return Err(Error::new(
-                ErrorKind::Other,
-                "MAXRETRIES, se retransmitió sin éxito.",
-            ));
+            return Err(MqttError::AckTimeout("mensaje".to_string()).into());
         }
 
         Ok(())
@@ -95,7 +373,7 @@ impl Retransmitter {
     /// Si eso no ocurre, debe retransmitir el mensaje original (el msg cuyo ack está esperando)
     /// hasta que llegue su ack o bien se llegue a una cantidad máxima de intentos definida como constante.
     /// Devuelve si recibió el ack.
-    fn has_ack_arrived(&self, packet_id: Option<u16>) -> Result<bool, Error> {
+    fn has_ack_arrived(&mut self, packet_id: Option<u16>) -> Result<bool, Error> {
         // Extrae el packet_id
         if let Some(packet_id) = packet_id {
             self.start_waiting_and_check_for_ack(packet_id)
@@ -109,45 +387,51 @@ impl Retransmitter {
 
     /// Espera por el ack como máximo un cierto tiempo,
     /// si no se cerró la conexión con listener, devuelve Ok de si llega el ack.
-    fn start_waiting_and_check_for_ack(&self, packet_id: u16) -> Result<bool, Error> {
+    /// Los pubacks de publish en vuelo que lleguen mientras tanto liberan su lugar en la ventana, y no se confunden con el ack esperado.
+    fn start_waiting_and_check_for_ack(&mut self, packet_id: u16) -> Result<bool, Error> {
         // Leo esperando un cierto tiempo, si en el período [0, ese tiempo) no me llega el ack, lo quiero retransmitir.
-        const ACK_WAITING_INTERVAL: u64 = 1000;
-        match self.ack_rx.recv_timeout(Duration::from_millis(ACK_WAITING_INTERVAL)){
-            Ok(ack_message) => {
-                // Se recibió el ack
-                if let Some(packet_identifier) = ack_message.get_packet_id() {
-                    if packet_id == packet_identifier {
-                        println!("   llegó el ack {:?}", ack_message); 
+        let deadline = Instant::now() + self.ack_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.ack_rx.recv_timeout(remaining) {
+                Ok(ack_message) => {
+                    if self.acknowledge_in_flight(&ack_message) {
+                        continue;
+                    }
+                    // Se recibió el ack
+                    if ack_message.get_packet_id() == Some(packet_id) {
+                        println!("   llegó el ack {:?}", ack_message);
                         return Ok(true);
                     }
                 }
-            },
-            Err(e) => {
-                match e {
-                    RecvTimeoutError::Timeout => {
-                        // Se cumplió el tiempo y el ack No se recibió.
-                        return Ok(false);
-
-                    },
-                    RecvTimeoutError::Disconnected => {
-                        // Se cerró el channel. Termina el programa.
-                        // Ver.
-                    },
+                Err(RecvTimeoutError::Timeout) => {
+                    // Se cumplió el tiempo y el ack No se recibió.
+                    return Ok(false);
                 }
-            },
+                Err(RecvTimeoutError::Disconnected) => {
+                    // Se cerró el channel. Termina el programa.
+                    return Ok(false);
+                }
+            }
         }
-        Ok(false)
     }
 
     /// Función para ser usada por `MQTTClient`, cuando el `Retransmitter` haya determinado que el `msg` debe
     /// enviarse por el stream a server.
     fn send_msg(&mut self, bytes_msg: Vec<u8>) -> Result<(), Error> {
         write_message_to_stream(&bytes_msg, &mut self.stream)?;
+        self.packet_observers.notify(PacketDirection::Outbound, &bytes_msg);
+        // Se registra el envío, para que el Pinger no envíe PingReq mientras haya otros mensajes.
+        update_last_activity(&self.last_activity)?;
         Ok(())
     }
     
     /// Envía el mensaje disconnect recibido por parámetro y cierra la conexión.
+    /// Antes espera los pubacks de los publish en vuelo, para no desconectarse con publish sin confirmar.
     pub fn send_and_shutdown_stream(&mut self, msg: DisconnectMessage) -> Result<(), Error> {
+        if let Err(e) = self.wait_for_all_in_flight_acks() {
+            self.logger.log(format!("Error al esperar los pubacks antes de desconectar: {:?}", e));
+        }
         self.send_msg(msg.to_bytes())?;
         // Cerramos la conexión con el servidor
         self.stream.shutdown(Shutdown::Both)?;
@@ -156,4 +440,27 @@ impl Retransmitter {
         Ok(())
     }
 
-}
\ No newline at end of file
+    /// Cierra la conexión sin enviar el disconnect, para que el cliente se reconecte.
+    pub fn shutdown_stream(&mut self) -> Result<(), Error> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+}
+
+/// Devuelve los bytes con los que se retransmite `msg`: si es un publish, con el flag de dup seteado,
+/// para que el server sepa que puede tratarse de un mensaje ya recibido.
+fn retransmission_bytes<T: Message>(msg: &T) -> Vec<u8> {
+    match msg.as_any().downcast_ref::<PublishMessage>() {
+        Some(pub_msg) => pub_msg.as_duplicate().to_bytes(),
+        None => msg.to_bytes(),
+    }
+}
+
+/// Toma el lock del retransmitter compartido.
+pub fn lock_retransmitter(
+    retransmitter: &ShareableRetransmitter,
+) -> Result<MutexGuard<'_, Retransmitter>, Error> {
+    retransmitter
+        .lock()
+        .map_err(|_| MqttError::LockPoisoned("retransmitter".to_string()).into())
+}
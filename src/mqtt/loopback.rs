@@ -0,0 +1,366 @@
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind, IoSlice, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr},
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+/// Próximo puerto a asignar a las direcciones de los extremos en memoria, para que cada conexión tenga
+/// una dirección distinta (el server identifica con ella a la conexión actual de cada cliente).
+static NEXT_LOOPBACK_PORT: AtomicU16 = AtomicU16::new(1);
+
+fn next_loopback_addr() -> SocketAddr {
+    let port = NEXT_LOOPBACK_PORT.fetch_add(1, Ordering::Relaxed);
+    SocketAddr::from((Ipv4Addr::LOCALHOST, port))
+}
+
+/// Bytes escritos por un extremo de la conexión, que aún no leyó el otro.
+#[derive(Debug, Default)]
+struct PipeState {
+    bytes: VecDeque<u8>,
+    closed: bool, // ya no se escribirá más; quien lee recibe eof al vaciarse.
+}
+
+/// Un sentido de la conexión en memoria.
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+impl Pipe {
+    fn lock(&self) -> Result<MutexGuard<'_, PipeState>, Error> {
+        self.state
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::Other, "Error al tomar lock del pipe en memoria."))
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.readable.notify_all();
+    }
+
+    fn write(&self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        let mut state = self.lock()?;
+        if state.closed {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "La conexión en memoria está cerrada.",
+            ));
+        }
+        let mut written = 0;
+        for buf in bufs {
+            state.bytes.extend(buf.iter());
+            written += buf.len();
+        }
+        self.readable.notify_all();
+        Ok(written)
+    }
+
+    /// Bloquea hasta que haya bytes para leer o se cierre el pipe, o hasta `timeout` si no es None.
+    fn read(&self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.lock()?;
+        while state.bytes.is_empty() && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::new(
+                            ErrorKind::WouldBlock,
+                            "Tiempo de lectura agotado.",
+                        ));
+                    }
+                    self.readable
+                        .wait_timeout(state, deadline - now)
+                        .map_err(|_| {
+                            Error::new(ErrorKind::Other, "Error al esperar en el pipe en memoria.")
+                        })?
+                        .0
+                }
+                None => self.readable.wait(state).map_err(|_| {
+                    Error::new(ErrorKind::Other, "Error al esperar en el pipe en memoria.")
+                })?,
+            };
+        }
+        let len = buf.len().min(state.bytes.len());
+        for (dst, src) in buf.iter_mut().zip(state.bytes.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+/// Un extremo de la conexión, compartido por todas sus copias de `try_clone`.
+/// Al dropearse la última copia se cierra la conexión, como al cerrarse un socket.
+#[derive(Debug)]
+struct Endpoint {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    read_timeout: Mutex<Option<Duration>>,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+/// Conexión en memoria, que se usa como un `TcpStream`: permite correr el server y sus clientes
+/// en un mismo proceso sin abrir sockets, ie para tests de integración o una demo.
+#[derive(Debug)]
+pub struct LoopbackStream {
+    endpoint: Arc<Endpoint>,
+}
+
+impl LoopbackStream {
+    /// Crea los dos extremos de una conexión: lo que se escribe en uno se lee en el otro.
+    pub fn pair() -> (LoopbackStream, LoopbackStream) {
+        let (a_to_b, b_to_a) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+        let (a_addr, b_addr) = (next_loopback_addr(), next_loopback_addr());
+        let a = Endpoint {
+            incoming: b_to_a.clone(),
+            outgoing: a_to_b.clone(),
+            read_timeout: Mutex::new(None),
+            local_addr: a_addr,
+            peer_addr: b_addr,
+        };
+        let b = Endpoint {
+            incoming: a_to_b,
+            outgoing: b_to_a,
+            read_timeout: Mutex::new(None),
+            local_addr: b_addr,
+            peer_addr: a_addr,
+        };
+        (
+            LoopbackStream {
+                endpoint: Arc::new(a),
+            },
+            LoopbackStream {
+                endpoint: Arc::new(b),
+            },
+        )
+    }
+
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(LoopbackStream {
+            endpoint: self.endpoint.clone(),
+        })
+    }
+
+    /// Cierra la lectura, la escritura, o ambas. El otro extremo lee eof luego de lo ya escrito.
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), Error> {
+        if how != Shutdown::Write {
+            self.endpoint.incoming.close();
+        }
+        if how != Shutdown::Read {
+            self.endpoint.outgoing.close();
+        }
+        Ok(())
+    }
+
+    /// Como en un `TcpStream`, el timeout es del extremo y vale para todas sus copias.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "El timeout no puede ser cero.",
+            ));
+        }
+        let mut read_timeout =
+            self.endpoint.read_timeout.lock().map_err(|_| {
+                Error::new(ErrorKind::Other, "Error al tomar lock del read timeout.")
+            })?;
+        *read_timeout = timeout;
+        Ok(())
+    }
+
+    fn get_read_timeout(&self) -> Result<Option<Duration>, Error> {
+        self.endpoint
+            .read_timeout
+            .lock()
+            .map(|read_timeout| *read_timeout)
+            .map_err(|_| Error::new(ErrorKind::Other, "Error al tomar lock del read timeout."))
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.endpoint.local_addr)
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.endpoint.peer_addr)
+    }
+}
+
+impl Read for LoopbackStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let timeout = self.get_read_timeout()?;
+        self.endpoint.incoming.read(buf, timeout)
+    }
+}
+
+impl Write for LoopbackStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.endpoint.outgoing.write(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize, Error> {
+        self.endpoint.outgoing.write(bufs)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Acepta las conexiones en memoria que se establecen mediante sus `LoopbackConnector`.
+#[derive(Debug)]
+pub struct LoopbackListener {
+    incoming_rx: Receiver<LoopbackStream>,
+    local_addr: SocketAddr,
+}
+
+impl LoopbackListener {
+    /// Crea el listener, y el connector con el que los clientes se conectan a él.
+    pub fn bind() -> (LoopbackListener, LoopbackConnector) {
+        let (incoming_tx, incoming_rx) = mpsc::channel();
+        let local_addr = next_loopback_addr();
+        (
+            LoopbackListener {
+                incoming_rx,
+                local_addr,
+            },
+            LoopbackConnector {
+                incoming_tx,
+                addr: local_addr,
+            },
+        )
+    }
+
+    /// Espera la próxima conexión. Devuelve error si ya no quedan connectors con los que conectarse.
+    pub fn accept(&self) -> Result<LoopbackStream, Error> {
+        self.incoming_rx.recv().map_err(|_| {
+            Error::new(
+                ErrorKind::NotConnected,
+                "Ya no quedan connectors para el listener en memoria.",
+            )
+        })
+    }
+
+    /// Iterador sobre las conexiones entrantes, como `TcpListener::incoming`.
+    pub fn incoming(&self) -> impl Iterator<Item = Result<LoopbackStream, Error>> + '_ {
+        self.incoming_rx.iter().map(Ok)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Permite conectarse a un `LoopbackListener`; se clona para cada cliente que se conecte.
+#[derive(Debug, Clone)]
+pub struct LoopbackConnector {
+    incoming_tx: Sender<LoopbackStream>,
+    addr: SocketAddr, // la del listener, identifica a qué listener se conecta.
+}
+
+impl LoopbackConnector {
+    /// Establece una conexión con el listener, y devuelve el extremo del cliente.
+    pub fn connect(&self) -> Result<LoopbackStream, Error> {
+        let (client_stream, server_stream) = LoopbackStream::pair();
+        self.incoming_tx.send(server_stream).map_err(|_| {
+            Error::new(
+                ErrorKind::ConnectionRefused,
+                "El listener en memoria ya no acepta conexiones.",
+            )
+        })?;
+        Ok(client_stream)
+    }
+
+    pub fn get_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl PartialEq for LoopbackConnector {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LoopbackListener, LoopbackStream};
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::Shutdown,
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_1_lo_escrito_en_un_extremo_se_lee_en_el_otro() {
+        let (mut a, mut b) = LoopbackStream::pair();
+
+        a.write_all(b"hola").unwrap();
+        let mut buf = [0; 4];
+        b.read_exact(&mut buf).unwrap();
+
+        assert_eq!(&buf, b"hola");
+        assert_eq!(a.peer_addr().unwrap(), b.local_addr().unwrap());
+        assert_ne!(a.local_addr().unwrap(), b.local_addr().unwrap());
+    }
+
+    #[test]
+    fn test_2_al_cerrarse_o_dropearse_un_extremo_el_otro_lee_eof() {
+        let (a, mut b) = LoopbackStream::pair();
+        let (c, mut d) = LoopbackStream::pair();
+        let mut buf = [0; 4];
+
+        a.shutdown(Shutdown::Both).unwrap();
+        drop(c);
+
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+        assert_eq!(d.read(&mut buf).unwrap(), 0);
+        assert_eq!(b.write(b"hola").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_3_la_lectura_respeta_el_read_timeout() {
+        let (_a, mut b) = LoopbackStream::pair();
+        b.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+
+        let mut buf = [0; 4];
+        assert_eq!(b.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_4_el_listener_acepta_las_conexiones_del_connector() {
+        let (listener, connector) = LoopbackListener::bind();
+        let handle = thread::spawn(move || {
+            let mut client = connector.connect().unwrap();
+            client.write_all(b"ping").unwrap();
+        });
+
+        let mut server = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(&buf, b"ping");
+        assert!(listener.accept().is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::io::Error;
 use crate::mqtt::messages::{
     connack_fixed_header::FixedHeader, connack_session_present::SessionPresent,
     connack_variable_header::VariableHeader, connect_return_code::ConnectReturnCode,
+    properties::Properties, protocol_version::ProtocolVersion,
 };
 
 #[derive(Debug)]
@@ -26,6 +27,7 @@ impl ConnackMessage {
         let variable_header = VariableHeader {
             connect_acknowledge_flags,
             connect_return_code: return_code,
+            properties: None,
         };
 
         ConnackMessage {
@@ -34,6 +36,18 @@ impl ConnackMessage {
         }
     }
 
+    /// Crea el connack de mqtt 5, que lleva el reason code y las `properties` (ie session expiry interval).
+    pub fn new_v5(
+        session_present: SessionPresent,
+        return_code: ConnectReturnCode,
+        properties: Properties,
+    ) -> Self {
+        let mut connack = Self::new(session_present, return_code);
+        connack.fixed_header.remaining_length += properties.encoded_len() as u8;
+        connack.variable_header.properties = Some(properties);
+        connack
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         // Fixed Header
         let message_type = self.fixed_header.message_type;
@@ -41,27 +55,44 @@ impl ConnackMessage {
 
         // Variable Header
         let connect_acknowledge_flags = self.variable_header.connect_acknowledge_flags;
-        let connect_return_code = self.variable_header.connect_return_code.to_byte()[0];
+        let connect_return_code = match &self.variable_header.properties {
+            Some(_) => self.variable_header.connect_return_code.to_reason_code(),
+            None => self.variable_header.connect_return_code.to_byte()[0],
+        };
 
-        let bytes = vec![
+        let mut bytes = vec![
             message_type,
             remaining_length,
             connect_acknowledge_flags,
             connect_return_code,
         ];
+        if let Some(properties) = &self.variable_header.properties {
+            bytes.extend(properties.to_bytes());
+        }
 
         bytes
     }
 
+    /// Interpreta el connack, que es de mqtt 5 si lleva properties luego del reason code.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
         let fixed_header = FixedHeader {
             message_type: bytes[0],
             remaining_length: bytes[1],
         };
 
-        let variable_header = VariableHeader {
-            connect_acknowledge_flags: bytes[2],
-            connect_return_code: ConnectReturnCode::from_byte([bytes[3]])?,
+        let variable_header = if fixed_header.remaining_length > 2 {
+            let mut index = 4;
+            VariableHeader {
+                connect_acknowledge_flags: bytes[2],
+                connect_return_code: ConnectReturnCode::from_reason_code(bytes[3]),
+                properties: Some(Properties::from_bytes(bytes, &mut index)?),
+            }
+        } else {
+            VariableHeader {
+                connect_acknowledge_flags: bytes[2],
+                connect_return_code: ConnectReturnCode::from_byte([bytes[3]])?,
+                properties: None,
+            }
         };
 
         // un if message_type != de (2<<4) {dar error}
@@ -75,6 +106,24 @@ impl ConnackMessage {
     pub fn get_connect_return_code(&self) -> ConnectReturnCode {
         self.variable_header.connect_return_code.clone()
     }
+
+    /// Devuelve si el server retoma una sesión que conservaba del cliente.
+    pub fn is_session_present(&self) -> bool {
+        self.variable_header.connect_acknowledge_flags & 0x01 == 0x01
+    }
+
+    /// Devuelve la versión de protocolo con la que respondió el server.
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        match self.variable_header.properties {
+            Some(_) => ProtocolVersion::Mqtt5,
+            None => ProtocolVersion::Mqtt311,
+        }
+    }
+
+    /// Devuelve las properties del connack, solamente presentes en mqtt 5.
+    pub fn get_properties(&self) -> Option<&Properties> {
+        self.variable_header.properties.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -122,5 +171,32 @@ mod tests {
             connack_packet.variable_header.connect_return_code,
             ConnectReturnCode::ConnectionAccepted
         );
+        assert_eq!(connack_packet.get_protocol_version(), ProtocolVersion::Mqtt311);
+        assert!(connack_packet.is_session_present());
+    }
+
+    #[test]
+    fn test_from_bytes_mqtt5() {
+        let connack_packet = ConnackMessage::new_v5(
+            SessionPresent::NotPresentInLastSession,
+            ConnectReturnCode::NotAuthorized,
+            Properties::default().with_session_expiry_interval(300),
+        );
+        let bytes = connack_packet.to_bytes();
+        assert_eq!(bytes[1] as usize, bytes.len() - 2);
+        assert_eq!(bytes[3], 0x87);
+
+        let connack_packet = ConnackMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(connack_packet.get_protocol_version(), ProtocolVersion::Mqtt5);
+        assert_eq!(
+            connack_packet.get_connect_return_code(),
+            ConnectReturnCode::NotAuthorized
+        );
+        assert_eq!(
+            connack_packet
+                .get_properties()
+                .and_then(|p| p.get_session_expiry_interval()),
+            Some(300)
+        );
     }
 }
@@ -1,7 +1,8 @@
-use super::connect_return_code::ConnectReturnCode;
+use super::{connect_return_code::ConnectReturnCode, properties::Properties};
 
 #[derive(Debug)]
 pub struct VariableHeader {
     pub connect_acknowledge_flags: u8, // byte 3 --> 0000_000X (X = 1 if session present)
-    pub connect_return_code: ConnectReturnCode, // byte 4
+    pub connect_return_code: ConnectReturnCode, // byte 4 (en mqtt 5, el reason code equivalente)
+    pub properties: Option<Properties>, // solamente en mqtt 5.
 }
@@ -1,7 +1,13 @@
-use crate::mqtt::{messages::{
-    connect_fixed_header::FixedHeader, connect_flags::ConnectFlags, connect_payload::Payload,
-    connect_variable_header::VariableHeader,
-}, mqtt_utils::will_message_utils::will_message::WillMessageData};
+use std::io::{Error, ErrorKind};
+
+use crate::mqtt::{
+    messages::{
+        connect_fixed_header::FixedHeader, connect_flags::ConnectFlags, connect_payload::Payload,
+        connect_variable_header::VariableHeader, properties::Properties,
+        protocol_version::ProtocolVersion,
+    },
+    mqtt_utils::will_message_utils::will_message::WillMessageData,
+};
 
 #[derive(Debug)]
 pub struct ConnectMessage {
@@ -13,33 +19,47 @@ pub struct ConnectMessage {
 impl ConnectMessage {
     pub fn new(
         client_id: String,
-        will_topic: Option<String>,
-        will_message: Option<String>, //
+        will: Option<WillMessageData>,
         username: Option<String>,
         password: Option<String>,
-        will_qos: u8,
+        keep_alive: u16,
     ) -> Self {
         let fixed_header = FixedHeader {
             message_type: 1 << 4,
             remaining_length: 0,
         };
 
+        // Por protocolo, si no hay will, el will_qos y el will_retain deben valer 0.
+        let (will_topic, will_message, will_qos, will_retain) = match will {
+            Some(will) => (
+                Some(will.get_will_topic()),
+                Some(will.get_will_msg_content()),
+                will.get_qos().min(2),
+                will.get_will_retain() == 1,
+            ),
+            None => (None, None, 0, false),
+        };
+        let will_flag = will_topic.is_some();
+
         let variable_header = VariableHeader {
             protocol_name: [77, 81, 84, 84], // "MQTT" en ASCII
             protocol_level: 4,               // MQTT 3.1.1
             connect_flags: ConnectFlags {
                 username_flag: username.is_some(),
                 password_flag: password.is_some(),
-                will_retain: true,
+                will_retain,
                 will_qos,
-                will_flag: will_topic.is_some() && will_message.is_some(),
+                will_flag,
                 clean_session: true,
                 reserved: false,
             },
+            keep_alive,
+            properties: Properties::default(),
         };
 
         let payload = Payload {
             client_id,
+            will_properties: Properties::default(),
             will_topic,
             will_message,
             username,
@@ -59,34 +79,49 @@ impl ConnectMessage {
     }
 
     fn calculate_remaining_length(&self) -> u8 {
-        let variable_header_length = 5 + 1 + 1;
-        let length_string_u8 = 1;
-        let payload_length = length_string_u8
+        // Según mqtt 3.1.1, cada string se codifica precedida por 2 bytes con su longitud.
+        let length_prefix = 2;
+        let variable_header_length = length_prefix
+            + self.variable_header.protocol_name.len() // "MQTT"
+            + 1 // protocol level
+            + 1 // connect flags
+            + 2 // keep alive
+            + self.properties_len(&self.variable_header.properties);
+        let optional_fields = [
+            &self.payload.will_topic,
+            &self.payload.will_message,
+            &self.payload.username,
+            &self.payload.password,
+        ];
+        let will_properties_length = if self.variable_header.connect_flags.will_flag {
+            self.properties_len(&self.payload.will_properties)
+        } else {
+            0
+        };
+        let payload_length = length_prefix
             + self.payload.client_id.len()
-            + self
-                .payload
-                .will_topic
-                .as_ref()
-                .map_or(0, |s| s.len() + length_string_u8)
-            + self
-                .payload
-                .will_message
-                .as_ref()
-                .map_or(0, |s| s.len() + length_string_u8)
-            + self
-                .payload
-                .username
-                .as_ref()
-                .map_or(0, |s| s.len() + length_string_u8)
-            + self
-                .payload
-                .password
-                .as_ref()
-                .map_or(0, |s| s.len() + length_string_u8);
+            + will_properties_length
+            + optional_fields
+                .iter()
+                .map(|field| field.as_ref().map_or(0, |s| s.len() + length_prefix))
+                .sum::<usize>();
 
         (variable_header_length + payload_length) as u8
     }
 
+    /// Devuelve la cantidad de bytes que ocupan las `properties` en el mensaje, que solamente las lleva en mqtt 5.
+    fn properties_len(&self, properties: &Properties) -> usize {
+        if self.is_mqtt5() {
+            properties.encoded_len()
+        } else {
+            0
+        }
+    }
+
+    fn is_mqtt5(&self) -> bool {
+        self.get_protocol_version() == Some(ProtocolVersion::Mqtt5)
+    }
+
     /// Pasa un ConnectMessage a bytes.
     pub fn to_bytes(&mut self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -97,147 +132,141 @@ impl ConnectMessage {
         bytes.push(self.fixed_header.remaining_length);
 
         // Variable Header
-        let protocol_name_len: u8 = self.variable_header.protocol_name.len() as u8;
-        bytes.push(protocol_name_len); // el valor 4, len de "MQTT".
-        bytes.extend_from_slice(&self.variable_header.protocol_name);
+        push_length_prefixed(&mut bytes, &self.variable_header.protocol_name); // 0, 4, "MQTT".
         bytes.push(self.variable_header.protocol_level);
         let connect_flags = self.variable_header.connect_flags.to_byte();
         bytes.push(connect_flags);
-
-        // Payload
-        bytes.push(self.payload.client_id.len() as u8);
-        bytes.extend_from_slice(self.payload.client_id.as_bytes());
-        if let Some(will_topic) = self.payload.will_topic.clone() {
-            bytes.push(will_topic.len() as u8);
-            bytes.extend_from_slice(will_topic.as_bytes());
-        }
-        if let Some(will_message) = self.payload.will_message.clone() {
-            bytes.push(will_message.len() as u8);
-            bytes.extend_from_slice(will_message.as_bytes());
+        bytes.extend_from_slice(&self.variable_header.keep_alive.to_be_bytes());
+        if self.is_mqtt5() {
+            bytes.extend(self.variable_header.properties.to_bytes());
         }
-        if let Some(username) = self.payload.username.clone() {
-            bytes.push(username.len() as u8);
-            bytes.extend_from_slice(username.as_bytes());
+
+        // Payload, en el orden que indica el protocolo
+        push_length_prefixed(&mut bytes, self.payload.client_id.as_bytes());
+        if self.is_mqtt5() && self.variable_header.connect_flags.will_flag {
+            bytes.extend(self.payload.will_properties.to_bytes());
         }
-        if let Some(password) = self.payload.password.clone() {
-            bytes.push(password.len() as u8);
-            bytes.extend_from_slice(password.as_bytes());
+        let optional_fields = [
+            &self.payload.will_topic,
+            &self.payload.will_message,
+            &self.payload.username,
+            &self.payload.password,
+        ];
+        for field in optional_fields.into_iter().flatten() {
+            push_length_prefixed(&mut bytes, field.as_bytes());
         }
 
         bytes
     }
 
     /// Parsea los bytes recibidos y devuelve un struct ConnectMessage.
-    pub fn from_bytes(bytes: &[u8]) -> Self {
+    /// Devuelve error si los bytes no conforman un connect válido.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un connect msg.",
+            ));
+        }
         let fixed_header = FixedHeader {
             message_type: bytes[0],
             remaining_length: bytes[1],
         };
+        // Verificar que el tipo sea correcto, siempre debe valer 1
+        if fixed_header.message_type >> 4 != 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo incorrecto."));
+        }
 
-        let variable_header = VariableHeader {
-            // el byte 2 es el protocol_name_len, debería valer siempre 4 que es la len de "MQTT". []
-            protocol_name: [bytes[3], bytes[4], bytes[5], bytes[6]],
-            protocol_level: bytes[7],
-            connect_flags: ConnectFlags::from_byte(bytes[8]),
-        };
+        // Se recorta al remaining length, para no leer más allá del mensaje
+        let msg_end = 2 + fixed_header.remaining_length as usize;
+        if bytes.len() < msg_end {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "El remaining length excede los bytes recibidos.",
+            ));
+        }
+        let bytes = &bytes[..msg_end];
+        let mut index = 2;
 
-        // Indice donde comienza el payload (son 2 bytes de fixed header y 7 bytes de var header)
-        let payload_start_index = 9;
+        // Variable Header
+        let protocol_name = read_length_prefixed(bytes, &mut index)?;
+        let protocol_name: [u8; 4] = protocol_name
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Nombre de protocolo incorrecto."))?;
+        let protocol_level = read_u8(bytes, &mut index)?;
+        let connect_flags = ConnectFlags::from_byte(read_u8(bytes, &mut index)?);
+        let keep_alive =
+            u16::from_be_bytes([read_u8(bytes, &mut index)?, read_u8(bytes, &mut index)?]);
+        let is_mqtt5 = ProtocolVersion::from_level(protocol_level) == Some(ProtocolVersion::Mqtt5);
+        let properties = if is_mqtt5 {
+            Properties::from_bytes(bytes, &mut index)?
+        } else {
+            Properties::default()
+        };
 
-        // Calcular la longitud del payload
-        let variable_header_len: usize = 7; // (esto podría ser un método del variable header) // es payload_start_index - 2:
-        let payload_length = fixed_header.remaining_length as usize - variable_header_len; // Total - 7 bytes del variable header
-                                                                                           // Extraer el payload del mensaje
-        let payload_bytes = &bytes[payload_start_index..payload_start_index + payload_length];
+        let variable_header = VariableHeader {
+            protocol_name,
+            protocol_level,
+            connect_flags,
+            keep_alive,
+            properties,
+        };
 
-        // Procesar el payload según los flags y su longitud
-        let payload = Self::process_payload(&variable_header.connect_flags, payload_bytes);
+        // Procesar el payload según los flags
+        let payload =
+            Self::process_payload(&variable_header.connect_flags, is_mqtt5, bytes, &mut index)?;
 
-        // Verificar que el tipo sea correcto, siempre debe valer 1
-        // algo del estilo if message_type != 1 {return error tipo incorrecto al crear ConnectMessage },
-        // va a cambiar la firma, lo dejo así ahora y dsp lo refactorizo []
-        // Construir y retornar el mensaje ConnectMessage completo
-        ConnectMessage {
+        Ok(ConnectMessage {
             fixed_header,
             variable_header,
             payload,
-        }
+        })
     }
 
-    /// Parsea los bytes correspondientes al payload, a un struct payload con sus campos.
-    fn process_payload(flags: &ConnectFlags, bytes_payload: &[u8]) -> Payload {
-        let mut payload_start_index: usize = 0;
-
-        // Extraer el client_id
-        let client_id_length = bytes_payload[payload_start_index] as usize;
-        let client_id = std::str::from_utf8(
-            &bytes_payload[payload_start_index + 1..payload_start_index + 1 + client_id_length],
-        )
-        .unwrap()
-        .to_string(); // Convertir a String
-        payload_start_index += 1 + client_id_length;
+    /// Parsea los bytes correspondientes al payload, a partir de `index`, a un struct payload con sus campos.
+    fn process_payload(
+        flags: &ConnectFlags,
+        is_mqtt5: bool,
+        bytes: &[u8],
+        index: &mut usize,
+    ) -> Result<Payload, Error> {
+        let client_id = read_length_prefixed_string(bytes, index)?;
+        let will_properties = if is_mqtt5 && flags.will_flag {
+            Properties::from_bytes(bytes, index)?
+        } else {
+            Properties::default()
+        };
 
         // Extraer el will_topic y will_message si los flags lo indican
         let (will_topic, will_message) = if flags.will_flag {
-            let will_topic_length = bytes_payload[payload_start_index] as usize;
-            let will_topic = std::str::from_utf8(
-                &bytes_payload
-                    [payload_start_index + 1..payload_start_index + 1 + will_topic_length],
-            )
-            .unwrap()
-            .to_string(); // Convertir a String
-            payload_start_index += 1 + will_topic_length;
-
-            let will_message_length = bytes_payload[payload_start_index] as usize;
-            let will_message = std::str::from_utf8(
-                &bytes_payload
-                    [payload_start_index + 1..payload_start_index + 1 + will_message_length],
-            )
-            .unwrap()
-            .to_string(); // Convertir a String
-            payload_start_index += 1 + will_message_length;
-
+            let will_topic = read_length_prefixed_string(bytes, index)?;
+            let will_message = read_length_prefixed_string(bytes, index)?;
             (Some(will_topic), Some(will_message))
         } else {
             (None, None)
         };
 
-        // Extraer el username si los flags lo indican
+        // Extraer el username y el password si los flags lo indican
         let username = if flags.username_flag {
-            let username_length = bytes_payload[payload_start_index] as usize;
-            let username = std::str::from_utf8(
-                &bytes_payload[payload_start_index + 1..payload_start_index + 1 + username_length],
-            )
-            .unwrap()
-            .to_string(); // Convertir a String
-            payload_start_index += 1 + username_length;
-
-            Some(username)
+            Some(read_length_prefixed_string(bytes, index)?)
         } else {
             None
         };
-
-        // Extraer el password si los flags lo indican
         let password = if flags.password_flag {
-            let password_length = bytes_payload[payload_start_index] as usize;
-            let password = std::str::from_utf8(
-                &bytes_payload[payload_start_index + 1..payload_start_index + 1 + password_length],
-            )
-            .unwrap()
-            .to_string(); // Convertir a String
-
-            Some(password)
+            Some(read_length_prefixed_string(bytes, index)?)
         } else {
             None
         };
 
-        Payload {
+        Ok(Payload {
             client_id,
+            will_properties,
             will_topic,
             will_message,
             username,
             password,
-        }
+        })
     }
 
     /// Devuelve el campo username del mensaje.
@@ -255,12 +284,45 @@ impl ConnectMessage {
         Some(&self.payload.client_id)
     }
 
+    /// Devuelve si el cliente solicita comenzar una sesión nueva, descartando la anterior.
+    pub fn is_clean_session(&self) -> bool {
+        self.variable_header.connect_flags.clean_session
+    }
+
+    /// Establece el flag clean_session del connect.
+    pub fn set_clean_session(&mut self, clean_session: bool) {
+        self.variable_header.connect_flags.clean_session = clean_session;
+    }
+
+    /// Devuelve la versión de protocolo que pide el cliente, o None si no es una versión soportada.
+    pub fn get_protocol_version(&self) -> Option<ProtocolVersion> {
+        ProtocolVersion::from_level(self.variable_header.protocol_level)
+    }
+
+    /// Establece la versión de protocolo del connect. Las properties solamente se envían con mqtt 5.
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.variable_header.protocol_level = protocol_version.level();
+    }
+
+    /// Devuelve las properties de mqtt 5 del connect (ie session expiry interval).
+    pub fn get_properties(&self) -> &Properties {
+        &self.variable_header.properties
+    }
+
+    pub fn set_properties(&mut self, properties: Properties) {
+        self.variable_header.properties = properties;
+    }
+
+    /// Devuelve el keep alive, en segundos, negociado en el connect. Si vale 0, no se usa keep alive.
+    pub fn get_keep_alive(&self) -> u16 {
+        self.variable_header.keep_alive
+    }
+
     /// Devuelve un WillMessageAndTopic con los campos will_message y will_topic del mensaje
     /// si ambos son some, o None en caso contrario.
     pub fn get_will_to_publish(&self) -> Option<WillMessageData> {
         if let Some(msg) = &self.payload.will_message {
             if let Some(topic) = &self.payload.will_topic {
-
                 let will_msg: WillMessageData = WillMessageData::new(
                     String::from(msg),
                     String::from(topic),
@@ -274,6 +336,41 @@ impl ConnectMessage {
     }
 }
 
+/// Agrega a `bytes` el campo `field` precedido por su longitud en 2 bytes (big endian), según mqtt 3.1.1.
+fn push_length_prefixed(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(field);
+}
+
+/// Lee un byte de `bytes` en la posición `index`, y avanza el índice.
+fn read_u8(bytes: &[u8], index: &mut usize) -> Result<u8, Error> {
+    let byte = bytes
+        .get(*index)
+        .copied()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Connect msg incompleto."))?;
+    *index += 1;
+    Ok(byte)
+}
+
+/// Lee de `bytes` un campo precedido por su longitud en 2 bytes, a partir de `index`, y avanza el índice.
+fn read_length_prefixed(bytes: &[u8], index: &mut usize) -> Result<Vec<u8>, Error> {
+    let len = u16::from_be_bytes([read_u8(bytes, index)?, read_u8(bytes, index)?]) as usize;
+    let field = bytes.get(*index..*index + len).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "La longitud de un campo excede el tamaño del connect msg.",
+        )
+    })?;
+    *index += len;
+    Ok(field.to_vec())
+}
+
+/// Lee de `bytes` una string precedida por su longitud en 2 bytes, a partir de `index`, y avanza el índice.
+fn read_length_prefixed_string(bytes: &[u8], index: &mut usize) -> Result<String, Error> {
+    String::from_utf8(read_length_prefixed(bytes, index)?)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -282,11 +379,15 @@ mod tests {
     fn create_connect_message() -> ConnectMessage {
         ConnectMessage::new(
             "test_client".to_string(),
-            Some("test/topic".to_string()),
-            Some("test message".to_string()),
+            Some(WillMessageData::new(
+                "test message".to_string(),
+                "test/topic".to_string(),
+                1,
+                1,
+            )),
             Some("test_user".to_string()),
             Some("test_password".to_string()),
-            0
+            60,
         )
     }
 
@@ -299,7 +400,7 @@ mod tests {
         let bytes = connect_message.to_bytes();
 
         // Convertimos los bytes a un nuevo mensaje
-        let new_connect_message = ConnectMessage::from_bytes(&bytes);
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
 
         // Comprobamos que los mensajes son iguales
         assert!(connect_message.fixed_header == new_connect_message.fixed_header);
@@ -313,7 +414,7 @@ mod tests {
         let bytes = connect_message.to_bytes();
 
         // Convertimos los bytes a un nuevo mensaje
-        let new_connect_message = ConnectMessage::from_bytes(&bytes);
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
 
         // Comprobamos que los mensajes son iguales
         assert_eq!(
@@ -331,7 +432,7 @@ mod tests {
         let bytes = connect_message.to_bytes();
 
         // Convertimos los bytes a un nuevo mensaje
-        let new_connect_message = ConnectMessage::from_bytes(&bytes);
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
 
         // Comprobamos que los mensajes son iguales
         assert_eq!(connect_message.payload, new_connect_message.payload);
@@ -356,31 +457,152 @@ mod tests {
         let bytes = connect_message.to_bytes();
 
         // Convertimos los bytes a un nuevo mensaje
-        let new_connect_message = ConnectMessage::from_bytes(&bytes);
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
 
         // La función get_user obtiene el user del mensaje luego de convertirlo a mensaje desde bytes
         assert_eq!(new_connect_message.get_user().unwrap(), "test_user");
         assert_eq!(new_connect_message.get_passwd().unwrap(), "test_password");
     }
 
+    #[test]
+    fn test_from_bytes_parsing_will_qos_y_retain() {
+        let mut connect_message = create_connect_message();
+
+        let bytes = connect_message.to_bytes();
+
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
+        let will = new_connect_message.get_will_to_publish().unwrap();
+
+        assert_eq!(will.get_will_topic(), "test/topic");
+        assert_eq!(will.get_will_msg_content(), "test message");
+        assert_eq!(will.get_qos(), 1);
+        assert_eq!(will.get_will_retain(), 1);
+    }
+
+    #[test]
+    fn test_sin_will_los_flags_de_will_valen_cero() {
+        let connect_message = ConnectMessage::new("test_client".to_string(), None, None, None, 0);
+
+        let flags = &connect_message.variable_header.connect_flags;
+        assert!(!flags.will_flag);
+        assert!(!flags.will_retain);
+        assert_eq!(flags.will_qos, 0);
+    }
+
+    #[test]
+    fn test_from_bytes_parsing_keep_alive() {
+        let mut connect_message = create_connect_message();
+
+        let bytes = connect_message.to_bytes();
+
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
+
+        assert_eq!(new_connect_message.get_keep_alive(), 60);
+    }
+
     #[test]
     fn test_from_bytes_works_properly_with_none_fields() {
         // Creamos una instancia de ConnectMessage con algunos valores en None
         let mut connect_message = ConnectMessage::new(
             "test_client".to_string(),
             None,
-            None,
             Some("test_user".to_string()),
             Some("test_password123".to_string()),
-            0
+            0,
         );
         // Convertimos el mensaje a bytes
         let bytes = connect_message.to_bytes();
 
         // Convertimos los bytes a un nuevo mensaje
-        let new_connect_message = ConnectMessage::from_bytes(&bytes);
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
 
         // Comprobamos que los mensajes son iguales
         assert_eq!(connect_message.payload, new_connect_message.payload);
     }
+
+    #[test]
+    fn test_to_bytes_usa_longitudes_de_2_bytes() {
+        let mut connect_message =
+            ConnectMessage::new("ab".to_string(), None, Some("u".to_string()), None, 5);
+
+        let bytes = connect_message.to_bytes();
+
+        let expected_bytes = vec![
+            0b0001_0000, // Fixed header, tipo 1
+            17,          // Remaining length: 10 de variable header + 4 de client_id + 3 de username
+            0,
+            4,
+            b'M',
+            b'Q',
+            b'T',
+            b'T',        // Protocol name
+            4,           // Protocol level
+            0b1000_0010, // Flags: username y clean session
+            0,
+            5, // Keep alive
+            0,
+            2,
+            b'a',
+            b'b', // Client id
+            0,
+            1,
+            b'u', // Username
+        ];
+        assert_eq!(bytes, expected_bytes);
+    }
+
+    #[test]
+    fn test_from_bytes_con_campo_truncado_da_error() {
+        let mut connect_message = create_connect_message();
+        let mut bytes = connect_message.to_bytes();
+        // Se recortan los últimos bytes, pero se deja el remaining length original
+        bytes.truncate(bytes.len() - 3);
+
+        assert!(ConnectMessage::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_mqtt5_con_properties_y_will() {
+        let mut connect_message = create_connect_message();
+        connect_message.set_protocol_version(ProtocolVersion::Mqtt5);
+        connect_message.set_properties(
+            Properties::default()
+                .with_session_expiry_interval(120)
+                .with_user_property("app", "dron"),
+        );
+
+        let bytes = connect_message.to_bytes();
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
+
+        assert_eq!(bytes[1] as usize, bytes.len() - 2);
+        assert_eq!(
+            new_connect_message.get_protocol_version(),
+            Some(ProtocolVersion::Mqtt5)
+        );
+        assert_eq!(
+            new_connect_message.get_properties().get_session_expiry_interval(),
+            Some(120)
+        );
+        assert_eq!(connect_message.payload, new_connect_message.payload);
+    }
+
+    #[test]
+    fn test_protocol_level_no_soportado() {
+        let mut connect_message = create_connect_message();
+        let mut bytes = connect_message.to_bytes();
+        bytes[8] = 3; // mqtt 3.1
+
+        let new_connect_message = ConnectMessage::from_bytes(&bytes).unwrap();
+
+        assert_eq!(new_connect_message.get_protocol_version(), None);
+    }
+
+    #[test]
+    fn test_from_bytes_con_tipo_incorrecto_da_error() {
+        let mut connect_message = create_connect_message();
+        let mut bytes = connect_message.to_bytes();
+        bytes[0] = 0b0011_0000; // tipo publish
+
+        assert!(ConnectMessage::from_bytes(&bytes).is_err());
+    }
 }
@@ -1,6 +1,9 @@
+use crate::mqtt::messages::properties::Properties;
+
 #[derive(Debug, PartialEq)]
 pub struct Payload {
     pub client_id: String,
+    pub will_properties: Properties, // solamente en mqtt 5, si hay will.
     pub will_topic: Option<String>,
     pub will_message: Option<String>, // de estar presente, se manda la len y la string.
     pub username: Option<String>,
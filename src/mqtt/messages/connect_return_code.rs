@@ -3,7 +3,7 @@ use std::io::{Error, ErrorKind};
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConnectReturnCode {
     ConnectionAccepted = 0x00,
-    ProtocolError = 0x01,
+    ProtocolError = 0x01, // versión de protocolo no aceptada por el server.
     IdentifierRejected = 0x02,
     ServerUnavailable = 0x03,
     BadUsernameOrPassword = 0x04,
@@ -38,4 +38,31 @@ impl ConnectReturnCode {
             )),
         }
     }
+
+    /// Devuelve el reason code de mqtt 5 equivalente, que es el que lleva el connack de mqtt 5.
+    pub fn to_reason_code(&self) -> u8 {
+        match self {
+            ConnectReturnCode::ConnectionAccepted => 0x00,
+            ConnectReturnCode::UnspecifiedError => 0x80,
+            ConnectReturnCode::ProtocolError => 0x84,
+            ConnectReturnCode::IdentifierRejected => 0x85,
+            ConnectReturnCode::BadUsernameOrPassword => 0x86,
+            ConnectReturnCode::NotAuthorized => 0x87,
+            ConnectReturnCode::ServerUnavailable => 0x88,
+        }
+    }
+
+    /// Interpreta el reason code de un connack de mqtt 5. Los reason codes de error
+    /// sin equivalente en mqtt 3.1.1 se interpretan como `UnspecifiedError`.
+    pub fn from_reason_code(reason_code: u8) -> Self {
+        match reason_code {
+            0x00 => ConnectReturnCode::ConnectionAccepted,
+            0x84 => ConnectReturnCode::ProtocolError,
+            0x85 => ConnectReturnCode::IdentifierRejected,
+            0x86 => ConnectReturnCode::BadUsernameOrPassword,
+            0x87 => ConnectReturnCode::NotAuthorized,
+            0x88 | 0x89 => ConnectReturnCode::ServerUnavailable,
+            _ => ConnectReturnCode::UnspecifiedError,
+        }
+    }
 }
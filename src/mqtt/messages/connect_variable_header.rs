@@ -1,8 +1,10 @@
-use crate::mqtt::messages::connect_flags::ConnectFlags;
+use crate::mqtt::messages::{connect_flags::ConnectFlags, properties::Properties};
 
 #[derive(Debug, PartialEq)]
 pub struct VariableHeader {
     pub protocol_name: [u8; 4],      // bytes 1-4
     pub protocol_level: u8,          // byte 6
     pub connect_flags: ConnectFlags, // byte 7
+    pub keep_alive: u16,             // bytes 8-9, en segundos
+    pub properties: Properties,      // solamente en mqtt 5.
 }
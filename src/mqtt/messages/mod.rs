@@ -10,16 +10,21 @@ pub mod connect_return_code;
 pub mod connect_variable_header;
 pub mod disconnect_fixed_header;
 pub mod disconnect_message;
-pub mod message_type;
 pub mod packet_type;
+pub mod pingreq_message;
+pub mod pingresp_message;
+pub mod properties;
+pub mod protocol_version;
 pub mod puback_message;
+pub mod pubcomp_message;
 pub mod publish_fixed_header;
 pub mod publish_flags;
 pub mod publish_message;
 pub mod publish_payload;
 pub mod publish_variable_header;
+pub mod pubrec_message;
+pub mod pubrel_message;
 pub mod suback_message;
-pub mod subscribe_flags;
 pub mod subscribe_message;
 pub mod subscribe_return_code; // Es igual que el connect_fixed_header, no lo quise sacar de allá, hice un arch nuevo para evitar conflictos de git con otras ramas.
 pub mod unsuback_fixed_header;
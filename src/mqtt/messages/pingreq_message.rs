@@ -0,0 +1,67 @@
+use std::io::{Error, ErrorKind};
+
+/// Mensaje que envía el cliente al server para indicarle que sigue vivo cuando no tiene otros mensajes que enviar,
+/// dentro del keep alive negociado en el connect.
+#[derive(Debug, PartialEq)]
+pub struct PingReqMessage {
+    // Fixed header
+    tipo: u8, // siempre vale 12; y son 4 bits al enviarlo, los restantes son ceros.
+    // El PingReq no lleva variable header ni payload, su remaining length es 0.
+}
+
+impl PingReqMessage {
+    pub fn new() -> Self {
+        PingReqMessage { tipo: 12 }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let byte_de_flags: u8 = self.tipo << 4;
+        let rem_len: u8 = 0;
+        vec![byte_de_flags, rem_len]
+    }
+
+    pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PingReqMessage, Error> {
+        if msg_bytes.len() < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un pingreq msg.",
+            ));
+        }
+        // Extraigo el tipo, del byte de flags
+        let tipo: u8 = msg_bytes[0] >> 4;
+
+        // Chequeo tipo correcto
+        if tipo != 12 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo incorrecto."));
+        }
+
+        Ok(PingReqMessage { tipo })
+    }
+}
+
+impl Default for PingReqMessage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PingReqMessage;
+
+    #[test]
+    fn test_1_pingreq_msg_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let msg = PingReqMessage::new();
+
+        let msg_reconstruido = PingReqMessage::msg_from_bytes(msg.to_bytes());
+
+        assert_eq!(msg_reconstruido.unwrap(), msg);
+    }
+
+    #[test]
+    fn test_2_pingreq_msg_tiene_tipo_y_remaining_length_acordes() {
+        let msg = PingReqMessage::new();
+        // | 1100 | 0000 |, y remaining length 0
+        assert_eq!(msg.to_bytes(), vec![0b1100_0000, 0]);
+    }
+}
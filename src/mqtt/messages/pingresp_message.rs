@@ -0,0 +1,66 @@
+use std::io::{Error, ErrorKind};
+
+/// Respuesta del server a un PingReq, indica al cliente que la conexión sigue activa.
+#[derive(Debug, PartialEq)]
+pub struct PingRespMessage {
+    // Fixed header
+    tipo: u8, // siempre vale 13; y son 4 bits al enviarlo, los restantes son ceros.
+    // El PingResp no lleva variable header ni payload, su remaining length es 0.
+}
+
+impl PingRespMessage {
+    pub fn new() -> Self {
+        PingRespMessage { tipo: 13 }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let byte_de_flags: u8 = self.tipo << 4;
+        let rem_len: u8 = 0;
+        vec![byte_de_flags, rem_len]
+    }
+
+    pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PingRespMessage, Error> {
+        if msg_bytes.len() < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un pingresp msg.",
+            ));
+        }
+        // Extraigo el tipo, del byte de flags
+        let tipo: u8 = msg_bytes[0] >> 4;
+
+        // Chequeo tipo correcto
+        if tipo != 13 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo incorrecto."));
+        }
+
+        Ok(PingRespMessage { tipo })
+    }
+}
+
+impl Default for PingRespMessage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PingRespMessage;
+
+    #[test]
+    fn test_1_pingresp_msg_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let msg = PingRespMessage::new();
+
+        let msg_reconstruido = PingRespMessage::msg_from_bytes(msg.to_bytes());
+
+        assert_eq!(msg_reconstruido.unwrap(), msg);
+    }
+
+    #[test]
+    fn test_2_pingresp_msg_tiene_tipo_y_remaining_length_acordes() {
+        let msg = PingRespMessage::new();
+        // | 1101 | 0000 |, y remaining length 0
+        assert_eq!(msg.to_bytes(), vec![0b1101_0000, 0]);
+    }
+}
@@ -0,0 +1,308 @@
+use std::io::Error;
+
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+// Identificadores de las properties de mqtt 5 que se interpretan.
+const MESSAGE_EXPIRY_INTERVAL: u8 = 0x02;
+const SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+const REASON_STRING: u8 = 0x1F;
+const TOPIC_ALIAS_MAXIMUM: u8 = 0x22;
+const TOPIC_ALIAS: u8 = 0x23;
+const USER_PROPERTY: u8 = 0x26;
+
+/// Properties de mqtt 5 de un mensaje. Se codifican precedidas por su longitud en bytes
+/// (variable byte integer), y un mensaje sin properties lleva solamente una longitud 0.
+/// Las properties que no se interpretan se saltean al leerlas.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties {
+    session_expiry_interval: Option<u32>, // connect y connack: segundos que se conserva la sesión.
+    message_expiry_interval: Option<u32>, // publish: segundos que el mensaje es válido.
+    reason_string: Option<String>,        // connack: detalle del reason code, para diagnóstico.
+    topic_alias_maximum: Option<u16>, // connect y connack: cantidad de topic aliases que se aceptan del otro extremo.
+    topic_alias: Option<u16>, // publish: número que reemplaza al topic en los siguientes publish.
+    user_properties: Vec<(String, String)>, // pares clave, valor de la aplicación.
+}
+
+impl Properties {
+    pub fn with_session_expiry_interval(mut self, seconds: u32) -> Self {
+        self.session_expiry_interval = Some(seconds);
+        self
+    }
+
+    pub fn with_message_expiry_interval(mut self, seconds: u32) -> Self {
+        self.message_expiry_interval = Some(seconds);
+        self
+    }
+
+    pub fn with_reason_string(mut self, reason: &str) -> Self {
+        self.reason_string = Some(reason.to_string());
+        self
+    }
+
+    pub fn with_topic_alias_maximum(mut self, maximum: u16) -> Self {
+        self.topic_alias_maximum = Some(maximum);
+        self
+    }
+
+    pub fn with_topic_alias(mut self, alias: u16) -> Self {
+        self.topic_alias = Some(alias);
+        self
+    }
+
+    /// Devuelve las properties sin el topic alias, ie una vez resuelto el topic del publish.
+    pub fn without_topic_alias(mut self) -> Self {
+        self.topic_alias = None;
+        self
+    }
+
+    pub fn with_user_property(mut self, key: &str, value: &str) -> Self {
+        self.user_properties
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn get_session_expiry_interval(&self) -> Option<u32> {
+        self.session_expiry_interval
+    }
+
+    pub fn get_message_expiry_interval(&self) -> Option<u32> {
+        self.message_expiry_interval
+    }
+
+    pub fn get_reason_string(&self) -> Option<&String> {
+        self.reason_string.as_ref()
+    }
+
+    pub fn get_topic_alias_maximum(&self) -> Option<u16> {
+        self.topic_alias_maximum
+    }
+
+    pub fn get_topic_alias(&self) -> Option<u16> {
+        self.topic_alias
+    }
+
+    pub fn get_user_properties(&self) -> &[(String, String)] {
+        &self.user_properties
+    }
+
+    /// Pasa las properties a bytes, precedidas por su longitud.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut properties = vec![];
+        if let Some(seconds) = self.session_expiry_interval {
+            properties.push(SESSION_EXPIRY_INTERVAL);
+            properties.extend(seconds.to_be_bytes());
+        }
+        if let Some(seconds) = self.message_expiry_interval {
+            properties.push(MESSAGE_EXPIRY_INTERVAL);
+            properties.extend(seconds.to_be_bytes());
+        }
+        if let Some(reason) = &self.reason_string {
+            properties.push(REASON_STRING);
+            push_string(&mut properties, reason);
+        }
+        if let Some(maximum) = self.topic_alias_maximum {
+            properties.push(TOPIC_ALIAS_MAXIMUM);
+            properties.extend(maximum.to_be_bytes());
+        }
+        if let Some(alias) = self.topic_alias {
+            properties.push(TOPIC_ALIAS);
+            properties.extend(alias.to_be_bytes());
+        }
+        for (key, value) in &self.user_properties {
+            properties.push(USER_PROPERTY);
+            push_string(&mut properties, key);
+            push_string(&mut properties, value);
+        }
+
+        let mut bytes = encode_variable_byte_integer(properties.len());
+        bytes.extend(properties);
+        bytes
+    }
+
+    /// Lee de `bytes` las properties (precedidas por su longitud) a partir de `index`, y avanza el índice.
+    pub fn from_bytes(bytes: &[u8], index: &mut usize) -> Result<Self, Error> {
+        let len = decode_variable_byte_integer(bytes, index)?;
+        let end = *index + len;
+        if end > bytes.len() {
+            return Err(malformed("la longitud de las properties excede el mensaje"));
+        }
+        let properties_bytes = &bytes[..end];
+
+        let mut properties = Properties::default();
+        while *index < end {
+            let id = read_u8(properties_bytes, index)?;
+            match id {
+                SESSION_EXPIRY_INTERVAL => {
+                    properties.session_expiry_interval = Some(read_u32(properties_bytes, index)?)
+                }
+                MESSAGE_EXPIRY_INTERVAL => {
+                    properties.message_expiry_interval = Some(read_u32(properties_bytes, index)?)
+                }
+                REASON_STRING => {
+                    properties.reason_string = Some(read_string(properties_bytes, index)?)
+                }
+                TOPIC_ALIAS_MAXIMUM => {
+                    properties.topic_alias_maximum = Some(read_u16(properties_bytes, index)?)
+                }
+                TOPIC_ALIAS => properties.topic_alias = Some(read_u16(properties_bytes, index)?),
+                USER_PROPERTY => {
+                    let key = read_string(properties_bytes, index)?;
+                    let value = read_string(properties_bytes, index)?;
+                    properties.user_properties.push((key, value));
+                }
+                _ => skip_property(id, properties_bytes, index)?,
+            }
+        }
+        Ok(properties)
+    }
+
+    /// Devuelve la cantidad de bytes que ocupan las properties codificadas, incluyendo su longitud.
+    pub fn encoded_len(&self) -> usize {
+        self.to_bytes().len()
+    }
+}
+
+/// Saltea el valor de una property que no se interpreta, según el tipo de dato que le asigna el protocolo.
+fn skip_property(id: u8, bytes: &[u8], index: &mut usize) -> Result<(), Error> {
+    let len = match id {
+        0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => 1,
+        0x13 | 0x21 => 2,
+        0x18 | 0x27 => 4,
+        0x0B => {
+            decode_variable_byte_integer(bytes, index)?;
+            0
+        }
+        0x03 | 0x08 | 0x09 | 0x12 | 0x15 | 0x16 | 0x1A | 0x1C => read_u16(bytes, index)? as usize,
+        _ => return Err(malformed(&format!("property desconocida {:#04x}", id))),
+    };
+    if *index + len > bytes.len() {
+        return Err(malformed("property incompleta"));
+    }
+    *index += len;
+    Ok(())
+}
+
+/// Codifica `value` como variable byte integer: 7 bits por byte, con el bit más significativo
+/// indicando si sigue otro byte.
+fn encode_variable_byte_integer(mut value: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let mut byte = (value % 128) as u8;
+        value /= 128;
+        if value > 0 {
+            byte |= 0x80;
+        }
+        bytes.push(byte);
+        if value == 0 {
+            return bytes;
+        }
+    }
+}
+
+/// Lee un variable byte integer de a lo sumo 4 bytes a partir de `index`, y avanza el índice.
+fn decode_variable_byte_integer(bytes: &[u8], index: &mut usize) -> Result<usize, Error> {
+    let mut value = 0;
+    for i in 0..4 {
+        let byte = read_u8(bytes, index)?;
+        value += ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(malformed("variable byte integer de más de 4 bytes"))
+}
+
+fn push_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend((string.len() as u16).to_be_bytes());
+    bytes.extend(string.as_bytes());
+}
+
+fn read_u8(bytes: &[u8], index: &mut usize) -> Result<u8, Error> {
+    let byte = *bytes
+        .get(*index)
+        .ok_or_else(|| malformed("properties incompletas"))?;
+    *index += 1;
+    Ok(byte)
+}
+
+fn read_u16(bytes: &[u8], index: &mut usize) -> Result<u16, Error> {
+    Ok(u16::from_be_bytes([
+        read_u8(bytes, index)?,
+        read_u8(bytes, index)?,
+    ]))
+}
+
+fn read_u32(bytes: &[u8], index: &mut usize) -> Result<u32, Error> {
+    Ok(((read_u16(bytes, index)? as u32) << 16) | read_u16(bytes, index)? as u32)
+}
+
+fn read_string(bytes: &[u8], index: &mut usize) -> Result<String, Error> {
+    let len = read_u16(bytes, index)? as usize;
+    let string = bytes
+        .get(*index..*index + len)
+        .ok_or_else(|| malformed("string de una property incompleta"))?;
+    *index += len;
+    String::from_utf8(string.to_vec()).map_err(|_| malformed("property no es UTF-8 válido"))
+}
+
+fn malformed(detail: &str) -> Error {
+    MqttError::MalformedPacket(detail.to_string()).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_variable_byte_integer, encode_variable_byte_integer, Properties};
+
+    #[test]
+    fn test_1_las_properties_se_recuperan_de_sus_bytes() {
+        let properties = Properties::default()
+            .with_session_expiry_interval(3600)
+            .with_message_expiry_interval(30)
+            .with_reason_string("ok")
+            .with_topic_alias_maximum(10)
+            .with_topic_alias(3)
+            .with_user_property("origen", "dron-1")
+            .with_user_property("zona", "norte");
+
+        let bytes = properties.to_bytes();
+        let mut index = 0;
+
+        assert_eq!(
+            Properties::from_bytes(&bytes, &mut index).unwrap(),
+            properties
+        );
+        assert_eq!(index, bytes.len());
+    }
+
+    #[test]
+    fn test_2_sin_properties_se_codifica_solamente_la_longitud_cero() {
+        assert_eq!(Properties::default().to_bytes(), vec![0]);
+    }
+
+    #[test]
+    fn test_3_las_properties_no_interpretadas_se_saltean() {
+        // Payload format indicator (1 byte) y content type (string), seguidas de session expiry.
+        let bytes = vec![
+            14, 0x01, 1, 0x03, 0, 4, b'j', b's', b'o', b'n', 0x11, 0, 0, 0, 60, 0xAA,
+        ];
+        let mut index = 0;
+
+        let properties = Properties::from_bytes(&bytes, &mut index).unwrap();
+
+        assert_eq!(properties.get_session_expiry_interval(), Some(60));
+        assert_eq!(index, 15);
+    }
+
+    #[test]
+    fn test_4_variable_byte_integer_de_varios_bytes() {
+        let bytes = encode_variable_byte_integer(321);
+        assert_eq!(bytes, vec![0xC1, 0x02]);
+
+        let mut index = 0;
+        assert_eq!(
+            decode_variable_byte_integer(&bytes, &mut index).unwrap(),
+            321
+        );
+        assert!(Properties::from_bytes(&[5, 0x11, 0, 0], &mut 0).is_err());
+    }
+}
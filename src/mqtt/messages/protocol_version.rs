@@ -0,0 +1,27 @@
+/// Versión del protocolo mqtt de una conexión, según el protocol level del connect.
+/// Con mqtt 5 los mensajes connect, connack y publish llevan además sus properties.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ProtocolVersion {
+    #[default]
+    Mqtt311,
+    Mqtt5,
+}
+
+impl ProtocolVersion {
+    /// Devuelve la versión correspondiente al protocol level `level` del connect, si es una versión soportada.
+    pub fn from_level(level: u8) -> Option<Self> {
+        match level {
+            4 => Some(ProtocolVersion::Mqtt311),
+            5 => Some(ProtocolVersion::Mqtt5),
+            _ => None,
+        }
+    }
+
+    /// Devuelve el protocol level que se envía en el connect.
+    pub fn level(&self) -> u8 {
+        match self {
+            ProtocolVersion::Mqtt311 => 4,
+            ProtocolVersion::Mqtt5 => 5,
+        }
+    }
+}
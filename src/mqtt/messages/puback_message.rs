@@ -2,6 +2,14 @@ use std::{
     io::{Error, ErrorKind},
     mem::size_of,
 };
+
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+/// Reason code del puback con el que el server acepta el publish.
+pub const PUBACK_SUCCESS: u8 = 0x00;
+/// Reason code del puback con el que el server informa que el cliente no está autorizado a publicar en el topic.
+pub const PUBACK_NOT_AUTHORIZED: u8 = 0x87;
+
 #[derive(Debug, PartialEq)]
 pub struct PubAckMessage {
     // Fixed header
@@ -53,7 +61,14 @@ impl PubAckMessage {
         rem_len
     }
 
+    /// Recibe bytes, y los interpreta. Devuelve error si no alcanzan para un puback.
     pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PubAckMessage, Error> {
+        if msg_bytes.len() < 4 {
+            return Err(MqttError::MalformedPacket(
+                "no hay suficientes bytes para un puback válido".to_string(),
+            )
+            .into());
+        }
         let size_of_u8 = size_of::<u8>();
         let mut idx = 0;
         // Leo byte de flags
@@ -73,10 +88,13 @@ impl PubAckMessage {
                 .try_into()
                 .map_err(|_| Error::new(ErrorKind::Other, "Error leyendo bytes puback msg."))?,
         ); // forma 1
-           // Leo, si corresponde, u8 de reason code
-        let mut puback_reason_code: u8 = 0;
+        idx += size_of_u16;
+        // Leo, si corresponde, u8 de reason code
+        let mut puback_reason_code: u8 = PUBACK_SUCCESS;
         if remaining_len == 3 {
-            puback_reason_code = (&msg_bytes[0..size_of_u8])[0];
+            puback_reason_code = *msg_bytes
+                .get(idx)
+                .ok_or_else(|| MqttError::MalformedPacket("falta el reason code del puback".to_string()))?;
         }
 
         // Chequeo tipo correcto
@@ -102,7 +120,7 @@ impl PubAckMessage {
 
 #[cfg(test)]
 mod test {
-    use super::PubAckMessage;
+    use super::{PubAckMessage, PUBACK_NOT_AUTHORIZED};
 
     #[test]
     fn test_1a_puback_msg_caso_success_tiene_rem_len_acorde() {
@@ -127,4 +145,25 @@ mod test {
 
         assert_eq!(msg_reconstruido.unwrap(), msg);
     }
+
+    #[test]
+    fn test_3_puback_msg_con_reason_code_se_reconstruye_con_su_reason_code() {
+        let msg = PubAckMessage::new(7, PUBACK_NOT_AUTHORIZED);
+
+        let msg_reconstruido = PubAckMessage::msg_from_bytes(msg.to_bytes()).unwrap();
+
+        assert_eq!(msg_reconstruido.get_reason_code(), PUBACK_NOT_AUTHORIZED);
+        assert_eq!(msg_reconstruido.get_packet_id(), 7);
+    }
+
+    #[test]
+    fn test_4_un_puback_truncado_es_malformado() {
+        let bytes = PubAckMessage::new(7, 0).to_bytes();
+        // Con remaining length 3, falta el reason code.
+        let without_reason_code = vec![bytes[0], 3, bytes[2], bytes[3]];
+        for truncated in [bytes[..2].to_vec(), bytes[..3].to_vec(), without_reason_code, vec![]] {
+            let err = PubAckMessage::msg_from_bytes(truncated).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
 }
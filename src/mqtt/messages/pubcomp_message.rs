@@ -0,0 +1,75 @@
+use std::{
+    io::{Error, ErrorKind},
+    mem::size_of,
+};
+
+/// Último mensaje del handshake de QoS 2, enviado por el receptor del Publish al recibir el PubRel.
+#[derive(Debug, PartialEq)]
+pub struct PubCompMessage {
+    // Fixed header
+    tipo: u8, // siempre vale 7; y son 4 bits al enviarlo, los restantes son ceros.
+    // Variable header
+    packet_id: u16,
+    // El PubComp no lleva payload.
+}
+
+impl PubCompMessage {
+    pub fn new(packet_id: u16) -> Self {
+        PubCompMessage { tipo: 7, packet_id }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut msg_bytes: Vec<u8> = vec![];
+
+        // Tipo
+        let byte_de_flags: u8 = self.tipo << 4;
+        msg_bytes.extend(byte_de_flags.to_be_bytes());
+
+        // Remaining length, siempre 2: el packet_id.
+        let rem_len: u8 = size_of::<u16>() as u8;
+        msg_bytes.extend(rem_len.to_be_bytes());
+
+        // Variable header: packet_id
+        msg_bytes.extend(self.packet_id.to_be_bytes());
+
+        msg_bytes
+    }
+
+    pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PubCompMessage, Error> {
+        if msg_bytes.len() < 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un pubcomp msg.",
+            ));
+        }
+        // Extraigo el tipo, del byte de flags
+        let tipo: u8 = msg_bytes[0] >> 4;
+        // Leo u16 de packet_id, salteando el byte de remaining length
+        let packet_id = u16::from_be_bytes([msg_bytes[2], msg_bytes[3]]);
+
+        // Chequeo tipo correcto
+        if tipo != 7 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo incorrecto."));
+        }
+
+        Ok(PubCompMessage { tipo, packet_id })
+    }
+
+    pub fn get_packet_id(&self) -> u16 {
+        self.packet_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PubCompMessage;
+
+    #[test]
+    fn test_1_pubcomp_msg_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let msg = PubCompMessage::new(12);
+
+        let msg_reconstruido = PubCompMessage::msg_from_bytes(msg.to_bytes());
+
+        assert_eq!(msg_reconstruido.unwrap(), msg);
+    }
+}
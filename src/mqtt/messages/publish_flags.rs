@@ -59,6 +59,21 @@ impl PublishFlags {
     pub fn get_qos(&self) -> u8 {
         self.qos
     }
+
+    /// Devuelve si el flag de retain está seteado.
+    pub fn is_retain(&self) -> bool {
+        self.retain == 1
+    }
+
+    /// Devuelve si el flag de dup está seteado, es decir si el mensaje es una retransmisión.
+    pub fn is_dup(&self) -> bool {
+        self.dup == 1
+    }
+
+    /// Setea el flag de dup según `dup`.
+    pub fn set_dup(&mut self, dup: bool) {
+        self.dup = dup as u8;
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +130,16 @@ mod test {
         assert!(flags_reconstruido_b.is_err());
     }
 
+    #[test]
+    fn test_5_flag_de_retain_se_interpreta_correctamente() {
+        let flags_retain = PublishFlags::new(0, 1, 1).unwrap();
+        let flags_no_retain = PublishFlags::new(0, 1, 0).unwrap();
+
+        let reconstruido = PublishFlags::from_flags_byte(flags_retain.to_flags_byte()).unwrap();
+
+        assert!(reconstruido.is_retain());
+        assert!(!flags_no_retain.is_retain());
+    }
 
 }
 
@@ -18,13 +18,24 @@ type TdesEde3Cbc = Cbc<TdesEde3, Pkcs7>;
 const KEY: [u8; 24] = [0x01; 24]; // Esto es solo un ejemplo, usa claves seguras en producción
 const IV: [u8; 8] = [0x02; 8];
 
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use crate::mqtt::messages::publish_fixed_header::FixedHeader;
 use crate::mqtt::messages::publish_flags::PublishFlags;
+use crate::mqtt::messages::properties::Properties;
+use crate::mqtt::messages::protocol_version::ProtocolVersion;
 use crate::mqtt::messages::publish_payload::Payload;
 use crate::mqtt::messages::publish_variable_header::VariableHeader;
 
 type TimestampType = u128;
 const  TIMESTAMP_LENGHT: usize = 16;
+/// Remaining length máxima de un publish: se codifica en un solo byte (y es la `max_packet_size` por defecto del
+/// server).
+pub const MAX_REMAINING_LENGTH: usize = u8::MAX as usize;
+/// Bytes que se reservan para las properties de mqtt 5 con las que el server puede reenviar un publish: su largo,
+/// el message expiry interval y el topic alias.
+const PUBLISH_PROPERTIES_BUDGET: usize = 1 + 5 + 3;
+/// Tamaño del bloque de 3DES con el que se cifra el payload.
+const CIPHER_BLOCK_LEN: usize = 8;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PublishMessage {
@@ -105,10 +116,16 @@ impl<'a> PublishMessage {
                 "El packet_identifier debe ser None si qos = 0".to_string(),
             ));
         }
+        // Sin importar cómo se codificó el payload, no puede exceder lo que entra en un publish.
+        let max_payload_len = Self::max_payload_len(topic_name);
+        if content.len() > max_payload_len {
+            return Err(MqttError::PayloadTooLarge(content.len(), max_payload_len).into());
+        }
 
         let variable_header = VariableHeader {
             topic_name: topic_name.to_string(),
             packet_identifier,
+            properties: None,
         };
 
         let content = encrypt_3des(content);
@@ -140,6 +157,16 @@ impl<'a> PublishMessage {
         Ok(publish_message)
     }
 
+    /// Devuelve la cantidad máxima de bytes de payload que puede llevar un publish por `topic` sin que su remaining
+    /// length exceda `MAX_REMAINING_LENGTH`, aunque lleve packet identifier y el server le agregue properties.
+    /// `new` devuelve error con un payload más grande, por lo que quien lo publica debe dividirlo en varios publish.
+    pub const fn max_payload_len(topic: &str) -> usize {
+        let overhead = 2 + topic.len() + 2 + PUBLISH_PROPERTIES_BUDGET + TIMESTAMP_LENGHT;
+        let available = MAX_REMAINING_LENGTH.saturating_sub(overhead);
+        // El cifrado completa el payload hasta el siguiente múltiplo del bloque, agregando siempre al menos un byte.
+        (available / CIPHER_BLOCK_LEN * CIPHER_BLOCK_LEN).saturating_sub(1)
+    }
+
     fn calculate_remaining_length_2(&self) -> u8 {
         //aux: remaining length = variable header + payload
         //aux: variable header = topic_name + packet_identifier
@@ -149,20 +176,85 @@ impl<'a> PublishMessage {
             Some(_) => 2, //si qos > 0
             None => 0,    //si qos = 0
         };
+        let properties_length = self.properties_len();
         let payload_length = self.payload.content.len();
         let timestamp_length = TIMESTAMP_LENGHT; // tamaño de u128
 
         (rem_len_in_two_bytes
             + topic_name_length
             + packet_identifier_length
+            + properties_length
             + payload_length
             + timestamp_length) as u8
     }
 
+    fn properties_len(&self) -> usize {
+        self.variable_header
+            .properties
+            .as_ref()
+            .map_or(0, |properties| properties.encoded_len())
+    }
+
     pub fn get_packet_id(&self) -> Option<u16> {
         self.variable_header.packet_identifier
     }
 
+    /// Devuelve el mensaje con las `properties` de mqtt 5 (ie message expiry interval), con las que
+    /// pasa a codificarse como publish de mqtt 5.
+    pub fn with_properties(mut self, properties: Properties) -> Self {
+        self.variable_header.properties = Some(properties);
+        self.fixed_header.remaining_length = self.calculate_remaining_length_2();
+        self
+    }
+
+    /// Devuelve las properties del mensaje, solamente presentes en mqtt 5.
+    pub fn get_properties(&self) -> Option<&Properties> {
+        self.variable_header.properties.as_ref()
+    }
+
+    /// Devuelve el topic alias del mensaje, si lo lleva (solamente en mqtt 5).
+    pub fn get_topic_alias(&self) -> Option<u16> {
+        self.get_properties().and_then(|properties| properties.get_topic_alias())
+    }
+
+    /// Devuelve el mensaje con el topic `topic`, ie para enviarlo sin topic cuando lleva un topic alias
+    /// ya conocido por el otro extremo, o para completarlo al recibirlo así.
+    pub fn with_topic(mut self, topic: &str) -> Self {
+        self.variable_header.topic_name = topic.to_string();
+        self.fixed_header.remaining_length = self.calculate_remaining_length_2();
+        self
+    }
+
+    /// Devuelve una copia del mensaje para entregarla a un suscriptor con qos `qos` y el packet id `packet_id`
+    /// elegido para él, conservando su contenido y timestamp. Devuelve error si `packet_id` no es None con qos 0.
+    pub fn for_delivery(&self, qos: u8, packet_id: Option<u16>) -> Result<PublishMessage, Error> {
+        let flags = PublishFlags::new(self.is_dup() as u8, qos, self.is_retain() as u8)?;
+        if !flags.is_qos_greater_than_0() && packet_id.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "El packet_identifier debe ser None si qos = 0".to_string(),
+            ));
+        }
+        let mut message = self.clone();
+        message.fixed_header.flags = flags;
+        message.variable_header.packet_identifier = packet_id;
+        message.fixed_header.remaining_length = message.calculate_remaining_length_2();
+        Ok(message)
+    }
+
+    /// Devuelve una copia del mensaje codificada para una conexión con la versión de protocolo `protocol_version`:
+    /// en mqtt 3.1.1 sin properties, y en mqtt 5 con las del mensaje (o vacías si no tenía).
+    pub fn for_protocol_version(&self, protocol_version: ProtocolVersion) -> PublishMessage {
+        let properties = match protocol_version {
+            ProtocolVersion::Mqtt311 => None,
+            ProtocolVersion::Mqtt5 => Some(self.get_properties().cloned().unwrap_or_default()),
+        };
+        let mut message = self.clone();
+        message.variable_header.properties = properties;
+        message.fixed_header.remaining_length = message.calculate_remaining_length_2();
+        message
+    }
+
     ///Devuelve: Vector de bytes segun MQTT:
     /// 1er byte: meesage type y flags
     /// 2do byte: remaining_length
@@ -215,6 +307,7 @@ impl<'a> PublishMessage {
         let remaining_length = 2
             + topic_name_length
             + 2 * self.variable_header.packet_identifier.is_some() as u8
+            + self.properties_len() as u8
             + self.payload.content.len() as u8
             + TIMESTAMP_LENGHT as u8; // tamaño del timestamp
         bytes.push(remaining_length);
@@ -228,6 +321,9 @@ impl<'a> PublishMessage {
             bytes.push((packet_identifier >> 8) as u8);
             bytes.push(packet_identifier as u8);
         }
+        if let Some(properties) = &self.variable_header.properties {
+            bytes.extend(properties.to_bytes());
+        }
 
         bytes.extend_from_slice(&self.payload.content);
 
@@ -238,39 +334,71 @@ impl<'a> PublishMessage {
     }
 
     pub fn from_bytes(bytes: Vec<u8>) -> Result<PublishMessage, std::io::Error> {
-        if bytes.len() < 13 {
-            // Mínimo 5 bytes + 8 bytes de timestamp
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "No hay suficientes bytes para un mensaje válido",
-            ));
+        Self::from_bytes_with_version(bytes, ProtocolVersion::Mqtt311)
+    }
+
+    /// Interpreta el publish recibido por una conexión con la versión de protocolo `protocol_version`,
+    /// ya que solamente en mqtt 5 lleva properties.
+    pub fn from_bytes_with_version(
+        bytes: Vec<u8>,
+        protocol_version: ProtocolVersion,
+    ) -> Result<PublishMessage, std::io::Error> {
+        if bytes.len() < 4 + TIMESTAMP_LENGHT {
+            // Mínimo el fixed header y el largo del topic, y el timestamp
+            return Err(MqttError::MalformedPacket(
+                "no hay suficientes bytes para un publish válido".to_string(),
+            )
+            .into());
         }
 
         let first_byte = bytes[0];
         let flags = PublishFlags::from_flags_byte(first_byte)?;
         let remaining_length = bytes[1];
 
+        let payload_end = bytes.len() - TIMESTAMP_LENGHT;
         let topic_name_length = ((bytes[2] as usize) << 8) | (bytes[3] as usize);
+        if 4 + topic_name_length > payload_end {
+            return Err(MqttError::MalformedPacket(
+                "el largo del topic excede el publish".to_string(),
+            )
+            .into());
+        }
         let topic_name = match String::from_utf8(bytes[4..4 + topic_name_length].to_vec()) {
             Ok(v) => v,
             Err(_) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "El nombre del tema no es válido UTF-8",
-                ))
+                return Err(MqttError::MalformedPacket(
+                    "el nombre del topic no es UTF-8 válido".to_string(),
+                )
+                .into())
             }
         };
 
+        // Por protocolo, solamente los publish de qos mayor a 0 llevan packet_identifier.
         let mut packet_identifier = None;
-        if remaining_length > (topic_name_length + 2) as u8 {
+        if flags.is_qos_greater_than_0() {
+            if 6 + topic_name_length > payload_end {
+                return Err(MqttError::MalformedPacket(
+                    "falta el packet identifier de un publish con qos mayor a 0".to_string(),
+                )
+                .into());
+            }
             packet_identifier = Some(
                 ((bytes[4 + topic_name_length] as u16) << 8)
                     | (bytes[5 + topic_name_length] as u16),
             );
         }
 
-        let payload_start = 4 + topic_name_length + 2 * packet_identifier.is_some() as usize;
-        let payload_end = bytes.len() - TIMESTAMP_LENGHT;
+        let mut payload_start = 4 + topic_name_length + 2 * packet_identifier.is_some() as usize;
+        let properties = match protocol_version {
+            ProtocolVersion::Mqtt311 => None,
+            ProtocolVersion::Mqtt5 => Some(Properties::from_bytes(&bytes, &mut payload_start)?),
+        };
+        if payload_start > payload_end {
+            return Err(MqttError::MalformedPacket(
+                "las properties exceden el publish".to_string(),
+            )
+            .into());
+        }
         let payload_content = bytes[payload_start..payload_end].to_vec();
 
         // Cambiar el u128 en caso de que se cambie el tipo de dato del TIMESTAMP
@@ -284,6 +412,7 @@ impl<'a> PublishMessage {
             variable_header: VariableHeader {
                 topic_name,
                 packet_identifier,
+                properties,
             },
             payload: Payload {
                 content: payload_content,
@@ -356,6 +485,23 @@ impl<'a> PublishMessage {
         self.fixed_header.flags.get_qos()
     }
 
+    /// Devuelve si el mensaje debe ser retenido por el server para su topic.
+    pub fn is_retain(&self) -> bool {
+        self.fixed_header.flags.is_retain()
+    }
+
+    /// Devuelve si el mensaje es una retransmisión de un publish ya enviado.
+    pub fn is_dup(&self) -> bool {
+        self.fixed_header.flags.is_dup()
+    }
+
+    /// Devuelve una copia del mensaje con el flag de dup seteado, para retransmitirlo.
+    pub fn as_duplicate(&self) -> PublishMessage {
+        let mut duplicate = self.clone();
+        duplicate.fixed_header.flags.set_dup(true);
+        duplicate
+    }
+
     pub fn get_topic_name(&self) -> String {
         self.variable_header.topic_name.to_string()
     }
@@ -462,6 +608,86 @@ mod tests {
         assert!(msg1.get_timestamp() < msg2.get_timestamp());
     }
 
+    #[test]
+    fn test_duplicate_conserva_el_mensaje_con_el_flag_de_dup() {
+        let publish_message = create_test_publish_message().unwrap();
+        assert!(!publish_message.is_dup());
+
+        let duplicate = publish_message.as_duplicate();
+        let deserialized_message = PublishMessage::from_bytes(duplicate.to_bytes()).unwrap();
+
+        assert!(deserialized_message.is_dup());
+        assert_eq!(deserialized_message.get_packet_id(), Some(42));
+        assert_eq!(
+            deserialized_message.get_payload(),
+            publish_message.get_payload()
+        );
+    }
+
+    #[test]
+    fn test_publish_mqtt5_con_message_expiry_interval() {
+        let publish_message = create_test_publish_message()
+            .unwrap()
+            .with_properties(Properties::default().with_message_expiry_interval(60));
+        let bytes = publish_message.to_bytes();
+        assert_eq!(bytes[1] as usize, bytes.len() - 2);
+
+        let deserialized_message =
+            PublishMessage::from_bytes_with_version(bytes, ProtocolVersion::Mqtt5).unwrap();
+
+        assert_eq!(deserialized_message, publish_message);
+        assert_eq!(
+            deserialized_message
+                .get_properties()
+                .and_then(|p| p.get_message_expiry_interval()),
+            Some(60)
+        );
+        assert_eq!(
+            deserialized_message.get_payload(),
+            publish_message.get_payload()
+        );
+    }
+
+    #[test]
+    fn test_publish_mqtt5_se_envia_sin_properties_a_mqtt311() {
+        let publish_message = create_test_publish_message()
+            .unwrap()
+            .with_properties(Properties::default().with_user_property("origen", "camara-3"));
+
+        let for_311 = publish_message.for_protocol_version(ProtocolVersion::Mqtt311);
+        let deserialized_message = PublishMessage::from_bytes(for_311.to_bytes()).unwrap();
+
+        assert_eq!(deserialized_message.get_properties(), None);
+        assert_eq!(
+            deserialized_message.get_payload(),
+            publish_message.get_payload()
+        );
+    }
+
+    #[test]
+    fn test_for_delivery_cambia_el_qos_y_el_packet_id_conservando_el_mensaje() {
+        let publish_message = create_test_publish_message().unwrap();
+
+        let delivery = publish_message.for_delivery(0, None).unwrap();
+        let deserialized_message = PublishMessage::from_bytes(delivery.to_bytes()).unwrap();
+
+        assert_eq!(deserialized_message.get_qos(), 0);
+        assert_eq!(deserialized_message.get_packet_id(), None);
+        assert_eq!(
+            deserialized_message.get_payload(),
+            publish_message.get_payload()
+        );
+        assert_eq!(
+            deserialized_message.get_timestamp(),
+            publish_message.get_timestamp()
+        );
+        assert_eq!(
+            publish_message.for_delivery(1, Some(7)).unwrap().get_packet_id(),
+            Some(7)
+        );
+        assert!(publish_message.for_delivery(0, Some(7)).is_err());
+    }
+
     // #[test]
     // ///Testea que si qos es 0, packet_identifier debe ser None.
     // fn test_packet_identifier_none_if_qos_0() {
@@ -492,6 +718,49 @@ mod tests {
     //     assert_eq!(recovered_message, original_message);
     // }
 
+    #[test]
+    fn test_el_payload_maximo_entra_en_un_publish_con_properties() {
+        let topic = "cam-snapshot";
+        let max_len = PublishMessage::max_payload_len(topic);
+        let content = vec![7; max_len];
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let properties = Properties::default().with_message_expiry_interval(60).with_topic_alias(3);
+        let message = PublishMessage::new(flags.clone(), topic, Some(1), &content)
+            .unwrap()
+            .with_properties(properties);
+
+        let bytes = message.to_bytes();
+        let remaining_length = bytes.len() - 2;
+        assert!(remaining_length <= MAX_REMAINING_LENGTH);
+        assert_eq!(bytes[1] as usize, remaining_length);
+        let received = PublishMessage::from_bytes_with_version(bytes, ProtocolVersion::Mqtt5).unwrap();
+        assert_eq!(received.get_payload(), content);
+
+        // Con un byte más, el payload cifrado ya no entra, y el publish no se crea.
+        let overhead = remaining_length - encrypt_3des(&content).len();
+        assert!(overhead + encrypt_3des(&vec![7; max_len + 1]).len() > MAX_REMAINING_LENGTH);
+        let err = PublishMessage::new(flags, topic, Some(1), &vec![7; max_len + 1]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_un_publish_truncado_o_con_largos_invalidos_es_malformado() {
+        let bytes = create_test_publish_message().unwrap().to_bytes();
+        let timestamp = &bytes[bytes.len() - TIMESTAMP_LENGHT..];
+
+        // El largo del topic excede el publish.
+        let mut bogus_topic_len = bytes.clone();
+        bogus_topic_len[2] = 0xFF;
+        bogus_topic_len[3] = 0xFF;
+        // Qos 1 sin packet identifier: solamente el topic y el timestamp.
+        let mut without_packet_id = vec![bytes[0], 0, 0, 1, b't'];
+        without_packet_id.extend_from_slice(timestamp);
+        for malformed in [bogus_topic_len, without_packet_id, bytes[..10].to_vec(), vec![]] {
+            let err = PublishMessage::from_bytes(malformed).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
     #[test]
     /// Testeo de la funcion encriptar
     fn test_encrypt() {
@@ -1,3 +1,5 @@
+use super::properties::Properties;
+
 #[derive(Debug, Clone, PartialEq)]
 
 pub struct VariableHeader {
@@ -6,4 +8,6 @@ pub struct VariableHeader {
     pub topic_name: String, // Cambiado de &'a str a String //bytes 1-5,(ejemplo topic_name: "a/b")
 
     pub packet_identifier: Option<u16>, // bytes 6-7, solo si qos > 0 ,1 o 2
+
+    pub properties: Option<Properties>, // solamente en mqtt 5, luego del packet_identifier.
 }
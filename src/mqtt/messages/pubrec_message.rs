@@ -0,0 +1,83 @@
+use std::{
+    io::{Error, ErrorKind},
+    mem::size_of,
+};
+
+/// Primer mensaje de respuesta del handshake de QoS 2, enviado por el receptor de un Publish.
+#[derive(Debug, PartialEq)]
+pub struct PubRecMessage {
+    // Fixed header
+    tipo: u8, // siempre vale 5; y son 4 bits al enviarlo, los restantes son ceros.
+    // Variable header
+    packet_id: u16,
+    // El PubRec no lleva payload.
+}
+
+impl PubRecMessage {
+    pub fn new(packet_id: u16) -> Self {
+        PubRecMessage { tipo: 5, packet_id }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut msg_bytes: Vec<u8> = vec![];
+
+        // Tipo
+        let byte_de_flags: u8 = self.tipo << 4;
+        msg_bytes.extend(byte_de_flags.to_be_bytes());
+
+        // Remaining length, siempre 2: el packet_id.
+        let rem_len: u8 = size_of::<u16>() as u8;
+        msg_bytes.extend(rem_len.to_be_bytes());
+
+        // Variable header: packet_id
+        msg_bytes.extend(self.packet_id.to_be_bytes());
+
+        msg_bytes
+    }
+
+    pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PubRecMessage, Error> {
+        if msg_bytes.len() < 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un pubrec msg.",
+            ));
+        }
+        // Extraigo el tipo, del byte de flags
+        let tipo: u8 = msg_bytes[0] >> 4;
+        // Leo u16 de packet_id, salteando el byte de remaining length
+        let packet_id = u16::from_be_bytes([msg_bytes[2], msg_bytes[3]]);
+
+        // Chequeo tipo correcto
+        if tipo != 5 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo incorrecto."));
+        }
+
+        Ok(PubRecMessage { tipo, packet_id })
+    }
+
+    pub fn get_packet_id(&self) -> u16 {
+        self.packet_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PubRecMessage;
+
+    #[test]
+    fn test_1_pubrec_msg_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let msg = PubRecMessage::new(7);
+
+        let msg_reconstruido = PubRecMessage::msg_from_bytes(msg.to_bytes());
+
+        assert_eq!(msg_reconstruido.unwrap(), msg);
+    }
+
+    #[test]
+    fn test_2_pubrec_msg_con_tipo_incorrecto_da_error() {
+        // Bytes de un puback
+        let bytes = vec![0b0100_0000, 2, 0, 7];
+
+        assert!(PubRecMessage::msg_from_bytes(bytes).is_err());
+    }
+}
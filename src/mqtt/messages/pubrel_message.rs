@@ -0,0 +1,122 @@
+use std::{
+    io::{Error, ErrorKind},
+    mem::size_of,
+};
+
+use super::{message::Message, packet_type::PacketType};
+
+/// Segundo mensaje del handshake de QoS 2, enviado por el emisor del Publish al recibir el PubRec.
+/// A diferencia de los otros acks, sus 4 bits inferiores del primer byte valen 0010 (por protocolo mqtt).
+#[derive(Debug, PartialEq)]
+pub struct PubRelMessage {
+    // Fixed header
+    tipo: u8,           // siempre vale 6.
+    reserved_flags: u8, // siempre vale 2.
+    // Variable header
+    packet_id: u16,
+    // El PubRel no lleva payload.
+}
+
+impl PubRelMessage {
+    pub fn new(packet_id: u16) -> Self {
+        PubRelMessage {
+            tipo: 6,
+            reserved_flags: 2,
+            packet_id,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut msg_bytes: Vec<u8> = vec![];
+
+        // Tipo y reserved flags
+        let mut byte_de_flags: u8 = self.tipo << 4;
+        byte_de_flags |= self.reserved_flags;
+        msg_bytes.extend(byte_de_flags.to_be_bytes());
+
+        // Remaining length, siempre 2: el packet_id.
+        let rem_len: u8 = size_of::<u16>() as u8;
+        msg_bytes.extend(rem_len.to_be_bytes());
+
+        // Variable header: packet_id
+        msg_bytes.extend(self.packet_id.to_be_bytes());
+
+        msg_bytes
+    }
+
+    pub fn msg_from_bytes(msg_bytes: Vec<u8>) -> Result<PubRelMessage, Error> {
+        if msg_bytes.len() < 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un pubrel msg.",
+            ));
+        }
+        // Extraigo el tipo y los reserved flags, del byte de flags
+        let tipo: u8 = msg_bytes[0] >> 4;
+        let reserved_flags: u8 = msg_bytes[0] & 0b0000_1111;
+        // Leo u16 de packet_id, salteando el byte de remaining length
+        let packet_id = u16::from_be_bytes([msg_bytes[2], msg_bytes[3]]);
+
+        // Chequeo tipo y flags correctos
+        if tipo != 6 || reserved_flags != 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "Tipo o flags incorrectos."));
+        }
+
+        Ok(PubRelMessage {
+            tipo,
+            reserved_flags,
+            packet_id,
+        })
+    }
+
+    pub fn get_packet_id(&self) -> u16 {
+        self.packet_id
+    }
+}
+
+// Se implementa Message para que el Retransmitter pueda retransmitirlo hasta recibir el PubComp.
+impl Message for PubRelMessage {
+    fn get_packet_id(&self) -> Option<u16> {
+        Some(self.get_packet_id())
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn get_type(&self) -> PacketType {
+        PacketType::Pubrel
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PubRelMessage;
+
+    #[test]
+    fn test_1_pubrel_msg_tiene_flags_reservados_acordes() {
+        let msg = PubRelMessage::new(1);
+        // El primer byte debe ser | 0110 | 0010 |
+        assert_eq!(msg.to_bytes()[0], 0b0110_0010);
+    }
+
+    #[test]
+    fn test_2_pubrel_msg_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let msg = PubRelMessage::new(300);
+
+        let msg_reconstruido = PubRelMessage::msg_from_bytes(msg.to_bytes());
+
+        assert_eq!(msg_reconstruido.unwrap(), msg);
+    }
+
+    #[test]
+    fn test_3_pubrel_msg_con_flags_reservados_incorrectos_da_error() {
+        let bytes = vec![0b0110_0000, 2, 1, 44];
+
+        assert!(PubRelMessage::msg_from_bytes(bytes).is_err());
+    }
+}
@@ -71,7 +71,14 @@ impl SubscribeMessage {
 
     /// Recibe bytes, y los interpreta.
     /// Devuelve un struct SubscribeMessage con los valores recibidos e interpretados.
+    /// Devuelve error si los bytes no alcanzan para lo que indican la remaining length y los largos de los topics.
     pub fn from_bytes(msg_bytes: Vec<u8>) -> Result<SubscribeMessage, Error> {
+        if msg_bytes.len() < 4 {
+            return Err(MqttError::MalformedPacket(
+                "no hay suficientes bytes para un subscribe válido".to_string(),
+            )
+            .into());
+        }
         let size_of_u8 = size_of::<u8>();
         // Leo u8 byte de tipo y reserved flags
         let byte_de_tipo_y_flags = (&msg_bytes[0..size_of_u8])[0];
@@ -95,15 +102,27 @@ impl SubscribeMessage {
         // Payload. Leo cada elemento del vector: primero la len de la string en u16
         // y luego el elemento, que será una tupla (String, u8)
         // Siendo que mqtt no envía la longitud del vector, utilizamos la remaining length
-        let mut rem_len_leida: u8 = 2;
+        let mut rem_len_leida: usize = 2;
         let mut topics: Vec<(String, u8)> = vec![];
-        while rem_len_leida < rem_len {
-            // Leo la string len
+        while rem_len_leida < rem_len as usize {
+            // Leo la string len, y me aseguro de que estén la string y el u8 que le siguen
+            if idx + size_of_u16 > msg_bytes.len() {
+                return Err(MqttError::MalformedPacket(
+                    "falta el largo de un topic del subscribe".to_string(),
+                )
+                .into());
+            }
             let elem_string_len = u16::from_be_bytes([msg_bytes[idx], msg_bytes[idx + size_of_u8]]); // forma 2
             idx += size_of_u16;
+            if idx + elem_string_len as usize + size_of_u8 > msg_bytes.len() {
+                return Err(MqttError::MalformedPacket(
+                    "el largo de un topic excede el subscribe".to_string(),
+                )
+                .into());
+            }
             // Leo la string, de tam "elem_string_len"
-            let string_leida =
-                from_utf8(&msg_bytes[idx..idx + (elem_string_len as usize)]).unwrap();
+            let string_leida = from_utf8(&msg_bytes[idx..idx + (elem_string_len as usize)])
+                .map_err(|_| MqttError::MalformedPacket("el topic no es UTF-8 válido".to_string()))?;
             idx += elem_string_len as usize;
             // Leo el u8
             let elem_qos = (&msg_bytes[idx..idx + size_of_u8])[0];
@@ -113,7 +132,7 @@ impl SubscribeMessage {
             let elemento = (String::from(string_leida), elem_qos);
             topics.push(elemento);
             // Avanzo la rem_len_leida para saber cuándo termino de leer todos los elementos
-            rem_len_leida += 2 + elem_string_len as u8 + 1;
+            rem_len_leida += 2 + elem_string_len as usize + 1;
         }
 
         let struct_interpretado = SubscribeMessage {
@@ -140,6 +159,7 @@ impl SubscribeMessage {
 }
 
 use crate::mqtt::messages::message::Message;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use super::packet_type::PacketType;
 //Trait Message
 impl Message for SubscribeMessage {
@@ -200,4 +220,22 @@ mod test {
         let msg_reconstruido = SubscribeMessage::from_bytes(bytes_msg);
         assert_eq!(msg_reconstruido.unwrap(), subscribe_msg);
     }
+
+    #[test]
+    fn test_4_un_subscribe_truncado_o_con_largos_invalidos_es_malformado() {
+        let bytes = SubscribeMessage::new(1, vec![(String::from("topic1"), 1)]).to_bytes();
+
+        // El largo del topic excede el subscribe.
+        let mut bogus_topic_len = bytes.clone();
+        bogus_topic_len[4] = 0xFF;
+        bogus_topic_len[5] = 0xFF;
+        // La remaining length indica más bytes de los recibidos.
+        let mut bogus_rem_len = bytes.clone();
+        bogus_rem_len[1] = 0xFF;
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        for malformed in [bogus_topic_len, bogus_rem_len, truncated, vec![0x82, 0]] {
+            let err = SubscribeMessage::from_bytes(malformed).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
 }
@@ -23,4 +23,14 @@ impl SubscribeReturnCode {
             )),
         }
     }
+
+    /// Devuelve el código de retorno que otorga el qos `qos` a una suscripción, o Failure si no es un qos válido.
+    pub fn for_granted_qos(qos: u8) -> SubscribeReturnCode {
+        match qos {
+            0 => SubscribeReturnCode::QoS0,
+            1 => SubscribeReturnCode::QoS1,
+            2 => SubscribeReturnCode::QoS2,
+            _ => SubscribeReturnCode::Failure,
+        }
+    }
 }
@@ -1,7 +1,10 @@
+use std::io::{Error, ErrorKind};
+
 use crate::mqtt::messages::{
     unsuback_fixed_header::FixedHeader, unsuback_variable_header::VariableHeader,
 };
 
+#[derive(Debug)]
 pub struct Unsuback {
     fixed_header: FixedHeader,
     variable_header: VariableHeader,
@@ -36,7 +39,13 @@ impl Unsuback {
         ]
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Unsuback {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Unsuback, Error> {
+        if bytes.len() < 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No hay suficientes bytes para un unsuback msg.",
+            ));
+        }
         let fixed_header = FixedHeader {
             message_type: bytes[0] >> 4,
             reserved: bytes[0] & 0b00001111,
@@ -48,10 +57,17 @@ impl Unsuback {
             packet_type_identifier_lsb: bytes[3],
         };
 
-        Unsuback {
+        Ok(Unsuback {
             fixed_header,
             variable_header,
-        }
+        })
+    }
+
+    pub fn get_packet_id(&self) -> u16 {
+        u16::from_be_bytes([
+            self.variable_header.packet_type_identifier_msb,
+            self.variable_header.packet_type_identifier_lsb,
+        ])
     }
 }
 
@@ -71,12 +87,13 @@ mod tests {
     #[test]
     fn test_from_bytes() {
         let bytes = vec![0b1011_0000, 0x02, 0x00, 0x01];
-        let unsuback = Unsuback::from_bytes(&bytes);
+        let unsuback = Unsuback::from_bytes(&bytes).unwrap();
 
         assert_eq!(unsuback.fixed_header.message_type, 0b1011);
         assert_eq!(unsuback.fixed_header.reserved, 0b0000);
         assert_eq!(unsuback.fixed_header.remaining_length, 2);
         assert_eq!(unsuback.variable_header.packet_type_identifier_msb, 0x00);
         assert_eq!(unsuback.variable_header.packet_type_identifier_lsb, 0x01);
+        assert_eq!(unsuback.get_packet_id(), 1);
     }
 }
@@ -1,4 +1,5 @@
 pub mod client;
+pub mod loopback;
 pub mod messages;
 pub mod mqtt_utils;
 pub mod server;
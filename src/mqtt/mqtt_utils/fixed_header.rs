@@ -29,6 +29,14 @@ impl FixedHeader {
         }
     }
 
+    /// Crea el fixed header a partir de los primeros dos bytes del mensaje `msg_bytes`.
+    pub fn from_msg_bytes(msg_bytes: &[u8]) -> Self {
+        Self {
+            message_type_byte: msg_bytes[0],
+            remaining_length: msg_bytes[1],
+        }
+    }
+
     pub fn get_message_type_byte(&self) -> u8 {
         self.message_type_byte >> 4
     }
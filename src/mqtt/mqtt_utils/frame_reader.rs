@@ -0,0 +1,240 @@
+use std::io::{Error, ErrorKind, Read};
+
+use crate::mqtt::mqtt_utils::fixed_header::FixedHeader;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+/// Capacidad por defecto del buffer de lectura. Alcanza para varios mensajes, ya que
+/// la remaining length ocupa un byte y un mensaje completo no supera los 257 bytes.
+const DEFAULT_BUFFER_CAPACITY: usize = 4096;
+
+/// Lector de mensajes mqtt completos desde un stream, con un buffer propio de la conexión que se reutiliza.
+/// Cada lectura del stream trae todos los bytes disponibles (posiblemente varios mensajes), y los mensajes
+/// se devuelven como slices del buffer, sin reservar memoria por mensaje.
+#[derive(Debug)]
+pub struct FrameReader {
+    buf: Vec<u8>,
+    start: usize,       // inicio de los bytes leídos que aún no se devolvieron.
+    end: usize,         // fin de los bytes leídos.
+    max_rem_len: usize, // remaining length máxima aceptada.
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_BUFFER_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let min_capacity = FixedHeader::fixed_header_len() + u8::MAX as usize;
+        FrameReader {
+            buf: vec![0; capacity.max(min_capacity)],
+            start: 0,
+            end: 0,
+            max_rem_len: u8::MAX as usize,
+        }
+    }
+
+    /// Devuelve el lector configurado para rechazar los mensajes con remaining length mayor a `max_rem_len`.
+    pub fn with_max_rem_len(mut self, max_rem_len: usize) -> Self {
+        self.max_rem_len = max_rem_len;
+        self
+    }
+
+    /// Devuelve el siguiente mensaje completo (fixed header incluido), leyendo del `stream` solamente si
+    /// los bytes ya leídos no alcanzan. Devuelve None si el stream se cerró entre dos mensajes,
+    /// y error si se cerró a mitad de un mensaje, si falla la lectura (ie vence el timeout),
+    /// o si el mensaje excede el tamaño máximo (sin esperar a leerlo completo).
+    /// Ante un error de lectura, los bytes ya leídos se conservan para la siguiente llamada.
+    pub fn read_frame<R: Read>(&mut self, stream: &mut R) -> Result<Option<&[u8]>, Error> {
+        let frame_len = loop {
+            self.check_rem_len()?;
+            if let Some(frame_len) = self.buffered_frame_len() {
+                break frame_len;
+            }
+            if self.fill(stream)? == 0 {
+                if self.start == self.end {
+                    return Ok(None);
+                }
+                return Err(MqttError::ConnectionClosed.into());
+            }
+        };
+
+        let frame_start = self.start;
+        self.start += frame_len;
+        Ok(Some(&self.buf[frame_start..frame_start + frame_len]))
+    }
+
+    /// Devuelve error si ya se leyó el fixed header del mensaje que comienza en `start`,
+    /// y su remaining length excede la máxima aceptada.
+    fn check_rem_len(&self) -> Result<(), Error> {
+        if self.end - self.start < FixedHeader::fixed_header_len() {
+            return Ok(());
+        }
+        let rem_len = self.buf[self.start + 1] as usize;
+        if rem_len > self.max_rem_len {
+            return Err(MqttError::PacketTooLarge(rem_len).into());
+        }
+        Ok(())
+    }
+
+    /// Devuelve la longitud del mensaje que comienza en `start`, si ya se leyó completo.
+    fn buffered_frame_len(&self) -> Option<usize> {
+        let available = self.end - self.start;
+        if available < FixedHeader::fixed_header_len() {
+            return None;
+        }
+        let rem_len = self.buf[self.start + 1] as usize;
+        let frame_len = FixedHeader::fixed_header_len() + rem_len;
+        (available >= frame_len).then_some(frame_len)
+    }
+
+    /// Lee del stream a continuación de los bytes pendientes, en una sola llamada a read.
+    /// Si no queda lugar al final del buffer, primero mueve los bytes pendientes al inicio.
+    /// Devuelve la cantidad de bytes leídos, 0 si se cerró el stream.
+    fn fill<R: Read>(&mut self, stream: &mut R) -> Result<usize, Error> {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = 0;
+        } else if self.end == self.buf.len() {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        loop {
+            match stream.read(&mut self.buf[self.end..]) {
+                Ok(n) => {
+                    self.end += n;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameReader;
+    use crate::mqtt::mqtt_utils::mqtt_error::is_packet_too_large;
+    use std::io::{Error, ErrorKind, Read};
+
+    /// Stream que devuelve los bytes de a `chunk_size` por llamada a read, y cuenta las llamadas.
+    struct ChunkedStream {
+        bytes: Vec<u8>,
+        pos: usize,
+        chunk_size: usize,
+        reads: usize,
+    }
+
+    impl ChunkedStream {
+        fn new(bytes: Vec<u8>, chunk_size: usize) -> Self {
+            ChunkedStream {
+                bytes,
+                pos: 0,
+                chunk_size,
+                reads: 0,
+            }
+        }
+    }
+
+    impl Read for ChunkedStream {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.reads += 1;
+            let n = self
+                .chunk_size
+                .min(buf.len())
+                .min(self.bytes.len() - self.pos);
+            buf[..n].copy_from_slice(&self.bytes[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    fn frame(packet_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![packet_type << 4, payload.len() as u8];
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_1_varios_mensajes_se_obtienen_de_una_sola_lectura() {
+        let mut bytes = frame(3, b"posicion dron 1");
+        bytes.extend(frame(4, &[0, 7]));
+        bytes.extend(frame(12, &[]));
+        let mut stream = ChunkedStream::new(bytes, 1024);
+        let mut reader = FrameReader::new();
+
+        assert_eq!(
+            reader.read_frame(&mut stream).unwrap().unwrap(),
+            frame(3, b"posicion dron 1").as_slice()
+        );
+        assert_eq!(
+            reader.read_frame(&mut stream).unwrap().unwrap(),
+            &[4 << 4, 2, 0, 7]
+        );
+        assert_eq!(
+            reader.read_frame(&mut stream).unwrap().unwrap(),
+            &[12 << 4, 0]
+        );
+        assert_eq!(stream.reads, 1);
+        assert!(reader.read_frame(&mut stream).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_2_un_mensaje_que_llega_en_partes_se_completa() {
+        let payload = [7u8; 200];
+        let mut stream = ChunkedStream::new(frame(3, &payload), 3);
+        let mut reader = FrameReader::new();
+
+        let read = reader.read_frame(&mut stream).unwrap().unwrap();
+
+        assert_eq!(read.len(), 202);
+        assert_eq!(&read[2..], &payload);
+    }
+
+    #[test]
+    fn test_3_los_mensajes_que_cruzan_el_final_del_buffer_se_compactan() {
+        let mut bytes = vec![];
+        for i in 0..10u8 {
+            bytes.extend(frame(3, &[i; 100]));
+        }
+        let mut stream = ChunkedStream::new(bytes, 1024);
+        let mut reader = FrameReader::with_capacity(300);
+
+        for i in 0..10u8 {
+            let read = reader.read_frame(&mut stream).unwrap().unwrap();
+            assert_eq!(read, frame(3, &[i; 100]).as_slice());
+        }
+        assert!(reader.read_frame(&mut stream).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_4_si_se_cierra_a_mitad_de_un_mensaje_devuelve_error() {
+        let mut bytes = frame(3, b"incidente");
+        bytes.truncate(5);
+        let mut stream = ChunkedStream::new(bytes, 1024);
+        let mut reader = FrameReader::new();
+
+        let err = reader.read_frame(&mut stream).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_5_un_mensaje_que_excede_el_maximo_se_rechaza_sin_leerlo_completo() {
+        let mut bytes = frame(3, &[0; 10]);
+        bytes.extend(frame(3, &[1; 200]));
+        let mut stream = ChunkedStream::new(bytes, 14);
+        let mut reader = FrameReader::new().with_max_rem_len(100);
+
+        assert_eq!(reader.read_frame(&mut stream).unwrap().unwrap().len(), 12);
+        let err = reader.read_frame(&mut stream).unwrap_err();
+        assert!(is_packet_too_large(&err));
+        assert_eq!(stream.reads, 1);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod utils;
 pub mod broker_errors;
 pub mod fixed_header;
+pub mod frame_reader;
+pub mod mqtt_error;
+pub mod packet_observer;
+pub mod topic_aliases;
 pub mod will_message_utils;
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::io::{Error, ErrorKind};
+
+use crate::mqtt::messages::connect_return_code::ConnectReturnCode;
+
+/// Errores del módulo mqtt, tanto del cliente como del server.
+/// Las funciones devuelven `std::io::Error`, pero construido a partir de un `MqttError`,
+/// que puede recuperarse con `MqttError::from_io_error` para distinguir el motivo del error.
+#[derive(Debug, thiserror::Error)]
+pub enum MqttError {
+    #[error("No hay conexión con el otro extremo.")]
+    NotConnected,
+    #[error("Se cerró la conexión a mitad de un mensaje.")]
+    ConnectionClosed,
+    #[error("Conexión rechazada por el server: {0:?}.")]
+    ConnectionRefused(ConnectReturnCode),
+    #[error("Paquete mal formado: {0}.")]
+    MalformedPacket(String),
+    #[error("Paquete de {0} bytes, excede el tamaño máximo aceptado.")]
+    PacketTooLarge(usize),
+    #[error("Payload de {0} bytes, excede los {1} bytes que entran en un publish por su topic.")]
+    PayloadTooLarge(usize, usize),
+    #[error("MAXRETRIES, se retransmitió sin éxito el {0}.")]
+    AckTimeout(String),
+    #[error("Cola llena: {0}.")]
+    QueueFull(String),
+    #[error("No hay packet ids libres, todos esperan su ack.")]
+    PacketIdsExhausted,
+    #[error("Error al tomar lock de {0}.")]
+    LockPoisoned(String),
+    #[error(transparent)]
+    Io(#[from] Error),
+}
+
+impl MqttError {
+    /// Devuelve el `MqttError` a partir del cual se construyó el io::Error `error`, si lo hay.
+    pub fn from_io_error(error: &Error) -> Option<&MqttError> {
+        error.get_ref()?.downcast_ref::<MqttError>()
+    }
+
+    /// Devuelve el `ErrorKind` de io con el que se convierte este error.
+    fn io_error_kind(&self) -> ErrorKind {
+        match self {
+            MqttError::NotConnected => ErrorKind::NotConnected,
+            MqttError::ConnectionClosed => ErrorKind::UnexpectedEof,
+            MqttError::ConnectionRefused(_) => ErrorKind::ConnectionRefused,
+            MqttError::MalformedPacket(_) | MqttError::PacketTooLarge(_) => ErrorKind::InvalidData,
+            MqttError::PayloadTooLarge(_, _) => ErrorKind::InvalidInput,
+            MqttError::AckTimeout(_) => ErrorKind::TimedOut,
+            MqttError::QueueFull(_) | MqttError::PacketIdsExhausted => ErrorKind::WouldBlock,
+            MqttError::LockPoisoned(_) => ErrorKind::Other,
+            MqttError::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl From<MqttError> for Error {
+    fn from(error: MqttError) -> Self {
+        match error {
+            MqttError::Io(e) => e,
+            _ => Error::new(error.io_error_kind(), error),
+        }
+    }
+}
+
+/// Devuelve si el io::Error `error` se debe a que no hay conexión.
+pub fn is_not_connected(error: &Error) -> bool {
+    matches!(
+        MqttError::from_io_error(error),
+        Some(MqttError::NotConnected)
+    )
+}
+
+/// Devuelve si el io::Error `error` se debe a un paquete que excede el tamaño máximo aceptado.
+pub fn is_packet_too_large(error: &Error) -> bool {
+    matches!(
+        MqttError::from_io_error(error),
+        Some(MqttError::PacketTooLarge(_))
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_not_connected, MqttError};
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn test_1_el_mqtt_error_se_recupera_del_io_error() {
+        let error: Error = MqttError::AckTimeout("publish 3".to_string()).into();
+
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(matches!(
+            MqttError::from_io_error(&error),
+            Some(MqttError::AckTimeout(_))
+        ));
+        assert!(!is_not_connected(&error));
+    }
+
+    #[test]
+    fn test_2_se_distingue_no_conectado_de_paquete_mal_formado() {
+        let not_connected: Error = MqttError::NotConnected.into();
+        let malformed: Error = MqttError::MalformedPacket("topic vacío".to_string()).into();
+
+        assert!(is_not_connected(&not_connected));
+        assert!(!is_not_connected(&malformed));
+        assert_eq!(malformed.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_3_un_io_error_sin_mqtt_error_se_conserva() {
+        let io_error = Error::new(ErrorKind::BrokenPipe, "se cerró el stream");
+        let error: Error = MqttError::from(io_error).into();
+
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+        assert!(MqttError::from_io_error(&error).is_none());
+    }
+}
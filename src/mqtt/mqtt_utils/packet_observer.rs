@@ -0,0 +1,166 @@
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use crate::mqtt::messages::packet_type::PacketType;
+
+/// Sentido de un paquete, visto desde quien lo observa: el server o el cliente.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketDirection {
+    Inbound,  // recibido por la conexión.
+    Outbound, // escrito por la conexión.
+}
+
+/// Paquete recibido o escrito por una conexión, tal como viaja por ella.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketEvent<'a> {
+    pub direction: PacketDirection,
+    pub packet_type: PacketType,
+    pub bytes: &'a [u8],
+    pub client_id: Option<&'a str>, // el del cliente de la conexión, si ya se conoce (ie en el server, luego del connect).
+    pub timestamp: SystemTime,      // cuándo se terminó de leer o se escribió.
+}
+
+/// Observa los paquetes de cada conexión del server o del cliente en que se registra, ie para depurar lo que
+/// viaja por la red o medir latencias. Se llama desde los hilos que leen y escriben, por lo que debe ser rápido.
+pub trait PacketObserver: Send + Sync {
+    fn on_packet(&self, event: &PacketEvent);
+}
+
+/// Observers registrados en un server o cliente, compartidos por todas sus conexiones.
+/// Pueden agregarse mientras el server o cliente corre.
+#[derive(Clone, Default)]
+pub struct PacketObservers {
+    observers: Arc<RwLock<Vec<Arc<dyn PacketObserver>>>>,
+    client_id: Option<String>, // el del cliente de la conexión, se informa en cada paquete.
+}
+
+impl PacketObservers {
+    /// Devuelve una copia que comparte los observers, e informa a `client_id` como cliente de la conexión.
+    pub fn with_client_id(&self, client_id: &str) -> Self {
+        PacketObservers {
+            observers: self.observers.clone(),
+            client_id: Some(client_id.to_string()),
+        }
+    }
+
+    pub fn add(&self, observer: Arc<dyn PacketObserver>) {
+        if let Ok(mut observers) = self.observers.write() {
+            observers.push(observer);
+        }
+    }
+
+    /// Informa a los observers el paquete `bytes`, que la conexión recibió o escribió según `direction`.
+    /// Si no hay observers registrados, no hace nada.
+    pub fn notify(&self, direction: PacketDirection, bytes: &[u8]) {
+        let Ok(observers) = self.observers.read() else {
+            return;
+        };
+        let Some(first_byte) = bytes.first() else {
+            return;
+        };
+        if observers.is_empty() {
+            return;
+        }
+        let event = PacketEvent {
+            direction,
+            packet_type: PacketType::from(first_byte >> 4),
+            bytes,
+            client_id: self.client_id.as_deref(),
+            timestamp: SystemTime::now(),
+        };
+        for observer in observers.iter() {
+            observer.on_packet(&event);
+        }
+    }
+}
+
+impl fmt::Debug for PacketObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = match self.observers.read() {
+            Ok(observers) => observers.len(),
+            Err(_) => 0,
+        };
+        f.debug_struct("PacketObservers")
+            .field("count", &count)
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+/// Son iguales si comparten los observers, para el mismo cliente.
+impl PartialEq for PacketObservers {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.observers, &other.observers) && self.client_id == other.client_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PacketDirection, PacketEvent, PacketObserver, PacketObservers};
+    use crate::mqtt::messages::packet_type::PacketType;
+    use std::sync::{Arc, Mutex};
+
+    /// Dirección, tipo, longitud y client id de un paquete observado.
+    type RecordedEvent = (PacketDirection, PacketType, usize, Option<String>);
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<RecordedEvent>>,
+    }
+
+    impl PacketObserver for RecordingObserver {
+        fn on_packet(&self, event: &PacketEvent) {
+            self.events.lock().unwrap().push((
+                event.direction,
+                event.packet_type,
+                event.bytes.len(),
+                event.client_id.map(|client_id| client_id.to_string()),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_1_los_observers_registrados_reciben_cada_paquete_con_su_tipo() {
+        let observers = PacketObservers::default();
+        let observer = Arc::new(RecordingObserver::default());
+        observers.add(observer.clone());
+
+        observers
+            .with_client_id("dron-1")
+            .notify(PacketDirection::Inbound, &[0xC0, 0]);
+        observers.notify(PacketDirection::Outbound, &[0xD0, 0]);
+        observers.notify(PacketDirection::Outbound, &[]);
+
+        let events = observer.events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                (
+                    PacketDirection::Inbound,
+                    PacketType::Pingreq,
+                    2,
+                    Some("dron-1".to_string())
+                ),
+                (PacketDirection::Outbound, PacketType::Pingresp, 2, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_2_las_copias_comparten_los_observers() {
+        let observers = PacketObservers::default();
+        let copy = observers.clone();
+        let observer = Arc::new(RecordingObserver::default());
+
+        copy.add(observer.clone());
+        observers.notify(PacketDirection::Inbound, &[0xE0, 0]);
+
+        assert_eq!(observer.events.lock().unwrap().len(), 1);
+        assert_eq!(observers, copy);
+        assert_ne!(observers, PacketObservers::default());
+        assert_ne!(observers, copy.with_client_id("dron-1"));
+    }
+}
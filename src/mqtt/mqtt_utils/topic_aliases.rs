@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::io::Error;
+
+use crate::mqtt::messages::{protocol_version::ProtocolVersion, publish_message::PublishMessage};
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+/// Topic aliases de mqtt 5 que asigna quien envía los publish de una conexión. El primer publish de cada topic
+/// lleva el topic y su alias, y los siguientes solamente el alias (2 bytes), ahorrando enviar el topic completo.
+/// Los aliases valen solamente para la conexión en la que se asignaron.
+#[derive(Debug, Default)]
+pub struct OutgoingTopicAliases {
+    maximum: u16, // cantidad de aliases que acepta el otro extremo, 0 si no los acepta.
+    aliases: HashMap<String, u16>,
+}
+
+impl OutgoingTopicAliases {
+    pub fn new(maximum: u16) -> Self {
+        OutgoingTopicAliases {
+            maximum,
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Devuelve el mensaje a enviar en lugar de `msg`: sin topic si éste ya tiene alias, con el topic y un alias
+    /// nuevo si todavía quedan aliases por asignar, o sin cambios si no (ie el mensaje es de mqtt 3.1.1,
+    /// o el otro extremo no acepta aliases).
+    pub fn apply(&mut self, msg: &PublishMessage) -> PublishMessage {
+        let Some(properties) = msg.get_properties() else {
+            return msg.clone();
+        };
+        let topic = msg.get_topic();
+        if let Some(&alias) = self.aliases.get(&topic) {
+            return msg
+                .clone()
+                .with_properties(properties.clone().with_topic_alias(alias))
+                .with_topic("");
+        }
+        if self.aliases.len() >= self.maximum as usize {
+            return msg.clone();
+        }
+        let alias = self.aliases.len() as u16 + 1;
+        self.aliases.insert(topic, alias);
+        msg.clone()
+            .with_properties(properties.clone().with_topic_alias(alias))
+    }
+}
+
+/// Topic aliases de mqtt 5 que asignó el otro extremo de una conexión a los topics de los publish que envía.
+#[derive(Debug, Default)]
+pub struct IncomingTopicAliases {
+    maximum: u16, // cantidad de aliases que se aceptan, 0 si no se aceptan.
+    topics: HashMap<u16, String>,
+}
+
+impl IncomingTopicAliases {
+    pub fn new(maximum: u16) -> Self {
+        IncomingTopicAliases {
+            maximum,
+            topics: HashMap::new(),
+        }
+    }
+
+    /// Devuelve si se aceptan topic aliases en la conexión.
+    pub fn is_enabled(&self) -> bool {
+        self.maximum > 0
+    }
+
+    /// Devuelve el mensaje `msg` con su topic completo y sin topic alias: si lleva topic, lo registra para su alias,
+    /// y si no, lo completa con el registrado. Devuelve error si el alias excede el máximo aceptado, o si
+    /// no tiene topic registrado, ya que el mensaje no puede interpretarse.
+    pub fn resolve(&mut self, msg: PublishMessage) -> Result<PublishMessage, Error> {
+        let (Some(properties), Some(alias)) =
+            (msg.get_properties().cloned(), msg.get_topic_alias())
+        else {
+            return Ok(msg);
+        };
+        if alias == 0 || alias > self.maximum {
+            return Err(MqttError::MalformedPacket(format!(
+                "topic alias {} fuera del máximo aceptado {}",
+                alias, self.maximum
+            ))
+            .into());
+        }
+        let msg = msg.with_properties(properties.without_topic_alias());
+        let topic = msg.get_topic();
+        if !topic.is_empty() {
+            self.topics.insert(alias, topic);
+            return Ok(msg);
+        }
+        match self.topics.get(&alias) {
+            Some(topic) => Ok(msg.with_topic(topic)),
+            None => Err(MqttError::MalformedPacket(format!(
+                "topic alias {} sin topic asignado",
+                alias
+            ))
+            .into()),
+        }
+    }
+
+    /// Como `resolve`, a partir de los bytes `msg_bytes` de un publish de mqtt 5.
+    /// Si el publish no lleva topic alias, devuelve sus bytes sin cambios.
+    pub fn resolve_bytes(&mut self, msg_bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let msg = PublishMessage::from_bytes_with_version(msg_bytes.clone(), ProtocolVersion::Mqtt5)?;
+        if msg.get_topic_alias().is_none() {
+            return Ok(msg_bytes);
+        }
+        Ok(self.resolve(msg)?.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IncomingTopicAliases, OutgoingTopicAliases};
+    use crate::mqtt::messages::{
+        properties::Properties, protocol_version::ProtocolVersion, publish_flags::PublishFlags,
+        publish_message::PublishMessage,
+    };
+
+    fn publish_msg(topic: &str, protocol_version: ProtocolVersion) -> PublishMessage {
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        PublishMessage::new(flags, topic, Some(1), b"posicion")
+            .unwrap()
+            .for_protocol_version(protocol_version)
+    }
+
+    #[test]
+    fn test_1_los_siguientes_publish_del_topic_se_envian_solamente_con_el_alias() {
+        let msg = publish_msg("drones/dron-1/posicion", ProtocolVersion::Mqtt5);
+        let mut outgoing = OutgoingTopicAliases::new(5);
+        let mut incoming = IncomingTopicAliases::new(5);
+
+        let first = outgoing.apply(&msg);
+        let second = outgoing.apply(&msg);
+
+        assert_eq!(first.get_topic(), "drones/dron-1/posicion");
+        assert_eq!(first.get_topic_alias(), Some(1));
+        assert_eq!(second.get_topic(), "");
+        assert_eq!(second.get_topic_alias(), Some(1));
+        assert!(second.to_bytes().len() < first.to_bytes().len());
+
+        // Quien los recibe los interpreta con el topic completo.
+        for sent in [first, second] {
+            let bytes = sent.to_bytes();
+            let received =
+                PublishMessage::from_bytes_with_version(bytes, ProtocolVersion::Mqtt5).unwrap();
+            let resolved = incoming.resolve(received).unwrap();
+            assert_eq!(resolved.get_topic(), "drones/dron-1/posicion");
+            assert_eq!(resolved.get_topic_alias(), None);
+            assert_eq!(resolved.get_payload(), b"posicion");
+        }
+    }
+
+    #[test]
+    fn test_2_sin_aliases_disponibles_o_en_mqtt_311_se_envia_el_topic() {
+        let mut outgoing = OutgoingTopicAliases::new(1);
+        outgoing.apply(&publish_msg("drones/dron-1", ProtocolVersion::Mqtt5));
+
+        let other_topic = outgoing.apply(&publish_msg("drones/dron-2", ProtocolVersion::Mqtt5));
+        let mqtt_311 = OutgoingTopicAliases::new(5)
+            .apply(&publish_msg("drones/dron-1", ProtocolVersion::Mqtt311));
+
+        assert_eq!(other_topic.get_topic(), "drones/dron-2");
+        assert_eq!(other_topic.get_topic_alias(), None);
+        assert_eq!(mqtt_311.get_topic(), "drones/dron-1");
+        assert_eq!(mqtt_311.get_properties(), None);
+    }
+
+    #[test]
+    fn test_3_un_alias_desconocido_o_fuera_del_maximo_es_un_error() {
+        let mut incoming = IncomingTopicAliases::new(2);
+        let with_alias = |topic: &str, alias: u16| {
+            publish_msg(topic, ProtocolVersion::Mqtt5)
+                .with_properties(Properties::default().with_topic_alias(alias))
+        };
+
+        assert!(incoming.resolve(with_alias("", 1)).is_err());
+        assert!(incoming.resolve(with_alias("drones/dron-1", 3)).is_err());
+        assert!(incoming.resolve(with_alias("drones/dron-1", 2)).is_ok());
+        assert_eq!(
+            incoming.resolve(with_alias("", 2)).unwrap().get_topic(),
+            "drones/dron-1"
+        );
+        assert!(!IncomingTopicAliases::new(0).is_enabled());
+    }
+}
@@ -1,13 +1,14 @@
 use std::{
     io::{Error, ErrorKind, Read, Write},
-    net::{Shutdown, TcpStream},
+    net::Shutdown,
+    thread,
+    time::Duration,
 };
 
-use crate::mqtt::messages::{
-    packet_type::PacketType, puback_message::PubAckMessage, publish_message::PublishMessage,
-};
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+use crate::mqtt::messages::packet_type::PacketType;
 use crate::mqtt::mqtt_utils::fixed_header::FixedHeader;
-type StreamType = TcpStream;
+use crate::mqtt::stream_type::StreamType;
 
 // Este archivo contiene funciones que utilizan para hacer read y write desde el stream
 // tanto el message_broker_server como el mqtt_client.
@@ -17,8 +18,20 @@ type StreamType = TcpStream;
 // Inicio funciones que manejan el stream, usadas tando por mqtt server como por client.
 /// Escribe el mensaje en bytes `msg_bytes` por el stream hacia el cliente.
 /// Puede devolver error si falla la escritura o el flush.
+/// Escribe todos los bytes aunque el stream sea no bloqueante (ie compartido con el transporte async del server):
+/// si el buffer de envío está lleno, reintenta hasta poder escribir.
 pub fn write_message_to_stream(msg_bytes: &[u8], stream: &mut StreamType) -> Result<(), Error> {
-    let _ = stream.write(msg_bytes)?;
+    let mut written = 0;
+    while written < msg_bytes.len() {
+        match stream.write(&msg_bytes[written..]) {
+            Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "No se pudo escribir en el stream.")),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(e),
+        }
+    }
     stream.flush()?;
 
     Ok(())
@@ -28,26 +41,19 @@ pub fn write_message_to_stream(msg_bytes: &[u8], stream: &mut StreamType) -> Res
 /// Determina el tipo del mensaje recibido que inicia por `fixed_header`.
 /// Devuelve el tipo, y por cuestiones de optimización (ahorrar conversiones)
 /// devuelve también fixed_header (el struct encabezado del mensaje) y fixed_header_buf (sus bytes).
+/// Devuelve None si el stream se cerró antes del mensaje, y error `ConnectionClosed` si se cerró a mitad del fixed header.
 pub fn get_fixed_header_from_stream(
     stream: &mut StreamType,
 ) -> Result<Option<([u8; 2], FixedHeader)>, Error> {
     const FIXED_HEADER_LEN: usize = FixedHeader::fixed_header_len();
-    let res: Result<Vec<u8>, Error> = stream.bytes().take(FIXED_HEADER_LEN).collect();
-    match res {
-        Ok(b) if b.len() == 2 => {
-            // He leído bytes de un fixed_header, tengo que ver de qué tipo es.
-            let fixed_header = FixedHeader::from_bytes(b.to_vec());
-            let fixed_header_buf = [b[0], b[1]];
-
-            //println!("DEVOLVIENDO FIXED HEADER");
-            Ok(Some((fixed_header_buf, fixed_header)))
-        }
-        Err(e) => Err(e),
-        _ => {
-            //println!("READ NUEVO: Fixed header rama None, vale: {:?}");
-            Ok(None)
-        }
+    let mut fixed_header_buf: [u8; 2] = [0; FIXED_HEADER_LEN];
+    if !read_exact_or_eof(stream, &mut fixed_header_buf)? {
+        return Ok(None);
     }
+    // He leído bytes de un fixed_header, tengo que ver de qué tipo es.
+    let fixed_header = FixedHeader::from_msg_bytes(&fixed_header_buf);
+
+    Ok(Some((fixed_header_buf, fixed_header)))
 }
 
 /// Una vez leídos los dos bytes del fixed header de un mensaje desde el stream,
@@ -61,32 +67,31 @@ pub fn get_whole_message_in_bytes_from_stream(
 ) -> Result<Vec<u8>, Error> {
     // Siendo que ya hemos leído fixed_header, sabemos que el resto del mensaje está disponible para ser leído.
     let msg_rem_len: usize = fixed_header.get_rem_len();
-    let rem_buf: Result<Vec<u8>, Error> = stream.bytes().take(msg_rem_len).collect();
-    //println!("obteniendo mensaje completo");
-    match rem_buf {
-        Ok(b) if b.len() == msg_rem_len => {
-            let mut buf = fixed_header_bytes.to_vec();
-            buf.extend(b);
-
-            Ok(buf)
-        }
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            "Se leyó menos de lo esperado",
-        )), // caso None o Err.
+    let mut buf = vec![0; fixed_header_bytes.len() + msg_rem_len];
+    buf[..fixed_header_bytes.len()].copy_from_slice(fixed_header_bytes);
+    // Si se cierra acá, es a mitad del mensaje (ya se leyó su fixed header).
+    if !read_exact_or_eof(stream, &mut buf[fixed_header_bytes.len()..])? && msg_rem_len > 0 {
+        return Err(MqttError::ConnectionClosed.into());
     }
+
+    Ok(buf)
 }
 
-/// Envía un mensaje de tipo PubAck por el stream.
-pub fn send_puback(msg: &PublishMessage, stream: &mut TcpStream) -> Result<(), Error> {
-    if let Some(packet_id) = msg.get_packet_id() {
-        let ack = PubAckMessage::new(packet_id, 0);
-        let ack_msg_bytes = ack.to_bytes();
-        write_message_to_stream(&ack_msg_bytes, stream)?;
-        println!("   tipo publish: Enviado el ack: {:?}", ack);
+/// Lee del `stream` exactamente `buf.len()` bytes, aunque lleguen en varias lecturas o alguna sea interrumpida.
+/// Devuelve false si el stream se cerró antes de leer ningún byte,
+/// y error `ConnectionClosed` si se cerró habiendo leído solo una parte.
+pub fn read_exact_or_eof<R: Read>(stream: &mut R, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match stream.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(MqttError::ConnectionClosed.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
     }
-
-    Ok(())
+    Ok(true)
 }
 
 /// Devuelve si el fixed header correspondía o no al tipo de DisconnectMessage.
@@ -94,6 +99,21 @@ pub fn is_disconnect_msg(fixed_header: &FixedHeader) -> bool {
     fixed_header.get_message_type() == PacketType::Disconnect
 }
 
+/// Devuelve si el fixed header corresponde a un mensaje que el cliente no puede enviar luego del connect:
+/// un segundo connect, uno que solamente envía el server, o uno de tipo reservado.
+/// Por protocolo, el server debe cerrar la conexión al recibirlo.
+pub fn is_unexpected_client_msg(fixed_header: &FixedHeader) -> bool {
+    matches!(
+        fixed_header.get_message_type(),
+        PacketType::Connect
+            | PacketType::Connack
+            | PacketType::Suback
+            | PacketType::Unsuback
+            | PacketType::Pingresp
+            | PacketType::Reserved
+    )
+}
+
 /// Cerramos la conexión por el stream recibido.
 pub fn shutdown(stream: &StreamType) {
     match stream.shutdown(Shutdown::Both) {
@@ -106,17 +126,62 @@ pub fn shutdown(stream: &StreamType) {
 /// Determina el tipo del mensaje recibido que inicia por `fixed_header`.
 /// Devuelve el tipo, y por cuestiones de optimización (ahorrar conversiones)
 /// devuelve también fixed_header (el struct encabezado del mensaje) y fixed_header_buf (sus bytes).
+/// Devuelve error `ConnectionClosed` si el stream se cierra antes de completar el fixed header.
 pub fn get_fixed_header_from_stream_for_conn(
     stream: &mut StreamType,
 ) -> Result<([u8; 2], FixedHeader), Error> {
-    const FIXED_HEADER_LEN: usize = FixedHeader::fixed_header_len();
-    let mut fixed_header_buf: [u8; 2] = [0; FIXED_HEADER_LEN];
+    get_fixed_header_from_stream(stream)?.ok_or_else(|| MqttError::ConnectionClosed.into())
+}
+#[cfg(test)]
+mod test {
+    use super::read_exact_or_eof;
+    use std::io::{Error, ErrorKind, Read};
+
+    /// Stream que devuelve de a un byte por lectura, con una lectura interrumpida antes de cada byte.
+    struct InterruptedStream {
+        bytes: Vec<u8>,
+        pos: usize,
+        interrupt: bool,
+    }
 
-    // Leer
-    let _res = stream.read(&mut fixed_header_buf)?;
+    impl Read for InterruptedStream {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(Error::new(ErrorKind::Interrupted, "señal"));
+            }
+            if self.pos == self.bytes.len() {
+                return Ok(0);
+            }
+            buf[0] = self.bytes[self.pos];
+            self.pos += 1;
+            Ok(1)
+        }
+    }
 
-    // He leído bytes de un fixed_header, tengo que ver de qué tipo es.
-    let fixed_header = FixedHeader::from_bytes(fixed_header_buf.to_vec());
+    fn create_stream(bytes: &[u8]) -> InterruptedStream {
+        InterruptedStream {
+            bytes: bytes.to_vec(),
+            pos: 0,
+            interrupt: false,
+        }
+    }
+
+    #[test]
+    fn test_1_se_completa_la_lectura_aunque_llegue_en_partes_e_interrumpida() {
+        let mut stream = create_stream(&[0x30, 2, 7, 9]);
+        let mut buf = [0; 4];
+
+        assert!(read_exact_or_eof(&mut stream, &mut buf).unwrap());
+        assert_eq!(buf, [0x30, 2, 7, 9]);
+    }
 
-    Ok((fixed_header_buf, fixed_header))
-}
\ No newline at end of file
+    #[test]
+    fn test_2_se_distingue_el_cierre_entre_mensajes_del_cierre_a_mitad_de_mensaje() {
+        let mut buf = [0; 2];
+        assert!(!read_exact_or_eof(&mut create_stream(&[]), &mut buf).unwrap());
+
+        let err = read_exact_or_eof(&mut create_stream(&[0x30]), &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -2,7 +2,7 @@
 /// Contiene la información relacionada al will_message extraída del ConnectMessage.
 /// Se almacena en un User del MQTTServer, y es necesaria para posteriormente construir el PublishMessage
 /// a enviar a los suscriptores del will_topic.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WillMessageData {
     will_message_content: String,
     will_topic: String,
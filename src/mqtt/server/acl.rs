@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use super::{credentials_store::matches_pattern, file_helper::read_lines};
+
+/// Acción sobre un topic que puede restringirse mediante la lista de control de acceso.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TopicAction {
+    Publish,
+    Subscribe,
+}
+
+impl TopicAction {
+    fn action_from_str(action: &str) -> Result<Self, Error> {
+        match action {
+            "publish" => Ok(TopicAction::Publish),
+            "subscribe" => Ok(TopicAction::Subscribe),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Acción inválida en la lista de control de acceso: {:?}", action),
+            )),
+        }
+    }
+}
+
+/// Lista de control de acceso del servidor: para cada usuario autenticado, los topics en los que puede
+/// publicar y a los que puede suscribirse. Se carga desde un archivo con líneas de la forma:
+/// `usuario publish|subscribe topic_1,topic_2`, con '*' como comodín.
+/// Un usuario sin reglas para una acción (o un cliente invitado, sin usuario) no tiene restricciones para la misma.
+#[derive(Debug, Default)]
+pub struct AccessControlList {
+    rules: HashMap<(String, TopicAction), Vec<String>>, // (usuario, acción) -> patrones de topics permitidos.
+}
+
+impl AccessControlList {
+    /// Lee el archivo `file_path` y devuelve la lista de control de acceso.
+    /// Devuelve error si el archivo no puede abrirse o alguna línea tiene un formato inválido.
+    pub fn from_file(file_path: &str) -> Result<Self, Error> {
+        let lines = read_lines(Path::new(file_path))?;
+        let mut acl = AccessControlList::default();
+        for line in lines.map_while(Result::ok) {
+            acl.add_line(&line)?;
+        }
+        Ok(acl)
+    }
+
+    /// Parsea una línea del archivo y agrega la regla correspondiente.
+    fn add_line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Línea de control de acceso inválida: {:?}", line),
+            ));
+        }
+        let action = TopicAction::action_from_str(parts[1])?;
+        let topics = parts[2].split(',').map(|s| s.to_string());
+        self.rules
+            .entry((parts[0].to_string(), action))
+            .or_default()
+            .extend(topics);
+        Ok(())
+    }
+
+    /// Devuelve si el usuario `username` puede realizar la acción `action` sobre el topic `topic`.
+    pub fn is_allowed(&self, username: Option<&String>, action: TopicAction, topic: &str) -> bool {
+        let Some(username) = username else {
+            return true;
+        };
+        match self.rules.get(&(username.to_string(), action)) {
+            Some(allowed_topics) => allowed_topics
+                .iter()
+                .any(|pattern| matches_pattern(pattern, topic)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AccessControlList, TopicAction};
+
+    fn create_acl() -> AccessControlList {
+        let mut acl = AccessControlList::default();
+        acl.add_line("usuario1 publish dron").unwrap();
+        acl.add_line("usuario1 subscribe inc,dron").unwrap();
+        acl.add_line("usuario2 publish cam,inc").unwrap();
+        acl
+    }
+
+    #[test]
+    fn test_1_usuario_con_reglas_solo_puede_usar_los_topics_permitidos() {
+        let acl = create_acl();
+        let usuario1 = "usuario1".to_string();
+
+        assert!(acl.is_allowed(Some(&usuario1), TopicAction::Publish, "dron"));
+        assert!(!acl.is_allowed(Some(&usuario1), TopicAction::Publish, "cam"));
+        assert!(acl.is_allowed(Some(&usuario1), TopicAction::Subscribe, "inc"));
+        assert!(!acl.is_allowed(Some(&usuario1), TopicAction::Subscribe, "desc"));
+    }
+
+    #[test]
+    fn test_2_sin_reglas_para_la_accion_no_hay_restricciones() {
+        let acl = create_acl();
+        let usuario2 = "usuario2".to_string();
+        let usuario9 = "usuario9".to_string();
+
+        assert!(acl.is_allowed(Some(&usuario2), TopicAction::Subscribe, "dron"));
+        assert!(acl.is_allowed(Some(&usuario9), TopicAction::Publish, "dron"));
+        assert!(acl.is_allowed(None, TopicAction::Publish, "dron"));
+    }
+
+    #[test]
+    fn test_3_linea_con_accion_invalida_da_error() {
+        let mut acl = AccessControlList::default();
+        assert!(acl.add_line("usuario1 borrar dron").is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Error, ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+use super::mqtt_server::MQTTServer;
+
+const HELP: &str = "clients: lista los clientes y su estado
+topics: lista los topics, con sus suscriptores y mensajes almacenados
+metrics: lista los publish, entregas, bytes y latencias de entrega de cada topic
+kick <client_id>: cierra la conexión del cliente
+queued <client_id>: lista los publish del cliente sin confirmar y encolados
+reload: vuelve a leer la configuración y la lista de control de acceso
+save: guarda el estado del servidor en el state dir
+help: muestra esta ayuda";
+
+/// Comando de la interfaz de administración del servidor, que permite a los operadores
+/// inspeccionar el estado del broker sin reiniciarlo.
+#[derive(Debug, PartialEq)]
+pub enum AdminCommand {
+    Clients,
+    Topics,
+    Metrics,
+    Kick(String),
+    Queued(String),
+    Reload,
+    Save,
+    Help,
+}
+
+impl AdminCommand {
+    /// Parsea una línea recibida por la interfaz de administración, de la forma `comando [client_id]`.
+    pub fn from_line(line: &str) -> Result<Self, Error> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["clients"] => Ok(AdminCommand::Clients),
+            ["topics"] => Ok(AdminCommand::Topics),
+            ["metrics"] => Ok(AdminCommand::Metrics),
+            ["kick", client_id] => Ok(AdminCommand::Kick(client_id.to_string())),
+            ["queued", client_id] => Ok(AdminCommand::Queued(client_id.to_string())),
+            ["reload"] => Ok(AdminCommand::Reload),
+            ["save"] => Ok(AdminCommand::Save),
+            ["help"] => Ok(AdminCommand::Help),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Comando inválido: {:?}, ver help.", line.trim()),
+            )),
+        }
+    }
+
+    /// Ejecuta el comando sobre `mqtt_server`, y devuelve las líneas de su respuesta.
+    pub fn execute(&self, mqtt_server: &MQTTServer) -> Result<Vec<String>, Error> {
+        match self {
+            AdminCommand::Clients => list_clients(mqtt_server),
+            AdminCommand::Topics => list_topics(mqtt_server),
+            AdminCommand::Metrics => Ok(list_topic_metrics(mqtt_server)),
+            AdminCommand::Kick(client_id) => {
+                if !mqtt_server.kick_client(client_id)? {
+                    return Err(not_connected(client_id));
+                }
+                Ok(vec![])
+            }
+            AdminCommand::Queued(client_id) => list_queued(mqtt_server, client_id),
+            AdminCommand::Reload => mqtt_server.reload_config().map(|_| vec![]),
+            AdminCommand::Save => {
+                if !mqtt_server.save_state()? {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        "El servidor no se inició con un state dir.",
+                    ));
+                }
+                Ok(vec![])
+            }
+            AdminCommand::Help => Ok(HELP.lines().map(|line| line.to_string()).collect()),
+        }
+    }
+}
+
+/// Escucha en `addr` las conexiones de los operadores, y atiende cada una en un hilo.
+/// Cada conexión envía un comando por línea, y recibe las líneas de la respuesta
+/// seguidas de una línea `ok`, o bien una línea `error: <motivo>`.
+pub fn spawn_admin(
+    addr: SocketAddr,
+    mqtt_server: MQTTServer,
+    logger: StringLogger,
+) -> Result<JoinHandle<()>, Error> {
+    let listener = TcpListener::bind(addr)?;
+    logger.info(format!(
+        "Interfaz de administración escuchando en {:?}.",
+        addr
+    ));
+
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let mqtt_server_c = mqtt_server.clone_ref();
+                    let logger_c = logger.clone_ref();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_admin_connection(stream, &mqtt_server_c, &logger_c) {
+                            logger_c
+                                .error(format!("Error en la conexión de administración: {:?}.", e));
+                        }
+                    });
+                }
+                Err(e) => logger.error(format!(
+                    "Error al aceptar conexión de administración: {:?}.",
+                    e
+                )),
+            }
+        }
+    }))
+}
+
+/// Atiende los comandos de una conexión de administración, hasta que el operador la cierre.
+fn handle_admin_connection(
+    stream: TcpStream,
+    mqtt_server: &MQTTServer,
+    logger: &StringLogger,
+) -> Result<(), Error> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        logger.info(format!("Comando de administración: {:?}.", line));
+        let response =
+            AdminCommand::from_line(&line).and_then(|command| command.execute(mqtt_server));
+        let mut response_text = String::new();
+        match response {
+            Ok(lines) => {
+                for response_line in lines {
+                    response_text.push_str(&response_line);
+                    response_text.push('\n');
+                }
+                response_text.push_str("ok\n");
+            }
+            Err(e) => response_text.push_str(&format!("error: {}\n", error_reason(&e))),
+        }
+        writer.write_all(response_text.as_bytes())?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Una línea por cliente, ordenados por client_id.
+fn list_clients(mqtt_server: &MQTTServer) -> Result<Vec<String>, Error> {
+    let connected_users = mqtt_server.get_connected_users();
+    let users = connected_users.lock().map_err(|_| users_lock_error())?;
+    let clients: BTreeMap<&String, String> = users
+        .iter()
+        .map(|(client_id, user)| {
+            let peer_addr = user
+                .get_peer_addr()
+                .map_or("-".to_string(), |addr| addr.to_string());
+            let line = format!(
+                "{} estado={:?} addr={} version={:?} topics={}",
+                client_id,
+                user.get_state(),
+                peer_addr,
+                user.get_protocol_version(),
+                user.get_topics().join(",")
+            );
+            (client_id, line)
+        })
+        .collect();
+    Ok(clients.into_values().collect())
+}
+
+/// Una línea por topic, con la cantidad de clientes suscriptos y de mensajes almacenados, ordenados por topic.
+fn list_topics(mqtt_server: &MQTTServer) -> Result<Vec<String>, Error> {
+    let mut topics: BTreeMap<String, (usize, usize)> = mqtt_server
+        .get_stored_messages_count_by_topic()
+        .into_iter()
+        .map(|(topic, stored)| (topic, (0, stored)))
+        .collect();
+
+    let connected_users = mqtt_server.get_connected_users();
+    let users = connected_users.lock().map_err(|_| users_lock_error())?;
+    for topic in users.values().flat_map(|user| user.get_topics()) {
+        topics.entry(topic.to_string()).or_default().0 += 1;
+    }
+
+    Ok(topics
+        .into_iter()
+        .map(|(topic, (subscribers, stored))| {
+            format!("{} suscriptores={} mensajes={}", topic, subscribers, stored)
+        })
+        .collect())
+}
+
+/// Una línea por topic con sus métricas, ordenados por topic. Las latencias, en milisegundos, son `-` si todavía
+/// no hubo entregas.
+fn list_topic_metrics(mqtt_server: &MQTTServer) -> Vec<String> {
+    let latency_ms = |latency: Option<Duration>| latency.map_or("-".to_string(), |l| l.as_millis().to_string());
+    mqtt_server
+        .get_topic_metrics()
+        .into_iter()
+        .map(|(topic, summary)| {
+            format!(
+                "{} publicados={} bytes_recibidos={} entregas={} bytes_entregados={} latencia_p50_ms={} latencia_p99_ms={}",
+                topic,
+                summary.publishes,
+                summary.received_bytes,
+                summary.deliveries,
+                summary.delivered_bytes,
+                latency_ms(summary.latency_p50),
+                latency_ms(summary.latency_p99)
+            )
+        })
+        .collect()
+}
+
+/// Los packet ids de los publish enviados al cliente sin su puback, y una línea por cada publish encolado.
+fn list_queued(mqtt_server: &MQTTServer, client_id: &str) -> Result<Vec<String>, Error> {
+    let connected_users = mqtt_server.get_connected_users();
+    let users = connected_users.lock().map_err(|_| users_lock_error())?;
+    let user = users
+        .get(client_id)
+        .ok_or_else(|| not_connected(client_id))?;
+
+    let mut lines = vec![format!(
+        "sin_confirmar={:?} encolados={}",
+        user.get_in_flight_packet_ids(),
+        user.get_pending_publishes().len()
+    )];
+    for msg in user.get_pending_publishes() {
+        lines.push(format!(
+            "{} qos={} packet_id={:?}",
+            msg.get_topic(),
+            msg.get_qos(),
+            msg.get_packet_id()
+        ));
+    }
+    Ok(lines)
+}
+
+/// Devuelve el motivo del error para la respuesta: el mensaje del `MqttError` si lo hay, o el del io::Error.
+fn error_reason(error: &Error) -> String {
+    match MqttError::from_io_error(error) {
+        Some(mqtt_error) => mqtt_error.to_string(),
+        None => error.to_string(),
+    }
+}
+
+fn not_connected(client_id: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("No hay un cliente {:?} en el servidor.", client_id),
+    )
+}
+
+fn users_lock_error() -> Error {
+    MqttError::LockPoisoned("los users conectados".to_string()).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{spawn_admin, AdminCommand};
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::messages::connect_message::ConnectMessage;
+    use crate::mqtt::messages::subscribe_message::SubscribeMessage;
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use crate::mqtt::server::{mqtt_server::MQTTServer, server_config::ServerConfig};
+    use crate::mqtt::stream_type::StreamType;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    fn create_server() -> MQTTServer {
+        let (tx, _rx) = mpsc::channel::<String>();
+        MQTTServer::with_config(StringLogger::new(tx), ServerConfig::default())
+    }
+
+    /// Conecta al cliente `client_id` suscripto a `topics`, y devuelve el extremo del cliente de la conexión.
+    fn connect_client(server: &MQTTServer, client_id: &str, topics: &[&str]) -> StreamType {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client_stream = StreamType::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        let connect_msg = ConnectMessage::new(client_id.to_string(), None, None, None, 0);
+        server
+            .add_new_user(&server_stream.into(), client_id, &connect_msg)
+            .unwrap();
+        let topics = topics.iter().map(|topic| (topic.to_string(), 0)).collect();
+        server
+            .add_topics_to_subscriber(client_id, &SubscribeMessage::new(1, topics))
+            .unwrap();
+        client_stream
+    }
+
+    #[test]
+    fn test_1_se_parsean_los_comandos_y_se_rechazan_los_invalidos() {
+        assert_eq!(
+            AdminCommand::from_line("clients").unwrap(),
+            AdminCommand::Clients
+        );
+        assert_eq!(
+            AdminCommand::from_line(" kick  dron-1 ").unwrap(),
+            AdminCommand::Kick("dron-1".to_string())
+        );
+        assert!(AdminCommand::from_line("kick").is_err());
+        assert!(AdminCommand::from_line("shutdown").is_err());
+    }
+
+    #[test]
+    fn test_2_se_listan_los_clientes_y_los_topics_con_sus_suscriptores() {
+        let server = create_server();
+        let _dron = connect_client(&server, "dron-1", &["inc"]);
+        let _monitoreo = connect_client(&server, "monitoreo", &["inc", "dron"]);
+
+        let clients = AdminCommand::Clients.execute(&server).unwrap();
+        assert_eq!(clients.len(), 2);
+        assert!(clients[0].starts_with("dron-1 estado=Active"));
+        assert!(clients[1].ends_with("topics=inc,dron"));
+
+        let topics = AdminCommand::Topics.execute(&server).unwrap();
+        assert_eq!(
+            topics,
+            vec![
+                "dron suscriptores=1 mensajes=0".to_string(),
+                "inc suscriptores=2 mensajes=0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_3_kick_cierra_la_conexion_del_cliente() {
+        let server = create_server();
+        let mut dron = connect_client(&server, "dron-1", &[]);
+
+        AdminCommand::Kick("dron-1".to_string())
+            .execute(&server)
+            .unwrap();
+
+        dron.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(dron.read(&mut [0u8; 1]).unwrap(), 0);
+        assert!(AdminCommand::Kick("camaras".to_string())
+            .execute(&server)
+            .is_err());
+    }
+
+    #[test]
+    fn test_4_los_comandos_se_atienden_por_tcp() {
+        let server = create_server();
+        let _dron = connect_client(&server, "dron-1", &["inc"]);
+        let (tx, _rx) = mpsc::channel::<String>();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        spawn_admin(addr, server.clone_ref(), StringLogger::new(tx)).unwrap();
+
+        let mut admin_stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(admin_stream.try_clone().unwrap());
+        let mut read_line = || {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line
+        };
+
+        admin_stream.write_all(b"queued dron-1\n").unwrap();
+        assert_eq!(read_line(), "sin_confirmar=[] encolados=0\n");
+        assert_eq!(read_line(), "ok\n");
+
+        admin_stream.write_all(b"queued camaras\n").unwrap();
+        assert!(read_line().starts_with("error: "));
+    }
+
+    #[test]
+    fn test_5_se_listan_las_metricas_de_cada_topic() {
+        let server = create_server();
+        let _dron = connect_client(&server, "dron-1", &["inc"]);
+        let _monitoreo = connect_client(&server, "monitoreo", &["inc"]);
+        let flags = PublishFlags::new(0, 0, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", None, "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+
+        let metrics = AdminCommand::from_line("metrics")
+            .unwrap()
+            .execute(&server)
+            .unwrap();
+
+        assert_eq!(metrics.len(), 1);
+        let bytes = msg.to_bytes().len();
+        assert!(metrics[0].starts_with(&format!(
+            "inc publicados=1 bytes_recibidos={} entregas=2 bytes_entregados={} latencia_p50_ms=",
+            bytes,
+            2 * bytes
+        )));
+    }
+}
@@ -0,0 +1,506 @@
+use std::{
+    future::Future,
+    io::{Error, ErrorKind},
+    net::{SocketAddr, TcpListener},
+    sync::mpsc::{SendError, SyncSender, TrySendError},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::AsyncReadExt,
+    net::TcpStream,
+    runtime::Builder,
+    task::block_in_place,
+    time::{sleep, timeout},
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::messages::{
+    connect_message::ConnectMessage, connect_return_code::ConnectReturnCode,
+    packet_type::PacketType,
+};
+use crate::mqtt::mqtt_utils::{
+    fixed_header::FixedHeader,
+    mqtt_error::MqttError,
+    packet_observer::{PacketDirection, PacketObservers},
+    topic_aliases::IncomingTopicAliases,
+    utils::{is_disconnect_msg, is_unexpected_client_msg, shutdown},
+};
+use crate::mqtt::stream_type::StreamType;
+
+use super::{
+    client_authenticator::AuthenticateClient,
+    connection_limits::PublishRateLimiter,
+    disconnect_reason::DisconnectReason,
+    log_context::{client_id_log_context, connection_log_context},
+    mqtt_server::MQTTServer,
+    packet::Packet,
+};
+
+/// Acepta las conexiones entrantes sobre un runtime de tokio, con una tarea (y no un hilo) por cliente.
+/// Los paquetes leídos se encolan en `packets_tx` para el message processor compartido, igual que con el transporte de hilos.
+pub fn handle_incoming_connections(
+    listener: TcpListener,
+    mqtt_server: MQTTServer,
+    packets_tx: SyncSender<Packet>,
+    logger: StringLogger,
+) -> Result<(), Error> {
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(accept_connections(
+        listener,
+        mqtt_server,
+        packets_tx,
+        logger,
+    ))
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    mqtt_server: MQTTServer,
+    packets_tx: SyncSender<Packet>,
+    logger: StringLogger,
+) -> Result<(), Error> {
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    logger.info("Servidor iniciado (tokio). Esperando conexiones.".to_string());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client_reader = AsyncClientReader::new(
+            stream,
+            mqtt_server.clone_ref(),
+            packets_tx.clone(),
+            logger.clone_ref(),
+        )?;
+        let logger_c = logger.clone_ref();
+        tokio::spawn(async move {
+            if let Err(e) = client_reader.handle_client().await {
+                logger_c.error(format!("Error en la tarea del cliente: {:?}.", e));
+            }
+        });
+    }
+}
+
+/// Versión async del ClientReader: lee los mensajes de un cliente sin ocupar un hilo mientras espera.
+/// Las escrituras hacia el cliente (connack, publish, acks) las sigue haciendo el server por `sync_stream`,
+/// que es el mismo socket y por lo tanto no bloqueante.
+struct AsyncClientReader {
+    stream: TcpStream,
+    sync_stream: StreamType,
+    peer_addr: Option<SocketAddr>,
+    mqtt_server: MQTTServer,
+    packets_tx: SyncSender<Packet>,
+    publish_rate_limiter: PublishRateLimiter,
+    topic_aliases: IncomingTopicAliases, // asignados por el cliente en sus publish, valen mientras dure la conexión.
+    max_packet_size: usize, // remaining length máxima aceptada, se cierra la conexión si se excede.
+    packet_observers: PacketObservers, // los del servidor, reciben los paquetes leídos.
+    logger: StringLogger,   // con el contexto de la conexión, como en el ClientReader.
+}
+
+impl AsyncClientReader {
+    fn new(
+        stream: TcpStream,
+        mqtt_server: MQTTServer,
+        packets_tx: SyncSender<Packet>,
+        logger: StringLogger,
+    ) -> Result<Self, Error> {
+        let peer_addr = stream.peer_addr().ok();
+        let std_stream = stream.into_std()?;
+        let sync_stream = StreamType::from(std_stream.try_clone()?);
+        Ok(AsyncClientReader {
+            stream: TcpStream::from_std(std_stream)?,
+            sync_stream,
+            peer_addr,
+            publish_rate_limiter: mqtt_server.create_publish_rate_limiter(),
+            topic_aliases: IncomingTopicAliases::default(),
+            max_packet_size: mqtt_server.get_max_packet_size(),
+            packet_observers: mqtt_server.get_packet_observers(),
+            mqtt_server,
+            packets_tx,
+            logger: logger.with_context(&connection_log_context(peer_addr, None)),
+        })
+    }
+
+    /// Procesa el connect del cliente y, si es aceptado, sus mensajes hasta que se desconecta.
+    /// El connect debe llegar completo dentro del connect timeout configurado; si no, se cierra la conexión.
+    async fn handle_client(mut self) -> Result<(), Error> {
+        let connect_timeout = self.mqtt_server.get_connect_timeout();
+        let msg_bytes = match within(Some(connect_timeout), self.read_connect()).await {
+            Ok(Some(msg_bytes)) => msg_bytes,
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::TimedOut => {
+                self.logger.warn(format!(
+                    "No se recibió el connect en {:?}. Cerrando la conexión.",
+                    connect_timeout
+                ));
+                shutdown(&self.sync_stream);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        // Por protocolo, un connect mal formado se responde cerrando la conexión, sin connack.
+        let connect_msg = match ConnectMessage::from_bytes(&msg_bytes) {
+            Ok(connect_msg) => connect_msg,
+            Err(e) => {
+                self.logger.warn(format!(
+                    "Error, connect mal formado: {}. Cerrando la conexión.",
+                    e
+                ));
+                shutdown(&self.sync_stream);
+                return Ok(());
+            }
+        };
+
+        // La conexión cuenta para los límites del servidor mientras se atiende al cliente.
+        let authenticator = AuthenticateClient::new(self.logger.clone_ref());
+        let Some(_connection_slot) = self.mqtt_server.try_register_connection(self.peer_addr)?
+        else {
+            block_in_place(|| {
+                authenticator.refuse_connection(
+                    &connect_msg,
+                    &mut self.sync_stream,
+                    ConnectReturnCode::ServerUnavailable,
+                    &self.mqtt_server,
+                )
+            })?;
+            shutdown(&self.sync_stream);
+            return Ok(());
+        };
+
+        // La autenticación verifica hashes y escribe el connack, no debe frenar a las demás tareas del runtime.
+        let is_valid = block_in_place(|| {
+            authenticator.is_it_a_valid_connection(
+                &connect_msg,
+                &mut self.sync_stream,
+                &self.mqtt_server,
+            )
+        })?;
+
+        if let (true, Some(client_id)) = (is_valid, connect_msg.get_client_id()) {
+            self.logger = self.logger.with_context(&client_id_log_context(client_id));
+            self.packet_observers = self.packet_observers.with_client_id(client_id);
+            self.logger.info("Cliente conectado.".to_string());
+            // Se aceptan los topic aliases informados al cliente en el connack.
+            self.topic_aliases = IncomingTopicAliases::new(
+                self.mqtt_server
+                    .get_topic_alias_maximum(connect_msg.get_protocol_version()),
+            );
+            let disconnect_reason = self
+                .read_packets(client_id, connect_msg.get_keep_alive())
+                .await?;
+            block_in_place(|| {
+                self.mqtt_server.handle_client_disconnection(
+                    client_id,
+                    self.peer_addr,
+                    disconnect_reason,
+                )
+            });
+        }
+        Ok(())
+    }
+
+    /// Lee el connect completo, o devuelve None si el cliente cerró la conexión o envió otro mensaje
+    /// o uno demasiado grande, en cuyo caso cierra la conexión.
+    async fn read_connect(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some((fixed_header_buf, fixed_header)) = self.read_fixed_header().await? else {
+            return Ok(None);
+        };
+        if fixed_header.get_message_type() != PacketType::Connect {
+            self.logger.warn(format!(
+                "Error, primer msj recibido debe ser connect, se recibió: {:?}. Cerrando la conexión.",
+                fixed_header
+            ));
+            shutdown(&self.sync_stream);
+            return Ok(None);
+        }
+        if self.is_too_large(&fixed_header) {
+            return Ok(None);
+        }
+        let msg_bytes = self
+            .read_whole_message(&fixed_header, fixed_header_buf)
+            .await?;
+        self.packet_observers
+            .notify(PacketDirection::Inbound, &msg_bytes);
+        Ok(Some(msg_bytes))
+    }
+
+    /// Lee los paquetes del cliente y los encola para ser procesados, hasta que el cliente se desconecta
+    /// o pasan 1.5 veces su `keep_alive` sin recibir nada (un keep alive de 0 desactiva el mecanismo).
+    /// El plazo vale también para completar cada mensaje, así un cliente no lo extiende enviando los bytes de a poco.
+    async fn read_packets(
+        &mut self,
+        client_id: &str,
+        keep_alive: u16,
+    ) -> Result<DisconnectReason, Error> {
+        let keep_alive_timeout =
+            Some(Duration::from_millis(keep_alive as u64 * 1500)).filter(|_| keep_alive > 0);
+        loop {
+            match within(keep_alive_timeout, self.read_fixed_header()).await {
+                Ok(Some((fixed_header_buf, fixed_header))) if is_disconnect_msg(&fixed_header) => {
+                    self.packet_observers
+                        .notify(PacketDirection::Inbound, &fixed_header_buf);
+                    self.logger.info("Recibo disconnect.".to_string());
+                    shutdown(&self.sync_stream);
+                    return Ok(DisconnectReason::Voluntaria);
+                }
+                Ok(Some((_, fixed_header))) if is_unexpected_client_msg(&fixed_header) => {
+                    self.logger.warn(format!(
+                        "Error, mensaje inesperado: {:?}. Cerrando la conexión.",
+                        fixed_header
+                    ));
+                    shutdown(&self.sync_stream);
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Ok(Some((_, fixed_header))) if self.is_too_large(&fixed_header) => {
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Ok(Some((fixed_header_buf, fixed_header))) => {
+                    let read_res = within(
+                        keep_alive_timeout,
+                        self.read_whole_message(&fixed_header, fixed_header_buf),
+                    );
+                    let msg_bytes = match read_res.await {
+                        Ok(msg_bytes) => msg_bytes,
+                        Err(e) if e.kind() == ErrorKind::TimedOut => {
+                            return Ok(self.handle_keep_alive_expiration())
+                        }
+                        Err(e) => return Ok(self.handle_read_error(&e)),
+                    };
+                    self.packet_observers
+                        .notify(PacketDirection::Inbound, &msg_bytes);
+                    let message_type = fixed_header.get_message_type();
+                    self.logger.debug(format!("Recibido {:?}.", message_type));
+                    if message_type == PacketType::Publish {
+                        self.throttle_if_publish_rate_exceeded().await;
+                    }
+                    let msg_bytes = match self.resolve_topic_alias(message_type, msg_bytes) {
+                        Ok(msg_bytes) => msg_bytes,
+                        Err(e) => return Ok(self.handle_invalid_topic_alias(&e)),
+                    };
+                    self.enqueue(Packet::new(
+                        message_type,
+                        msg_bytes,
+                        client_id.to_string(),
+                        self.peer_addr,
+                    ));
+                }
+                Ok(None) => {
+                    self.logger.info("Se desconectó el cliente.".to_string());
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    return Ok(self.handle_keep_alive_expiration())
+                }
+                Err(e) => return Ok(self.handle_read_error(&e)),
+            }
+        }
+    }
+
+    /// El cliente no envió nada durante 1.5 veces su keep alive, se cierra la conexión.
+    fn handle_keep_alive_expiration(&self) -> DisconnectReason {
+        self.logger
+            .warn("Keep alive vencido, cerrando la conexión.".to_string());
+        shutdown(&self.sync_stream);
+        DisconnectReason::Involuntaria
+    }
+
+    /// Falló la lectura del stream del cliente (ie la cerró a mitad de un mensaje): se cierra la conexión,
+    /// y se la considera una desconexión involuntaria.
+    fn handle_read_error(&self, e: &Error) -> DisconnectReason {
+        self.logger.warn(format!(
+            "Error al leer del cliente: {:?}. Cerrando la conexión.",
+            e
+        ));
+        shutdown(&self.sync_stream);
+        DisconnectReason::Involuntaria
+    }
+
+    /// Si el mensaje es un publish con topic alias, devuelve sus bytes con el topic completo, como el ClientReader.
+    fn resolve_topic_alias(
+        &mut self,
+        message_type: PacketType,
+        msg_bytes: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        if message_type != PacketType::Publish || !self.topic_aliases.is_enabled() {
+            return Ok(msg_bytes);
+        }
+        self.topic_aliases.resolve_bytes(msg_bytes)
+    }
+
+    /// El cliente envió un publish cuyo topic no puede determinarse, se cierra la conexión.
+    fn handle_invalid_topic_alias(&self, e: &Error) -> DisconnectReason {
+        self.logger.warn(format!(
+            "Error, publish inválido: {}. Cerrando la conexión.",
+            e
+        ));
+        shutdown(&self.sync_stream);
+        DisconnectReason::Involuntaria
+    }
+
+    /// Devuelve si el mensaje excede el tamaño máximo aceptado, en cuyo caso cierra la conexión sin leerlo.
+    fn is_too_large(&self, fixed_header: &FixedHeader) -> bool {
+        let rem_len = fixed_header.get_rem_len();
+        if rem_len <= self.max_packet_size {
+            return false;
+        }
+        self.logger.warn(format!(
+            "Error en la conexión: {}. Cerrando la conexión.",
+            MqttError::PacketTooLarge(rem_len)
+        ));
+        shutdown(&self.sync_stream);
+        true
+    }
+
+    /// Si el cliente supera la tasa máxima de publish configurada, espera antes de seguir leyendo su stream,
+    /// frenando así al cliente sin ocupar un hilo del runtime.
+    async fn throttle_if_publish_rate_exceeded(&mut self) {
+        // La tasa puede haber cambiado al recargar la configuración.
+        let max_publish_rate = self.mqtt_server.get_max_publish_rate();
+        self.publish_rate_limiter.set_max_publish_rate(max_publish_rate);
+        let delay = self.publish_rate_limiter.register_publish(Instant::now());
+        if !delay.is_zero() {
+            self.logger.warn(format!(
+                "Supera la tasa máxima de publish, se lo frena {:?}.",
+                delay
+            ));
+            sleep(delay).await;
+        }
+    }
+
+    /// Lee el fixed header del próximo mensaje, o devuelve None si se cerró la conexión.
+    async fn read_fixed_header(&mut self) -> Result<Option<([u8; 2], FixedHeader)>, Error> {
+        let mut fixed_header_buf = [0u8; FixedHeader::fixed_header_len()];
+        match self.stream.read_exact(&mut fixed_header_buf).await {
+            Ok(_) => {
+                let fixed_header = FixedHeader::from_bytes(fixed_header_buf.to_vec());
+                Ok(Some((fixed_header_buf, fixed_header)))
+            }
+            Err(e)
+                if e.kind() == ErrorKind::UnexpectedEof
+                    || e.kind() == ErrorKind::ConnectionReset =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Lee los `remaining length` bytes del mensaje, y devuelve el mensaje completo incluyendo su fixed header.
+    async fn read_whole_message(
+        &mut self,
+        fixed_header: &FixedHeader,
+        fixed_header_buf: [u8; 2],
+    ) -> Result<Vec<u8>, Error> {
+        let mut rem_buf = vec![0u8; fixed_header.get_rem_len()];
+        self.stream.read_exact(&mut rem_buf).await?;
+        let mut msg_bytes = fixed_header_buf.to_vec();
+        msg_bytes.extend(rem_buf);
+        Ok(msg_bytes)
+    }
+
+    /// Encola el paquete para el message processor; si la cola está llena, espera a que se libere lugar.
+    fn enqueue(&self, packet: Packet) {
+        let send_res = match self.packets_tx.try_send(packet) {
+            Err(TrySendError::Full(packet)) => {
+                self.logger
+                    .debug("Cola de paquetes llena, esperando para encolar.".to_string());
+                block_in_place(|| self.packets_tx.send(packet))
+            }
+            Err(TrySendError::Disconnected(packet)) => Err(SendError(packet)),
+            Ok(()) => Ok(()),
+        };
+        if let Err(e) = send_res {
+            self.logger.error(format!(
+                "Error al enviar por channel interno, en enqueue: {:?}.",
+                e
+            ));
+        }
+    }
+}
+
+/// Espera a que termine la lectura `read`, a lo sumo `limit` si no es None.
+/// Si el plazo vence antes, devuelve un error de tipo `TimedOut`.
+async fn within<T>(
+    limit: Option<Duration>,
+    read: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let Some(limit) = limit else {
+        return read.await;
+    };
+    match timeout(limit, read).await {
+        Ok(res) => res,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "Plazo de lectura vencido.")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::handle_incoming_connections;
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::messages::{
+        connack_message::ConnackMessage, connect_message::ConnectMessage,
+        connect_return_code::ConnectReturnCode,
+    };
+    use crate::mqtt::server::{mqtt_server::MQTTServer, server_config::ServerConfig};
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Lanza el transporte tokio con la configuración `config`, y devuelve la dirección en que escucha.
+    fn spawn_transport(config: ServerConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (logger_tx, _logger_rx) = mpsc::channel::<String>();
+        // Los tests no llegan a encolar paquetes para el message processor.
+        let (packets_tx, _) = mpsc::sync_channel(1);
+        let server = MQTTServer::with_config(StringLogger::new(logger_tx.clone()), config);
+        thread::spawn(move || {
+            let _ = handle_incoming_connections(
+                listener,
+                server,
+                packets_tx,
+                StringLogger::new(logger_tx),
+            );
+        });
+        addr
+    }
+
+    #[test]
+    fn test_1_el_transporte_tokio_acepta_un_connect_y_responde_connack() {
+        let addr = spawn_transport(ServerConfig::default());
+
+        // Cliente invitado, sin usuario ni contraseña
+        let mut client = TcpStream::connect(addr).unwrap();
+        let mut connect_msg = ConnectMessage::new("dron-1".to_string(), None, None, None, 10);
+        client.write_all(&connect_msg.to_bytes()).unwrap();
+
+        let mut connack_bytes = [0u8; 4];
+        client.read_exact(&mut connack_bytes).unwrap();
+        let connack = ConnackMessage::from_bytes(&connack_bytes).unwrap();
+        assert_eq!(
+            connack.get_connect_return_code(),
+            ConnectReturnCode::ConnectionAccepted
+        );
+    }
+
+    #[test]
+    fn test_2_si_el_connect_no_llega_a_tiempo_se_cierra_la_conexion() {
+        let config = ServerConfig::default().with_connect_timeout(Duration::from_millis(300));
+        let addr = spawn_transport(config);
+        let mut client = TcpStream::connect(addr).unwrap();
+        let start = Instant::now();
+
+        // Envía el connect de a un byte, sin llegar a completarlo antes del plazo.
+        for byte in [0x10, 12, 0] {
+            client.write_all(&[byte]).unwrap();
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        assert_eq!(client.read(&mut [0u8; 1]).unwrap(), 0);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    sync::mpsc::Receiver,
+    thread::{self, JoinHandle},
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::client::mqtt_client_builder::MqttClientBuilder;
+use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+
+use super::{credentials_store::matches_pattern, mqtt_server::MQTTServer};
+
+const DEFAULT_BRIDGE_CLIENT_ID: &str = "rustx-bridge";
+
+/// Configuración del bridge: el broker remoto al que se conecta el servidor local como cliente,
+/// y qué topics se reenvían en cada sentido. Un topic local `t` se corresponde con el topic remoto
+/// `remote_prefix + t`, y un topic remoto `remote_prefix + t` con el local `local_prefix + t`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BridgeConfig {
+    remote_addr: Option<SocketAddr>, // si no se configura, el bridge está deshabilitado.
+    client_id: Option<String>,
+    topics_out: Vec<String>, // patrones de topics locales a publicar en el remoto, admiten '*'.
+    topics_in: Vec<String>, // topics remotos (sin prefijo) a los que suscribirse y publicar localmente.
+    remote_prefix: String,
+    local_prefix: String,
+}
+
+impl BridgeConfig {
+    /// Asigna el valor de una clave `bridge_*` del archivo de configuración del servidor.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "bridge_remote_addr" if value.is_empty() => self.remote_addr = None,
+            "bridge_remote_addr" => {
+                let addr = value.parse::<SocketAddr>().map_err(|e| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Valor inválido para {}: {:?}", key, e),
+                    )
+                })?;
+                self.remote_addr = Some(addr);
+            }
+            "bridge_client_id" => self.client_id = Some(value.to_string()),
+            "bridge_topics_out" => self.topics_out = split_topics(value),
+            "bridge_topics_in" => self.topics_in = split_topics(value),
+            "bridge_remote_prefix" => self.remote_prefix = value.to_string(),
+            "bridge_local_prefix" => self.local_prefix = value.to_string(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Clave de bridge desconocida: {:?}", key),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve si se configuró un broker remoto al cual conectarse.
+    pub fn is_enabled(&self) -> bool {
+        self.remote_addr.is_some()
+    }
+
+    /// Devuelve si los publish del topic local `topic` deben reenviarse al broker remoto.
+    pub fn forwards_out(&self, topic: &str) -> bool {
+        self.topics_out
+            .iter()
+            .any(|pattern| matches_pattern(pattern, topic))
+    }
+
+    /// Devuelve el topic remoto correspondiente al topic local `topic`.
+    pub fn local_to_remote(&self, topic: &str) -> String {
+        format!("{}{}", self.remote_prefix, topic)
+    }
+
+    /// Devuelve el topic local correspondiente al topic remoto `topic`, o None si no tiene el prefijo remoto.
+    pub fn remote_to_local(&self, topic: &str) -> Option<String> {
+        topic
+            .strip_prefix(&self.remote_prefix)
+            .map(|t| format!("{}{}", self.local_prefix, t))
+    }
+}
+
+/// Conecta el servidor local como cliente del broker remoto, y lanza los hilos que reenvían los publish:
+/// los recibidos por `outgoing_rx` (publicados localmente) hacia el remoto, y los del remoto hacia `mqtt_server`.
+pub fn spawn_bridge(
+    config: BridgeConfig,
+    mqtt_server: MQTTServer,
+    outgoing_rx: Receiver<PublishMessage>,
+    logger: StringLogger,
+) -> Result<Vec<JoinHandle<()>>, Error> {
+    let Some(remote_addr) = config.remote_addr else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "El bridge no tiene configurado el broker remoto.",
+        ));
+    };
+    let client_id = config
+        .client_id
+        .clone()
+        .unwrap_or(DEFAULT_BRIDGE_CLIENT_ID.to_string());
+    let (mut mqtt_client, incoming_rx, listener_handle) =
+        MqttClientBuilder::new(&client_id).connect(&remote_addr, logger.clone_ref())?;
+    logger.info(format!(
+        "Bridge conectado al broker remoto {:?}.",
+        remote_addr
+    ));
+
+    if !config.topics_in.is_empty() {
+        let remote_topics = config
+            .topics_in
+            .iter()
+            .map(|topic| (config.local_to_remote(topic), 1))
+            .collect();
+        mqtt_client.mqtt_subscribe(remote_topics)?;
+    }
+
+    // Hilo que publica en el broker remoto lo que se publica localmente en los topics configurados
+    let config_c = config.clone();
+    let logger_c = logger.clone_ref();
+    let outgoing_handle = thread::spawn(move || {
+        for msg in outgoing_rx {
+            let remote_topic = config_c.local_to_remote(&msg.get_topic());
+            let (payload, qos) = (msg.get_payload(), msg.get_qos());
+            let publish_res = if msg.is_retain() {
+                mqtt_client.mqtt_publish_retained(&remote_topic, &payload, qos)
+            } else {
+                mqtt_client.mqtt_publish(&remote_topic, &payload, qos)
+            };
+            if let Err(e) = publish_res {
+                logger_c.error(format!(
+                    "Error al reenviar publish al broker remoto: {:?}",
+                    e
+                ));
+            }
+        }
+    });
+
+    // Hilo que publica localmente lo recibido del broker remoto
+    let incoming_handle = thread::spawn(move || {
+        for msg in incoming_rx {
+            let res = remap_to_local(&config, &msg)
+                .and_then(|local_msg| mqtt_server.handle_bridged_publish(&local_msg));
+            if let Err(e) = res {
+                logger.error(format!(
+                    "Error al publicar localmente desde el bridge: {:?}",
+                    e
+                ));
+            }
+        }
+    });
+
+    Ok(vec![listener_handle, outgoing_handle, incoming_handle])
+}
+
+/// Crea el publish local correspondiente al publish `msg` recibido del broker remoto.
+fn remap_to_local(config: &BridgeConfig, msg: &PublishMessage) -> Result<PublishMessage, Error> {
+    let Some(local_topic) = config.remote_to_local(&msg.get_topic()) else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Topic remoto sin el prefijo del bridge: {:?}",
+                msg.get_topic()
+            ),
+        ));
+    };
+    let flags = PublishFlags::new(0, msg.get_qos(), msg.is_retain() as u8)?;
+    PublishMessage::new(flags, &local_topic, msg.get_packet_id(), &msg.get_payload())
+}
+
+fn split_topics(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{remap_to_local, BridgeConfig};
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+
+    fn create_config() -> BridgeConfig {
+        let mut config = BridgeConfig::default();
+        config.set("bridge_remote_addr", "10.0.0.1:1883").unwrap();
+        config.set("bridge_topics_out", "inc, dron").unwrap();
+        config.set("bridge_topics_in", "inc").unwrap();
+        config.set("bridge_remote_prefix", "campus/").unwrap();
+        config.set("bridge_local_prefix", "central/").unwrap();
+        config
+    }
+
+    #[test]
+    fn test_1_se_reenvian_solo_los_topics_configurados_con_el_prefijo_remoto() {
+        let config = create_config();
+
+        assert!(config.is_enabled());
+        assert!(config.forwards_out("inc"));
+        assert!(!config.forwards_out("cam"));
+        assert_eq!(config.local_to_remote("inc"), "campus/inc");
+    }
+
+    #[test]
+    fn test_2_los_topics_remotos_se_mapean_al_prefijo_local() {
+        let config = create_config();
+
+        assert_eq!(
+            config.remote_to_local("campus/inc"),
+            Some("central/inc".to_string())
+        );
+        assert_eq!(config.remote_to_local("otro/inc"), None);
+    }
+
+    #[test]
+    fn test_3_el_publish_remoto_se_recrea_con_el_topic_local_y_el_mismo_contenido() {
+        let config = create_config();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg =
+            PublishMessage::new(flags, "campus/inc", Some(7), "incidente".as_bytes()).unwrap();
+
+        let local_msg = remap_to_local(&config, &msg).unwrap();
+
+        assert_eq!(local_msg.get_topic(), "central/inc");
+        assert_eq!(local_msg.get_payload(), msg.get_payload());
+        assert_eq!(local_msg.get_qos(), 1);
+    }
+
+    #[test]
+    fn test_4_sin_broker_remoto_el_bridge_esta_deshabilitado() {
+        let mut config = BridgeConfig::default();
+        assert!(!config.is_enabled());
+        assert!(config
+            .set("bridge_remote_addr", "no es una dirección")
+            .is_err());
+    }
+}
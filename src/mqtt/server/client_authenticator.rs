@@ -1,16 +1,21 @@
-use std::{io::Error, path::Path};
+use std::io::Error;
 
 use crate::logging::string_logger::StringLogger;
 use crate::mqtt::messages::{
     connack_message::ConnackMessage, connack_session_present::SessionPresent,
     connect_message::ConnectMessage, connect_return_code::ConnectReturnCode,
+    properties::Properties, protocol_version::ProtocolVersion,
+};
+use crate::mqtt::mqtt_utils::{
+    packet_observer::PacketDirection, utils::write_message_to_stream,
 };
-use crate::mqtt::mqtt_utils::utils::write_message_to_stream;
 use crate::mqtt::stream_type::StreamType;
 
-use super::file_helper::read_lines;
+use super::credentials_store::CredentialsStore;
 use super::mqtt_server::MQTTServer;
 
+const CREDENTIALS_FILE_PATH: &str = "credentials.txt";
+
 #[derive(Debug)]
 pub struct AuthenticateClient {
     logger: StringLogger,
@@ -31,9 +36,9 @@ impl AuthenticateClient {
         mqtt_server: &MQTTServer,
     ) -> Result<bool, Error> {
         let (is_authentic, connack_response) =
-            self.was_the_session_created_succesfully(connect_msg)?;
+            self.was_the_session_created_succesfully(connect_msg, mqtt_server)?;
 
-        self.send_connection_response(&connack_response, stream)?; // aux: y si mejor le devuelve el connack? []
+        self.send_connection_response(&connack_response, connect_msg, stream, mqtt_server)?; // aux: y si mejor le devuelve el connack? []
 
         if is_authentic {
             self.handle_successful_authentication(connect_msg, stream, mqtt_server)
@@ -43,12 +48,73 @@ impl AuthenticateClient {
         }
     }
 
+    /// Rechaza la conexión respondiendo al cliente con un connack con el código `return_code`.
+    pub fn refuse_connection(
+        &self,
+        connect_msg: &ConnectMessage,
+        stream: &mut StreamType,
+        return_code: ConnectReturnCode,
+        mqtt_server: &MQTTServer,
+    ) -> Result<(), Error> {
+        self.logger.warn(format!(
+            "Conexión rechazada para el client_id {:?}: {:?}",
+            connect_msg.get_client_id(),
+            return_code
+        ));
+        let connack_response = self.create_connack(
+            connect_msg,
+            return_code,
+            SessionPresent::NotPresentInLastSession,
+            mqtt_server,
+        );
+        self.send_connection_response(&connack_response, connect_msg, stream, mqtt_server)
+    }
+
+    /// Crea el connack con el código `return_code`, en la versión de protocolo que pidió el cliente si el
+    /// servidor la acepta, o en mqtt 3.1.1 si no (así un cliente de mqtt 5 puede reintentar con 3.1.1).
+    /// En mqtt 5, un rechazo lleva además el motivo como reason string.
+    fn create_connack(
+        &self,
+        connect_msg: &ConnectMessage,
+        return_code: ConnectReturnCode,
+        session_present: SessionPresent,
+        mqtt_server: &MQTTServer,
+    ) -> ConnackMessage {
+        let protocol_version = connect_msg.get_protocol_version();
+        if protocol_version != Some(ProtocolVersion::Mqtt5)
+            || !mqtt_server.accepts_protocol_version(protocol_version)
+        {
+            return ConnackMessage::new(session_present, return_code);
+        }
+        let properties = match return_code {
+            ConnectReturnCode::ConnectionAccepted => {
+                // Si se aceptan topic aliases, se informa cuántos, para que el cliente pueda usarlos.
+                match mqtt_server.get_topic_alias_maximum(protocol_version) {
+                    0 => Properties::default(),
+                    maximum => Properties::default().with_topic_alias_maximum(maximum),
+                }
+            }
+            _ => Properties::default().with_reason_string(&format!("{:?}", return_code)),
+        };
+        ConnackMessage::new_v5(session_present, return_code, properties)
+    }
+
+    /// Escribe el connack al cliente, e informa a los packet observers del servidor.
     fn send_connection_response(
         &self,
         connack_response: &ConnackMessage,
+        connect_msg: &ConnectMessage,
         stream: &mut StreamType,
+        mqtt_server: &MQTTServer,
     ) -> Result<(), Error> {
-        write_message_to_stream(&connack_response.to_bytes(), stream) // aux: (ok xq todavía no existe el User).
+        let connack_bytes = connack_response.to_bytes();
+        write_message_to_stream(&connack_bytes, stream)?; // aux: (ok xq todavía no existe el User).
+        let mut packet_observers = mqtt_server.get_packet_observers();
+        if let Some(client_id) = connect_msg.get_client_id() {
+            packet_observers = packet_observers.with_client_id(client_id);
+        }
+        packet_observers.notify(PacketDirection::Outbound, &connack_bytes);
+        Ok(())
     }
 
     /// Devuelve true si el cliente que se conecta posee client_id.
@@ -59,11 +125,13 @@ impl AuthenticateClient {
         mqtt_server: &MQTTServer,
     ) -> Result<bool, Error> {
         if let Some(username) = connect_msg.get_client_id() {
-            let is_reconnection =
-                mqtt_server.manage_possible_reconnecting_or_duplicate_user(username, stream)?;
+            let is_reconnection = mqtt_server.manage_possible_reconnecting_or_duplicate_user(
+                username,
+                stream,
+                connect_msg.get_protocol_version().unwrap_or_default(),
+            )?;
             if !is_reconnection {
-                println!("Agregando nuevo user al server con username {:?}", username);
-                self.logger.log(format!("Agregando nuevo user al server con username {:?}", username));
+                self.logger.debug(format!("Agregando nuevo user al server con username {:?}", username));
                 mqtt_server.add_new_user(stream, username, connect_msg)?;
             }
             Ok(true)
@@ -72,69 +140,70 @@ impl AuthenticateClient {
         }
     }
 
-    /// Verifica si la sesión fue creada exitosamente: usuario valido o invitado
-    /// y devuelve un mensaje CONNACK acorde.
+    /// Verifica si la sesión fue creada exitosamente: versión de protocolo aceptada por el servidor,
+    /// client_id no vacío (el servidor no asigna uno), usuario valido o invitado,
+    /// y client_id no rechazado por duplicado según la política del servidor;
+    /// y devuelve un mensaje CONNACK acorde, indicando si se retoma una sesión que el servidor conservaba.
     fn was_the_session_created_succesfully(
         &self,
         connect_msg: &ConnectMessage,
+        mqtt_server: &MQTTServer,
     ) -> Result<(bool, ConnackMessage), Error> {
-        if self.is_guest_mode_active(connect_msg.get_user(), connect_msg.get_passwd())
-            || self.authenticate(connect_msg.get_user(), connect_msg.get_passwd())
+        let client_id = connect_msg.get_client_id().map_or("", |id| id.as_str());
+        let mut return_code =
+            if !mqtt_server.accepts_protocol_version(connect_msg.get_protocol_version()) {
+                ConnectReturnCode::ProtocolError
+            } else if client_id.is_empty() {
+                ConnectReturnCode::IdentifierRejected
+            } else if self.is_guest_mode_active(connect_msg.get_user(), connect_msg.get_passwd()) {
+                ConnectReturnCode::ConnectionAccepted
+            } else {
+                self.authenticate(connect_msg)
+            };
+
+        if return_code == ConnectReturnCode::ConnectionAccepted
+            && mqtt_server.rejects_duplicate_client_id(client_id)
         {
-            let connack_response = ConnackMessage::new(
-                SessionPresent::NotPresentInLastSession,
-                ConnectReturnCode::ConnectionAccepted,
-            );
-            Ok((true, connack_response))
-        } else {
-            let connack_response = ConnackMessage::new(
-                SessionPresent::NotPresentInLastSession,
-                ConnectReturnCode::NotAuthorized,
-            );
-            Ok((false, connack_response))
+            return_code = ConnectReturnCode::IdentifierRejected;
         }
+
+        if return_code != ConnectReturnCode::ConnectionAccepted {
+            self.logger.warn(format!(
+                "Conexión rechazada para el client_id {:?}: {:?}",
+                connect_msg.get_client_id(),
+                return_code
+            ));
+        }
+        let is_authentic = return_code == ConnectReturnCode::ConnectionAccepted;
+        // Por protocolo, con clean session el connack indica que no había sesión.
+        let session_present =
+            if is_authentic && !connect_msg.is_clean_session() && mqtt_server.has_session(client_id) {
+                SessionPresent::PresentInLastSession
+            } else {
+                SessionPresent::NotPresentInLastSession
+            };
+        let connack_response =
+            self.create_connack(connect_msg, return_code, session_present, mqtt_server);
+        Ok((is_authentic, connack_response))
     }
 
     fn is_guest_mode_active(&self, user: Option<&String>, passwd: Option<&String>) -> bool {
         user.is_none() && passwd.is_none()
     }
 
-    /// Autentica al usuario con las credenciales almacenadas en el archivo credentials.txt
-    fn authenticate(&self, user: Option<&String>, passwd: Option<&String>) -> bool {
-        let credentials = self.read_credentials_from_file("credentials.txt");
-        self.verify_authentication(user, passwd, &credentials)
-    }
-
-    /// Lee las credenciales del archivo especificado y devuelve un vector de pares (usuario, contraseña)
-    fn read_credentials_from_file(&self, file_path: &str) -> Vec<(String, String)> {
-        let path = Path::new(file_path);
-        let mut credentials = Vec::new();
-
-        if let Ok(lines) = read_lines(path) {
-            for line in lines.map_while(Result::ok) {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() == 2 {
-                    credentials.push((parts[0].to_string(), parts[1].to_string()));
-                }
+    /// Autentica al usuario con las credenciales almacenadas en el archivo credentials.txt,
+    /// y devuelve el código de retorno para el connack.
+    /// Si el archivo no puede leerse, no se acepta ninguna conexión autenticada.
+    fn authenticate(&self, connect_msg: &ConnectMessage) -> ConnectReturnCode {
+        match CredentialsStore::from_file(CREDENTIALS_FILE_PATH) {
+            Ok(store) => {
+                let client_id = connect_msg.get_client_id().map_or("", |id| id.as_str());
+                store.authenticate(connect_msg.get_user(), connect_msg.get_passwd(), client_id)
+            }
+            Err(e) => {
+                self.logger.error(format!("Error al leer el archivo de credenciales: {:?}", e));
+                ConnectReturnCode::ServerUnavailable
             }
-        }
-
-        credentials
-    }
-
-    /// Verifica si el usuario y la contraseña proporcionados coinciden con alguna de las credenciales almacenadas
-    fn verify_authentication(
-        &self,
-        user: Option<&String>,
-        passwd: Option<&String>,
-        credentials: &[(String, String)],
-    ) -> bool {
-        if let (Some(u), Some(p)) = (user, passwd) {
-            credentials
-                .iter()
-                .any(|(username, password)| u == username && p == password)
-        } else {
-            false
         }
     }
-}
\ No newline at end of file
+}
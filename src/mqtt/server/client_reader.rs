@@ -1,76 +1,123 @@
 use crate::logging::string_logger::StringLogger;
-use crate::mqtt::messages::{connect_message::ConnectMessage, packet_type::PacketType};
+use crate::mqtt::messages::{
+    connect_message::ConnectMessage, connect_return_code::ConnectReturnCode,
+    packet_type::PacketType,
+};
 use crate::mqtt::mqtt_utils::{
     fixed_header::FixedHeader,
-    utils::{
-        get_fixed_header_from_stream, get_fixed_header_from_stream_for_conn,
-        get_whole_message_in_bytes_from_stream, is_disconnect_msg, shutdown,
-    },
+    frame_reader::FrameReader,
+    mqtt_error::is_packet_too_large,
+    packet_observer::{PacketDirection, PacketObservers},
+    topic_aliases::IncomingTopicAliases,
+    utils::{is_disconnect_msg, is_unexpected_client_msg, shutdown},
 };
 
 use crate::mqtt::server::{
-    client_authenticator::AuthenticateClient, disconnect_reason::DisconnectReason,
-    message_processor::MessageProcessor, mqtt_server::MQTTServer, packet::Packet,
+    client_authenticator::AuthenticateClient, connection_limits::PublishRateLimiter,
+    disconnect_reason::DisconnectReason,
+    log_context::{client_id_log_context, connection_log_context},
+    mqtt_server::MQTTServer,
+    packet::Packet,
 };
 use crate::mqtt::stream_type::StreamType;
 
 use std::{
-    io::Error,
-    sync::mpsc::{Receiver, Sender},
-    thread::JoinHandle,
+    io::{Error, ErrorKind, Read},
+    net::SocketAddr,
+    sync::mpsc::{SendError, SyncSender, TrySendError},
+    thread,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug)]
 pub struct ClientReader {
     stream: StreamType,
+    peer_addr: Option<SocketAddr>, // se guarda al crearlo, ya que deja de estar disponible al cerrarse el stream.
     mqtt_server: MQTTServer,
-    logger: StringLogger,
+    packets_tx: SyncSender<Packet>, // cola compartida con el message processor del servidor.
+    frame_reader: FrameReader,      // buffer de lectura de la conexión, se reutiliza para todos sus mensajes.
+    publish_rate_limiter: PublishRateLimiter,
+    topic_aliases: IncomingTopicAliases, // asignados por el cliente en sus publish, valen mientras dure la conexión.
+    packet_observers: PacketObservers, // los del servidor, reciben los paquetes leídos; con el client_id luego del connect.
+    logger: StringLogger, // con el contexto de la conexión: la dirección del cliente, y su client_id luego del connect.
 }
 
 impl ClientReader {
     pub fn new(
         stream: StreamType,
         mqtt_server: MQTTServer,
+        packets_tx: SyncSender<Packet>,
         logger: StringLogger,
     ) -> Result<ClientReader, Error> {
+        let peer_addr = stream.peer_addr().ok();
         Ok(ClientReader {
+            logger: logger.with_context(&connection_log_context(peer_addr, None)),
+            peer_addr,
             stream,
+            publish_rate_limiter: mqtt_server.create_publish_rate_limiter(),
+            topic_aliases: IncomingTopicAliases::default(),
+            frame_reader: FrameReader::new().with_max_rem_len(mqtt_server.get_max_packet_size()),
+            packet_observers: mqtt_server.get_packet_observers(),
             mqtt_server,
-            logger,
+            packets_tx,
         })
     }
 
     /// Procesa los mensajes entrantes de un dado cliente.
+    /// El primero debe llegar completo dentro del connect timeout configurado; si no, se cierra la conexión.
     pub fn handle_client(&mut self, stream: &mut StreamType) -> Result<(), Error> {
-        let (fixed_header_buf, fixed_header) = self.read_and_validate_header(stream)?;
+        let connect_timeout = self.mqtt_server.get_connect_timeout();
+        let mut connect_reader = DeadlineReader::new(stream, connect_timeout);
+        let read_res = read_message(&mut self.frame_reader, &mut connect_reader, &self.packet_observers);
+        let (fixed_header, msg_bytes) = match read_res {
+            Ok(Some(message)) => message,
+            // El cliente cerró la conexión sin enviar el connect.
+            Ok(None) => return Ok(()),
+            Err(e) if is_packet_too_large(&e) => {
+                self.handle_packet_too_large(&e);
+                return Ok(());
+            }
+            Err(e) if is_read_timeout(&e) => {
+                self.handle_connect_timeout(connect_timeout, stream);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        // Desde ahora, la espera depende del keep alive que indique el connect.
+        stream.set_read_timeout(None)?;
 
         let authenticator = AuthenticateClient::new(self.logger.clone_ref());
-        self.authenticate_and_handle_connection(
-            &fixed_header,
-            &fixed_header_buf,
-            &authenticator,
-            stream,
-        )
-    }
-
-    fn read_and_validate_header(
-        &mut self,
-        stream: &mut StreamType,
-    ) -> Result<([u8; 2], FixedHeader), Error> {
-        let (fixed_header_buf, fixed_header) = get_fixed_header_from_stream_for_conn(stream)?;
-        Ok((fixed_header_buf, fixed_header))
+        self.authenticate_and_handle_connection(&fixed_header, &msg_bytes, &authenticator, stream)
     }
 
     fn authenticate_and_handle_connection(
         &mut self,
         fixed_header: &FixedHeader,
-        fixed_header_buf: &[u8; 2],
+        msg_bytes: &[u8],
         authenticator: &AuthenticateClient,
         stream: &mut StreamType,
     ) -> Result<(), Error> {
         match fixed_header.get_message_type() {
             PacketType::Connect => {
-                let connect_msg = get_connect_message(fixed_header, stream, fixed_header_buf)?;
+                let connect_msg = match ConnectMessage::from_bytes(msg_bytes) {
+                    Ok(connect_msg) => connect_msg,
+                    Err(e) => {
+                        self.handle_malformed_connect(&e, stream);
+                        return Ok(());
+                    }
+                };
+                // La conexión cuenta para los límites del servidor mientras se atiende al cliente.
+                let Some(_connection_slot) = self.mqtt_server.try_register_connection(self.peer_addr)?
+                else {
+                    authenticator.refuse_connection(
+                        &connect_msg,
+                        stream,
+                        ConnectReturnCode::ServerUnavailable,
+                        &self.mqtt_server,
+                    )?;
+                    shutdown(stream);
+                    return Ok(());
+                };
                 if authenticator.is_it_a_valid_connection(
                     &connect_msg,
                     stream,
@@ -78,6 +125,15 @@ impl ClientReader {
                 )? {
                     // Aux: ok en realidad acá arriba al terminar el authenticator se crea el User. [].
                     if let Some(client_id) = connect_msg.get_client_id() {
+                        self.logger = self.logger.with_context(&client_id_log_context(client_id));
+                        self.packet_observers = self.packet_observers.with_client_id(client_id);
+                        self.logger.info("Cliente conectado.".to_string());
+                        self.set_keep_alive_timeout(connect_msg.get_keep_alive())?;
+                        // Se aceptan los topic aliases informados al cliente en el connack.
+                        let protocol_version = connect_msg.get_protocol_version();
+                        self.topic_aliases = IncomingTopicAliases::new(
+                            self.mqtt_server.get_topic_alias_maximum(protocol_version),
+                        );
                         self.handle_packets(client_id)?;
                     }
                 }
@@ -87,116 +143,112 @@ impl ClientReader {
         Ok(())
     }
 
+    /// Configura el timeout de lectura del stream en 1.5 veces el keep alive negociado en el connect,
+    /// para detectar a los clientes que dejaron de enviar mensajes sin desconectarse.
+    /// Un keep alive de 0 desactiva el mecanismo.
+    fn set_keep_alive_timeout(&self, keep_alive: u16) -> Result<(), Error> {
+        if keep_alive > 0 {
+            let timeout = Duration::from_millis(keep_alive as u64 * 1500);
+            self.stream.set_read_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+
+    /// El cliente no envió el connect completo a tiempo (ie un ataque slow loris), se cierra la conexión.
+    fn handle_connect_timeout(&self, connect_timeout: Duration, stream: &mut StreamType) {
+        self.logger.warn(format!(
+            "No se recibió el connect en {:?}. Cerrando la conexión.",
+            connect_timeout
+        ));
+        shutdown(stream);
+    }
+
     fn handle_invalid_message(&self, fixed_header: &FixedHeader, stream: &mut StreamType) {
-        println!("Error, el primer mensaje recibido DEBE ser un connect.");
-        println!("   recibido: {:?}", fixed_header);
-        println!("Cerrando la conexión.");
-        self.logger.log(format!(
+        self.logger.warn(format!(
             "Error, primer msj recibido debe ser connect, se recibió: {:?}. Cerrando la conexión.",
             fixed_header
         ));
         shutdown(stream);
     }
 
-    // Función modificada para usar las nuevas funciones modulares
-    // Aux: dsp de lo de is_authentic, una vez que ya fue connect msg todo bien, viene esto:
-    fn handle_packets(&mut self, client_id: &String) -> Result<(), Error> {
-        let (tx_1, rx_1) = std::sync::mpsc::channel::<Packet>();
-
-        // Hilo para obtener los bytes que llegan al servidor en el stream
-        let h1 = self.spawn_stream_handler(client_id.to_owned(), tx_1);
-
-        // Hilo para manejar la recepción y procesamiento de mensajes
-        let h2 = self.spawn_message_processor(rx_1);
-
-        let handles = vec![h1, h2]; // Clippy lo quiere así.
-
-        for h in handles {
-            let _ = h.join();
-        }
-
-        Ok(())
-    }
-
-    // Hilo para obtener los bytes que llegan al servidor en el stream
-    fn spawn_stream_handler(&self, client_id: String, tx_1: Sender<Packet>) -> JoinHandle<()> {
-        let mut self_clone = self.clone_ref(); // []
-        let logger_c = self.logger.clone_ref();
-        std::thread::spawn(move || {
-            if let Ok(disconnect_reason) =
-                self_clone.read_packets_from_stream(client_id.as_str(), tx_1)
-                {
-                match disconnect_reason {
-                    DisconnectReason::Voluntaria => {
-                        if let Err(e) = self_clone.server_handle_disconnect(client_id.as_str()){
-                            logger_c.log(format!("Error al manejar disconnect: {:?}.", e));
-                        }
-                    }
-                    DisconnectReason::Involuntaria => {
-                        if let Err(e) = self_clone.server_handle_client_disconnection(client_id.as_str()){
-                            logger_c.log(format!("Error al manejar desconexión involuntaria: {:?}.", e));
-                        }
-                    }
-                }
-            }
-        })
-    }
-
-    /// Desconexión voluntaria.
-    fn server_handle_disconnect(&mut self, client_id: &str) -> Result<(), Error> {
-        self.mqtt_server.publish_users_will_message(client_id)?;
-        self.mqtt_server.remove_user(client_id);
-        Ok(())
+    /// El connect no pudo interpretarse: por protocolo, se cierra la conexión sin enviar connack.
+    fn handle_malformed_connect(&self, e: &Error, stream: &mut StreamType) {
+        self.logger.warn(format!(
+            "Error, connect mal formado: {}. Cerrando la conexión.",
+            e
+        ));
+        shutdown(stream);
     }
 
-    /// Desconexión involuntaria (ie se le fue internet).
-    fn server_handle_client_disconnection(&mut self, client_id: &str) -> Result<(), Error> {
+    // Función modificada para usar las nuevas funciones modulares
+    // Aux: dsp de lo de is_authentic, una vez que ya fue connect msg todo bien, viene esto:
+    /// Lee los paquetes que llegan al servidor en el stream y los envía a la cola compartida del message processor,
+    /// hasta que el cliente se desconecta. Se ejecuta en el hilo del cliente, sin crear hilos adicionales.
+    fn handle_packets(&mut self, client_id: &str) -> Result<(), Error> {
+        let packets_tx = self.packets_tx.clone();
+        let disconnect_reason = self.read_packets_from_stream(client_id, &packets_tx)?;
         self.mqtt_server
-            .set_user_as_temporally_disconnected(client_id)?;
-        self.mqtt_server.publish_users_will_message(client_id)?;
+            .handle_client_disconnection(client_id, self.peer_addr, disconnect_reason);
         Ok(())
     }
 
-    // Hilo para manejar la recepción y procesamiento de mensajes
-    fn spawn_message_processor(&self, rx_1: Receiver<Packet>) -> JoinHandle<()> {
-        let mut message_processor = MessageProcessor::new(self.mqtt_server.clone_ref());
-        std::thread::spawn(move || {
-            let _ = message_processor.handle_packets(rx_1);
-        })
-    }
 
     // Espera por paquetes que llegan desde su stream y los envia al hilo de arriba
     pub fn read_packets_from_stream(
         &mut self,
         client_id: &str,
-        tx_1: Sender<Packet>,
+        tx_1: &SyncSender<Packet>,
     ) -> Result<DisconnectReason, Error> {
-        println!("Eperando más mensajes.");
-        self.logger.log("Esperando más mensajes.".to_string());
+        self.logger.debug("Esperando más mensajes.".to_string());
 
         loop {
-            match get_fixed_header_from_stream(&mut self.stream) {
-                Ok(Some((fixed_h_buf, fixed_h))) => {
+            match read_message(&mut self.frame_reader, &mut self.stream, &self.packet_observers) {
+                Ok(Some((fixed_h, _))) if is_unexpected_client_msg(&fixed_h) => {
+                    self.handle_protocol_violation(&fixed_h);
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Ok(Some((fixed_h, msg_bytes))) => {
+                    self.logger.debug(format!("Recibido {:?}.", fixed_h.get_message_type()));
                     if is_disconnect_msg(&fixed_h) {
                         self.handle_disconnect(client_id)?; // aux: llama a mqtt []
                         return Ok(DisconnectReason::Voluntaria);
                         // AUX, hace:
-                        // aux: self.mqtt_server.publish_users_will_message(client_id)?;
                         // aux: self.mqtt_server.remove_user(client_id);
                         //break;
                     }
+                    self.throttle_if_publish_rate_exceeded(&fixed_h);
+                    let msg_bytes = match self.resolve_topic_alias(&fixed_h, msg_bytes) {
+                        Ok(msg_bytes) => msg_bytes,
+                        Err(e) => {
+                            self.handle_invalid_topic_alias(&e);
+                            return Ok(DisconnectReason::Involuntaria);
+                        }
+                    };
                     // Completa la lectura del stream, y envía al otro hilo para ser procesado
-                    self.handle_packet(fixed_h, fixed_h_buf, client_id, &tx_1)?;
+                    self.handle_packet(&fixed_h, msg_bytes, client_id, tx_1)?;
                 }
                 Ok(None) => {
-                    self.handle_client_disconnection(client_id)?; // aux: llama a mqtt []
+                    self.handle_client_disconnection()?; // aux: llama a mqtt []
                     return Ok(DisconnectReason::Involuntaria);
                     // Aux hace:
                     //aux: self.mqtt_server.set_user_as_temporally_disconnected(client_id)?;
                     //aux: self.mqtt_server.publish_users_will_message(client_id)?;
                     //break;
                 }
-                Err(_) => todo!(),
+                Err(e) if is_packet_too_large(&e) => {
+                    self.handle_packet_too_large(&e);
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Err(e) if is_read_timeout(&e) => {
+                    // El cliente no envió nada durante 1.5 veces su keep alive, se lo desconecta.
+                    self.handle_keep_alive_expiration()?;
+                    return Ok(DisconnectReason::Involuntaria);
+                }
+                Err(e) => {
+                    // Ie el cliente reseteó la conexión, o la cerró a mitad de un mensaje.
+                    self.handle_read_error(&e);
+                    return Ok(DisconnectReason::Involuntaria);
+                }
             }
         }
         //Ok(())
@@ -206,64 +258,375 @@ impl ClientReader {
     fn handle_disconnect(&mut self, _client_id: &str) -> Result<(), Error> {
         //self.mqtt_server.publish_users_will_message(client_id)?;
         //self.mqtt_server.remove_user(client_id);
-        println!("Recibo disconnect");
-        self.logger.log("Recibo disconnect.".to_string());
+        self.logger.info("Recibo disconnect.".to_string());
+        shutdown(&self.stream);
+        Ok(())
+    }
+
+    /// Desconexión por keep alive vencido, se cierra la conexión con el cliente.
+    fn handle_keep_alive_expiration(&mut self) -> Result<(), Error> {
+        self.logger.warn("Keep alive vencido, cerrando la conexión.".to_string());
         shutdown(&self.stream);
         Ok(())
     }
 
+    /// El cliente envió un mensaje que no puede enviar luego del connect, se cierra la conexión.
+    fn handle_protocol_violation(&self, fixed_h: &FixedHeader) {
+        self.logger.warn(format!(
+            "Error, mensaje inesperado: {:?}. Cerrando la conexión.",
+            fixed_h
+        ));
+        shutdown(&self.stream);
+    }
+
+    /// Falló la lectura del stream del cliente, se cierra la conexión.
+    fn handle_read_error(&self, e: &Error) {
+        self.logger.warn(format!(
+            "Error al leer del cliente: {:?}. Cerrando la conexión.",
+            e
+        ));
+        shutdown(&self.stream);
+    }
+
+    /// El cliente envió un paquete mayor al tamaño máximo aceptado, se cierra la conexión sin leerlo.
+    fn handle_packet_too_large(&self, e: &Error) {
+        self.logger.warn(format!("Error en la conexión: {}. Cerrando la conexión.", e));
+        shutdown(&self.stream);
+    }
+
+    /// Si el mensaje es un publish y el cliente supera la tasa máxima de publish configurada,
+    /// espera antes de seguir leyendo su stream, frenando así al cliente.
+    fn throttle_if_publish_rate_exceeded(&mut self, fixed_h: &FixedHeader) {
+        if fixed_h.get_message_type() != PacketType::Publish {
+            return;
+        }
+        // La tasa puede haber cambiado al recargar la configuración.
+        let max_publish_rate = self.mqtt_server.get_max_publish_rate();
+        self.publish_rate_limiter.set_max_publish_rate(max_publish_rate);
+        let delay = self.publish_rate_limiter.register_publish(Instant::now());
+        if !delay.is_zero() {
+            self.logger.warn(format!(
+                "Supera la tasa máxima de publish, se lo frena {:?}.",
+                delay
+            ));
+            thread::sleep(delay);
+        }
+    }
+
+    /// Si el mensaje es un publish con topic alias, devuelve sus bytes con el topic completo, para que se procese
+    /// como cualquier otro publish. Los demás mensajes se devuelven sin cambios.
+    fn resolve_topic_alias(&mut self, fixed_h: &FixedHeader, msg_bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if fixed_h.get_message_type() != PacketType::Publish || !self.topic_aliases.is_enabled() {
+            return Ok(msg_bytes);
+        }
+        self.topic_aliases.resolve_bytes(msg_bytes)
+    }
+
+    /// El cliente envió un publish cuyo topic no puede determinarse (ie un topic alias sin topic asignado),
+    /// se cierra la conexión.
+    fn handle_invalid_topic_alias(&self, e: &Error) {
+        self.logger.warn(format!("Error, publish inválido: {}. Cerrando la conexión.", e));
+        shutdown(&self.stream);
+    }
+
     fn handle_packet(
         &mut self,
-        fixed_h: FixedHeader,
-        fixed_h_buf: [u8; 2],
+        fixed_h: &FixedHeader,
+        msg_bytes: Vec<u8>,
         client_id: &str,
-        tx_1: &Sender<Packet>,
+        tx_1: &SyncSender<Packet>,
     ) -> Result<(), Error> {
-        let packet = create_packet(&fixed_h, &mut self.stream, &fixed_h_buf, client_id)?;
-        if let Err(e) = tx_1.send(packet) {
-            self.logger.log(format!("Error al enviar por channel interno, en handle_packet: {:?}.", e));
+        let packet = Packet::new(
+            fixed_h.get_message_type(),
+            msg_bytes,
+            client_id.to_string(),
+            self.peer_addr,
+        );
+        // Si la cola está llena, se espera a que se libere lugar: deja de leerse el stream de este cliente.
+        let send_res = match tx_1.try_send(packet) {
+            Err(TrySendError::Full(packet)) => {
+                self.logger.debug("Cola de paquetes llena, esperando para encolar.".to_string());
+                tx_1.send(packet)
+            }
+            Err(TrySendError::Disconnected(packet)) => Err(SendError(packet)),
+            Ok(()) => Ok(()),
+        };
+        if let Err(e) = send_res {
+            self.logger.error(format!("Error al enviar por channel interno, en handle_packet: {:?}.", e));
         }
         Ok(())
     }
 
     /// Desconexión involuntaria (ie se le fue internet).
-    fn handle_client_disconnection(&mut self, client_id: &str) -> Result<(), Error> {
-        println!("Se desconectó el cliente: {:?}.", client_id);
-        self.logger
-            .log(format!("Se desconectó el cliente: {:?}.", client_id));
+    fn handle_client_disconnection(&mut self) -> Result<(), Error> {
+        self.logger.info("Se desconectó el cliente.".to_string());
         //self.mqtt_server.set_user_as_temporally_disconnected(client_id)?;
         //self.mqtt_server.publish_users_will_message(client_id)?;
         Ok(())
     }
+}
+
+/// Lee el siguiente mensaje completo del `stream`, y devuelve su fixed header y sus bytes.
+/// Se lo informa a los `packet_observers`, como recibido.
+/// Devuelve None si el cliente cerró la conexión.
+fn read_message<R: Read>(
+    frame_reader: &mut FrameReader,
+    stream: &mut R,
+    packet_observers: &PacketObservers,
+) -> Result<Option<(FixedHeader, Vec<u8>)>, Error> {
+    let Some(frame) = frame_reader.read_frame(stream)? else {
+        return Ok(None);
+    };
+    packet_observers.notify(PacketDirection::Inbound, frame);
+    Ok(Some((FixedHeader::from_msg_bytes(frame), frame.to_vec())))
+}
+
+/// Lee del stream sin pasarse de un plazo: antes de cada lectura ajusta el timeout al tiempo restante,
+/// para que un cliente que envía los bytes de a poco no pueda extender la espera indefinidamente.
+struct DeadlineReader<'a> {
+    stream: &'a mut StreamType,
+    deadline: Instant,
+}
+
+impl<'a> DeadlineReader<'a> {
+    fn new(stream: &'a mut StreamType, timeout: Duration) -> Self {
+        DeadlineReader {
+            stream,
+            deadline: Instant::now() + timeout,
+        }
+    }
+}
 
-    fn clone_ref(&self) -> Self {
-        ClientReader {
-            stream: self.stream.try_clone().unwrap(),
-            mqtt_server: self.mqtt_server.clone_ref(),
-            logger: self.logger.clone_ref(),
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::new(ErrorKind::TimedOut, "Plazo de lectura vencido."));
         }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
     }
 }
 
-fn create_packet(
-    fixed_header: &FixedHeader,
-    stream: &mut StreamType, // []
-    fixed_header_bytes: &[u8; 2],
-    client_id: &str,
-) -> Result<Packet, Error> {
-    let msg_bytes =
-        get_whole_message_in_bytes_from_stream(fixed_header, stream, fixed_header_bytes)?;
-    let message_type = fixed_header.get_message_type();
-    Ok(Packet::new(message_type, msg_bytes, client_id.to_string()))
+/// Devuelve si el error de lectura se debe a que venció el timeout configurado
+/// (el del connect, o el calculado a partir del keep alive).
+fn is_read_timeout(e: &Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
 }
 
-/// Completa la lectura y devuelve el `ConnectMessage`.
-fn get_connect_message(
-    fixed_header: &FixedHeader,
-    stream: &mut StreamType,
-    fixed_header_bytes: &[u8; 2],
-) -> Result<ConnectMessage, Error> {
-    let msg_bytes =
-        get_whole_message_in_bytes_from_stream(fixed_header, stream, fixed_header_bytes)?;
-    Ok(ConnectMessage::from_bytes(&msg_bytes))
+#[cfg(test)]
+mod test {
+    use super::ClientReader;
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::messages::{
+        connack_message::ConnackMessage, connect_message::ConnectMessage,
+        connect_return_code::ConnectReturnCode, publish_message::PublishMessage,
+        subscribe_message::SubscribeMessage,
+    };
+    use crate::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
+    use crate::mqtt::server::{
+        mqtt_server::MQTTServer, server_config::ServerConfig, user_state::UserState,
+    };
+    use crate::mqtt::stream_type::StreamType;
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, TcpListener},
+        sync::mpsc,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    /// Devuelve los dos extremos de una conexión local: (extremo del servidor, extremo del cliente).
+    fn create_connection(listener: &TcpListener) -> (StreamType, StreamType) {
+        let client_stream = StreamType::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        (server_stream.into(), client_stream)
+    }
+
+    fn create_server() -> MQTTServer {
+        let (tx, _rx) = mpsc::channel::<String>();
+        MQTTServer::with_config(StringLogger::new(tx), ServerConfig::default())
+    }
+
+    /// Atiende en otro hilo la conexión `server_stream` con un ClientReader, como lo hace el servidor.
+    fn spawn_client_reader(server: &MQTTServer, mut server_stream: StreamType) -> JoinHandle<bool> {
+        let (logger_tx, _logger_rx) = mpsc::channel::<String>();
+        let (packets_tx, _packets_rx) = mpsc::sync_channel(10);
+        let mut client_reader = ClientReader::new(
+            server_stream.try_clone().unwrap(),
+            server.clone_ref(),
+            packets_tx,
+            StringLogger::new(logger_tx),
+        )
+        .unwrap();
+        thread::spawn(move || client_reader.handle_client(&mut server_stream).is_ok())
+    }
+
+    /// Envía el connect y devuelve el connack recibido.
+    fn send_connect(client_stream: &mut StreamType, connect_msg: &mut ConnectMessage) -> ConnackMessage {
+        client_stream.write_all(&connect_msg.to_bytes()).unwrap();
+        let mut connack = [0u8; 4];
+        client_stream.read_exact(&mut connack).unwrap();
+        ConnackMessage::from_bytes(&connack).unwrap()
+    }
+
+    /// Envía el connect de `client_id` y verifica que sea aceptado.
+    fn connect(client_stream: &mut StreamType, client_id: &str, will: Option<WillMessageData>) {
+        let mut connect_msg = ConnectMessage::new(client_id.to_string(), will, None, None, 0);
+        let connack = send_connect(client_stream, &mut connect_msg);
+        assert_eq!(
+            connack.get_connect_return_code(),
+            ConnectReturnCode::ConnectionAccepted
+        );
+    }
+
+    fn is_temporally_disconnected(server: &MQTTServer, client_id: &str) -> bool {
+        let users = server.get_connected_users();
+        let users = users.lock().unwrap();
+        users
+            .get(client_id)
+            .is_some_and(|user| *user.get_state() == UserState::TemporallyDisconnected)
+    }
+
+    #[test]
+    fn test_1_si_el_cliente_corta_a_mitad_de_un_mensaje_se_publica_su_will() {
+        let server = create_server();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        // Un suscriptor al topic del will, agregado directamente al servidor.
+        let (subscriber_stream, mut subscriber_client) = create_connection(&listener);
+        let subscriber_connect = ConnectMessage::new("monitoreo".to_string(), None, None, None, 0);
+        server
+            .add_new_user(&subscriber_stream, "monitoreo", &subscriber_connect)
+            .unwrap();
+        let subscribe_msg = SubscribeMessage::new(1, vec![("desc".to_string(), 0)]);
+        server
+            .add_topics_to_subscriber("monitoreo", &subscribe_msg)
+            .unwrap();
+
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+        let will = WillMessageData::new("dron-1 caido".to_string(), "desc".to_string(), 0, 0);
+        connect(&mut client_stream, "dron-1", Some(will));
+
+        // Un publish del que llega solamente el principio.
+        client_stream.write_all(&[0x30, 20, 0, 4]).unwrap();
+        client_stream.shutdown(Shutdown::Both).unwrap();
+
+        assert!(reader_handle.join().unwrap());
+        assert!(is_temporally_disconnected(&server, "dron-1"));
+        let mut header = [0u8; 2];
+        subscriber_client.read_exact(&mut header).unwrap();
+        let mut msg_bytes = header.to_vec();
+        msg_bytes.resize(2 + header[1] as usize, 0);
+        subscriber_client.read_exact(&mut msg_bytes[2..]).unwrap();
+        let will_msg = PublishMessage::from_bytes(msg_bytes).unwrap();
+        assert_eq!(will_msg.get_payload(), b"dron-1 caido");
+    }
+
+    #[test]
+    fn test_2_un_mensaje_de_tipo_invalido_cierra_la_conexion() {
+        let server = create_server();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+        connect(&mut client_stream, "dron-2", None);
+
+        // Tipo reservado 15.
+        client_stream.write_all(&[0xF0, 0]).unwrap();
+
+        assert!(reader_handle.join().unwrap());
+        assert_eq!(client_stream.read(&mut [0u8; 1]).unwrap(), 0);
+        assert!(is_temporally_disconnected(&server, "dron-2"));
+    }
+
+    #[test]
+    fn test_3_un_connect_mal_formado_cierra_la_conexion_sin_connack() {
+        let server = create_server();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+
+        client_stream.write_all(&[0x10, 3, 0, 9, b'M']).unwrap();
+
+        assert!(reader_handle.join().unwrap());
+        let mut received = vec![];
+        client_stream.read_to_end(&mut received).unwrap();
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn test_4_el_connack_indica_si_se_retoma_la_sesion() {
+        let server = create_server();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut connect_msg = ConnectMessage::new("dron-4".to_string(), None, None, None, 0);
+        connect_msg.set_clean_session(false);
+
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+        assert!(!send_connect(&mut client_stream, &mut connect_msg).is_session_present());
+        client_stream.shutdown(Shutdown::Both).unwrap();
+        assert!(reader_handle.join().unwrap());
+
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let _reader_handle = spawn_client_reader(&server, server_stream);
+        assert!(send_connect(&mut client_stream, &mut connect_msg).is_session_present());
+    }
+
+    #[test]
+    fn test_5_un_client_id_vacio_se_rechaza() {
+        let server = create_server();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+
+        let mut connect_msg = ConnectMessage::new(String::new(), None, None, None, 0);
+        let connack = send_connect(&mut client_stream, &mut connect_msg);
+
+        assert_eq!(
+            connack.get_connect_return_code(),
+            ConnectReturnCode::IdentifierRejected
+        );
+        assert!(!connack.is_session_present());
+        assert!(reader_handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_6_si_el_connect_no_llega_a_tiempo_se_cierra_la_conexion() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let config = ServerConfig::default().with_connect_timeout(Duration::from_millis(300));
+        let server = MQTTServer::with_config(StringLogger::new(tx), config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let start = Instant::now();
+        let reader_handle = spawn_client_reader(&server, server_stream);
+
+        // Envía el connect de a un byte, sin llegar a completarlo antes del plazo.
+        for byte in [0x10, 12, 0] {
+            client_stream.write_all(&[byte]).unwrap();
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        assert!(reader_handle.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(client_stream.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_7_luego_del_connect_el_plazo_del_connect_ya_no_aplica() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let config = ServerConfig::default().with_connect_timeout(Duration::from_millis(100));
+        let server = MQTTServer::with_config(StringLogger::new(tx), config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let reader_handle = spawn_client_reader(&server, server_stream);
+
+        // Keep alive 0: el server no debe cerrar la conexión aunque el cliente no envíe nada.
+        connect(&mut client_stream, "dron-7", None);
+        thread::sleep(Duration::from_millis(300));
+
+        assert!(!reader_handle.is_finished());
+        client_stream.shutdown(Shutdown::Both).unwrap();
+        assert!(reader_handle.join().unwrap());
+    }
 }
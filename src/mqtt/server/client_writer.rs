@@ -0,0 +1,311 @@
+use std::{
+    io::{Error, ErrorKind, IoSlice, Write},
+    net::Shutdown,
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+use crate::mqtt::mqtt_utils::{
+    mqtt_error::MqttError,
+    packet_observer::{PacketDirection, PacketObservers},
+};
+use crate::mqtt::stream_type::StreamType;
+
+use super::{delivery_stats::DeliveryStats, topic_metrics::TopicMetrics};
+
+/// Máximo de mensajes encolados que se escriben juntos.
+const MAX_BATCH_LEN: usize = 64;
+
+/// Lo que el hilo escritor de un cliente debe hacer con su conexión.
+#[derive(Debug)]
+enum Outgoing {
+    Bytes(Vec<u8>),
+    Close, // luego de escribir lo encolado antes, ie el disconnect a una sesión desplazada.
+}
+
+/// Lo que se registra de las escrituras hacia los clientes: los contadores del servidor, las entregas de
+/// cada topic, y los paquetes escritos para sus packet observers.
+#[derive(Debug, Clone, Default)]
+pub struct WriteTracking {
+    stats: Arc<DeliveryStats>,
+    topic_metrics: Arc<TopicMetrics>,
+    packet_observers: PacketObservers,
+}
+
+impl WriteTracking {
+    pub fn new(
+        stats: Arc<DeliveryStats>,
+        topic_metrics: Arc<TopicMetrics>,
+        packet_observers: PacketObservers,
+    ) -> Self {
+        WriteTracking {
+            stats,
+            topic_metrics,
+            packet_observers,
+        }
+    }
+
+    /// Devuelve una copia que informa a los packet observers los paquetes como escritos hacia `client_id`.
+    pub fn with_client_id(&self, client_id: &str) -> Self {
+        WriteTracking {
+            stats: self.stats.clone(),
+            topic_metrics: self.topic_metrics.clone(),
+            packet_observers: self.packet_observers.with_client_id(client_id),
+        }
+    }
+
+    /// Registra en las métricas de su topic la entrega del publish `msg`, de `bytes` bytes, al cliente.
+    pub fn record_delivery(&self, msg: &PublishMessage, bytes: usize) {
+        self.topic_metrics.record_delivery(msg, bytes);
+    }
+}
+
+/// Escribe hacia un cliente desde un hilo propio, los mensajes encolados en una cola acotada. Así un cliente lento
+/// o bloqueado (ie una cámara que no lee) no frena a quien le escribe, como la distribución de un publish al resto
+/// de los suscriptores. Si la cola se llena, el cliente no está consumiendo sus mensajes y se cierra su conexión;
+/// al reconectarse recibe los publish que no le llegaron.
+/// Los mensajes que se encolaron mientras se escribía el anterior se escriben juntos, con una escritura vectorizada.
+#[derive(Debug)]
+pub struct ClientWriter {
+    outgoing_tx: SyncSender<Outgoing>,
+    stream: StreamType, // para cerrar la conexión sin esperar al hilo, si la cola está llena.
+}
+
+impl ClientWriter {
+    /// Lanza el hilo que escribe por `stream`, con lugar para `queue_size` mensajes pendientes de escribir,
+    /// y que registra sus escrituras en `tracking`.
+    pub fn spawn(
+        stream: StreamType,
+        queue_size: usize,
+        tracking: WriteTracking,
+    ) -> Result<Self, Error> {
+        let (outgoing_tx, outgoing_rx) = mpsc::sync_channel(queue_size.max(1));
+        let writer_stream = stream.try_clone()?;
+        thread::spawn(move || write_outgoing(writer_stream, outgoing_rx, &tracking));
+        Ok(ClientWriter {
+            outgoing_tx,
+            stream,
+        })
+    }
+
+    /// Encola el mensaje en bytes `msg_bytes` para escribirlo hacia el cliente, sin esperar a que se escriba.
+    /// Devuelve error si la conexión se cerró, o si la cola está llena, en cuyo caso cierra la conexión.
+    pub fn write(&self, msg_bytes: &[u8]) -> Result<(), Error> {
+        match self
+            .outgoing_tx
+            .try_send(Outgoing::Bytes(msg_bytes.to_vec()))
+        {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                Err(MqttError::QueueFull("la cola de escritura del cliente".to_string()).into())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(MqttError::NotConnected.into()),
+        }
+    }
+
+    /// Cierra la conexión luego de escribir los mensajes ya encolados, o inmediatamente si la cola está llena.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        match self.outgoing_tx.try_send(Outgoing::Close) {
+            Ok(()) => Ok(()),
+            Err(_) => self.stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+/// Escribe por `stream` lo recibido por `outgoing_rx`, hasta que deba cerrarse la conexión o el `ClientWriter`
+/// se dropee. Si falla una escritura cierra la conexión, para que el client reader detecte la desconexión.
+fn write_outgoing(mut stream: StreamType, outgoing_rx: Receiver<Outgoing>, tracking: &WriteTracking) {
+    while let Ok(first) = outgoing_rx.recv() {
+        let mut batch = vec![];
+        let mut is_closing = false;
+        let mut next = Some(first);
+        // Junto con el primero, los que ya están encolados.
+        while let Some(outgoing) = next.take() {
+            match outgoing {
+                Outgoing::Bytes(msg_bytes) => batch.push(msg_bytes),
+                Outgoing::Close => is_closing = true,
+            }
+            if !is_closing && batch.len() < MAX_BATCH_LEN {
+                next = outgoing_rx.try_recv().ok();
+            }
+        }
+
+        match write_batch(&mut stream, &batch) {
+            Ok(writes) if !batch.is_empty() => {
+                let bytes = batch.iter().map(|msg_bytes| msg_bytes.len()).sum();
+                tracking.stats.record_batch(batch.len(), bytes, writes);
+                for msg_bytes in &batch {
+                    tracking.packet_observers.notify(PacketDirection::Outbound, msg_bytes);
+                }
+            }
+            Ok(_) => {}
+            Err(_) => is_closing = true,
+        }
+        if is_closing {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+/// Escribe los mensajes de `batch` con escrituras vectorizadas, y devuelve cuántas escrituras hicieron falta.
+fn write_batch<W: Write>(stream: &mut W, batch: &[Vec<u8>]) -> Result<usize, Error> {
+    let mut slices: Vec<IoSlice> = batch
+        .iter()
+        .filter(|msg_bytes| !msg_bytes.is_empty())
+        .map(|msg_bytes| IoSlice::new(msg_bytes))
+        .collect();
+    let mut slices = &mut slices[..];
+    let mut writes = 0;
+    while !slices.is_empty() {
+        match stream.write_vectored(slices) {
+            Ok(0) => {
+                return Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "No se pudo escribir en el stream.",
+                ))
+            }
+            Ok(n) => {
+                writes += 1;
+                IoSlice::advance_slices(&mut slices, n);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    stream.flush()?;
+    Ok(writes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_batch, ClientWriter, WriteTracking};
+    use crate::mqtt::mqtt_utils::packet_observer::{
+        PacketDirection, PacketEvent, PacketObserver, PacketObservers,
+    };
+    use crate::mqtt::server::delivery_stats::DeliveryStats;
+    use crate::mqtt::stream_type::StreamType;
+    use std::{
+        io::{ErrorKind, Read},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    /// Devuelve los dos extremos de una conexión local: (extremo del servidor, extremo del cliente).
+    fn create_connection() -> (StreamType, StreamType) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client_stream = StreamType::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        (server_stream.into(), client_stream)
+    }
+
+    #[test]
+    fn test_1_al_cerrar_se_escriben_antes_los_mensajes_encolados() {
+        let (server_stream, mut client_stream) = create_connection();
+        let writer =
+            ClientWriter::spawn(server_stream, 10, WriteTracking::default()).unwrap();
+
+        writer.write(&[0xE0, 0]).unwrap();
+        writer.shutdown().unwrap();
+
+        let mut received = vec![];
+        client_stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, vec![0xE0, 0]);
+    }
+
+    #[test]
+    fn test_2_si_el_cliente_no_lee_y_se_llena_la_cola_se_cierra_la_conexion() {
+        let (server_stream, _client_stream) = create_connection();
+        let writer =
+            ClientWriter::spawn(server_stream, 1, WriteTracking::default()).unwrap();
+
+        // El hilo escritor queda bloqueado en un mensaje que no entra en los buffers de la conexión.
+        writer.write(&vec![0u8; 64 * 1024 * 1024]).unwrap();
+        let full_res = (0..3)
+            .map(|_| writer.write(&[0xD0, 0]))
+            .find(|res| res.is_err())
+            .unwrap();
+
+        assert_eq!(full_res.unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_3_los_mensajes_encolados_se_escriben_juntos() {
+        let batch = vec![vec![0xE0, 0], vec![], vec![0xD0, 0]];
+        let mut written = vec![];
+
+        let writes = write_batch(&mut written, &batch).unwrap();
+
+        assert_eq!(writes, 1);
+        assert_eq!(written, vec![0xE0, 0, 0xD0, 0]);
+    }
+
+    #[test]
+    fn test_4_se_registran_los_mensajes_escritos() {
+        let (server_stream, mut client_stream) = create_connection();
+        let stats = Arc::new(DeliveryStats::default());
+        let tracking =
+            WriteTracking::new(stats.clone(), Arc::default(), PacketObservers::default());
+        let writer = ClientWriter::spawn(server_stream, 10, tracking).unwrap();
+
+        writer.write(&[0xD0, 0]).unwrap();
+        writer.write(&[0xD0, 0]).unwrap();
+        writer.shutdown().unwrap();
+        client_stream.read_to_end(&mut vec![]).unwrap();
+
+        assert_eq!(stats.get_messages(), 2);
+        assert_eq!(stats.get_bytes(), 4);
+        assert!(stats.get_writes() <= 2);
+    }
+
+    /// Dirección, bytes y client id de un paquete observado.
+    type RecordedEvent = (PacketDirection, Vec<u8>, Option<String>);
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<RecordedEvent>>,
+    }
+
+    impl PacketObserver for RecordingObserver {
+        fn on_packet(&self, event: &PacketEvent) {
+            self.events.lock().unwrap().push((
+                event.direction,
+                event.bytes.to_vec(),
+                event.client_id.map(|client_id| client_id.to_string()),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_5_los_observers_reciben_los_mensajes_escritos_al_cliente() {
+        let (server_stream, mut client_stream) = create_connection();
+        let observers = PacketObservers::default();
+        let observer = Arc::new(RecordingObserver::default());
+        observers.add(observer.clone());
+        let tracking = WriteTracking::new(Arc::default(), Arc::default(), observers);
+        let writer =
+            ClientWriter::spawn(server_stream, 10, tracking.with_client_id("dron-1")).unwrap();
+
+        writer.write(&[0xD0, 0]).unwrap();
+        writer.shutdown().unwrap();
+        client_stream.read_to_end(&mut vec![]).unwrap();
+
+        let events = observer.events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![(
+                PacketDirection::Outbound,
+                vec![0xD0, 0],
+                Some("dron-1".to_string())
+            )]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::logging::string_logger::StringLogger;
+use crate::mqtt::client::mqtt_client_builder::MqttClientBuilder;
+use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+use super::mqtt_server::MQTTServer;
+
+/// Prefijo del client id con el que cada broker del cluster se conecta a los demás.
+pub const CLUSTER_CLIENT_ID_PREFIX: &str = "rustx-cluster-";
+/// Topic reservado en el que cada broker publica los topics a los que están suscriptos sus clientes.
+pub const CLUSTER_SUBSCRIPTIONS_TOPIC: &str = "$cluster/subscriptions";
+
+const DEFAULT_GOSSIP_INTERVAL: Duration = Duration::from_secs(5);
+/// Contenido máximo de la tabla de suscripciones, para que el publish no exceda el remaining length de un byte.
+const MAX_SUBSCRIPTIONS_PAYLOAD_LEN: usize = 150;
+
+/// Configuración del cluster: el nombre de este broker y los demás brokers del cluster. Cada broker se conecta
+/// como cliente a todos sus peers, y les reenvía los publish de los topics en los que tienen suscriptores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterConfig {
+    node_id: String, // debe ser distinto en cada broker, ya que forma el client id con el que se conecta a los peers.
+    peers: Vec<SocketAddr>, // si no se configuran, el cluster está deshabilitado.
+    gossip_interval: Duration, // cada cuánto se publica la tabla de suscripciones a los peers.
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            node_id: String::new(),
+            peers: vec![],
+            gossip_interval: DEFAULT_GOSSIP_INTERVAL,
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Asigna el valor de una clave `cluster_*` del archivo de configuración del servidor.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "cluster_node_id" => self.node_id = value.to_string(),
+            "cluster_peers" => {
+                self.peers = value
+                    .split(',')
+                    .map(|peer| peer.trim())
+                    .filter(|peer| !peer.is_empty())
+                    .map(|peer| peer.parse::<SocketAddr>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| invalid_value(key, e))?;
+            }
+            "cluster_gossip_interval_secs" => {
+                let secs = value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| invalid_value(key, value))?;
+                self.gossip_interval = Duration::from_secs(secs);
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Clave de cluster desconocida: {:?}", key),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve si se configuraron otros brokers con los que formar el cluster.
+    pub fn is_enabled(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    /// Devuelve el client id con el que este broker se conecta a sus peers.
+    pub fn client_id(&self) -> String {
+        format!("{}{}", CLUSTER_CLIENT_ID_PREFIX, self.node_id)
+    }
+
+    pub fn get_peers(&self) -> &[SocketAddr] {
+        &self.peers
+    }
+
+    pub fn get_gossip_interval(&self) -> Duration {
+        self.gossip_interval
+    }
+}
+
+/// Devuelve si `client_id` es el de otro broker del cluster conectado a este.
+pub fn is_cluster_client(client_id: &str) -> bool {
+    client_id.starts_with(CLUSTER_CLIENT_ID_PREFIX)
+}
+
+/// Otro broker del cluster: los topics en los que tiene suscriptores, según su última tabla de suscripciones,
+/// y el canal por el que se le reenvían los publish.
+#[derive(Debug)]
+pub struct ClusterPeer {
+    addr: SocketAddr,
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    publish_tx: Sender<PublishMessage>,
+}
+
+impl ClusterPeer {
+    pub fn get_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Devuelve si el peer tiene clientes suscriptos al topic `topic`.
+    pub fn has_subscribers_for(&self, topic: &str) -> bool {
+        match self.subscriptions.lock() {
+            Ok(subscriptions) => subscriptions.contains(topic),
+            Err(_) => false,
+        }
+    }
+
+    /// Envía el publish `msg` al hilo que lo publica en el peer.
+    pub fn forward(&self, msg: &PublishMessage) -> Result<(), Error> {
+        self.publish_tx
+            .send(msg.clone())
+            .map_err(|_| MqttError::NotConnected.into())
+    }
+}
+
+/// Lanza, para cada peer, el hilo que se conecta a él (reintentando hasta que esté disponible) y le reenvía
+/// los publish, y el hilo que publica periódicamente en este broker su tabla de suscripciones para los peers.
+/// Devuelve los peers, a los que el servidor reenvía los publish de sus clientes.
+pub fn spawn_cluster(
+    config: ClusterConfig,
+    mqtt_server: MQTTServer,
+    logger: StringLogger,
+) -> Result<Vec<ClusterPeer>, Error> {
+    if config.node_id.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "El cluster no tiene configurado cluster_node_id.",
+        ));
+    }
+
+    let mut peers = vec![];
+    for addr in config.get_peers() {
+        let (publish_tx, publish_rx) = mpsc::channel::<PublishMessage>();
+        let peer = ClusterPeer {
+            addr: *addr,
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            publish_tx,
+        };
+        spawn_peer_connection(&config, &peer, publish_rx, logger.clone_ref());
+        peers.push(peer);
+    }
+    spawn_gossip(config.get_gossip_interval(), mqtt_server, logger);
+    Ok(peers)
+}
+
+/// Hilo que se conecta al peer, se suscribe a su tabla de suscripciones, y le publica lo recibido por `publish_rx`.
+fn spawn_peer_connection(
+    config: &ClusterConfig,
+    peer: &ClusterPeer,
+    publish_rx: Receiver<PublishMessage>,
+    logger: StringLogger,
+) -> JoinHandle<()> {
+    let (addr, client_id, retry_interval) = (peer.addr, config.client_id(), config.gossip_interval);
+    let subscriptions = peer.subscriptions.clone();
+    thread::spawn(move || {
+        let (mut mqtt_client, incoming_rx, _listener_handle) = loop {
+            match MqttClientBuilder::new(&client_id).connect(&addr, logger.clone_ref()) {
+                Ok(connection) => break connection,
+                Err(e) => {
+                    logger.debug(format!(
+                        "Peer del cluster {:?} no disponible: {:?}",
+                        addr, e
+                    ));
+                    thread::sleep(retry_interval);
+                }
+            }
+        };
+        logger.info(format!("Conectado al peer del cluster {:?}.", addr));
+        if let Err(e) =
+            mqtt_client.mqtt_subscribe(vec![(CLUSTER_SUBSCRIPTIONS_TOPIC.to_string(), 1)])
+        {
+            logger.error(format!(
+                "Error al suscribirse a las suscripciones del peer {:?}: {:?}",
+                addr, e
+            ));
+        }
+
+        // Hilo que actualiza los topics del peer con cada tabla de suscripciones que publica
+        let logger_c = logger.clone_ref();
+        thread::spawn(move || {
+            for msg in incoming_rx {
+                if msg.get_topic() != CLUSTER_SUBSCRIPTIONS_TOPIC {
+                    continue;
+                }
+                let topics = decode_subscriptions(&msg.get_payload());
+                logger_c.debug(format!("Suscripciones del peer {:?}: {:?}", addr, topics));
+                if let Ok(mut subscriptions_l) = subscriptions.lock() {
+                    *subscriptions_l = topics;
+                }
+            }
+        });
+
+        for msg in publish_rx {
+            let (topic, payload, qos) = (msg.get_topic(), msg.get_payload(), msg.get_qos());
+            let publish_res = if msg.is_retain() {
+                mqtt_client.mqtt_publish_retained(&topic, &payload, qos)
+            } else {
+                mqtt_client.mqtt_publish(&topic, &payload, qos)
+            };
+            if let Err(e) = publish_res {
+                logger.error(format!(
+                    "Error al reenviar publish al peer del cluster {:?}: {:?}",
+                    addr, e
+                ));
+            }
+        }
+    })
+}
+
+/// Hilo que publica cada `interval` la tabla de suscripciones de este broker, para que los peers
+/// le reenvíen solamente los publish de los topics en los que tiene suscriptores.
+fn spawn_gossip(
+    interval: Duration,
+    mqtt_server: MQTTServer,
+    logger: StringLogger,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let payload = encode_subscriptions(&mqtt_server.get_local_subscribed_topics());
+        if payload.len() > MAX_SUBSCRIPTIONS_PAYLOAD_LEN {
+            logger.warn(format!(
+                "La tabla de suscripciones ocupa {} bytes, más que los {} que admite un publish; no se publica.",
+                payload.len(),
+                MAX_SUBSCRIPTIONS_PAYLOAD_LEN
+            ));
+        } else {
+            let publish_res = PublishFlags::new(0, 0, 0)
+                .and_then(|flags| {
+                    PublishMessage::new(flags, CLUSTER_SUBSCRIPTIONS_TOPIC, None, &payload)
+                })
+                .and_then(|msg| mqtt_server.handle_cluster_publish(&msg));
+            if let Err(e) = publish_res {
+                logger.error(format!(
+                    "Error al publicar la tabla de suscripciones: {:?}",
+                    e
+                ));
+            }
+        }
+        thread::sleep(interval);
+    })
+}
+
+/// Codifica los topics de la tabla de suscripciones, uno por línea y ordenados.
+fn encode_subscriptions(topics: &HashSet<String>) -> Vec<u8> {
+    let mut topics: Vec<&String> = topics.iter().collect();
+    topics.sort();
+    topics
+        .iter()
+        .map(|topic| topic.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .into_bytes()
+}
+
+fn decode_subscriptions(payload: &[u8]) -> HashSet<String> {
+    String::from_utf8_lossy(payload)
+        .lines()
+        .filter(|topic| !topic.is_empty())
+        .map(|topic| topic.to_string())
+        .collect()
+}
+
+fn invalid_value<E: std::fmt::Debug>(key: &str, detail: E) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Valor inválido para {}: {:?}", key, detail),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        decode_subscriptions, encode_subscriptions, is_cluster_client, ClusterConfig,
+        CLUSTER_CLIENT_ID_PREFIX,
+    };
+    use std::{collections::HashSet, time::Duration};
+
+    #[test]
+    fn test_1_se_habilita_al_configurar_peers() {
+        let mut config = ClusterConfig::default();
+        assert!(!config.is_enabled());
+
+        config.set("cluster_node_id", "edificio-a").unwrap();
+        config
+            .set("cluster_peers", "10.0.0.2:9090, 10.0.0.3:9090")
+            .unwrap();
+        config.set("cluster_gossip_interval_secs", "2").unwrap();
+
+        assert!(config.is_enabled());
+        assert_eq!(config.get_peers().len(), 2);
+        assert_eq!(config.get_gossip_interval(), Duration::from_secs(2));
+        assert_eq!(config.client_id(), "rustx-cluster-edificio-a");
+    }
+
+    #[test]
+    fn test_2_valores_invalidos_son_un_error() {
+        let mut config = ClusterConfig::default();
+        assert!(config.set("cluster_peers", "no es una dirección").is_err());
+        assert!(config.set("cluster_gossip_interval_secs", "0").is_err());
+        assert!(config.set("cluster_otra_clave", "valor").is_err());
+    }
+
+    #[test]
+    fn test_3_la_tabla_de_suscripciones_se_recupera_de_su_payload() {
+        let topics: HashSet<String> = ["inc", "dron", "camera"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+
+        let payload = encode_subscriptions(&topics);
+
+        assert_eq!(payload, b"camera\ndron\ninc");
+        assert_eq!(decode_subscriptions(&payload), topics);
+        assert!(decode_subscriptions(&encode_subscriptions(&HashSet::new())).is_empty());
+    }
+
+    #[test]
+    fn test_4_los_client_ids_del_cluster_se_reconocen_por_su_prefijo() {
+        assert!(is_cluster_client(&format!(
+            "{}edificio-b",
+            CLUSTER_CLIENT_ID_PREFIX
+        )));
+        assert!(!is_cluster_client("dron-1"));
+    }
+}
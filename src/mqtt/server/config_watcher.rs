@@ -0,0 +1,124 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use notify::{event::EventKind, RecursiveMode, Watcher};
+
+use crate::logging::string_logger::StringLogger;
+
+/// Espera luego de un cambio, para que los varios eventos que genera un editor al guardar se procesen juntos.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Vigila los archivos `paths` y llama a `on_change` cada vez que alguno se crea o modifica.
+/// Se vigilan sus directorios en lugar de los archivos, ya que muchos editores guardan
+/// reemplazando el archivo por uno nuevo.
+pub fn spawn_config_watcher<F>(
+    paths: &[&str],
+    on_change: F,
+    logger: StringLogger,
+) -> Result<JoinHandle<()>, Error>
+where
+    F: Fn() + Send + 'static,
+{
+    let file_names: HashSet<OsString> = paths
+        .iter()
+        .filter_map(|path| Path::new(path).file_name())
+        .map(|name| name.to_os_string())
+        .collect();
+    let dirs: HashSet<PathBuf> = paths.iter().map(|path| parent_dir(path)).collect();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+    for dir in &dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+    }
+    logger.info(format!("Vigilando cambios en {:?}.", paths));
+
+    Ok(thread::spawn(move || {
+        // El watcher deja de vigilar al dropearse, por lo que vive mientras el hilo.
+        let _watcher = watcher;
+        while let Ok(event_res) = rx.recv() {
+            let event = match event_res {
+                Ok(event) => event,
+                Err(e) => {
+                    logger.error(format!("Error al vigilar la configuración: {:?}.", e));
+                    continue;
+                }
+            };
+            let is_change = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+            let is_watched_file = event.paths.iter().any(|path| {
+                path.file_name()
+                    .is_some_and(|name| file_names.contains(name))
+            });
+            if !(is_change && is_watched_file) {
+                continue;
+            }
+
+            thread::sleep(DEBOUNCE);
+            while rx.try_recv().is_ok() {}
+            on_change();
+        }
+    }))
+}
+
+/// Devuelve el directorio que contiene al archivo `path`, el actual si es un path relativo sin directorio.
+fn parent_dir(path: &str) -> PathBuf {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn watch_error(error: notify::Error) -> Error {
+    Error::new(
+        ErrorKind::Other,
+        format!("No se pudo vigilar el archivo: {:?}", error),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parent_dir, spawn_config_watcher};
+    use crate::logging::string_logger::StringLogger;
+    use std::{fs, path::PathBuf, sync::mpsc, time::Duration};
+
+    #[test]
+    fn test_1_al_modificar_el_archivo_se_notifica_el_cambio() {
+        let dir = std::env::temp_dir().join("rustx_config_watcher_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("server.properties");
+        fs::write(&config_path, "max_publish_rate=\"10\"\n").unwrap();
+
+        let (changes_tx, changes_rx) = mpsc::channel();
+        let (tx, _rx) = mpsc::channel::<String>();
+        spawn_config_watcher(
+            &[config_path.to_str().unwrap()],
+            move || changes_tx.send(()).unwrap(),
+            StringLogger::new(tx),
+        )
+        .unwrap();
+
+        // Un archivo no vigilado del mismo directorio no notifica.
+        fs::write(dir.join("otro.txt"), "otro").unwrap();
+        assert!(changes_rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        fs::write(&config_path, "max_publish_rate=\"20\"\n").unwrap();
+        assert!(changes_rx.recv_timeout(Duration::from_secs(2)).is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_2_un_archivo_sin_directorio_se_vigila_en_el_directorio_actual() {
+        assert_eq!(parent_dir("acl.txt"), PathBuf::from("."));
+        assert_eq!(parent_dir("conf/acl.txt"), PathBuf::from("conf"));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    io::Error,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+
+/// Límites que el servidor impone a los clientes, para que uno que se comporta mal
+/// (ie un sistema de cámaras que inunda el topic `camera`) no afecte al resto.
+/// Cada límite en None significa que no hay límite.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionLimits {
+    max_clients: Option<usize>, // clientes conectados simultáneamente.
+    max_connections_per_ip: Option<usize>, // de ellos, desde una misma ip.
+    max_publish_rate: Option<u32>, // publish por segundo de cada cliente.
+}
+
+impl ConnectionLimits {
+    pub fn new(
+        max_clients: Option<usize>,
+        max_connections_per_ip: Option<usize>,
+        max_publish_rate: Option<u32>,
+    ) -> Self {
+        ConnectionLimits {
+            max_clients,
+            max_connections_per_ip,
+            max_publish_rate,
+        }
+    }
+
+    pub fn set_max_clients(&mut self, max_clients: Option<usize>) {
+        self.max_clients = max_clients;
+    }
+
+    pub fn set_max_connections_per_ip(&mut self, max_connections_per_ip: Option<usize>) {
+        self.max_connections_per_ip = max_connections_per_ip;
+    }
+
+    pub fn set_max_publish_rate(&mut self, max_publish_rate: Option<u32>) {
+        self.max_publish_rate = max_publish_rate;
+    }
+
+    pub fn get_max_clients(&self) -> Option<usize> {
+        self.max_clients
+    }
+
+    pub fn get_max_connections_per_ip(&self) -> Option<usize> {
+        self.max_connections_per_ip
+    }
+
+    pub fn get_max_publish_rate(&self) -> Option<u32> {
+        self.max_publish_rate
+    }
+}
+
+/// Cantidad de conexiones abiertas, en total y por ip.
+#[derive(Debug, Default)]
+struct OpenConnections {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+/// Lleva la cuenta de las conexiones abiertas, para rechazar las que exceden los límites configurados.
+#[derive(Debug)]
+pub struct ConnectionTracker {
+    limits: RwLock<ConnectionLimits>, // pueden cambiar al recargar la configuración.
+    open_connections: Mutex<OpenConnections>,
+}
+
+impl ConnectionTracker {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        ConnectionTracker {
+            limits: RwLock::new(limits),
+            open_connections: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    /// Reemplaza los límites; las conexiones ya abiertas se conservan aunque los excedan.
+    pub fn set_limits(&self, limits: ConnectionLimits) -> Result<(), Error> {
+        let mut limits_l = self
+            .limits
+            .write()
+            .map_err(|_| MqttError::LockPoisoned("los límites de conexiones".to_string()))?;
+        *limits_l = limits;
+        Ok(())
+    }
+
+    /// Registra una nueva conexión desde `ip`, si no excede los límites.
+    /// Devuelve el lugar ocupado por la conexión, que se libera al dropearlo, o None si excede algún límite.
+    pub fn try_register(
+        tracker: &Arc<ConnectionTracker>,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<ConnectionSlot>, Error> {
+        let limits = *tracker
+            .limits
+            .read()
+            .map_err(|_| MqttError::LockPoisoned("los límites de conexiones".to_string()))?;
+        let mut open_connections = tracker.lock_open_connections()?;
+        if exceeds(open_connections.total, limits.max_clients) {
+            return Ok(None);
+        }
+        if let Some(ip) = ip {
+            let connections_from_ip = open_connections.by_ip.get(&ip).copied().unwrap_or(0);
+            if exceeds(connections_from_ip, limits.max_connections_per_ip) {
+                return Ok(None);
+            }
+            open_connections.by_ip.insert(ip, connections_from_ip + 1);
+        }
+        open_connections.total += 1;
+
+        Ok(Some(ConnectionSlot {
+            tracker: tracker.clone(),
+            ip,
+        }))
+    }
+
+    fn release(&self, ip: Option<IpAddr>) {
+        let Ok(mut open_connections) = self.lock_open_connections() else {
+            return;
+        };
+        open_connections.total = open_connections.total.saturating_sub(1);
+        if let Some(ip) = ip {
+            if let Some(connections_from_ip) = open_connections.by_ip.get_mut(&ip) {
+                *connections_from_ip -= 1;
+                if *connections_from_ip == 0 {
+                    open_connections.by_ip.remove(&ip);
+                }
+            }
+        }
+    }
+
+    fn lock_open_connections(&self) -> Result<MutexGuard<'_, OpenConnections>, Error> {
+        self.open_connections
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("las conexiones abiertas".to_string()).into())
+    }
+}
+
+/// Devuelve si agregar una conexión a las `open` existentes supera el límite `max`.
+fn exceeds(open: usize, max: Option<usize>) -> bool {
+    max.is_some_and(|max| open >= max)
+}
+
+/// Lugar que ocupa una conexión aceptada en el `ConnectionTracker`; se libera al dropearse,
+/// por lo que debe vivir mientras se atiende a su cliente.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    tracker: Arc<ConnectionTracker>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.tracker.release(self.ip);
+    }
+}
+
+/// Limita los publish por segundo de un cliente (token bucket): se permiten ráfagas de hasta
+/// `max_publish_rate` publish, y luego uno cada `1 / max_publish_rate` segundos.
+/// Quien lee del cliente espera el tiempo indicado antes de seguir leyendo, frenando así al cliente.
+#[derive(Debug)]
+pub struct PublishRateLimiter {
+    max_publish_rate: Option<u32>,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl PublishRateLimiter {
+    pub fn new(max_publish_rate: Option<u32>) -> Self {
+        PublishRateLimiter {
+            max_publish_rate,
+            tokens: max_publish_rate.unwrap_or(0) as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Cambia la tasa máxima, ie al recargar la configuración. Con la nueva tasa se conservan
+    /// los publish disponibles para la ráfaga, sin superar la nueva tasa.
+    pub fn set_max_publish_rate(&mut self, max_publish_rate: Option<u32>) {
+        if self.max_publish_rate == max_publish_rate {
+            return;
+        }
+        self.tokens = match (self.max_publish_rate, max_publish_rate) {
+            (Some(_), Some(rate)) => self.tokens.min(rate as f64),
+            (_, rate) => rate.unwrap_or(0) as f64,
+        };
+        self.max_publish_rate = max_publish_rate;
+    }
+
+    /// Registra un publish recibido en el instante `now`, y devuelve cuánto debe esperarse
+    /// antes de aceptar más mensajes del cliente para no superar el límite (cero si no lo supera).
+    pub fn register_publish(&mut self, now: Instant) -> Duration {
+        let Some(rate) = self.max_publish_rate.map(f64::from) else {
+            return Duration::ZERO;
+        };
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate) - 1.0;
+        self.last_refill = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConnectionLimits, ConnectionTracker, PublishRateLimiter};
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    fn ip(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last)))
+    }
+
+    #[test]
+    fn test_1_se_rechazan_las_conexiones_que_exceden_el_maximo_por_ip() {
+        let tracker = Arc::new(ConnectionTracker::new(ConnectionLimits::new(
+            None,
+            Some(2),
+            None,
+        )));
+        let first = ConnectionTracker::try_register(&tracker, ip(1)).unwrap();
+        let _second = ConnectionTracker::try_register(&tracker, ip(1)).unwrap();
+
+        assert!(first.is_some());
+        assert!(ConnectionTracker::try_register(&tracker, ip(1))
+            .unwrap()
+            .is_none());
+        assert!(ConnectionTracker::try_register(&tracker, ip(2))
+            .unwrap()
+            .is_some());
+
+        drop(first);
+        assert!(ConnectionTracker::try_register(&tracker, ip(1))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_2_se_rechazan_las_conexiones_que_exceden_el_maximo_de_clientes() {
+        let tracker = Arc::new(ConnectionTracker::new(ConnectionLimits::new(
+            Some(1),
+            None,
+            None,
+        )));
+        let first = ConnectionTracker::try_register(&tracker, ip(1)).unwrap();
+
+        assert!(ConnectionTracker::try_register(&tracker, ip(2))
+            .unwrap()
+            .is_none());
+        drop(first);
+        assert!(ConnectionTracker::try_register(&tracker, None)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_3_los_publish_que_exceden_la_tasa_deben_esperar() {
+        let mut limiter = PublishRateLimiter::new(Some(2));
+        let start = Instant::now();
+
+        assert_eq!(limiter.register_publish(start), Duration::ZERO);
+        assert_eq!(limiter.register_publish(start), Duration::ZERO);
+        assert_eq!(limiter.register_publish(start), Duration::from_millis(500));
+
+        // Pasado un segundo se recupera la tasa, descontando el publish que excedió.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.register_publish(later), Duration::ZERO);
+        assert_eq!(limiter.register_publish(later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_4_se_aplican_los_cambios_de_limites() {
+        let tracker = Arc::new(ConnectionTracker::new(ConnectionLimits::default()));
+        let _first = ConnectionTracker::try_register(&tracker, ip(1)).unwrap();
+
+        tracker
+            .set_limits(ConnectionLimits::new(Some(1), None, None))
+            .unwrap();
+        assert!(ConnectionTracker::try_register(&tracker, ip(2))
+            .unwrap()
+            .is_none());
+
+        let mut limiter = PublishRateLimiter::new(None);
+        let now = Instant::now();
+        limiter.set_max_publish_rate(Some(1));
+        assert_eq!(limiter.register_publish(now), Duration::ZERO);
+        assert_eq!(limiter.register_publish(now), Duration::from_secs(1));
+        limiter.set_max_publish_rate(None);
+        assert_eq!(limiter.register_publish(now), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_5_sin_tasa_maxima_no_se_espera() {
+        let mut limiter = PublishRateLimiter::new(None);
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert_eq!(limiter.register_publish(now), Duration::ZERO);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+use crate::mqtt::messages::connect_return_code::ConnectReturnCode;
+
+use super::file_helper::read_lines;
+
+/// Credenciales de un usuario: el hash argon2 de su contraseña, los client_id con los que puede conectarse, y
+/// opcionalmente su rol como operador de las apps.
+#[derive(Debug, Clone, PartialEq)]
+struct UserCredentials {
+    password_hash: String,
+    allowed_client_ids: Vec<String>, // patrones, admiten '*' como comodín.
+    role: Option<String>,
+}
+
+/// Almacén de credenciales del servidor, cargado desde un archivo con una línea por usuario de la forma:
+/// `usuario hash_argon2 client_id_1,client_id_2 [rol]`. Las líneas vacías o que empiezan con '#' se ignoran.
+/// El broker no usa el rol; lo usan las apps que piden a sus operadores que inicien sesión (ej monitoreo).
+#[derive(Debug, Default)]
+pub struct CredentialsStore {
+    users: HashMap<String, UserCredentials>,
+}
+
+impl CredentialsStore {
+    /// Lee el archivo de credenciales `file_path` y devuelve el almacén con sus usuarios.
+    /// Devuelve error si el archivo no puede abrirse o alguna línea tiene un formato inválido.
+    pub fn from_file(file_path: &str) -> Result<Self, Error> {
+        let lines = read_lines(Path::new(file_path))?;
+        let mut store = CredentialsStore::default();
+        for line in lines.map_while(Result::ok) {
+            store.add_line(&line)?;
+        }
+        Ok(store)
+    }
+
+    /// Parsea una línea del archivo de credenciales y agrega al usuario correspondiente.
+    fn add_line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 && parts.len() != 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Línea de credenciales inválida: {:?}", line),
+            ));
+        }
+        // Se valida que el hash sea un hash argon2 bien formado
+        PasswordHash::new(parts[1]).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Hash inválido para el usuario {:?}: {:?}", parts[0], e),
+            )
+        })?;
+
+        let credentials = UserCredentials {
+            password_hash: parts[1].to_string(),
+            allowed_client_ids: parts[2].split(',').map(|s| s.to_string()).collect(),
+            role: parts.get(3).map(|role| role.to_string()),
+        };
+        self.users.insert(parts[0].to_string(), credentials);
+        Ok(())
+    }
+
+    /// Autentica al usuario `user` con la contraseña `passwd`, y verifica que pueda conectarse con `client_id`.
+    /// Devuelve el código de retorno que debe enviarse en el connack:
+    /// `BadUsernameOrPassword` si las credenciales no son correctas,
+    /// `NotAuthorized` si son correctas pero el usuario no puede usar ese client_id,
+    /// y `ConnectionAccepted` en caso contrario.
+    pub fn authenticate(
+        &self,
+        user: Option<&String>,
+        passwd: Option<&String>,
+        client_id: &str,
+    ) -> ConnectReturnCode {
+        let (Some(user), Some(passwd)) = (user, passwd) else {
+            return ConnectReturnCode::BadUsernameOrPassword;
+        };
+        let Some(credentials) = self.verified_credentials(user, passwd) else {
+            return ConnectReturnCode::BadUsernameOrPassword;
+        };
+
+        let is_client_id_allowed = credentials
+            .allowed_client_ids
+            .iter()
+            .any(|pattern| matches_pattern(pattern, client_id));
+        if is_client_id_allowed {
+            ConnectReturnCode::ConnectionAccepted
+        } else {
+            ConnectReturnCode::NotAuthorized
+        }
+    }
+
+    /// Devuelve si `user` existe y `passwd` es su contraseña.
+    pub fn verify(&self, user: &str, passwd: &str) -> bool {
+        self.verified_credentials(user, passwd).is_some()
+    }
+
+    /// Devuelve el rol de `user`, o None si el usuario no existe o su línea no indica un rol.
+    pub fn get_role(&self, user: &str) -> Option<&str> {
+        self.users.get(user).and_then(|credentials| credentials.role.as_deref())
+    }
+
+    /// Devuelve las credenciales de `user` si existe y `passwd` es su contraseña.
+    fn verified_credentials(&self, user: &str, passwd: &str) -> Option<&UserCredentials> {
+        self.users
+            .get(user)
+            .filter(|credentials| verify_password(passwd, &credentials.password_hash))
+    }
+}
+
+/// Devuelve el hash argon2 (en formato PHC, apto para el archivo de credenciales) de la contraseña `passwd`.
+pub fn hash_password(passwd: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    match Argon2::default().hash_password(passwd.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+    }
+}
+
+/// Devuelve si la contraseña `passwd` corresponde al hash argon2 `password_hash`.
+fn verify_password(passwd: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(passwd.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Devuelve si `client_id` coincide con el patrón `pattern`, donde cada '*' representa cualquier secuencia de caracteres.
+pub fn matches_pattern(pattern: &str, client_id: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == client_id;
+    }
+
+    // El primer fragmento debe ser prefijo, y el último sufijo; los intermedios deben aparecer en orden.
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if client_id.len() < first.len() + last.len()
+        || !client_id.starts_with(first)
+        || !client_id.ends_with(last)
+    {
+        return false;
+    }
+    let mut remaining = &client_id[first.len()..client_id.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match remaining.find(part) {
+            Some(pos) => remaining = &remaining[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_password, matches_pattern, CredentialsStore};
+    use crate::mqtt::messages::connect_return_code::ConnectReturnCode;
+
+    fn create_store() -> CredentialsStore {
+        let mut store = CredentialsStore::default();
+        let line = format!(
+            "usuario1 {} dron-*,Sistema-Camaras",
+            hash_password("clave1").unwrap()
+        );
+        store.add_line(&line).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_1_credenciales_correctas_y_client_id_permitido_se_acepta() {
+        let store = create_store();
+        let code = store.authenticate(
+            Some(&"usuario1".to_string()),
+            Some(&"clave1".to_string()),
+            "dron-3",
+        );
+        assert_eq!(code, ConnectReturnCode::ConnectionAccepted);
+    }
+
+    #[test]
+    fn test_2_contrasenia_o_usuario_incorrectos_da_bad_username_or_password() {
+        let store = create_store();
+        let wrong_passwd = store.authenticate(
+            Some(&"usuario1".to_string()),
+            Some(&"otra".to_string()),
+            "dron-3",
+        );
+        let wrong_user = store.authenticate(
+            Some(&"usuario9".to_string()),
+            Some(&"clave1".to_string()),
+            "dron-3",
+        );
+        assert_eq!(wrong_passwd, ConnectReturnCode::BadUsernameOrPassword);
+        assert_eq!(wrong_user, ConnectReturnCode::BadUsernameOrPassword);
+    }
+
+    #[test]
+    fn test_3_client_id_no_permitido_da_not_authorized() {
+        let store = create_store();
+        let code = store.authenticate(
+            Some(&"usuario1".to_string()),
+            Some(&"clave1".to_string()),
+            "Sistema-Monitoreo",
+        );
+        assert_eq!(code, ConnectReturnCode::NotAuthorized);
+    }
+
+    #[test]
+    fn test_4_linea_con_formato_invalido_da_error() {
+        let mut store = CredentialsStore::default();
+        assert!(store.add_line("usuario1 clave_sin_hashear *").is_err());
+        assert!(store.add_line("usuario1").is_err());
+        assert!(store.add_line("# comentario").is_ok());
+        let line = format!("usuario2 {} * admin extra", hash_password("clave2").unwrap());
+        assert!(store.add_line(&line).is_err());
+    }
+
+    #[test]
+    fn test_5_patrones_de_client_id() {
+        assert!(matches_pattern("*", "cualquiera"));
+        assert!(matches_pattern("dron-*", "dron-12"));
+        assert!(!matches_pattern("dron-*", "camara-1"));
+        assert!(matches_pattern("*-Camaras", "Sistema-Camaras"));
+        assert!(matches_pattern("a*b*c", "axxbyyc"));
+        assert!(!matches_pattern("a*b*c", "axxc"));
+        assert!(matches_pattern("Sistema-Monitoreo", "Sistema-Monitoreo"));
+    }
+
+    #[test]
+    fn test_6_el_archivo_de_credenciales_del_proyecto_acepta_a_las_apps() {
+        let store = CredentialsStore::from_file("credentials.txt").unwrap();
+        let code = store.authenticate(
+            Some(&"usuario0".to_string()),
+            Some(&"rustx123".to_string()),
+            "dron-1",
+        );
+        assert_eq!(code, ConnectReturnCode::ConnectionAccepted);
+    }
+
+    #[test]
+    fn test_7_el_rol_es_opcional_y_se_devuelve_por_usuario() {
+        let mut store = create_store();
+        let line = format!("usuario2 {} Sistema-Monitoreo operator", hash_password("clave2").unwrap());
+        store.add_line(&line).unwrap();
+
+        assert_eq!(store.get_role("usuario2"), Some("operator"));
+        assert_eq!(store.get_role("usuario1"), None);
+        assert_eq!(store.get_role("usuario9"), None);
+        assert!(store.verify("usuario2", "clave2"));
+        assert!(!store.verify("usuario2", "clave1"));
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Prefijo de los topics en los que el servidor publica sus contadores de escritura hacia los clientes.
+const SYS_DELIVERY_TOPIC_PREFIX: &str = "$SYS/broker/delivery/";
+
+/// Contadores de la escritura hacia los clientes, compartidos por los hilos escritores de todos ellos.
+/// Como cada hilo escribe juntos los mensajes que tiene encolados, la relación entre mensajes y
+/// escrituras muestra cuántas llamadas al sistema se ahorran al distribuir los publish.
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    messages: AtomicU64, // mensajes escritos.
+    batches: AtomicU64,  // grupos de mensajes escritos juntos.
+    writes: AtomicU64,   // llamadas a write_vectored.
+    bytes: AtomicU64,
+}
+
+impl DeliveryStats {
+    /// Registra la escritura de un grupo de `messages` mensajes, de `bytes` bytes en total, en `writes` escrituras.
+    pub fn record_batch(&self, messages: usize, bytes: usize, writes: usize) {
+        self.messages.fetch_add(messages as u64, Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.writes.fetch_add(writes as u64, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn get_messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    pub fn get_batches(&self) -> u64 {
+        self.batches.load(Ordering::Relaxed)
+    }
+
+    pub fn get_writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    pub fn get_bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Devuelve los pares (topic, valor) a publicar en `$SYS`.
+    pub fn to_sys_topics(&self) -> Vec<(String, String)> {
+        [
+            ("messages", self.get_messages()),
+            ("batches", self.get_batches()),
+            ("writes", self.get_writes()),
+            ("bytes", self.get_bytes()),
+        ]
+        .iter()
+        .map(|(name, value)| {
+            (
+                format!("{}{}", SYS_DELIVERY_TOPIC_PREFIX, name),
+                value.to_string(),
+            )
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DeliveryStats;
+
+    #[test]
+    fn test_1_se_acumulan_los_grupos_escritos() {
+        let stats = DeliveryStats::default();
+        stats.record_batch(3, 60, 1);
+        stats.record_batch(1, 20, 1);
+
+        assert_eq!(stats.get_messages(), 4);
+        assert_eq!(stats.get_batches(), 2);
+        assert_eq!(stats.get_writes(), 2);
+        assert_eq!(
+            stats.to_sys_topics()[0],
+            ("$SYS/broker/delivery/messages".to_string(), "4".to_string())
+        );
+    }
+}
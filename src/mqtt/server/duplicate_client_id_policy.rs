@@ -0,0 +1,28 @@
+use std::io::{Error, ErrorKind};
+
+/// Qué hacer cuando llega un connect con un client_id que ya tiene una sesión activa en el servidor.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DuplicateClientIdPolicy {
+    /// Se desconecta la sesión anterior y se acepta la nueva (comportamiento indicado por el protocolo mqtt).
+    #[default]
+    DisconnectOld,
+    /// Se rechaza la nueva conexión, y la sesión anterior sigue activa.
+    RejectNew,
+}
+
+impl DuplicateClientIdPolicy {
+    /// Devuelve la política correspondiente al valor del archivo de configuración: `disconnect_old` o `reject_new`.
+    pub fn from_config_value(value: &str) -> Result<Self, Error> {
+        match value {
+            "disconnect_old" => Ok(DuplicateClientIdPolicy::DisconnectOld),
+            "reject_new" => Ok(DuplicateClientIdPolicy::RejectNew),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Valor inválido para duplicate_client_id_policy: {:?}",
+                    value
+                ),
+            )),
+        }
+    }
+}
@@ -1,8 +1,30 @@
-use std::{io::Error, net::TcpListener, result::Result, thread::JoinHandle};
+use std::{
+    io::Error, net::TcpListener, result::Result, sync::mpsc::SyncSender, thread::JoinHandle,
+};
 
-use crate::{logging::string_logger::StringLogger, mqtt::stream_type::StreamType};
+use crate::{
+    logging::string_logger::StringLogger,
+    mqtt::{loopback::LoopbackListener, stream_type::StreamType},
+};
 
-use super::{client_reader::ClientReader, mqtt_server::MQTTServer};
+use super::{client_reader::ClientReader, mqtt_server::MQTTServer, packet::Packet};
+
+/// Por dónde el server acepta las conexiones de los clientes: por tcp, o en memoria si corren en el mismo proceso.
+#[derive(Debug)]
+pub enum ServerListener {
+    Tcp(TcpListener),
+    Loopback(LoopbackListener),
+}
+
+impl ServerListener {
+    /// Iterador sobre las conexiones entrantes.
+    fn incoming(&self) -> Box<dyn Iterator<Item = Result<StreamType, Error>> + '_> {
+        match self {
+            ServerListener::Tcp(listener) => Box::new(listener.incoming().map(|stream| stream.map(StreamType::from))),
+            ServerListener::Loopback(listener) => Box::new(listener.incoming().map(|stream| stream.map(StreamType::from))),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ClientListener {
@@ -16,19 +38,19 @@ impl ClientListener {
 
     pub fn handle_incoming_connections(
         &mut self,
-        listener: TcpListener,
+        listener: ServerListener,
         mqtt_server: MQTTServer,
+        packets_tx: SyncSender<Packet>,
     ) -> Result<(), Error> {
         let mut handles = Vec::<JoinHandle<()>>::new();
-        println!("Servidor iniciado. Esperando conexiones.\n");
-        self.logger.log("Servidor iniciado. Esperando conexiones.".to_string());
+        self.logger.info("Servidor iniciado. Esperando conexiones.".to_string());
         for stream in listener.incoming() {
-            handles.push(self.handle_stream(stream?, mqtt_server.clone_ref())?);
+            handles.push(self.handle_stream(stream?, mqtt_server.clone_ref(), packets_tx.clone())?);
         }
 
         for h in handles {
             if let Err(e) = h.join() {
-                self.logger.log(format!("Error al esperar a hilo, en handle_incoming_connections: {:?}.", e));
+                self.logger.error(format!("Error al esperar a hilo, en handle_incoming_connections: {:?}.", e));
             }
         }
 
@@ -39,16 +61,16 @@ impl ClientListener {
         &mut self,
         mut stream: StreamType,
         mqtt_server: MQTTServer,
+        packets_tx: SyncSender<Packet>,
     ) -> Result<JoinHandle<()>, Error> {
-        println!("DEBUG: CREANDO NUEVO CLIENT READER");
-        self.logger.log("Creando nuevo client reader.".to_string());
-        let mut client_reader = ClientReader::new(stream.try_clone()?, mqtt_server, self.logger.clone_ref())?; //
+        self.logger.debug("Creando nuevo client reader.".to_string());
+        let mut client_reader = ClientReader::new(stream.try_clone()?, mqtt_server, packets_tx, self.logger.clone_ref())?; //
 
         // Hilo para cada cliente
         let logger_c = self.logger.clone_ref();
         Ok(std::thread::spawn(move || {
             if let Err(e) = client_reader.handle_client(&mut stream) {
-                logger_c.log(format!("Error al esperar a hilo, en handle_stream: {:?}.", e));
+                logger_c.error(format!("Error al esperar a hilo, en handle_stream: {:?}.", e));
             }
 
         }))
@@ -0,0 +1,435 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Error, ErrorKind, Read, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+
+const PUBLISH_RECORD: u8 = 1;
+/// Suscripción sin qos, escrita cuando el servidor otorgaba siempre qos 1; se sigue leyendo como tal.
+const LEGACY_SUBSCRIBE_RECORD: u8 = 2;
+const UNSUBSCRIBE_RECORD: u8 = 3;
+const SESSION_REMOVED_RECORD: u8 = 4;
+const SUBSCRIBE_RECORD: u8 = 5;
+
+/// Cada cuánto se fuerza la escritura a disco de los registros del journal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JournalSyncPolicy {
+    /// Se sincroniza luego de cada registro: no se pierde nada, pero es lo más lento.
+    Always,
+    /// Se sincroniza cada tantos registros.
+    EveryRecords(usize),
+    /// No se sincroniza explícitamente, lo decide el sistema operativo.
+    Never,
+}
+
+impl JournalSyncPolicy {
+    /// Devuelve la política correspondiente al valor del archivo de configuración:
+    /// `always`, `never`, o la cantidad de registros entre sincronizaciones.
+    pub fn from_config_value(value: &str) -> Result<Self, Error> {
+        match value {
+            "always" => Ok(JournalSyncPolicy::Always),
+            "never" => Ok(JournalSyncPolicy::Never),
+            _ => match value.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(JournalSyncPolicy::EveryRecords(n)),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Valor inválido para journal_sync: {:?}", value),
+                )),
+            },
+        }
+    }
+}
+
+/// Un cambio de estado del servidor que debe sobrevivir a un reinicio.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalRecord {
+    /// Publish de qos mayor a 0 almacenado en el server.
+    Publish(PublishMessage),
+    /// Suscripción con el qos otorgado.
+    Subscribe {
+        client_id: String,
+        topic: String,
+        qos: u8,
+    },
+    Unsubscribe {
+        client_id: String,
+        topic: String,
+    },
+    /// El server eliminó la sesión del cliente, y con ella sus suscripciones.
+    SessionRemoved {
+        client_id: String,
+    },
+}
+
+impl JournalRecord {
+    /// Devuelve los bytes del registro: | tipo (1 byte) | longitud (4 bytes) | contenido |.
+    fn to_bytes(&self) -> Vec<u8> {
+        let (record_type, content) = match self {
+            JournalRecord::Publish(msg) => (PUBLISH_RECORD, msg.to_bytes()),
+            JournalRecord::Subscribe {
+                client_id,
+                topic,
+                qos,
+            } => {
+                let mut content = vec![*qos];
+                content.extend(strings_to_bytes(&[client_id, topic]));
+                (SUBSCRIBE_RECORD, content)
+            }
+            JournalRecord::Unsubscribe { client_id, topic } => {
+                (UNSUBSCRIBE_RECORD, strings_to_bytes(&[client_id, topic]))
+            }
+            JournalRecord::SessionRemoved { client_id } => {
+                (SESSION_REMOVED_RECORD, strings_to_bytes(&[client_id]))
+            }
+        };
+
+        let mut bytes = vec![record_type];
+        bytes.extend((content.len() as u32).to_be_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    /// Reconstruye el registro de tipo `record_type` a partir de su contenido.
+    fn from_bytes(record_type: u8, content: &[u8]) -> Result<Self, Error> {
+        match record_type {
+            PUBLISH_RECORD => Ok(JournalRecord::Publish(PublishMessage::from_bytes(
+                content.to_vec(),
+            )?)),
+            SUBSCRIBE_RECORD => {
+                let Some((qos, content)) = content.split_first() else {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Registro de journal incompleto.",
+                    ));
+                };
+                let mut strings = strings_from_bytes(content, 2)?.into_iter();
+                Ok(JournalRecord::Subscribe {
+                    client_id: next_string(&mut strings)?,
+                    topic: next_string(&mut strings)?,
+                    qos: *qos,
+                })
+            }
+            LEGACY_SUBSCRIBE_RECORD | UNSUBSCRIBE_RECORD => {
+                let mut strings = strings_from_bytes(content, 2)?.into_iter();
+                let (client_id, topic) = (next_string(&mut strings)?, next_string(&mut strings)?);
+                if record_type == LEGACY_SUBSCRIBE_RECORD {
+                    Ok(JournalRecord::Subscribe {
+                        client_id,
+                        topic,
+                        qos: 1,
+                    })
+                } else {
+                    Ok(JournalRecord::Unsubscribe { client_id, topic })
+                }
+            }
+            SESSION_REMOVED_RECORD => {
+                let mut strings = strings_from_bytes(content, 1)?.into_iter();
+                Ok(JournalRecord::SessionRemoved {
+                    client_id: next_string(&mut strings)?,
+                })
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Tipo de registro de journal desconocido: {}", record_type),
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    file: File,
+    records_since_sync: usize,
+    records_since_compaction: usize,
+}
+
+/// Write-ahead log del servidor: persiste en disco los publish de qos mayor a 0 y los cambios de suscripciones,
+/// para poder reconstruir ese estado al reiniciar el servidor.
+/// Para que el archivo no crezca indefinidamente, se compacta reescribiéndolo con el estado actual.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    sync_policy: JournalSyncPolicy,
+    compaction_threshold: usize, // cantidad de registros agregados luego de la cual conviene compactar.
+    journal_file: Mutex<JournalFile>,
+}
+
+impl Journal {
+    /// Abre (o crea) el journal en `path`, y devuelve los registros que ya contenía, en orden, para ser reaplicados.
+    /// Un registro incompleto al final del archivo (ie el servidor se cortó mientras lo escribía) se descarta.
+    pub fn open(
+        path: &str,
+        sync_policy: JournalSyncPolicy,
+        compaction_threshold: usize,
+    ) -> Result<(Self, Vec<JournalRecord>), Error> {
+        let records = match File::open(path) {
+            Ok(mut file) => {
+                let mut bytes = vec![];
+                file.read_to_end(&mut bytes)?;
+                records_from_bytes(&bytes)?
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let journal = Journal {
+            path: PathBuf::from(path),
+            sync_policy,
+            compaction_threshold,
+            journal_file: Mutex::new(JournalFile {
+                file,
+                records_since_sync: 0,
+                records_since_compaction: records.len(),
+            }),
+        };
+        Ok((journal, records))
+    }
+
+    /// Agrega el registro al final del journal, sincronizando a disco según la política configurada.
+    pub fn append(&self, record: &JournalRecord) -> Result<(), Error> {
+        let mut journal_file = self.lock_file()?;
+        journal_file.file.write_all(&record.to_bytes())?;
+        journal_file.records_since_sync += 1;
+        journal_file.records_since_compaction += 1;
+
+        let must_sync = match self.sync_policy {
+            JournalSyncPolicy::Always => true,
+            JournalSyncPolicy::EveryRecords(n) => journal_file.records_since_sync >= n,
+            JournalSyncPolicy::Never => false,
+        };
+        if must_sync {
+            journal_file.file.sync_data()?;
+            journal_file.records_since_sync = 0;
+        }
+        Ok(())
+    }
+
+    /// Devuelve si se agregaron suficientes registros desde la última compactación como para volver a compactar.
+    pub fn needs_compaction(&self) -> bool {
+        match self.journal_file.lock() {
+            Ok(journal_file) => journal_file.records_since_compaction >= self.compaction_threshold,
+            Err(_) => false,
+        }
+    }
+
+    /// Reemplaza el contenido del journal por `snapshot`, los registros que describen el estado actual del servidor.
+    /// Se escribe primero a un archivo temporal que luego se renombra, para no perder el journal si se corta a mitad.
+    pub fn compact(&self, snapshot: &[JournalRecord]) -> Result<(), Error> {
+        let mut journal_file = self.lock_file()?;
+
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        for record in snapshot {
+            tmp_file.write_all(&record.to_bytes())?;
+        }
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        journal_file.file = OpenOptions::new().append(true).open(&self.path)?;
+        journal_file.records_since_sync = 0;
+        journal_file.records_since_compaction = 0;
+        Ok(())
+    }
+
+    fn lock_file(&self) -> Result<std::sync::MutexGuard<'_, JournalFile>, Error> {
+        self.journal_file.lock().map_err(|_| {
+            Error::new(
+                ErrorKind::Other,
+                "Error: no se pudo tomar lock al archivo del journal.",
+            )
+        })
+    }
+}
+
+/// Parsea todos los registros completos de `bytes`, descartando un posible registro incompleto al final.
+fn records_from_bytes(bytes: &[u8]) -> Result<Vec<JournalRecord>, Error> {
+    let mut records = vec![];
+    let mut idx = 0;
+    while idx + 5 <= bytes.len() {
+        let record_type = bytes[idx];
+        let len_bytes = [
+            bytes[idx + 1],
+            bytes[idx + 2],
+            bytes[idx + 3],
+            bytes[idx + 4],
+        ];
+        let content_len = u32::from_be_bytes(len_bytes) as usize;
+        let content_start = idx + 5;
+        if content_start + content_len > bytes.len() {
+            break;
+        }
+        let content = &bytes[content_start..content_start + content_len];
+        records.push(JournalRecord::from_bytes(record_type, content)?);
+        idx = content_start + content_len;
+    }
+    Ok(records)
+}
+
+/// Concatena los strings, cada uno precedido por su longitud en 2 bytes.
+fn strings_to_bytes(strings: &[&String]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for s in strings {
+        bytes.extend((s.len() as u16).to_be_bytes());
+        bytes.extend(s.as_bytes());
+    }
+    bytes
+}
+
+/// Lee `count` strings precedidos por su longitud en 2 bytes.
+fn strings_from_bytes(bytes: &[u8], count: usize) -> Result<Vec<String>, Error> {
+    let mut strings = vec![];
+    let mut idx = 0;
+    for _ in 0..count {
+        if idx + 2 > bytes.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Registro de journal incompleto.",
+            ));
+        }
+        let len = u16::from_be_bytes([bytes[idx], bytes[idx + 1]]) as usize;
+        idx += 2;
+        let Some(string_bytes) = bytes.get(idx..idx + len) else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Registro de journal incompleto.",
+            ));
+        };
+        let string = String::from_utf8(string_bytes.to_vec())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        strings.push(string);
+        idx += len;
+    }
+    Ok(strings)
+}
+
+fn next_string(strings: &mut impl Iterator<Item = String>) -> Result<String, Error> {
+    strings
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Registro de journal incompleto."))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        strings_to_bytes, Journal, JournalRecord, JournalSyncPolicy, LEGACY_SUBSCRIBE_RECORD,
+    };
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use std::{fs, io::Write};
+
+    fn temp_journal_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rustx_journal_test_{}.bin", name));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    fn create_records() -> Vec<JournalRecord> {
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(3), "incidente".as_bytes()).unwrap();
+        vec![
+            JournalRecord::Subscribe {
+                client_id: "dron-1".to_string(),
+                topic: "inc".to_string(),
+                qos: 0,
+            },
+            JournalRecord::Publish(msg),
+            JournalRecord::Unsubscribe {
+                client_id: "dron-1".to_string(),
+                topic: "inc".to_string(),
+            },
+            JournalRecord::SessionRemoved {
+                client_id: "dron-1".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_1_los_registros_agregados_se_recuperan_al_reabrir() {
+        let path = temp_journal_path("reabrir");
+        let records = create_records();
+        {
+            let (journal, previous) = Journal::open(&path, JournalSyncPolicy::Always, 100).unwrap();
+            assert!(previous.is_empty());
+            for record in &records {
+                journal.append(record).unwrap();
+            }
+        }
+
+        let (_journal, recovered) = Journal::open(&path, JournalSyncPolicy::Always, 100).unwrap();
+        assert_eq!(recovered, records);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_2_un_registro_incompleto_al_final_se_descarta() {
+        let path = temp_journal_path("incompleto");
+        let records = create_records();
+        {
+            let (journal, _) = Journal::open(&path, JournalSyncPolicy::Never, 100).unwrap();
+            journal.append(&records[0]).unwrap();
+        }
+        // Simula un corte a mitad de escritura de un registro
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[1, 0, 0, 0, 50, 1, 2]).unwrap();
+
+        let (_journal, recovered) = Journal::open(&path, JournalSyncPolicy::Never, 100).unwrap();
+        assert_eq!(recovered, vec![records[0].clone()]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_3_compactar_reemplaza_el_contenido_por_el_snapshot() {
+        let path = temp_journal_path("compactar");
+        let records = create_records();
+        let (journal, _) = Journal::open(&path, JournalSyncPolicy::EveryRecords(2), 3).unwrap();
+        for record in &records {
+            journal.append(record).unwrap();
+        }
+        assert!(journal.needs_compaction());
+
+        journal.compact(&records[1..2]).unwrap();
+        assert!(!journal.needs_compaction());
+        journal.append(&records[0]).unwrap();
+
+        let (_journal, recovered) = Journal::open(&path, JournalSyncPolicy::Never, 3).unwrap();
+        assert_eq!(recovered, vec![records[1].clone(), records[0].clone()]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_4_politica_de_sincronizacion_desde_configuracion() {
+        assert_eq!(
+            JournalSyncPolicy::from_config_value("always").unwrap(),
+            JournalSyncPolicy::Always
+        );
+        assert_eq!(
+            JournalSyncPolicy::from_config_value("10").unwrap(),
+            JournalSyncPolicy::EveryRecords(10)
+        );
+        assert!(JournalSyncPolicy::from_config_value("0").is_err());
+    }
+
+    #[test]
+    fn test_5_las_suscripciones_sin_qos_de_journals_anteriores_se_leen_con_qos_1() {
+        let path = temp_journal_path("suscripcion_sin_qos");
+        let content = strings_to_bytes(&[&"dron-1".to_string(), &"inc".to_string()]);
+        let mut bytes = vec![LEGACY_SUBSCRIBE_RECORD];
+        bytes.extend((content.len() as u32).to_be_bytes());
+        bytes.extend(content);
+        fs::write(&path, bytes).unwrap();
+
+        let (_journal, recovered) = Journal::open(&path, JournalSyncPolicy::Never, 100).unwrap();
+        assert_eq!(
+            recovered,
+            vec![JournalRecord::Subscribe {
+                client_id: "dron-1".to_string(),
+                topic: "inc".to_string(),
+                qos: 1,
+            }]
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,16 @@
+use std::net::SocketAddr;
+
+/// Devuelve el contexto con el que se loggean los eventos de la conexión de un cliente,
+/// ie `addr=127.0.0.1:5000 client_id=dron-1`. Antes del connect todavía no se conoce el client_id.
+pub fn connection_log_context(peer_addr: Option<SocketAddr>, client_id: Option<&str>) -> String {
+    let peer_addr = peer_addr.map_or("-".to_string(), |addr| addr.to_string());
+    match client_id {
+        Some(client_id) => format!("addr={} {}", peer_addr, client_id_log_context(client_id)),
+        None => format!("addr={}", peer_addr),
+    }
+}
+
+/// Contexto que se agrega al de la conexión una vez conocido el client_id.
+pub fn client_id_log_context(client_id: &str) -> String {
+    format!("client_id={}", client_id)
+}
@@ -3,13 +3,40 @@ use rustx::mqtt::server::mqtt_server::MQTTServer;
 use std::env::args;
 use std::io::{Error, ErrorKind};
 
-/// Lee el puerto por la consola, y devuelve la dirección IP y el puerto.
-pub fn load_port() -> Result<(String, u16), Error> {
+const USAGE: &str = "Uso: message_broker_server <puerto> [--state-dir <directorio>] [--fresh]";
+
+/// Opciones con las que se inicia el servidor, leídas por la consola.
+#[derive(Debug, Default)]
+pub struct StartupOptions {
+    state_dir: Option<String>, // donde se guarda el estado al cerrar, y desde donde se restaura al iniciar.
+    fresh: bool,               // si se inicia sin restaurar el estado guardado (ie porque está corrupto).
+}
+
+/// Lee el puerto y las opciones por la consola, y devuelve la dirección IP, el puerto y las opciones.
+pub fn load_args() -> Result<(String, u16, StartupOptions), Error> {
     let argv = args().collect::<Vec<String>>();
-    if argv.len() != 2 {
-        return Err(Error::new(ErrorKind::InvalidInput, "Cantidad de argumentos inválido. Debe ingresar el puerto en el que desea correr el servidor."));
+    if argv.len() < 2 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Cantidad de argumentos inválido. Debe ingresar el puerto en el que desea correr el servidor.\n{}", USAGE)));
+    }
+    let (ip, port) = load_port(&argv[1])?;
+    let mut options = StartupOptions::default();
+    let mut remaining = argv[2..].iter();
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--state-dir" => match remaining.next() {
+                Some(state_dir) => options.state_dir = Some(state_dir.to_string()),
+                None => return Err(Error::new(ErrorKind::InvalidInput, format!("Falta el directorio de --state-dir.\n{}", USAGE))),
+            },
+            "--fresh" => options.fresh = true,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("Argumento inválido: {:?}.\n{}", arg, USAGE))),
+        }
     }
-    let port = match argv[1].parse::<u16>() {
+    Ok((ip, port, options))
+}
+
+/// Parsea el puerto, y devuelve la dirección IP y el puerto.
+pub fn load_port(port_arg: &str) -> Result<(String, u16), Error> {
+    let port = match port_arg.parse::<u16>() {
         Ok(port) => port,
         Err(_) => {
             return Err(Error::new(
@@ -25,12 +52,20 @@ pub fn load_port() -> Result<(String, u16), Error> {
 
 
 fn main() -> Result<(), Error> {
-    let (ip, port) = load_port()?;
+    let (ip, port, options) = load_args()?;
 
     // Se crean y configuran ambos extremos del string logger
     let (mut logger, handle_logger) = StringLogger::create_logger(get_formatted_app_id());
 
-    let mqtt_server = MQTTServer::new(logger.clone_ref());
+    let mut mqtt_server = MQTTServer::new(logger.clone_ref());
+    if let Some(state_dir) = &options.state_dir {
+        if let Err(e) = mqtt_server.open_state_dir(state_dir, options.fresh) {
+            if e.kind() == ErrorKind::InvalidData {
+                println!("No se pudo restaurar el estado guardado. Iniciar con --fresh para descartarlo.");
+            }
+            return Err(e);
+        }
+    }
     mqtt_server.run(ip, port)?;
 
     // Se cierra el logger
@@ -1,24 +1,41 @@
-use std::sync::mpsc::Receiver;
-
-//use rayon::ThreadPool;
-
-use rayon::ThreadPool;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+};
 
 use crate::mqtt::messages::{
-        packet_type::PacketType, puback_message::PubAckMessage, publish_message::PublishMessage,
-        subscribe_message::SubscribeMessage, subscribe_return_code::SubscribeReturnCode,
+        packet_type::PacketType, pingreq_message::PingReqMessage, puback_message::{PubAckMessage, PUBACK_NOT_AUTHORIZED, PUBACK_SUCCESS}, pubcomp_message::PubCompMessage,
+        publish_message::PublishMessage, pubrec_message::PubRecMessage,
+        pubrel_message::PubRelMessage, subscribe_message::SubscribeMessage,
+        subscribe_return_code::SubscribeReturnCode, unsubscribe_message::UnsubscribeMessage,
 };
 
 use std::io::Error;
 
+use crate::logging::string_logger::StringLogger;
+
 use super::{
     mqtt_server::MQTTServer,
     packet::Packet,
 };
 
+/// Cantidad de packet ids de publish qos 1 recientes que se recuerdan por cliente.
+const RECENT_PACKET_IDS_LEN: usize = 64;
+type RecentPacketIds = Arc<Mutex<HashMap<String, VecDeque<u16>>>>; // String = client_id.
+/// Paquetes asignados a cada hilo que pueden esperar a ser procesados, antes de frenar el reparto.
+const WORKER_QUEUE_LEN: usize = 64;
+
 #[derive(Debug)]
 pub struct MessageProcessor {
     mqtt_server: MQTTServer,
+    recent_packet_ids: RecentPacketIds, // para detectar las retransmisiones de publish qos 1 ya distribuidos.
+    logger: StringLogger,
 }
 
 // fn contains_dron(input: &str) -> bool {
@@ -26,69 +43,138 @@ pub struct MessageProcessor {
 // }
 
 impl MessageProcessor {
-    pub fn new(mqtt_server: MQTTServer) -> Self {
-        MessageProcessor { mqtt_server }
+    pub fn new(mqtt_server: MQTTServer, logger: StringLogger) -> Self {
+        MessageProcessor {
+            mqtt_server,
+            recent_packet_ids: Arc::new(Mutex::new(HashMap::new())),
+            logger,
+        }
     }
 
-    pub fn handle_packets(&mut self, rx_1: Receiver<Packet>) -> Result<(), Error> {
+    /// Procesa los paquetes de todos los clientes recibidos por `rx`, con `num_threads` hilos.
+    /// Los paquetes de un mismo cliente se procesan siempre en el mismo hilo, en el orden en que llegaron, para
+    /// que se distribuyan en ese orden (ie que un incidente resuelto no llegue antes que el incidente).
+    /// Termina cuando se cierran todos los extremos de envío.
+    pub fn handle_packets(&self, rx: Receiver<Packet>, num_threads: usize) {
+        let self_clone = Arc::new(self.clone_ref());
+        let handles = process_in_order_by_client(rx, num_threads, self.logger.clone_ref(), move |packet| {
+            self_clone.process_packet(packet)
+        });
 
-        // Con threadpool sería:
-        match create_thread_pool_with(20) {
-            Ok(thread_pool) => {
-                for packet in rx_1 {
-                    let self_clone = self.clone_ref();
-                    thread_pool.spawn(move || {
-                        self_clone.process_packet(packet);
-                    });
-                }
-            }
-            Err(e) => {
-                println!("   ERROR: {:?}", e);
-                for packet in rx_1 {
-                    self.process_packet(packet);
-                }
+        for h in handles {
+            if let Err(e) = h.join() {
+                self.logger.error(format!("Error al esperar hilo del message processor: {:?}", e));
             }
         }
-
-        // Sin threadpool era:
-        /*for packet in rx_1 {
-            self.process_packet(packet); // Ejecuta en el hilo actual
-        }*/     
-
-        Ok(())
     }
 
+    /// Procesa el paquete, loggeando con el contexto de su cliente y su tipo.
     fn process_packet(&self, packet: Packet) {
+        let logger = self.logger.with_context(&packet.log_context());
         let msg_bytes = packet.get_msg_bytes();
         let client_id = packet.get_username();
         match packet.get_message_type() {
-            PacketType::Publish => self.handle_publish(msg_bytes, client_id),
-            PacketType::Subscribe => self.handle_subscribe(msg_bytes, client_id),
-            PacketType::Puback => self.handle_puback(msg_bytes),
-            _ => println!("   ERROR: Tipo de mensaje desconocido\n "),
+            PacketType::Publish => self.handle_publish(msg_bytes, client_id, &logger),
+            PacketType::Subscribe => self.handle_subscribe(msg_bytes, client_id, &logger),
+            PacketType::Unsubscribe => self.handle_unsubscribe(msg_bytes, client_id, &logger),
+            PacketType::Puback => self.handle_puback(msg_bytes, client_id, &logger),
+            PacketType::Pubrec => self.handle_pubrec(msg_bytes, client_id, &logger),
+            PacketType::Pubrel => self.handle_pubrel(msg_bytes, client_id, &logger),
+            PacketType::Pubcomp => self.handle_pubcomp(msg_bytes, &logger),
+            PacketType::Pingreq => self.handle_pingreq(msg_bytes, client_id, &logger),
+            _ => logger.error("Tipo de mensaje desconocido.".to_string()),
         };
     }
 
-    fn handle_publish(&self, msg_bytes: Vec<u8>, client_id: &str) {
-        let publish_msg_res = PublishMessage::from_bytes(msg_bytes);
+    fn handle_publish(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
+        let protocol_version = self.mqtt_server.get_protocol_version_of(client_id);
+        let publish_msg_res = PublishMessage::from_bytes_with_version(msg_bytes, protocol_version);
         match publish_msg_res {
             Ok(publish_msg) => {
-                println!("Publish recibido, topic: {:?}, packet_id: {:?}", publish_msg.get_topic(), publish_msg.get_packet_id());
-                let puback_res = self.send_puback_to(client_id, &publish_msg);
+                logger.debug(format!("Publish recibido, topic: {:?}, packet_id: {:?}", publish_msg.get_topic(), publish_msg.get_packet_id()));
+                if publish_msg.get_qos() == 2 {
+                    self.handle_qos2_publish(&publish_msg, client_id, logger);
+                    return;
+                }
+                // Se envía el ack igual, para que el cliente no retransmita, pero el publish no autorizado se descarta
+                // (en mqtt 5, el reason code del ack le informa el motivo).
+                let is_allowed = self.is_publish_allowed(&publish_msg, client_id, logger);
+                let reason_code = if is_allowed { PUBACK_SUCCESS } else { PUBACK_NOT_AUTHORIZED };
+                let puback_res = self.send_puback_to(client_id, &publish_msg, reason_code);
                 if let Err(e) = puback_res {
-                    println!("   Error en handle_publish: {:?}", e);
+                    logger.error(format!("Error en handle_publish: {:?}", e));
+                }
+                if !is_allowed {
+                    return;
                 }
-                if let Err(e) = self.mqtt_server.handle_publish_message(&publish_msg){
+                // Una retransmisión de un publish ya recibido se confirma, pero no se redistribuye.
+                if self.is_retransmitted_publish(&publish_msg, client_id) {
+                    logger.debug(format!("Publish qos 1 duplicado, no se redistribuye, packet_id: {:?}", publish_msg.get_packet_id()));
+                    return;
+                }
+                if let Err(e) = self.mqtt_server.handle_publish_from(client_id, &publish_msg){
                     // No quiero retornar si falló alguna operación hacia Un user, solamente logguearlo.
-                    println!("   Error en handle_publish: {:?}", e);
+                    logger.error(format!("Error en handle_publish: {:?}", e));
                 };                
 
             }
-            Err(e) => println!("   Error en handle_publish: {:?}", e),
+            Err(e) => logger.error(format!("Error en handle_publish: {:?}", e)),
+        }
+    }
+
+    /// Maneja un publish de qos 2: lo distribuye solamente la primera vez que se recibe su packet_id
+    /// (hasta que llegue su pubrel), y responde con pubrec.
+    fn handle_qos2_publish(&self, publish_msg: &PublishMessage, client_id: &str, logger: &StringLogger) {
+        let packet_id = publish_msg.get_packet_id().unwrap_or(0);
+        match self.mqtt_server.register_qos2_publish(client_id, packet_id) {
+            Ok(true) if self.is_publish_allowed(publish_msg, client_id, logger) => {
+                if let Err(e) = self.mqtt_server.handle_publish_from(client_id, publish_msg) {
+                    logger.error(format!("Error en handle_qos2_publish: {:?}", e));
+                }
+            }
+            Ok(true) => {} // Publish no autorizado, se descarta.
+            Ok(false) => logger.debug(format!("Publish qos 2 duplicado, no se redistribuye, packet_id: {:?}", packet_id)),
+            Err(e) => logger.error(format!("Error en handle_qos2_publish: {:?}", e)),
+        }
+        if let Err(e) = self.mqtt_server.send_pubrec_to(client_id, packet_id) {
+            logger.error(format!("Error en handle_qos2_publish: {:?}", e));
         }
     }
 
-    fn handle_subscribe(&self, msg_bytes: Vec<u8>, client_id: &str) {
+    /// Registra el packet id del publish entre los recientes del cliente, y devuelve si el publish es una
+    /// retransmisión (con el flag de dup) de uno ya recibido. Un publish sin dup con un packet id reciente
+    /// es un mensaje nuevo, cuyo packet id el cliente reutilizó luego de recibir el puback.
+    fn is_retransmitted_publish(&self, publish_msg: &PublishMessage, client_id: &str) -> bool {
+        let Some(packet_id) = publish_msg.get_packet_id() else {
+            return false;
+        };
+        let Ok(mut recent_packet_ids) = self.recent_packet_ids.lock() else {
+            return false;
+        };
+        let client_packet_ids = recent_packet_ids.entry(client_id.to_string()).or_default();
+        if publish_msg.is_dup() && client_packet_ids.contains(&packet_id) {
+            return true;
+        }
+        client_packet_ids.retain(|id| *id != packet_id);
+        client_packet_ids.push_back(packet_id);
+        if client_packet_ids.len() > RECENT_PACKET_IDS_LEN {
+            client_packet_ids.pop_front();
+        }
+        false
+    }
+
+    /// Devuelve si el cliente tiene permiso para publicar en el topic del mensaje.
+    fn is_publish_allowed(&self, publish_msg: &PublishMessage, client_id: &str, logger: &StringLogger) -> bool {
+        match self.mqtt_server.is_allowed_to_publish(client_id, &publish_msg.get_topic()) {
+            Ok(is_allowed) => is_allowed,
+            Err(e) => {
+                logger.error(format!("Error al verificar el permiso de publish: {:?}", e));
+                false
+            }
+        }
+    }
+
+    fn handle_subscribe(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
         let subscribe_msg_res = SubscribeMessage::from_bytes(msg_bytes);
         match subscribe_msg_res {
             Ok(msg) => {
@@ -97,23 +183,88 @@ impl MessageProcessor {
                     .mqtt_server
                     .send_preexisting_msgs_to_new_subscriber(client_id, &msg);
                 if let Err(e) = operation_result {
-                    println!("   ERROR: {:?}", e);
+                    logger.error(format!("Error al enviar los mensajes preexistentes: {:?}", e));
                 }
                 let packet_id = msg.get_packet_id();
                 let suback_res = self.send_suback_to(client_id, return_codes_res, packet_id);
                 if let Err(e) = suback_res {
-                    println!("   ERROR: {:?}", e);
+                    logger.error(format!("Error al enviar el suback: {:?}", e));
+                }
+            }
+            Err(e) => logger.error(format!("Error en handle_subscribe: {:?}", e)),
+        }
+    }
+
+    fn handle_unsubscribe(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
+        match UnsubscribeMessage::from_bytes(msg_bytes) {
+            Ok(msg) => {
+                if let Err(e) = self.mqtt_server.remove_topics_from_subscriber(client_id, &msg) {
+                    logger.error(format!("Error al quitar los topics: {:?}", e));
+                }
+                if let Err(e) = self.mqtt_server.send_unsuback_to(client_id, msg.get_packet_id()) {
+                    logger.error(format!("Error al enviar el unsuback: {:?}", e));
                 }
             }
-            Err(e) => println!("   ERROR: {:?}", e),
+            Err(e) => logger.error(format!("Error en handle_unsubscribe: {:?}", e)),
         }
     }
 
-    fn handle_puback(&self, msg_bytes: Vec<u8>) {
+    /// Un suscriptor confirmó un publish de qos 1 que le enviamos.
+    fn handle_puback(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
         let puback_msg_res = PubAckMessage::msg_from_bytes(msg_bytes);
         match puback_msg_res {
-            Ok(puback_msg) => println!("Pub ack recibido, packet_id: {:?}", puback_msg.get_packet_id()),
-            Err(e) => println!("   ERROR: {:?}", e),
+            Ok(puback_msg) => {
+                logger.debug(format!("Pub ack recibido, packet_id: {:?}", puback_msg.get_packet_id()));
+                if let Err(e) = self.mqtt_server.handle_puback_from(client_id, puback_msg.get_packet_id()) {
+                    logger.error(format!("Error en handle_puback: {:?}", e));
+                }
+            }
+            Err(e) => logger.error(format!("Error en handle_puback: {:?}", e)),
+        }
+    }
+
+    /// Un suscriptor recibió un publish de qos 2 que le enviamos, se le responde con pubrel.
+    fn handle_pubrec(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
+        match PubRecMessage::msg_from_bytes(msg_bytes) {
+            Ok(pubrec_msg) => {
+                if let Err(e) = self.mqtt_server.send_pubrel_to(client_id, pubrec_msg.get_packet_id()) {
+                    logger.error(format!("Error en handle_pubrec: {:?}", e));
+                }
+            }
+            Err(e) => logger.error(format!("Error en handle_pubrec: {:?}", e)),
+        }
+    }
+
+    /// El publicador de un publish de qos 2 liberó su packet_id, se le responde con pubcomp.
+    fn handle_pubrel(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
+        match PubRelMessage::msg_from_bytes(msg_bytes) {
+            Ok(pubrel_msg) => {
+                let packet_id = pubrel_msg.get_packet_id();
+                self.mqtt_server.release_qos2_publish(client_id, packet_id);
+                if let Err(e) = self.mqtt_server.send_pubcomp_to(client_id, packet_id) {
+                    logger.error(format!("Error en handle_pubrel: {:?}", e));
+                }
+            }
+            Err(e) => logger.error(format!("Error en handle_pubrel: {:?}", e)),
+        }
+    }
+
+    fn handle_pubcomp(&self, msg_bytes: Vec<u8>, logger: &StringLogger) {
+        match PubCompMessage::msg_from_bytes(msg_bytes) {
+            Ok(pubcomp_msg) => logger.debug(format!("Pub comp recibido, packet_id: {:?}", pubcomp_msg.get_packet_id())),
+            Err(e) => logger.error(format!("Error en handle_pubcomp: {:?}", e)),
+        }
+    }
+
+    /// El cliente indica que sigue vivo, se le responde con pingresp.
+    fn handle_pingreq(&self, msg_bytes: Vec<u8>, client_id: &str, logger: &StringLogger) {
+        match PingReqMessage::msg_from_bytes(msg_bytes) {
+            Ok(_) => {
+                if let Err(e) = self.mqtt_server.send_pingresp_to(client_id) {
+                    logger.error(format!("Error al enviar el pingresp: {:?}", e));
+                }
+            }
+            Err(e) => logger.error(format!("Error en handle_pingreq: {:?}", e)),
         }
     }
 
@@ -121,8 +272,9 @@ impl MessageProcessor {
         &self,
         client_id: &str,
         publish_msg: &PublishMessage,
+        reason_code: u8,
     ) -> Result<(), Error> {
-        self.mqtt_server.send_puback_to(client_id, publish_msg)?;
+        self.mqtt_server.send_puback_to(client_id, publish_msg, reason_code)?;
 
         Ok(())
     }
@@ -141,16 +293,176 @@ impl MessageProcessor {
     fn clone_ref(&self) -> Self {
         MessageProcessor {
             mqtt_server: self.mqtt_server.clone_ref(),
+            recent_packet_ids: self.recent_packet_ids.clone(),
+            logger: self.logger.clone_ref(),
         }
     }
 }
 
-fn create_thread_pool_with(num_threads: usize) -> Result<ThreadPool, Error> {
-    match rayon::ThreadPoolBuilder::new()
-        .num_threads(num_threads)
-        .build()
-    {
-        Ok(thread_pool) => Ok(thread_pool),
-        Err(e) => Err(Error::new(std::io::ErrorKind::Other, e)),
+/// Reparte los paquetes recibidos por `rx` entre `num_threads` hilos que los procesan con `process`, asignando
+/// todos los de un mismo cliente al mismo hilo. Devuelve los handles de los hilos, que terminan luego de
+/// procesar lo recibido cuando se cierran todos los extremos de envío de `rx`.
+/// Si procesar un paquete provoca un panic, ese paquete se descarta y se loggea, y el hilo sigue procesando los
+/// siguientes: un paquete malformado de un cliente no frena a los demás clientes asignados al mismo hilo.
+fn process_in_order_by_client<F>(
+    rx: Receiver<Packet>,
+    num_threads: usize,
+    logger: StringLogger,
+    process: F,
+) -> Vec<thread::JoinHandle<()>>
+where
+    F: Fn(Packet) + Clone + Send + 'static,
+{
+    let mut handles = vec![];
+    let mut workers_tx = vec![];
+    for _ in 0..num_threads.max(1) {
+        let (worker_tx, worker_rx) = mpsc::sync_channel::<Packet>(WORKER_QUEUE_LEN);
+        let process = process.clone();
+        let logger = logger.clone_ref();
+        handles.push(thread::spawn(move || {
+            for packet in worker_rx {
+                let log_context = packet.log_context();
+                if panic::catch_unwind(AssertUnwindSafe(|| process(packet))).is_err() {
+                    logger
+                        .with_context(&log_context)
+                        .error("Panic al procesar el paquete, se descarta.".to_string());
+                }
+            }
+        }));
+        workers_tx.push(worker_tx);
+    }
+
+    handles.push(thread::spawn(move || {
+        for packet in rx {
+            let worker_tx = &workers_tx[worker_index(packet.get_username(), workers_tx.len())];
+            // Los hilos no terminan mientras el reparto siga abierto, por lo que no debería fallar.
+            if let Err(e) = worker_tx.send(packet) {
+                logger
+                    .with_context(&e.0.log_context())
+                    .error("El hilo que procesa los paquetes del cliente terminó, se descarta el paquete.".to_string());
+            }
+        }
+    }));
+    handles
+}
+
+/// Devuelve el hilo, entre `num_workers`, que procesa los paquetes del cliente `client_id`.
+fn worker_index(client_id: &str, num_workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    client_id.hash(&mut hasher);
+    (hasher.finish() % num_workers as u64) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_in_order_by_client, MessageProcessor};
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::messages::{
+        packet_type::PacketType, publish_flags::PublishFlags, publish_message::PublishMessage,
+    };
+    use crate::mqtt::server::{mqtt_server::MQTTServer, packet::Packet, server_config::ServerConfig};
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    fn create_logger() -> StringLogger {
+        let (tx, _rx) = mpsc::channel::<String>();
+        StringLogger::new(tx)
+    }
+
+    fn create_message_processor() -> MessageProcessor {
+        let logger = create_logger();
+        MessageProcessor::new(
+            MQTTServer::with_config(logger.clone_ref(), ServerConfig::default()),
+            logger,
+        )
+    }
+
+    #[test]
+    fn test_1_la_retransmision_de_un_publish_recibido_es_duplicada() {
+        let processor = create_message_processor();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(5), "incidente".as_bytes()).unwrap();
+
+        assert!(!processor.is_retransmitted_publish(&msg, "dron-1"));
+        assert!(processor.is_retransmitted_publish(&msg.as_duplicate(), "dron-1"));
+        // El mismo packet id de otro cliente es otro mensaje
+        assert!(!processor.is_retransmitted_publish(&msg.as_duplicate(), "dron-2"));
+    }
+
+    #[test]
+    fn test_2_un_packet_id_reutilizado_sin_dup_es_un_mensaje_nuevo() {
+        let processor = create_message_processor();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(5), "incidente".as_bytes()).unwrap();
+
+        assert!(!processor.is_retransmitted_publish(&msg, "dron-1"));
+        assert!(!processor.is_retransmitted_publish(&msg, "dron-1"));
+    }
+
+    #[test]
+    fn test_3_los_paquetes_de_cada_cliente_se_procesan_en_el_orden_en_que_llegaron() {
+        let (tx, rx) = mpsc::sync_channel::<Packet>(100);
+        let processed = Arc::new(Mutex::new(vec![]));
+        let processed_clone = processed.clone();
+        let handles = process_in_order_by_client(rx, 4, create_logger(), move |packet: Packet| {
+            // El primer paquete de cada cliente tarda más, para que un procesamiento en paralelo lo adelante.
+            if packet.get_msg_bytes() == vec![0] {
+                thread::sleep(Duration::from_millis(50));
+            }
+            let entry = (packet.get_username().to_string(), packet.get_msg_bytes()[0]);
+            processed_clone.lock().unwrap().push(entry);
+        });
+
+        for i in 0..5 {
+            for client_id in ["dron-1", "dron-2", "camaras"] {
+                let packet = Packet::new(PacketType::Publish, vec![i], client_id.to_string(), None);
+                tx.send(packet).unwrap();
+            }
+        }
+        drop(tx);
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let processed = processed.lock().unwrap();
+        assert_eq!(processed.len(), 15);
+        for client_id in ["dron-1", "dron-2", "camaras"] {
+            let order: Vec<u8> = processed
+                .iter()
+                .filter(|(id, _)| id == client_id)
+                .map(|(_, i)| *i)
+                .collect();
+            assert_eq!(order, vec![0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn test_4_un_paquete_que_provoca_un_panic_no_frena_a_los_demas_clientes_del_mismo_hilo() {
+        let (tx, rx) = mpsc::sync_channel::<Packet>(100);
+        let processed = Arc::new(Mutex::new(vec![]));
+        let processed_clone = processed.clone();
+        // Con un solo hilo, todos los clientes se asignan al mismo.
+        let handles = process_in_order_by_client(rx, 1, create_logger(), move |packet: Packet| {
+            if packet.get_username() == "malicioso" {
+                panic!("paquete malformado");
+            }
+            processed_clone.lock().unwrap().push(packet.get_msg_bytes()[0]);
+        });
+
+        for i in 0..3 {
+            for client_id in ["malicioso", "dron-1"] {
+                let packet = Packet::new(PacketType::Publish, vec![i], client_id.to_string(), None);
+                tx.send(packet).unwrap();
+            }
+        }
+        drop(tx);
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(*processed.lock().unwrap(), vec![0, 1, 2]);
     }
 }
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+
+/// Tiempo de vida de los mensajes de cada topic que el servidor conserva (ie para los clientes que se
+/// reconectan o se suscriben luego), contado desde que se creó el publish. Los topics sin tiempo de vida
+/// conservan sus mensajes, como `inc`, mientras que las posiciones viejas de `dron` pueden descartarse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageTtls {
+    ttls: HashMap<String, Duration>, // String = topic
+}
+
+impl MessageTtls {
+    /// Parsea los tiempos de vida de la forma `topic=segundos`, separados por comas (ie `dron=30, camera=60`).
+    pub fn from_config_value(value: &str) -> Result<Self, Error> {
+        let mut ttls = MessageTtls::default();
+        for entry in value.split(',').map(|entry| entry.trim()) {
+            if entry.is_empty() {
+                continue;
+            }
+            let secs = entry
+                .split_once('=')
+                .and_then(|(topic, secs)| Some((topic.trim(), secs.trim().parse::<u64>().ok()?)))
+                .filter(|(topic, secs)| !topic.is_empty() && *secs > 0);
+            let Some((topic, secs)) = secs else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Valor inválido para message_ttl: {:?}", entry),
+                ));
+            };
+            ttls = ttls.with_ttl(topic, Duration::from_secs(secs));
+        }
+        Ok(ttls)
+    }
+
+    /// Devuelve los tiempos de vida, con `ttl` para los mensajes del topic `topic`.
+    pub fn with_ttl(mut self, topic: &str, ttl: Duration) -> Self {
+        self.ttls.insert(topic.to_string(), ttl);
+        self
+    }
+
+    pub fn get_ttl(&self, topic: &str) -> Option<Duration> {
+        self.ttls.get(topic).copied()
+    }
+
+    /// Devuelve si ningún topic tiene tiempo de vida.
+    pub fn is_empty(&self) -> bool {
+        self.ttls.is_empty()
+    }
+
+    /// Devuelve si el mensaje `msg` superó el tiempo de vida de su topic en el instante `now`.
+    pub fn is_expired(&self, msg: &PublishMessage, now: SystemTime) -> bool {
+        let Some(ttl) = self.get_ttl(&msg.get_topic()) else {
+            return false;
+        };
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        now.saturating_sub(msg.get_timestamp()) > ttl.as_nanos()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MessageTtls;
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_1_se_parsean_los_tiempos_de_vida_por_topic() {
+        let ttls = MessageTtls::from_config_value("dron=30, camera=60").unwrap();
+
+        assert_eq!(ttls.get_ttl("dron"), Some(Duration::from_secs(30)));
+        assert_eq!(ttls.get_ttl("camera"), Some(Duration::from_secs(60)));
+        assert_eq!(ttls.get_ttl("inc"), None);
+        assert!(MessageTtls::from_config_value("").unwrap().is_empty());
+        assert!(MessageTtls::from_config_value("dron").is_err());
+        assert!(MessageTtls::from_config_value("dron=0").is_err());
+    }
+
+    #[test]
+    fn test_2_solamente_expiran_los_mensajes_de_topics_con_tiempo_de_vida() {
+        let ttls = MessageTtls::default().with_ttl("dron", Duration::from_secs(30));
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let dron_msg = PublishMessage::new(flags.clone(), "dron", Some(1), b"posicion").unwrap();
+        let inc_msg = PublishMessage::new(flags, "inc", Some(2), b"incidente").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(31);
+
+        assert!(!ttls.is_expired(&dron_msg, SystemTime::now()));
+        assert!(ttls.is_expired(&dron_msg, later));
+        assert!(!ttls.is_expired(&inc_msg, later));
+    }
+}
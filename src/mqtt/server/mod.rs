@@ -1,10 +1,28 @@
+#[cfg(feature = "async_server")]
+pub mod async_transport;
+pub mod acl;
+pub mod admin;
+pub mod bridge;
 pub mod client_authenticator;
 pub mod client_reader;
+pub mod client_writer;
+pub mod cluster;
+pub mod config_watcher;
+pub mod connection_limits;
+pub mod credentials_store;
+pub mod delivery_stats;
 pub mod disconnect_reason;
+pub mod duplicate_client_id_policy;
 pub mod file_helper;
 pub mod incoming_connections;
+pub mod journal;
+pub mod log_context;
 pub mod message_processor;
+pub mod message_ttl;
 pub mod mqtt_server;
 pub mod packet;
+pub mod server_config;
+pub mod state_snapshot;
+pub mod topic_metrics;
 pub mod user;
 pub mod user_state;
@@ -1,27 +1,62 @@
 use crate::logging::string_logger::StringLogger;
 use crate::mqtt::messages::connect_message::ConnectMessage;
+use crate::mqtt::messages::protocol_version::ProtocolVersion;
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
+use crate::mqtt::mqtt_utils::packet_observer::{PacketObserver, PacketObservers};
 use crate::mqtt::messages::{
-    disconnect_message::DisconnectMessage, puback_message::PubAckMessage,
-    publish_message::PublishMessage, suback_message::SubAckMessage,
+    disconnect_message::DisconnectMessage, pingresp_message::PingRespMessage,
+    puback_message::{PubAckMessage, PUBACK_SUCCESS},
+    pubcomp_message::PubCompMessage, publish_flags::PublishFlags, publish_message::PublishMessage,
+    pubrec_message::PubRecMessage, pubrel_message::PubRelMessage, suback_message::SubAckMessage,
     subscribe_message::SubscribeMessage, subscribe_return_code::SubscribeReturnCode,
+    unsuback_message::Unsuback, unsubscribe_message::UnsubscribeMessage,
 };
 
+#[cfg(feature = "async_server")]
+use crate::mqtt::server::async_transport;
 use crate::mqtt::server::{
-    incoming_connections::ClientListener, user::User, user_state::UserState,
+    acl::{AccessControlList, TopicAction},
+    admin, bridge,
+    client_writer::WriteTracking,
+    cluster::{self, ClusterPeer},
+    config_watcher,
+    connection_limits::{ConnectionSlot, ConnectionTracker, PublishRateLimiter},
+    delivery_stats::DeliveryStats,
+    disconnect_reason::DisconnectReason,
+    duplicate_client_id_policy::DuplicateClientIdPolicy,
+    incoming_connections::{ClientListener, ServerListener},
+    journal::{Journal, JournalRecord},
+    log_context::client_id_log_context,
+    message_processor::MessageProcessor,
+    message_ttl::MessageTtls,
+    packet::Packet,
+    server_config::{ServerConfig, ServerTransport},
+    state_snapshot::{StateSnapshot, StateStore, StoredSession},
+    topic_metrics::{TopicMetrics, TopicMetricsSummary},
+    user::User,
+    user_state::UserState,
 };
-use crate::mqtt::stream_type::StreamType;
+use crate::mqtt::{loopback::LoopbackListener, stream_type::StreamType};
 use std::{
-    collections::{hash_map::ValuesMut, HashMap, VecDeque},
+    collections::{hash_map::ValuesMut, BTreeMap, HashMap, HashSet, VecDeque},
     fs::File,
     io::{Error, ErrorKind, Write},
-    net::TcpListener,
-    sync::{Arc, Mutex},
+    net::{SocketAddr, TcpListener},
+    path::Path,
+    sync::{mpsc, Arc, Mutex, MutexGuard, RwLock},
     thread,
+    time::{Duration, SystemTime},
 };
 
 const TOPIC_MESSAGES_LEN: usize = 50;
+const ACL_FILE_PATH: &str = "acl.txt";
+const SERVER_CONFIG_FILE_PATH: &str = "message_broker_server_config.properties";
 type ShareableUsers = Arc<Mutex<HashMap<String, User>>>;
 type TopicMessages = VecDeque<PublishMessage>; // Se guardaran todos los mensajes, y se enviaran en caso de reconexión o si un cliente no recibio ciertos mensajes.
+type RetainedMessages = Arc<Mutex<HashMap<String, PublishMessage>>>; // String = topic, el último publish con retain de cada topic.
+type RestoredSessions = Arc<Mutex<HashMap<String, StoredSession>>>; // String = client_id, sesiones recuperadas al reiniciar.
+type BridgeSender = Arc<Mutex<Option<mpsc::Sender<PublishMessage>>>>; // Publish a reenviar al broker remoto, si hay bridge.
+type ClusterPeers = Arc<Mutex<Vec<ClusterPeer>>>; // Demás brokers del cluster, si hay cluster.
 
 fn clean_file(file_path: &str) -> Result<(), Error> {
     let mut file = File::create(file_path)?;
@@ -34,6 +69,18 @@ pub struct MQTTServer {
     connected_users: ShareableUsers,
     available_packet_id: u16,                                      //
     messages_by_topic: Arc<Mutex<HashMap<String, TopicMessages>>>, // String = topic
+    retained_messages: RetainedMessages,
+    acl: Arc<RwLock<AccessControlList>>,
+    config: Arc<RwLock<ServerConfig>>, // puede recargarse mientras el servidor corre, desde la interfaz de administración.
+    journal: Option<Arc<Journal>>,
+    restored_sessions: RestoredSessions, // se asignan al user cuando el cliente vuelve a conectarse.
+    state_store: Option<Arc<StateStore>>, // si se indicó un state dir, donde se guarda el estado al cerrar.
+    bridge_tx: BridgeSender,
+    cluster_peers: ClusterPeers,
+    connections: Arc<ConnectionTracker>, // conexiones abiertas, para aplicar los límites configurados.
+    delivery_stats: Arc<DeliveryStats>, // escrituras hacia los clientes, se publican en `$SYS`.
+    topic_metrics: Arc<TopicMetrics>, // publish y entregas de cada topic, se publican en `$SYS`.
+    packet_observers: PacketObservers, // reciben los paquetes leídos y escritos de todas las conexiones.
     logger: StringLogger,
 }
 
@@ -41,37 +88,454 @@ impl MQTTServer {
     pub fn new(logger: StringLogger) -> Self {
         let file_path = "log.txt";
         if let Err(e) = clean_file(file_path) {
-            println!("Error al limpiar el archivo: {:?}", e);
+            logger.warn(format!("Error al limpiar el archivo: {:?}", e));
         }
 
-        Self {
+        let config = ServerConfig::from_file(SERVER_CONFIG_FILE_PATH).unwrap_or_else(|e| {
+            logger.warn(format!("No se cargó la configuración del servidor: {:?}", e));
+            ServerConfig::default()
+        });
+
+        Self::with_config(logger, config)
+    }
+
+    /// Crea el servidor con la configuración `config`.
+    pub fn with_config(logger: StringLogger, config: ServerConfig) -> Self {
+        logger.set_level(config.get_log_level());
+        // Si no hay lista de control de acceso, ningún usuario tiene restricciones sobre los topics.
+        let acl = AccessControlList::from_file(ACL_FILE_PATH).unwrap_or_else(|e| {
+            logger.warn(format!("No se cargó la lista de control de acceso: {:?}", e));
+            AccessControlList::default()
+        });
+
+        let connections = ConnectionTracker::new(config.get_connection_limits());
+        let mut server = Self {
             connected_users: Arc::new(Mutex::new(HashMap::new())),
             available_packet_id: 0,
             messages_by_topic: Arc::new(Mutex::new(HashMap::new())),
+            retained_messages: Arc::new(Mutex::new(HashMap::new())),
+            acl: Arc::new(RwLock::new(acl)),
+            config: Arc::new(RwLock::new(config)),
+            journal: None,
+            restored_sessions: Arc::new(Mutex::new(HashMap::new())),
+            state_store: None,
+            bridge_tx: Arc::new(Mutex::new(None)),
+            cluster_peers: Arc::new(Mutex::new(vec![])),
+            connections: Arc::new(connections),
+            delivery_stats: Arc::new(DeliveryStats::default()),
+            topic_metrics: Arc::new(TopicMetrics::default()),
+            packet_observers: PacketObservers::default(),
             logger,
+        };
+        if let Some(journal_path) = server.read_config(|config| config.get_journal_path().cloned()) {
+            server.open_journal(&journal_path);
         }
+        server
     }
 
-    pub fn run(&self, ip: String, port: u16) -> Result<(), Error> {
+    /// Abre el journal, reconstruye a partir de sus registros el estado que el servidor tenía antes de reiniciarse,
+    /// y lo compacta para que quede solamente ese estado. Si no puede abrirse, el servidor sigue sin persistir.
+    fn open_journal(&mut self, journal_path: &str) {
+        let sync_policy = self.read_config(|config| config.get_journal_sync());
+        let compaction_threshold = self.read_config(|config| config.get_journal_compaction_threshold());
+        match Journal::open(journal_path, sync_policy, compaction_threshold) {
+            Ok((journal, records)) => {
+                self.logger.info(format!("Se recuperaron {} registros del journal.", records.len()));
+                self.replay_journal(records);
+                self.journal = Some(Arc::new(journal));
+                self.compact_journal();
+            }
+            Err(e) => self.logger.error(format!("No se pudo abrir el journal {:?}: {:?}", journal_path, e)),
+        }
+    }
+
+    /// Reaplica los registros del journal: los publish vuelven a los mensajes de su topic (y a los retenidos, si tenían retain),
+    /// y las suscripciones quedan esperando a que su cliente se conecte.
+    fn replay_journal(&self, records: Vec<JournalRecord>) {
+        let (Ok(mut messages_by_topic), Ok(mut retained_messages), Ok(mut restored_sessions)) = (
+            self.messages_by_topic.lock(),
+            self.retained_messages.lock(),
+            self.restored_sessions.lock(),
+        ) else {
+            self.logger.error("Error: no se pudo tomar lock para reaplicar el journal.".to_string());
+            return;
+        };
+
+        for record in records {
+            match record {
+                JournalRecord::Publish(msg) => {
+                    if msg.is_retain() {
+                        retained_messages.insert(msg.get_topic(), msg.clone());
+                    }
+                    messages_by_topic.entry(msg.get_topic()).or_default().push_back(msg);
+                }
+                JournalRecord::Subscribe { client_id, topic, qos } => {
+                    // Si ya estaba suscripto, se conserva el último qos otorgado.
+                    let topics = &mut restored_sessions.entry(client_id).or_default().topics;
+                    topics.retain(|(t, _)| *t != topic);
+                    topics.push((topic, qos));
+                }
+                JournalRecord::Unsubscribe { client_id, topic } => {
+                    if let Some(session) = restored_sessions.get_mut(&client_id) {
+                        session.topics.retain(|(t, _)| *t != topic);
+                    }
+                }
+                JournalRecord::SessionRemoved { client_id } => {
+                    restored_sessions.remove(&client_id);
+                }
+            }
+        }
+
+        // Como todavía no hay users, se conservan solamente los últimos mensajes de cada topic.
+        for topic_messages in messages_by_topic.values_mut() {
+            while topic_messages.len() > TOPIC_MESSAGES_LEN {
+                topic_messages.pop_front();
+            }
+        }
+    }
+
+    /// Agrega el registro al journal, si el servidor persiste su estado.
+    fn journal_record(&self, record: JournalRecord) {
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.append(&record) {
+                self.logger.error(format!("Error al escribir en el journal: {:?}", e));
+            }
+        }
+    }
+
+    /// Compacta el journal si se le agregaron suficientes registros desde la última compactación.
+    fn compact_journal_if_needed(&self) {
+        if self.journal.as_ref().is_some_and(|journal| journal.needs_compaction()) {
+            self.compact_journal();
+        }
+    }
+
+    /// Reescribe el journal con el estado actual: los publish de qos mayor a 0 almacenados,
+    /// y las suscripciones de los users y las restauradas que todavía no se asignaron.
+    /// Se mantienen los locks durante la compactación, para que no se pierdan registros agregados mientras tanto.
+    fn compact_journal(&self) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let (Ok(connected_users), Ok(messages_by_topic), Ok(restored_sessions)) = (
+            self.connected_users.lock(),
+            self.messages_by_topic.lock(),
+            self.restored_sessions.lock(),
+        ) else {
+            self.logger.error("Error: no se pudo tomar lock para compactar el journal.".to_string());
+            return;
+        };
 
+        let mut snapshot = vec![];
+        for msg in messages_by_topic.values().flatten() {
+            if msg.get_qos() > 0 {
+                snapshot.push(JournalRecord::Publish(msg.clone()));
+            }
+        }
+        let users_topics = connected_users
+            .iter()
+            .map(|(client_id, user)| (client_id, user.get_subscriptions()));
+        let restored_topics = restored_sessions
+            .iter()
+            .map(|(client_id, session)| (client_id, session.topics.clone()));
+        for (client_id, topics) in users_topics.chain(restored_topics) {
+            for (topic, qos) in topics {
+                snapshot.push(JournalRecord::Subscribe {
+                    client_id: client_id.to_string(),
+                    topic,
+                    qos,
+                });
+            }
+        }
+
+        if let Err(e) = journal.compact(&snapshot) {
+            self.logger.error(format!("Error al compactar el journal: {:?}", e));
+        }
+    }
+
+    /// Quita y devuelve la sesión de `client_id` recuperada al reiniciar (del journal o del state dir), si la había.
+    fn take_restored_session(&self, client_id: &str) -> Option<StoredSession> {
+        match self.restored_sessions.lock() {
+            Ok(mut restored_sessions) => restored_sessions.remove(client_id),
+            Err(_) => None,
+        }
+    }
+
+    /// Usa `state_dir` para guardar el estado del servidor (mensajes retenidos y almacenados, y sesiones de los clientes)
+    /// al cerrarse y periódicamente, y restaura el que se guardó la vez anterior, salvo que se pida empezar con `fresh`.
+    /// Si también hay journal, el estado restaurado reemplaza al reconstruido desde él.
+    /// Devuelve error si el estado guardado está corrupto, para no perderlo al guardar el nuevo; iniciar con `fresh` lo descarta.
+    pub fn open_state_dir(&mut self, state_dir: &str, fresh: bool) -> Result<(), Error> {
+        let state_store = StateStore::open(state_dir)?;
+        if fresh {
+            self.logger.info(format!("Se inicia sin restaurar el estado guardado en {:?}.", state_store.get_path()));
+        } else if let Some(snapshot) = state_store.load()? {
+            self.restore_snapshot(snapshot)?;
+            self.logger.info(format!("Se restauró el estado guardado en {:?}.", state_store.get_path()));
+            self.compact_journal();
+        }
+        self.state_store = Some(Arc::new(state_store));
+        Ok(())
+    }
+
+    /// Reemplaza los mensajes retenidos y almacenados, y las sesiones a retomar, por los del `snapshot`.
+    fn restore_snapshot(&self, snapshot: StateSnapshot) -> Result<(), Error> {
+        let (Ok(mut messages_by_topic), Ok(mut retained_messages), Ok(mut restored_sessions)) = (
+            self.messages_by_topic.lock(),
+            self.retained_messages.lock(),
+            self.restored_sessions.lock(),
+        ) else {
+            return Err(MqttError::LockPoisoned("el estado del servidor".to_string()).into());
+        };
+        *retained_messages = snapshot
+            .retained_messages
+            .into_iter()
+            .map(|msg| (msg.get_topic(), msg))
+            .collect();
+        *messages_by_topic = snapshot
+            .messages_by_topic
+            .into_iter()
+            .map(|(topic, messages)| (topic, messages.into()))
+            .collect();
+        *restored_sessions = snapshot.sessions;
+        Ok(())
+    }
+
+    /// Devuelve el estado actual del servidor. Los users, conectados o no, se guardan como sesiones a retomar.
+    fn create_snapshot(&self) -> Result<StateSnapshot, Error> {
+        let (Ok(connected_users), Ok(messages_by_topic), Ok(retained_messages), Ok(restored_sessions)) = (
+            self.connected_users.lock(),
+            self.messages_by_topic.lock(),
+            self.retained_messages.lock(),
+            self.restored_sessions.lock(),
+        ) else {
+            return Err(MqttError::LockPoisoned("el estado del servidor".to_string()).into());
+        };
+        let mut sessions = restored_sessions.clone();
+        for (client_id, user) in connected_users.iter() {
+            let session = StoredSession {
+                topics: user.get_subscriptions(),
+                last_id_by_topic: user.get_last_ids_by_topic().clone(),
+                pending_publishes: user.get_pending_publishes().iter().cloned().collect(),
+            };
+            sessions.insert(client_id.to_string(), session);
+        }
+        Ok(StateSnapshot {
+            retained_messages: retained_messages.values().cloned().collect(),
+            messages_by_topic: messages_by_topic
+                .iter()
+                .map(|(topic, messages)| (topic.to_string(), messages.iter().cloned().collect()))
+                .collect(),
+            sessions,
+        })
+    }
+
+    /// Guarda el estado actual en el state dir, si se indicó uno. Devuelve si lo guardó.
+    pub fn save_state(&self) -> Result<bool, Error> {
+        let Some(state_store) = &self.state_store else {
+            return Ok(false);
+        };
+        state_store.save(&self.create_snapshot()?)?;
+        self.logger.debug(format!("Se guardó el estado en {:?}.", state_store.get_path()));
+        Ok(true)
+    }
+
+    /// Si se indicó un state dir, hilo que guarda periódicamente el estado, para no perderlo todo si el servidor se corta.
+    fn start_state_snapshots(&self) {
+        if self.state_store.is_none() {
+            return;
+        }
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            while let Some(interval) = self_clone.read_config(|config| config.get_state_snapshot_interval()) {
+                thread::sleep(interval);
+                if let Err(e) = self_clone.save_state() {
+                    self_clone.logger.error(format!("Error al guardar el estado del servidor: {:?}", e));
+                }
+            }
+        });
+    }
+
+    pub fn run(&self, ip: String, port: u16) -> Result<(), Error> {
         let listener = create_server(ip, port)?;
-        let mut incoming_connections = ClientListener::new(self.logger.clone_ref());
+        self.serve(ServerListener::Tcp(listener))
+    }
+
+    /// Como `run`, pero acepta las conexiones en memoria de los clientes del mismo proceso que se conecten
+    /// mediante el connector de `listener`. Termina cuando ya no quedan connectors con los que conectarse.
+    pub fn run_loopback(&self, listener: LoopbackListener) -> Result<(), Error> {
+        self.serve(ServerListener::Loopback(listener))
+    }
+
+    /// Atiende las conexiones que acepta `listener`, hasta que deja de aceptarlas.
+    fn serve(&self, listener: ServerListener) -> Result<(), Error> {
+        // Cola acotada compartida por todos los clientes: si se llena, los clientes esperan para enviar más paquetes.
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Packet>(self.read_config(|config| config.get_packet_queue_size()));
+        let thread_processor = self.spawn_message_processor(packets_rx);
+        self.start_bridge();
+        self.start_cluster();
+        self.start_admin();
+        self.start_config_watcher();
+        self.start_expiry_sweeper();
+        self.start_sys_publisher();
+        self.start_state_snapshots();
+
+        let thread_incoming = self.spawn_incoming_connections(listener, packets_tx);
+
+        if let Err(e) = thread_incoming.join(){
+            self.logger.error(format!("Error al esperar al hilo incoming, en run: {:?}.", e));
+        }
+        if let Err(e) = thread_processor.join(){
+            self.logger.error(format!("Error al esperar al hilo del message processor, en run: {:?}.", e));
+        }
+        // Al cerrarse, se guarda el estado para restaurarlo al volver a iniciar.
+        if let Err(e) = self.save_state() {
+            self.logger.error(format!("Error al guardar el estado del servidor: {:?}", e));
+        }
+
+        Ok(())
+    }
+
+    /// Hilo para manejar las conexiones entrantes, con el transporte configurado.
+    fn spawn_incoming_connections(
+        &self,
+        listener: ServerListener,
+        packets_tx: mpsc::SyncSender<Packet>,
+    ) -> thread::JoinHandle<()> {
         let self_clone = self.clone_ref();
         let logger_c = self.logger.clone_ref();
-        // Hilo para manejar las conexiones entrantes
-        let thread_incoming = thread::spawn(move || {
-            if let Err(e) = incoming_connections.handle_incoming_connections(listener, self_clone) {
-                logger_c.log(format!("Error en handle_incoming_connections, en run: {:?}.", e));
+        let transport = self.read_config(|config| config.get_transport());
+        thread::spawn(move || {
+            let res = match (transport, listener) {
+                #[cfg(feature = "async_server")]
+                (ServerTransport::Tokio, ServerListener::Tcp(listener)) => async_transport::handle_incoming_connections(
+                    listener,
+                    self_clone,
+                    packets_tx,
+                    logger_c.clone_ref(),
+                ),
+                (_, listener) => {
+                    if transport == ServerTransport::Tokio {
+                        logger_c.warn("Transporte tokio no disponible (compilar con la feature async_server, solamente por tcp), se usan hilos.".to_string());
+                    }
+                    let mut incoming_connections = ClientListener::new(logger_c.clone_ref());
+                    incoming_connections.handle_incoming_connections(listener, self_clone, packets_tx)
+                }
+            };
+            if let Err(e) = res {
+                logger_c.error(format!("Error en handle_incoming_connections, en run: {:?}.", e));
+            }
+        })
+    }
+
+    /// Si hay un bridge configurado, se conecta al broker remoto y comienza a reenviar los topics configurados.
+    /// Si no puede conectarse, el servidor sigue funcionando sin bridge.
+    fn start_bridge(&self) {
+        let Some(bridge_config) = self.read_config(|config| config.get_bridge().cloned()) else {
+            return;
+        };
+        let (bridge_tx, bridge_rx) = mpsc::channel::<PublishMessage>();
+        match bridge::spawn_bridge(bridge_config, self.clone_ref(), bridge_rx, self.logger.clone_ref()) {
+            Ok(_) => {
+                if let Ok(mut bridge_tx_l) = self.bridge_tx.lock() {
+                    *bridge_tx_l = Some(bridge_tx);
+                }
+            }
+            Err(e) => self.logger.error(format!("No se pudo iniciar el bridge: {:?}", e)),
+        }
+    }
+
+    /// Si hay un cluster configurado, se conecta a los demás brokers para intercambiar las tablas de suscripciones
+    /// y reenviarles los publish de sus suscriptores. Si no puede iniciarse, el servidor sigue funcionando solo.
+    fn start_cluster(&self) {
+        let Some(cluster_config) = self.read_config(|config| config.get_cluster().cloned()) else {
+            return;
+        };
+        match cluster::spawn_cluster(cluster_config, self.clone_ref(), self.logger.clone_ref()) {
+            Ok(peers) => {
+                if let Ok(mut cluster_peers_l) = self.cluster_peers.lock() {
+                    *cluster_peers_l = peers;
+                }
+            }
+            Err(e) => self.logger.error(format!("No se pudo iniciar el cluster: {:?}", e)),
+        }
+    }
+
+    /// Hilo que descarta periódicamente los mensajes que superaron el tiempo de vida de su topic.
+    fn start_expiry_sweeper(&self) {
+        let self_clone = self.clone_ref();
+        thread::spawn(move || loop {
+            thread::sleep(self_clone.read_config(|config| config.get_expiry_sweep_interval()));
+            match self_clone.remove_expired_messages() {
+                Ok(0) => {}
+                Ok(removed) => self_clone.logger.debug(format!("Se descartaron {} mensajes expirados.", removed)),
+                Err(e) => self_clone.logger.error(format!("Error al descartar los mensajes expirados: {:?}", e)),
             }
         });
+    }
 
-        if let Err(e) = thread_incoming.join(){
-            self.logger.log(format!("Error al esperar al hilo incoming, en run: {:?}.", e));
+    /// Si está configurado, hilo que publica periódicamente (retenidos) los contadores del servidor en `$SYS`.
+    fn start_sys_publisher(&self) {
+        if self.read_config(|config| config.get_sys_interval()).is_none() {
+            return;
         }
+        let self_clone = self.clone_ref();
+        thread::spawn(move || {
+            while let Some(interval) = self_clone.read_config(|config| config.get_sys_interval()) {
+                thread::sleep(interval);
+                if let Err(e) = self_clone.publish_sys_topics() {
+                    self_clone.logger.error(format!("Error al publicar los contadores en $SYS: {:?}", e));
+                }
+            }
+        });
+    }
 
+    /// Publica los contadores del servidor en sus topics de `$SYS`, solamente para los clientes de este broker.
+    fn publish_sys_topics(&self) -> Result<(), Error> {
+        let flags = PublishFlags::new(0, 0, 1)?;
+        let sys_topics = self.delivery_stats.to_sys_topics().into_iter().chain(self.topic_metrics.to_sys_topics());
+        for (topic, value) in sys_topics {
+            let msg = PublishMessage::new(flags.clone(), &topic, None, value.as_bytes())?;
+            self.publish_locally(&msg)?;
+        }
         Ok(())
     }
 
+    /// Si hay una dirección de administración configurada, comienza a atender allí los comandos de los operadores.
+    /// Si no puede iniciarse, el servidor sigue funcionando sin interfaz de administración.
+    fn start_admin(&self) {
+        let Some(admin_addr) = self.read_config(|config| config.get_admin_addr()) else {
+            return;
+        };
+        if let Err(e) = admin::spawn_admin(admin_addr, self.clone_ref(), self.logger.clone_ref()) {
+            self.logger.error(format!("No se pudo iniciar la interfaz de administración: {:?}", e));
+        }
+    }
+
+    /// Comienza a vigilar los archivos de configuración y de control de acceso, para recargarlos cuando se modifiquen.
+    /// Si no puede iniciarse, la configuración puede recargarse desde la interfaz de administración.
+    fn start_config_watcher(&self) {
+        let paths = [SERVER_CONFIG_FILE_PATH, ACL_FILE_PATH];
+        let self_clone = self.clone_ref();
+        let reload = move || {
+            if let Err(e) = self_clone.reload_config() {
+                self_clone.logger.error(format!("No se pudo recargar la configuración: {:?}", e));
+            }
+        };
+        if let Err(e) = config_watcher::spawn_config_watcher(&paths, reload, self.logger.clone_ref()) {
+            self.logger.error(format!("No se pudo vigilar la configuración: {:?}", e));
+        }
+    }
+
+    /// Hilo que procesa los paquetes recibidos de todos los clientes, con la cantidad de hilos configurada.
+    fn spawn_message_processor(&self, packets_rx: mpsc::Receiver<Packet>) -> thread::JoinHandle<()> {
+        let message_processor = MessageProcessor::new(self.clone_ref(), self.logger.clone_ref());
+        let processor_threads = self.read_config(|config| config.get_processor_threads());
+        thread::spawn(move || {
+            message_processor.handle_packets(packets_rx, processor_threads);
+        })
+    }
+
     /// Agrega un PublishMessage a la estructura de mensajes de su topic.
     fn add_message_to_topic_messages(
         &self,
@@ -89,6 +553,106 @@ impl MQTTServer {
         topic_messages.push_back(publish_msg);
     }
 
+    /// Devuelve si debe rechazarse el connect de `client_id` por ya existir una sesión activa con ese client_id,
+    /// según la política configurada. Un user temporalmente desconectado no cuenta como duplicado, se está reconectando.
+    pub fn rejects_duplicate_client_id(&self, client_id: &str) -> bool {
+        if self.read_config(|config| config.get_duplicate_client_id_policy()) != DuplicateClientIdPolicy::RejectNew {
+            return false;
+        }
+        if let Ok(connected_users_locked) = self.connected_users.lock() {
+            if let Some(client) = connected_users_locked.get(client_id) {
+                return *client.get_state() == UserState::Active;
+            }
+        }
+        false
+    }
+
+    /// Registra la conexión de un cliente desde `peer_addr`, si no excede la cantidad máxima de clientes
+    /// ni de conexiones desde su ip. Devuelve None si la excede, en cuyo caso debe rechazarse su connect.
+    /// La conexión cuenta para los límites mientras el `ConnectionSlot` devuelto no se dropee.
+    pub fn try_register_connection(
+        &self,
+        peer_addr: Option<SocketAddr>,
+    ) -> Result<Option<ConnectionSlot>, Error> {
+        let ip = peer_addr.map(|addr| addr.ip());
+        ConnectionTracker::try_register(&self.connections, ip)
+    }
+
+    /// Devuelve la remaining length máxima de los paquetes que el servidor acepta de sus clientes.
+    pub fn get_max_packet_size(&self) -> usize {
+        self.read_config(|config| config.get_max_packet_size())
+    }
+
+    /// Devuelve cuánto se espera el connect de una conexión nueva antes de cerrarla.
+    pub fn get_connect_timeout(&self) -> Duration {
+        self.read_config(|config| config.get_connect_timeout())
+    }
+
+    /// Devuelve si el servidor acepta la versión de protocolo `protocol_version` pedida en un connect
+    /// (None si no es una versión soportada). Mqtt 5 se acepta solamente si está habilitado en la configuración.
+    pub fn accepts_protocol_version(&self, protocol_version: Option<ProtocolVersion>) -> bool {
+        match protocol_version {
+            Some(ProtocolVersion::Mqtt311) => true,
+            Some(ProtocolVersion::Mqtt5) => self.read_config(|config| config.is_mqtt5_enabled()),
+            None => false,
+        }
+    }
+
+    /// Devuelve la versión de protocolo negociada con el cliente `client_id`, con la que se interpretan sus publish.
+    pub fn get_protocol_version_of(&self, client_id: &str) -> ProtocolVersion {
+        if let Ok(connected_users_locked) = self.connected_users.lock() {
+            if let Some(user) = connected_users_locked.get(client_id) {
+                return user.get_protocol_version();
+            }
+        }
+        ProtocolVersion::default()
+    }
+
+    /// Devuelve cuántos topic aliases se aceptan en los publish de una conexión con la versión `protocol_version`:
+    /// los configurados en mqtt 5, y ninguno en mqtt 3.1.1, que no los soporta.
+    pub fn get_topic_alias_maximum(&self, protocol_version: Option<ProtocolVersion>) -> u16 {
+        match protocol_version {
+            Some(ProtocolVersion::Mqtt5) => self.read_config(|config| config.get_topic_alias_maximum()),
+            _ => 0,
+        }
+    }
+
+    /// Crea el limitador de publish por segundo para la conexión de un cliente, según la tasa configurada.
+    pub fn create_publish_rate_limiter(&self) -> PublishRateLimiter {
+        PublishRateLimiter::new(self.get_max_publish_rate())
+    }
+
+    /// Devuelve la tasa máxima de publish por segundo de cada cliente, que puede cambiar al recargar la configuración.
+    pub fn get_max_publish_rate(&self) -> Option<u32> {
+        self.read_config(|config| config.get_connection_limits().get_max_publish_rate())
+    }
+
+    /// Devuelve si el servidor conserva una sesión de `client_id` que retomará al conectarse:
+    /// la de un user desconectado temporalmente, o la recuperada al reiniciar.
+    pub fn has_session(&self, client_id: &str) -> bool {
+        if let Ok(connected_users_locked) = self.connected_users.lock() {
+            if let Some(client) = connected_users_locked.get(client_id) {
+                return *client.get_state() == UserState::TemporallyDisconnected;
+            }
+        }
+        match self.restored_sessions.lock() {
+            Ok(restored_sessions) => restored_sessions.contains_key(client_id),
+            Err(_) => false,
+        }
+    }
+
+    /// Devuelve si la sesión actual de `client_id` es la de la conexión desde `peer_addr`.
+    /// Permite que la conexión de una sesión anterior (ie desplazada por un client_id duplicado)
+    /// no modifique al user de la sesión nueva al cerrarse.
+    pub fn is_current_session_of(&self, client_id: &str, peer_addr: Option<SocketAddr>) -> bool {
+        if let Ok(connected_users_locked) = self.connected_users.lock() {
+            if let Some(client) = connected_users_locked.get(client_id) {
+                return client.is_connected_from(peer_addr);
+            }
+        }
+        false
+    }
+
     /// Busca al client_id en el hashmap de conectados, si ya existía analiza su estado:
     /// si ya estaba como activo, es un usuario duplicado por lo que le envía disconnect al stream anterior;
     /// si estaba como desconectado temporalmente (ie ctrl+C), se está reconectando.
@@ -97,6 +661,7 @@ impl MQTTServer {
         &self,
         client_id: &str,
         new_stream_of_reconnected_user: &StreamType,
+        protocol_version: ProtocolVersion,
     ) -> Result<bool, Error> {
         if let Ok(mut connected_users_locked) = self.connected_users.lock() {
             if let Some(client) = connected_users_locked.get_mut(client_id) {
@@ -105,12 +670,14 @@ impl MQTTServer {
                         // El cliente ya se encontraba activo ==> Es duplicado.
                         self.handle_duplicate_user(client)?;
                         let _ = connected_users_locked.remove(client_id);
-                        println!("Se conecta usuario duplicado: {:?}, desconectando el anterior.", client_id);
+                        self.journal_record(JournalRecord::SessionRemoved { client_id: client_id.to_string() });
+                        self.client_logger(client_id).info("Se conecta con un client_id duplicado, desconectando la sesión anterior.".to_string());
                     }
                     UserState::TemporallyDisconnected => {
                         // El cliente se encontraba temp desconectado ==> Se está reconectando.
+                        client.set_protocol_version(protocol_version);
                         self.handle_reconnecting_user(client, new_stream_of_reconnected_user)?;
-                        println!("Se reconecta el usuario: {:?}, emviándole mensajes.", client_id);
+                        self.client_logger(client_id).info("Se reconecta, enviándole los mensajes pendientes.".to_string());
                         // Único caso en que devuelve true.
                         return Ok(true);
                     }
@@ -125,7 +692,9 @@ impl MQTTServer {
         // Desconecto al user que ya que existía
         let msg = DisconnectMessage::new();
         client.write_message(&msg.to_bytes())?;
-        client.shutdown();
+        if let Err(e) = client.shutdown() {
+            self.client_logger(&client.get_username()).warn(format!("Error al cerrar la sesión anterior: {:?}", e));
+        }
         
         Ok(())
     }
@@ -138,7 +707,14 @@ impl MQTTServer {
         new_stream_of_reconnected_user: &StreamType,
     ) -> Result<(), Error> {
         client.set_state(UserState::Active);
-        client.update_stream_with(new_stream_of_reconnected_user.try_clone()?);
+        client.update_stream_with(new_stream_of_reconnected_user.try_clone()?)?;
+        self.send_session_messages(client)
+    }
+
+    /// Envía al user que retoma su sesión los mensajes que no recibió mientras estaba desconectado.
+    fn send_session_messages(&self, client: &mut User) -> Result<(), Error> {
+        // Primero los publish que habían quedado encolados esperando lugar en la ventana
+        client.send_pending_publishes()?;
 
         // Envía los mensajes que no recibió de todos los topics a los que está suscripto
         let topics = client.get_topics().to_vec();
@@ -167,7 +743,7 @@ impl MQTTServer {
         topic_messages: &VecDeque<PublishMessage>,
     ) -> Result<(), Error> {
         if let Some(diff) = check_subscription_and_calculate_diff(user, topic, topic_messages)?{
-            send_unreceived_messages_to_user(user, topic, topic_messages, diff)?;
+            send_unreceived_messages_to_user(user, topic, topic_messages, diff, &self.client_logger(&user.get_username()))?;
         };
 
         Ok(())
@@ -186,20 +762,108 @@ impl MQTTServer {
 
         let username_c = username.to_string();
         //[] Aux: Nos guardamos el stream, volver a ver esto.
-        let user = User::new(stream.try_clone()?, username_c.to_owned(), will_msg_info); //[]
+        let auth_username = connect_msg.get_user().cloned();
+        let max_in_flight = self.read_config(|config| config.get_max_in_flight_messages());
+        let write_queue_size = self.read_config(|config| config.get_client_write_queue_size());
+        let mut user = User::new(stream.try_clone()?, username_c.to_owned(), auth_username, will_msg_info, max_in_flight, write_queue_size, self.create_write_tracking(username))?; //[]
+        user.set_protocol_version(connect_msg.get_protocol_version().unwrap_or_default());
+        // Si el servidor se reinició, el cliente retoma la sesión que tenía, y recibe los mensajes que no le llegaron.
+        let restored_session = self.take_restored_session(username);
+        let is_restored = restored_session.is_some();
+        if let Some(session) = restored_session {
+            user.restore_session(session);
+        }
         if let Ok(mut users) = self.connected_users.lock() {
-            println!("Username agregado a la lista del server: {:?}", username);
-            users.insert(username_c, user); //inserta el usuario en el hashmap
+            self.client_logger(username).debug("Agregado a los users del server.".to_string());
+            users.insert(username_c.to_owned(), user); //inserta el usuario en el hashmap
                                             // Aux: Ver Acá [].
+            if let Some(user) = users.get_mut(&username_c).filter(|_| is_restored) {
+                self.send_session_messages(user)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve el resultado de `f` sobre la configuración actual.
+    fn read_config<T>(&self, f: impl FnOnce(&ServerConfig) -> T) -> T {
+        match self.config.read() {
+            Ok(config) => f(&config),
+            Err(poisoned) => f(&poisoned.into_inner()),
+        }
+    }
+
+    /// Devuelve el logger con el client_id de `client_id` como contexto.
+    fn client_logger(&self, client_id: &str) -> StringLogger {
+        self.logger.with_context(&client_id_log_context(client_id))
+    }
+
+    /// Vuelve a leer la configuración y la lista de control de acceso de sus archivos, sin cerrar las conexiones.
+    /// Se aplica a lo que se consulta en cada conexión o mensaje (ie política de client_id duplicado, tamaño máximo
+    /// de paquete, will delay, límites de conexiones y tasa de publish, permisos de los topics); lo que se usa
+    /// al iniciar el servidor (transporte, hilos, journal, bridge, interfaz de administración) requiere reiniciarlo.
+    /// Las credenciales no necesitan recargarse, ya que se leen de su archivo en cada connect.
+    pub fn reload_config(&self) -> Result<(), Error> {
+        self.reload_config_from(SERVER_CONFIG_FILE_PATH, ACL_FILE_PATH)
+    }
+
+    fn reload_config_from(&self, config_path: &str, acl_path: &str) -> Result<(), Error> {
+        let config = ServerConfig::from_file(config_path)?;
+        // Como al iniciar, si no hay lista de control de acceso no hay restricciones.
+        let acl = if Path::new(acl_path).exists() {
+            AccessControlList::from_file(acl_path)?
+        } else {
+            AccessControlList::default()
+        };
+
+        self.connections.set_limits(config.get_connection_limits())?;
+        self.logger.set_level(config.get_log_level());
+        match (self.config.write(), self.acl.write()) {
+            (Ok(mut config_l), Ok(mut acl_l)) => {
+                *config_l = config;
+                *acl_l = acl;
+            }
+            _ => return Err(MqttError::LockPoisoned("la configuración".to_string()).into()),
         }
+        self.logger.info("Se recargó la configuración del servidor.".to_string());
         Ok(())
     }
 
+    /// Registra `observer`, que recibirá cada paquete que el servidor lea o escriba por las conexiones de los
+    /// clientes, con su client_id y el momento en que se leyó o escribió. Puede registrarse mientras el servidor corre.
+    pub fn add_packet_observer(&self, observer: Arc<dyn PacketObserver>) {
+        self.packet_observers.add(observer);
+    }
+
+    pub fn get_packet_observers(&self) -> PacketObservers {
+        self.packet_observers.clone()
+    }
+
+    /// Devuelve con qué registrar las escrituras hacia el cliente `client_id`.
+    fn create_write_tracking(&self, client_id: &str) -> WriteTracking {
+        WriteTracking::new(
+            self.delivery_stats.clone(),
+            self.topic_metrics.clone(),
+            self.packet_observers.with_client_id(client_id),
+        )
+    }
+
     pub fn clone_ref(&self) -> Self {
         Self {
             connected_users: self.connected_users.clone(),
             available_packet_id: self.available_packet_id,
             messages_by_topic: self.messages_by_topic.clone(),
+            retained_messages: self.retained_messages.clone(),
+            acl: self.acl.clone(),
+            config: self.config.clone(),
+            journal: self.journal.clone(),
+            restored_sessions: self.restored_sessions.clone(),
+            state_store: self.state_store.clone(),
+            bridge_tx: self.bridge_tx.clone(),
+            cluster_peers: self.cluster_peers.clone(),
+            connections: self.connections.clone(),
+            delivery_stats: self.delivery_stats.clone(),
+            topic_metrics: self.topic_metrics.clone(),
+            packet_observers: self.packet_observers.clone(),
             logger: self.logger.clone_ref(),
         }
     }
@@ -224,14 +888,79 @@ impl MQTTServer {
     }
 
     /// Procesa el PublishMessage: lo agrega al hashmap de su topic, y luego lo envía a los suscriptores de ese topic
-    /// que estén conectados.
+    /// que estén conectados. Si su topic se reenvía por el bridge, también lo envía al broker remoto,
+    /// y lo envía a los brokers del cluster que tengan suscriptores del topic.
     pub fn handle_publish_message(&self, msg: &PublishMessage) -> Result<(), Error> {
+        self.publish_locally(msg)?;
+        self.forward_to_bridge(msg);
+        self.forward_to_cluster(msg);
+        Ok(())
+    }
+
+    /// Procesa un PublishMessage recibido del cliente `client_id`. Si el cliente es otro broker del cluster,
+    /// el publish ya se envió desde su broker de origen a todos los brokers con suscriptores, por lo que se procesa
+    /// como `handle_cluster_publish`.
+    pub fn handle_publish_from(&self, client_id: &str, msg: &PublishMessage) -> Result<(), Error> {
+        if cluster::is_cluster_client(client_id) {
+            return self.handle_cluster_publish(msg);
+        }
+        self.handle_publish_message(msg)
+    }
+
+    /// Procesa un PublishMessage recibido de otro broker del cluster, o la tabla de suscripciones de este broker.
+    /// Se envía solamente a los suscriptores locales: reenviarlo por el bridge o al cluster lo haría circular
+    /// indefinidamente entre los brokers.
+    pub fn handle_cluster_publish(&self, msg: &PublishMessage) -> Result<(), Error> {
+        self.publish_locally(msg)
+    }
+
+    /// Procesa un PublishMessage recibido del broker remoto por el bridge. No se reenvía al broker remoto,
+    /// para que un mismo topic configurado en ambos sentidos no circule indefinidamente entre los brokers.
+    pub fn handle_bridged_publish(&self, msg: &PublishMessage) -> Result<(), Error> {
+        self.publish_locally(msg)
+    }
+
+    /// Envía el mensaje al bridge, si hay uno iniciado y su topic está entre los que se reenvían.
+    fn forward_to_bridge(&self, msg: &PublishMessage) {
+        let forwards_out = self.read_config(|config| {
+            config.get_bridge().is_some_and(|bridge_config| bridge_config.forwards_out(&msg.get_topic()))
+        });
+        if !forwards_out {
+            return;
+        }
+        if let Ok(bridge_tx_l) = self.bridge_tx.lock() {
+            if let Some(bridge_tx) = bridge_tx_l.as_ref() {
+                if let Err(e) = bridge_tx.send(msg.clone()) {
+                    self.logger.error(format!("Error al enviar publish al bridge: {:?}", e));
+                }
+            }
+        }
+    }
+
+    /// Envía el mensaje a los brokers del cluster que tienen suscriptores de su topic.
+    fn forward_to_cluster(&self, msg: &PublishMessage) {
+        if let Ok(cluster_peers) = self.cluster_peers.lock() {
+            for peer in cluster_peers.iter().filter(|peer| peer.has_subscribers_for(&msg.get_topic())) {
+                if let Err(e) = peer.forward(msg) {
+                    self.logger.error(format!("Error al enviar publish al peer del cluster {:?}: {:?}", peer.get_addr(), e));
+                }
+            }
+        }
+    }
+
+    fn publish_locally(&self, msg: &PublishMessage) -> Result<(), Error> {
+        self.topic_metrics.record_publish(&msg.get_topic(), msg.to_bytes().len());
+        if msg.is_retain() {
+            self.store_retained_message(msg)?;
+        }
         self.store_and_distribute_publish_msg(msg)?;
         self.remove_old_messages_from_server(msg.get_topic())?;
+        self.compact_journal_if_needed();
         Ok(())
     }
 
-    /// Agrega los topics al suscriptor correspondiente. y devuelve los códigos de retorno(qos)
+    /// Agrega los topics al suscriptor correspondiente, otorgándole en cada uno el qos que pidió, y devuelve
+    /// los códigos de retorno (qos). Los publish se le entregarán con el menor entre ese qos y el del publish.
     pub fn add_topics_to_subscriber(
         &self,
         username: &str,
@@ -242,13 +971,22 @@ impl MQTTServer {
         // Agrega los topics a los que se suscribió el usuario
         if let Ok(mut connected_users) = self.connected_users.lock() {
             if let Some(user) = connected_users.get_mut(username) {
-                for (topic, _qos) in msg.get_topic_filters() {
-                    user.add_topic(topic.to_string());
-                    return_codes.push(SubscribeReturnCode::QoS1);
-                    println!(
-                        "   Se agregó el topic {:?} al suscriptor {:?}",
-                        topic, username
-                    );
+                for (topic, qos) in msg.get_topic_filters() {
+                    let return_code = SubscribeReturnCode::for_granted_qos(*qos);
+                    if return_code == SubscribeReturnCode::Failure
+                        || !self.is_user_allowed_to(user, TopicAction::Subscribe, topic)
+                    {
+                        return_codes.push(SubscribeReturnCode::Failure);
+                        continue;
+                    }
+                    user.add_topic(topic.to_string(), *qos);
+                    self.journal_record(JournalRecord::Subscribe {
+                        client_id: username.to_string(),
+                        topic: topic.to_string(),
+                        qos: *qos,
+                    });
+                    return_codes.push(return_code);
+                    self.client_logger(username).debug(format!("Suscripto al topic {:?} con qos {}.", topic, qos));
                 }
             }
         }
@@ -271,15 +1009,116 @@ impl MQTTServer {
                         user.write_message(&ack_msg_bytes)?;
                     }
                 }
-                println!("   tipo subscribe: Enviando el ack: {:?}", ack);
+                self.client_logger(client_id).debug(format!("Enviado el suback: {:?}", ack));
             }
             Err(e) => {
-                println!("   ERROR: {:?}", e);
+                self.client_logger(client_id).error(format!("No se envía el suback: {:?}", e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve si el cliente `client_id` puede publicar en el topic `topic`, según la lista de control de acceso.
+    pub fn is_allowed_to_publish(&self, client_id: &str, topic: &str) -> Result<bool, Error> {
+        // Los topics que empiezan con `$` los publica solamente el propio broker (ie `$SYS`, o su tabla de
+        // suscripciones para el cluster).
+        if topic.starts_with('$') {
+            return Ok(false);
+        }
+        if let Ok(connected_users) = self.connected_users.lock() {
+            if let Some(user) = connected_users.get(client_id) {
+                return Ok(self.is_user_allowed_to(user, TopicAction::Publish, topic));
+            }
+            Ok(false)
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                "Error: no se pudo tomar lock a users para verificar permisos de publicación.",
+            ))
+        }
+    }
+
+    /// Devuelve si `user` puede realizar `action` sobre `topic`, y loguea si no tiene permiso.
+    fn is_user_allowed_to(&self, user: &User, action: TopicAction, topic: &str) -> bool {
+        let is_allowed = match self.acl.read() {
+            Ok(acl) => acl.is_allowed(user.get_auth_username(), action, topic),
+            Err(_) => false,
+        };
+        if !is_allowed {
+            self.client_logger(&user.get_username())
+                .warn(format!("Acceso denegado: {:?} sobre el topic {:?}.", action, topic));
+        }
+        is_allowed
+    }
+
+    /// Quita los topics del mensaje unsubscribe `msg` de los topics a los que está suscripto el usuario,
+    /// para que deje de recibir los mensajes publicados en ellos.
+    pub fn remove_topics_from_subscriber(
+        &self,
+        username: &str,
+        msg: &UnsubscribeMessage,
+    ) -> Result<(), Error> {
+        if let Ok(mut connected_users) = self.connected_users.lock() {
+            if let Some(user) = connected_users.get_mut(username) {
+                for topic in msg.get_topics() {
+                    if user.remove_topic(topic) {
+                        self.journal_record(JournalRecord::Unsubscribe {
+                            client_id: username.to_string(),
+                            topic: topic.to_string(),
+                        });
+                        self.client_logger(username).debug(format!("Desuscripto del topic {:?}.", topic));
+                    }
+                }
             }
+        } else {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Error: no se pudo tomar lock a users para quitar topics de un suscriptor.",
+            ));
         }
         Ok(())
     }
 
+    /// Envía un mensaje de tipo PingResp al cliente, en respuesta a su PingReq.
+    pub fn send_pingresp_to(&self, client_id: &str) -> Result<(), Error> {
+        self.write_message_to_user(client_id, &PingRespMessage::new().to_bytes())
+    }
+
+    /// Envía un mensaje de tipo UnsubAck al cliente.
+    pub fn send_unsuback_to(&self, client_id: &str, packet_id: u16) -> Result<(), Error> {
+        let [msb, lsb] = packet_id.to_be_bytes();
+        let ack = Unsuback::new(msb, lsb);
+        self.write_message_to_user(client_id, &ack.to_bytes())?;
+        self.client_logger(client_id).debug(format!("Enviado el unsuback: {:?}", ack));
+        Ok(())
+    }
+
+    /// Guarda el `PublishMessage` como el mensaje retenido de su topic, reemplazando al anterior.
+    /// Un publish con retain y payload vacío borra el mensaje retenido del topic.
+    fn store_retained_message(&self, msg: &PublishMessage) -> Result<(), Error> {
+        if let Ok(mut retained_messages_locked) = self.retained_messages.lock() {
+            if msg.get_payload().is_empty() {
+                retained_messages_locked.remove(&msg.get_topic());
+            } else {
+                retained_messages_locked.insert(msg.get_topic(), msg.clone());
+            }
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                "Error: no se pudo tomar lock a retained_messages para almacenar un Publish.",
+            ))
+        }
+    }
+
+    /// Devuelve el mensaje retenido del topic `topic`, si lo había.
+    fn get_retained_message(&self, topic: &str) -> Option<PublishMessage> {
+        if let Ok(retained_messages_locked) = self.retained_messages.lock() {
+            return retained_messages_locked.get(topic).cloned();
+        }
+        None
+    }
+
     /// Almacena el `PublishMessage` en la estructura del server para su topic, y lo envía a sus suscriptores.
     fn store_and_distribute_publish_msg(&self, msg: &PublishMessage) -> Result<(), Error> {
         // Vamos a recorrer todos los usuarios
@@ -288,6 +1127,10 @@ impl MQTTServer {
             if let Ok(mut messages_by_topic_locked) = self.messages_by_topic.lock() {
                 // Procesamos el mensaje
                 self.add_message_to_topic_messages(msg.clone(), &mut messages_by_topic_locked);
+                // Los publish de qos mayor a 0 se persisten, para no perderlos si el servidor se reinicia.
+                if msg.get_qos() > 0 {
+                    self.journal_record(JournalRecord::Publish(msg.clone()));
+                }
                 if let Some(topic_messages) = messages_by_topic_locked.get_mut(&msg.get_topic()) {
                     self.send_msgs_to_subscribers(
                         msg.get_topic(),
@@ -337,9 +1180,13 @@ impl MQTTServer {
         topic_messages: &VecDeque<PublishMessage>,
         users: &mut ValuesMut<'_, String, User>,
     ) -> Result<(), Error> {
-        // Recorremos todos los usuarios
-        for user in users {
-            self.send_unreceived_messages(user, &topic, topic_messages)?;
+        // Recorremos los usuarios conectados; a los desconectados no se les escribe, y al reconectarse reciben los
+        // mensajes que les falten. Si no se le puede enviar a uno (ie su cola de escritura está llena), se sigue
+        // con los demás.
+        for user in users.filter(|user| *user.get_state() == UserState::Active) {
+            if let Err(e) = self.send_unreceived_messages(user, &topic, topic_messages) {
+                self.client_logger(&user.get_username()).debug(format!("No se le envió el publish: {:?}", e));
+            }
         }
         Ok(())
     }
@@ -444,47 +1291,111 @@ impl MQTTServer {
         Ok(min_last_id)
     }
 
+    /// Actualiza al user `client_id` cuya conexión desde `peer_addr` terminó por `disconnect_reason`:
+    /// si fue voluntaria se lo quita del server descartando su will, como indica el protocolo;
+    /// si no, queda como temporalmente desconectado y se publica su will (luego del will delay configurado).
+    pub fn handle_client_disconnection(
+        &self,
+        client_id: &str,
+        peer_addr: Option<SocketAddr>,
+        disconnect_reason: DisconnectReason,
+    ) {
+        // Si la sesión fue desplazada por otra con el mismo client_id, el user ya no es de esta conexión.
+        if !self.is_current_session_of(client_id, peer_addr) {
+            self.client_logger(client_id).debug("Se cierra la sesión desplazada.".to_string());
+            return;
+        }
+        let res = match disconnect_reason {
+            DisconnectReason::Voluntaria => {
+                self.remove_user(client_id);
+                Ok(())
+            }
+            DisconnectReason::Involuntaria => self
+                .set_user_as_temporally_disconnected(client_id)
+                .and_then(|_| self.publish_will_after_delay(client_id, peer_addr)),
+        };
+        if let Err(e) = res {
+            self.client_logger(client_id).error(format!("Error al manejar desconexión {:?}: {:?}.", disconnect_reason, e));
+        }
+    }
+
+    /// Publica el will de `client_id` una vez transcurrido el will delay configurado, salvo que mientras tanto
+    /// el cliente se haya reconectado; así una reconexión rápida (ie un dron que pierde señal un instante)
+    /// no publica que el cliente se desconectó. Sin will delay, lo publica inmediatamente.
+    fn publish_will_after_delay(&self, client_id: &str, peer_addr: Option<SocketAddr>) -> Result<(), Error> {
+        let will_delay = self.read_config(|config| config.get_will_delay());
+        if will_delay.is_zero() {
+            return self.publish_users_will_message(client_id);
+        }
+        let server = self.clone_ref();
+        let client_id = client_id.to_string();
+        thread::spawn(move || {
+            thread::sleep(will_delay);
+            if !server.is_waiting_reconnection(&client_id, peer_addr) {
+                return;
+            }
+            if let Err(e) = server.publish_users_will_message(&client_id) {
+                server.client_logger(&client_id).error(format!("Error al publicar el will: {:?}.", e));
+            }
+        });
+        Ok(())
+    }
+
+    /// Devuelve si `client_id` sigue temporalmente desconectado desde que se cortó su conexión desde `peer_addr`.
+    fn is_waiting_reconnection(&self, client_id: &str, peer_addr: Option<SocketAddr>) -> bool {
+        if let Ok(connected_users_locked) = self.connected_users.lock() {
+            if let Some(client) = connected_users_locked.get(client_id) {
+                return client.is_connected_from(peer_addr)
+                    && *client.get_state() == UserState::TemporallyDisconnected;
+            }
+        }
+        false
+    }
+
     /// Remueve al usuario `username` del hashmap de usuarios
     pub fn remove_user(&self, username: &str) {
         if let Ok(mut users) = self.connected_users.lock() {
             users.remove(username);
-            println!("Username removido de la lista del server: {:?}", username);
-            // debug
+            self.journal_record(JournalRecord::SessionRemoved { client_id: username.to_string() });
+            self.client_logger(username).debug("Removido de los users del server.".to_string());
         }
     }
 
     /// Cambia el estado del usuario del server con username `username` a TemporallyDisconnected,
     /// para que no se le envíen mensajes si se encuentra en dicho estado y de esa forma evitar errores en writes.
+    /// Se cierra su conexión, que ya no se usará; sus suscripciones se conservan hasta que se reconecte.
     pub fn set_user_as_temporally_disconnected(&self, username: &str) -> Result<(), Error> {
         if let Ok(mut users) = self.connected_users.lock() {
             if let Some(user) = users.get_mut(username) {
+                if let Err(e) = user.shutdown() {
+                    self.client_logger(username).debug(format!("Error al cerrar la conexión: {:?}", e));
+                }
                 user.set_state(UserState::TemporallyDisconnected);
-                println!(
-                    "Username seteado como temporalmente desconectado: {:?}",
-                    username
-                );
+                self.client_logger(username).debug("Seteado como temporalmente desconectado.".to_string());
             }
         }
         Ok(())
     }
 
     // Aux: esta función está comentada solo temporalmente mientras probamos algo, dsp se volverá a usar [].
-    /// Envía un mensaje de tipo PubAck al cliente.
-    pub fn send_puback_to(&self, client_id: &str, msg: &PublishMessage) -> Result<(), Error> {
+    /// Envía un mensaje de tipo PubAck al cliente, con `reason_code` si el cliente usa mqtt 5.
+    /// En mqtt 3.1.1 el puback no lleva reason code, por lo que se envía siempre el de éxito.
+    pub fn send_puback_to(&self, client_id: &str, msg: &PublishMessage, reason_code: u8) -> Result<(), Error> {
         let option_packet_id = msg.get_packet_id();
         let packet_id = option_packet_id.unwrap_or(0);
+        let reason_code = match self.get_protocol_version_of(client_id) {
+            ProtocolVersion::Mqtt5 => reason_code,
+            ProtocolVersion::Mqtt311 => PUBACK_SUCCESS,
+        };
 
-        let ack = PubAckMessage::new(packet_id, 0);
+        let ack = PubAckMessage::new(packet_id, reason_code);
         let ack_msg_bytes = ack.to_bytes();
         if let Ok(mut connected_users_locked) = self.get_connected_users().lock() {
             if let Some(user) = connected_users_locked.get_mut(client_id) {
                 user.write_message(&ack_msg_bytes)?;
             }
         }
-        println!(
-            "   tipo publish: Enviado el ack para packet_id: {:?}",
-            ack.get_packet_id()
-        );
+        self.client_logger(client_id).debug(format!("Enviado el puback para packet_id: {:?}", ack.get_packet_id()));
         Ok(())
     }
 
@@ -500,9 +1411,16 @@ impl MQTTServer {
             // Al user que se conecta, se le envía lo que no tenía del topic en cuestión
             if let Ok(mut connected_users_locked) = self.connected_users.lock() {
                 if let Some(user) = connected_users_locked.get_mut(username) {
+                    // Si la suscripción al topic fue rechazada, no hay nada que enviarle
+                    if !user.get_topics().contains(topic) {
+                        continue;
+                    }
                     // Necesitamos también los mensajes
                     if let Ok(mut messages_by_topic_locked) = self.messages_by_topic.lock() {
-                        if let Some(topic_messages) = messages_by_topic_locked.get_mut(topic) {
+                        let topic_messages = messages_by_topic_locked.get_mut(topic);
+                        // El retenido se envía solo si no le llegará entre los mensajes previos del topic
+                        self.send_retained_message_if_not_in_history(user, topic, &topic_messages)?;
+                        if let Some(topic_messages) = topic_messages {
                             if self.there_are_old_messages_to_send_for(topic_messages) {
                                 self.send_unreceived_messages(user, topic, topic_messages)?;
                             }
@@ -522,9 +1440,181 @@ impl MQTTServer {
         Ok(())
     }
 
+    /// Envía al `user` el mensaje retenido del `topic`, si existe y no se encuentra entre los mensajes
+    /// del topic que el user todavía no recibió (en cuyo caso le llegará igualmente, y así no se duplica).
+    fn send_retained_message_if_not_in_history(
+        &self,
+        user: &mut User,
+        topic: &String,
+        topic_messages: &Option<&mut TopicMessages>,
+    ) -> Result<(), Error> {
+        if let Some(retained_msg) = self.get_retained_message(topic) {
+            let user_last_id = user.get_last_id_by_topic(topic) as usize;
+            let will_be_sent_with_history = topic_messages.as_ref().is_some_and(|msgs| {
+                msgs.iter().skip(user_last_id).any(|msg| *msg == retained_msg)
+            });
+            if !will_be_sent_with_history {
+                user.send_publish(&retained_msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Registra el puback del cliente para un publish de qos 1 que le enviamos, lo que libera lugar
+    /// para enviarle los publish que tenga encolados.
+    pub fn handle_puback_from(&self, client_id: &str, packet_id: u16) -> Result<(), Error> {
+        if let Ok(mut connected_users_locked) = self.connected_users.lock() {
+            if let Some(user) = connected_users_locked.get_mut(client_id) {
+                user.acknowledge_publish(packet_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Envía un mensaje de tipo PubRec al cliente, como respuesta a un publish de qos 2.
+    pub fn send_pubrec_to(&self, client_id: &str, packet_id: u16) -> Result<(), Error> {
+        let pubrec = PubRecMessage::new(packet_id);
+        self.write_message_to_user(client_id, &pubrec.to_bytes())?;
+        self.client_logger(client_id).debug(format!("Enviado el pubrec para packet_id: {:?}", packet_id));
+        Ok(())
+    }
+
+    /// Envía un mensaje de tipo PubRel al cliente, como respuesta a su pubrec de un publish de qos 2 que le enviamos.
+    pub fn send_pubrel_to(&self, client_id: &str, packet_id: u16) -> Result<(), Error> {
+        let pubrel = PubRelMessage::new(packet_id);
+        self.write_message_to_user(client_id, &pubrel.to_bytes())?;
+        self.client_logger(client_id).debug(format!("Enviado el pubrel para packet_id: {:?}", packet_id));
+        Ok(())
+    }
+
+    /// Envía un mensaje de tipo PubComp al cliente, como respuesta a su pubrel, finalizando el handshake de qos 2.
+    pub fn send_pubcomp_to(&self, client_id: &str, packet_id: u16) -> Result<(), Error> {
+        let pubcomp = PubCompMessage::new(packet_id);
+        self.write_message_to_user(client_id, &pubcomp.to_bytes())?;
+        self.client_logger(client_id).debug(format!("Enviado el pubcomp para packet_id: {:?}", packet_id));
+        Ok(())
+    }
+
+    /// Registra el packet_id del publish de qos 2 recibido del cliente `client_id`.
+    /// Devuelve true si es la primera vez que se recibe (y por lo tanto debe distribuirse), false si es un duplicado.
+    pub fn register_qos2_publish(&self, client_id: &str, packet_id: u16) -> Result<bool, Error> {
+        if let Ok(mut connected_users_locked) = self.connected_users.lock() {
+            if let Some(user) = connected_users_locked.get_mut(client_id) {
+                return Ok(user.register_qos2_packet_id(packet_id));
+            }
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "Error: no se encontró al user para registrar su publish de qos 2.",
+            ));
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            "Error: no se pudo tomar lock a users para registrar un publish de qos 2.",
+        ))
+    }
+
+    /// Libera el packet_id del publish de qos 2 del cliente `client_id`, al recibir su pubrel.
+    pub fn release_qos2_publish(&self, client_id: &str, packet_id: u16) {
+        if let Ok(mut connected_users_locked) = self.connected_users.lock() {
+            if let Some(user) = connected_users_locked.get_mut(client_id) {
+                user.release_qos2_packet_id(packet_id);
+            }
+        }
+    }
+
+    /// Escribe los bytes `msg_bytes` por el stream del user `client_id`, si el mismo se encuentra en el server.
+    fn write_message_to_user(&self, client_id: &str, msg_bytes: &[u8]) -> Result<(), Error> {
+        if let Ok(mut connected_users_locked) = self.connected_users.lock() {
+            if let Some(user) = connected_users_locked.get_mut(client_id) {
+                user.write_message(msg_bytes)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_connected_users(&self) -> ShareableUsers {
         self.connected_users.clone()
     }
+
+    /// Descarta los mensajes que superaron el tiempo de vida de su topic: los almacenados para enviar a los
+    /// suscriptores, los encolados para cada cliente, y los retenidos. Devuelve cuántos mensajes se descartaron.
+    pub fn remove_expired_messages(&self) -> Result<usize, Error> {
+        let ttls = self.read_config(|config| config.get_message_ttls().clone());
+        if ttls.is_empty() {
+            return Ok(0);
+        }
+        let now = SystemTime::now();
+        let mut removed = {
+            let mut connected_users = self
+                .connected_users
+                .lock()
+                .map_err(|_| MqttError::LockPoisoned("los users conectados".to_string()))?;
+            let mut messages_by_topic = self
+                .messages_by_topic
+                .lock()
+                .map_err(|_| MqttError::LockPoisoned("los mensajes por topic".to_string()))?;
+            let removed_stored = remove_expired_topic_messages(&ttls, now, &mut messages_by_topic, &mut connected_users);
+            let removed_pending: usize = connected_users
+                .values_mut()
+                .map(|user| user.remove_pending_publishes_where(|msg| ttls.is_expired(msg, now)))
+                .sum();
+            removed_stored + removed_pending
+        };
+
+        let mut retained_messages = self
+            .retained_messages
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("los mensajes retenidos".to_string()))?;
+        let retained_len = retained_messages.len();
+        retained_messages.retain(|_, msg| !ttls.is_expired(msg, now));
+        removed += retained_len - retained_messages.len();
+        Ok(removed)
+    }
+
+    /// Devuelve los topics a los que están suscriptos los clientes del broker, sin contar a los demás brokers del cluster.
+    pub fn get_local_subscribed_topics(&self) -> HashSet<String> {
+        match self.connected_users.lock() {
+            Ok(connected_users) => connected_users
+                .iter()
+                .filter(|(client_id, _)| !cluster::is_cluster_client(client_id))
+                .flat_map(|(_, user)| user.get_topics().iter().cloned())
+                .filter(|topic| topic != cluster::CLUSTER_SUBSCRIPTIONS_TOPIC)
+                .collect(),
+            Err(_) => HashSet::new(),
+        }
+    }
+
+    /// Devuelve la cantidad de mensajes almacenados de cada topic.
+    pub fn get_stored_messages_count_by_topic(&self) -> HashMap<String, usize> {
+        match self.messages_by_topic.lock() {
+            Ok(messages_by_topic) => messages_by_topic
+                .iter()
+                .map(|(topic, topic_messages)| (topic.to_string(), topic_messages.len()))
+                .collect(),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    /// Devuelve las métricas de publish y entregas de cada topic, ordenados por topic.
+    pub fn get_topic_metrics(&self) -> BTreeMap<String, TopicMetricsSummary> {
+        self.topic_metrics.get_summaries()
+    }
+
+    /// Cierra la conexión del cliente `client_id`. Su client reader lo detecta como un corte de la conexión,
+    /// por lo que se publica su will y conserva la sesión si se reconecta. Devuelve si el cliente estaba conectado.
+    pub fn kick_client(&self, client_id: &str) -> Result<bool, Error> {
+        let mut connected_users = self
+            .connected_users
+            .lock()
+            .map_err(|_| MqttError::LockPoisoned("los users conectados".to_string()))?;
+        match connected_users.get_mut(client_id) {
+            Some(user) if *user.get_state() == UserState::Active => {
+                user.shutdown()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
 }
 
 /// Crea un servidor en la dirección ip y puerto especificados.
@@ -545,9 +1635,7 @@ fn check_subscription_and_calculate_diff(
     topic_messages: &VecDeque<PublishMessage>,
 ) -> Result<Option<u32>, Error> {
     let user_subscribed_topics = user.get_topics();
-    println!("[DEBUG TOPICS]: user: {:?}, topics: {:?}.", user.get_username(), user.get_topics());
     if user_subscribed_topics.contains(topic) {
-        println!("[DEBUG TOPICS]: user: {:?}, sí estpá suscripto a topic: {:?}.", user.get_username(), topic);
         let topic_server_last_id = topic_messages.len() as u32;
         let user_last_id = user.get_last_id_by_topic(topic);
 
@@ -564,6 +1652,32 @@ fn check_subscription_and_calculate_diff(
     }
 }
 
+/// Quita de cada topic los mensajes que superaron su tiempo de vida, y ajusta el last_id de sus suscriptores
+/// para que los índices sigan siendo consistentes. Devuelve cuántos mensajes se quitaron.
+fn remove_expired_topic_messages(
+    ttls: &MessageTtls,
+    now: SystemTime,
+    messages_by_topic: &mut MutexGuard<'_, HashMap<String, TopicMessages>>,
+    users: &mut MutexGuard<'_, HashMap<String, User>>,
+) -> usize {
+    let mut removed = 0;
+    for (topic, topic_messages) in messages_by_topic.iter_mut() {
+        // Los mensajes se almacenan en el orden en que llegaron, por lo que los expirados están al principio.
+        let expired = topic_messages.iter().take_while(|msg| ttls.is_expired(msg, now)).count();
+        if expired == 0 {
+            continue;
+        }
+        topic_messages.drain(..expired);
+        for user in users.values_mut().filter(|user| user.get_topics().contains(topic)) {
+            // Un suscriptor que no había recibido los mensajes expirados ya no los recibe.
+            let last_id = user.get_last_id_by_topic(topic);
+            user.update_last_id_by_topic(topic, last_id.saturating_sub(expired as u32));
+        }
+        removed += expired;
+    }
+    removed
+}
+
 /// Envia al usuario `user` los mensajes del topic `topic` no recibidos.
 /// 
 fn send_unreceived_messages_to_user(
@@ -571,15 +1685,614 @@ fn send_unreceived_messages_to_user(
     topic: &String,
     topic_messages: &VecDeque<PublishMessage>,
     diff: u32,
+    logger: &StringLogger,
 ) -> Result<(), Error> {
     for _ in 0..diff {
         let next_message_index = user.get_last_id_by_topic(topic);
         if let Some(msg) = topic_messages.get(next_message_index as usize) {
-            user.write_message(&msg.to_bytes())?;
+            user.send_publish(msg)?;
             user.update_last_id_by_topic(topic, next_message_index + 1);
         } else {
-            println!("ERROR NO SE ENCUENTRA EL TOPIC_MSGS.GET(TOPIC) A ENVIAR!!!");
+            logger.error(format!("No se encuentra el mensaje {} del topic {:?} a enviar.", next_message_index, topic));
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::MQTTServer;
+    use crate::logging::string_logger::StringLogger;
+    use crate::mqtt::client::{
+        mqtt_client_builder::MqttClientBuilder, mqtt_client_delivery_token::DeliveryOutcome,
+    };
+    use crate::mqtt::loopback::LoopbackListener;
+    use crate::mqtt::messages::connect_message::ConnectMessage;
+    use crate::mqtt::messages::{
+        packet_type::PacketType, protocol_version::ProtocolVersion, publish_flags::PublishFlags,
+        publish_message::PublishMessage, subscribe_message::SubscribeMessage,
+        subscribe_return_code::SubscribeReturnCode,
+    };
+    use crate::mqtt::mqtt_utils::packet_observer::{PacketDirection, PacketEvent, PacketObserver};
+    use crate::mqtt::mqtt_utils::will_message_utils::will_message::WillMessageData;
+    use crate::mqtt::server::{
+        disconnect_reason::DisconnectReason, duplicate_client_id_policy::DuplicateClientIdPolicy,
+        journal::JournalSyncPolicy, message_ttl::MessageTtls, server_config::ServerConfig,
+    };
+    use crate::mqtt::stream_type::StreamType;
+    use std::{
+        fs,
+        io::Read,
+        net::TcpListener,
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Registra la dirección, el tipo y el client id de los paquetes que observa.
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<(PacketDirection, PacketType, Option<String>)>>,
+    }
+
+    impl PacketObserver for RecordingObserver {
+        fn on_packet(&self, event: &PacketEvent) {
+            self.events.lock().unwrap().push((
+                event.direction,
+                event.packet_type,
+                event.client_id.map(|client_id| client_id.to_string()),
+            ));
+        }
+    }
+
+    impl RecordingObserver {
+        fn has_event(
+            &self,
+            direction: PacketDirection,
+            packet_type: PacketType,
+            client_id: Option<&str>,
+        ) -> bool {
+            let event = (direction, packet_type, client_id.map(|id| id.to_string()));
+            self.events.lock().unwrap().contains(&event)
+        }
+
+        /// Espera a lo sumo `timeout` a que se observe el evento indicado. Devuelve si se observó.
+        fn wait_for_event(
+            &self,
+            direction: PacketDirection,
+            packet_type: PacketType,
+            client_id: Option<&str>,
+            timeout: Duration,
+        ) -> bool {
+            let deadline = Instant::now() + timeout;
+            while !self.has_event(direction, packet_type, client_id) {
+                if Instant::now() >= deadline {
+                    return false;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            true
+        }
+    }
+
+    /// Lo que los clientes de los tests esperan por cada ack, amplio para que no retransmitan aunque el server
+    /// demore en responder (ie al correr muchos tests a la vez).
+    const TEST_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Devuelve los dos extremos de una conexión local: (extremo del servidor, extremo del cliente).
+    fn create_connection(listener: &TcpListener) -> (StreamType, StreamType) {
+        let client_stream = StreamType::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        (server_stream.into(), client_stream)
+    }
+
+    fn create_server_with(policy: DuplicateClientIdPolicy) -> MQTTServer {
+        let (tx, _rx) = mpsc::channel::<String>();
+        MQTTServer::with_config(StringLogger::new(tx), ServerConfig::new(policy, 1, 1))
+    }
+
+    fn connect_user(server: &MQTTServer, stream: &StreamType, client_id: &str) {
+        let connect_msg = ConnectMessage::new(client_id.to_string(), None, None, None, 0);
+        let is_reconnection = server
+            .manage_possible_reconnecting_or_duplicate_user(
+                client_id,
+                stream,
+                connect_msg.get_protocol_version().unwrap_or_default(),
+            )
+            .unwrap();
+        if !is_reconnection {
+            server.add_new_user(stream, client_id, &connect_msg).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_1_con_disconnect_old_el_duplicado_desplaza_a_la_sesion_anterior() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (old_server_stream, mut old_client_stream) = create_connection(&listener);
+        let (new_server_stream, _new_client_stream) = create_connection(&listener);
+
+        let old_addr = old_server_stream.peer_addr().ok();
+        let new_addr = new_server_stream.peer_addr().ok();
+
+        connect_user(&server, &old_server_stream, "dron-1");
+        assert!(!server.rejects_duplicate_client_id("dron-1"));
+        connect_user(&server, &new_server_stream, "dron-1");
+
+        // La sesión anterior recibe un disconnect, y la sesión actual pasa a ser la nueva
+        let mut buf = [0u8; 1];
+        old_client_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0] >> 4, 14);
+        assert!(!server.is_current_session_of("dron-1", old_addr));
+        assert!(server.is_current_session_of("dron-1", new_addr));
+    }
+
+    #[test]
+    fn test_2_con_reject_new_se_rechaza_el_duplicado_de_una_sesion_activa() {
+        let server = create_server_with(DuplicateClientIdPolicy::RejectNew);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, _client_stream) = create_connection(&listener);
+
+        assert!(!server.rejects_duplicate_client_id("dron-1"));
+        connect_user(&server, &server_stream, "dron-1");
+        assert!(server.rejects_duplicate_client_id("dron-1"));
+        assert!(server.is_current_session_of("dron-1", server_stream.peer_addr().ok()));
+
+        // Un user temporalmente desconectado puede reconectarse
+        server.set_user_as_temporally_disconnected("dron-1").unwrap();
+        assert!(!server.rejects_duplicate_client_id("dron-1"));
+    }
+
+    #[test]
+    fn test_3_al_reiniciar_se_recuperan_los_publish_y_suscripciones_del_journal() {
+        let journal_path = std::env::temp_dir().join("rustx_journal_test_server.bin");
+        let journal_path = journal_path.to_string_lossy().to_string();
+        let _ = fs::remove_file(&journal_path);
+        let config = ServerConfig::default().with_journal(&journal_path, JournalSyncPolicy::Always);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, _client_stream) = create_connection(&listener);
+
+        let (tx, _rx) = mpsc::channel::<String>();
+        let server = MQTTServer::with_config(StringLogger::new(tx.clone()), config.clone());
+        connect_user(&server, &server_stream, "Sistema-Monitoreo");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 1)]);
+        server
+            .add_topics_to_subscriber("Sistema-Monitoreo", &subscribe_msg)
+            .unwrap();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(1), "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+        drop(server);
+
+        // El server reiniciado tiene el publish, y le devuelve la suscripción al cliente al reconectarse
+        let restarted = MQTTServer::with_config(StringLogger::new(tx), config);
+        let stored_msgs = restarted.messages_by_topic.lock().unwrap().get("inc").cloned();
+        assert_eq!(stored_msgs.unwrap().front(), Some(&msg));
+        connect_user(&restarted, &server_stream, "Sistema-Monitoreo");
+        let users = restarted.connected_users.lock().unwrap();
+        let user = users.get("Sistema-Monitoreo").unwrap();
+        assert_eq!(user.get_topics(), &vec!["inc".to_string()]);
+        let _ = fs::remove_file(&journal_path);
+    }
+
+    #[test]
+    fn test_4_los_publish_qos_1_que_exceden_la_ventana_se_encolan_hasta_el_puback() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let config = ServerConfig::default().with_max_in_flight_messages(1);
+        let server = MQTTServer::with_config(StringLogger::new(tx), config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        connect_user(&server, &server_stream, "dron-1");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 1)]);
+        server.add_topics_to_subscriber("dron-1", &subscribe_msg).unwrap();
+
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let first = PublishMessage::new(flags.clone(), "inc", Some(1), "incidente 1".as_bytes()).unwrap();
+        let second = PublishMessage::new(flags, "inc", Some(2), "incidente 2".as_bytes()).unwrap();
+        server.handle_publish_message(&first).unwrap();
+        server.handle_publish_message(&second).unwrap();
+
+        // Solamente se escribe el primero, el segundo espera el puback
+        let mut buf = vec![0u8; first.to_bytes().len()];
+        client_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, first.to_bytes());
+        client_stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        assert!(client_stream.read(&mut [0u8; 1]).is_err());
+
+        server.handle_puback_from("dron-1", 1).unwrap();
+        client_stream.set_read_timeout(None).unwrap();
+        let mut buf = vec![0u8; second.to_bytes().len()];
+        client_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, second.to_bytes());
+    }
+
+    #[test]
+    fn test_5_los_publish_se_codifican_segun_la_version_negociada_por_cada_cliente() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let server = MQTTServer::with_config(
+            StringLogger::new(tx),
+            ServerConfig::default().with_mqtt5_enabled(true).with_topic_alias_maximum(10),
+        );
+        assert!(server.accepts_protocol_version(Some(ProtocolVersion::Mqtt5)));
+        // Solamente los clientes de mqtt 5 pueden usar topic aliases.
+        assert_eq!(server.get_topic_alias_maximum(Some(ProtocolVersion::Mqtt5)), 10);
+        assert_eq!(server.get_topic_alias_maximum(Some(ProtocolVersion::Mqtt311)), 0);
+        assert!(!create_server_with(DuplicateClientIdPolicy::DisconnectOld)
+            .accepts_protocol_version(Some(ProtocolVersion::Mqtt5)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        let mut connect_msg = ConnectMessage::new("dron-5".to_string(), None, None, None, 0);
+        connect_msg.set_protocol_version(ProtocolVersion::Mqtt5);
+        server.add_new_user(&server_stream, "dron-5", &connect_msg).unwrap();
+        assert_eq!(server.get_protocol_version_of("dron-5"), ProtocolVersion::Mqtt5);
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 0)]);
+        server.add_topics_to_subscriber("dron-5", &subscribe_msg).unwrap();
+
+        let flags = PublishFlags::new(0, 0, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", None, "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+
+        let expected = msg.for_protocol_version(ProtocolVersion::Mqtt5).to_bytes();
+        let mut buf = vec![0u8; expected.len()];
+        client_stream.read_exact(&mut buf).unwrap();
+        let received =
+            PublishMessage::from_bytes_with_version(buf, ProtocolVersion::Mqtt5).unwrap();
+        assert_eq!(received.get_payload(), msg.get_payload());
+    }
+
+    /// Conecta a `client_id` con un will en el topic `desc`, y a un suscriptor de ese topic.
+    /// Devuelve el stream del server para `client_id`, y el del cliente suscriptor.
+    fn connect_user_with_will_and_subscriber(
+        server: &MQTTServer,
+        listener: &TcpListener,
+        client_id: &str,
+    ) -> (StreamType, StreamType) {
+        let (subscriber_server_stream, subscriber_stream) = create_connection(listener);
+        connect_user(server, &subscriber_server_stream, "monitoreo");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("desc".to_string(), 0)]);
+        server.add_topics_to_subscriber("monitoreo", &subscribe_msg).unwrap();
+
+        let (server_stream, _) = create_connection(listener);
+        let will = WillMessageData::new("dron caido".to_string(), "desc".to_string(), 0, 0);
+        let connect_msg = ConnectMessage::new(client_id.to_string(), Some(will), None, None, 0);
+        server.add_new_user(&server_stream, client_id, &connect_msg).unwrap();
+        (server_stream, subscriber_stream)
+    }
+
+    fn receives_something_within(stream: &mut StreamType, timeout: Duration) -> bool {
+        stream.set_read_timeout(Some(timeout)).unwrap();
+        stream.read(&mut [0u8; 1]).is_ok()
+    }
+
+    #[test]
+    fn test_6_al_desconectarse_con_disconnect_no_se_publica_el_will() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut subscriber_stream) =
+            connect_user_with_will_and_subscriber(&server, &listener, "dron-1");
+
+        server.handle_client_disconnection(
+            "dron-1",
+            server_stream.peer_addr().ok(),
+            DisconnectReason::Voluntaria,
+        );
+
+        assert!(!receives_something_within(&mut subscriber_stream, Duration::from_millis(200)));
+        assert!(!server.has_session("dron-1"));
+    }
+
+    #[test]
+    fn test_7_el_will_se_publica_luego_del_will_delay_salvo_que_el_cliente_se_reconecte() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let config = ServerConfig::default().with_will_delay(Duration::from_millis(300));
+        let server = MQTTServer::with_config(StringLogger::new(tx), config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut subscriber_stream) =
+            connect_user_with_will_and_subscriber(&server, &listener, "dron-1");
+
+        // Se corta la conexión, pero se reconecta antes de que venza el will delay.
+        server.handle_client_disconnection(
+            "dron-1",
+            server_stream.peer_addr().ok(),
+            DisconnectReason::Involuntaria,
+        );
+        let (new_server_stream, _) = create_connection(&listener);
+        connect_user(&server, &new_server_stream, "dron-1");
+        thread::sleep(Duration::from_millis(400));
+        assert!(!receives_something_within(&mut subscriber_stream, Duration::from_millis(100)));
+
+        // Se corta nuevamente y no se reconecta: se publica el will.
+        server.handle_client_disconnection(
+            "dron-1",
+            new_server_stream.peer_addr().ok(),
+            DisconnectReason::Involuntaria,
+        );
+        assert!(!receives_something_within(&mut subscriber_stream, Duration::from_millis(100)));
+        assert!(receives_something_within(&mut subscriber_stream, Duration::from_secs(2)));
+    }
+    #[test]
+    fn test_8_al_recargar_la_configuracion_se_aplican_los_nuevos_valores() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let config_path = std::env::temp_dir().join("rustx_reload_test_server.properties");
+        fs::write(&config_path, "max_packet_size=\"100\"\nmqtt5_enabled=\"true\"\n").unwrap();
+        assert_eq!(server.get_max_packet_size(), 255);
+
+        let clone = server.clone_ref();
+        clone
+            .reload_config_from(config_path.to_str().unwrap(), "no_existe_acl.txt")
+            .unwrap();
+
+        assert_eq!(server.get_max_packet_size(), 100);
+        assert!(server.accepts_protocol_version(Some(ProtocolVersion::Mqtt5)));
+        fs::write(&config_path, "max_packet_size=\"0\"\n").unwrap();
+        assert!(server.reload_config_from(config_path.to_str().unwrap(), "no_existe_acl.txt").is_err());
+        assert_eq!(server.get_max_packet_size(), 100);
+        let _ = fs::remove_file(&config_path);
+    }
+
+    #[test]
+    fn test_9_se_descartan_los_mensajes_expirados_de_los_topics_con_tiempo_de_vida() {
+        let (tx, _rx) = mpsc::channel::<String>();
+        let ttls = MessageTtls::default().with_ttl("dron", Duration::from_millis(50));
+        let server = MQTTServer::with_config(StringLogger::new(tx), ServerConfig::default().with_message_ttls(ttls));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, _client_stream) = create_connection(&listener);
+        connect_user(&server, &server_stream, "Sistema-Monitoreo");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("dron".to_string(), 1)]);
+        server.add_topics_to_subscriber("Sistema-Monitoreo", &subscribe_msg).unwrap();
+
+        let retained_flags = PublishFlags::new(0, 0, 1).unwrap();
+        for topic in ["dron", "inc"] {
+            let msg = PublishMessage::new(retained_flags.clone(), topic, None, "mensaje".as_bytes()).unwrap();
+            server.handle_publish_message(&msg).unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+
+        // Expiran el publish almacenado y el retenido de dron, los de inc se conservan.
+        assert_eq!(server.remove_expired_messages().unwrap(), 2);
+        let stored = server.get_stored_messages_count_by_topic();
+        assert_eq!(stored.get("dron"), Some(&0));
+        assert_eq!(stored.get("inc"), Some(&1));
+        assert!(server.get_retained_message("dron").is_none());
+        assert!(server.get_retained_message("inc").is_some());
+        // El suscriptor sigue recibiendo los siguientes mensajes del topic.
+        let users = server.connected_users.lock().unwrap();
+        assert_eq!(users.get("Sistema-Monitoreo").unwrap().get_last_id_by_topic(&"dron".to_string()), 0);
+    }
+
+    #[test]
+    fn test_10_los_contadores_de_escritura_se_publican_retenidos_en_sys() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        connect_user(&server, &server_stream, "Sistema-Monitoreo");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 1)]);
+        server.add_topics_to_subscriber("Sistema-Monitoreo", &subscribe_msg).unwrap();
+
+        let flags = PublishFlags::new(0, 0, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", None, "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+        client_stream.read_exact(&mut vec![0u8; msg.to_bytes().len()]).unwrap();
+        // El hilo escritor registra la escritura luego de completarla.
+        while server.delivery_stats.get_messages() == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.publish_sys_topics().unwrap();
+
+        let sys_msg = server.get_retained_message("$SYS/broker/delivery/messages").unwrap();
+        assert_eq!(sys_msg.get_payload(), b"1");
+        let sys_msg = server.get_retained_message("$SYS/broker/topics/inc/deliveries").unwrap();
+        assert_eq!(sys_msg.get_payload(), b"1");
+        // Los clientes no pueden publicar en los topics del broker.
+        assert!(!server.is_allowed_to_publish("Sistema-Monitoreo", "$SYS/broker/delivery/messages").unwrap());
+    }
+
+    #[test]
+    fn test_11_al_reiniciar_con_state_dir_se_restauran_los_retenidos_y_las_sesiones() {
+        let state_dir = std::env::temp_dir().join("rustx_state_dir_test_server");
+        let state_dir = state_dir.to_string_lossy().to_string();
+        let _ = fs::remove_dir_all(&state_dir);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, _client_stream) = create_connection(&listener);
+
+        let mut server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        server.open_state_dir(&state_dir, false).unwrap();
+        connect_user(&server, &server_stream, "Sistema-Monitoreo");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 1)]);
+        server.add_topics_to_subscriber("Sistema-Monitoreo", &subscribe_msg).unwrap();
+        let flags = PublishFlags::new(0, 0, 1).unwrap();
+        let retained_msg = PublishMessage::new(flags, "dron", None, "posicion".as_bytes()).unwrap();
+        server.handle_publish_message(&retained_msg).unwrap();
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(1), "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+        assert!(server.save_state().unwrap());
+        drop(server);
+
+        // El server reiniciado tiene el retenido, y el cliente retoma su sesión sin volver a recibir el publish
+        let mut restarted = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        restarted.open_state_dir(&state_dir, false).unwrap();
+        assert_eq!(restarted.get_retained_message("dron"), Some(retained_msg));
+        assert!(restarted.has_session("Sistema-Monitoreo"));
+        connect_user(&restarted, &server_stream, "Sistema-Monitoreo");
+        {
+            let users = restarted.connected_users.lock().unwrap();
+            let user = users.get("Sistema-Monitoreo").unwrap();
+            assert_eq!(user.get_subscriptions(), vec![("inc".to_string(), 1)]);
+            assert_eq!(user.get_last_id_by_topic(&"inc".to_string()), 1);
+        }
+
+        // Con fresh se inicia sin el estado guardado
+        let mut fresh = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        fresh.open_state_dir(&state_dir, true).unwrap();
+        assert_eq!(fresh.get_retained_message("dron"), None);
+        assert!(!fresh.has_session("Sistema-Monitoreo"));
+        let _ = fs::remove_dir_all(&state_dir);
+    }
+
+    #[test]
+    fn test_12_un_estado_guardado_corrupto_se_rechaza_salvo_con_fresh() {
+        let state_dir = std::env::temp_dir().join("rustx_state_dir_test_corrupto");
+        let _ = fs::remove_dir_all(&state_dir);
+        fs::create_dir_all(&state_dir).unwrap();
+        fs::write(state_dir.join("broker_state.bin"), b"RXST\x01corrupto").unwrap();
+        let state_dir = state_dir.to_string_lossy().to_string();
+
+        let mut server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        assert!(server.open_state_dir(&state_dir, false).is_err());
+        assert!(server.open_state_dir(&state_dir, true).is_ok());
+        let _ = fs::remove_dir_all(&state_dir);
+    }
+
+    #[test]
+    fn test_13_el_server_y_sus_clientes_corren_en_el_mismo_proceso_sin_tcp() {
+        let (listener, connector) = LoopbackListener::bind();
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        thread::spawn(move || server.run_loopback(listener));
+        let (tx, _rx) = mpsc::channel::<String>();
+
+        // El connect y el subscribe esperan su ack antes de volver: con un timeout amplio, el publish se envía
+        // recién cuando el dron ya está suscripto, aunque el server demore en responder.
+        let (mut dron, dron_rx, _) = MqttClientBuilder::new("dron-1")
+            .ack_timeout(TEST_ACK_TIMEOUT)
+            .connect_loopback(&connector, StringLogger::new(tx.clone()))
+            .unwrap();
+        dron.mqtt_subscribe(vec![("inc".to_string(), 1)]).unwrap();
+        let (mut camaras, _, _) = MqttClientBuilder::new("Sistema-Camaras")
+            .ack_timeout(TEST_ACK_TIMEOUT)
+            .connect_loopback(&connector, StringLogger::new(tx))
+            .unwrap();
+        let outcome = camaras.mqtt_publish_and_wait("inc", b"incidente", 1).unwrap();
+
+        assert_eq!(outcome, DeliveryOutcome::Acknowledged(0));
+        let msg = dron_rx.recv_timeout(TEST_ACK_TIMEOUT).unwrap();
+        assert_eq!(msg.get_topic(), "inc");
+        assert_eq!(msg.get_payload(), b"incidente");
+    }
+
+    #[test]
+    fn test_14_los_packet_observers_ven_los_paquetes_del_server_y_del_cliente() {
+        let (listener, connector) = LoopbackListener::bind();
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let server_observer = Arc::new(RecordingObserver::default());
+        server.add_packet_observer(server_observer.clone());
+        thread::spawn(move || server.run_loopback(listener));
+        let (tx, _rx) = mpsc::channel::<String>();
+
+        let client_observer = Arc::new(RecordingObserver::default());
+        let (mut camaras, _, _) = MqttClientBuilder::new("Sistema-Camaras")
+            .ack_timeout(TEST_ACK_TIMEOUT)
+            .packet_observer(client_observer.clone())
+            .connect_loopback(&connector, StringLogger::new(tx))
+            .unwrap();
+        let outcome = camaras.mqtt_publish_and_wait("inc", b"incidente", 1).unwrap();
+        assert_eq!(outcome, DeliveryOutcome::Acknowledged(0));
+
+        // El server informa a sus observers los paquetes que envía luego de escribirlos, por lo que el intercambio
+        // termina recién cuando observa el puback que envió, el último paquete.
+        let client_id = Some("Sistema-Camaras");
+        assert!(server_observer.wait_for_event(
+            PacketDirection::Outbound,
+            PacketType::Puback,
+            client_id,
+            TEST_ACK_TIMEOUT
+        ));
+        assert!(client_observer.has_event(PacketDirection::Outbound, PacketType::Connect, client_id));
+        assert!(client_observer.has_event(PacketDirection::Inbound, PacketType::Connack, client_id));
+        assert!(client_observer.has_event(PacketDirection::Outbound, PacketType::Publish, client_id));
+        assert!(client_observer.has_event(PacketDirection::Inbound, PacketType::Puback, client_id));
+        // El server no conoce el client id hasta leer el connect.
+        assert!(server_observer.has_event(PacketDirection::Inbound, PacketType::Connect, None));
+        assert!(server_observer.has_event(PacketDirection::Outbound, PacketType::Connack, client_id));
+        assert!(server_observer.has_event(PacketDirection::Inbound, PacketType::Publish, client_id));
+    }
+
+    #[test]
+    fn test_15_los_publish_se_entregan_con_el_menor_qos_entre_el_publish_y_la_suscripcion() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (monitoreo_server_stream, mut monitoreo_stream) = create_connection(&listener);
+        let (dron_server_stream, mut dron_stream) = create_connection(&listener);
+        connect_user(&server, &monitoreo_server_stream, "monitoreo");
+        connect_user(&server, &dron_server_stream, "dron-1");
+
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 0), ("desc".to_string(), 3)]);
+        let return_codes = server.add_topics_to_subscriber("monitoreo", &subscribe_msg).unwrap();
+        assert_eq!(return_codes, vec![SubscribeReturnCode::QoS0, SubscribeReturnCode::Failure]);
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 2)]);
+        let return_codes = server.add_topics_to_subscriber("dron-1", &subscribe_msg).unwrap();
+        assert_eq!(return_codes, vec![SubscribeReturnCode::QoS2]);
+
+        let flags = PublishFlags::new(0, 1, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", Some(9), "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+
+        // Al suscripto con qos 0 le llega con qos 0 y sin packet id; al suscripto con qos 2, con qos 1
+        // y un packet id propio de su conexión.
+        let expected = msg.for_delivery(0, None).unwrap().to_bytes();
+        let mut buf = vec![0u8; expected.len()];
+        monitoreo_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+        let expected = msg.for_delivery(1, Some(1)).unwrap().to_bytes();
+        let mut buf = vec![0u8; expected.len()];
+        dron_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_16_al_desconectarse_no_se_le_escribe_y_al_reconectarse_no_se_duplican_sus_suscripciones() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, mut client_stream) = create_connection(&listener);
+        connect_user(&server, &server_stream, "dron-1");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 0)]);
+        server.add_topics_to_subscriber("dron-1", &subscribe_msg).unwrap();
+
+        // Al cortarse la conexión se cierra, y los publish se le guardan para cuando se reconecte
+        server.handle_client_disconnection(
+            "dron-1",
+            server_stream.peer_addr().ok(),
+            DisconnectReason::Involuntaria,
+        );
+        client_stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(client_stream.read(&mut [0u8; 1]).unwrap(), 0);
+        let flags = PublishFlags::new(0, 0, 0).unwrap();
+        let msg = PublishMessage::new(flags, "inc", None, "incidente".as_bytes()).unwrap();
+        server.handle_publish_message(&msg).unwrap();
+
+        // Al reconectarse recibe el publish, y volver a suscribirse no duplica la suscripción
+        let (new_server_stream, mut new_client_stream) = create_connection(&listener);
+        connect_user(&server, &new_server_stream, "dron-1");
+        server.add_topics_to_subscriber("dron-1", &subscribe_msg).unwrap();
+        let mut buf = vec![0u8; msg.to_bytes().len()];
+        new_client_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, msg.to_bytes());
+        let users = server.connected_users.lock().unwrap();
+        assert_eq!(users.get("dron-1").unwrap().get_topics(), &vec!["inc".to_string()]);
+    }
+
+    #[test]
+    fn test_17_al_desconectarse_con_disconnect_se_quitan_sus_suscripciones() {
+        let server = create_server_with(DuplicateClientIdPolicy::DisconnectOld);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server_stream, _client_stream) = create_connection(&listener);
+        connect_user(&server, &server_stream, "dron-1");
+        let subscribe_msg = SubscribeMessage::new(1, vec![("inc".to_string(), 1)]);
+        server.add_topics_to_subscriber("dron-1", &subscribe_msg).unwrap();
+
+        server.handle_client_disconnection(
+            "dron-1",
+            server_stream.peer_addr().ok(),
+            DisconnectReason::Voluntaria,
+        );
+
+        // Al volver a conectarse empieza sin suscripciones
+        let (new_server_stream, _new_client_stream) = create_connection(&listener);
+        connect_user(&server, &new_server_stream, "dron-1");
+        let users = server.connected_users.lock().unwrap();
+        assert!(users.get("dron-1").unwrap().get_topics().is_empty());
+    }
+}
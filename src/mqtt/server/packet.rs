@@ -1,20 +1,40 @@
+use std::net::SocketAddr;
+
 use crate::mqtt::messages::packet_type::PacketType;
 
+use super::log_context::connection_log_context;
+
 pub struct Packet {
     message_type: PacketType,
     msg_bytes: Vec<u8>,
     username: String,
+    peer_addr: Option<SocketAddr>, // dirección del cliente que lo envió, para el log.
 }
 
 impl Packet {
-    pub fn new(message_type: PacketType, msg_bytes: Vec<u8>, username: String) -> Packet {
+    pub fn new(
+        message_type: PacketType,
+        msg_bytes: Vec<u8>,
+        username: String,
+        peer_addr: Option<SocketAddr>,
+    ) -> Packet {
         Packet {
             message_type,
             msg_bytes,
             username,
+            peer_addr,
         }
     }
 
+    /// Devuelve el contexto con el que se loggea el procesamiento del paquete: su cliente y su tipo.
+    pub fn log_context(&self) -> String {
+        format!(
+            "{} packet={:?}",
+            connection_log_context(self.peer_addr, Some(&self.username)),
+            self.message_type
+        )
+    }
+
     pub fn get_message_type(&self) -> PacketType {
         self.message_type
     }
@@ -0,0 +1,527 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    path::Path,
+    time::Duration,
+};
+
+use crate::logging::log_level::LogLevel;
+
+use super::{
+    bridge::BridgeConfig, cluster::ClusterConfig, connection_limits::ConnectionLimits,
+    duplicate_client_id_policy::DuplicateClientIdPolicy, file_helper::read_lines,
+    journal::JournalSyncPolicy, message_ttl::MessageTtls,
+};
+
+/// Implementación con la que el servidor acepta conexiones y lee de los clientes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ServerTransport {
+    /// Un hilo del sistema operativo por cliente.
+    #[default]
+    Threads,
+    /// Tareas de tokio sobre un runtime compartido; requiere compilar con la feature `async_server`.
+    Tokio,
+}
+
+impl ServerTransport {
+    fn from_config_value(value: &str) -> Result<Self, Error> {
+        match value {
+            "threads" => Ok(ServerTransport::Threads),
+            "tokio" => Ok(ServerTransport::Tokio),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Valor inválido para transport: {:?}", value),
+            )),
+        }
+    }
+}
+
+const DEFAULT_PROCESSOR_THREADS: usize = 20;
+const DEFAULT_PACKET_QUEUE_SIZE: usize = 200;
+const DEFAULT_JOURNAL_COMPACTION_THRESHOLD: usize = 1000;
+const DEFAULT_MAX_IN_FLIGHT_MESSAGES: usize = 10;
+const DEFAULT_CLIENT_WRITE_QUEUE_SIZE: usize = 100;
+const DEFAULT_MAX_PACKET_SIZE: usize = u8::MAX as usize;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_SYS_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_STATE_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Configuración del servidor, leída del archivo de configuración del message broker,
+/// con líneas de la forma `clave=valor` (el valor puede ir entre comillas).
+/// Las claves ausentes toman su valor por defecto, y las desconocidas (ie ip, port) se ignoran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    duplicate_client_id_policy: DuplicateClientIdPolicy,
+    processor_threads: usize, // hilos que procesan los paquetes de todos los clientes.
+    packet_queue_size: usize, // paquetes leídos que pueden esperar a ser procesados, antes de frenar la lectura.
+    max_in_flight_messages: usize, // publish qos 1 enviados a cada cliente sin su puback, antes de encolar los siguientes.
+    client_write_queue_size: usize, // mensajes a escribir hacia cada cliente; si se llena, se cierra su conexión.
+    max_packet_size: usize, // remaining length máxima de los paquetes recibidos; si se excede, se cierra la conexión.
+    connect_timeout: Duration, // para recibir el connect completo luego de aceptar la conexión; si vence, se cierra.
+    mqtt5_enabled: bool, // si no se habilita, los connect de mqtt 5 se rechazan para que el cliente use 3.1.1.
+    topic_alias_maximum: u16, // topic aliases que acepta en los publish de cada cliente de mqtt 5, 0 si no los acepta.
+    will_delay: Duration, // espera antes de publicar el will, para que una reconexión rápida no lo publique.
+    message_ttls: MessageTtls, // por defecto, los mensajes de ningún topic expiran.
+    expiry_sweep_interval: Duration, // cada cuánto se descartan los mensajes expirados.
+    transport: ServerTransport,
+    journal_path: Option<String>, // si no se configura, el servidor no persiste su estado.
+    journal_sync: JournalSyncPolicy,
+    journal_compaction_threshold: usize,
+    bridge: BridgeConfig, // claves `bridge_*`; el bridge se habilita al configurar `bridge_remote_addr`.
+    cluster: ClusterConfig, // claves `cluster_*`; el cluster se habilita al configurar `cluster_peers`.
+    connection_limits: ConnectionLimits, // por defecto, sin límites.
+    admin_addr: Option<SocketAddr>, // si no se configura, no se habilita la interfaz de administración.
+    sys_interval: Option<Duration>, // cada cuánto se publican los contadores en `$SYS`; None si no se publican.
+    state_snapshot_interval: Option<Duration>, // cada cuánto se guarda el estado en el state dir; None si solo al cerrar.
+    log_level: LogLevel,
+}
+
+impl ServerConfig {
+    pub fn new(
+        duplicate_client_id_policy: DuplicateClientIdPolicy,
+        processor_threads: usize,
+        packet_queue_size: usize,
+    ) -> Self {
+        Self {
+            duplicate_client_id_policy,
+            processor_threads,
+            packet_queue_size,
+            max_in_flight_messages: DEFAULT_MAX_IN_FLIGHT_MESSAGES,
+            client_write_queue_size: DEFAULT_CLIENT_WRITE_QUEUE_SIZE,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            mqtt5_enabled: false,
+            topic_alias_maximum: 0,
+            will_delay: Duration::ZERO,
+            message_ttls: MessageTtls::default(),
+            expiry_sweep_interval: DEFAULT_EXPIRY_SWEEP_INTERVAL,
+            transport: ServerTransport::default(),
+            journal_path: None,
+            journal_sync: JournalSyncPolicy::Always,
+            journal_compaction_threshold: DEFAULT_JOURNAL_COMPACTION_THRESHOLD,
+            bridge: BridgeConfig::default(),
+            cluster: ClusterConfig::default(),
+            connection_limits: ConnectionLimits::default(),
+            admin_addr: None,
+            sys_interval: Some(DEFAULT_SYS_INTERVAL),
+            state_snapshot_interval: Some(DEFAULT_STATE_SNAPSHOT_INTERVAL),
+            log_level: LogLevel::default(),
+        }
+    }
+
+    /// Devuelve la configuración con el transporte `transport`.
+    pub fn with_transport(mut self, transport: ServerTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Devuelve la configuración con un máximo de `max_in_flight_messages` publish qos 1 sin confirmar por cliente.
+    pub fn with_max_in_flight_messages(mut self, max_in_flight_messages: usize) -> Self {
+        self.max_in_flight_messages = max_in_flight_messages;
+        self
+    }
+
+    /// Devuelve la configuración con lugar para `client_write_queue_size` mensajes a escribir hacia cada cliente.
+    pub fn with_client_write_queue_size(mut self, client_write_queue_size: usize) -> Self {
+        self.client_write_queue_size = client_write_queue_size;
+        self
+    }
+
+    /// Devuelve la configuración con paquetes de remaining length de a lo sumo `max_packet_size`.
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    /// Devuelve la configuración esperando a lo sumo `connect_timeout` el connect de cada conexión nueva.
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Devuelve la configuración aceptando, o no, conexiones de mqtt 5.
+    pub fn with_mqtt5_enabled(mut self, mqtt5_enabled: bool) -> Self {
+        self.mqtt5_enabled = mqtt5_enabled;
+        self
+    }
+
+    /// Devuelve la configuración aceptando hasta `topic_alias_maximum` topic aliases por cliente de mqtt 5.
+    pub fn with_topic_alias_maximum(mut self, topic_alias_maximum: u16) -> Self {
+        self.topic_alias_maximum = topic_alias_maximum;
+        self
+    }
+
+    /// Devuelve la configuración esperando `will_delay` antes de publicar el will de un cliente desconectado.
+    pub fn with_will_delay(mut self, will_delay: Duration) -> Self {
+        self.will_delay = will_delay;
+        self
+    }
+
+    /// Devuelve la configuración descartando los mensajes de cada topic según `message_ttls`.
+    pub fn with_message_ttls(mut self, message_ttls: MessageTtls) -> Self {
+        self.message_ttls = message_ttls;
+        self
+    }
+
+    /// Devuelve la configuración con el journal en `journal_path`, sincronizado según `journal_sync`.
+    pub fn with_journal(mut self, journal_path: &str, journal_sync: JournalSyncPolicy) -> Self {
+        self.journal_path = Some(journal_path.to_string());
+        self.journal_sync = journal_sync;
+        self
+    }
+
+    /// Devuelve la configuración con los límites de conexiones y publish `connection_limits`.
+    pub fn with_connection_limits(mut self, connection_limits: ConnectionLimits) -> Self {
+        self.connection_limits = connection_limits;
+        self
+    }
+
+    /// Devuelve la configuración con la interfaz de administración escuchando en `admin_addr`.
+    pub fn with_admin_addr(mut self, admin_addr: SocketAddr) -> Self {
+        self.admin_addr = Some(admin_addr);
+        self
+    }
+
+    /// Devuelve la configuración publicando los contadores del servidor en `$SYS` cada `sys_interval`, o nunca si es None.
+    pub fn with_sys_interval(mut self, sys_interval: Option<Duration>) -> Self {
+        self.sys_interval = sys_interval;
+        self
+    }
+
+    /// Devuelve la configuración guardando el estado en el state dir cada `state_snapshot_interval`,
+    /// o solamente al cerrar el servidor si es None.
+    pub fn with_state_snapshot_interval(mut self, state_snapshot_interval: Option<Duration>) -> Self {
+        self.state_snapshot_interval = state_snapshot_interval;
+        self
+    }
+
+    /// Devuelve la configuración grabando en el log los eventos de nivel `log_level` y más graves.
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Lee la configuración del archivo `file_path`.
+    /// Devuelve error si el archivo no puede abrirse o algún valor es inválido.
+    pub fn from_file(file_path: &str) -> Result<Self, Error> {
+        let lines = read_lines(Path::new(file_path))?;
+        let mut config = ServerConfig::default();
+        for line in lines.map_while(Result::ok) {
+            if let Some((key, value)) = line.split_once('=') {
+                config.set(key.trim(), value.trim().trim_matches('"'))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Asigna el valor `value` a la clave `key`, si la misma corresponde a la configuración.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "duplicate_client_id_policy" => {
+                self.duplicate_client_id_policy = DuplicateClientIdPolicy::from_config_value(value)?
+            }
+            "processor_threads" => self.processor_threads = parse_positive(key, value)?,
+            "packet_queue_size" => self.packet_queue_size = parse_positive(key, value)?,
+            "max_in_flight_messages" => self.max_in_flight_messages = parse_positive(key, value)?,
+            "client_write_queue_size" => self.client_write_queue_size = parse_positive(key, value)?,
+            "max_packet_size" => self.max_packet_size = parse_positive(key, value)?,
+            "connect_timeout_secs" => {
+                self.connect_timeout = Duration::from_secs(parse_positive(key, value)? as u64)
+            }
+            "mqtt5_enabled" => self.mqtt5_enabled = parse_bool(key, value)?,
+            "topic_alias_maximum" => {
+                self.topic_alias_maximum = value.parse::<u16>().map_err(|_| invalid_value(key, value))?
+            }
+            "will_delay_secs" => {
+                let secs = value.parse::<u64>().map_err(|_| invalid_value(key, value))?;
+                self.will_delay = Duration::from_secs(secs)
+            }
+            "message_ttl" => self.message_ttls = MessageTtls::from_config_value(value)?,
+            "expiry_sweep_interval_secs" => {
+                self.expiry_sweep_interval = Duration::from_secs(parse_positive(key, value)? as u64)
+            }
+            "transport" => self.transport = ServerTransport::from_config_value(value)?,
+            "journal_path" if value.is_empty() => self.journal_path = None,
+            "journal_path" => self.journal_path = Some(value.to_string()),
+            "journal_sync" => self.journal_sync = JournalSyncPolicy::from_config_value(value)?,
+            "journal_compaction_threshold" => {
+                self.journal_compaction_threshold = parse_positive(key, value)?
+            }
+            "max_clients" => self.connection_limits.set_max_clients(parse_limit(key, value)?),
+            "max_connections_per_ip" => {
+                self.connection_limits.set_max_connections_per_ip(parse_limit(key, value)?)
+            }
+            "max_publish_rate" => {
+                let max_publish_rate = parse_limit(key, value)?.map(u32::try_from).transpose();
+                let max_publish_rate = max_publish_rate.map_err(|_| invalid_value(key, value))?;
+                self.connection_limits.set_max_publish_rate(max_publish_rate)
+            }
+            "log_level" => self.log_level = LogLevel::from_config_value(value)?,
+            "sys_interval_secs" => {
+                // 0 deshabilita la publicación de los contadores.
+                let secs = value.parse::<u64>().map_err(|_| invalid_value(key, value))?;
+                self.sys_interval = Some(Duration::from_secs(secs)).filter(|interval| !interval.is_zero())
+            }
+            "state_snapshot_interval_secs" => {
+                // 0 deshabilita el guardado periódico, el estado se guarda solamente al cerrar.
+                let secs = value.parse::<u64>().map_err(|_| invalid_value(key, value))?;
+                self.state_snapshot_interval = Some(Duration::from_secs(secs)).filter(|interval| !interval.is_zero())
+            }
+            "admin_addr" if value.is_empty() => self.admin_addr = None,
+            "admin_addr" => {
+                let admin_addr = value.parse::<SocketAddr>().map_err(|_| invalid_value(key, value))?;
+                self.admin_addr = Some(admin_addr)
+            }
+            _ if key.starts_with("bridge_") => self.bridge.set(key, value)?,
+            _ if key.starts_with("cluster_") => self.cluster.set(key, value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_duplicate_client_id_policy(&self) -> DuplicateClientIdPolicy {
+        self.duplicate_client_id_policy
+    }
+
+    pub fn get_processor_threads(&self) -> usize {
+        self.processor_threads
+    }
+
+    pub fn get_packet_queue_size(&self) -> usize {
+        self.packet_queue_size
+    }
+
+    pub fn get_max_in_flight_messages(&self) -> usize {
+        self.max_in_flight_messages
+    }
+
+    pub fn get_client_write_queue_size(&self) -> usize {
+        self.client_write_queue_size
+    }
+
+    pub fn get_max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    pub fn get_connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+
+    pub fn is_mqtt5_enabled(&self) -> bool {
+        self.mqtt5_enabled
+    }
+
+    pub fn get_topic_alias_maximum(&self) -> u16 {
+        self.topic_alias_maximum
+    }
+
+    pub fn get_will_delay(&self) -> Duration {
+        self.will_delay
+    }
+
+    pub fn get_message_ttls(&self) -> &MessageTtls {
+        &self.message_ttls
+    }
+
+    pub fn get_expiry_sweep_interval(&self) -> Duration {
+        self.expiry_sweep_interval
+    }
+
+    pub fn get_transport(&self) -> ServerTransport {
+        self.transport
+    }
+
+    pub fn get_journal_path(&self) -> Option<&String> {
+        self.journal_path.as_ref()
+    }
+
+    pub fn get_journal_sync(&self) -> JournalSyncPolicy {
+        self.journal_sync
+    }
+
+    pub fn get_journal_compaction_threshold(&self) -> usize {
+        self.journal_compaction_threshold
+    }
+
+    pub fn get_connection_limits(&self) -> ConnectionLimits {
+        self.connection_limits
+    }
+
+    pub fn get_log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    pub fn get_admin_addr(&self) -> Option<SocketAddr> {
+        self.admin_addr
+    }
+
+    pub fn get_sys_interval(&self) -> Option<Duration> {
+        self.sys_interval
+    }
+
+    pub fn get_state_snapshot_interval(&self) -> Option<Duration> {
+        self.state_snapshot_interval
+    }
+
+    /// Devuelve la configuración del bridge, si se configuró un broker remoto.
+    pub fn get_bridge(&self) -> Option<&BridgeConfig> {
+        Some(&self.bridge).filter(|bridge| bridge.is_enabled())
+    }
+
+    /// Devuelve la configuración del cluster, si se configuraron otros brokers.
+    pub fn get_cluster(&self) -> Option<&ClusterConfig> {
+        Some(&self.cluster).filter(|cluster| cluster.is_enabled())
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self::new(
+            DuplicateClientIdPolicy::default(),
+            DEFAULT_PROCESSOR_THREADS,
+            DEFAULT_PACKET_QUEUE_SIZE,
+        )
+    }
+}
+
+/// Parsea `value` como un número mayor a cero.
+fn parse_positive(key: &str, value: &str) -> Result<usize, Error> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(invalid_value(key, value)),
+    }
+}
+
+/// Parsea `value` como un límite: un número mayor a cero, o vacío si no hay límite.
+fn parse_limit(key: &str, value: &str) -> Result<Option<usize>, Error> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse_positive(key, value).map(Some)
+}
+
+/// Parsea `value` como `true` o `false`.
+fn parse_bool(key: &str, value: &str) -> Result<bool, Error> {
+    value.parse::<bool>().map_err(|_| invalid_value(key, value))
+}
+
+fn invalid_value(key: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Valor inválido para {}: {:?}", key, value),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ServerConfig, ServerTransport};
+    use crate::logging::log_level::LogLevel;
+    use crate::mqtt::server::{
+        connection_limits::ConnectionLimits, duplicate_client_id_policy::DuplicateClientIdPolicy,
+        journal::JournalSyncPolicy, message_ttl::MessageTtls,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_1_se_asignan_las_claves_conocidas_y_se_ignoran_las_demas() {
+        let mut config = ServerConfig::default();
+        config.set("ip", "127.0.0.1").unwrap();
+        config
+            .set("duplicate_client_id_policy", "reject_new")
+            .unwrap();
+        config.set("processor_threads", "4").unwrap();
+        config.set("packet_queue_size", "50").unwrap();
+        config.set("max_in_flight_messages", "5").unwrap();
+        config.set("client_write_queue_size", "50").unwrap();
+        config.set("max_packet_size", "128").unwrap();
+        config.set("connect_timeout_secs", "3").unwrap();
+        config.set("mqtt5_enabled", "true").unwrap();
+        config.set("topic_alias_maximum", "10").unwrap();
+        config.set("will_delay_secs", "5").unwrap();
+        config.set("message_ttl", "dron=30").unwrap();
+        config.set("transport", "tokio").unwrap();
+        config.set("journal_path", "journal.bin").unwrap();
+        config.set("journal_sync", "10").unwrap();
+        config.set("admin_addr", "127.0.0.1:9091").unwrap();
+        config.set("log_level", "debug").unwrap();
+        config.set("sys_interval_secs", "0").unwrap();
+        config.set("state_snapshot_interval_secs", "60").unwrap();
+
+        let expected = ServerConfig::new(DuplicateClientIdPolicy::RejectNew, 4, 50)
+            .with_max_in_flight_messages(5)
+            .with_client_write_queue_size(50)
+            .with_max_packet_size(128)
+            .with_connect_timeout(Duration::from_secs(3))
+            .with_mqtt5_enabled(true)
+            .with_topic_alias_maximum(10)
+            .with_will_delay(Duration::from_secs(5))
+            .with_message_ttls(MessageTtls::default().with_ttl("dron", Duration::from_secs(30)))
+            .with_transport(ServerTransport::Tokio)
+            .with_journal("journal.bin", JournalSyncPolicy::EveryRecords(10))
+            .with_admin_addr("127.0.0.1:9091".parse().unwrap())
+            .with_log_level(LogLevel::Debug)
+            .with_sys_interval(None)
+            .with_state_snapshot_interval(Some(Duration::from_secs(60)));
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn test_2_valores_invalidos_dan_error() {
+        let mut config = ServerConfig::default();
+        assert!(config.set("processor_threads", "0").is_err());
+        assert!(config.set("packet_queue_size", "muchos").is_err());
+        assert!(config.set("duplicate_client_id_policy", "otra").is_err());
+        assert!(config.set("transport", "procesos").is_err());
+        assert!(config.set("mqtt5_enabled", "si").is_err());
+        assert!(config.set("topic_alias_maximum", "70000").is_err());
+        assert!(config.set("will_delay_secs", "-1").is_err());
+        assert!(config.set("message_ttl", "dron=treinta").is_err());
+        assert!(config.set("expiry_sweep_interval_secs", "0").is_err());
+        assert!(config.set("connect_timeout_secs", "0").is_err());
+        assert!(config.set("admin_addr", "localhost").is_err());
+        assert!(config.set("log_level", "trace").is_err());
+        assert!(config.set("bridge_otra_clave", "valor").is_err());
+    }
+
+    #[test]
+    fn test_3_el_bridge_se_habilita_al_configurar_el_broker_remoto() {
+        let mut config = ServerConfig::default();
+        assert!(config.get_bridge().is_none());
+
+        config.set("bridge_remote_addr", "127.0.0.1:1883").unwrap();
+        config.set("bridge_topics_out", "inc").unwrap();
+        let bridge = config.get_bridge().unwrap();
+        assert!(bridge.forwards_out("inc"));
+    }
+
+    #[test]
+    fn test_6_el_cluster_se_habilita_al_configurar_los_peers() {
+        let mut config = ServerConfig::default();
+        config.set("cluster_node_id", "edificio-1").unwrap();
+        assert!(config.get_cluster().is_none());
+
+        config.set("cluster_peers", "127.0.0.1:9190").unwrap();
+        let cluster = config.get_cluster().unwrap();
+        assert_eq!(cluster.client_id(), "rustx-cluster-edificio-1");
+        assert!(config.set("cluster_otra_clave", "valor").is_err());
+    }
+
+    #[test]
+    fn test_4_los_limites_vacios_significan_sin_limite() {
+        let mut config = ServerConfig::default();
+        config.set("max_clients", "").unwrap();
+        config.set("max_connections_per_ip", "3").unwrap();
+        config.set("max_publish_rate", "50").unwrap();
+
+        let expected = ConnectionLimits::new(None, Some(3), Some(50));
+        assert_eq!(config.get_connection_limits(), expected);
+        assert!(config.set("max_clients", "0").is_err());
+        assert!(config.set("max_publish_rate", "99999999999").is_err());
+    }
+
+    #[test]
+    fn test_5_el_archivo_de_configuracion_del_proyecto_es_valido() {
+        assert!(ServerConfig::from_file("message_broker_server_config.properties").is_ok());
+    }
+}
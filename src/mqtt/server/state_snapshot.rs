@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+
+/// Nombre del archivo con el estado del servidor, dentro del state dir.
+const STATE_FILE_NAME: &str = "broker_state.bin";
+/// Identifica a los archivos de estado del servidor.
+const STATE_MAGIC: &[u8; 4] = b"RXST";
+/// Versión del formato que se escribe; al cambiarlo, se incrementa para no leer archivos de otro formato.
+const STATE_VERSION: u8 = 2;
+/// Bytes de magic, versión y longitud del contenido, que preceden al contenido.
+const STATE_HEADER_LEN: usize = 9;
+
+/// Sesión de un cliente conservada por el servidor entre reinicios.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoredSession {
+    pub topics: Vec<(String, u8)>, // String = topic, con el qos otorgado en su suscripción.
+    pub last_id_by_topic: HashMap<String, u32>, // String = topic, último mensaje del topic que se le envió.
+    pub pending_publishes: Vec<PublishMessage>, // publish qos 1 que esperaban lugar en su ventana.
+}
+
+/// Estado del servidor que se guarda en el state dir y se restaura al iniciar:
+/// los mensajes retenidos, los mensajes almacenados de cada topic, y las sesiones de los clientes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateSnapshot {
+    pub retained_messages: Vec<PublishMessage>,
+    pub messages_by_topic: HashMap<String, Vec<PublishMessage>>, // String = topic
+    pub sessions: HashMap<String, StoredSession>,                // String = client_id
+}
+
+impl StateSnapshot {
+    /// Devuelve los bytes del snapshot:
+    /// | magic (4 bytes) | versión (1 byte) | longitud del contenido (4 bytes) | contenido | crc32 del contenido (4 bytes) |.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut content = vec![];
+        put_publishes(&mut content, &self.retained_messages);
+        put_u32(&mut content, self.messages_by_topic.len());
+        for (topic, messages) in &self.messages_by_topic {
+            put_string(&mut content, topic);
+            put_publishes(&mut content, messages);
+        }
+        put_u32(&mut content, self.sessions.len());
+        for (client_id, session) in &self.sessions {
+            put_string(&mut content, client_id);
+            put_u32(&mut content, session.topics.len());
+            for (topic, qos) in &session.topics {
+                put_string(&mut content, topic);
+                content.push(*qos);
+            }
+            put_u32(&mut content, session.last_id_by_topic.len());
+            for (topic, last_id) in &session.last_id_by_topic {
+                put_string(&mut content, topic);
+                content.extend(last_id.to_be_bytes());
+            }
+            put_publishes(&mut content, &session.pending_publishes);
+        }
+
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.push(STATE_VERSION);
+        put_u32(&mut bytes, content.len());
+        let crc = crc32(&content);
+        bytes.extend(content);
+        bytes.extend(crc.to_be_bytes());
+        bytes
+    }
+
+    /// Reconstruye el snapshot a partir de sus bytes. Devuelve error si no son de un archivo de estado,
+    /// si son de una versión del formato que no se soporta, o si están incompletos o corruptos.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < STATE_HEADER_LEN || &bytes[0..4] != STATE_MAGIC {
+            return Err(invalid_state("no es un archivo de estado del servidor"));
+        }
+        if bytes[4] != STATE_VERSION {
+            return Err(invalid_state(&format!(
+                "versión de formato {} no soportada",
+                bytes[4]
+            )));
+        }
+        let content_len = u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) as usize;
+        let Some(content) = bytes.get(STATE_HEADER_LEN..STATE_HEADER_LEN + content_len) else {
+            return Err(invalid_state("archivo incompleto"));
+        };
+        let Some(crc_bytes) = bytes.get(STATE_HEADER_LEN + content_len..) else {
+            return Err(invalid_state("archivo incompleto"));
+        };
+        if crc_bytes != crc32(content).to_be_bytes() {
+            return Err(invalid_state(
+                "el checksum no coincide, el archivo está corrupto",
+            ));
+        }
+
+        let mut reader = StateReader { bytes: content };
+        let mut snapshot = StateSnapshot {
+            retained_messages: reader.publishes()?,
+            ..Default::default()
+        };
+        for _ in 0..reader.u32()? {
+            let topic = reader.string()?;
+            snapshot
+                .messages_by_topic
+                .insert(topic, reader.publishes()?);
+        }
+        for _ in 0..reader.u32()? {
+            let client_id = reader.string()?;
+            let mut session = StoredSession::default();
+            for _ in 0..reader.u32()? {
+                let topic = reader.string()?;
+                session.topics.push((topic, reader.u8()?));
+            }
+            for _ in 0..reader.u32()? {
+                let topic = reader.string()?;
+                session.last_id_by_topic.insert(topic, reader.u32()?);
+            }
+            session.pending_publishes = reader.publishes()?;
+            snapshot.sessions.insert(client_id, session);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid_state("hay bytes de más luego del contenido"));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Archivo de estado del servidor en el state dir, donde se guarda el snapshot y desde donde se restaura.
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    /// Crea (si no existe) el directorio `state_dir`, donde se guardará el estado.
+    pub fn open(state_dir: &str) -> Result<Self, Error> {
+        fs::create_dir_all(state_dir)?;
+        Ok(StateStore {
+            path: Path::new(state_dir).join(STATE_FILE_NAME),
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Devuelve el snapshot guardado, o None si todavía no se guardó ninguno.
+    pub fn load(&self) -> Result<Option<StateSnapshot>, Error> {
+        let mut bytes = vec![];
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_end(&mut bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        StateSnapshot::from_bytes(&bytes).map(Some)
+    }
+
+    /// Guarda el snapshot, reemplazando al anterior.
+    /// Se escribe primero a un archivo temporal que luego se renombra, para no perder el anterior si se corta a mitad.
+    pub fn save(&self, snapshot: &StateSnapshot) -> Result<(), Error> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(&snapshot.to_bytes())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Lee del contenido de un snapshot, avanzando sobre los bytes leídos.
+struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl StateReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], Error> {
+        if self.bytes.len() < len {
+            return Err(invalid_state("contenido incompleto"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn publishes(&mut self) -> Result<Vec<PublishMessage>, Error> {
+        let mut messages = vec![];
+        for _ in 0..self.u32()? {
+            let len = self.u32()? as usize;
+            messages.push(PublishMessage::from_bytes(self.take(len)?.to_vec())?);
+        }
+        Ok(messages)
+    }
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend((value as u32).to_be_bytes());
+}
+
+fn put_string(bytes: &mut Vec<u8>, string: &str) {
+    put_u32(bytes, string.len());
+    bytes.extend(string.as_bytes());
+}
+
+fn put_publishes<'a>(bytes: &mut Vec<u8>, messages: impl IntoIterator<Item = &'a PublishMessage>) {
+    let messages: Vec<Vec<u8>> = messages.into_iter().map(|msg| msg.to_bytes()).collect();
+    put_u32(bytes, messages.len());
+    for msg_bytes in messages {
+        put_u32(bytes, msg_bytes.len());
+        bytes.extend(msg_bytes);
+    }
+}
+
+/// Calcula el crc32 (IEEE) de `bytes`, para detectar si el archivo de estado se corrompió.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn invalid_state(reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Estado del servidor inválido: {}.", reason),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc32, StateSnapshot, StateStore, StoredSession};
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use std::{collections::HashMap, fs};
+
+    fn create_snapshot() -> StateSnapshot {
+        let msg = PublishMessage::new(
+            PublishFlags::new(0, 1, 1).unwrap(),
+            "inc",
+            Some(3),
+            b"incidente",
+        )
+        .unwrap();
+        let session = StoredSession {
+            topics: vec![("inc".to_string(), 1)],
+            last_id_by_topic: HashMap::from([("inc".to_string(), 1)]),
+            pending_publishes: vec![msg.clone()],
+        };
+        StateSnapshot {
+            retained_messages: vec![msg.clone()],
+            messages_by_topic: HashMap::from([("inc".to_string(), vec![msg])]),
+            sessions: HashMap::from([("dron-1".to_string(), session)]),
+        }
+    }
+
+    #[test]
+    fn test_1_el_snapshot_se_pasa_a_bytes_y_reconstruye_correctamente() {
+        let snapshot = create_snapshot();
+
+        let reconstruido = StateSnapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+
+        assert_eq!(reconstruido, snapshot);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_2_se_detecta_un_archivo_corrupto_o_de_otra_version() {
+        let bytes = create_snapshot().to_bytes();
+
+        let mut corrupto = bytes.clone();
+        corrupto[12] ^= 0xFF;
+        let mut otra_version = bytes.clone();
+        otra_version[4] = 1;
+
+        assert!(StateSnapshot::from_bytes(&corrupto).is_err());
+        assert!(StateSnapshot::from_bytes(&otra_version).is_err());
+        assert!(StateSnapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(StateSnapshot::from_bytes(b"no es un estado").is_err());
+    }
+
+    #[test]
+    fn test_3_el_estado_guardado_se_recupera_del_state_dir() {
+        let state_dir = std::env::temp_dir().join("rustx_state_snapshot_test");
+        let _ = fs::remove_dir_all(&state_dir);
+        let store = StateStore::open(&state_dir.to_string_lossy()).unwrap();
+        assert_eq!(store.load().unwrap(), None);
+
+        let snapshot = create_snapshot();
+        store.save(&snapshot).unwrap();
+
+        assert_eq!(store.load().unwrap(), Some(snapshot));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::mqtt::messages::publish_message::PublishMessage;
+
+/// Prefijo de los topics en los que el servidor publica las métricas de cada topic.
+const SYS_TOPICS_TOPIC_PREFIX: &str = "$SYS/broker/topics/";
+/// Cotas superiores, en milisegundos, de los rangos en que se agrupan las latencias de entrega.
+/// Las mayores a la última se cuentan en un rango más, y se informan con esa última cota.
+const LATENCY_BUCKETS_MS: [u64; 13] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000, 10000];
+
+/// Contadores de un topic. Se actualizan sin tomar locks, desde los hilos que procesan y envían los publish.
+#[derive(Debug, Default)]
+struct TopicCounters {
+    publishes: AtomicU64, // publish recibidos.
+    received_bytes: AtomicU64,
+    deliveries: AtomicU64, // publish enviados a los suscriptores.
+    delivered_bytes: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1], // entregas en cada rango de latencia.
+}
+
+impl TopicCounters {
+    /// Devuelve la latencia debajo de la cual quedó la fracción `quantile` de las entregas,
+    /// aproximada por la cota del rango en que cae, o None si no hubo entregas.
+    fn latency_quantile(&self, quantile: f64) -> Option<Duration> {
+        let counts: Vec<u64> = self
+            .latency_buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((total as f64 * quantile).ceil() as u64).max(1);
+        let mut accumulated = 0;
+        for (i, count) in counts.iter().enumerate() {
+            accumulated += count;
+            if accumulated >= rank {
+                let bound_ms = LATENCY_BUCKETS_MS[i.min(LATENCY_BUCKETS_MS.len() - 1)];
+                return Some(Duration::from_millis(bound_ms));
+            }
+        }
+        None
+    }
+
+    fn summary(&self) -> TopicMetricsSummary {
+        TopicMetricsSummary {
+            publishes: self.publishes.load(Ordering::Relaxed),
+            received_bytes: self.received_bytes.load(Ordering::Relaxed),
+            deliveries: self.deliveries.load(Ordering::Relaxed),
+            delivered_bytes: self.delivered_bytes.load(Ordering::Relaxed),
+            latency_p50: self.latency_quantile(0.5),
+            latency_p99: self.latency_quantile(0.99),
+        }
+    }
+}
+
+/// Valores de las métricas de un topic en un momento dado.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TopicMetricsSummary {
+    pub publishes: u64,
+    pub received_bytes: u64,
+    pub deliveries: u64,
+    pub delivered_bytes: u64,
+    pub latency_p50: Option<Duration>, // None si todavía no hubo entregas.
+    pub latency_p99: Option<Duration>,
+}
+
+/// Métricas de cada topic: publish recibidos, entregas a los suscriptores, bytes, y latencia de entrega (desde que
+/// se creó el publish hasta que se le envía a cada suscriptor). Permiten ver qué topics saturan al broker.
+/// Solamente se toma el lock de escritura la primera vez que se registra un topic; luego se actualizan sus contadores.
+/// Los topics del propio broker (que empiezan con `$`) no se registran.
+#[derive(Debug, Default)]
+pub struct TopicMetrics {
+    topics: RwLock<HashMap<String, Arc<TopicCounters>>>,
+}
+
+impl TopicMetrics {
+    /// Registra que se recibió un publish de `bytes` bytes en el topic `topic`.
+    pub fn record_publish(&self, topic: &str, bytes: usize) {
+        if let Some(counters) = self.counters_of(topic) {
+            counters.publishes.fetch_add(1, Ordering::Relaxed);
+            counters
+                .received_bytes
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Registra que el publish `msg`, de `bytes` bytes, se envió a un suscriptor de su topic.
+    pub fn record_delivery(&self, msg: &PublishMessage, bytes: usize) {
+        if let Some(counters) = self.counters_of(&msg.get_topic()) {
+            counters.deliveries.fetch_add(1, Ordering::Relaxed);
+            counters
+                .delivered_bytes
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+            let latency_ms = delivery_latency(msg).as_millis();
+            let bucket = LATENCY_BUCKETS_MS
+                .iter()
+                .position(|bound_ms| latency_ms <= *bound_ms as u128)
+                .unwrap_or(LATENCY_BUCKETS_MS.len());
+            counters.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Devuelve las métricas de cada topic, ordenados por topic.
+    pub fn get_summaries(&self) -> BTreeMap<String, TopicMetricsSummary> {
+        match self.topics.read() {
+            Ok(topics) => topics
+                .iter()
+                .map(|(topic, counters)| (topic.to_string(), counters.summary()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    /// Devuelve los pares (topic, valor) a publicar en `$SYS`, de la forma `$SYS/broker/topics/<topic>/<métrica>`.
+    /// Las latencias se publican en milisegundos, una vez que hubo entregas.
+    pub fn to_sys_topics(&self) -> Vec<(String, String)> {
+        let mut sys_topics = vec![];
+        for (topic, summary) in self.get_summaries() {
+            let mut values = vec![
+                ("publishes", summary.publishes.to_string()),
+                ("received_bytes", summary.received_bytes.to_string()),
+                ("deliveries", summary.deliveries.to_string()),
+                ("delivered_bytes", summary.delivered_bytes.to_string()),
+            ];
+            if let (Some(p50), Some(p99)) = (summary.latency_p50, summary.latency_p99) {
+                values.push(("latency_p50_ms", p50.as_millis().to_string()));
+                values.push(("latency_p99_ms", p99.as_millis().to_string()));
+            }
+            for (name, value) in values {
+                sys_topics.push((
+                    format!("{}{}/{}", SYS_TOPICS_TOPIC_PREFIX, topic, name),
+                    value,
+                ));
+            }
+        }
+        sys_topics
+    }
+
+    /// Devuelve los contadores del topic, creándolos si es la primera vez que se registra; o None si es
+    /// un topic del broker.
+    fn counters_of(&self, topic: &str) -> Option<Arc<TopicCounters>> {
+        if topic.starts_with('$') {
+            return None;
+        }
+        if let Ok(topics) = self.topics.read() {
+            if let Some(counters) = topics.get(topic) {
+                return Some(counters.clone());
+            }
+        }
+        let mut topics = self.topics.write().ok()?;
+        Some(topics.entry(topic.to_string()).or_default().clone())
+    }
+}
+
+/// Devuelve cuánto pasó desde que se creó el publish `msg`, según su timestamp.
+fn delivery_latency(msg: &PublishMessage) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let elapsed_nanos = now.saturating_sub(msg.get_timestamp());
+    Duration::from_nanos(elapsed_nanos.min(u64::MAX as u128) as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TopicCounters, TopicMetrics, LATENCY_BUCKETS_MS};
+    use crate::mqtt::messages::{publish_flags::PublishFlags, publish_message::PublishMessage};
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    #[test]
+    fn test_1_se_registran_los_publish_y_las_entregas_de_cada_topic() {
+        let metrics = TopicMetrics::default();
+        let flags = PublishFlags::new(0, 0, 0).unwrap();
+        let msg = PublishMessage::new(flags, "dron", None, "posicion".as_bytes()).unwrap();
+
+        metrics.record_publish("dron", 40);
+        metrics.record_delivery(&msg, 40);
+        metrics.record_delivery(&msg, 40);
+        metrics.record_publish("$SYS/broker/delivery/messages", 10);
+
+        let summaries = metrics.get_summaries();
+        assert_eq!(summaries.len(), 1);
+        let summary = summaries.get("dron").unwrap();
+        assert_eq!(summary.publishes, 1);
+        assert_eq!(summary.received_bytes, 40);
+        assert_eq!(summary.deliveries, 2);
+        assert_eq!(summary.delivered_bytes, 80);
+        assert!(summary.latency_p99.is_some());
+        assert!(metrics.to_sys_topics().contains(&(
+            "$SYS/broker/topics/dron/deliveries".to_string(),
+            "2".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_2_los_percentiles_de_latencia_se_aproximan_por_rangos() {
+        let counters = TopicCounters::default();
+        assert_eq!(counters.latency_quantile(0.5), None);
+
+        // 98 entregas de hasta 1 ms, y 2 de más de 10 s.
+        counters.latency_buckets[0].store(98, Ordering::Relaxed);
+        counters.latency_buckets[LATENCY_BUCKETS_MS.len()].store(2, Ordering::Relaxed);
+
+        assert_eq!(
+            counters.latency_quantile(0.5),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            counters.latency_quantile(0.99),
+            Some(Duration::from_millis(10000))
+        );
+    }
+}
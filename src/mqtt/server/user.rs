@@ -1,15 +1,25 @@
 use std::{
-    collections::HashMap,
-    io::{Error, Write}, net::Shutdown,
+    collections::{HashMap, HashSet, VecDeque},
+    io::Error, net::SocketAddr,
 };
 
+use crate::mqtt::mqtt_utils::mqtt_error::MqttError;
 use crate::mqtt::{
-    messages::{publish_flags::PublishFlags, publish_message::PublishMessage},
+    messages::{
+        protocol_version::ProtocolVersion, publish_flags::PublishFlags,
+        publish_message::PublishMessage,
+    },
     mqtt_utils::will_message_utils::will_message::WillMessageData,
     stream_type::StreamType,
 };
 
-use super::user_state::UserState;
+use super::{
+    client_writer::{ClientWriter, WriteTracking}, state_snapshot::StoredSession,
+    user_state::UserState,
+};
+
+/// Máximo qos de mqtt; con él, los publish se entregan con su propio qos.
+const MAX_QOS: u8 = 2;
 
 /// Representa a un usuario (cliente) conectado al MQTTServer, del lado del servidor.
 #[derive(Debug)]
@@ -17,28 +27,54 @@ use super::user_state::UserState;
 
 pub struct User {
     username: String, // se identifica por el username.
-    stream: StreamType,
+    auth_username: Option<String>, // usuario con el que se autenticó en el connect, None si es invitado.
+    writer: ClientWriter, // escribe hacia el cliente desde su propio hilo, sin bloquear a quien le envía.
+    write_queue_size: usize, // mensajes que pueden esperar a escribirse hacia el cliente.
+    write_tracking: WriteTracking, // contadores de escritura de todos los clientes, y packet observers.
+    peer_addr: Option<SocketAddr>, // identifica la conexión actual del user, para distinguirla de una sesión anterior.
+    protocol_version: ProtocolVersion, // negociada en el connect de la conexión actual.
     state: UserState,
     will_message: Option<WillMessageData>,
     topics: Vec<String>,                    // topics a los que esta suscripto
+    granted_qos_by_topic: HashMap<String, u8>, // por cada topic, el qos otorgado en su suscripción.
     last_id_by_topic: HashMap<String, u32>, // por cada topic tiene el ultimo id de mensaje enviado.
+    qos2_packet_ids_awaiting_pubrel: HashSet<u16>, // publish qos 2 recibidos, cuyo pubrel aún no llegó.
+    max_in_flight: usize, // máximo de publish qos 1 enviados al cliente sin su puback.
+    in_flight_packet_ids: VecDeque<u16>, // publish qos 1 enviados, cuyo puback aún no llegó.
+    last_packet_id: u16, // último packet id asignado a un publish enviado al cliente.
+    pending_publishes: VecDeque<PublishMessage>, // publish qos 1 a enviar cuando haya lugar en la ventana.
 }
 
 impl User {
-    /// Crea un User.
+    /// Crea un User, con el hilo que escribe hacia el cliente por `stream`.
     pub fn new(
         stream: StreamType,
         username: String,
+        auth_username: Option<String>,
         will_msg_and_topic: Option<WillMessageData>,
-    ) -> Self {
-        User {
+        max_in_flight: usize,
+        write_queue_size: usize,
+        write_tracking: WriteTracking,
+    ) -> Result<Self, Error> {
+        Ok(User {
             username,
-            stream,
+            auth_username,
+            peer_addr: stream.peer_addr().ok(),
+            protocol_version: ProtocolVersion::default(),
+            writer: ClientWriter::spawn(stream, write_queue_size, write_tracking.clone())?,
+            write_queue_size,
+            write_tracking,
             state: UserState::Active,
             will_message: will_msg_and_topic,
             topics: Vec::new(),
+            granted_qos_by_topic: HashMap::new(),
             last_id_by_topic: HashMap::new(),
-        }
+            qos2_packet_ids_awaiting_pubrel: HashSet::new(),
+            max_in_flight: max_in_flight.max(1),
+            in_flight_packet_ids: VecDeque::new(),
+            last_packet_id: 0,
+            pending_publishes: VecDeque::new(),
+        })
     }
 
     /// Devuelve si el user no está desconectado.
@@ -52,6 +88,7 @@ impl User {
     }
 
     /// Crea el PublishMessage necesario para publicar el will message que User tiene almacenado desde el principio de la conexión.
+    /// Respeta el qos y el retain configurados en el connect, de modo que un will con retain quede como último estado del topic.
     pub fn get_publish_message_with(
         &self,
         dup_flag: u8,
@@ -59,10 +96,12 @@ impl User {
     ) -> Result<Option<PublishMessage>, Error> {
         if let Some(info) = &self.will_message {
             let flags = PublishFlags::new(dup_flag, info.get_qos(), info.get_will_retain())?;
+            // Por protocolo, un publish de qos 0 no lleva packet_id.
+            let packet_id = if info.get_qos() > 0 { Some(packet_id) } else { None };
             let publish_msg = PublishMessage::new(
                 flags,
                 &info.get_will_topic(),
-                Some(packet_id),
+                packet_id,
                 info.get_will_msg_content().as_bytes(),
             )?;
 
@@ -85,14 +124,75 @@ impl User {
         self.last_id_by_topic.insert(topic.to_owned(), last_id);
     }
 
+    /// Devuelve el last_id de cada topic.
+    pub fn get_last_ids_by_topic(&self) -> &HashMap<String, u32> {
+        &self.last_id_by_topic
+    }
+
+    /// Retoma la sesión `session` que el servidor conservaba del cliente: sus suscripciones, el último mensaje
+    /// enviado de cada topic, y los publish que esperaban lugar en su ventana.
+    pub fn restore_session(&mut self, session: StoredSession) {
+        for (topic, qos) in session.topics {
+            self.add_topic(topic, qos);
+        }
+        self.last_id_by_topic.extend(session.last_id_by_topic);
+        self.pending_publishes.extend(session.pending_publishes);
+    }
+
     /// Devuelve los topics a los que el user está suscripto.
     pub fn get_topics(&self) -> &Vec<String> {
         &self.topics
     }
 
-    /// Se guarda el nuevo stream, después de una reconexión.
-    pub fn update_stream_with(&mut self, new_stream: StreamType) {
-        self.stream = new_stream
+    /// Devuelve los topics a los que el user está suscripto, cada uno con el qos otorgado en su suscripción.
+    pub fn get_subscriptions(&self) -> Vec<(String, u8)> {
+        self.topics
+            .iter()
+            .map(|topic| (topic.to_string(), self.get_granted_qos(topic)))
+            .collect()
+    }
+
+    /// Devuelve el qos otorgado en la suscripción al topic `topic`.
+    pub fn get_granted_qos(&self, topic: &str) -> u8 {
+        self.granted_qos_by_topic.get(topic).copied().unwrap_or(MAX_QOS)
+    }
+
+    /// Se escribe por el nuevo stream, después de una reconexión.
+    /// Los pubacks pendientes de la conexión anterior ya no llegarán, por lo que se libera la ventana.
+    pub fn update_stream_with(&mut self, new_stream: StreamType) -> Result<(), Error> {
+        self.peer_addr = new_stream.peer_addr().ok();
+        self.writer = ClientWriter::spawn(new_stream, self.write_queue_size, self.write_tracking.clone())?;
+        self.in_flight_packet_ids.clear();
+        Ok(())
+    }
+
+    /// Guarda la versión de protocolo negociada en el connect, con la que se le codifican los publish.
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.protocol_version = protocol_version;
+    }
+
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// Devuelve la dirección del cliente en la conexión actual.
+    pub fn get_peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Devuelve los packet ids de los publish qos 1 enviados al cliente, cuyo puback aún no llegó.
+    pub fn get_in_flight_packet_ids(&self) -> &VecDeque<u16> {
+        &self.in_flight_packet_ids
+    }
+
+    /// Devuelve los publish a enviar con qos 1, encolados hasta que haya lugar en la ventana.
+    pub fn get_pending_publishes(&self) -> &VecDeque<PublishMessage> {
+        &self.pending_publishes
+    }
+
+    /// Devuelve si la conexión actual del user es la del cliente en la dirección `peer_addr`.
+    pub fn is_connected_from(&self, peer_addr: Option<SocketAddr>) -> bool {
+        peer_addr.is_some() && self.peer_addr == peer_addr
     }
 
     /// Setea el estado del user.
@@ -100,25 +200,127 @@ impl User {
         self.state = state;
     }
 
-    /// Agrega el topic a los topics a los que user está suscripto.
-    pub fn add_topic(&mut self, topic: String) {
-        self.topics.push(topic.clone());
+    /// Agrega el topic a los topics a los que user está suscripto, con el qos `qos` otorgado.
+    pub fn add_topic(&mut self, topic: String, qos: u8) {
+        // Un topic al que ya estaba suscripto (ie restaurado del journal) no se repite, pero se actualiza su qos.
+        if !self.topics.contains(&topic) {
+            self.topics.push(topic.clone());
+        }
+        self.granted_qos_by_topic.insert(topic.clone(), qos);
         // Inicializa su last_id para ese topic en 0 si el mismo no existía.
         self.last_id_by_topic.entry(topic).or_insert(0);
     }
 
-    /// Escribe el mensaje en bytes `msg_bytes` por el stream hacia el cliente.
-    /// Puede devolver error si falla la escritura o el flush.
+    /// Quita el topic de los topics a los que user está suscripto, junto con su last_id.
+    /// Devuelve si el user estaba suscripto al topic.
+    pub fn remove_topic(&mut self, topic: &String) -> bool {
+        let was_subscribed = self.topics.contains(topic);
+        self.topics.retain(|t| t != topic);
+        self.granted_qos_by_topic.remove(topic);
+        self.last_id_by_topic.remove(topic);
+        was_subscribed
+    }
+
+    /// Registra el packet_id de un publish de qos 2 recibido de este user, hasta que llegue su pubrel.
+    /// Devuelve true si es la primera vez que se recibe, false si es un duplicado que no debe distribuirse.
+    pub fn register_qos2_packet_id(&mut self, packet_id: u16) -> bool {
+        self.qos2_packet_ids_awaiting_pubrel.insert(packet_id)
+    }
+
+    /// Libera el packet_id de un publish de qos 2, al recibir su pubrel.
+    pub fn release_qos2_packet_id(&mut self, packet_id: u16) {
+        self.qos2_packet_ids_awaiting_pubrel.remove(&packet_id);
+    }
+
+    /// Encola el mensaje en bytes `msg_bytes` para que su hilo escritor lo escriba hacia el cliente.
+    /// Devuelve error si el cliente no está conectado, o si su cola de escritura está llena.
     pub fn write_message(&mut self, msg_bytes: &[u8]) -> Result<(), Error> {
         if self.is_not_disconnected() {
-            let _ = self.stream.write(msg_bytes)?;
-            self.stream.flush()?;
-            return Ok(());
+            return self.writer.write(msg_bytes);
+        }
+        Err(not_connected_error())
+    }
+
+    /// Envía el publish al cliente, con el menor qos entre el del publish y el otorgado en la suscripción a su topic,
+    /// y con un packet id propio de esta conexión. Los de qos 1 se escriben mientras el cliente tenga menos de
+    /// `max_in_flight` publish sin confirmar, y si no quedan encolados hasta que lleguen sus pubacks; así un cliente
+    /// lento no se satura.
+    pub fn send_publish(&mut self, msg: &PublishMessage) -> Result<(), Error> {
+        match msg.get_qos().min(self.get_granted_qos(&msg.get_topic())) {
+            0 => self.write_publish(&msg.for_delivery(0, None)?),
+            1 => {
+                // Si no está conectado no se encola, el mensaje le llegará al reconectarse junto con los demás no recibidos.
+                if !self.is_not_disconnected() {
+                    return Err(not_connected_error());
+                }
+                self.pending_publishes.push_back(msg.clone());
+                self.send_pending_publishes()
+            }
+            qos => {
+                let packet_id = self.next_packet_id();
+                self.write_publish(&msg.for_delivery(qos, Some(packet_id))?)
+            }
+        }
+    }
+
+    /// Devuelve el packet id para el próximo publish de qos mayor a 0 enviado al cliente, salteando
+    /// los de los publish que todavía esperan su puback.
+    fn next_packet_id(&mut self) -> u16 {
+        loop {
+            // El packet id 0 no es válido.
+            self.last_packet_id = self.last_packet_id.checked_add(1).unwrap_or(1);
+            if !self.in_flight_packet_ids.contains(&self.last_packet_id) {
+                return self.last_packet_id;
+            }
+        }
+    }
+
+    /// Escribe con qos 1 los publish encolados mientras haya lugar en la ventana de publish sin confirmar.
+    pub fn send_pending_publishes(&mut self) -> Result<(), Error> {
+        while self.in_flight_packet_ids.len() < self.max_in_flight {
+            let Some(msg) = self.pending_publishes.pop_front() else {
+                break;
+            };
+            let packet_id = self.next_packet_id();
+            let write_res = msg
+                .for_delivery(1, Some(packet_id))
+                .and_then(|delivery| self.write_publish(&delivery));
+            if let Err(e) = write_res {
+                // Sigue encolado, para enviarlo cuando se reconecte.
+                self.pending_publishes.push_front(msg);
+                return Err(e);
+            }
+            self.in_flight_packet_ids.push_back(packet_id);
         }
-        Err(Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Error: User no conectado",
-        ))
+        Ok(())
+    }
+
+    /// Descarta los publish encolados que cumplen `is_expired`, y devuelve cuántos se descartaron.
+    pub fn remove_pending_publishes_where<F>(&mut self, is_expired: F) -> usize
+    where
+        F: Fn(&PublishMessage) -> bool,
+    {
+        let pending_len = self.pending_publishes.len();
+        self.pending_publishes.retain(|msg| !is_expired(msg));
+        pending_len - self.pending_publishes.len()
+    }
+
+    /// Escribe el publish codificado según la versión de protocolo de la conexión (en mqtt 5, con sus properties),
+    /// y registra su entrega en las métricas de su topic.
+    fn write_publish(&mut self, msg: &PublishMessage) -> Result<(), Error> {
+        let msg_bytes = msg.for_protocol_version(self.protocol_version).to_bytes();
+        self.write_message(&msg_bytes)?;
+        self.write_tracking.record_delivery(msg, msg_bytes.len());
+        Ok(())
+    }
+
+    /// Registra el puback del cliente para el publish `packet_id`, liberando su lugar en la ventana,
+    /// y escribe los publish encolados que ahora entren en ella.
+    pub fn acknowledge_publish(&mut self, packet_id: u16) -> Result<(), Error> {
+        if let Some(pos) = self.in_flight_packet_ids.iter().position(|id| *id == packet_id) {
+            self.in_flight_packet_ids.remove(pos);
+        }
+        self.send_pending_publishes()
     }
 
     // Aux: Usado para debugging.
@@ -127,11 +329,17 @@ impl User {
         self.username.to_string()
     }
 
-    /// Cerramos la conexión por el stream recibido.
-    pub fn shutdown(&mut self) {
-        match self.stream.shutdown(Shutdown::Both) {
-            Ok(_) => println!("Conexión terminada con éxito"),
-            Err(e) => println!("Error al terminar la conexión: {:?}", e),
-        }
+    /// Devuelve el usuario con el que se autenticó en el connect, o None si se conectó como invitado.
+    pub fn get_auth_username(&self) -> Option<&String> {
+        self.auth_username.as_ref()
+    }
+
+    /// Cerramos la conexión, luego de escribir los mensajes que ya se le enviaron.
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        self.writer.shutdown()
     }
 }
+
+fn not_connected_error() -> Error {
+    MqttError::NotConnected.into()
+}
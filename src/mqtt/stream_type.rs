@@ -1,3 +1,113 @@
-use std::net::TcpStream;
+use std::{
+    io::{Error, IoSlice, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
 
-pub type StreamType = TcpStream; // Aux: que lo use solo el server por ahora, así es más fácil hacer un refactor dsp-
+use super::loopback::LoopbackStream;
+
+/// Conexión entre el server y un cliente: por tcp, o en memoria si corren en el mismo proceso.
+#[derive(Debug)]
+pub enum StreamType {
+    Tcp(TcpStream),
+    Loopback(LoopbackStream),
+}
+
+impl StreamType {
+    /// Establece una conexión tcp con `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        TcpStream::connect(addr).map(StreamType::Tcp)
+    }
+
+    /// Establece una conexión tcp con `addr`, esperando como máximo `timeout`.
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> Result<Self, Error> {
+        TcpStream::connect_timeout(addr, timeout).map(StreamType::Tcp)
+    }
+
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.try_clone().map(StreamType::Tcp),
+            StreamType::Loopback(stream) => stream.try_clone().map(StreamType::Loopback),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.shutdown(how),
+            StreamType::Loopback(stream) => stream.shutdown(how),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.set_read_timeout(timeout),
+            StreamType::Loopback(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// En memoria la escritura nunca bloquea, por lo que el timeout solamente aplica a tcp.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.set_write_timeout(timeout),
+            StreamType::Loopback(_) => Ok(()),
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.local_addr(),
+            StreamType::Loopback(stream) => stream.local_addr(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.peer_addr(),
+            StreamType::Loopback(stream) => stream.peer_addr(),
+        }
+    }
+}
+
+impl From<TcpStream> for StreamType {
+    fn from(stream: TcpStream) -> Self {
+        StreamType::Tcp(stream)
+    }
+}
+
+impl From<LoopbackStream> for StreamType {
+    fn from(stream: LoopbackStream) -> Self {
+        StreamType::Loopback(stream)
+    }
+}
+
+impl Read for StreamType {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.read(buf),
+            StreamType::Loopback(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for StreamType {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.write(buf),
+            StreamType::Loopback(stream) => stream.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize, Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.write_vectored(bufs),
+            StreamType::Loopback(stream) => stream.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        match self {
+            StreamType::Tcp(stream) => stream.flush(),
+            StreamType::Loopback(stream) => stream.flush(),
+        }
+    }
+}